@@ -0,0 +1,16 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! The raw newc reader, reading every entry's data.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lowell_core::formats::cpio::Reader;
+use std::io::Read;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = Reader::new(data);
+    while let Ok(Some(_)) = reader.next_entry() {
+        if reader.data().read_to_end(&mut Vec::new()).is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Multi-segment initramfs walking, decompressors included, under tight
+//! limits so decompression bombs fail fast instead of exhausting memory.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lowell_core::formats::initramfs;
+use lowell_core::limits::ParserLimits;
+
+fuzz_target!(|data: &[u8]| {
+    let mut limits = ParserLimits::default();
+    limits.max_entries = 10_000;
+    limits.max_decompressed_bytes = 16 << 20;
+    let _ = initramfs::payload_compression(data);
+    let _ = initramfs::walk_with_limits(data, &limits, |_, body| {
+        std::io::copy(body, &mut std::io::sink())?;
+        Ok(())
+    });
+});
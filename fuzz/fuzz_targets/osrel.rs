@@ -0,0 +1,12 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! os-release parsing, as applied to a UKI's `.osrel` section.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lowell_core::formats::osrel::read_os_release_from_str;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = read_os_release_from_str(text);
+    }
+});
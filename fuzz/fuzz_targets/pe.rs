@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! PE parsing plus everything `uki inspect` reads from a parsed image.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lowell_core::formats::{kernel, osrel, pe::PeFile};
+use lowell_core::limits::ParserLimits;
+
+fuzz_target!(|data: &[u8]| {
+    let mut limits = ParserLimits::default();
+    limits.max_section_size = 1 << 20;
+    let Ok(pe) = PeFile::from_bytes_with_limits(data.to_vec(), &limits) else {
+        return;
+    };
+    let _ = pe.arch_summary();
+    let _ = pe.is_signed();
+    let _ = pe.certificate_metadata();
+    let _ = pe.read_text(".cmdline");
+    let _ = osrel::read_os_release(&pe);
+    let _ = pe.section_table();
+    let _ = pe.section(".initrd").map(|s| s.contents());
+    let _ = pe.sections();
+    let _ = pe.checksum();
+    let _ = pe.authenticode_parts();
+    if let Some(format) = kernel::detect(data) {
+        let _ = kernel::arch(data, format);
+    }
+});
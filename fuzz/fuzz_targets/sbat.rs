@@ -0,0 +1,15 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! SBAT parsing, as applied to a UKI's `.sbat` section, and the
+//! revocation check against the records themselves as a level.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lowell_core::formats::sbat;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Ok(entries) = sbat::parse(text) {
+            let _ = sbat::revoked(&entries, &entries);
+        }
+    }
+});
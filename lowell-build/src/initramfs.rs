@@ -0,0 +1,329 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Pack a directory tree into a compressed initramfs.
+//!
+//! [`pack`] runs as a three-stage pipeline, so disk reads, cpio encoding
+//! and compression overlap instead of taking turns:
+//!
+//! 1. a reader thread walks the tree in sorted order and reads each file;
+//! 2. an encoder thread turns those into newc records;
+//! 3. the calling thread compresses the records into the output.
+//!
+//! The stages are joined by bounded channels: a slow compressor holds the
+//! reader back instead of letting file contents pile up in memory. Entries
+//! carry no timestamps or owners (mtime 0, root:root), so a tree always
+//! packs to the same bytes.
+
+use lowell_formats::cancel::CancelToken;
+use lowell_formats::cpio::{self, Header};
+use lowell_formats::error::{Error, IoResultExt, Result};
+use lowell_formats::initramfs::Compression;
+use lowell_formats::progress::{NoProgress, Phase, ProgressSink};
+use lowell_formats::timing::Stopwatch;
+use lowell_formats::trace::{debug, debug_span};
+use lowell_formats::uring;
+use std::fs::Metadata;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+/// Files the reader may run ahead of the encoder.
+const FILES_AHEAD: usize = 16;
+/// Encoded bytes per message to the compressor.
+const CHUNK: usize = 1 << 20;
+/// Chunks the encoder may run ahead of the compressor.
+const CHUNKS_AHEAD: usize = 4;
+/// Regular files read together (one io_uring batch where available).
+const BATCH_FILES: usize = 64;
+const BATCH_BYTES: u64 = 64 << 20;
+
+/// Pack the contents of `root` (not `root` itself) into `out`.
+pub fn pack<W: Write>(root: &Path, compression: Compression, out: W) -> Result<W> {
+    pack_with(root, compression, out, &NoProgress, &CancelToken::new())
+}
+
+/// [`pack`], reporting uncompressed bytes to `sink` as [`Phase::Pack`] and
+/// stopping with [`Error::Cancelled`] once `cancel` is cancelled (checked
+/// between files).
+pub fn pack_with<W: Write>(
+    root: &Path,
+    compression: Compression,
+    out: W,
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<W> {
+    let _span = debug_span!("pack", root = %root.display(), %compression).entered();
+    let t = Stopwatch::start();
+    let (files_tx, files_rx) = sync_channel(FILES_AHEAD);
+    let (chunks_tx, chunks_rx) = sync_channel(CHUNKS_AHEAD);
+    sink.phase_started(Phase::Pack, None);
+    let out = std::thread::scope(|s| {
+        s.spawn(move || {
+            if let Err(e) = read_tree(root, "", &files_tx, cancel) {
+                // Fails only if the encoder is gone, which is fine.
+                let _ = files_tx.send(Err(e));
+            }
+        });
+        s.spawn(move || encode(files_rx, chunks_tx));
+        compress(compression, out, chunks_rx, sink)
+    })?;
+    sink.phase_finished(Phase::Pack);
+    debug!(elapsed_ms = t.ms(), "pack");
+    Ok(out)
+}
+
+type Record = Result<(Header, Vec<u8>)>;
+
+/// Stage 1: every entry below `dir`, parents before children, each
+/// directory's entries in name order.
+fn read_tree(
+    dir: &Path,
+    prefix: &str,
+    tx: &SyncSender<Record>,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let mut names = std::fs::read_dir(dir)
+        .at("read", dir)?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<io::Result<Vec<_>>>()
+        .at("read", dir)?;
+    names.sort();
+    // Consecutive regular files, read as one batch to keep order.
+    let mut batch: Vec<(Header, PathBuf)> = Vec::new();
+    let mut batch_bytes = 0;
+    for name in names {
+        cancel.check()?;
+        let path = dir.join(&name);
+        let name = name
+            .into_string()
+            .map_err(|_| Error::Invalid(format!("{}: name is not UTF-8", path.display())))?;
+        let name = format!("{prefix}{name}");
+        let meta = std::fs::symlink_metadata(&path).at("stat", &path)?;
+        if meta.is_file() {
+            batch_bytes += meta.len();
+            batch.push((Header::new(name, mode(&meta)), path));
+            if batch.len() >= BATCH_FILES || batch_bytes >= BATCH_BYTES {
+                send_files(std::mem::take(&mut batch), tx)?;
+                batch_bytes = 0;
+            }
+            continue;
+        }
+        send_files(std::mem::take(&mut batch), tx)?;
+        batch_bytes = 0;
+        let data = if meta.is_symlink() {
+            let target = std::fs::read_link(&path).at("readlink", &path)?;
+            target.to_string_lossy().into_owned().into_bytes()
+        } else {
+            Vec::new()
+        };
+        let mut header = Header::new(name.as_str(), mode(&meta));
+        (header.rdevmajor, header.rdevminor) = rdev(&meta);
+        // The encoder has stopped, so nobody wants the rest.
+        tx.send(Ok((header, data))).map_err(|_| Error::Cancelled)?;
+        if meta.is_dir() {
+            read_tree(&path, &format!("{name}/"), tx, cancel)?;
+        }
+    }
+    send_files(batch, tx)
+}
+
+/// Read a batch of regular files (through io_uring if it is available)
+/// and pass them on in order.
+fn send_files(batch: Vec<(Header, PathBuf)>, tx: &SyncSender<Record>) -> Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let paths: Vec<&Path> = batch.iter().map(|(_, p)| p.as_path()).collect();
+    let contents =
+        uring::read_many(&paths).unwrap_or_else(|| paths.iter().map(std::fs::read).collect());
+    for ((header, path), data) in batch.into_iter().zip(contents) {
+        let data = data.at("read", &path)?;
+        tx.send(Ok((header, data))).map_err(|_| Error::Cancelled)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode(meta: &Metadata) -> u32 {
+    std::os::unix::fs::MetadataExt::mode(meta)
+}
+
+#[cfg(not(unix))]
+fn mode(meta: &Metadata) -> u32 {
+    if meta.is_dir() {
+        cpio::S_IFDIR | 0o755
+    } else if meta.is_symlink() {
+        cpio::S_IFLNK | 0o777
+    } else {
+        cpio::S_IFREG | 0o644
+    }
+}
+
+/// Device numbers of a device node, split the way Linux encodes them.
+#[cfg(unix)]
+fn rdev(meta: &Metadata) -> (u32, u32) {
+    let rdev = std::os::unix::fs::MetadataExt::rdev(meta);
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+    (major as u32, minor as u32)
+}
+
+#[cfg(not(unix))]
+fn rdev(_: &Metadata) -> (u32, u32) {
+    (0, 0)
+}
+
+/// Stage 2: newc records, cut into chunks for the compressor.
+fn encode(files: Receiver<Record>, tx: SyncSender<Result<Vec<u8>>>) {
+    let mut writer = cpio::Writer::new(Chunks {
+        tx: tx.clone(),
+        buf: Vec::with_capacity(CHUNK),
+    });
+    let result = files
+        .into_iter()
+        .try_for_each(|record| {
+            let (header, data) = record?;
+            writer.append(&header, &data)
+        })
+        .and_then(|()| writer.finish()?.flush().map_err(Error::from));
+    if let Err(e) = result {
+        let _ = tx.send(Err(e));
+    }
+}
+
+/// Sends whatever is written in [`CHUNK`]-sized pieces.
+struct Chunks {
+    tx: SyncSender<Result<Vec<u8>>>,
+    buf: Vec<u8>,
+}
+
+impl Write for Chunks {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK));
+        self.tx
+            .send(Ok(chunk))
+            .map_err(|_| io::Error::other("compressor stopped"))
+    }
+}
+
+/// Stage 3: compress the chunks into `out`.
+fn compress<W: Write>(
+    compression: Compression,
+    out: W,
+    chunks: Receiver<Result<Vec<u8>>>,
+    sink: &dyn ProgressSink,
+) -> Result<W> {
+    let copy = |w: &mut dyn Write| {
+        let mut done = 0u64;
+        for chunk in chunks {
+            let chunk = chunk?;
+            w.write_all(&chunk)?;
+            done += chunk.len() as u64;
+            sink.bytes_processed(Phase::Pack, done);
+        }
+        Ok::<_, Error>(())
+    };
+    Ok(match compression {
+        Compression::Uncompressed => {
+            let mut out = out;
+            copy(&mut out)?;
+            out
+        }
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            let mut w = flate2::write::GzEncoder::new(out, flate2::Compression::default());
+            copy(&mut w)?;
+            w.finish()?
+        }
+        #[cfg(feature = "xz")]
+        Compression::Xz => {
+            // The kernel's xz decoder only knows CRC32 checks.
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            let stream = liblzma::stream::MtStreamBuilder::new()
+                .threads(u32::try_from(threads).unwrap_or(u32::MAX))
+                .preset(6)
+                .check(liblzma::stream::Check::Crc32)
+                .encoder()
+                .map_err(io::Error::other)?;
+            let mut w = liblzma::write::XzEncoder::new_stream(out, stream);
+            copy(&mut w)?;
+            w.finish()?
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut w = zstd::stream::write::Encoder::new(out, 0)?;
+            copy(&mut w)?;
+            w.finish()?
+        }
+        // Unknown, or a backend compiled out (see the crate features).
+        #[allow(unreachable_patterns)]
+        _ => return Err(Error::UnsupportedCompression),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_formats::initramfs;
+
+    #[test]
+    fn packs_a_tree_reproducibly() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("usr/bin")).unwrap();
+        std::fs::write(root.join("usr/bin/sh"), b"#!shell").unwrap();
+        std::fs::write(root.join("big"), vec![7; 3 * CHUNK]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("usr/bin/sh", root.join("init")).unwrap();
+
+        #[allow(unused_mut)]
+        let mut compressions = vec![Compression::Uncompressed];
+        #[cfg(feature = "gzip")]
+        compressions.push(Compression::Gzip);
+        #[cfg(feature = "xz")]
+        compressions.push(Compression::Xz);
+        #[cfg(feature = "zstd")]
+        compressions.push(Compression::Zstd);
+        for c in compressions {
+            let image = pack(root, c, Vec::new()).unwrap();
+            assert_eq!(initramfs::detect(&image), c);
+            assert_eq!(image, pack(root, c, Vec::new()).unwrap(), "{c}");
+
+            let mut seen = Vec::new();
+            initramfs::walk(&image, |h, data| {
+                let mut body = Vec::new();
+                data.read_to_end(&mut body)?;
+                seen.push((h.name.clone(), body.len()));
+                Ok(())
+            })
+            .unwrap();
+            let mut want = vec![("big", 3 * CHUNK)];
+            #[cfg(unix)]
+            want.push(("init", 10));
+            want.extend([("usr", 0), ("usr/bin", 0), ("usr/bin/sh", 7)]);
+            let want: Vec<_> = want.into_iter().map(|(n, l)| (n.to_string(), l)).collect();
+            assert_eq!(seen, want, "{c}");
+        }
+    }
+
+    #[test]
+    fn missing_root_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = pack(
+            &dir.path().join("nope"),
+            Compression::Uncompressed,
+            Vec::new(),
+        );
+        assert!(matches!(err, Err(Error::File { .. })));
+    }
+}
@@ -0,0 +1,8 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! UKI assembly and the TOML build profiles that drive it.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod initramfs;
+#[cfg(feature = "profile")]
+pub mod profile;
+pub mod uki;
@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Curated profiles compiled into lowell, selectable as `builtin:<name>`.
+//!
+//! These are starting points, not policy: copy one out with
+//! `lowell profile show --profile builtin:<name>` and adjust it.
+
+use super::Profile;
+use lowell_formats::error::{Error, Result};
+
+/// (name, TOML source) for every built-in profile.
+const BUILTINS: &[(&str, &str)] = &[
+    ("kvm-virtio", include_str!("builtin/kvm-virtio.toml")),
+    ("cloud-generic", include_str!("builtin/cloud-generic.toml")),
+    (
+        "baremetal-server",
+        include_str!("builtin/baremetal-server.toml"),
+    ),
+    ("raspberry-pi", include_str!("builtin/raspberry-pi.toml")),
+];
+
+/// Names of all built-in profiles, in display order.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|(name, _)| *name)
+}
+
+/// Raw TOML source of a built-in profile, if it exists.
+pub fn source(name: &str) -> Option<&'static str> {
+    BUILTINS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, text)| *text)
+}
+
+/// Parse a built-in profile by name.
+pub fn load(name: &str) -> Result<Profile> {
+    let Some(text) = source(name) else {
+        return Err(Error::UnknownBuiltin {
+            name: name.to_string(),
+            available: names().collect::<Vec<_>>().join(", "),
+        });
+    };
+    Profile::from_toml_str(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_parse_and_names_match() {
+        for name in names() {
+            let p = load(name).expect("built-in profile parses");
+            assert_eq!(p.name, name);
+            assert!(!p.modules.is_empty());
+        }
+    }
+
+    #[test]
+    fn resolve_builtin_prefix_and_unknown_name() {
+        let p = Profile::resolve("builtin:kvm-virtio").expect("resolve builtin");
+        assert_eq!(p.name, "kvm-virtio");
+        assert!(Profile::resolve("builtin:does-not-exist").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Kernel command line as a list of named, ordered fragments.
+//!
+//! ```toml
+//! cmdline = "console=ttyS0,115200n8"          # one fragment named "base"
+//!
+//! [[cmdline]]
+//! name = "base"
+//! args = "console=ttyS0,115200n8 quiet"
+//!
+//! [[cmdline]]
+//! name = "debug"
+//! args = "rd.debug loglevel=7"
+//! order = 50                                  # default 0; ties keep file order
+//! ```
+//!
+//! Fragments are concatenated by `order`, then declaration order; exact
+//! duplicate arguments are dropped (first occurrence wins). Overlays can add
+//! or replace a single fragment by name instead of rewriting the whole line.
+
+use lowell_formats::error::{Error, Result};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Fragment name used for the plain-string form.
+pub const BASE_FRAGMENT: &str = "base";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fragment {
+    pub name: String,
+    pub args: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cmdline {
+    pub fragments: Vec<Fragment>,
+}
+
+impl Cmdline {
+    /// Resolved arguments: ordered and de-duplicated.
+    pub fn args(&self) -> Vec<&str> {
+        let mut frags: Vec<&Fragment> = self.fragments.iter().collect();
+        frags.sort_by_key(|f| f.order.unwrap_or(0));
+        let mut out: Vec<&str> = Vec::new();
+        for arg in frags.iter().flat_map(|f| f.args.split_whitespace()) {
+            if !out.contains(&arg) {
+                out.push(arg);
+            }
+        }
+        out
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args().is_empty()
+    }
+
+    pub fn fragment(&self, name: &str) -> Option<&Fragment> {
+        self.fragments.iter().find(|f| f.name == name)
+    }
+
+    /// Add a fragment, replacing any existing one with the same name in place.
+    pub fn set(&mut self, fragment: Fragment) {
+        match self.fragments.iter_mut().find(|f| f.name == fragment.name) {
+            Some(slot) => *slot = fragment,
+            None => self.fragments.push(fragment),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, f) in self.fragments.iter().enumerate() {
+            if f.name.is_empty() {
+                return Err(Error::Invalid(format!(
+                    "cmdline fragment #{i} has an empty name"
+                )));
+            }
+            if self.fragments[..i].iter().any(|g| g.name == f.name) {
+                return Err(Error::Invalid(format!(
+                    "duplicate cmdline fragment {:?}",
+                    f.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for Cmdline {
+    /// A single `base` fragment (empty input gives an empty cmdline).
+    fn from(args: &str) -> Self {
+        let fragments = if args.trim().is_empty() {
+            Vec::new()
+        } else {
+            vec![Fragment {
+                name: BASE_FRAGMENT.to_string(),
+                args: args.trim().to_string(),
+                order: None,
+            }]
+        };
+        Self { fragments }
+    }
+}
+
+impl fmt::Display for Cmdline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.args().join(" "))
+    }
+}
+
+impl Serialize for Cmdline {
+    /// Plain string when it is just an unordered `base` fragment.
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.fragments.as_slice() {
+            [] => s.serialize_str(""),
+            [Fragment {
+                name,
+                args,
+                order: None,
+            }] if name == BASE_FRAGMENT => s.serialize_str(args),
+            frags => frags.serialize(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Cmdline {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct CmdlineVisitor;
+
+        impl<'de> Visitor<'de> for CmdlineVisitor {
+            type Value = Cmdline;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string or an array of { name, args, order } fragments")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Cmdline, E> {
+                Ok(Cmdline::from(v))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Cmdline, A::Error> {
+                let fragments = Vec::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                let cmdline = Cmdline { fragments };
+                cmdline.validate().map_err(de::Error::custom)?;
+                Ok(cmdline)
+            }
+        }
+
+        d.deserialize_any(CmdlineVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+
+    #[test]
+    fn fragments_order_and_dedup() {
+        let p = Profile::from_toml_str(
+            r#"
+name = "t"
+root = "ostree"
+
+[[cmdline]]
+name = "site"
+args = "quiet systemd.journald.forward_to_console=1"
+order = 90
+
+[[cmdline]]
+name = "base"
+args = "console=tty0 console=ttyS0,115200n8 quiet"
+
+[[cmdline]]
+name = "debug"
+args = "rd.debug"
+order = 50
+"#,
+        )
+        .expect("parse");
+        assert_eq!(
+            p.cmdline.args(),
+            [
+                "console=tty0",
+                "console=ttyS0,115200n8",
+                "quiet",
+                "rd.debug",
+                "systemd.journald.forward_to_console=1"
+            ]
+        );
+        let again = Profile::from_toml_str(&p.to_toml_string().unwrap()).unwrap();
+        assert_eq!(again, p);
+    }
+
+    #[test]
+    fn string_form_is_a_base_fragment_and_set_replaces_by_name() {
+        let mut c = Cmdline::from("console=ttyS0");
+        c.set(Fragment {
+            name: "debug".into(),
+            args: "rd.debug".into(),
+            order: None,
+        });
+        c.set(Fragment {
+            name: BASE_FRAGMENT.into(),
+            args: "console=tty0".into(),
+            order: None,
+        });
+        assert_eq!(c.to_string(), "console=tty0 rd.debug");
+    }
+
+    #[test]
+    fn duplicate_fragment_names_are_rejected() {
+        let text = "name = \"t\"\nroot = \"plain\"\ncmdline = [{ name = \"a\", args = \"x\" }, { name = \"a\", args = \"y\" }]\n";
+        assert!(Profile::from_toml_str(text).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Semantic diff between two resolved profiles.
+//!
+//! Compares what a build would actually use: module *sets*, cmdline
+//! arguments (order matters for e.g. `console=`), root, compression and
+//! artifact paths.
+
+use super::{Profile, RootSpec};
+use lowell_formats::initramfs::Compression;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// A scalar field that differs between the two profiles.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+/// Added/removed items of a list-valued field.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ListDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Same items, different order (only tracked where order is meaningful).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reordered: bool,
+}
+
+impl ListDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && !self.reordered
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ProfileDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Change<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<Change<RootSpec>>,
+    #[serde(skip_serializing_if = "ListDiff::is_empty")]
+    pub modules: ListDiff,
+    #[serde(skip_serializing_if = "ListDiff::is_empty")]
+    pub cmdline: ListDiff,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Change<Option<Compression>>>,
+    /// Artifact entries whose path was added, removed or changed
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub artifacts: BTreeMap<String, Change<Option<PathBuf>>>,
+}
+
+impl ProfileDiff {
+    /// True when the profiles would produce the same build.
+    ///
+    /// A differing `name` alone does not count.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+            && self.modules.is_empty()
+            && self.cmdline.is_empty()
+            && self.compression.is_none()
+            && self.artifacts.is_empty()
+    }
+}
+
+/// Effective changes going from `a` to `b`.
+pub fn diff(a: &Profile, b: &Profile) -> ProfileDiff {
+    let a_args = a.cmdline.args();
+    let b_args = b.cmdline.args();
+    let mut cmdline = set_diff(a_args.iter().copied(), b_args.iter().copied());
+    cmdline.reordered = cmdline.added.is_empty() && cmdline.removed.is_empty() && a_args != b_args;
+
+    ProfileDiff {
+        name: change(&a.name, &b.name),
+        root: change(&a.root, &b.root),
+        modules: set_diff(
+            a.modules.iter().map(String::as_str),
+            b.modules.iter().map(String::as_str),
+        ),
+        cmdline,
+        compression: change(&a.compression, &b.compression),
+        artifacts: a
+            .artifacts
+            .keys()
+            .chain(b.artifacts.keys())
+            .filter_map(|name| {
+                let c = change(
+                    &a.artifacts.get(name).cloned(),
+                    &b.artifacts.get(name).cloned(),
+                )?;
+                Some((name.clone(), c))
+            })
+            .collect(),
+    }
+}
+
+fn change<T: PartialEq + Clone>(from: &T, to: &T) -> Option<Change<T>> {
+    (from != to).then(|| Change {
+        from: from.clone(),
+        to: to.clone(),
+    })
+}
+
+fn set_diff<'a>(
+    from: impl Iterator<Item = &'a str>,
+    to: impl Iterator<Item = &'a str>,
+) -> ListDiff {
+    let from: BTreeSet<&str> = from.collect();
+    let to: BTreeSet<&str> = to.collect();
+    ListDiff {
+        added: to.difference(&from).map(|s| s.to_string()).collect(),
+        removed: from.difference(&to).map(|s| s.to_string()).collect(),
+        reordered: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Cmdline;
+
+    fn profile(modules: &[&str], cmdline: &str) -> Profile {
+        Profile {
+            name: "p".into(),
+            root: RootSpec::Ostree { reference: None },
+            modules: modules.iter().map(|m| m.to_string()).collect(),
+            cmdline: Cmdline::from(cmdline),
+            compression: None,
+            artifacts: Default::default(),
+            fetch: Default::default(),
+        }
+    }
+
+    #[test]
+    fn diff_reports_module_and_cmdline_changes() {
+        let a = profile(&["xfs", "virtio_blk"], "console=tty0 quiet");
+        let b = profile(&["virtio_blk", "nvme"], "console=tty0 rd.debug");
+        let d = diff(&a, &b);
+        assert_eq!(d.modules.added, ["nvme"]);
+        assert_eq!(d.modules.removed, ["xfs"]);
+        assert_eq!(d.cmdline.added, ["rd.debug"]);
+        assert_eq!(d.cmdline.removed, ["quiet"]);
+        assert!(!d.is_empty());
+    }
+
+    #[test]
+    fn diff_ignores_module_order_but_not_cmdline_order() {
+        let a = profile(&["a", "b"], "console=tty0 console=ttyS0");
+        let b = profile(&["b", "a"], "console=ttyS0 console=tty0");
+        let d = diff(&a, &b);
+        assert!(d.modules.is_empty());
+        assert!(d.cmdline.reordered);
+
+        assert!(diff(&a, &a).is_empty());
+    }
+}
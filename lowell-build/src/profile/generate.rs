@@ -0,0 +1,331 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Profile generators: derive a starting [`Profile`] from an existing system.
+//!
+//! Heuristics only — the output is meant to be reviewed and committed, not
+//! trusted blindly. Every probe is read-only.
+
+use super::root::fs_module;
+use super::{Cmdline, Profile, RootSpec};
+use lowell_formats::error::{IoResultExt, Result};
+use lowell_formats::initramfs;
+use lowell_formats::pe::PeFile;
+use lowell_formats::trace::debug;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Loaded-module prefixes worth carrying into an initramfs (storage, fs, dm).
+const BOOT_MODULE_PREFIXES: &[&str] = &[
+    "virtio",
+    "nvme",
+    "ahci",
+    "sd_mod",
+    "scsi_",
+    "dm_",
+    "raid",
+    "md_mod",
+    "xfs",
+    "ext4",
+    "btrfs",
+    "vfat",
+    "mmc",
+    "sdhci",
+    "usb_storage",
+    "uas",
+    "hv_",
+    "xen_",
+    "ena",
+    "megaraid",
+    "mpt3sas",
+];
+
+/// Inspect the running system and capture what it needs to boot.
+pub fn from_host() -> Result<Profile> {
+    from_host_at(Path::new("/"))
+}
+
+/// Like [`from_host`], but read `proc/` and `etc/` below `sysroot`.
+pub fn from_host_at(sysroot: &Path) -> Result<Profile> {
+    let read = |rel: &str| -> Result<Option<String>> {
+        let p = sysroot.join(rel);
+        match std::fs::read_to_string(&p) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).at("read", &p),
+        }
+    };
+
+    let mounts = read("proc/self/mounts")?.unwrap_or_default();
+    let cmdline = read("proc/cmdline")?.unwrap_or_default();
+    let loaded = read("proc/modules")?.unwrap_or_default();
+    let crypttab = read("etc/crypttab")?.unwrap_or_default();
+
+    let mut modules = BTreeSet::new();
+    let mount = root_mount(&mounts);
+    if let Some((source, fstype)) = mount {
+        debug!(source, fstype, "root_mount");
+        modules.extend(fs_module(fstype).map(str::to_string));
+        modules.extend(storage_modules(source).iter().map(|m| m.to_string()));
+    }
+    if crypttab_entries(&crypttab) > 0 {
+        modules.extend(["dm_mod", "dm_crypt"].map(str::to_string));
+    }
+    modules.extend(boot_relevant_modules(&loaded));
+
+    let hostname = read("etc/hostname")?
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "host".to_string());
+
+    let root = root_from_cmdline(&cmdline).unwrap_or_else(|| RootSpec::Plain {
+        device: mount
+            .map(|(source, _)| source.to_string())
+            .filter(|s| s.starts_with("/dev/")),
+        fstype: mount.map(|(_, fstype)| fstype.to_string()),
+    });
+
+    Ok(Profile {
+        name: hostname,
+        root,
+        modules: modules.into_iter().collect(),
+        cmdline: Cmdline::from(console_args(&cmdline).join(" ").as_str()),
+        compression: None,
+        artifacts: Default::default(),
+        fetch: Default::default(),
+    })
+}
+
+/// Reverse-engineer the closest profile from an existing UKI.
+///
+/// Modules come from `lib/modules/**.ko*` entries in `.initrd`, the cmdline
+/// and compression are taken verbatim, and the root flavor is guessed from
+/// the cmdline and initrd contents.
+pub fn from_uki(path: &Path) -> Result<Profile> {
+    let pef = PeFile::from_path(path)?;
+    let cmdline = pef
+        .read_text(".cmdline")?
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let initrd = pef.section(".initrd")?.contents();
+
+    let compression = initramfs::payload_compression(initrd)?;
+    let mut modules = BTreeSet::new();
+    let mut ostree_initrd = false;
+    initramfs::walk(initrd, |h, _| {
+        if let Some(m) = module_name(&h.name) {
+            modules.insert(m);
+        }
+        ostree_initrd |= h.name.contains("ostree-prepare-root");
+        Ok(())
+    })?;
+    debug!(modules = modules.len(), %compression, ostree_initrd, "from_uki");
+
+    let root = match root_from_cmdline(&cmdline) {
+        Some(root) => root,
+        None if ostree_initrd => RootSpec::Ostree { reference: None },
+        None => RootSpec::Plain {
+            device: None,
+            fstype: None,
+        },
+    };
+    // Root arguments are regenerated from `root`; keep the rest verbatim.
+    let cmdline = cmdline
+        .split_whitespace()
+        .filter(|a| !is_root_arg(a))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cmdline = Cmdline::from(cmdline.as_str());
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "uki".to_string());
+
+    Ok(Profile {
+        name,
+        root,
+        modules: modules.into_iter().collect(),
+        cmdline,
+        compression: Some(compression),
+        artifacts: Default::default(),
+        fetch: Default::default(),
+    })
+}
+
+/// Module name for a `lib/modules/<kver>/**/<name>.ko[.xz|.zst|.gz]` path.
+///
+/// Dashes are normalized to underscores, as `modprobe` does.
+fn module_name(path: &str) -> Option<String> {
+    if !path.contains("lib/modules/") {
+        return None;
+    }
+    let file = path.rsplit('/').next()?;
+    let stem = [".ko", ".ko.xz", ".ko.zst", ".ko.gz"]
+        .iter()
+        .find_map(|ext| file.strip_suffix(ext))?;
+    Some(stem.replace('-', "_"))
+}
+
+/// `(source, fstype)` of the `/` mount from `/proc/self/mounts` text.
+///
+/// The last matching line wins, mirroring how the kernel stacks mounts.
+fn root_mount(mounts: &str) -> Option<(&str, &str)> {
+    mounts
+        .lines()
+        .filter_map(|l| {
+            let mut it = l.split_whitespace();
+            let (src, target, fstype) = (it.next()?, it.next()?, it.next()?);
+            (target == "/" && fstype != "rootfs").then_some((src, fstype))
+        })
+        .next_back()
+}
+
+/// Block-layer modules implied by the root device path.
+fn storage_modules(source: &str) -> &'static [&'static str] {
+    let dev = source.strip_prefix("/dev/").unwrap_or(source);
+    if dev.starts_with("mapper/") || dev.starts_with("dm-") {
+        &["dm_mod"]
+    } else if dev.starts_with("md") {
+        &["md_mod", "raid1"]
+    } else if dev.starts_with("nvme") {
+        &["nvme"]
+    } else if dev.starts_with("vd") {
+        &["virtio_blk"]
+    } else if dev.starts_with("sd") {
+        &["sd_mod"]
+    } else if dev.starts_with("mmcblk") {
+        &["mmc_block"]
+    } else {
+        &[]
+    }
+}
+
+/// Count of non-comment lines in a crypttab.
+fn crypttab_entries(crypttab: &str) -> usize {
+    crypttab
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .count()
+}
+
+/// Loaded modules (from `/proc/modules`) that look boot-relevant.
+fn boot_relevant_modules(proc_modules: &str) -> impl Iterator<Item = String> + '_ {
+    proc_modules
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .filter(|m| BOOT_MODULE_PREFIXES.iter().any(|p| m.starts_with(p)))
+        .map(str::to_string)
+}
+
+/// Root specification implied by a booted/embedded kernel command line.
+///
+/// `None` if the cmdline says nothing about the root.
+fn root_from_cmdline(cmdline: &str) -> Option<RootSpec> {
+    let arg = |key: &str| {
+        cmdline
+            .split_whitespace()
+            .find_map(|a| a.strip_prefix(key))
+            .map(str::to_string)
+    };
+    if arg("ostree=").is_some() {
+        return Some(RootSpec::Ostree { reference: None });
+    }
+    if let Some(digest) = arg("composefs=") {
+        let spec = RootSpec::Composefs { digest };
+        return spec.validate().is_ok().then_some(spec);
+    }
+    let device = arg("root=")?;
+    let spec = RootSpec::Plain {
+        device: Some(device),
+        fstype: arg("rootfstype="),
+    };
+    spec.validate().is_ok().then_some(spec)
+}
+
+/// Arguments owned by [`RootSpec::cmdline_args`] (or the ostree deployment).
+fn is_root_arg(arg: &str) -> bool {
+    ["root=", "rootfstype=", "ostree=", "composefs="]
+        .iter()
+        .any(|k| arg.starts_with(k))
+}
+
+/// The `console=` arguments of a kernel command line, in order.
+fn console_args(cmdline: &str) -> Vec<&str> {
+    cmdline
+        .split_whitespace()
+        .filter(|a| a.starts_with("console="))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_host_at_fake_sysroot() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("proc/self")).unwrap();
+        std::fs::create_dir_all(root.join("etc")).unwrap();
+        std::fs::write(
+            root.join("proc/self/mounts"),
+            "rootfs / rootfs rw 0 0\n/dev/mapper/luks-root / xfs rw,relatime 0 0\nproc /proc proc rw 0 0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("proc/cmdline"),
+            "BOOT_IMAGE=/vmlinuz ostree=/ostree/boot.1/fedora/abc/0 console=tty0 console=ttyS0,115200n8 quiet\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("proc/modules"),
+            "nvme 65536 2 - Live 0x0\nsnd_hda_intel 61440 0 - Live 0x0\nvirtio_net 77824 0 - Live 0x0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("etc/crypttab"),
+            "# comment\nluks-root UUID=1234 none discard\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("etc/hostname"), "edge01\n").unwrap();
+
+        let p = from_host_at(root).expect("generate");
+        assert_eq!(p.name, "edge01");
+        assert_eq!(p.root, RootSpec::Ostree { reference: None });
+        assert_eq!(p.cmdline.to_string(), "console=tty0 console=ttyS0,115200n8");
+        assert_eq!(
+            p.modules,
+            ["dm_crypt", "dm_mod", "nvme", "virtio_net", "xfs"]
+        );
+    }
+
+    #[test]
+    fn module_names_from_initrd_paths() {
+        assert_eq!(
+            module_name("usr/lib/modules/6.11.4/kernel/drivers/block/virtio_blk.ko.xz").as_deref(),
+            Some("virtio_blk")
+        );
+        assert_eq!(
+            module_name("lib/modules/6.1/kernel/drivers/md/dm-crypt.ko").as_deref(),
+            Some("dm_crypt")
+        );
+        assert_eq!(module_name("usr/lib/modules/6.11.4/modules.dep"), None);
+        assert_eq!(module_name("usr/bin/not-a-module.ko"), None);
+    }
+
+    #[test]
+    fn from_host_at_empty_sysroot_is_plain() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let p = from_host_at(dir.path()).expect("generate");
+        assert_eq!(p.name, "host");
+        assert_eq!(
+            p.root,
+            RootSpec::Plain {
+                device: None,
+                fstype: None
+            }
+        );
+        assert!(p.modules.is_empty());
+        assert!(p.cmdline.is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Import dracut configuration (`dracut.conf`, `dracut.conf.d/*.conf`,
+//! read in name order). What has a lowell equivalent is translated:
+//!
+//! * `add_drivers`, `force_drivers` → `modules`; `omit_drivers` removes
+//! * `compress` → `compression` (`gzip`/`pigz`, `xz`, `zstd`, `cat`)
+//! * `kernel_cmdline` → `cmdline`
+//! * `hostonly="yes"` → the root and modules [`generate::from_host_at`]
+//!   finds on the system at `sysroot`
+//!
+//! The rest (`omit_dracutmodules`, `install_items`, unknown keys) has no
+//! lowell counterpart and comes back as [`Imported::notes`], to be handled
+//! by hand.
+
+use super::{compression, conf_files, parse, words, Imported};
+use crate::profile::{generate, Cmdline, Profile, RootSpec};
+use lowell_formats::error::{IoResultExt, Result};
+use lowell_formats::trace::debug;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Keys that are translated (or explained) rather than reported unknown.
+const KNOWN: &[&str] = &[
+    "add_drivers",
+    "force_drivers",
+    "omit_drivers",
+    "compress",
+    "kernel_cmdline",
+    "hostonly",
+    "omit_dracutmodules",
+    "install_items",
+];
+
+/// Import `path`, a dracut configuration file or a `dracut.conf.d`
+/// directory, probing `sysroot` for a host-only configuration.
+pub fn import(path: &Path, sysroot: &Path) -> Result<Imported> {
+    let files = match path.is_dir() {
+        true => conf_files(path)?,
+        false => vec![path.to_path_buf()],
+    };
+    let mut vars = BTreeMap::new();
+    for file in files {
+        debug!(file = %file.display(), "dracut_conf");
+        let text = std::fs::read_to_string(&file).at("read", &file)?;
+        parse(&text, &mut vars);
+    }
+    translate(&vars, sysroot)
+}
+
+fn translate(vars: &BTreeMap<String, String>, sysroot: &Path) -> Result<Imported> {
+    let words = |key: &str| words(vars, key);
+    let mut notes = Vec::new();
+
+    let hostonly = vars
+        .get("hostonly")
+        .is_some_and(|v| matches!(v.trim(), "yes" | "1" | "true"));
+    let (root, mut modules) = match hostonly {
+        true => {
+            let host = generate::from_host_at(sysroot)?;
+            notes.push(format!(
+                "hostonly: root and modules detected on {}",
+                sysroot.display()
+            ));
+            (host.root, host.modules)
+        }
+        false => (
+            RootSpec::Plain {
+                device: None,
+                fstype: None,
+            },
+            Vec::new(),
+        ),
+    };
+    for m in words("add_drivers")
+        .into_iter()
+        .chain(words("force_drivers"))
+    {
+        let m = m.replace('-', "_");
+        if !modules.contains(&m) {
+            modules.push(m);
+        }
+    }
+    let omit: Vec<String> = words("omit_drivers")
+        .iter()
+        .map(|m| m.replace('-', "_"))
+        .collect();
+    modules.retain(|m| !omit.contains(m));
+
+    let compression = match vars.get("compress") {
+        None => None,
+        Some(command) => {
+            let compression = compression(command);
+            if compression.is_none() {
+                notes.push(format!(
+                    "compress={command:?}: not supported, the builder default is used"
+                ));
+            }
+            compression
+        }
+    };
+
+    let omitted = words("omit_dracutmodules");
+    if !omitted.is_empty() {
+        notes.push(format!(
+            "omit_dracutmodules: lowell has no dracut modules, dropped: {}",
+            omitted.join(" ")
+        ));
+    }
+    let items = words("install_items");
+    if !items.is_empty() {
+        notes.push(format!(
+            "install_items: add to the initrd directory by hand: {}",
+            items.join(" ")
+        ));
+    }
+    let unknown: Vec<&str> = vars
+        .keys()
+        .map(String::as_str)
+        .filter(|k| !KNOWN.contains(k))
+        .collect();
+    if !unknown.is_empty() {
+        notes.push(format!("not translated: {}", unknown.join(", ")));
+    }
+
+    let cmdline = vars
+        .get("kernel_cmdline")
+        .map(|c| c.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default();
+    Ok(Imported {
+        profile: Profile {
+            name: "dracut".to_string(),
+            root,
+            modules,
+            cmdline: Cmdline::from(cmdline.as_str()),
+            compression,
+            artifacts: Default::default(),
+            fetch: Default::default(),
+        },
+        notes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_formats::initramfs::Compression;
+
+    #[test]
+    fn conf_d_translates_in_name_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf = dir.path().join("dracut.conf.d");
+        std::fs::create_dir(&conf).unwrap();
+        std::fs::write(
+            conf.join("10-drivers.conf"),
+            "# storage\nadd_drivers+=\" virtio_blk nvme \"\nforce_drivers+=\" dm-crypt \"\n\
+             compress=\"xz -9\"\nomit_dracutmodules+=\" plymouth\n  nfs \"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            conf.join("20-site.conf"),
+            "add_drivers+=\" e1000e \"\nomit_drivers+=\" nvme \"\ncompress=zstd # fast\n\
+             kernel_cmdline=\"console=ttyS0  quiet\"\ninstall_items+=\" /etc/site.key \"\n\
+             early_microcode=yes\n",
+        )
+        .unwrap();
+        std::fs::write(conf.join("README"), "add_drivers=ignored\n").unwrap();
+
+        let imported = import(&conf, dir.path()).unwrap();
+        let p = &imported.profile;
+        assert_eq!(p.modules, ["virtio_blk", "e1000e", "dm_crypt"]);
+        assert_eq!(p.compression, Some(Compression::Zstd));
+        assert_eq!(p.cmdline.to_string(), "console=ttyS0 quiet");
+        assert_eq!(p.root.kind(), "plain");
+        assert_eq!(
+            imported.notes,
+            [
+                "omit_dracutmodules: lowell has no dracut modules, dropped: plymouth nfs",
+                "install_items: add to the initrd directory by hand: /etc/site.key",
+                "not translated: early_microcode",
+            ]
+        );
+    }
+
+    #[test]
+    fn hostonly_probes_the_sysroot() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("proc/self")).unwrap();
+        std::fs::write(
+            dir.path().join("proc/self/mounts"),
+            "/dev/vda3 / ext4 rw 0 0\n",
+        )
+        .unwrap();
+        let conf = dir.path().join("dracut.conf");
+        std::fs::write(&conf, "hostonly=\"yes\"\nadd_drivers+=\" ext4 \"\n").unwrap();
+        let p = import(&conf, dir.path()).unwrap().profile;
+        assert_eq!(p.modules, ["ext4", "virtio_blk"]);
+        assert_eq!(p.compression, None);
+    }
+}
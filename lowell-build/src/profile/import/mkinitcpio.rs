@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Import mkinitcpio configuration (`/etc/mkinitcpio.conf` and its
+//! `mkinitcpio.conf.d/*.conf` drop-ins). What has a lowell equivalent is
+//! translated:
+//!
+//! * `MODULES` → `modules` (optional `name?` entries included)
+//! * `COMPRESSION` → `compression` (`gzip`, `xz`, `zstd`, `cat`)
+//! * `HOOKS`: `autodetect` → the root and modules
+//!   [`generate::from_host_at`] finds on the system at `sysroot`;
+//!   `encrypt`/`sd-encrypt`, `lvm2` and `mdadm_udev` → the device-mapper
+//!   and md modules they load
+//!
+//! `BINARIES`, `FILES`, hooks that install more than modules (`plymouth`,
+//! `keymap`, ...) and unknown keys come back as notes. Hooks whose work a
+//! lowell build does anyway (`base`, `udev`, `systemd`, `block`, ...) are
+//! dropped silently.
+
+use super::{compression, conf_files, parse, words, Imported};
+use crate::profile::{generate, Cmdline, Profile, RootSpec};
+use lowell_formats::error::{IoResultExt, Result};
+use lowell_formats::trace::debug;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Keys that are translated (or explained) rather than reported unknown.
+const KNOWN: &[&str] = &["MODULES", "BINARIES", "FILES", "HOOKS", "COMPRESSION"];
+
+/// Hooks that need nothing from a profile.
+const IMPLIED_HOOKS: &[&str] = &[
+    "base",
+    "udev",
+    "systemd",
+    "modconf",
+    "block",
+    "filesystems",
+    "fsck",
+    "kms",
+    "keyboard",
+    "microcode",
+];
+
+/// Modules a hook loads.
+fn hook_modules(hook: &str) -> Option<&'static [&'static str]> {
+    match hook {
+        "encrypt" | "sd-encrypt" => Some(&["dm_mod", "dm_crypt"]),
+        "lvm2" => Some(&["dm_mod"]),
+        "mdadm_udev" | "mdadm" => Some(&["md_mod", "raid1"]),
+        _ => None,
+    }
+}
+
+/// Import `path`: `mkinitcpio.conf` (then the drop-ins in
+/// `mkinitcpio.conf.d` beside it), or a drop-in directory alone, probing
+/// `sysroot` when the `autodetect` hook is on.
+pub fn import(path: &Path, sysroot: &Path) -> Result<Imported> {
+    let mut files = Vec::new();
+    let drop_ins = match path.is_dir() {
+        true => Some(path.to_path_buf()),
+        false => {
+            files.push(path.to_path_buf());
+            let mut dir = path.as_os_str().to_owned();
+            dir.push(".d");
+            Some(PathBuf::from(dir)).filter(|d| d.is_dir())
+        }
+    };
+    if let Some(dir) = drop_ins {
+        files.extend(conf_files(&dir)?);
+    }
+    let mut vars = BTreeMap::new();
+    for file in files {
+        debug!(file = %file.display(), "mkinitcpio_conf");
+        let text = std::fs::read_to_string(&file).at("read", &file)?;
+        parse(&text, &mut vars);
+    }
+    translate(&vars, sysroot)
+}
+
+fn translate(vars: &BTreeMap<String, String>, sysroot: &Path) -> Result<Imported> {
+    let words = |key: &str| words(vars, key);
+    let mut notes = Vec::new();
+    let hooks = words("HOOKS");
+
+    let (root, mut modules) = match hooks.iter().any(|h| h == "autodetect") {
+        true => {
+            let host = generate::from_host_at(sysroot)?;
+            notes.push(format!(
+                "autodetect: root and modules detected on {}",
+                sysroot.display()
+            ));
+            (host.root, host.modules)
+        }
+        false => (
+            RootSpec::Plain {
+                device: None,
+                fstype: None,
+            },
+            Vec::new(),
+        ),
+    };
+    let mut add = |m: &str| {
+        let m = m.trim_end_matches('?').replace('-', "_");
+        if !m.is_empty() && !modules.contains(&m) {
+            modules.push(m);
+        }
+    };
+    for m in words("MODULES") {
+        add(&m);
+    }
+    let mut untranslated = Vec::new();
+    for hook in &hooks {
+        match hook_modules(hook) {
+            Some(implied) => implied.iter().for_each(|m| add(m)),
+            None if hook == "autodetect" || IMPLIED_HOOKS.contains(&hook.as_str()) => {}
+            None => untranslated.push(hook.as_str()),
+        }
+    }
+    if !untranslated.is_empty() {
+        notes.push(format!("HOOKS not translated: {}", untranslated.join(" ")));
+    }
+
+    let compression = match vars.get("COMPRESSION") {
+        None => None,
+        Some(command) => {
+            let compression = compression(command);
+            if compression.is_none() {
+                notes.push(format!(
+                    "COMPRESSION={command:?}: not supported, the builder default is used"
+                ));
+            }
+            compression
+        }
+    };
+
+    for key in ["BINARIES", "FILES"] {
+        let items = words(key);
+        if !items.is_empty() {
+            notes.push(format!(
+                "{key}: add to the initrd directory by hand: {}",
+                items.join(" ")
+            ));
+        }
+    }
+    let unknown: Vec<&str> = vars
+        .keys()
+        .map(String::as_str)
+        .filter(|k| !KNOWN.contains(k))
+        .collect();
+    if !unknown.is_empty() {
+        notes.push(format!("not translated: {}", unknown.join(", ")));
+    }
+
+    Ok(Imported {
+        profile: Profile {
+            name: "mkinitcpio".to_string(),
+            root,
+            modules,
+            cmdline: Cmdline::default(),
+            compression,
+            artifacts: Default::default(),
+            fetch: Default::default(),
+        },
+        notes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_formats::initramfs::Compression;
+
+    #[test]
+    fn conf_and_drop_ins_translate() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf = dir.path().join("mkinitcpio.conf");
+        std::fs::write(
+            &conf,
+            "# vim:set ft=sh\nMODULES=(virtio_blk i915?)\nBINARIES=()\nFILES=(/etc/crypttab)\n\
+             HOOKS=(base systemd keyboard sd-vconsole\n       block sd-encrypt filesystems fsck)\n\
+             COMPRESSION=\"xz\"\n#COMPRESSION_OPTIONS=()\n",
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join("mkinitcpio.conf.d")).unwrap();
+        std::fs::write(
+            dir.path().join("mkinitcpio.conf.d/10-site.conf"),
+            "MODULES+=(nvme)\nCOMPRESSION=\"lz4\"\nMODULES_DECOMPRESS=\"yes\"\n",
+        )
+        .unwrap();
+
+        let imported = import(&conf, dir.path()).unwrap();
+        let p = &imported.profile;
+        assert_eq!(
+            p.modules,
+            ["virtio_blk", "i915", "nvme", "dm_mod", "dm_crypt"]
+        );
+        assert_eq!(p.compression, None);
+        assert_eq!(p.root.kind(), "plain");
+        assert_eq!(
+            imported.notes,
+            [
+                "HOOKS not translated: sd-vconsole",
+                "COMPRESSION=\"lz4\": not supported, the builder default is used",
+                "FILES: add to the initrd directory by hand: /etc/crypttab",
+                "not translated: MODULES_DECOMPRESS",
+            ]
+        );
+
+        std::fs::remove_dir_all(dir.path().join("mkinitcpio.conf.d")).unwrap();
+        std::fs::write(
+            &conf,
+            "HOOKS=(base udev autodetect block)\nCOMPRESSION=zstd\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.path().join("proc/self")).unwrap();
+        std::fs::write(
+            dir.path().join("proc/self/mounts"),
+            "/dev/nvme0n1p2 / btrfs rw 0 0\n",
+        )
+        .unwrap();
+        let p = import(&conf, dir.path()).unwrap().profile;
+        assert_eq!(p.modules, ["btrfs", "nvme"]);
+        assert_eq!(p.compression, Some(Compression::Zstd));
+    }
+}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Importers: translate another initramfs generator's configuration into a
+//! starting [`Profile`](super::Profile).
+//!
+//! Both dracut and mkinitcpio source their configuration as shell, so one
+//! reader serves both: `key=value`, `key="value"` and `key=(a b)` assign,
+//! `key+=...` appends, and later files win. What has no lowell equivalent
+//! comes back as [`Imported::notes`], to be handled by hand.
+
+pub mod dracut;
+pub mod mkinitcpio;
+
+use super::Profile;
+use lowell_formats::error::{IoResultExt, Result};
+use lowell_formats::initramfs::Compression;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// An imported profile and what could not be carried over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Imported {
+    pub profile: Profile,
+    pub notes: Vec<String>,
+}
+
+/// The `*.conf` files in `dir` in name order.
+fn conf_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).at("read", dir)? {
+        let file = entry.at("read", dir)?.path();
+        if file.extension().is_some_and(|e| e == "conf") && file.is_file() {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Apply the assignments in `text` to `vars`. Quoted values and arrays may
+/// span lines (array items are kept space-separated); anything that is not
+/// an assignment is skipped.
+fn parse(text: &str, vars: &mut BTreeMap<String, String>) {
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, append) = match key.strip_suffix('+') {
+            Some(key) => (key.trim(), true),
+            None => (key.trim(), false),
+        };
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            continue;
+        }
+        let mut value = value.trim().to_string();
+        let (open, close) = match value.chars().next() {
+            Some(q @ ('"' | '\'')) => (q, q),
+            Some('(') => ('(', ')'),
+            _ => (' ', ' '),
+        };
+        let value = match open {
+            ' ' => value
+                .split_once(" #")
+                .map_or(value.as_str(), |(v, _)| v)
+                .trim()
+                .to_string(),
+            _ => {
+                let end = loop {
+                    if let Some(end) = value[1..].find(close) {
+                        break end + 1;
+                    }
+                    match lines.next() {
+                        Some(more) => {
+                            value.push('\n');
+                            value.push_str(strip_comment(more));
+                        }
+                        None => break value.len(),
+                    }
+                };
+                let inner = &value[1..end];
+                match open {
+                    '(' => array_items(inner).join(" "),
+                    _ => inner.to_string(),
+                }
+            }
+        };
+        match (append, open) {
+            (true, '(') => {
+                let joined = vars.entry(key.to_string()).or_default();
+                if !joined.is_empty() && !value.is_empty() {
+                    joined.push(' ');
+                }
+                joined.push_str(&value);
+            }
+            (true, _) => vars.entry(key.to_string()).or_default().push_str(&value),
+            (false, _) => {
+                vars.insert(key.to_string(), value);
+            }
+        }
+    }
+}
+
+/// The line before a `#` comment that starts a word.
+fn strip_comment(line: &str) -> &str {
+    match line.trim_start().starts_with('#') {
+        true => "",
+        false => line.split_once(" #").map_or(line, |(v, _)| v),
+    }
+}
+
+/// The words of an array body, unquoted.
+fn array_items(body: &str) -> Vec<String> {
+    body.split_whitespace()
+        .map(|w| w.trim_matches(['"', '\'']).to_string())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+/// The compression a compressor command line asks for, if lowell has it.
+fn compression(command: &str) -> Option<Compression> {
+    match command.split_whitespace().next()? {
+        "gzip" | "pigz" => Some(Compression::Gzip),
+        "xz" => Some(Compression::Xz),
+        "zstd" => Some(Compression::Zstd),
+        "cat" => Some(Compression::Uncompressed),
+        _ => None,
+    }
+}
+
+/// The whitespace-separated words of `key`'s value.
+fn words(vars: &BTreeMap<String, String>, key: &str) -> Vec<String> {
+    vars.get(key)
+        .map(|v| v.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_assignments_and_arrays() {
+        let mut vars = BTreeMap::new();
+        parse(
+            "A=plain # comment\nB=\"two words\"\nC=(x \"y\"\n  # skipped\n  z)\n\
+             C+=(w)\nB+=\" more\"\nnot an assignment\nD=()\n",
+            &mut vars,
+        );
+        assert_eq!(vars["A"], "plain");
+        assert_eq!(vars["B"], "two words more");
+        assert_eq!(vars["C"], "x y z w");
+        assert_eq!(vars["D"], "");
+    }
+}
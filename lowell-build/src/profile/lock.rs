@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `profile.lock`: pinned digests of everything a profile resolves to.
+//!
+//! Cargo-style reproducibility for boot artifacts: [`Lockfile::generate`]
+//! records the profile digest plus a sha256 for every entry of the
+//! profile's `[artifacts]` table; [`Lockfile::drift`] reports anything that
+//! no longer matches so `--locked` callers can refuse to proceed.
+
+use super::Profile;
+use lowell_formats::error::{Error, IoResultExt, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Bumped on incompatible lockfile layout changes.
+pub const LOCKFILE_VERSION: u32 = 1;
+
+const HEADER: &str = "# This file is generated by `lowell profile lock`. Do not edit.\n";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Lockfile {
+    pub version: u32,
+    pub profile: String,
+    /// sha256 of the profile's canonical TOML form
+    pub profile_sha256: String,
+    #[serde(default, rename = "artifact")]
+    pub artifacts: Vec<LockedArtifact>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LockedArtifact {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// One way the current inputs differ from the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    Version {
+        locked: u32,
+    },
+    Profile,
+    Added {
+        name: String,
+    },
+    Removed {
+        name: String,
+    },
+    Changed {
+        name: String,
+        locked: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Version { locked } => {
+                write!(f, "lockfile version {locked} (expected {LOCKFILE_VERSION})")
+            }
+            Drift::Profile => f.write_str("profile contents changed"),
+            Drift::Added { name } => write!(f, "artifact {name:?} is not locked"),
+            Drift::Removed { name } => write!(f, "locked artifact {name:?} is no longer used"),
+            Drift::Changed {
+                name,
+                locked,
+                actual,
+            } => write!(f, "artifact {name:?} sha256 {actual} (locked {locked})"),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Hash the profile and every artifact it references.
+    ///
+    /// Relative artifact paths are resolved against `base_dir`
+    /// (normally the directory holding the profile).
+    pub fn generate(profile: &Profile, base_dir: &Path) -> Result<Self> {
+        let artifacts = profile
+            .artifacts
+            .iter()
+            .map(|(name, path)| {
+                let (size, sha256) = hash_file(&base_dir.join(path))?;
+                Ok(LockedArtifact {
+                    name: name.clone(),
+                    path: path.clone(),
+                    size,
+                    sha256,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            version: LOCKFILE_VERSION,
+            profile: profile.name.clone(),
+            profile_sha256: profile_digest(profile)?,
+            artifacts,
+        })
+    }
+
+    /// Compare against freshly computed inputs; empty means no drift.
+    pub fn drift(&self, profile: &Profile, base_dir: &Path) -> Result<Vec<Drift>> {
+        if self.version != LOCKFILE_VERSION {
+            return Ok(vec![Drift::Version {
+                locked: self.version,
+            }]);
+        }
+        let current = Self::generate(profile, base_dir)?;
+        let mut drift = Vec::new();
+        if current.profile_sha256 != self.profile_sha256 {
+            drift.push(Drift::Profile);
+        }
+
+        let locked: BTreeMap<&str, &LockedArtifact> = self
+            .artifacts
+            .iter()
+            .map(|a| (a.name.as_str(), a))
+            .collect();
+        let actual: BTreeMap<&str, &LockedArtifact> = current
+            .artifacts
+            .iter()
+            .map(|a| (a.name.as_str(), a))
+            .collect();
+        for (name, a) in &actual {
+            match locked.get(name) {
+                None => drift.push(Drift::Added {
+                    name: name.to_string(),
+                }),
+                Some(l) if l.sha256 != a.sha256 => drift.push(Drift::Changed {
+                    name: name.to_string(),
+                    locked: l.sha256.clone(),
+                    actual: a.sha256.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        for name in locked.keys().filter(|n| !actual.contains_key(*n)) {
+            drift.push(Drift::Removed {
+                name: name.to_string(),
+            });
+        }
+        Ok(drift)
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).at("read", path)?;
+        toml::from_str(&text).map_err(|source| Error::InvalidLockfile {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        })
+    }
+
+    pub fn to_toml_string(&self) -> Result<String> {
+        Ok(format!("{HEADER}{}", toml::to_string(self)?))
+    }
+}
+
+/// sha256 over the profile's canonical TOML serialization.
+fn profile_digest(profile: &Profile) -> Result<String> {
+    Ok(format!(
+        "{:x}",
+        Sha256::digest(profile.to_toml_string()?.as_bytes())
+    ))
+}
+
+fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let mut file = std::fs::File::open(path).at("open", path)?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut file, &mut hasher).at("read", path)?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::cmdline::Fragment;
+
+    #[test]
+    fn lock_roundtrip_and_detect_drift() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(dir.path().join("vmlinuz"), b"kernel v1").unwrap();
+        let mut profile = Profile::resolve("builtin:kvm-virtio").unwrap();
+        profile
+            .artifacts
+            .insert("kernel".into(), PathBuf::from("vmlinuz"));
+
+        let lock = Lockfile::generate(&profile, dir.path()).expect("generate");
+        let text = lock.to_toml_string().unwrap();
+        let parsed: Lockfile = toml::from_str(&text).expect("parse lockfile");
+        assert_eq!(parsed, lock);
+        assert!(parsed.drift(&profile, dir.path()).unwrap().is_empty());
+
+        std::fs::write(dir.path().join("vmlinuz"), b"kernel v2").unwrap();
+        profile.cmdline.set(Fragment {
+            name: "debug".into(),
+            args: "rd.debug".into(),
+            order: None,
+        });
+        let drift = parsed.drift(&profile, dir.path()).unwrap();
+        assert_eq!(drift.len(), 2);
+        assert_eq!(drift[0], Drift::Profile);
+        assert!(matches!(&drift[1], Drift::Changed { name, .. } if name == "kernel"));
+    }
+}
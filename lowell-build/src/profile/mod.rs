@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Build profiles: what a target machine needs in its initramfs/UKI.
+//!
+//! A profile is a small TOML document (see `profiles/` in the repo root):
+//!
+//! ```toml
+//! name = "kvm-ostree"
+//! root = "ostree"
+//! modules = ["virtio_blk", "virtio_net", "xfs", "ext4"]
+//! cmdline = "console=ttyS0,115200n8"
+//! ```
+//!
+//! An optional `[artifacts]` table names input files (paths relative to the
+//! profile); `lowell profile lock` pins their digests in a lockfile. Inputs
+//! that live elsewhere go in `[fetch]` instead, pinned in the profile itself:
+//!
+//! ```toml
+//! [fetch.kernel]
+//! url = "https://example.com/vmlinuz-6.12.0"
+//! sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! signature = "https://example.com/vmlinuz-6.12.0.sig"  # optional
+//! key = "vendor.pub"                                    # required with it
+//! ```
+//!
+//! Profiles are referenced by a *spec* string: a filesystem path,
+//! `builtin:<name>` for one of the curated profiles shipped with lowell, or a
+//! bare name looked up as `<dir>/<name>.toml` in configured search paths.
+
+pub mod builtin;
+pub mod cmdline;
+pub mod diff;
+pub mod generate;
+pub mod import;
+pub mod lock;
+pub mod ostree;
+pub mod root;
+
+pub use cmdline::Cmdline;
+use lowell_formats::error::{Error, IoResultExt, Result};
+use lowell_formats::initramfs::Compression;
+pub use root::RootSpec;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Prefix selecting a built-in profile instead of a file path.
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    /// Root filesystem specification (see [`root::RootSpec`])
+    #[serde(
+        deserialize_with = "root::deserialize",
+        serialize_with = "root::serialize"
+    )]
+    pub root: RootSpec,
+    /// Kernel modules to include in the initramfs
+    #[serde(default)]
+    pub modules: Vec<String>,
+    /// Kernel command line embedded in the UKI (string or named fragments)
+    #[serde(default)]
+    pub cmdline: Cmdline,
+    /// Initramfs compression (builder default when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Named input files (kernel, stub, ...), pinned by `profile.lock`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub artifacts: BTreeMap<String, PathBuf>,
+    /// Named inputs downloaded (and verified) before use, by the same keys
+    /// as `artifacts`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fetch: BTreeMap<String, Remote>,
+}
+
+/// Where a `[fetch]` input comes from and what it must hash to.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Remote {
+    /// `https://`, `http://` or `file://` URL
+    pub url: String,
+    /// Expected sha256 of the download (hex)
+    pub sha256: String,
+    /// URL of a detached signature over the download (cosign `--key` style)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Public key (PEM) the signature must verify with, relative to the profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+}
+
+impl Profile {
+    /// Parse a profile from TOML text.
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|source| Error::InvalidProfile {
+            path: None,
+            source: Box::new(source),
+        })
+    }
+
+    /// Read and parse a profile TOML file.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).at("read", path)?;
+        toml::from_str(&text).map_err(|source| Error::InvalidProfile {
+            path: Some(path.to_path_buf()),
+            source: Box::new(source),
+        })
+    }
+
+    /// Resolve a profile spec: `builtin:<name>` or a path to a TOML file.
+    pub fn resolve(spec: &str) -> Result<Self> {
+        Source::locate(spec, &[])?.load()
+    }
+
+    /// Full kernel command line: root arguments first, then the resolved
+    /// `cmdline` fragments.
+    pub fn kernel_cmdline(&self) -> String {
+        let mut args = self.root.cmdline_args();
+        for arg in self.cmdline.args() {
+            if !args.iter().any(|a| a == arg) {
+                args.push(arg.to_string());
+            }
+        }
+        args.join(" ")
+    }
+
+    /// `modules` plus whatever the root handler needs, without duplicates.
+    pub fn effective_modules(&self) -> Vec<String> {
+        let mut out = self.modules.clone();
+        for m in self.root.required_modules() {
+            if !out.iter().any(|have| have == m) {
+                out.push(m.to_string());
+            }
+        }
+        out
+    }
+
+    /// Serialize back to TOML.
+    pub fn to_toml_string(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+/// Where a profile spec points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Builtin(String),
+    File(PathBuf),
+}
+
+impl Source {
+    /// Interpret `spec`, consulting `search_paths` for bare names.
+    ///
+    /// An existing path always wins; otherwise a spec without a path
+    /// separator or `.toml` suffix is tried as `<dir>/<spec>.toml` in order.
+    pub fn locate(spec: &str, search_paths: &[PathBuf]) -> Result<Self> {
+        if let Some(name) = spec.strip_prefix(BUILTIN_PREFIX) {
+            return Ok(Source::Builtin(name.to_string()));
+        }
+        let path = Path::new(spec);
+        let bare = !spec.contains(std::path::MAIN_SEPARATOR) && !spec.ends_with(".toml");
+        if path.exists() || !bare {
+            return Ok(Source::File(path.to_path_buf()));
+        }
+        search_paths
+            .iter()
+            .map(|dir| dir.join(format!("{spec}.toml")))
+            .find(|candidate| candidate.is_file())
+            .map(Source::File)
+            .ok_or_else(|| Error::ProfileNotFound {
+                spec: spec.to_string(),
+                searched: search_paths.len(),
+            })
+    }
+
+    pub fn load(&self) -> Result<Profile> {
+        match self {
+            Source::Builtin(name) => builtin::load(name),
+            Source::File(path) => Profile::from_path(path),
+        }
+    }
+}
@@ -0,0 +1,328 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! OSTree deployments, for profiles with `root = "ostree"`.
+//!
+//! A deployed sysroot (`/` on a booted host, or the tree an image build
+//! populated) has one BLS entry per deployment in
+//! `boot/loader/entries/ostree-*.conf`. Its `options` are the deployment's
+//! kernel arguments, including the `ostree=/ostree/boot.N/<stateroot>/
+//! <bootcsum>/<serial>` link that `ostree-prepare-root` follows from the
+//! initramfs; its `version` orders deployments, highest first (the one
+//! that boots by default). Following that link inside the sysroot leads to
+//! the deployment, `ostree/deploy/<stateroot>/deploy/<checksum>.<serial>`,
+//! whose `.origin` file names the ref it was deployed from and whose
+//! `usr/lib/modules/<kver>/` holds the kernel and the initramfs (built
+//! with ostree's dracut module, so it can set up the root).
+//!
+//! [`Deployment::apply`] puts all of that into a profile, so nobody has to
+//! copy `ostree=/ostree/boot.1/...` out of a running system by hand.
+
+use super::cmdline::Fragment;
+use super::{Profile, RootSpec};
+use lowell_formats::error::{Error, IoResultExt, Result};
+use std::path::{Path, PathBuf};
+
+/// Name of the cmdline fragment [`Deployment::apply`] sets.
+pub const FRAGMENT: &str = "ostree";
+
+/// One deployment of a sysroot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deployment {
+    pub stateroot: String,
+    pub checksum: String,
+    pub serial: u32,
+    /// The deployment's root directory.
+    pub path: PathBuf,
+    /// `refspec` from the origin file (`remote:ref` or `ref`).
+    pub refspec: Option<String>,
+    /// `title` of the BLS entry.
+    pub title: Option<String>,
+    /// Kernel arguments, `ostree=` included, in BLS order.
+    pub kargs: Vec<String>,
+    /// `usr/lib/modules/<kver>/vmlinuz`, if present.
+    pub kernel: Option<PathBuf>,
+    /// `usr/lib/modules/<kver>/initramfs.img`, if present.
+    pub initramfs: Option<PathBuf>,
+}
+
+impl Deployment {
+    /// Every deployment with a BLS entry, the default first.
+    pub fn list(sysroot: &Path) -> Result<Vec<Self>> {
+        let entries = sysroot.join("boot/loader/entries");
+        let dir = std::fs::read_dir(&entries).at("read", &entries)?;
+        let mut found = Vec::new();
+        for entry in dir {
+            let path = entry.at("read", &entries)?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if !(name.starts_with("ostree-") && name.ends_with(".conf")) {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).at("read", &path)?;
+            let (version, deployment) = Self::from_entry(sysroot, &path, &text)?;
+            found.push((version, deployment));
+        }
+        found.sort_by_key(|(version, _)| std::cmp::Reverse(*version));
+        Ok(found.into_iter().map(|(_, d)| d).collect())
+    }
+
+    /// The deployment of `reference` (the default one if `None`).
+    pub fn find(sysroot: &Path, reference: Option<&str>) -> Result<Self> {
+        let all = Self::list(sysroot)?;
+        let wanted = |d: &Deployment| match reference {
+            None => true,
+            Some(r) => d.reference() == Some(r),
+        };
+        if let Some(d) = all.iter().find(|d| wanted(d)) {
+            return Ok(d.clone());
+        }
+        let have: Vec<String> = all
+            .iter()
+            .map(|d| d.reference().unwrap_or("(no origin)").to_string())
+            .collect();
+        Err(Error::Invalid(match reference {
+            Some(r) => format!(
+                "{}: no deployment of ref {r:?} (deployed: {})",
+                sysroot.display(),
+                have.join(", ")
+            ),
+            None => format!("{}: no ostree deployments", sysroot.display()),
+        }))
+    }
+
+    /// The ref without its remote.
+    pub fn reference(&self) -> Option<&str> {
+        let refspec = self.refspec.as_deref()?;
+        Some(refspec.split_once(':').map_or(refspec, |(_, r)| r))
+    }
+
+    /// The `ostree=` argument.
+    pub fn ostree_arg(&self) -> &str {
+        self.kargs
+            .iter()
+            .find(|a| a.starts_with("ostree="))
+            .map(String::as_str)
+            .expect("checked while parsing")
+    }
+
+    /// Make `profile` build for this deployment: its kernel arguments go
+    /// first on the command line (as the [`FRAGMENT`] fragment), and it
+    /// supplies `kernel` and `initrd` unless `artifacts` or `fetch` already
+    /// do.
+    pub fn apply(&self, profile: &mut Profile) {
+        let order = profile
+            .cmdline
+            .fragments
+            .iter()
+            .filter_map(|f| f.order)
+            .chain([0])
+            .min()
+            .unwrap_or(0);
+        profile.cmdline.set(Fragment {
+            name: FRAGMENT.to_string(),
+            args: self.kargs.join(" "),
+            order: Some(order.saturating_sub(1)),
+        });
+        for (key, path) in [("kernel", &self.kernel), ("initrd", &self.initramfs)] {
+            let Some(path) = path else { continue };
+            if !profile.artifacts.contains_key(key) && !profile.fetch.contains_key(key) {
+                profile.artifacts.insert(key.to_string(), path.clone());
+            }
+        }
+    }
+
+    /// Parse one BLS entry; returns its `version` too, for ordering.
+    fn from_entry(sysroot: &Path, path: &Path, text: &str) -> Result<(u64, Self)> {
+        let invalid = |why: &str| Error::Invalid(format!("{}: {why}", path.display()));
+        let mut version = 0;
+        let mut title = None;
+        let mut kargs: Vec<String> = Vec::new();
+        let mut linux = None;
+        for line in text.lines().map(str::trim) {
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "version" => version = value.parse().unwrap_or(0),
+                "title" => title = Some(value.to_string()),
+                "options" => kargs.extend(value.split_whitespace().map(str::to_string)),
+                "linux" => linux = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        let boot = kargs
+            .iter()
+            .find_map(|a| a.strip_prefix("ostree="))
+            .ok_or_else(|| invalid("no ostree= in options"))?;
+        // A chain of relative links inside the sysroot; resolve them there.
+        let link = sysroot.join(boot.trim_start_matches('/'));
+        let deploy = link.canonicalize().at("resolve ostree= link", &link)?;
+        let name = deploy
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| invalid("ostree= does not lead to a deployment"))?;
+        let (checksum, serial) = name
+            .rsplit_once('.')
+            .and_then(|(c, s)| Some((c.to_string(), s.parse().ok()?)))
+            .ok_or_else(|| invalid("ostree= does not lead to a deployment"))?;
+        let stateroot = deploy
+            .parent()
+            .filter(|p| p.ends_with("deploy"))
+            .and_then(Path::parent)
+            .and_then(Path::file_name)
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| invalid("ostree= does not lead to a deployment"))?
+            .to_string();
+        let origin = deploy.with_file_name(format!("{name}.origin"));
+        let refspec = std::fs::read_to_string(&origin)
+            .ok()
+            .and_then(|t| origin_refspec(&t));
+        let kver = linux
+            .as_deref()
+            .and_then(|l| l.rsplit('/').next())
+            .and_then(|f| f.strip_prefix("vmlinuz-"));
+        let modules = kernel_dir(&deploy.join("usr/lib/modules"), kver);
+        let existing = |file: &str| {
+            let p = modules.as_ref()?.join(file);
+            p.is_file().then_some(p)
+        };
+        Ok((
+            version,
+            Self {
+                stateroot,
+                checksum,
+                serial,
+                kernel: existing("vmlinuz"),
+                initramfs: existing("initramfs.img"),
+                path: deploy,
+                refspec,
+                title,
+                kargs,
+            },
+        ))
+    }
+}
+
+impl RootSpec {
+    /// The ref an `ostree` root asks for, if any.
+    pub fn ostree_ref(&self) -> Option<&str> {
+        match self {
+            RootSpec::Ostree { reference } => reference.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// `refspec=` (or the older `baserefspec=`) in an origin keyfile's
+/// `[origin]` group.
+fn origin_refspec(text: &str) -> Option<String> {
+    let mut in_origin = false;
+    let mut found = None;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_origin = line == "[origin]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match key.trim() {
+            "refspec" if in_origin => return Some(value.trim().to_string()),
+            "baserefspec" if in_origin => found = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    found
+}
+
+/// `usr/lib/modules/<kver>`: the one named by the BLS entry, else the only
+/// one with a kernel.
+fn kernel_dir(modules: &Path, kver: Option<&str>) -> Option<PathBuf> {
+    if let Some(dir) = kver.map(|k| modules.join(k)).filter(|d| d.is_dir()) {
+        return Some(dir);
+    }
+    let mut with_kernel = std::fs::read_dir(modules)
+        .ok()?
+        .filter_map(|e| Some(e.ok()?.path()))
+        .filter(|d| d.join("vmlinuz").is_file());
+    let first = with_kernel.next()?;
+    with_kernel.next().is_none().then_some(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// A sysroot with two deployments of `fedora/41/x86_64/iot`, the
+    /// newer one (serial 1) the default, and one of a rebased ref.
+    fn sysroot(root: &Path) {
+        let deploy = root.join("ostree/deploy/fedora/deploy");
+        let boot = root.join("ostree/boot.1.1/fedora");
+        let entries = root.join("boot/loader/entries");
+        std::fs::create_dir_all(&entries).unwrap();
+        std::fs::create_dir_all(&boot).unwrap();
+        symlink("boot.1.1", root.join("ostree/boot.1")).unwrap();
+        for (version, checksum, csum, refspec) in [
+            (2, "aaaa", "b1", "fedora:fedora/41/x86_64/iot"),
+            (3, "cccc", "b2", "fedora:fedora/41/x86_64/iot"),
+            (1, "dddd", "b3", "fedora/41/x86_64/iot-devel"),
+        ] {
+            let dir = deploy.join(format!("{checksum}.0"));
+            let modules = dir.join("usr/lib/modules/6.12.0");
+            std::fs::create_dir_all(&modules).unwrap();
+            std::fs::write(modules.join("vmlinuz"), b"kernel").unwrap();
+            std::fs::write(modules.join("initramfs.img"), b"initrd").unwrap();
+            std::fs::write(
+                deploy.join(format!("{checksum}.0.origin")),
+                format!("[origin]\nrefspec={refspec}\n"),
+            )
+            .unwrap();
+            std::fs::create_dir_all(boot.join(csum)).unwrap();
+            symlink(
+                format!("../../../deploy/fedora/deploy/{checksum}.0"),
+                boot.join(csum).join("0"),
+            )
+            .unwrap();
+            std::fs::write(
+                entries.join(format!("ostree-{version}-fedora.conf")),
+                format!(
+                    "title Fedora {version}\nversion {version}\n\
+                     linux /ostree/fedora-{csum}/vmlinuz-6.12.0\n\
+                     options root=UUID=1234 rw ostree=/ostree/boot.1/fedora/{csum}/0\n"
+                ),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn deployments_come_from_bls_entries_and_fill_the_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        sysroot(dir.path());
+
+        let all = Deployment::list(dir.path()).unwrap();
+        let checksums: Vec<&str> = all.iter().map(|d| d.checksum.as_str()).collect();
+        assert_eq!(checksums, ["cccc", "aaaa", "dddd"]);
+
+        let d = Deployment::find(dir.path(), None).unwrap();
+        assert_eq!(d.ostree_arg(), "ostree=/ostree/boot.1/fedora/b2/0");
+        assert_eq!((d.stateroot.as_str(), d.serial), ("fedora", 0));
+        assert_eq!(d.reference(), Some("fedora/41/x86_64/iot"));
+        let devel = Deployment::find(dir.path(), Some("fedora/41/x86_64/iot-devel")).unwrap();
+        assert_eq!(devel.checksum, "dddd");
+        let err = Deployment::find(dir.path(), Some("centos/10")).unwrap_err();
+        assert!(err.to_string().contains("iot-devel"), "{err}");
+
+        let mut profile = Profile::from_toml_str(
+            "name = \"p\"\nroot = { ostree = { ref = \"fedora/41/x86_64/iot\" } }\n\
+             cmdline = \"console=ttyS0 rw\"\n[artifacts]\ninitrd = \"mine.img\"\n",
+        )
+        .unwrap();
+        let d = Deployment::find(dir.path(), profile.root.ostree_ref()).unwrap();
+        d.apply(&mut profile);
+        assert_eq!(
+            profile.kernel_cmdline(),
+            "root=UUID=1234 rw ostree=/ostree/boot.1/fedora/b2/0 console=ttyS0"
+        );
+        assert_eq!(profile.artifacts["initrd"], Path::new("mine.img"));
+        assert!(profile.artifacts["kernel"].ends_with("cccc.0/usr/lib/modules/6.12.0/vmlinuz"));
+    }
+}
@@ -0,0 +1,242 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Typed root filesystem specification for profiles.
+//!
+//! ```toml
+//! root = "ostree"                                   # shorthand
+//! root = { ostree = { ref = "fedora/41/x86_64/iot" } }
+//! root = { composefs = { digest = "<sha256 hex>" } }
+//! root = { plain = { device = "UUID=…", fstype = "xfs" } }
+//! ```
+//!
+//! The shorthand string form is accepted for variants whose fields are all
+//! optional (`ostree`, `plain`). Values are validated while deserializing,
+//! so a profile that parses is a profile a build can act on.
+
+use lowell_formats::error::{Error, Result};
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum RootSpec {
+    /// OSTree deployment; the `ostree=` argument is deployment-specific and
+    /// is added when the target deployment is known (see
+    /// [`ostree::Deployment`](super::ostree::Deployment)).
+    Ostree {
+        #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+        reference: Option<String>,
+    },
+    /// composefs image identified by its fs-verity digest.
+    Composefs { digest: String },
+    /// A regular block device. Without `device`, the root is left to
+    /// systemd's GPT auto-discovery.
+    Plain {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        device: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fstype: Option<String>,
+    },
+}
+
+impl RootSpec {
+    /// Short variant name (`ostree`, `composefs`, `plain`).
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RootSpec::Ostree { .. } => "ostree",
+            RootSpec::Composefs { .. } => "composefs",
+            RootSpec::Plain { .. } => "plain",
+        }
+    }
+
+    /// Check field values; called from deserialization.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            RootSpec::Ostree { reference } => {
+                if let Some(r) = reference {
+                    if r.is_empty() || r.contains(char::is_whitespace) {
+                        return Err(Error::Invalid(format!("invalid ostree ref {r:?}")));
+                    }
+                }
+            }
+            RootSpec::Composefs { digest } => {
+                let hex = digest.chars().all(|c| c.is_ascii_hexdigit());
+                if !hex || !matches!(digest.len(), 64 | 128) {
+                    return Err(Error::Invalid(format!(
+                        "composefs digest must be 64 or 128 hex chars, got {digest:?}"
+                    )));
+                }
+            }
+            RootSpec::Plain { device, fstype } => {
+                if let Some(d) = device {
+                    let known = ["/dev/", "UUID=", "PARTUUID=", "LABEL=", "PARTLABEL="]
+                        .iter()
+                        .any(|p| d.starts_with(p));
+                    if !known || d.contains(char::is_whitespace) {
+                        return Err(Error::Invalid(format!("invalid root device {d:?} (expected /dev/…, UUID=, PARTUUID=, LABEL= or PARTLABEL=)")));
+                    }
+                }
+                if let Some(t) = fstype {
+                    if t.is_empty() || !t.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                        return Err(Error::Invalid(format!("invalid root fstype {t:?}")));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Kernel arguments this root needs on the command line.
+    pub fn cmdline_args(&self) -> Vec<String> {
+        match self {
+            RootSpec::Ostree { .. } => Vec::new(),
+            RootSpec::Composefs { digest } => vec![format!("composefs={digest}")],
+            RootSpec::Plain { device, fstype } => {
+                let mut args = Vec::new();
+                if let Some(d) = device {
+                    args.push(format!("root={d}"));
+                }
+                if let Some(t) = fstype {
+                    args.push(format!("rootfstype={t}"));
+                }
+                args
+            }
+        }
+    }
+
+    /// Kernel modules the initramfs needs to mount this root.
+    pub fn required_modules(&self) -> Vec<&'static str> {
+        match self {
+            RootSpec::Ostree { .. } => Vec::new(),
+            RootSpec::Composefs { .. } => vec!["erofs", "overlay"],
+            RootSpec::Plain { fstype, .. } => {
+                fstype.as_deref().and_then(fs_module).into_iter().collect()
+            }
+        }
+    }
+
+    /// True if this value round-trips through the shorthand string form.
+    fn is_shorthand(&self) -> bool {
+        matches!(
+            self,
+            RootSpec::Ostree { reference: None }
+                | RootSpec::Plain {
+                    device: None,
+                    fstype: None
+                }
+        )
+    }
+}
+
+impl fmt::Display for RootSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.kind())?;
+        match self {
+            RootSpec::Ostree { reference: Some(r) } => write!(f, "(ref={r})"),
+            RootSpec::Composefs { digest } => write!(f, "(digest={digest})"),
+            RootSpec::Plain { device, fstype } if device.is_some() || fstype.is_some() => {
+                let parts: Vec<String> = [("device", device), ("fstype", fstype)]
+                    .into_iter()
+                    .filter_map(|(k, v)| v.as_ref().map(|v| format!("{k}={v}")))
+                    .collect();
+                write!(f, "({})", parts.join(", "))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Kernel module backing a filesystem type, if it is usually modular.
+pub(crate) fn fs_module(fstype: &str) -> Option<&'static str> {
+    match fstype {
+        "xfs" => Some("xfs"),
+        "ext4" | "ext3" | "ext2" => Some("ext4"),
+        "btrfs" => Some("btrfs"),
+        "vfat" => Some("vfat"),
+        "f2fs" => Some("f2fs"),
+        "erofs" => Some("erofs"),
+        _ => None,
+    }
+}
+
+/// `deserialize_with` for profile `root` fields: shorthand or table, validated.
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<RootSpec, D::Error> {
+    struct RootVisitor;
+
+    impl<'de> Visitor<'de> for RootVisitor {
+        type Value = RootSpec;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("\"ostree\", \"plain\", or a table like { composefs = { digest = … } }")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<RootSpec, E> {
+            match v {
+                "ostree" => Ok(RootSpec::Ostree { reference: None }),
+                "plain" => Ok(RootSpec::Plain {
+                    device: None,
+                    fstype: None,
+                }),
+                "composefs" => Err(E::custom("root `composefs` needs a table with `digest`")),
+                other => Err(E::unknown_variant(other, &["ostree", "composefs", "plain"])),
+            }
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<RootSpec, A::Error> {
+            let spec = RootSpec::deserialize(de::value::MapAccessDeserializer::new(map))?;
+            spec.validate().map_err(de::Error::custom)?;
+            Ok(spec)
+        }
+    }
+
+    d.deserialize_any(RootVisitor)
+}
+
+/// `serialize_with` counterpart: emits the shorthand string when possible.
+pub(crate) fn serialize<S: Serializer>(root: &RootSpec, s: S) -> Result<S::Ok, S::Error> {
+    if root.is_shorthand() {
+        s.serialize_str(root.kind())
+    } else {
+        root.serialize(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::Profile;
+
+    fn parse(root: &str) -> lowell_formats::Result<Profile> {
+        Profile::from_toml_str(&format!("name = \"t\"\nroot = {root}\n"))
+    }
+
+    #[test]
+    fn root_shorthand_and_tables_roundtrip() {
+        for root in [
+            "\"ostree\"",
+            "\"plain\"",
+            "{ ostree = { ref = \"fedora/41/x86_64/iot\" } }",
+            "{ plain = { device = \"LABEL=root\", fstype = \"xfs\" } }",
+            &format!("{{ composefs = {{ digest = \"{}\" }} }}", "ab".repeat(32)),
+        ] {
+            let p = parse(root).unwrap_or_else(|e| panic!("{root}: {e:#}"));
+            let again = Profile::from_toml_str(&p.to_toml_string().unwrap()).unwrap();
+            assert_eq!(again, p, "{root}");
+        }
+    }
+
+    #[test]
+    fn root_validation_rejects_bad_values() {
+        assert!(parse("\"btrfs\"").is_err());
+        assert!(parse("\"composefs\"").is_err());
+        assert!(parse("{ composefs = { digest = \"xyz\" } }").is_err());
+        assert!(parse("{ plain = { device = \"sda1\" } }").is_err());
+        assert!(parse("{ plain = { devices = \"/dev/sda1\" } }").is_err());
+    }
+
+    #[test]
+    fn root_handlers_emit_cmdline_and_modules() {
+        let p = parse("{ plain = { device = \"UUID=1234\", fstype = \"ext4\" } }").unwrap();
+        assert_eq!(p.root.cmdline_args(), ["root=UUID=1234", "rootfstype=ext4"]);
+        assert_eq!(p.root.required_modules(), ["ext4"]);
+    }
+}
@@ -0,0 +1,925 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Assemble a UKI: append payload sections to a systemd-stub PE image.
+//!
+//! This is the `ukify build` step. The stub's headers are patched in place:
+//! new section headers go into the slack after the existing section table,
+//! section data is appended at the end of the file, and `SizeOfImage` /
+//! `NumberOfSections` are updated. A signature on the stub would no longer
+//! cover the result, so any certificate table is dropped and `CheckSum`
+//! is cleared. [`edit`] swaps payload sections of a finished UKI the same
+//! way, without touching the others.
+//!
+//! Inputs come from the profile's `[artifacts]` table:
+//!
+//! | key      | section    | required |
+//! |----------|------------|----------|
+//! | `kernel` | `.linux`   | yes      |
+//! | `initrd` | `.initrd`  | yes (a file, or a directory to [pack]) |
+//! | `stub`   | —          | no (systemd's stub for the host arch) |
+//! | `osrel`  | `.osrel`   | no       |
+//! | `dtb`    | `.dtb`     | no       |
+//!
+//! `.cmdline` is the profile's [`Profile::kernel_cmdline`]. A directory
+//! `initrd` is packed with the profile's `compression` (zstd when unset).
+//! Generating the initramfs itself from `modules` is not implemented yet.
+//!
+//! [pack]: crate::initramfs::pack
+
+#[cfg(feature = "profile")]
+use crate::profile::Profile;
+use lowell_formats::cancel::CancelToken;
+use lowell_formats::error::{Error, Result};
+use lowell_formats::initramfs::Compression;
+use lowell_formats::progress::{read_file, NoProgress, Phase, ProgressSink};
+use lowell_formats::timing::Stopwatch;
+use lowell_formats::trace::{debug, debug_span};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+const SECTION_HEADER_LEN: usize = 40;
+/// IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ
+const DATA_SECTION: u32 = 0x4000_0040;
+const SECURITY_DIR: usize = 4;
+/// Bytes per copy step in [`BuildInputs::write_to`].
+#[cfg(not(target_arch = "wasm32"))]
+const COPY_CHUNK: usize = 1 << 20;
+/// For a directory `initrd` whose profile names no compression.
+pub const DEFAULT_COMPRESSION: Compression = Compression::Zstd;
+
+/// Files a build reads, resolved against the profile's directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInputs {
+    pub stub: PathBuf,
+    pub kernel: PathBuf,
+    /// A prebuilt initramfs, or a directory packed into one.
+    pub initrd: PathBuf,
+    /// How a directory `initrd` is compressed.
+    pub initrd_compression: Compression,
+    pub osrel: Option<PathBuf>,
+    pub dtb: Option<PathBuf>,
+    pub cmdline: String,
+}
+
+impl BuildInputs {
+    #[cfg(feature = "profile")]
+    pub fn from_profile(profile: &Profile, base_dir: &Path) -> Result<Self> {
+        let artifact = |key: &str| profile.artifacts.get(key).map(|p| base_dir.join(p));
+        let required = |key: &'static str, hint: &'static str| {
+            artifact(key).ok_or_else(|| Error::MissingArtifact {
+                profile: profile.name.clone(),
+                key,
+                hint,
+            })
+        };
+        let initrd = required(
+            "initrd",
+            " (a file or a directory; building one from `modules` is not supported yet)",
+        )?;
+        Ok(Self {
+            stub: artifact("stub").unwrap_or_else(default_stub),
+            kernel: required("kernel", "")?,
+            initrd,
+            initrd_compression: profile.compression.unwrap_or(DEFAULT_COMPRESSION),
+            osrel: artifact("osrel"),
+            dtb: artifact("dtb"),
+            cmdline: profile.kernel_cmdline(),
+        })
+    }
+
+    /// Every file the output depends on (for `--watch`).
+    pub fn paths(&self) -> Vec<&Path> {
+        let mut v = vec![self.stub.as_path(), &self.kernel, &self.initrd];
+        v.extend(self.osrel.as_deref());
+        v.extend(self.dtb.as_deref());
+        v
+    }
+
+    /// Read all inputs and assemble the UKI image.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        self.build_with(&NoProgress, &CancelToken::new())
+    }
+
+    /// [`build`](Self::build), reporting each input read and the assembly
+    /// to `sink`, and stopping with [`Error::Cancelled`] once `cancel` is
+    /// cancelled (checked between read chunks).
+    pub fn build_with(&self, sink: &dyn ProgressSink, cancel: &CancelToken) -> Result<Vec<u8>> {
+        let _span = debug_span!("build", kernel = %self.kernel.display()).entered();
+        let read = |what: &'static str, p: &Path| {
+            let t = Stopwatch::start();
+            let data = read_file(p, Phase::Read(what), sink, cancel)?;
+            debug!(what, path = %p.display(), size = data.len(), elapsed_ms = t.ms(), "read_input");
+            Ok::<_, Error>(data)
+        };
+        let stub = read("stub", &self.stub)?;
+        let mut sections: Vec<(&'static str, Vec<u8>)> = Vec::new();
+        for (name, path) in self.section_paths() {
+            let data = match self.packed_initrd(name, path) {
+                Some(root) => self.pack_initrd(root, sink, cancel)?,
+                None => read(name, path)?,
+            };
+            sections.push((name, data));
+        }
+        let sections = self.with_cmdline(sections);
+        let total = sections.iter().map(|(_, d)| d.len() as u64).sum();
+        cancel.check()?;
+        sink.phase_started(Phase::Assemble, Some(total));
+        let t = Stopwatch::start();
+        let image = assemble_owned(&stub, sections)?;
+        debug!(
+            payload = total,
+            size = image.len(),
+            elapsed_ms = t.ms(),
+            "assemble"
+        );
+        sink.phase_finished(Phase::Assemble);
+        Ok(image)
+    }
+
+    /// [`build_with`](Self::build_with), streaming the image into `out`
+    /// instead of holding it in memory: only the stub's headers and one
+    /// copy buffer are resident, whatever the size of the inputs. A
+    /// directory `initrd` is packed into a temporary file first.
+    ///
+    /// Inputs must not change while this runs; a payload whose size no
+    /// longer matches its headers is an error.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_to<W: Write>(
+        &self,
+        mut out: W,
+        sink: &dyn ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<W> {
+        use lowell_formats::error::IoResultExt;
+        let _span = debug_span!("build_streaming", kernel = %self.kernel.display()).entered();
+        let stub = read_file(&self.stub, Phase::Read("stub"), sink, cancel)?;
+        let mut sources: Vec<(&'static str, Box<dyn Read>, usize)> = Vec::new();
+        for (name, path) in self.section_paths() {
+            let (file, len) = match self.packed_initrd(name, path) {
+                Some(root) => {
+                    let mut file = tempfile::tempfile().at("create", &std::env::temp_dir())?;
+                    file = crate::initramfs::pack_with(
+                        root,
+                        self.initrd_compression,
+                        file,
+                        sink,
+                        cancel,
+                    )?;
+                    let len = file.stream_position().at("seek", root)?;
+                    file.rewind().at("seek", root)?;
+                    (file, len)
+                }
+                None => {
+                    let file = std::fs::File::open(path).at("open", path)?;
+                    let len = file.metadata().at("stat", path)?.len();
+                    (file, len)
+                }
+            };
+            let len = usize::try_from(len)
+                .map_err(|_| Error::Invalid(format!("{}: too large", path.display())))?;
+            sources.push((name, Box::new(file), len));
+        }
+        if !self.cmdline.is_empty() {
+            let cmdline = format!("{}\0", self.cmdline).into_bytes();
+            let at = usize::from(self.osrel.is_some());
+            let len = cmdline.len();
+            sources.insert(
+                at,
+                (".cmdline", Box::new(std::io::Cursor::new(cmdline)), len),
+            );
+        }
+
+        let sizes: Vec<(&str, usize)> = sources.iter().map(|(n, _, len)| (*n, *len)).collect();
+        let total = sizes.iter().map(|(_, len)| *len as u64).sum();
+        cancel.check()?;
+        sink.phase_started(Phase::Assemble, Some(total));
+        let t = Stopwatch::start();
+        let layout = Layout::new(&stub, &sizes)?;
+        drop(stub);
+        out.write_all(&layout.head)?;
+        let mut written = layout.head.len();
+        let mut buf = vec![0; COPY_CHUNK];
+        let mut done = 0u64;
+        for (name, mut source, len) in sources {
+            let mut left = len;
+            while left > 0 {
+                cancel.check()?;
+                let n = source.read(&mut buf[..left.min(COPY_CHUNK)])?;
+                if n == 0 {
+                    return Err(Error::Invalid(format!(
+                        "{name}: input shrank while building"
+                    )));
+                }
+                out.write_all(&buf[..n])?;
+                left -= n;
+                done += n as u64;
+                sink.bytes_processed(Phase::Assemble, done);
+            }
+            if source.read(&mut buf[..1])? != 0 {
+                return Err(Error::Invalid(format!("{name}: input grew while building")));
+            }
+            written += len;
+            let pad = align(written, layout.file_align) - written;
+            out.write_all(&vec![0; pad])?;
+            written += pad;
+        }
+        debug!(
+            payload = total,
+            size = written,
+            elapsed_ms = t.ms(),
+            "assemble"
+        );
+        sink.phase_finished(Phase::Assemble);
+        Ok(out)
+    }
+
+    /// [`build`](Self::build) with async file reads; assembly runs on
+    /// tokio's blocking pool.
+    #[cfg(feature = "tokio")]
+    pub async fn build_async(&self) -> Result<Vec<u8>> {
+        use lowell_formats::error::IoResultExt;
+        let read = |p: &Path| {
+            let p = p.to_path_buf();
+            async move { tokio::fs::read(&p).await.at("read", &p) }
+        };
+        let stub = read(&self.stub).await?;
+        let mut sections: Vec<(&'static str, Vec<u8>)> = Vec::new();
+        for (name, path) in self.section_paths() {
+            let data = match self.packed_initrd(name, path) {
+                Some(root) => {
+                    let (inputs, root) = (self.clone(), root.to_path_buf());
+                    lowell_formats::task::blocking(move || {
+                        inputs.pack_initrd(&root, &NoProgress, &CancelToken::new())
+                    })
+                    .await?
+                }
+                None => read(path).await?,
+            };
+            sections.push((name, data));
+        }
+        let sections = self.with_cmdline(sections);
+        lowell_formats::task::blocking(move || assemble_owned(&stub, sections)).await
+    }
+
+    /// The directory to pack, when `name` is `.initrd` and `path` is one.
+    fn packed_initrd<'p>(&self, name: &str, path: &'p Path) -> Option<&'p Path> {
+        (name == ".initrd" && path.is_dir()).then_some(path)
+    }
+
+    fn pack_initrd(
+        &self,
+        root: &Path,
+        sink: &dyn ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<u8>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        return crate::initramfs::pack_with(
+            root,
+            self.initrd_compression,
+            Vec::new(),
+            sink,
+            cancel,
+        );
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = (sink, cancel);
+            Err(Error::Invalid(format!(
+                "{}: packing a directory initrd needs threads",
+                root.display()
+            )))
+        }
+    }
+
+    /// File-backed sections other than `.cmdline`, in output order.
+    fn section_paths(&self) -> Vec<(&'static str, &Path)> {
+        let mut v = Vec::new();
+        v.extend(self.osrel.as_deref().map(|p| (".osrel", p)));
+        v.extend(self.dtb.as_deref().map(|p| (".dtb", p)));
+        v.push((".initrd", self.initrd.as_path()));
+        v.push((".linux", self.kernel.as_path()));
+        v
+    }
+
+    /// Insert `.cmdline` after `.osrel`, where ukify puts it.
+    fn with_cmdline(
+        &self,
+        mut sections: Vec<(&'static str, Vec<u8>)>,
+    ) -> Vec<(&'static str, Vec<u8>)> {
+        if !self.cmdline.is_empty() {
+            let at = usize::from(self.osrel.is_some());
+            let cmdline = format!("{}\0", self.cmdline).into_bytes();
+            sections.insert(at, (".cmdline", cmdline));
+        }
+        sections
+    }
+}
+
+/// systemd's stub for the architecture lowell was built for.
+pub fn default_stub() -> PathBuf {
+    let name = match std::env::consts::ARCH {
+        "aarch64" => "linuxaa64.efi.stub",
+        "x86" => "linuxia32.efi.stub",
+        "riscv64" => "linuxriscv64.efi.stub",
+        _ => "linuxx64.efi.stub",
+    };
+    Path::new("/usr/lib/systemd/boot/efi").join(name)
+}
+
+fn assemble_owned(stub: &[u8], sections: Vec<(&'static str, Vec<u8>)>) -> Result<Vec<u8>> {
+    let refs: Vec<(&str, &[u8])> = sections.iter().map(|(n, d)| (*n, d.as_slice())).collect();
+    assemble(stub, &refs)
+}
+
+/// Append `sections` (in order) to a copy of `stub`.
+pub fn assemble(stub: &[u8], sections: &[(&str, &[u8])]) -> Result<Vec<u8>> {
+    let sizes: Vec<(&str, usize)> = sections.iter().map(|(n, d)| (*n, d.len())).collect();
+    let layout = Layout::new(stub, &sizes)?;
+    let mut img = layout.head;
+    for (_, data) in sections {
+        img.extend_from_slice(data);
+        img.resize(align(img.len(), layout.file_align), 0);
+    }
+    Ok(img)
+}
+
+/// Payload sections in the order [`edit`] inserts new ones: ukify's order,
+/// which [`BuildInputs`] follows.
+const PAYLOAD_ORDER: [&str; 7] = [
+    ".osrel", ".cmdline", ".dtb", ".uname", ".splash", ".initrd", ".linux",
+];
+
+/// A UKI rewritten by [`edit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edited {
+    pub image: Vec<u8>,
+    /// Payload sections whose raw bytes, padding included, are the same as
+    /// before: digests taken over them still hold.
+    pub unchanged: Vec<String>,
+}
+
+/// Replace (`Some`), add or remove (`None`) payload sections of an
+/// assembled `uki`, keeping the rest as they were.
+///
+/// The payload is every section from the first one named in ukify's
+/// layout (`.osrel`, `.cmdline`, `.dtb`, `.uname`, `.splash`, `.initrd`,
+/// `.linux`) to the end of the table; the sections before it are the
+/// stub's. The payload is laid out again after the stub, so untouched
+/// sections move but keep their bytes, and new ones go where ukify would
+/// put them. As with [`assemble`], any signature is dropped.
+pub fn edit(uki: &[u8], changes: &[(&str, Option<&[u8]>)]) -> Result<Edited> {
+    let t = Stopwatch::start();
+    let h = Headers::parse(uki)?;
+    let names = h.section_names(uki)?;
+    let first = names
+        .iter()
+        .position(|n| PAYLOAD_ORDER.contains(&n.as_str()))
+        .ok_or_else(|| Error::Assemble("no UKI payload sections to edit".into()))?;
+
+    // (name, contents, raw bytes unchanged by relayout)
+    let mut stub_end = h.size_of_headers;
+    let mut payload: Vec<(String, &[u8], bool)> = Vec::new();
+    let mut payload_raw = 0usize;
+    for (i, name) in names.into_iter().enumerate() {
+        let at = h.section(uki, i)?;
+        let vsize = u32_at(uki, at + 8)? as usize;
+        let raw_size = u32_at(uki, at + 16)? as usize;
+        let ptr = u32_at(uki, at + 20)? as usize;
+        if i < first {
+            stub_end = stub_end.max(ptr + raw_size);
+            continue;
+        }
+        let raw = uki
+            .get(ptr..ptr + raw_size)
+            .ok_or_else(|| bad_stub(format!("{name} section lies outside the file")))?;
+        if ptr < stub_end {
+            return Err(Error::Assemble(format!(
+                "{name} section overlaps the stub's sections"
+            )));
+        }
+        let len = if vsize == 0 {
+            raw_size
+        } else {
+            vsize.min(raw_size)
+        };
+        let (contents, pad) = raw.split_at(len);
+        let kept = raw.len() == align(len, h.file_align) && pad.iter().all(|&b| b == 0);
+        payload.push((name, contents, kept));
+        payload_raw += raw_size;
+    }
+
+    for &(name, data) in changes {
+        let at = payload.iter().position(|(n, _, _)| n == name);
+        match (at, data) {
+            (Some(at), Some(data)) => payload[at] = (name.to_string(), data, false),
+            (Some(at), None) => {
+                payload.remove(at);
+            }
+            (None, Some(data)) => {
+                let rank = |n: &str| PAYLOAD_ORDER.iter().position(|o| *o == n);
+                let at = match rank(name) {
+                    Some(r) => payload
+                        .iter()
+                        .position(|(n, _, _)| rank(n).is_some_and(|o| o > r))
+                        .unwrap_or(payload.len()),
+                    None => payload.len(),
+                };
+                payload.insert(at, (name.to_string(), data, false));
+            }
+            (None, None) => {
+                return Err(Error::SectionMissing {
+                    name: name.to_string(),
+                })
+            }
+        }
+    }
+
+    // The stub alone: its sections, its headers, no payload.
+    let mut stub = uki[..stub_end.min(uki.len())].to_vec();
+    let table_end = h.section_table + h.nsections * SECTION_HEADER_LEN;
+    stub[h.section_table + first * SECTION_HEADER_LEN..table_end].fill(0);
+    put_u16(&mut stub, h.coff + 2, first as u16);
+    let init = u32_at(&stub, h.opt + 8)? as usize;
+    put_u32(
+        &mut stub,
+        h.opt + 8,
+        to_u32(init.saturating_sub(payload_raw))?,
+    );
+    let sections: Vec<(&str, &[u8])> = payload.iter().map(|(n, d, _)| (n.as_str(), *d)).collect();
+    let image = assemble(&stub, &sections)?;
+    let unchanged: Vec<String> = payload
+        .into_iter()
+        .filter_map(|(n, _, kept)| kept.then_some(n))
+        .collect();
+    debug!(
+        changes = changes.len(),
+        unchanged = unchanged.len(),
+        size = image.len(),
+        elapsed_ms = t.ms(),
+        "edit"
+    );
+    Ok(Edited { image, unchanged })
+}
+
+/// A UKI up to its first new section: the stub with every header already
+/// describing sections of the given sizes, which follow in order, each
+/// padded to `file_align`.
+struct Layout {
+    head: Vec<u8>,
+    file_align: usize,
+}
+
+impl Layout {
+    fn new(stub: &[u8], sections: &[(&str, usize)]) -> Result<Self> {
+        let mut img = stub.to_vec();
+        let h = Headers::parse(&img)?;
+
+        // Drop the stub's signature; it cannot cover the new sections.
+        let sec_dir = h.data_dir + SECURITY_DIR * 8;
+        if h.dir_count > SECURITY_DIR as u32 {
+            let cert_off = u32_at(&img, sec_dir)? as usize;
+            if cert_off != 0 {
+                img.truncate(cert_off.min(img.len()));
+                put_u32(&mut img, sec_dir, 0);
+                put_u32(&mut img, sec_dir + 4, 0);
+            }
+        }
+
+        let existing = h.section_names(&img)?;
+        for (name, _) in sections {
+            if name.len() > 8 {
+                return Err(Error::Assemble(format!(
+                    "section name {name:?} is longer than 8 bytes"
+                )));
+            }
+            if existing.iter().any(|e| e == name) {
+                return Err(Error::Assemble(format!(
+                    "stub already has a {name} section"
+                )));
+            }
+        }
+
+        let table_end = h.section_table + h.nsections * SECTION_HEADER_LEN;
+        let new_end = table_end + sections.len() * SECTION_HEADER_LEN;
+        let first_raw = h.first_raw_data(&img)?;
+        if new_end > h.size_of_headers || new_end > first_raw {
+            return Err(Error::Assemble(format!(
+                "stub has no room for {} more section headers (headers end at {:#x})",
+                sections.len(),
+                h.size_of_headers.min(first_raw)
+            )));
+        }
+
+        let (mut raw_end, mut va_end) = h.extents(&img)?;
+        raw_end = raw_end.max(img.len());
+        let mut file_pos = align(raw_end, h.file_align);
+        let mut va = align(va_end, h.section_align);
+        img.resize(file_pos, 0);
+
+        let mut init_data = 0usize;
+        for (i, &(name, len)) in sections.iter().enumerate() {
+            let raw_size = align(len, h.file_align);
+            let mut hdr = [0u8; SECTION_HEADER_LEN];
+            hdr[..name.len()].copy_from_slice(name.as_bytes());
+            hdr[8..12].copy_from_slice(&to_u32(len)?.to_le_bytes());
+            hdr[12..16].copy_from_slice(&to_u32(va)?.to_le_bytes());
+            hdr[16..20].copy_from_slice(&to_u32(raw_size)?.to_le_bytes());
+            hdr[20..24].copy_from_slice(&to_u32(file_pos)?.to_le_bytes());
+            hdr[36..40].copy_from_slice(&DATA_SECTION.to_le_bytes());
+            let at = table_end + i * SECTION_HEADER_LEN;
+            img[at..at + SECTION_HEADER_LEN].copy_from_slice(&hdr);
+
+            file_pos += raw_size;
+            va = align(va + len.max(1), h.section_align);
+            init_data += raw_size;
+        }
+        va_end = va;
+
+        put_u16(
+            &mut img,
+            h.coff + 2,
+            u16::try_from(h.nsections + sections.len())
+                .map_err(|_| Error::Assemble("too many sections".into()))?,
+        );
+        put_u32(&mut img, h.opt + 56, to_u32(va_end)?);
+        let old_init = u32_at(&img, h.opt + 8)? as usize;
+        put_u32(&mut img, h.opt + 8, to_u32(old_init + init_data)?);
+        put_u32(&mut img, h.opt + 64, 0);
+        Ok(Self {
+            head: img,
+            file_align: h.file_align,
+        })
+    }
+}
+
+/// Byte offsets of the PE header fields `assemble` touches.
+struct Headers {
+    coff: usize,
+    opt: usize,
+    nsections: usize,
+    section_table: usize,
+    section_align: usize,
+    file_align: usize,
+    size_of_headers: usize,
+    data_dir: usize,
+    dir_count: u32,
+}
+
+impl Headers {
+    fn parse(img: &[u8]) -> Result<Self> {
+        if !img.starts_with(b"MZ") {
+            return Err(bad_stub("stub is not a PE image (no MZ header)"));
+        }
+        let pe = u32_at(img, 0x3c)? as usize;
+        if img.get(pe..pe + 4) != Some(b"PE\0\0") {
+            return Err(bad_stub("stub is not a PE image (no PE signature)"));
+        }
+        let coff = pe + 4;
+        let opt = coff + 20;
+        let nsections = u16_at(img, coff + 2)? as usize;
+        let opt_size = u16_at(img, coff + 16)? as usize;
+        let (data_dir, dir_count) = match u16_at(img, opt)? {
+            0x20b => (opt + 112, u32_at(img, opt + 108)?),
+            0x10b => (opt + 96, u32_at(img, opt + 92)?),
+            m => return Err(bad_stub(format!("unknown optional header magic {m:#x}"))),
+        };
+        let h = Headers {
+            coff,
+            opt,
+            nsections,
+            section_table: opt + opt_size,
+            section_align: u32_at(img, opt + 32)? as usize,
+            file_align: u32_at(img, opt + 36)? as usize,
+            size_of_headers: u32_at(img, opt + 60)? as usize,
+            data_dir,
+            dir_count,
+        };
+        if h.section_align == 0 || h.file_align == 0 {
+            return Err(bad_stub("stub has zero section/file alignment"));
+        }
+        Ok(h)
+    }
+
+    fn section(&self, img: &[u8], i: usize) -> Result<usize> {
+        let at = self.section_table + i * SECTION_HEADER_LEN;
+        if at + SECTION_HEADER_LEN > img.len() {
+            return Err(bad_stub("truncated section table"));
+        }
+        Ok(at)
+    }
+
+    fn section_names(&self, img: &[u8]) -> Result<Vec<String>> {
+        (0..self.nsections)
+            .map(|i| {
+                let at = self.section(img, i)?;
+                let raw = &img[at..at + 8];
+                let end = raw.iter().position(|&c| c == 0).unwrap_or(8);
+                Ok(String::from_utf8_lossy(&raw[..end]).into_owned())
+            })
+            .collect()
+    }
+
+    /// Lowest PointerToRawData of any section (headers must end before it).
+    fn first_raw_data(&self, img: &[u8]) -> Result<usize> {
+        let mut first = usize::MAX;
+        for i in 0..self.nsections {
+            let at = self.section(img, i)?;
+            let ptr = u32_at(img, at + 20)? as usize;
+            if ptr != 0 {
+                first = first.min(ptr);
+            }
+        }
+        Ok(first)
+    }
+
+    /// (end of raw data, end of virtual address space) over all sections.
+    fn extents(&self, img: &[u8]) -> Result<(usize, usize)> {
+        let (mut raw_end, mut va_end) = (self.size_of_headers, self.size_of_headers);
+        for i in 0..self.nsections {
+            let at = self.section(img, i)?;
+            let vsize = u32_at(img, at + 8)? as usize;
+            let va = u32_at(img, at + 12)? as usize;
+            let raw_size = u32_at(img, at + 16)? as usize;
+            let ptr = u32_at(img, at + 20)? as usize;
+            raw_end = raw_end.max(ptr + raw_size);
+            va_end = va_end.max(va + vsize.max(raw_size));
+        }
+        Ok((raw_end, va_end))
+    }
+}
+
+fn bad_stub(msg: impl Into<String>) -> Error {
+    Error::BadStub(msg.into())
+}
+
+fn align(n: usize, to: usize) -> usize {
+    n.div_ceil(to) * to
+}
+
+fn to_u32(n: usize) -> Result<u32> {
+    u32::try_from(n).map_err(|_| Error::Assemble("UKI would exceed 4 GiB".into()))
+}
+
+fn u16_at(b: &[u8], at: usize) -> Result<u16> {
+    let s = b
+        .get(at..at + 2)
+        .ok_or_else(|| bad_stub("truncated PE header"))?;
+    Ok(u16::from_le_bytes([s[0], s[1]]))
+}
+
+fn u32_at(b: &[u8], at: usize) -> Result<u32> {
+    let s = b
+        .get(at..at + 4)
+        .ok_or_else(|| bad_stub("truncated PE header"))?;
+    Ok(u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+}
+
+fn put_u16(b: &mut [u8], at: usize, v: u16) {
+    b[at..at + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(b: &mut [u8], at: usize, v: u32) {
+    b[at..at + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_formats::pe::PeFile;
+    use lowell_test_util::stub;
+    use std::sync::Mutex;
+
+    /// Records every progress call as a line.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ProgressSink for Recorder {
+        fn phase_started(&self, phase: Phase, total: Option<u64>) {
+            let total = total.map_or("?".into(), |t| t.to_string());
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("start {phase} {total}"));
+        }
+        fn bytes_processed(&self, phase: Phase, done: u64) {
+            self.0.lock().unwrap().push(format!("{phase} {done}"));
+        }
+        fn phase_finished(&self, phase: Phase) {
+            self.0.lock().unwrap().push(format!("end {phase}"));
+        }
+    }
+
+    #[test]
+    fn assembled_sections_read_back() {
+        let img = assemble(
+            &stub(),
+            &[
+                (".osrel", b"ID=test\n"),
+                (".cmdline", b"console=ttyS0\0"),
+                (".linux", &[0xAA; 5000]),
+            ],
+        )
+        .unwrap();
+        let pe = PeFile::from_bytes(img).unwrap();
+        let names: Vec<String> = pe
+            .section_table()
+            .unwrap()
+            .into_iter()
+            .map(|(n, _, _)| n)
+            .collect();
+        assert_eq!(names, [".text", ".osrel", ".cmdline", ".linux"]);
+        assert_eq!(
+            pe.section_contents(".osrel").unwrap().unwrap(),
+            b"ID=test\n"
+        );
+        assert_eq!(pe.read_text(".cmdline").unwrap().unwrap(), "console=ttyS0");
+        assert_eq!(
+            pe.section_contents(".linux").unwrap().unwrap(),
+            [0xAA; 5000]
+        );
+        assert!(!pe.is_signed().unwrap());
+    }
+
+    #[test]
+    fn edits_keep_untouched_sections() {
+        let linux = vec![0xAA; 5000];
+        let img = assemble(
+            &stub(),
+            &[
+                (".osrel", b"ID=test\n"),
+                (".cmdline", b"quiet\0"),
+                (".initrd", b"070701"),
+                (".linux", &linux),
+            ],
+        )
+        .unwrap();
+        let edited = edit(
+            &img,
+            &[
+                (".cmdline", Some(b"quiet loglevel=0\0")),
+                (".osrel", None),
+                (".dtb", Some(b"\xd0\x0d\xfe\xed")),
+            ],
+        )
+        .unwrap();
+        assert_eq!(edited.unchanged, [".initrd", ".linux"]);
+        let pe = PeFile::from_bytes(edited.image.clone()).unwrap();
+        let names: Vec<String> = pe
+            .section_table()
+            .unwrap()
+            .into_iter()
+            .map(|(n, _, _)| n)
+            .collect();
+        assert_eq!(names, [".text", ".cmdline", ".dtb", ".initrd", ".linux"]);
+        assert_eq!(
+            pe.read_text(".cmdline").unwrap().unwrap(),
+            "quiet loglevel=0"
+        );
+        let before = PeFile::from_bytes(img.clone()).unwrap();
+        for name in [".initrd", ".linux"] {
+            assert_eq!(
+                pe.section(name).unwrap().bytes,
+                before.section(name).unwrap().bytes
+            );
+        }
+
+        // Editing back gives the image a fresh build would.
+        let back = edit(
+            &edited.image,
+            &[
+                (".cmdline", Some(b"quiet\0")),
+                (".osrel", Some(b"ID=test\n")),
+                (".dtb", None),
+            ],
+        )
+        .unwrap();
+        assert_eq!(back.image, img);
+        assert!(edit(&img, &[(".splash", None)]).is_err());
+        assert!(edit(&stub(), &[(".cmdline", Some(b"x\0"))]).is_err());
+    }
+
+    #[test]
+    fn duplicate_or_long_names_are_rejected() {
+        assert!(assemble(&stub(), &[(".text", b"x")]).is_err());
+        assert!(assemble(&stub(), &[(".toolongname", b"x")]).is_err());
+    }
+
+    #[test]
+    fn build_reports_progress_per_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, data: &[u8]| {
+            let p = dir.path().join(name);
+            std::fs::write(&p, data).unwrap();
+            p
+        };
+        let inputs = BuildInputs {
+            stub: file("stub.efi", &stub()),
+            kernel: file("vmlinuz", &[0xAA; 100]),
+            initrd: file("initrd", b"070701"),
+            initrd_compression: DEFAULT_COMPRESSION,
+            osrel: None,
+            dtb: None,
+            cmdline: String::new(),
+        };
+        let rec = Recorder::default();
+        inputs.build_with(&rec, &CancelToken::new()).unwrap();
+        let log = rec.0.into_inner().unwrap();
+        assert_eq!(
+            log[..3],
+            ["start read stub 1536", "read stub 1536", "end read stub"]
+        );
+        assert!(log.contains(&"read .linux 100".to_string()));
+        assert_eq!(log[log.len() - 2..], ["start assemble 106", "end assemble"]);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn directory_initrd_is_packed() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("initrd");
+        std::fs::create_dir(&tree).unwrap();
+        std::fs::write(tree.join("init"), b"#!/bin/sh\n").unwrap();
+        let (stub_path, kernel) = (dir.path().join("stub.efi"), dir.path().join("vmlinuz"));
+        std::fs::write(&stub_path, stub()).unwrap();
+        std::fs::write(&kernel, [0xAA; 100]).unwrap();
+        let inputs = BuildInputs {
+            stub: stub_path,
+            kernel,
+            initrd: tree,
+            initrd_compression: Compression::Gzip,
+            osrel: None,
+            dtb: None,
+            cmdline: String::new(),
+        };
+        let pe = PeFile::from_bytes(inputs.build().unwrap()).unwrap();
+        let initrd = pe.section(".initrd").unwrap();
+        assert_eq!(
+            lowell_formats::initramfs::detect(initrd.contents()),
+            Compression::Gzip
+        );
+    }
+
+    #[test]
+    fn streamed_build_matches_in_memory_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, data: &[u8]| {
+            let p = dir.path().join(name);
+            std::fs::write(&p, data).unwrap();
+            p
+        };
+        let tree = dir.path().join("initrd");
+        std::fs::create_dir(&tree).unwrap();
+        std::fs::write(tree.join("init"), b"#!/bin/sh\n").unwrap();
+        let mut inputs = BuildInputs {
+            stub: file("stub.efi", &stub()),
+            kernel: file("vmlinuz", &vec![0xAA; COPY_CHUNK + 3]),
+            initrd: tree,
+            initrd_compression: Compression::Uncompressed,
+            osrel: Some(file("os-release", b"ID=test\n")),
+            dtb: None,
+            cmdline: "quiet".into(),
+        };
+        let streamed = inputs
+            .write_to(Vec::new(), &NoProgress, &CancelToken::new())
+            .unwrap();
+        assert_eq!(streamed, inputs.build().unwrap());
+
+        inputs.initrd = file("initrd.img", b"070701");
+        let streamed = inputs
+            .write_to(Vec::new(), &NoProgress, &CancelToken::new())
+            .unwrap();
+        assert_eq!(streamed, inputs.build().unwrap());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_build_matches_blocking_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, data: &[u8]| {
+            let p = dir.path().join(name);
+            std::fs::write(&p, data).unwrap();
+            p
+        };
+        let inputs = BuildInputs {
+            stub: file("stub.efi", &stub()),
+            kernel: file("vmlinuz", &[0xAA; 100]),
+            initrd: file("initrd", b"070701"),
+            initrd_compression: DEFAULT_COMPRESSION,
+            osrel: Some(file("os-release", b"ID=test\n")),
+            dtb: None,
+            cmdline: "quiet".into(),
+        };
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let img = rt.block_on(inputs.build_async()).unwrap();
+        assert_eq!(img, inputs.build().unwrap());
+        let names: Vec<String> = PeFile::from_bytes(img)
+            .unwrap()
+            .section_table()
+            .unwrap()
+            .into_iter()
+            .map(|(n, _, _)| n)
+            .collect();
+        assert_eq!(names, [".text", ".osrel", ".cmdline", ".initrd", ".linux"]);
+    }
+}
@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `--baseline FILE`: compare a report with a committed one, for CI gates.
+//!
+//! Both reports are compared in their JSON form, field by field. Fields
+//! are named by dotted paths as for `--get` (`initrd.section.digest`,
+//! `sections.0.name`); an `--ignore` pattern names a field, or a whole
+//! subtree, that may change (a hash that moves with every kernel build),
+//! and `*` in a pattern matches any one segment (`*.digest`).
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// One field that differs: absent on the side where it is `None`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+    pub path: String,
+    pub baseline: Option<Value>,
+    pub current: Option<Value>,
+}
+
+/// Where `current` differs from `baseline`, outside the `ignore`d fields.
+pub fn compare(baseline: &Value, current: &Value, ignore: &[String]) -> Vec<Difference> {
+    let ignore: Vec<Vec<&str>> = ignore.iter().map(|p| p.split('.').collect()).collect();
+    let mut out = Vec::new();
+    walk(
+        &mut Vec::new(),
+        Some(baseline),
+        Some(current),
+        &ignore,
+        &mut out,
+    );
+    out
+}
+
+fn walk(
+    path: &mut Vec<String>,
+    baseline: Option<&Value>,
+    current: Option<&Value>,
+    ignore: &[Vec<&str>],
+    out: &mut Vec<Difference>,
+) {
+    if ignored(path, ignore) {
+        return;
+    }
+    match (baseline, current) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                path.push(key.clone());
+                walk(path, a.get(key), b.get(key), ignore, out);
+                path.pop();
+            }
+        }
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            for i in 0..a.len().max(b.len()) {
+                path.push(i.to_string());
+                walk(path, a.get(i), b.get(i), ignore, out);
+                path.pop();
+            }
+        }
+        (a, b) if a == b => {}
+        (a, b) => out.push(Difference {
+            path: path.join("."),
+            baseline: a.cloned(),
+            current: b.cloned(),
+        }),
+    }
+}
+
+/// Whether a pattern matches `path` or one of its parents.
+fn ignored(path: &[String], ignore: &[Vec<&str>]) -> bool {
+    ignore.iter().any(|pattern| {
+        pattern.len() <= path.len()
+            && pattern
+                .iter()
+                .zip(path)
+                .all(|(p, seg)| *p == "*" || p == seg)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn differences_outside_the_allow_list() {
+        let baseline = json!({
+            "cmdline": "quiet",
+            "linux": {"size": 10, "digest": "sha256:aa"},
+            "initrd": {"section": {"size": 5, "digest": "sha256:bb"}},
+            "sections": [{"name": ".linux"}, {"name": ".initrd"}],
+        });
+        let current = json!({
+            "cmdline": "quiet splash",
+            "linux": {"size": 10, "digest": "sha256:cc"},
+            "initrd": {"section": {"size": 5, "digest": "sha256:dd"}},
+            "sections": [{"name": ".linux"}, {"name": ".initrd"}, {"name": ".dtb"}],
+            "os_release": null,
+        });
+        let paths = |ignore: &[&str]| {
+            let ignore: Vec<String> = ignore.iter().map(|s| s.to_string()).collect();
+            compare(&baseline, &current, &ignore)
+                .into_iter()
+                .map(|d| d.path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            paths(&[]),
+            [
+                "cmdline",
+                "initrd.section.digest",
+                "linux.digest",
+                "os_release",
+                "sections.2"
+            ]
+        );
+        assert_eq!(
+            paths(&["*.digest", "initrd", "sections", "os_release"]),
+            ["cmdline"]
+        );
+        let d = &compare(&baseline, &current, &["cmdline".into()])[0];
+        assert_eq!(d.path, "initrd.section.digest");
+        assert_eq!(d.baseline, Some(json!("sha256:bb")));
+        assert_eq!(compare(&current, &current, &[]), []);
+    }
+}
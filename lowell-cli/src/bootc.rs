@@ -0,0 +1,614 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Boot inputs from a bootc container image (`lowell build --image`).
+//!
+//! A bootc (ostree-native) image carries everything a UKI needs: the
+//! kernel and its initramfs at `usr/lib/modules/<kver>/vmlinuz` and
+//! `initramfs.img`, the OS identity in `usr/lib/os-release`, and kernel
+//! arguments in `usr/lib/bootc/kargs.d/*.toml` (`kargs = [...]`, applied
+//! in file name order, optionally only on some `match-architectures`).
+//! With several kernels the newest version is used.
+//!
+//! Only those files are extracted. The image's layers are indexed first,
+//! later layers winning and whiteouts deleting, and the wanted paths are
+//! resolved through symlinks (`usr/lib/os-release` is often one) and hard
+//! links (ostree-encapsulated layers store most files once, in
+//! `sysroot/ostree/repo/objects`, and link them into `usr`); then the
+//! layers holding the content are read again to copy it out.
+//!
+//! Images come from a registry (`REGISTRY/REPOSITORY[:TAG][@DIGEST]`, as
+//! for `lowell pull`), their layers kept in the download cache, or from an
+//! OCI layout directory (`oci:DIR[:TAG]`, as `skopeo copy` writes one). An
+//! index resolves to the Linux image for this host's architecture.
+
+use crate::esp::vercmp;
+use crate::fetch::Fetcher;
+use crate::registry::reference::Reference;
+use crate::registry::{self, Access, Client, Descriptor, Manifest};
+use anyhow::{bail, Context, Result};
+use lowell_core::digest::Algorithm;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tempfile::TempDir;
+use tracing::{debug, info};
+
+const MODULES: &str = "usr/lib/modules";
+const KARGS_D: &str = "usr/lib/bootc/kargs.d/";
+
+/// Where an image comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageSource {
+    Registry(Reference),
+    /// An OCI layout directory, with the tag to pick from its index.
+    Layout {
+        dir: PathBuf,
+        tag: Option<String>,
+    },
+}
+
+impl FromStr for ImageSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some(rest) = s.strip_prefix("oci:") else {
+            return Ok(ImageSource::Registry(s.parse()?));
+        };
+        let (dir, tag) = match rest.rsplit_once(':') {
+            Some((dir, tag)) if !tag.contains('/') && !dir.is_empty() => (dir, Some(tag)),
+            _ => (rest, None),
+        };
+        Ok(ImageSource::Layout {
+            dir: dir.into(),
+            tag: tag.map(str::to_string),
+        })
+    }
+}
+
+impl fmt::Display for ImageSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageSource::Registry(r) => write!(f, "{r}"),
+            ImageSource::Layout { dir, tag: None } => write!(f, "oci:{}", dir.display()),
+            ImageSource::Layout { dir, tag: Some(t) } => write!(f, "oci:{}:{t}", dir.display()),
+        }
+    }
+}
+
+/// An image's boot inputs, copied out into a temporary directory that
+/// lives as long as this does.
+pub struct BootFiles {
+    _dir: TempDir,
+    /// Digest of the manifest (or index) the source named.
+    pub digest: String,
+    pub kver: String,
+    pub kernel: PathBuf,
+    pub initramfs: PathBuf,
+    pub osrel: Option<PathBuf>,
+    /// From `kargs.d`, in order.
+    pub kargs: Vec<String>,
+}
+
+/// Pull `source`'s boot inputs out of its layers.
+pub fn extract(source: &ImageSource, plain_http: bool, fetcher: &Fetcher) -> Result<BootFiles> {
+    let (layers, digest) = layers(source, plain_http, fetcher)?;
+    let index = Index::scan(&layers)?;
+
+    let kver = index
+        .entries
+        .keys()
+        .filter_map(|p| {
+            p.strip_prefix(MODULES)?
+                .strip_prefix('/')?
+                .strip_suffix("/vmlinuz")
+        })
+        .filter(|k| !k.contains('/'))
+        .max_by(|a, b| vercmp(a, b))
+        .map(str::to_string)
+        .with_context(|| format!("{source}: no kernel in {MODULES}/*/vmlinuz"))?;
+    let module_dir = format!("{MODULES}/{kver}");
+    let mut wanted: Vec<(&str, String)> = vec![
+        ("kernel", format!("{module_dir}/vmlinuz")),
+        ("initramfs", format!("{module_dir}/initramfs.img")),
+    ];
+    if let Some(osrel) = ["usr/lib/os-release", "etc/os-release"]
+        .into_iter()
+        .find(|p| index.resolve(p).is_some())
+    {
+        wanted.push(("osrel", osrel.to_string()));
+    }
+    let kargs_files: Vec<String> = index
+        .entries
+        .keys()
+        .filter(|p| p.starts_with(KARGS_D) && p.ends_with(".toml"))
+        .filter(|p| !p[KARGS_D.len()..].contains('/'))
+        .cloned()
+        .collect();
+    for file in &kargs_files {
+        wanted.push(("kargs", file.clone()));
+    }
+
+    let dir = tempfile::tempdir()?;
+    let mut copies: Vec<(usize, String, PathBuf)> = Vec::new();
+    let mut out: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for (i, (what, path)) in wanted.iter().enumerate() {
+        let Some((layer, name)) = index.resolve(path) else {
+            bail!("{source}: no {path} (needed for the {what})");
+        };
+        let dest = dir.path().join(format!("{i}-{what}"));
+        copies.push((layer, name, dest.clone()));
+        out.insert(path.clone(), dest);
+    }
+    copy_out(&layers, &copies)?;
+
+    let mut kargs = Vec::new();
+    for file in &kargs_files {
+        let text = std::fs::read_to_string(&out[file])?;
+        let parsed: KargsFile =
+            toml::from_str(&text).with_context(|| format!("{source}: parse {file}"))?;
+        match &parsed.match_architectures {
+            Some(arches) if !arches.iter().any(|a| a == std::env::consts::ARCH) => {
+                debug!(file, "kargs.d file is for other architectures")
+            }
+            _ => kargs.extend(parsed.kargs),
+        }
+    }
+    let files = BootFiles {
+        digest,
+        kernel: out.remove(&wanted[0].1).expect("wanted"),
+        initramfs: out.remove(&wanted[1].1).expect("wanted"),
+        osrel: wanted
+            .iter()
+            .find(|(what, _)| *what == "osrel")
+            .and_then(|(_, p)| out.remove(p)),
+        kver,
+        kargs,
+        _dir: dir,
+    };
+    info!(image = %source, kver = files.kver, kargs = files.kargs.len(), "extracted boot files");
+    Ok(files)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct KargsFile {
+    #[serde(default)]
+    kargs: Vec<String>,
+    #[serde(default)]
+    match_architectures: Option<Vec<String>>,
+}
+
+/// The image's layer blobs, bottom first, and the digest of what `source`
+/// named.
+fn layers(
+    source: &ImageSource,
+    plain_http: bool,
+    fetcher: &Fetcher,
+) -> Result<(Vec<PathBuf>, String)> {
+    match source {
+        ImageSource::Registry(reference) => {
+            let mut client = Client::new(reference, Access::Pull, plain_http);
+            let (manifest, digest) = client.image_manifest(reference.target())?;
+            let mut paths = Vec::new();
+            for layer in &manifest.layers {
+                let hex = sha256_hex(layer)?;
+                let (path, _, cached) = fetcher
+                    .store(hex, |file| client.blob_to(layer, file))
+                    .with_context(|| format!("{reference}: layer {}", layer.digest))?;
+                debug!(layer = %layer.digest, cached, "layer");
+                paths.push(path);
+            }
+            Ok((paths, digest))
+        }
+        ImageSource::Layout { dir, tag } => {
+            let blob = |desc: &Descriptor| -> Result<PathBuf> {
+                Ok(dir.join("blobs/sha256").join(sha256_hex(desc)?))
+            };
+            let read = |desc: &Descriptor| -> Result<Vec<u8>> {
+                let path = blob(desc)?;
+                std::fs::read(&path).with_context(|| format!("read {}", path.display()))
+            };
+            let index_path = dir.join("index.json");
+            let index: registry::Index = serde_json::from_slice(
+                &std::fs::read(&index_path)
+                    .with_context(|| format!("read {}", index_path.display()))?,
+            )
+            .with_context(|| format!("{}: not an OCI index", index_path.display()))?;
+            const REF_NAME: &str = "org.opencontainers.image.ref.name";
+            let named = |e: &&registry::IndexEntry| match tag {
+                Some(t) => e.descriptor.annotations.get(REF_NAME) == Some(t),
+                None => true,
+            };
+            let entries: Vec<_> = index.manifests.iter().filter(named).collect();
+            let desc = match entries.as_slice() {
+                [one] => &one.descriptor,
+                [] => bail!(
+                    "{source}: no image tagged {:?}",
+                    tag.as_deref().unwrap_or("")
+                ),
+                _ => bail!("{source}: several images; add :TAG"),
+            };
+            let bytes = read(desc)?;
+            desc.verify(&bytes)?;
+            let manifest: Manifest = registry::image_manifest(&bytes, read)
+                .with_context(|| format!("{source}: {}", desc.digest))?;
+            let paths = manifest.layers.iter().map(blob).collect::<Result<_>>()?;
+            Ok((paths, Algorithm::Sha256.digest(&bytes).to_string()))
+        }
+    }
+}
+
+fn sha256_hex(desc: &Descriptor) -> Result<&str> {
+    match desc.digest.split_once(':') {
+        Some(("sha256", hex)) if hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            Ok(hex)
+        }
+        _ => bail!("unsupported layer digest {}", desc.digest),
+    }
+}
+
+/// A layer as a tar stream, decompressed by its magic.
+fn open_layer(path: &Path) -> Result<tar::Archive<Box<dyn Read>>> {
+    let mut file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut magic = [0u8; 4];
+    let n = file.read(&mut magic)?;
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = match &magic[..n] {
+        [0x1f, 0x8b, ..] => Box::new(flate2::read::GzDecoder::new(file)),
+        [0x28, 0xb5, 0x2f, 0xfd] => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// A tar path relative to the image root: no `./`, leading or trailing `/`.
+fn clean(path: &Path) -> String {
+    let s = path.to_string_lossy();
+    let s = s.trim_start_matches("./").trim_start_matches('/');
+    s.trim_end_matches('/').to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    File,
+    Dir,
+    /// Hard link to another path of the image.
+    Link(String),
+    Symlink(String),
+}
+
+/// What every path of the merged image is, and in which layer.
+struct Index {
+    entries: BTreeMap<String, (usize, Node)>,
+}
+
+impl Index {
+    fn scan(layers: &[PathBuf]) -> Result<Self> {
+        let mut entries: BTreeMap<String, (usize, Node)> = BTreeMap::new();
+        for (layer, blob) in layers.iter().enumerate() {
+            let mut archive = open_layer(blob)?;
+            let read = || format!("read layer {}", blob.display());
+            for entry in archive.entries().with_context(read)? {
+                let entry = entry.with_context(read)?;
+                let path = clean(&entry.path().with_context(read)?);
+                let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+                if name == ".wh..wh..opq" {
+                    let prefix = format!("{dir}/");
+                    entries.retain(|p, (l, _)| *l == layer || !p.starts_with(&prefix));
+                    continue;
+                }
+                if let Some(gone) = name.strip_prefix(".wh.") {
+                    let gone = if dir.is_empty() {
+                        gone.to_string()
+                    } else {
+                        format!("{dir}/{gone}")
+                    };
+                    let below = format!("{gone}/");
+                    entries.retain(|p, _| *p != gone && !p.starts_with(&below));
+                    continue;
+                }
+                let link = || -> Result<String> {
+                    let target = entry.link_name().with_context(read)?.unwrap_or_default();
+                    Ok(target.to_string_lossy().into_owned())
+                };
+                let node = match entry.header().entry_type() {
+                    tar::EntryType::Regular | tar::EntryType::Continuous => Node::File,
+                    tar::EntryType::Directory => Node::Dir,
+                    tar::EntryType::Link => Node::Link(clean(Path::new(&link()?))),
+                    tar::EntryType::Symlink => Node::Symlink(link()?),
+                    _ => continue,
+                };
+                entries.insert(path, (layer, node));
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// The layer and tar path holding `path`'s content, through symlinks
+    /// (in any directory along the way) and hard links.
+    fn resolve(&self, path: &str) -> Option<(usize, String)> {
+        let mut path = path.to_string();
+        for _ in 0..40 {
+            match self.entries.get(&path) {
+                Some((layer, Node::File)) => return Some((*layer, path)),
+                Some((_, Node::Link(target))) => path = target.clone(),
+                Some((_, Node::Symlink(target))) => {
+                    let dir = path.rsplit_once('/').map_or("", |(d, _)| d);
+                    path = join(dir, target);
+                }
+                Some((_, Node::Dir)) => return None,
+                None => path = self.through_dir_links(&path)?,
+            }
+        }
+        None
+    }
+
+    /// `path` with its deepest symlinked directory replaced by the target.
+    fn through_dir_links(&self, path: &str) -> Option<String> {
+        let mut prefix = path;
+        while let Some((dir, _)) = prefix.rsplit_once('/') {
+            if let Some((_, Node::Symlink(target))) = self.entries.get(dir) {
+                let parent = dir.rsplit_once('/').map_or("", |(d, _)| d);
+                return Some(format!("{}{}", join(parent, target), &path[dir.len()..]));
+            }
+            prefix = dir;
+        }
+        None
+    }
+}
+
+/// Resolve a symlink `target` found in `dir`, staying inside the image.
+fn join(dir: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = match target.starts_with('/') {
+        true => Vec::new(),
+        false => dir.split('/').filter(|p| !p.is_empty()).collect(),
+    };
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            p => parts.push(p),
+        }
+    }
+    parts.join("/")
+}
+
+/// Copy each `(layer, tar path)` to its destination, one read per layer.
+fn copy_out(layers: &[PathBuf], copies: &[(usize, String, PathBuf)]) -> Result<()> {
+    let needed: BTreeSet<usize> = copies.iter().map(|(l, _, _)| *l).collect();
+    for layer in needed {
+        let blob = &layers[layer];
+        let read = || format!("read layer {}", blob.display());
+        let mut left: Vec<&(usize, String, PathBuf)> =
+            copies.iter().filter(|(l, _, _)| *l == layer).collect();
+        let mut archive = open_layer(blob)?;
+        for entry in archive.entries().with_context(read)? {
+            let mut entry = entry.with_context(read)?;
+            let path = clean(&entry.path().with_context(read)?);
+            let hits: Vec<&PathBuf> = left
+                .iter()
+                .filter(|(_, name, _)| *name == path)
+                .map(|(_, _, dest)| dest)
+                .collect();
+            let Some((first, rest)) = hits.split_first() else {
+                continue;
+            };
+            let mut file = File::create(first)?;
+            std::io::copy(&mut entry, &mut file).with_context(read)?;
+            for dest in rest {
+                std::fs::copy(first, dest)?;
+            }
+            left.retain(|(_, name, _)| *name != path);
+            if left.is_empty() {
+                break;
+            }
+        }
+        if let Some((_, name, _)) = left.first() {
+            bail!(
+                "layer {}: {name} vanished on the second read",
+                blob.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// A tar layer (gzipped) of `(path, content or link)` entries, `->`
+    /// marking a symlink and `=>` a hard link.
+    fn layer(entries: &[(&str, &str)]) -> Vec<u8> {
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        let mut tar = tar::Builder::new(gz);
+        for (path, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            if let Some(target) = content.strip_prefix("-> ") {
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                tar.append_link(&mut header, path, target).unwrap();
+            } else if let Some(target) = content.strip_prefix("=> ") {
+                header.set_entry_type(tar::EntryType::Link);
+                header.set_size(0);
+                tar.append_link(&mut header, path, target).unwrap();
+            } else {
+                header.set_size(content.len() as u64);
+                tar.append_data(&mut header, path, content.as_bytes())
+                    .unwrap();
+            }
+        }
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// An OCI layout at `dir` holding one image of `layers` under `tag`,
+    /// behind an index for this host; returns the layer descriptors.
+    fn layout(dir: &Path, tag: &str, layers: &[Vec<u8>]) -> Vec<Descriptor> {
+        let blobs = dir.join("blobs/sha256");
+        std::fs::create_dir_all(&blobs).unwrap();
+        let put = |media: &str, data: &[u8]| {
+            let desc = Descriptor::of(media, data, None);
+            std::fs::write(blobs.join(&desc.digest[7..]), data).unwrap();
+            desc
+        };
+        let layers: Vec<Descriptor> = layers
+            .iter()
+            .map(|l| put("application/vnd.oci.image.layer.v1.tar+gzip", l))
+            .collect();
+        let config = put("application/vnd.oci.image.config.v1+json", b"{}");
+        let manifest = serde_json::json!({
+            "schemaVersion": 2, "mediaType": registry::MANIFEST,
+            "config": config, "layers": layers,
+        });
+        let manifest = put(registry::MANIFEST, manifest.to_string().as_bytes());
+        let platform = serde_json::json!({"os": "linux", "architecture": registry::host_goarch()});
+        let other = serde_json::json!({"os": "linux", "architecture": "s390x"});
+        let mut wrong = config.clone();
+        wrong.media_type = registry::MANIFEST.into();
+        let index = serde_json::json!({
+            "schemaVersion": 2, "mediaType": registry::INDEX,
+            "manifests": [
+                {"mediaType": registry::MANIFEST, "digest": wrong.digest, "size": wrong.size, "platform": other},
+                {"mediaType": registry::MANIFEST, "digest": manifest.digest, "size": manifest.size, "platform": platform},
+            ],
+        });
+        let index = put(registry::INDEX, index.to_string().as_bytes());
+        let top = serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": [{
+                "mediaType": registry::INDEX, "digest": index.digest, "size": index.size,
+                "annotations": {"org.opencontainers.image.ref.name": tag},
+            }],
+        });
+        let mut f = File::create(dir.join("index.json")).unwrap();
+        f.write_all(top.to_string().as_bytes()).unwrap();
+        layers
+    }
+
+    /// A bootc-like image: ostree objects hard-linked into `usr`, an
+    /// os-release symlink, two kernels (one removed by a later layer's
+    /// whiteout) and kargs for this and another architecture.
+    fn bootc_layers() -> Vec<Vec<u8>> {
+        let other_arch = if std::env::consts::ARCH == "s390x" {
+            "x86_64"
+        } else {
+            "s390x"
+        };
+        let kargs = format!(
+            "kargs = [\"quiet\"]\nmatch-architectures = [\"{}\"]\n",
+            std::env::consts::ARCH
+        );
+        let other = format!("kargs = [\"nope\"]\nmatch-architectures = [\"{other_arch}\"]\n");
+        vec![
+            layer(&[
+                ("sysroot/ostree/repo/objects/aa/11.file", "vmlinuz 6.11"),
+                ("sysroot/ostree/repo/objects/aa/22.file", "initramfs 6.11"),
+                (
+                    "usr/lib/modules/6.11.0/vmlinuz",
+                    "=> sysroot/ostree/repo/objects/aa/11.file",
+                ),
+                (
+                    "usr/lib/modules/6.11.0/initramfs.img",
+                    "=> sysroot/ostree/repo/objects/aa/22.file",
+                ),
+                ("usr/lib/os-release.d/fedora", "ID=fedora\nVERSION_ID=41\n"),
+                ("usr/lib/os-release", "-> os-release.d/fedora"),
+                (
+                    "usr/lib/bootc/kargs.d/10-console.toml",
+                    "kargs = [\"console=ttyS0\", \"rw\"]\n",
+                ),
+            ]),
+            layer(&[
+                ("sysroot/ostree/repo/objects/bb/33.file", "vmlinuz 6.12"),
+                (
+                    "usr/lib/modules/6.12.0/vmlinuz",
+                    "=> sysroot/ostree/repo/objects/bb/33.file",
+                ),
+                ("usr/lib/modules/6.12.0/initramfs.img", "initramfs 6.12"),
+                ("usr/lib/modules/6.13.0/vmlinuz", "vmlinuz 6.13"),
+                ("usr/lib/bootc/kargs.d/20-quiet.toml", &kargs),
+                ("usr/lib/bootc/kargs.d/30-other.toml", &other),
+            ]),
+            layer(&[("usr/lib/modules/.wh.6.13.0", "")]),
+        ]
+    }
+
+    #[test]
+    fn boot_files_come_out_of_the_merged_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        layout(dir.path(), "41", &bootc_layers());
+        let fetcher = Fetcher::open(Some(dir.path()), &Default::default()).unwrap();
+        let source: ImageSource = format!("oci:{}:41", dir.path().display()).parse().unwrap();
+
+        let files = extract(&source, false, &fetcher).unwrap();
+        assert_eq!(files.kver, "6.12.0");
+        assert_eq!(
+            std::fs::read_to_string(&files.kernel).unwrap(),
+            "vmlinuz 6.12"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&files.initramfs).unwrap(),
+            "initramfs 6.12"
+        );
+        let osrel = std::fs::read_to_string(files.osrel.as_ref().unwrap()).unwrap();
+        assert!(osrel.contains("VERSION_ID=41"));
+        assert_eq!(files.kargs, ["console=ttyS0", "rw", "quiet"]);
+
+        let wrong: ImageSource = format!("oci:{}:40", dir.path().display()).parse().unwrap();
+        assert!(extract(&wrong, false, &fetcher).is_err());
+    }
+
+    #[test]
+    fn registry_images_keep_their_layers_in_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let oci = dir.path().join("oci");
+        let layers = layout(&oci, "41", &bootc_layers());
+        let (host, state) = crate::registry::mock::serve();
+        {
+            let mut s = state.lock().unwrap();
+            for blob in std::fs::read_dir(oci.join("blobs/sha256")).unwrap() {
+                let blob = blob.unwrap();
+                let digest = format!("sha256:{}", blob.file_name().to_string_lossy());
+                let data = std::fs::read(blob.path()).unwrap();
+                s.blobs.insert(digest.clone(), data.clone());
+                s.manifests.insert(digest, data);
+            }
+            let top: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(oci.join("index.json")).unwrap()).unwrap();
+            let index = s.manifests[top["manifests"][0]["digest"].as_str().unwrap()].clone();
+            s.manifests.insert("41".into(), index);
+        }
+        let fetcher = Fetcher::open(Some(&dir.path().join("cache")), &Default::default()).unwrap();
+        let source: ImageSource = format!("{host}/fedora/fedora-bootc:41").parse().unwrap();
+
+        let files = extract(&source, true, &fetcher).unwrap();
+        assert_eq!(files.kver, "6.12.0");
+        assert_eq!(files.kargs, ["console=ttyS0", "rw", "quiet"]);
+        for layer in &layers {
+            let cached = dir
+                .path()
+                .join("cache/fetch/sha256")
+                .join(&layer.digest[7..]);
+            assert!(cached.is_file(), "{} not cached", layer.digest);
+        }
+    }
+
+    #[test]
+    fn symlinks_resolve_inside_the_image() {
+        assert_eq!(join("usr/lib", "os-release.d/x"), "usr/lib/os-release.d/x");
+        assert_eq!(join("etc", "../usr/lib/os-release"), "usr/lib/os-release");
+        assert_eq!(
+            join("etc", "/../../usr/lib/os-release"),
+            "usr/lib/os-release"
+        );
+        let source: ImageSource = "quay.io/fedora/fedora-bootc:41".parse().unwrap();
+        assert!(matches!(source, ImageSource::Registry(r) if r.tag.as_deref() == Some("41")));
+    }
+}
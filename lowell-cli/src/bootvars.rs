@@ -0,0 +1,335 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Firmware boot entries: `Boot####` load options and `BootOrder`, written
+//! through efivarfs as `efibootmgr --create` would.
+//!
+//! A load option is `EFI_LOAD_OPTION`: attributes, the length of its
+//! device path, a UCS-2 description and the path itself, here a hard
+//! drive node (GPT partition number, start, size and GUID) followed by a
+//! file node (`\EFI\Linux\fedora.efi`). An existing entry with the same
+//! device path is reused, so reinstalling does not pile up entries.
+
+use crate::esp::Action;
+use crate::secureboot::{self, Efivars};
+use anyhow::{bail, Result};
+use std::path::Path;
+
+/// Non-volatile, boot service and runtime access.
+pub const ATTRIBUTES: u32 = 0x7;
+const LOAD_OPTION_ACTIVE: u32 = 0x1;
+
+/// The GPT partition a file lives on, as a hard drive media node names it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    pub number: u32,
+    /// First LBA and length, in the disk's logical blocks.
+    pub start: u64,
+    pub size: u64,
+    /// Unique partition GUID, as stored.
+    pub guid: [u8; 16],
+}
+
+impl Partition {
+    /// The partition mounted at `mount`, from sysfs and
+    /// `/dev/disk/by-partuuid`.
+    #[cfg(target_os = "linux")]
+    pub fn of_mount(mount: &Path) -> Result<Self> {
+        use anyhow::Context;
+        use std::os::unix::fs::MetadataExt;
+        let dev = std::fs::metadata(mount)
+            .with_context(|| format!("stat {}", mount.display()))?
+            .dev();
+        let sys = std::path::PathBuf::from(format!(
+            "/sys/dev/block/{}:{}",
+            libc::major(dev),
+            libc::minor(dev)
+        ));
+        let read = |name: &str| -> Option<String> {
+            std::fs::read_to_string(sys.join(name))
+                .ok()
+                .map(|s| s.trim().to_string())
+        };
+        let number = |name: &str| read(name).and_then(|s| s.parse::<u64>().ok());
+        let Some(partition) = number("partition") else {
+            bail!("{} is not on a disk partition", mount.display());
+        };
+        // sysfs counts 512-byte sectors whatever the disk's block size.
+        let block = number("../queue/logical_block_size").unwrap_or(512);
+        let (start, size) = (number("start"), number("size"));
+        let (Some(start), Some(size)) = (start, size) else {
+            bail!("{}: no start/size in {}", mount.display(), sys.display());
+        };
+        let devname = read("uevent")
+            .and_then(|u| {
+                u.lines()
+                    .find_map(|l| l.strip_prefix("DEVNAME=").map(str::to_string))
+            })
+            .with_context(|| format!("{}: no DEVNAME", sys.display()))?;
+        let device = Path::new("/dev").join(&devname);
+        let by_uuid = Path::new("/dev/disk/by-partuuid");
+        let guid = std::fs::read_dir(by_uuid)
+            .with_context(|| format!("read {}", by_uuid.display()))?
+            .filter_map(|e| e.ok())
+            .find(|e| std::fs::canonicalize(e.path()).ok().as_deref() == Some(&device))
+            .and_then(|e| parse_guid(&e.file_name().to_string_lossy()))
+            .with_context(|| format!("{devname} has no GPT partition UUID"))?;
+        Ok(Self {
+            number: partition as u32,
+            start: start * 512 / block,
+            size: size * 512 / block,
+            guid,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn of_mount(mount: &Path) -> Result<Self> {
+        bail!("{}: finding its partition needs Linux", mount.display())
+    }
+}
+
+/// `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` in EFI's mixed-endian layout.
+pub fn parse_guid(s: &str) -> Option<[u8; 16]> {
+    let parts: Vec<&str> = s.split('-').collect();
+    let [a, b, c, d, e] = parts.as_slice() else {
+        return None;
+    };
+    if [a.len(), b.len(), c.len(), d.len(), e.len()] != [8, 4, 4, 4, 12] {
+        return None;
+    }
+    let tail = u64::from_str_radix(&format!("{d}{e}"), 16).ok()?;
+    Some(secureboot::guid(
+        u32::from_str_radix(a, 16).ok()?,
+        u16::from_str_radix(b, 16).ok()?,
+        u16::from_str_radix(c, 16).ok()?,
+        tail.to_be_bytes(),
+    ))
+}
+
+/// An `EFI_LOAD_OPTION`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOption {
+    pub attributes: u32,
+    pub description: String,
+    /// The encoded device path list.
+    pub path: Vec<u8>,
+    /// Optional data passed to the program.
+    pub data: Vec<u8>,
+}
+
+impl LoadOption {
+    /// An active entry booting `file` (relative to the partition, `/` or
+    /// `\` separated) on `partition`.
+    pub fn new(description: &str, partition: &Partition, file: &str) -> Self {
+        Self {
+            attributes: LOAD_OPTION_ACTIVE,
+            description: description.to_string(),
+            path: device_path(partition, file),
+            data: Vec::new(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.attributes.to_le_bytes().to_vec();
+        out.extend((self.path.len() as u16).to_le_bytes());
+        out.extend(ucs2(&self.description));
+        out.extend(&self.path);
+        out.extend(&self.data);
+        out
+    }
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let attributes = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?);
+        let path_len = u16::from_le_bytes(bytes.get(4..6)?.try_into().ok()?) as usize;
+        let units: Vec<u16> = bytes[6..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&u| u != 0)
+            .collect();
+        let path_at = 6 + (units.len() + 1) * 2;
+        let path = bytes.get(path_at..path_at + path_len)?.to_vec();
+        Some(Self {
+            attributes,
+            description: String::from_utf16_lossy(&units),
+            path,
+            data: bytes[path_at + path_len..].to_vec(),
+        })
+    }
+}
+
+/// Hard drive node, file path node, end node.
+fn device_path(partition: &Partition, file: &str) -> Vec<u8> {
+    let mut out = vec![0x04, 0x01, 42, 0];
+    out.extend(partition.number.to_le_bytes());
+    out.extend(partition.start.to_le_bytes());
+    out.extend(partition.size.to_le_bytes());
+    out.extend(partition.guid);
+    // GPT partition format, GUID signature.
+    out.extend([0x02, 0x02]);
+    let file = format!(
+        "\\{}",
+        file.trim_start_matches(['/', '\\']).replace('/', "\\")
+    );
+    let name = ucs2(&file);
+    out.extend([0x04, 0x04]);
+    out.extend(((4 + name.len()) as u16).to_le_bytes());
+    out.extend(name);
+    out.extend([0x7f, 0xff, 0x04, 0x00]);
+    out
+}
+
+/// NUL-terminated UCS-2.
+fn ucs2(s: &str) -> Vec<u8> {
+    s.encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// `BootOrder` as entry numbers.
+pub fn boot_order(vars: &Efivars) -> Result<Vec<u16>> {
+    Ok(vars
+        .read("BootOrder", secureboot::GLOBAL)?
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect())
+}
+
+/// What [`ensure`] did.
+#[derive(Debug)]
+pub struct BootEntry {
+    /// `Boot####` number.
+    pub number: u16,
+    pub action: Action,
+    /// Whether it had to be moved to the front of `BootOrder`.
+    pub reordered: bool,
+}
+
+/// Make `option` a `Boot####` entry, reusing one with the same device path
+/// or taking the lowest free number, and put it first in `BootOrder`.
+pub fn ensure(vars: &Efivars, option: &LoadOption) -> Result<BootEntry> {
+    let mut used = Vec::new();
+    let mut existing = None;
+    for name in vars.names(secureboot::GLOBAL)? {
+        let Some(number) = name
+            .strip_prefix("Boot")
+            .filter(|n| n.len() == 4)
+            .and_then(|n| u16::from_str_radix(n, 16).ok())
+        else {
+            continue;
+        };
+        used.push(number);
+        let Some(data) = vars.read(&name, secureboot::GLOBAL)? else {
+            continue;
+        };
+        if existing.is_none() && LoadOption::parse(&data).is_some_and(|o| o.path == option.path) {
+            existing = Some((number, data));
+        }
+    }
+    let encoded = option.encode();
+    let (number, action) = match existing {
+        Some((number, data)) if data == encoded => (number, Action::Current),
+        Some((number, _)) => (number, Action::Updated { from: None }),
+        None => {
+            let Some(free) = (0..=u16::MAX).find(|n| !used.contains(n)) else {
+                bail!("no free Boot#### number");
+            };
+            (free, Action::Installed)
+        }
+    };
+    if action != Action::Current {
+        let name = format!("Boot{number:04X}");
+        vars.write(&name, secureboot::GLOBAL, ATTRIBUTES, &encoded)?;
+    }
+    let order = boot_order(vars)?;
+    let reordered = order.first() != Some(&number);
+    if reordered {
+        let mut new = vec![number];
+        new.extend(order.into_iter().filter(|&n| n != number));
+        let bytes: Vec<u8> = new.into_iter().flat_map(u16::to_le_bytes).collect();
+        vars.write("BootOrder", secureboot::GLOBAL, ATTRIBUTES, &bytes)?;
+    }
+    Ok(BootEntry {
+        number,
+        action,
+        reordered,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_reused_and_moved_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let vars = Efivars::open(dir.path()).unwrap();
+        let guid = parse_guid("c12a7328-f81f-11d2-ba4b-00a0c93ec93b").unwrap();
+        assert_eq!(guid[..4], [0x28, 0x73, 0x2a, 0xc1]);
+        assert_eq!(guid[8..], [0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b]);
+        assert!(parse_guid("c12a7328-f81f-11d2-ba4b").is_none());
+        let esp = Partition {
+            number: 1,
+            start: 2048,
+            size: 1_048_576,
+            guid,
+        };
+
+        // Someone else's entries, in order 0, 1.
+        let other = LoadOption::new("Fedora", &esp, "EFI/fedora/shimx64.efi");
+        vars.write("Boot0000", secureboot::GLOBAL, ATTRIBUTES, &other.encode())
+            .unwrap();
+        vars.write(
+            "Boot0001",
+            secureboot::GLOBAL,
+            ATTRIBUTES,
+            b"\x01\x00\x00\x00",
+        )
+        .unwrap();
+        vars.write("BootOrder", secureboot::GLOBAL, ATTRIBUTES, &[0, 0, 1, 0])
+            .unwrap();
+
+        let option = LoadOption::new(
+            "Linux Boot Manager",
+            &esp,
+            "/EFI/systemd/systemd-bootx64.efi",
+        );
+        let round = LoadOption::parse(&option.encode()).unwrap();
+        assert_eq!(round, option);
+        // Hard drive node, then `\EFI\systemd\systemd-bootx64.efi`.
+        assert_eq!(option.path[..4], [0x04, 0x01, 42, 0]);
+        let file: Vec<u16> = option.path[46..option.path.len() - 6]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        assert_eq!(
+            String::from_utf16(&file).unwrap(),
+            "\\EFI\\systemd\\systemd-bootx64.efi"
+        );
+
+        let e = ensure(&vars, &option).unwrap();
+        assert_eq!(
+            (e.number, &e.action, e.reordered),
+            (2, &Action::Installed, true)
+        );
+        assert_eq!(boot_order(&vars).unwrap(), [2, 0, 1]);
+        let e = ensure(&vars, &option).unwrap();
+        assert_eq!(
+            (e.number, &e.action, e.reordered),
+            (2, &Action::Current, false)
+        );
+
+        // Same program, new label: the entry is updated in place.
+        vars.write("BootOrder", secureboot::GLOBAL, ATTRIBUTES, &[0, 0, 2, 0])
+            .unwrap();
+        let renamed = LoadOption::new("systemd-boot", &esp, "EFI/systemd/systemd-bootx64.efi");
+        let e = ensure(&vars, &renamed).unwrap();
+        assert_eq!(e.number, 2);
+        assert_eq!(e.action, Action::Updated { from: None });
+        assert_eq!(boot_order(&vars).unwrap(), [2, 0]);
+        let stored = vars.read("Boot0002", secureboot::GLOBAL).unwrap().unwrap();
+        assert_eq!(
+            LoadOption::parse(&stored).unwrap().description,
+            "systemd-boot"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Inspect report and build cache (`--cache-dir`, or `cache-dir` in config).
+//!
+//! Reports are stored as JSON in `<dir>/inspect/<key>.json`. The key hashes
+//! the lowell version, the kind of report and the input's canonical path,
+//! size, modification time, mode and owner, so a hit costs a `stat` and
+//! never reads the input, while rewriting the file, `chmod` or `chown` (or
+//! upgrading lowell) misses.
+//!
+//! Built UKIs are stored as `<dir>/build/<key>.efi`, keyed the same way on
+//! every input (each file below a directory initrd included) and on the
+//! build settings. Entries and outputs are copied with
+//! [`clone_atomic`](crate::output::clone_atomic), so on a filesystem with
+//! reflinks a cached UKI and the outputs made from it share their extents
+//! instead of storing the kernel and initrd twice.
+//!
+//! Downloads of profile `[fetch]` inputs share the directory, under
+//! `<dir>/fetch/` (see [`crate::fetch`]).
+//!
+//! Nothing is ever evicted; deleting the directory is always safe.
+
+use crate::config::Config;
+use crate::output::{clone_atomic, write_atomic};
+use anyhow::{Context, Result};
+use lowell_core::digest::Algorithm;
+use lowell_core::uki::build::BuildInputs;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tracing::{debug, warn};
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// The cache under `flag`, else under the configured `cache-dir`;
+    /// `None` when neither is set.
+    pub fn open(flag: Option<&Path>, cfg: &Config) -> Option<Self> {
+        flag.or(cfg.cache_dir.as_deref()).map(|dir| Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// The `kind` report for `file`: from the cache, or from `inspect` and
+    /// then stored. Cache trouble is logged and never fails the command.
+    pub fn report<T, F>(&self, kind: &str, file: &Path, inspect: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>,
+    {
+        let entry = match self.entry(kind, file) {
+            Ok(entry) => entry,
+            // Most likely the input is missing; `inspect` says so properly.
+            Err(e) => {
+                debug!(path = %file.display(), "not cached: {e:#}");
+                return inspect();
+            }
+        };
+        if let Some(report) = load(&entry) {
+            debug!(path = %file.display(), entry = %entry.display(), "cache hit");
+            return Ok(report);
+        }
+        let report = inspect()?;
+        if let Err(e) = store(&entry, &report) {
+            warn!(entry = %entry.display(), "could not cache report: {e:#}");
+        }
+        Ok(report)
+    }
+
+    /// Write the UKI for `inputs` to `out`: a cached copy, or whatever
+    /// `build` writes there, which is then cached. Returns whether it was
+    /// a hit. Cache trouble is logged and never fails the build.
+    pub fn build<F>(&self, inputs: &BuildInputs, out: &Path, build: F) -> Result<bool>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let entry = match build_key(inputs) {
+            Ok(hex) => self.dir.join("build").join(format!("{hex}.efi")),
+            Err(e) => {
+                debug!("not cached: {e:#}");
+                build()?;
+                return Ok(false);
+            }
+        };
+        if entry.is_file() {
+            match clone_atomic(&entry, out) {
+                Ok(reflinked) => {
+                    debug!(entry = %entry.display(), reflinked, "cache hit");
+                    return Ok(true);
+                }
+                Err(e) => warn!(entry = %entry.display(), "could not use cached UKI: {e:#}"),
+            }
+        }
+        build()?;
+        let stored = entry
+            .parent()
+            .map_or(Ok(()), |dir| {
+                std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))
+            })
+            .and_then(|()| clone_atomic(out, &entry));
+        match stored {
+            Ok(reflinked) => debug!(entry = %entry.display(), reflinked, "cached UKI"),
+            Err(e) => warn!(entry = %entry.display(), "could not cache UKI: {e:#}"),
+        }
+        Ok(false)
+    }
+
+    fn entry(&self, kind: &str, file: &Path) -> Result<PathBuf> {
+        let meta = std::fs::metadata(file).with_context(|| format!("stat {}", file.display()))?;
+        let path = std::fs::canonicalize(file)?;
+        let key = format!(
+            "{}\0{kind}\0{}\0{}",
+            env!("CARGO_PKG_VERSION"),
+            path.display(),
+            stamp(&meta)?
+        );
+        let hex = Algorithm::Sha256.digest(key.as_bytes()).hex;
+        Ok(self.dir.join("inspect").join(format!("{hex}.json")))
+    }
+}
+
+/// Size and modification time, and on Unix mode and owner: `chmod` and
+/// `chown` leave the mtime alone, but a packed initrd records the mode.
+fn stamp(meta: &Metadata) -> Result<String> {
+    let mtime = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    Ok(format!("{}\0{mtime}{}", meta.len(), mode_and_owner(meta)))
+}
+
+#[cfg(unix)]
+fn mode_and_owner(meta: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    format!("\0{:o}\0{}\0{}", meta.mode(), meta.uid(), meta.gid())
+}
+
+#[cfg(not(unix))]
+fn mode_and_owner(_: &Metadata) -> String {
+    String::new()
+}
+
+/// Hash of the lowell version, the build settings and every input's
+/// canonical path and [`stamp`]; a directory contributes each entry below
+/// it (symlinks by target).
+fn build_key(inputs: &BuildInputs) -> Result<String> {
+    let mut key = format!("{}\0build\0{inputs:?}", env!("CARGO_PKG_VERSION"));
+    for path in inputs.paths() {
+        let meta = std::fs::metadata(path).with_context(|| format!("stat {}", path.display()))?;
+        let path = std::fs::canonicalize(path)?;
+        key += &format!("\0{}\0{}", path.display(), stamp(&meta)?);
+        if meta.is_dir() {
+            stamp_tree(&path, &mut key)?;
+        }
+    }
+    Ok(Algorithm::Sha256.digest(key.as_bytes()).hex)
+}
+
+fn stamp_tree(dir: &Path, key: &mut String) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| format!("read {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let meta = std::fs::symlink_metadata(&path)?;
+        *key += &format!("\0{}\0{}", path.display(), stamp(&meta)?);
+        if meta.is_symlink() {
+            *key += &format!("\0{}", std::fs::read_link(&path)?.display());
+        } else if meta.is_dir() {
+            stamp_tree(&path, key)?;
+        }
+    }
+    Ok(())
+}
+
+/// A stored report; unreadable or stale-schema entries count as misses.
+fn load<T: DeserializeOwned>(entry: &Path) -> Option<T> {
+    let bytes = std::fs::read(entry).ok()?;
+    serde_json::from_slice(&bytes)
+        .inspect_err(|e| debug!(entry = %entry.display(), "ignoring cache entry: {e}"))
+        .ok()
+}
+
+fn store<T: Serialize>(entry: &Path, report: &T) -> Result<()> {
+    if let Some(dir) = entry.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    write_atomic(entry, &serde_json::to_vec(report)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn builds_hit_until_an_input_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, data: &[u8]| {
+            let p = dir.path().join(name);
+            std::fs::write(&p, data).unwrap();
+            p
+        };
+        let tree = dir.path().join("initrd");
+        std::fs::create_dir(&tree).unwrap();
+        std::fs::write(tree.join("init"), b"#!/bin/sh\n").unwrap();
+        let inputs = BuildInputs {
+            stub: file("stub.efi", b"stub"),
+            kernel: file("vmlinuz", b"kernel"),
+            initrd: tree.clone(),
+            initrd_compression: lowell_core::uki::build::DEFAULT_COMPRESSION,
+            osrel: None,
+            dtb: None,
+            cmdline: "quiet".into(),
+        };
+        let cache = Cache::open(Some(&dir.path().join("cache")), &Config::default()).unwrap();
+        let out = dir.path().join("uki.efi");
+
+        let runs = Cell::new(0);
+        let build = || {
+            runs.set(runs.get() + 1);
+            Ok(std::fs::write(&out, format!("uki {}", runs.get()))?)
+        };
+        assert!(!cache.build(&inputs, &out, build).unwrap());
+        std::fs::remove_file(&out).unwrap();
+        assert!(cache.build(&inputs, &out, build).unwrap());
+        assert_eq!(std::fs::read(&out).unwrap(), b"uki 1");
+
+        // Settings are part of the key, and so is every file in the tree.
+        let quieter = BuildInputs {
+            cmdline: "quiet loglevel=0".into(),
+            ..inputs.clone()
+        };
+        assert!(!cache.build(&quieter, &out, build).unwrap());
+        std::fs::write(tree.join("init"), b"#!/bin/busybox sh\n").unwrap();
+        assert!(!cache.build(&inputs, &out, build).unwrap());
+        assert_eq!(std::fs::read(&out).unwrap(), b"uki 3");
+        assert_eq!(runs.get(), 3);
+
+        // A mode change keeps the mtime, and still misses.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let init = tree.join("init");
+            let mtime = std::fs::metadata(&init).unwrap().modified().unwrap();
+            std::fs::set_permissions(&init, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert_eq!(std::fs::metadata(&init).unwrap().modified().unwrap(), mtime);
+            assert!(!cache.build(&inputs, &out, build).unwrap());
+            assert_eq!(runs.get(), 4);
+        }
+    }
+
+    #[test]
+    fn hits_until_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("image.efi");
+        std::fs::write(&file, b"one").unwrap();
+        let cache = Cache::open(Some(&dir.path().join("cache")), &Config::default()).unwrap();
+
+        let runs = Cell::new(0);
+        let inspect = || {
+            runs.set(runs.get() + 1);
+            Ok(std::fs::read_to_string(&file)?)
+        };
+        assert_eq!(cache.report("test", &file, inspect).unwrap(), "one");
+        assert_eq!(cache.report("test", &file, inspect).unwrap(), "one");
+        assert_eq!(runs.get(), 1);
+        // Other kinds of report for the same file are stored apart.
+        cache.report("other", &file, inspect).unwrap();
+        assert_eq!(runs.get(), 2);
+
+        std::fs::write(&file, b"two").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(cache.report("test", &file, inspect).unwrap(), "two");
+        assert_eq!(runs.get(), 3);
+    }
+}
@@ -0,0 +1,303 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell bench`: time inspect and build over a corpus, phase by phase.
+//!
+//! Every run reports its phases through a [`ProgressSink`] that notes when
+//! each starts and finishes, so the numbers come from the same code paths
+//! as `lowell inspect` and `lowell build` rather than a separate harness.
+//! Phases are grouped into the steps worth comparing between releases:
+//!
+//! | step         | phases                                  |
+//! |--------------|-----------------------------------------|
+//! | `read`       | reading build inputs                    |
+//! | `parse`      | mapping and parsing PE headers          |
+//! | `hash`       | `.linux` / `.initrd` digests            |
+//! | `decompress` | walking the initramfs (entry count)     |
+//! | `compress`   | packing a directory initrd              |
+//! | `assemble`   | laying out the UKI                      |
+//!
+//! `total` is the wall time of the whole run. Builds stay in memory;
+//! nothing is written.
+
+use crate::cli::{build, Ctx, ReportFormat};
+use crate::inputs;
+use anyhow::{bail, Result};
+use clap::Args;
+use lowell_core::cancel::CancelToken;
+use lowell_core::digest::Algorithm;
+use lowell_core::progress::{Phase, ProgressSink};
+use lowell_core::uki::inspect::{self, InspectOptions};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// UKIs to inspect: files, directories (searched recursively for
+    /// `*.efi`) or quoted glob patterns
+    #[arg(value_name = "FILE", required_unless_present = "profile")]
+    files: Vec<PathBuf>,
+    /// Also build this profile, in memory (repeatable)
+    #[arg(long, value_name = "PROFILE")]
+    profile: Vec<String>,
+    /// Timed runs per input
+    #[arg(long, short = 'n', value_name = "N", default_value_t = 10)]
+    iterations: u32,
+    /// Untimed runs per input first (warms the page cache)
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    warmup: u32,
+    /// Digest to time for `.linux`/`.initrd`
+    #[arg(long, value_name = "ALG", default_value_t = Algorithm::Sha256)]
+    digest: Algorithm,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    version: &'static str,
+    iterations: u32,
+    warmup: u32,
+    benches: Vec<Bench>,
+}
+
+#[derive(Serialize)]
+struct Bench {
+    /// `inspect` or `build`.
+    task: &'static str,
+    /// The UKI or profile.
+    input: String,
+    /// One per step seen, then `total`.
+    steps: Vec<Stats>,
+}
+
+/// Timings of one step over all runs, in milliseconds.
+#[derive(Debug, PartialEq, Serialize)]
+struct Stats {
+    step: &'static str,
+    min_ms: f64,
+    median_ms: f64,
+    mean_ms: f64,
+    max_ms: f64,
+    stddev_ms: f64,
+}
+
+impl BenchArgs {
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        if self.iterations == 0 {
+            bail!("--iterations must be at least 1");
+        }
+        let mut benches = Vec::new();
+        for file in inputs::expand(&self.files)? {
+            info!(path = %file.display(), "benchmarking inspect");
+            let steps = self.measure(|sink| {
+                let opts = InspectOptions::new(&file)
+                    .digest(self.digest)
+                    .count_entries(true)
+                    .progress(sink);
+                inspect::inspect(opts)?;
+                Ok(())
+            })?;
+            benches.push(Bench {
+                task: "inspect",
+                input: file.display().to_string(),
+                steps,
+            });
+        }
+        for profile in &self.profile {
+            info!(profile, "benchmarking build");
+            let (_, _, inputs) = build::load(profile, None, None, ctx)?;
+            let steps = self.measure(|sink| {
+                inputs.build_with(&*sink, &CancelToken::new())?;
+                Ok(())
+            })?;
+            benches.push(Bench {
+                task: "build",
+                input: profile.clone(),
+                steps,
+            });
+        }
+        let report = Report {
+            version: env!("CARGO_PKG_VERSION"),
+            iterations: self.iterations,
+            warmup: self.warmup,
+            benches,
+        };
+        match self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human)
+        {
+            ReportFormat::Human => print_human(out, &report, ctx),
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut *out, &report)?;
+                Ok(out.write_all(b"\n")?)
+            }
+            ReportFormat::JsonPretty => {
+                serde_json::to_writer_pretty(&mut *out, &report)?;
+                Ok(out.write_all(b"\n")?)
+            }
+        }
+    }
+
+    /// Run `task` `warmup` times untimed, then `iterations` times timed.
+    fn measure(&self, task: impl Fn(Arc<dyn ProgressSink>) -> Result<()>) -> Result<Vec<Stats>> {
+        for _ in 0..self.warmup {
+            task(Arc::new(StepTimer::default()))?;
+        }
+        let mut samples: BTreeMap<&'static str, Vec<Duration>> = BTreeMap::new();
+        let mut totals = Vec::new();
+        for _ in 0..self.iterations {
+            let timer = Arc::new(StepTimer::default());
+            let start = Instant::now();
+            task(timer.clone())?;
+            totals.push(start.elapsed());
+            let steps = std::mem::take(&mut timer.0.lock().unwrap().steps);
+            for (step, elapsed) in steps {
+                samples.entry(step).or_default().push(elapsed);
+            }
+        }
+        let mut stats: Vec<Stats> = STEPS
+            .iter()
+            .filter_map(|step| Some(Stats::of(step, samples.get(step)?)))
+            .collect();
+        stats.push(Stats::of("total", &totals));
+        Ok(stats)
+    }
+}
+
+/// Steps in the order a run goes through them.
+const STEPS: [&str; 7] = [
+    "read",
+    "parse",
+    "hash",
+    "decompress",
+    "compress",
+    "assemble",
+    "other",
+];
+
+fn step(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Read(_) => "read",
+        Phase::Parse => "parse",
+        Phase::Hash(_) => "hash",
+        Phase::CountEntries => "decompress",
+        Phase::Pack => "compress",
+        Phase::Assemble => "assemble",
+        // `Phase` is non-exhaustive: newer phases until named here.
+        _ => "other",
+    }
+}
+
+/// Time spent in each step during one run (phases of a step add up).
+#[derive(Default)]
+struct StepTimer(Mutex<Timings>);
+
+#[derive(Default)]
+struct Timings {
+    started: Option<Instant>,
+    steps: BTreeMap<&'static str, Duration>,
+}
+
+impl ProgressSink for StepTimer {
+    fn phase_started(&self, _: Phase, _: Option<u64>) {
+        self.0.lock().unwrap().started = Some(Instant::now());
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        let mut t = self.0.lock().unwrap();
+        if let Some(start) = t.started.take() {
+            *t.steps.entry(step(phase)).or_default() += start.elapsed();
+        }
+    }
+}
+
+impl Stats {
+    fn of(step: &'static str, samples: &[Duration]) -> Self {
+        let mut ms: Vec<f64> = samples.iter().map(|d| d.as_secs_f64() * 1e3).collect();
+        ms.sort_by(f64::total_cmp);
+        let n = ms.len() as f64;
+        let mean = ms.iter().sum::<f64>() / n;
+        let median = match ms.len() % 2 {
+            1 => ms[ms.len() / 2],
+            _ => (ms[ms.len() / 2 - 1] + ms[ms.len() / 2]) / 2.0,
+        };
+        let variance = ms.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        Self {
+            step,
+            min_ms: ms[0],
+            median_ms: median,
+            mean_ms: mean,
+            max_ms: ms[ms.len() - 1],
+            stddev_ms: variance.sqrt(),
+        }
+    }
+}
+
+fn print_human(out: &mut dyn Write, report: &Report, ctx: &Ctx) -> Result<()> {
+    let p = ctx.palette;
+    for (i, bench) in report.benches.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "{} {} ({} runs, times in ms)",
+            p.bold(bench.task),
+            bench.input,
+            report.iterations
+        )?;
+        writeln!(
+            out,
+            "  {:<10} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            "step", "min", "median", "mean", "max", "stddev"
+        )?;
+        for s in &bench.steps {
+            writeln!(
+                out,
+                "  {:<10} {:>9.2} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+                s.step, s.min_ms, s.median_ms, s.mean_ms, s.max_ms, s.stddev_ms
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_over_samples() {
+        let ms = |v: &[u64]| {
+            v.iter()
+                .map(|&m| Duration::from_millis(m))
+                .collect::<Vec<_>>()
+        };
+        let s = Stats::of("hash", &ms(&[4, 1, 3, 2]));
+        assert_eq!((s.min_ms, s.max_ms), (1.0, 4.0));
+        assert_eq!((s.median_ms, s.mean_ms), (2.5, 2.5));
+        assert!((s.stddev_ms - 1.25f64.sqrt()).abs() < 1e-9);
+
+        let one = Stats::of("total", &ms(&[7]));
+        assert_eq!((one.median_ms, one.stddev_ms), (7.0, 0.0));
+    }
+
+    #[test]
+    fn phases_of_a_step_add_up() {
+        let timer = StepTimer::default();
+        for phase in [Phase::Hash(".linux"), Phase::Hash(".initrd"), Phase::Parse] {
+            timer.phase_started(phase, None);
+            std::thread::sleep(Duration::from_millis(2));
+            timer.phase_finished(phase);
+        }
+        let steps = timer.0.into_inner().unwrap().steps;
+        assert_eq!(steps.keys().copied().collect::<Vec<_>>(), ["hash", "parse"]);
+        assert!(steps["hash"] >= Duration::from_millis(4));
+    }
+}
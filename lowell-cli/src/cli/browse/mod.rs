@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell browse`: interactive terminal browser for a UKI and its initrd.
+//!
+//! Two panes: the PE section table and the initramfs file tree. The left
+//! side lists entries, the right side shows metadata plus a text (or hex)
+//! preview of the selection. Everything is loaded up front; the UI itself
+//! never touches the file again.
+
+mod tree;
+mod ui;
+
+use anyhow::{bail, Result};
+use clap::Args;
+use lowell_core::formats::pe::PeFile;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use tree::Tree;
+
+#[derive(Args, Debug)]
+pub struct BrowseArgs {
+    /// Path to the UKI to browse
+    file: PathBuf,
+}
+
+impl BrowseArgs {
+    pub fn run(self) -> Result<()> {
+        if !std::io::stdout().is_terminal() {
+            bail!("browse needs an interactive terminal (try `lowell uki inspect` instead)");
+        }
+        let mut app = App::load(&self.file)?;
+        let mut terminal = ratatui::init();
+        let res = app.event_loop(&mut terminal);
+        ratatui::restore();
+        res
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Sections,
+    Files,
+}
+
+/// One row of the section table, with its bytes kept for the preview.
+struct Section {
+    name: String,
+    offset: usize,
+    size: usize,
+    data: Vec<u8>,
+}
+
+struct App {
+    title: String,
+    sections: Vec<Section>,
+    tree: Tree,
+    pane: Pane,
+    section_sel: usize,
+    /// Directory being listed in the Files pane, and the selection in it.
+    cwd: String,
+    file_sel: usize,
+    /// Selection to restore when leaving each visited directory.
+    history: Vec<usize>,
+    quit: bool,
+}
+
+impl App {
+    fn load(path: &std::path::Path) -> Result<Self> {
+        let pe = PeFile::from_path_mmap(path)?;
+        let sections = pe
+            .section_table()?
+            .into_iter()
+            .map(|(name, offset, size)| {
+                let data = pe.section_bytes(&name)?.unwrap_or_default().to_vec();
+                Ok(Section {
+                    name,
+                    offset,
+                    size,
+                    data,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Without the file-alignment padding, which zstd would take for
+        // another (broken) frame.
+        let tree = match pe.section_contents(".initrd")? {
+            Some(initrd) => Tree::from_initrd(initrd)?,
+            None => Tree::default(),
+        };
+        Ok(Self {
+            title: path.display().to_string(),
+            sections,
+            tree,
+            pane: Pane::Sections,
+            section_sel: 0,
+            cwd: String::new(),
+            file_sel: 0,
+            history: Vec::new(),
+            quit: false,
+        })
+    }
+
+    fn event_loop(&mut self, terminal: &mut ratatui::DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|f| ui::draw(f, self))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.on_key(key.code);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.pane = match self.pane {
+                    Pane::Sections => Pane::Files,
+                    Pane::Files => Pane::Sections,
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::PageDown => self.move_by(10),
+            KeyCode::PageUp => self.move_by(-10),
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => self.enter(),
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => self.leave(),
+            _ => {}
+        }
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let (sel, len) = match self.pane {
+            Pane::Sections => (&mut self.section_sel, self.sections.len()),
+            Pane::Files => (&mut self.file_sel, self.tree.children(&self.cwd).len()),
+        };
+        if len > 0 {
+            *sel = sel.saturating_add_signed(delta).min(len - 1);
+        }
+    }
+
+    fn enter(&mut self) {
+        if self.pane != Pane::Files {
+            return;
+        }
+        let Some(path) = self.tree.children(&self.cwd).get(self.file_sel) else {
+            return;
+        };
+        if self.tree.get(path).is_some_and(|n| n.is_dir()) {
+            self.cwd = path.clone();
+            self.history.push(self.file_sel);
+            self.file_sel = 0;
+        }
+    }
+
+    fn leave(&mut self) {
+        if self.pane != Pane::Files || self.cwd.is_empty() {
+            return;
+        }
+        self.cwd = self
+            .cwd
+            .rsplit_once('/')
+            .map_or(String::new(), |(p, _)| p.to_string());
+        self.file_sel = self.history.pop().unwrap_or(0);
+    }
+
+    fn selected_file(&self) -> Option<&tree::Node> {
+        let path = self.tree.children(&self.cwd).get(self.file_sel)?;
+        self.tree.get(path)
+    }
+}
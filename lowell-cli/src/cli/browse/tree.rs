@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! In-memory directory tree of an initramfs for the browser.
+
+use anyhow::Result;
+use lowell_core::formats::{cpio, initramfs};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Bytes of each regular file kept for the preview pane.
+const PREVIEW_BYTES: u64 = 16 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// Path relative to the archive root, no leading `./` or `/` (root is "").
+    pub path: String,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: u32,
+    pub size: u64,
+    pub link_target: Option<String>,
+    pub preview: Vec<u8>,
+}
+
+impl Node {
+    fn dir(path: &str) -> Self {
+        Node {
+            path: path.to_string(),
+            mode: cpio::S_IFDIR | 0o755,
+            uid: 0,
+            gid: 0,
+            mtime: 0,
+            size: 0,
+            link_target: None,
+            preview: Vec::new(),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & cpio::S_IFMT == cpio::S_IFDIR
+    }
+
+    /// Last path component ("/" for the root).
+    pub fn name(&self) -> &str {
+        match self.path.rsplit_once('/') {
+            Some((_, name)) => name,
+            None if self.path.is_empty() => "/",
+            None => &self.path,
+        }
+    }
+}
+
+/// Flattened archive: nodes by path, children sorted dirs-first then by name.
+#[derive(Debug, Default)]
+pub struct Tree {
+    nodes: BTreeMap<String, Node>,
+    children: BTreeMap<String, Vec<String>>,
+}
+
+impl Tree {
+    pub fn from_initrd(bytes: &[u8]) -> Result<Self> {
+        let mut tree = Tree::default();
+        tree.insert(Node::dir(""));
+        initramfs::walk(bytes, |h, data| {
+            let path = normalize(&h.name);
+            if path.is_empty() {
+                return Ok(());
+            }
+            let mut buf = Vec::new();
+            if h.is_file() || h.is_symlink() {
+                data.take(PREVIEW_BYTES).read_to_end(&mut buf)?;
+            }
+            let link_target = h
+                .is_symlink()
+                .then(|| String::from_utf8_lossy(&buf).into_owned());
+            tree.insert(Node {
+                path,
+                mode: h.mode,
+                uid: h.uid,
+                gid: h.gid,
+                mtime: h.mtime,
+                size: u64::from(h.filesize),
+                link_target,
+                preview: if h.is_file() { buf } else { Vec::new() },
+            });
+            Ok(())
+        })?;
+        tree.sort();
+        Ok(tree)
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Node> {
+        self.nodes.get(path)
+    }
+
+    pub fn children(&self, dir: &str) -> &[String] {
+        self.children.get(dir).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// Later entries win (the kernel unpacks segments in order).
+    fn insert(&mut self, node: Node) {
+        if let Some((parent, _)) = node.path.rsplit_once('/') {
+            if !self.nodes.contains_key(parent) {
+                self.insert(Node::dir(parent));
+            }
+        }
+        if !node.path.is_empty() {
+            let parent = node.path.rsplit_once('/').map_or("", |(p, _)| p);
+            let siblings = self.children.entry(parent.to_string()).or_default();
+            if !siblings.contains(&node.path) {
+                siblings.push(node.path.clone());
+            }
+        }
+        self.nodes.insert(node.path.clone(), node);
+    }
+
+    fn sort(&mut self) {
+        let nodes = &self.nodes;
+        for list in self.children.values_mut() {
+            list.sort_by_key(|p| (!nodes[p].is_dir(), p.clone()));
+        }
+    }
+}
+
+fn normalize(name: &str) -> String {
+    let name = name.trim_start_matches("./").trim_start_matches('/');
+    let name = if name == "." { "" } else { name };
+    name.trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_fills_implicit_dirs_and_sorts_dirs_first() {
+        let mut w = cpio::Writer::new(Vec::new());
+        for (name, mode, data) in [
+            (".", cpio::S_IFDIR | 0o755, &b""[..]),
+            ("init", cpio::S_IFREG | 0o755, b"#!/bin/sh\n"),
+            (
+                "usr/lib/modules/virtio_blk.ko",
+                cpio::S_IFREG | 0o644,
+                b"\x7fELF",
+            ),
+            ("bin", cpio::S_IFLNK | 0o777, b"usr/bin"),
+        ] {
+            w.append(&cpio::Header::new(name, mode), data).unwrap();
+        }
+        let archive = w.finish().unwrap();
+        let tree = Tree::from_initrd(&archive).unwrap();
+        assert_eq!(tree.children(""), ["usr", "bin", "init"]);
+        assert_eq!(tree.children("usr/lib"), ["usr/lib/modules"]);
+        assert!(tree.get("usr/lib").unwrap().is_dir());
+        assert_eq!(
+            tree.get("bin").unwrap().link_target.as_deref(),
+            Some("usr/bin")
+        );
+        assert_eq!(tree.get("init").unwrap().preview, b"#!/bin/sh\n");
+        assert_eq!(tree.len(), 6);
+    }
+}
@@ -0,0 +1,212 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Rendering for `lowell browse` (stateless: everything comes from `App`).
+
+use super::{App, Pane};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Tabs, Wrap};
+use ratatui::Frame;
+use std::fmt::Write as _;
+
+/// Bytes shown in the hex preview of binary data.
+const HEX_PREVIEW: usize = 512;
+
+pub fn draw(f: &mut Frame, app: &App) {
+    let [top, body, help] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(f.area());
+    let [left, right] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(body);
+
+    let tabs = Tabs::new([
+        format!("Sections ({})", app.sections.len()),
+        format!("Files ({})", app.tree.len()),
+    ])
+    .select(match app.pane {
+        Pane::Sections => 0,
+        Pane::Files => 1,
+    })
+    .highlight_style(Style::new().add_modifier(Modifier::BOLD | Modifier::REVERSED))
+    .block(Block::new().title(format!("{} ", app.title)));
+    f.render_widget(tabs, top);
+
+    match app.pane {
+        Pane::Sections => draw_sections(f, app, left, right),
+        Pane::Files => draw_files(f, app, left, right),
+    }
+
+    let keys = "q quit • tab switch pane • ↑↓/jk move • enter/→ open dir • ←/backspace up";
+    f.render_widget(
+        Paragraph::new(keys).style(Style::new().fg(Color::DarkGray)),
+        help,
+    );
+}
+
+fn draw_sections(f: &mut Frame, app: &App, left: Rect, right: Rect) {
+    let items: Vec<ListItem> = app
+        .sections
+        .iter()
+        .map(|s| ListItem::new(format!("{:<10} {:>10}", s.name, human_size(s.size as u64))))
+        .collect();
+    render_list(f, left, "PE sections", items, app.section_sel);
+
+    let Some(s) = app.sections.get(app.section_sel) else {
+        return;
+    };
+    let mut lines = vec![
+        kv("name", &s.name),
+        kv("offset", &format!("{:#x}", s.offset)),
+        kv(
+            "size",
+            &format!("{} ({} bytes)", human_size(s.size as u64), s.size),
+        ),
+        Line::raw(""),
+    ];
+    lines.extend(preview(&s.data));
+    render_detail(f, right, &s.name, lines);
+}
+
+fn draw_files(f: &mut Frame, app: &App, left: Rect, right: Rect) {
+    let items: Vec<ListItem> = app
+        .tree
+        .children(&app.cwd)
+        .iter()
+        .filter_map(|p| app.tree.get(p))
+        .map(|n| {
+            let style = if n.is_dir() {
+                Style::new().fg(Color::Blue).add_modifier(Modifier::BOLD)
+            } else if n.link_target.is_some() {
+                Style::new().fg(Color::Cyan)
+            } else {
+                Style::new()
+            };
+            let suffix = if n.is_dir() { "/" } else { "" };
+            ListItem::new(Span::styled(format!("{}{suffix}", n.name()), style))
+        })
+        .collect();
+    render_list(f, left, &format!("/{}", app.cwd), items, app.file_sel);
+
+    let Some(n) = app.selected_file() else {
+        render_detail(f, right, "", vec![Line::raw("(empty directory)")]);
+        return;
+    };
+    let mut lines = vec![
+        kv("path", &format!("/{}", n.path)),
+        kv(
+            "mode",
+            &format!("{} ({:o})", mode_string(n.mode), n.mode & 0o7777),
+        ),
+        kv("owner", &format!("{}:{}", n.uid, n.gid)),
+        kv(
+            "size",
+            &format!("{} ({} bytes)", human_size(n.size), n.size),
+        ),
+        kv("mtime", &n.mtime.to_string()),
+    ];
+    if let Some(t) = &n.link_target {
+        lines.push(kv("target", t));
+    }
+    if !n.preview.is_empty() {
+        lines.push(Line::raw(""));
+        lines.extend(preview(&n.preview));
+        if n.size > n.preview.len() as u64 {
+            lines.push(Line::styled(
+                format!("… {} more bytes", n.size - n.preview.len() as u64),
+                Style::new().fg(Color::DarkGray),
+            ));
+        }
+    }
+    render_detail(f, right, n.name(), lines);
+}
+
+fn render_list(f: &mut Frame, area: Rect, title: &str, items: Vec<ListItem>, sel: usize) {
+    let list = List::new(items)
+        .block(Block::bordered().title(title.to_string()))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("› ");
+    let mut state = ListState::default().with_selected(Some(sel));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_detail(f: &mut Frame, area: Rect, title: &str, lines: Vec<Line>) {
+    let p = Paragraph::new(lines)
+        .block(Block::bordered().title(title.to_string()))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, area);
+}
+
+fn kv(key: &str, value: &str) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(
+            format!("{key:<7} "),
+            Style::new().add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(value.to_string()),
+    ])
+}
+
+/// Text when the bytes look like text, otherwise a hex dump of the start.
+fn preview(data: &[u8]) -> Vec<Line<'static>> {
+    let trimmed = &data[..data.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1)];
+    match std::str::from_utf8(trimmed) {
+        Ok(text) if !text.contains('\0') => {
+            text.lines().map(|l| Line::raw(l.to_string())).collect()
+        }
+        _ => hexdump(&data[..data.len().min(HEX_PREVIEW)]),
+    }
+}
+
+fn hexdump(data: &[u8]) -> Vec<Line<'static>> {
+    data.chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut line = format!("{:08x}  ", i * 16);
+            for b in chunk {
+                let _ = write!(line, "{b:02x} ");
+            }
+            line.push_str(&"   ".repeat(16 - chunk.len()));
+            line.extend(chunk.iter().map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            }));
+            Line::raw(line)
+        })
+        .collect()
+}
+
+fn mode_string(mode: u32) -> String {
+    use lowell_core::formats::cpio::{S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+    let kind = match mode & S_IFMT {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFREG => '-',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        0o010000 => 'p',
+        0o140000 => 's',
+        _ => '?',
+    };
+    let mut s = String::from(kind);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 7;
+        s.push(if bits & 4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+fn human_size(n: u64) -> String {
+    match n {
+        n if n >= 1 << 20 => format!("{:.1} MiB", n as f64 / (1u64 << 20) as f64),
+        n if n >= 1 << 10 => format!("{:.1} KiB", n as f64 / 1024.0),
+        n => format!("{n} B"),
+    }
+}
@@ -0,0 +1,489 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::bootc::{self, BootFiles, ImageSource};
+use crate::cache::Cache;
+use crate::cli::{parse_size, Ctx};
+use crate::error::Coded;
+use crate::fetch::Fetcher;
+use crate::output::{self, Output};
+use crate::provenance::{self, Build};
+use crate::refvalues;
+use crate::sign::{self, Envelope};
+use crate::sysupdate::Transfer;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use lowell_core::cancel::CancelToken;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::osrel::read_os_release;
+use lowell_core::formats::pe::PeFile;
+use lowell_core::formats::tpm::Bank;
+use lowell_core::profile::cmdline::Fragment;
+use lowell_core::profile::ostree::Deployment;
+use lowell_core::profile::{Cmdline, Profile, RootSpec, Source};
+use lowell_core::progress::NoProgress;
+use lowell_core::uki::build::BuildInputs;
+use notify::{RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info};
+
+/// Quiet period after the last change before rebuilding (editors often
+/// write a file in several steps).
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[derive(Args, Debug)]
+pub struct BuildArgs {
+    /// Profile to build: a TOML path, `builtin:<name>` or a name on the profile path
+    #[arg(long, required_unless_present = "image")]
+    profile: Option<String>,
+    /// Take the kernel, initramfs, os-release and kernel arguments from a
+    /// bootc container image (`REGISTRY/REPOSITORY[:TAG][@DIGEST]` or
+    /// `oci:DIR[:TAG]`); `--profile` becomes optional and only adds to them
+    #[arg(long, value_name = "REF", conflicts_with = "watch")]
+    image: Option<ImageSource>,
+    /// Pull `--image` over plain HTTP (a local test registry)
+    #[arg(long, requires = "image")]
+    plain_http: bool,
+    /// Where to write the UKI
+    #[arg(long, value_name = "FILE")]
+    out: PathBuf,
+    /// Keep running and rebuild whenever the profile or an artifact changes
+    #[arg(long)]
+    watch: bool,
+    /// Stream the UKI to disk instead of assembling it in memory when the
+    /// inputs would need more than SIZE (e.g. `256M`)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_memory: Option<u64>,
+    /// Reuse UKIs cached in DIR when no input has changed, sharing storage
+    /// with the output where the filesystem supports reflinks (default:
+    /// `cache-dir` from config, else no cache)
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+    /// Also write `<FILE>.sig`, a signature by KEY that `lowell verify` and
+    /// `cosign verify-blob` check
+    #[arg(long, value_name = "KEY")]
+    sign_key: Option<PathBuf>,
+    /// `--sign-key` with `signing-key` from config
+    #[arg(long, conflicts_with = "sign_key")]
+    sign: bool,
+    /// Write SLSA provenance for the UKI (an in-toto statement, or a DSSE
+    /// envelope with `--sign-key`) to FILE
+    #[arg(long, value_name = "FILE")]
+    provenance: Option<PathBuf>,
+    /// Write the UKI's predicted PCR values and event digests for
+    /// attestation verifiers to FILE (see `--reference-format`)
+    #[arg(long, value_name = "FILE")]
+    reference_values: Option<PathBuf>,
+    /// Format of `--reference-values`
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value = "corim",
+        requires = "reference_values"
+    )]
+    reference_format: refvalues::Format,
+    /// PCR bank of `--reference-values`: sha1, sha256, sha384 or sha512
+    #[arg(long, default_value_t = Bank::Sha256, requires = "reference_values")]
+    reference_bank: Bank,
+    /// Write a systemd-sysupdate `.transfer` for the UKI into DIR; the
+    /// `--out` file name must contain the version (e.g. `fedora_41.1.efi`)
+    #[arg(long, value_name = "DIR")]
+    sysupdate: Option<PathBuf>,
+    /// URL the UKIs are published at, as the transfer's source (default:
+    /// the directory of `--out`); also records the UKI in `SHA256SUMS`
+    /// there
+    #[arg(long, value_name = "URL", requires = "sysupdate")]
+    sysupdate_url: Option<String>,
+    /// Version for the transfer (default: the UKI's os-release
+    /// `IMAGE_VERSION`, else `VERSION_ID`)
+    #[arg(long, value_name = "VERSION", requires = "sysupdate")]
+    sysupdate_version: Option<String>,
+    /// Builder id recorded in the provenance (e.g. your CI job's URI)
+    #[arg(long, value_name = "URI", default_value = provenance::DEFAULT_BUILDER)]
+    builder_id: String,
+    /// For `root = "ostree"`: take `ostree=` and the other kernel arguments,
+    /// kernel and initramfs from the deployment of the profile's `ref` (the
+    /// default deployment without one) in this sysroot (`/` when booted)
+    #[arg(long, value_name = "DIR")]
+    ostree_sysroot: Option<PathBuf>,
+}
+
+impl BuildArgs {
+    pub fn run(self, ctx: &Ctx) -> Result<()> {
+        if !self.watch {
+            return self.build_once(ctx).map(drop);
+        }
+        self.watch(ctx)
+    }
+
+    /// Build and write the UKI; returns the files it depended on.
+    fn build_once(&self, ctx: &Ctx) -> Result<Vec<PathBuf>> {
+        let started = SystemTime::now();
+        // With an image, its extracted files must outlive the build.
+        let (source, profile, inputs, _image) = match &self.image {
+            Some(image) => {
+                let (source, profile, inputs, files) = self.load_image(image, ctx)?;
+                (source, profile, inputs, Some(files))
+            }
+            None => {
+                let (source, profile, inputs) = load(
+                    self.profile.as_deref().unwrap_or_default(),
+                    self.cache_dir.as_deref(),
+                    self.ostree_sysroot.as_deref(),
+                    ctx,
+                )?;
+                (Some(source), profile, inputs, None)
+            }
+        };
+        let key = sign::requested_key(self.sign_key.as_deref(), self.sign, &ctx.cfg)?;
+        let build = || match self.max_memory {
+            Some(budget) if !fits(&inputs, budget) => {
+                info!(
+                    budget,
+                    "inputs exceed --max-memory, streaming the UKI to disk"
+                );
+                let mut out = Output::open(Some(&self.out))?;
+                inputs.write_to(&mut out, &NoProgress, &CancelToken::new())?;
+                out.commit()
+            }
+            _ => output::write_atomic(&self.out, &inputs.build()?),
+        };
+        let cached = match Cache::open(self.cache_dir.as_deref(), &ctx.cfg) {
+            Some(cache) => cache.build(&inputs, &self.out, build)?,
+            None => {
+                build()?;
+                false
+            }
+        };
+        let size = std::fs::metadata(&self.out).map_or(0, |m| m.len());
+        info!(out = %self.out.display(), size, cached, profile = %profile.name, "built UKI");
+        if let Some(key) = &key {
+            let data =
+                std::fs::read(&self.out).with_context(|| format!("read {}", self.out.display()))?;
+            let dest = sign::sig_path(&self.out);
+            output::write_atomic(&dest, sign::sign(key, &data).as_bytes())?;
+            info!(signature = %dest.display(), "signed UKI");
+        }
+        if let Some(dest) = &self.provenance {
+            let build = Build {
+                profile_spec: &self.spec(),
+                profile_name: &profile.name,
+                profile_toml: &profile.to_toml_string()?,
+                builder_id: &self.builder_id,
+                started,
+                finished: SystemTime::now(),
+            };
+            let statement =
+                serde_json::to_vec(&provenance::statement(&self.out, &inputs, &build)?)?;
+            let doc = match &key {
+                Some(key) => {
+                    serde_json::to_vec(&Envelope::seal(key, provenance::IN_TOTO, &statement))?
+                }
+                None => statement,
+            };
+            output::write_atomic(dest, &doc)?;
+            info!(provenance = %dest.display(), signed = key.is_some(), "wrote provenance");
+        }
+        if let Some(dest) = &self.reference_values {
+            let pe = PeFile::from_path(&self.out)?;
+            let doc = refvalues::document(
+                &pe,
+                self.reference_bank,
+                self.reference_format,
+                &profile.name,
+            )?;
+            output::write_atomic(dest, &serde_json::to_vec_pretty(&doc)?)?;
+            info!(
+                reference_values = %dest.display(),
+                format = ?self.reference_format,
+                "wrote reference values"
+            );
+        }
+        if let Some(dir) = &self.sysupdate {
+            let version = match &self.sysupdate_version {
+                Some(v) => v.clone(),
+                None => read_os_release(&PeFile::from_path(&self.out)?)?
+                    .and_then(|os| os.image_version.or(os.version_id))
+                    .ok_or_else(|| {
+                        Coded::new(
+                            ErrorCode::InvalidArgument,
+                            "no IMAGE_VERSION or VERSION_ID in the UKI's os-release; \
+                             pass --sysupdate-version",
+                        )
+                    })?,
+            };
+            let transfer = Transfer::new(&self.out, &version, self.sysupdate_url.as_deref())?;
+            for path in transfer.write(dir, &self.out)? {
+                info!(path = %path.display(), version, "wrote sysupdate file");
+            }
+        }
+
+        let mut deps: Vec<PathBuf> = inputs.paths().into_iter().map(Path::to_path_buf).collect();
+        if let Some(Source::File(path)) = source {
+            deps.push(path);
+        }
+        Ok(deps)
+    }
+
+    fn watch(&self, ctx: &Ctx) -> Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).context("start file watcher")?;
+        // Watch parent directories: editors and `mv` replace files by
+        // renaming, which a watch on the old inode would miss.
+        let mut dirs: BTreeSet<PathBuf> = BTreeSet::new();
+        let mut deps = self.rebuild(ctx, &mut watcher, &mut dirs, Vec::new());
+
+        loop {
+            let event = rx.recv().context("file watcher stopped")?;
+            if !touches(&event, &deps) {
+                continue;
+            }
+            // Coalesce bursts of events into a single rebuild.
+            let mut deadline = Instant::now() + DEBOUNCE;
+            while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+                match rx.recv_timeout(left) {
+                    Ok(e) if touches(&e, &deps) => deadline = Instant::now() + DEBOUNCE,
+                    Ok(_) => {}
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(e) => return Err(e).context("file watcher stopped"),
+                }
+            }
+            info!("change detected, rebuilding");
+            deps = self.rebuild(ctx, &mut watcher, &mut dirs, deps);
+        }
+    }
+
+    /// What the UKI was built from, for provenance.
+    fn spec(&self) -> String {
+        match (&self.profile, &self.image) {
+            (Some(profile), Some(image)) => format!("{profile} + {image}"),
+            (Some(profile), None) => profile.clone(),
+            (None, Some(image)) => image.to_string(),
+            (None, None) => unreachable!("clap requires --profile or --image"),
+        }
+    }
+
+    /// Inputs for `--image`: the profile if given (else a bare ostree
+    /// one named after the image) with the image's boot files and kernel
+    /// arguments taking precedence.
+    fn load_image(
+        &self,
+        image: &ImageSource,
+        ctx: &Ctx,
+    ) -> Result<(Option<Source>, Profile, BuildInputs, BootFiles)> {
+        let (source, mut profile, base_dir) = match &self.profile {
+            Some(spec) => {
+                let source = Source::locate(spec, &ctx.cfg.profile_path)?;
+                let profile = source.load()?;
+                let base_dir = match &source {
+                    Source::Builtin(_) => PathBuf::from("."),
+                    Source::File(path) => path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+                };
+                (Some(source), profile, base_dir)
+            }
+            None => {
+                let profile = Profile {
+                    name: image.to_string(),
+                    root: RootSpec::Ostree { reference: None },
+                    modules: Vec::new(),
+                    cmdline: Cmdline::default(),
+                    compression: None,
+                    artifacts: Default::default(),
+                    fetch: Default::default(),
+                };
+                (None, profile, PathBuf::from("."))
+            }
+        };
+        let fetcher = Fetcher::open(self.cache_dir.as_deref(), &ctx.cfg)?;
+        let files = bootc::extract(image, self.plain_http, &fetcher)?;
+        info!(image = %image, digest = files.digest, kver = files.kver, "building from image");
+        for key in ["kernel", "initrd", "osrel"] {
+            profile.fetch.remove(key);
+        }
+        let mut resolved = profile.clone();
+        resolved.artifacts.extend(
+            fetcher
+                .resolve(&profile, &base_dir)?
+                .into_iter()
+                .map(|(name, f)| (name, f.path)),
+        );
+        resolved
+            .artifacts
+            .insert("kernel".into(), files.kernel.clone());
+        resolved
+            .artifacts
+            .insert("initrd".into(), files.initramfs.clone());
+        if let Some(osrel) = &files.osrel {
+            resolved.artifacts.insert("osrel".into(), osrel.clone());
+        }
+        if !files.kargs.is_empty() {
+            let order = resolved
+                .cmdline
+                .fragments
+                .iter()
+                .filter_map(|f| f.order)
+                .chain([0])
+                .min()
+                .unwrap_or(0);
+            resolved.cmdline.set(Fragment {
+                name: "bootc".to_string(),
+                args: files.kargs.join(" "),
+                order: Some(order.saturating_sub(1)),
+            });
+        }
+        let inputs = BuildInputs::from_profile(&resolved, &base_dir)?;
+        Ok((source, resolved, inputs, files))
+    }
+
+    /// Build, logging (not returning) failures so watch mode keeps going,
+    /// then make sure every dependency's directory is watched.
+    fn rebuild(
+        &self,
+        ctx: &Ctx,
+        watcher: &mut dyn Watcher,
+        dirs: &mut BTreeSet<PathBuf>,
+        previous: Vec<PathBuf>,
+    ) -> Vec<PathBuf> {
+        let deps = match self.build_once(ctx) {
+            Ok(deps) => deps,
+            Err(e) => {
+                error!("build failed: {e:#}");
+                // Keep watching what we knew about (plus the profile) so a
+                // fix triggers the next attempt.
+                let mut deps = previous;
+                deps.extend(self.profile.as_ref().map(PathBuf::from));
+                deps
+            }
+        };
+        for dep in &deps {
+            // A directory initrd: anything below it counts.
+            if dep.is_dir() && !dirs.contains(dep) {
+                match watcher.watch(dep, RecursiveMode::Recursive) {
+                    Ok(()) => {
+                        dirs.insert(dep.clone());
+                    }
+                    Err(e) => error!(dir = %dep.display(), "cannot watch: {e}"),
+                }
+            }
+            let dir = match dep.parent() {
+                Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            if dirs.contains(&dir) {
+                continue;
+            }
+            match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    dirs.insert(dir);
+                }
+                Err(e) => error!(dir = %dir.display(), "cannot watch: {e}"),
+            }
+        }
+        info!(files = deps.len(), "watching for changes (Ctrl-C to stop)");
+        deps
+    }
+}
+
+/// Locate and load `profile` (a path, `builtin:<name>` or a name on the
+/// profile path) and resolve its inputs against its directory, fetching
+/// any `[fetch]` inputs into the download cache under `cache_dir` first.
+/// With `sysroot`, an ostree profile is completed from its deployment.
+pub(super) fn load(
+    profile: &str,
+    cache_dir: Option<&Path>,
+    sysroot: Option<&Path>,
+    ctx: &Ctx,
+) -> Result<(Source, Profile, BuildInputs)> {
+    let source = Source::locate(profile, &ctx.cfg.profile_path)?;
+    let mut profile = source.load()?;
+    if let Some(sysroot) = sysroot {
+        for_deployment(&mut profile, sysroot)?;
+    }
+    let base_dir = match &source {
+        Source::Builtin(_) => PathBuf::from("."),
+        Source::File(path) => path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+    };
+    if profile.fetch.is_empty() {
+        let inputs = BuildInputs::from_profile(&profile, &base_dir)?;
+        return Ok((source, profile, inputs));
+    }
+    let fetched = Fetcher::open(cache_dir, &ctx.cfg)?.resolve(&profile, &base_dir)?;
+    let mut resolved = profile.clone();
+    resolved
+        .artifacts
+        .extend(fetched.into_iter().map(|(name, f)| (name, f.path)));
+    let inputs = BuildInputs::from_profile(&resolved, &base_dir)?;
+    Ok((source, profile, inputs))
+}
+
+/// Complete an ostree `profile` from the deployment it targets in
+/// `sysroot`.
+pub(super) fn for_deployment(profile: &mut Profile, sysroot: &Path) -> Result<()> {
+    if !matches!(profile.root, RootSpec::Ostree { .. }) {
+        bail!(Coded::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "--ostree-sysroot needs root = \"ostree\" (profile {:?} has {})",
+                profile.name, profile.root
+            )
+        ));
+    }
+    let deployment = Deployment::find(sysroot, profile.root.ostree_ref())?;
+    info!(
+        deployment = %deployment.path.display(),
+        arg = deployment.ostree_arg(),
+        "building for ostree deployment"
+    );
+    deployment.apply(profile);
+    Ok(())
+}
+
+/// Whether an in-memory build fits in `budget` bytes: it holds every input
+/// and then the image, about twice their total. A directory initrd's
+/// packed size is not known up front, so it never fits.
+fn fits(inputs: &BuildInputs, budget: u64) -> bool {
+    let mut total = 0u64;
+    for path in inputs.paths() {
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.is_dir() => return false,
+            Ok(meta) => total = total.saturating_add(meta.len()),
+            // Missing: the build itself reports that.
+            Err(_) => {}
+        }
+    }
+    total.saturating_mul(2) <= budget
+}
+
+fn touches(event: &notify::Result<notify::Event>, deps: &[PathBuf]) -> bool {
+    let Ok(event) = event else {
+        return false;
+    };
+    if event.kind.is_access() {
+        return false;
+    }
+    event.paths.iter().any(|p| {
+        deps.iter().any(|d| {
+            same_file(p, d) || (d.is_dir() && d.canonicalize().is_ok_and(|d| p.starts_with(d)))
+        })
+    })
+}
+
+/// Event paths are absolute; dependencies may be relative.
+fn same_file(event_path: &Path, dep: &Path) -> bool {
+    if event_path == dep {
+        return true;
+    }
+    match (event_path.file_name(), dep.file_name()) {
+        (Some(a), Some(b)) if a == b => {
+            let dir = dep.parent().filter(|d| !d.as_os_str().is_empty());
+            let dir = dir.unwrap_or(Path::new("."));
+            match (event_path.parent(), dir.canonicalize()) {
+                (Some(ep), Ok(d)) => ep == d,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
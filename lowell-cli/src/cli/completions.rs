@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use anyhow::Result;
+use clap::{Args, CommandFactory};
+use clap_complete::{Generator, Shell};
+use std::io;
+
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    /// Shell to generate completions for
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+impl CompletionsArgs {
+    pub fn run(self, out: &mut dyn io::Write) -> Result<()> {
+        // try_generate: a closed pipe (e.g. `| head`) is an error, not a panic
+        generate(self.shell, out)
+    }
+}
+
+fn generate(shell: Shell, out: &mut dyn io::Write) -> Result<()> {
+    let mut cmd = super::Cli::command();
+    let name = cmd.get_name().to_string();
+    cmd.set_bin_name(name);
+    cmd.build();
+    shell.try_generate(&cmd, out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+
+    #[test]
+    fn completions_generate_for_every_shell() {
+        for shell in Shell::value_variants() {
+            let mut out = Vec::new();
+            generate(*shell, &mut out).expect("generate");
+            assert!(!out.is_empty(), "{shell}");
+        }
+    }
+}
@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cli::Ctx;
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use std::io::Write;
+
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    cmd: ConfigCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCmd {
+    /// Print the merged configuration and the files it came from
+    Show,
+}
+
+impl ConfigArgs {
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        match self.cmd {
+            ConfigCmd::Show => {
+                if ctx.cfg.loaded_from.is_empty() {
+                    writeln!(out, "# no config files found")?;
+                }
+                for path in &ctx.cfg.loaded_from {
+                    writeln!(out, "# from {}", path.display())?;
+                }
+                out.write_all(toml::to_string(&ctx.cfg)?.as_bytes())?;
+                Ok(())
+            }
+        }
+    }
+}
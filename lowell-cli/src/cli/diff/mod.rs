@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+mod uki;
+
+use crate::cli::{Ctx, ReportFormat};
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use std::io::Write;
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    #[command(subcommand)]
+    cmd: DiffCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum DiffCmd {
+    /// Compare two UKIs section by section: sections, command line, kernel,
+    /// initramfs contents and signers
+    Uki(uki::UkiDiffArgs),
+}
+
+impl DiffArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        match &self.cmd {
+            DiffCmd::Uki(a) => a.json_errors(default),
+        }
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        match self.cmd {
+            DiffCmd::Uki(a) => a.run(ctx, out),
+        }
+    }
+}
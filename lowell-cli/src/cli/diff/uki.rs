@@ -0,0 +1,430 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell diff uki OLD NEW`: what changed between two UKIs.
+//!
+//! Sections are paired by name (the n-th `.profile` with the n-th) and
+//! compared by digest; the command line, kernel and signers are compared
+//! as inspect reports them, and when `.initrd` changed its archives are
+//! walked to name the files that were added, removed or modified.
+
+use crate::cli::uki::inspect::fmt_bytes;
+use crate::cli::{Ctx, ReportFormat};
+use crate::style::Palette;
+use anyhow::{Context, Result};
+use clap::Args;
+use lowell_core::digest::Digest;
+use lowell_core::formats::authenticode::Signer;
+use lowell_core::formats::pe::PeFile;
+use lowell_core::initrd::inspect::{diff_bytes, Diff};
+use lowell_core::uki::inspect::{self, InspectOptions, NamedSection, Report};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct UkiDiffArgs {
+    /// The UKI to compare against
+    old: PathBuf,
+    /// The UKI to compare
+    new: PathBuf,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl Kind {
+    fn sign(self) -> char {
+        match self {
+            Kind::Added => '+',
+            Kind::Removed => '-',
+            Kind::Modified => '~',
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Added => "added",
+            Kind::Removed => "removed",
+            Kind::Modified => "modified",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SectionChange {
+    name: String,
+    change: Kind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_size: Option<usize>,
+}
+
+/// A value on either side; `None` where it is absent.
+#[derive(Debug, Serialize)]
+struct Change {
+    old: Option<String>,
+    new: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SignerChange {
+    signer: String,
+    change: Kind,
+}
+
+#[derive(Debug, Serialize)]
+struct UkiDiff {
+    old: PathBuf,
+    new: PathBuf,
+    identical: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sections: Vec<SectionChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cmdline: Option<Change>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kernel: Option<Change>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kernel_version: Option<Change>,
+    #[serde(skip_serializing_if = "Diff::is_empty")]
+    initrd: Diff,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    signers: Vec<SignerChange>,
+}
+
+impl UkiDiffArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let format = self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human);
+        let diff = self.diff()?;
+        if ctx.porcelain {
+            return print_porcelain(out, &diff);
+        }
+        match format {
+            ReportFormat::Human => print_human(out, ctx.palette, &diff)?,
+            ReportFormat::Json => serde_json::to_writer(&mut *out, &diff)?,
+            ReportFormat::JsonPretty => serde_json::to_writer_pretty(&mut *out, &diff)?,
+        }
+        if format != ReportFormat::Human {
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn diff(&self) -> Result<UkiDiff> {
+        let report = |file: &Path| {
+            inspect::inspect(InspectOptions::new(file))
+                .with_context(|| format!("inspect {}", file.display()))
+        };
+        let (old, new) = (report(&self.old)?, report(&self.new)?);
+        let initrd = match (&old.initrd.section.digest, &new.initrd.section.digest) {
+            (Some(a), Some(b)) if a == b => Diff::default(),
+            _ => {
+                let (a, b) = (
+                    PeFile::from_path_mmap(&self.old)?,
+                    PeFile::from_path_mmap(&self.new)?,
+                );
+                match (
+                    a.section_contents(".initrd")?,
+                    b.section_contents(".initrd")?,
+                ) {
+                    (Some(a), Some(b)) => diff_bytes(a, b)?,
+                    _ => Diff::default(),
+                }
+            }
+        };
+        let sections = sections(&old.section_table, &new.section_table);
+        let cmdline = changed(Some(&old.cmdline), Some(&new.cmdline));
+        let kernel = changed(
+            old.linux.digest.as_ref().map(Digest::to_string).as_ref(),
+            new.linux.digest.as_ref().map(Digest::to_string).as_ref(),
+        );
+        let kernel_version = changed(old.kernel_version.as_ref(), new.kernel_version.as_ref());
+        let signers = signers(&old, &new);
+        let identical = sections.is_empty()
+            && cmdline.is_none()
+            && kernel.is_none()
+            && kernel_version.is_none()
+            && initrd.is_empty()
+            && signers.is_empty();
+        Ok(UkiDiff {
+            old: self.old.clone(),
+            new: self.new.clone(),
+            identical,
+            sections,
+            cmdline,
+            kernel,
+            kernel_version,
+            initrd,
+            signers,
+        })
+    }
+}
+
+fn changed(old: Option<&String>, new: Option<&String>) -> Option<Change> {
+    (old != new).then(|| Change {
+        old: old.cloned(),
+        new: new.cloned(),
+    })
+}
+
+/// Sections added, removed or with other contents, in `new`'s header
+/// order and then the removed ones.
+fn sections(old: &[NamedSection], new: &[NamedSection]) -> Vec<SectionChange> {
+    // The n-th occurrence of each name, so repeated profile sections pair up.
+    let keyed = |table: &[NamedSection]| -> Vec<(String, usize, usize, Option<Digest>)> {
+        let mut seen: Vec<&str> = Vec::new();
+        table
+            .iter()
+            .map(|s| {
+                let n = seen.iter().filter(|name| **name == s.name).count();
+                seen.push(&s.name);
+                (s.name.clone(), n, s.section.size, s.section.digest.clone())
+            })
+            .collect()
+    };
+    let (old, new) = (keyed(old), keyed(new));
+    let find = |table: &[(String, usize, usize, Option<Digest>)], name: &str, n: usize| {
+        table
+            .iter()
+            .find(|(other, m, _, _)| other == name && *m == n)
+            .cloned()
+    };
+    let mut changes = Vec::new();
+    for (name, n, size, digest) in &new {
+        match find(&old, name, *n) {
+            None => changes.push(SectionChange {
+                name: name.clone(),
+                change: Kind::Added,
+                old_size: None,
+                new_size: Some(*size),
+            }),
+            Some((_, _, old_size, old_digest)) if old_size != *size || old_digest != *digest => {
+                changes.push(SectionChange {
+                    name: name.clone(),
+                    change: Kind::Modified,
+                    old_size: Some(old_size),
+                    new_size: Some(*size),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, n, size, _) in &old {
+        if find(&new, name, *n).is_none() {
+            changes.push(SectionChange {
+                name: name.clone(),
+                change: Kind::Removed,
+                old_size: Some(*size),
+                new_size: None,
+            });
+        }
+    }
+    changes
+}
+
+/// Signers only one side has, matched by issuer and serial number.
+fn signers(old: &Report, new: &Report) -> Vec<SignerChange> {
+    let same = |a: &Signer, b: &Signer| a.issuer == b.issuer && a.serial == b.serial;
+    let name = |s: &Signer| match &s.subject {
+        Some(subject) => format!("{subject} (serial {})", s.serial),
+        None => format!("{} serial {}", s.issuer, s.serial),
+    };
+    let only = |from: &[Signer], other: &[Signer], change: Kind| {
+        from.iter()
+            .filter(|s| !other.iter().any(|o| same(s, o)))
+            .map(|s| SignerChange {
+                signer: name(s),
+                change,
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut changes = only(&new.signatures, &old.signatures, Kind::Added);
+    changes.extend(only(&old.signatures, &new.signatures, Kind::Removed));
+    changes
+}
+
+fn print_human(out: &mut dyn Write, palette: Palette, d: &UkiDiff) -> Result<()> {
+    writeln!(out, "--- {}", d.old.display())?;
+    writeln!(out, "+++ {}", d.new.display())?;
+    if d.identical {
+        writeln!(out, "{}", palette.good("identical"))?;
+        return Ok(());
+    }
+    let paint = |kind: Kind, text: &str| match kind {
+        Kind::Added => palette.good(text).to_string(),
+        Kind::Removed => palette.bad(text).to_string(),
+        Kind::Modified => palette.warn(text).to_string(),
+    };
+    let side = |v: &Option<String>| match v {
+        Some(v) => format!("{v:?}"),
+        None => "<absent>".to_string(),
+    };
+    for s in &d.sections {
+        let sizes = match (s.old_size, s.new_size) {
+            (Some(a), Some(b)) if a != b => format!(" ({} -> {})", fmt_bytes(a), fmt_bytes(b)),
+            (Some(n), None) | (None, Some(n)) => format!(" ({})", fmt_bytes(n)),
+            _ => String::new(),
+        };
+        let line = format!("{} {}", s.change.sign(), s.name);
+        writeln!(out, "section : {}{sizes}", paint(s.change, &line))?;
+    }
+    if let Some(c) = &d.cmdline {
+        writeln!(out, "cmdline : {} -> {}", side(&c.old), side(&c.new))?;
+    }
+    if let Some(c) = &d.kernel {
+        writeln!(out, "kernel  : {} -> {}", side(&c.old), side(&c.new))?;
+    }
+    if let Some(c) = &d.kernel_version {
+        writeln!(out, "version : {} -> {}", side(&c.old), side(&c.new))?;
+    }
+    let i = &d.initrd;
+    if !i.is_empty() {
+        writeln!(
+            out,
+            "initrd  : {} added, {} removed, {} modified",
+            i.added.len(),
+            i.removed.len(),
+            i.modified.len()
+        )?;
+        for (kind, paths) in [
+            (Kind::Added, &i.added),
+            (Kind::Removed, &i.removed),
+            (Kind::Modified, &i.modified),
+        ] {
+            for p in paths {
+                writeln!(out, "  {}", paint(kind, &format!("{} {p}", kind.sign())))?;
+            }
+        }
+    }
+    for s in &d.signers {
+        let line = format!("{} {}", s.change.sign(), s.signer);
+        writeln!(out, "signer  : {}", paint(s.change, &line))?;
+    }
+    Ok(())
+}
+
+/// One tab-separated `area, change, name` line per difference.
+fn print_porcelain(out: &mut dyn Write, d: &UkiDiff) -> Result<()> {
+    for s in &d.sections {
+        writeln!(out, "section\t{}\t{}", s.change.as_str(), s.name)?;
+    }
+    for (area, change) in [
+        ("cmdline", &d.cmdline),
+        ("kernel", &d.kernel),
+        ("kernel_version", &d.kernel_version),
+    ] {
+        if let Some(c) = change {
+            writeln!(out, "{area}\tmodified\t{}", c.new.as_deref().unwrap_or(""))?;
+        }
+    }
+    for (kind, paths) in [
+        (Kind::Added, &d.initrd.added),
+        (Kind::Removed, &d.initrd.removed),
+        (Kind::Modified, &d.initrd.modified),
+    ] {
+        for p in paths {
+            writeln!(out, "initrd\t{}\t{p}", kind.as_str())?;
+        }
+    }
+    for s in &d.signers {
+        writeln!(out, "signer\t{}\t{}", s.change.as_str(), s.signer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use clap::Parser;
+    use lowell_test_util::{Initrd, UkiBuilder};
+
+    #[derive(Parser)]
+    struct Diff {
+        #[command(flatten)]
+        args: UkiDiffArgs,
+    }
+
+    #[test]
+    fn changes_are_reported_by_section_and_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old.efi"), dir.path().join("new.efi"));
+        let initrd = Initrd::new()
+            .file("init", b"#!/bin/sh\n")
+            .file("etc/fstab", b"");
+        std::fs::write(
+            &old,
+            UkiBuilder::new()
+                .cmdline("quiet")
+                .linux(&[0xAA; 4096])
+                .initrd(&initrd.build())
+                .build(),
+        )
+        .unwrap();
+        std::fs::write(
+            &new,
+            UkiBuilder::new()
+                .cmdline("quiet debug")
+                .linux(&[0xAA; 4096])
+                .initrd(&initrd.file("etc/hostname", b"box\n").build())
+                .section(".sbat", b"sbat,1\n")
+                .build(),
+        )
+        .unwrap();
+        let ctx = |porcelain| Ctx {
+            cfg: Config::default(),
+            palette: Palette::new(false),
+            porcelain,
+        };
+        let run = |argv: &[&str], porcelain| {
+            let mut out = Vec::new();
+            Diff::parse_from(argv)
+                .args
+                .run(&ctx(porcelain), &mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let (a, b) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+        let out = run(&["diff", a, b], false);
+        assert!(out.contains("section : + .sbat"), "{out}");
+        assert!(out.contains("section : ~ .initrd"), "{out}");
+        assert!(
+            out.contains("cmdline : \"quiet\" -> \"quiet debug\""),
+            "{out}"
+        );
+        assert!(
+            out.contains("initrd  : 1 added, 0 removed, 0 modified"),
+            "{out}"
+        );
+        assert!(out.contains("  + etc/hostname"), "{out}");
+        assert!(!out.contains("kernel  :"), "{out}");
+
+        let out = run(&["diff", a, b], true);
+        assert!(out.contains("initrd\tadded\tetc/hostname\n"), "{out}");
+
+        let out = run(&["diff", a, b, "--format", "json"], false);
+        let doc: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(doc["identical"], false);
+        assert_eq!(doc["initrd"]["added"][0], "etc/hostname");
+
+        assert!(run(&["diff", a, a], false).contains("identical"));
+    }
+}
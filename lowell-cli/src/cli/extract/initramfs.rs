@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use anyhow::{Context, Result};
+use clap::Args;
+use lowell_core::formats::pe::PeFile;
+use lowell_core::formats::unpack::{self, UnpackOptions};
+use lowell_core::mapped::Mapped;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct ExtractInitramfsArgs {
+    /// An initramfs image, or a UKI (its .initrd section is used)
+    file: PathBuf,
+    /// Directory to unpack into (created if missing)
+    #[arg(long, value_name = "DIR")]
+    out_dir: PathBuf,
+    /// Only extract entries below this archive path, dropping the prefix
+    /// (e.g. `--strip-prefix usr/lib/modules`)
+    #[arg(long, value_name = "PATH")]
+    strip_prefix: Option<PathBuf>,
+    /// Restore uid/gid and setuid/setgid bits (normally needs root)
+    #[arg(long)]
+    preserve_owner: bool,
+}
+
+impl ExtractInitramfsArgs {
+    pub fn run(self) -> Result<()> {
+        let bytes = Mapped::open(&self.file)?;
+        let pe;
+        let initrd = if bytes.starts_with(b"MZ") {
+            pe = PeFile::from_path_mmap(&self.file)?;
+            pe.section_contents(".initrd")?
+                .with_context(|| format!("no .initrd section in {}", self.file.display()))?
+        } else {
+            &bytes[..]
+        };
+        let opts = UnpackOptions {
+            strip_prefix: self.strip_prefix,
+            preserve_owner: self.preserve_owner,
+        };
+        let stats = unpack::unpack(initrd, &self.out_dir, &opts)?;
+        info!(
+            dir = %self.out_dir.display(),
+            files = stats.files,
+            dirs = stats.dirs,
+            symlinks = stats.symlinks,
+            skipped = stats.skipped,
+            "unpacked initramfs"
+        );
+        Ok(())
+    }
+}
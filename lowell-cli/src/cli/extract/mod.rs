@@ -0,0 +1,29 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+mod initramfs;
+mod uki;
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+
+#[derive(Args, Debug)]
+pub struct ExtractArgs {
+    #[command(subcommand)]
+    cmd: ExtractCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum ExtractCmd {
+    /// Unpack an initramfs (or a UKI's .initrd) into a directory
+    Initramfs(initramfs::ExtractInitramfsArgs),
+    /// Write UKI sections (.linux, .initrd, ...) to files
+    Uki(uki::ExtractUkiArgs),
+}
+
+impl ExtractArgs {
+    pub fn run(self) -> Result<()> {
+        match self.cmd {
+            ExtractCmd::Initramfs(a) => a.run(),
+            ExtractCmd::Uki(a) => a.run(),
+        }
+    }
+}
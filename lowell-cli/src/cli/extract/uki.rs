@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::error::Coded;
+use crate::output;
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Args};
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::pe::PeFile;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("which").required(true)))]
+pub struct ExtractUkiArgs {
+    /// Path to the UKI
+    file: PathBuf,
+    /// Section to extract (e.g. `.initrd`); repeat for several
+    #[arg(long, group = "which", value_name = "NAME")]
+    section: Vec<String>,
+    /// Extract every section
+    #[arg(long, group = "which")]
+    all: bool,
+    /// Directory to write into; each section becomes `<name>` without the
+    /// leading dot (`.linux` -> `linux`), a repeated one `<name>.1`, …
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    out_dir: PathBuf,
+}
+
+impl ExtractUkiArgs {
+    pub fn run(self) -> Result<()> {
+        let pe = PeFile::from_path_mmap(&self.file)?;
+        let sections = pe.sections()?;
+        let names: Vec<&str> = sections.iter().map(|s| s.name).collect();
+        for name in &self.section {
+            if !names.contains(&name.as_str()) {
+                bail!(Coded::new(
+                    ErrorCode::SectionMissing,
+                    format!(
+                        "no {name} section in {} (has: {})",
+                        self.file.display(),
+                        names.join(", ")
+                    )
+                ));
+            }
+        }
+
+        std::fs::create_dir_all(&self.out_dir)
+            .with_context(|| format!("create {}", self.out_dir.display()))?;
+        // Multi-profile UKIs repeat names: the later ones get `.1`, `.2`, …
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for s in &sections {
+            let nth = seen.entry(s.name).or_default();
+            let dest = match *nth {
+                0 => file_name(s.name),
+                n => format!("{}.{n}", file_name(s.name)),
+            };
+            *nth += 1;
+            if !self.all && !self.section.iter().any(|w| w == s.name) {
+                continue;
+            }
+            let dest = self.out_dir.join(dest);
+            let bytes = s.contents();
+            output::write_atomic(&dest, bytes)?;
+            info!(section = %s.name, path = %dest.display(), size = bytes.len(), "extracted");
+        }
+        Ok(())
+    }
+}
+
+/// On-disk name for a section: no leading dot, no path separators.
+fn file_name(section: &str) -> String {
+    let name = section.trim_start_matches('.').replace(['/', '\\'], "_");
+    if name.is_empty() {
+        "section".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use lowell_test_util::UkiBuilder;
+
+    #[derive(Parser)]
+    struct Extract {
+        #[command(flatten)]
+        args: ExtractUkiArgs,
+    }
+
+    #[test]
+    fn sections_are_written_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        let image = UkiBuilder::new()
+            .cmdline("quiet")
+            .linux(b"kernel")
+            .section(".profile", b"ID=debug")
+            .cmdline("debug")
+            .build();
+        std::fs::write(&path, image).unwrap();
+        let run = |out: &str, which: &[&str]| {
+            let out = dir.path().join(out);
+            let mut argv = vec![
+                "extract",
+                path.to_str().unwrap(),
+                "--out-dir",
+                out.to_str().unwrap(),
+            ];
+            argv.extend(which);
+            Extract::parse_from(argv).args.run().map(|()| out)
+        };
+        let read = |p: PathBuf| std::fs::read(p).unwrap();
+
+        let out = run("one", &["--section", ".linux"]).unwrap();
+        assert_eq!(read(out.join("linux")), b"kernel");
+        assert!(!out.join("cmdline").exists());
+
+        // The second profile's command line is kept apart from the first.
+        let out = run("all", &["--all"]).unwrap();
+        assert_eq!(read(out.join("cmdline")), b"quiet\0");
+        assert_eq!(read(out.join("cmdline.1")), b"debug\0");
+        assert_eq!(read(out.join("profile")), b"ID=debug");
+
+        let err = run("none", &["--section", ".pcrsig"]).unwrap_err();
+        assert_eq!(crate::error::code_of(&err), ErrorCode::SectionMissing);
+        assert!(
+            err.to_string().contains("has: .text, .cmdline, .linux"),
+            "{err}"
+        );
+    }
+}
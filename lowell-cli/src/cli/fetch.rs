@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell fetch`: download and verify a profile's `[fetch]` inputs ahead
+//! of a build (e.g. before going offline).
+
+use crate::cli::{Ctx, ReportFormat};
+use crate::fetch::{Fetched, Fetcher};
+use anyhow::Result;
+use clap::Args;
+use lowell_core::profile::Source;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct FetchArgs {
+    /// Profile whose inputs to fetch: a TOML path, `builtin:<name>` or a
+    /// name on the profile path
+    #[arg(long)]
+    profile: String,
+    /// Download cache (default: `cache-dir` from config, else
+    /// `~/.cache/lowell`)
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+}
+
+impl FetchArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let source = Source::locate(&self.profile, &ctx.cfg.profile_path)?;
+        let profile = source.load()?;
+        let base_dir = match &source {
+            Source::Builtin(_) => PathBuf::from("."),
+            Source::File(path) => path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        };
+        let fetched =
+            Fetcher::open(self.cache_dir.as_deref(), &ctx.cfg)?.resolve(&profile, &base_dir)?;
+        let fetched: Vec<&Fetched> = fetched.values().collect();
+        match self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human)
+        {
+            ReportFormat::Human => {
+                for f in fetched {
+                    let state = if f.cached { "cached" } else { "downloaded" };
+                    if ctx.porcelain {
+                        let path = f.path.display();
+                        writeln!(out, "{}\t{state}\t{}\t{path}", f.name, f.sha256)?;
+                        continue;
+                    }
+                    let signed = if f.signed { ", signed" } else { "" };
+                    writeln!(
+                        out,
+                        "{} {} ({}{signed})",
+                        ctx.palette.bold(format_args!("{:<12}", f.name)),
+                        f.path.display(),
+                        ctx.palette.good(state)
+                    )?;
+                }
+            }
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut *out, &fetched)?;
+                out.write_all(b"\n")?;
+            }
+            ReportFormat::JsonPretty => {
+                serde_json::to_writer_pretty(&mut *out, &fetched)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+}
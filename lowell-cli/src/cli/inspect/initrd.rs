@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cache::Cache;
+use crate::cli::uki::inspect::fmt_bytes;
+use crate::cli::{Ctx, ReportFormat};
+use anyhow::Result;
+use clap::Args;
+use lowell_core::initrd::inspect::{self, Entry, EntryKind, Report, Sizes};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct InitrdArgs {
+    /// initramfs image (any mix of cpio, gzip, xz, zstd segments)
+    #[arg(value_name = "FILE", required_unless_present = "file_flag")]
+    pub(in crate::cli) file: Option<PathBuf>,
+    /// The initramfs image, as a flag
+    #[arg(long = "file", value_name = "FILE", conflicts_with = "file")]
+    pub(in crate::cli) file_flag: Option<PathBuf>,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    pub(in crate::cli) format: Option<ReportFormat>,
+    /// Print only this field (e.g. `sha256`); repeat for several
+    #[arg(long, value_name = "PATH")]
+    pub(in crate::cli) get: Vec<String>,
+    /// Reuse reports cached in DIR, keyed by each file's path, size and
+    /// mtime (default: `cache-dir` from config, else no cache)
+    #[arg(long, value_name = "DIR")]
+    pub(in crate::cli) cache_dir: Option<PathBuf>,
+    /// List every entry instead, written as the archive is walked (one
+    /// JSON object per line with `--format json`)
+    #[arg(long, conflicts_with = "get")]
+    pub(in crate::cli) list: bool,
+    /// Decompress and total the file sizes per top-level directory
+    /// instead, largest first
+    #[arg(long, conflicts_with_all = ["get", "list"])]
+    pub(in crate::cli) size_report: bool,
+    /// With `--size-report`: also list the N largest files
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "size_report")]
+    pub(in crate::cli) top: usize,
+}
+
+impl InitrdArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        if self.list {
+            return self.list(ctx, out);
+        }
+        if self.size_report {
+            let sizes = inspect::sizes(self.file(), self.top)?;
+            return write_sizes(ctx, out, self.format, &sizes);
+        }
+        let file = self.file();
+        let report = match Cache::open(self.cache_dir.as_deref(), &ctx.cfg) {
+            Some(cache) => cache.report("initrd", file, || Ok(inspect::inspect(file)?))?,
+            None => inspect::inspect(file)?,
+        };
+        super::emit(ctx, out, self.format, &self.get, &report, |out| {
+            print_human(out, &report)
+        })
+    }
+}
+
+impl InitrdArgs {
+    fn file(&self) -> &Path {
+        // clap requires one or the other.
+        self.file
+            .as_deref()
+            .or(self.file_flag.as_deref())
+            .expect("FILE or --file")
+    }
+
+    fn list(&self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        match self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human)
+        {
+            ReportFormat::Human => {
+                inspect::list(self.file(), |e| Ok(print_entry(&mut *out, &e)?))?;
+            }
+            format => {
+                let mut list = super::JsonList::new(&mut *out, format == ReportFormat::JsonPretty);
+                inspect::list(self.file(), |e| Ok(list.push(&e)?))?;
+                list.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `ls -l`-style: type, permissions, size, path.
+pub(in crate::cli) fn print_entry(out: &mut dyn Write, e: &Entry) -> std::io::Result<()> {
+    let kind = match e.kind {
+        EntryKind::File => '-',
+        EntryKind::Dir => 'd',
+        EntryKind::Symlink => 'l',
+        EntryKind::Other => '?',
+    };
+    write!(out, "{kind} {:04o} {:>10} {}", e.mode, e.size, e.path)?;
+    match &e.target {
+        Some(target) => writeln!(out, " -> {target}"),
+        None => writeln!(out),
+    }
+}
+
+/// A `--size-report`: human, JSON, or with `--porcelain` tab-separated
+/// `SIZE FILES PATH` lines, the largest files after a blank line.
+pub(in crate::cli) fn write_sizes(
+    ctx: &Ctx,
+    out: &mut dyn Write,
+    format: Option<ReportFormat>,
+    sizes: &Sizes,
+) -> Result<()> {
+    if ctx.porcelain {
+        for e in &sizes.top_level {
+            writeln!(out, "{}\t{}\t{}", e.size, e.files, e.path)?;
+        }
+        if !sizes.largest.is_empty() {
+            writeln!(out)?;
+        }
+        for e in &sizes.largest {
+            writeln!(out, "{}\t{}\t{}", e.size, e.files, e.path)?;
+        }
+        return Ok(());
+    }
+    match format.or(ctx.cfg.format).unwrap_or(ReportFormat::Human) {
+        ReportFormat::Human => {}
+        ReportFormat::Json => {
+            serde_json::to_writer(&mut *out, sizes)?;
+            out.write_all(b"\n")?;
+            return Ok(());
+        }
+        ReportFormat::JsonPretty => {
+            serde_json::to_writer_pretty(&mut *out, sizes)?;
+            out.write_all(b"\n")?;
+            return Ok(());
+        }
+    }
+    let size = |n: u64| fmt_bytes(n as usize);
+    writeln!(
+        out,
+        "total   : {} in {} files",
+        size(sizes.total),
+        sizes.files
+    )?;
+    for e in &sizes.top_level {
+        let share = match sizes.total {
+            0 => 0.0,
+            total => e.size as f64 * 100.0 / total as f64,
+        };
+        writeln!(
+            out,
+            "  {:>10} {share:>5.1}% {:>7} files  {}",
+            size(e.size),
+            e.files,
+            e.path
+        )?;
+    }
+    if !sizes.largest.is_empty() {
+        writeln!(out, "largest :")?;
+        for e in &sizes.largest {
+            writeln!(out, "  {:>10}  {}", size(e.size), e.path)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_human(out: &mut dyn Write, r: &Report) -> Result<()> {
+    writeln!(out, "initramfs • {} • {} entries", r.compression, r.entries)?;
+    writeln!(out, "size    : {} bytes", r.size)?;
+    writeln!(out, "sha256  : {}", r.sha256)?;
+    for s in &r.segments {
+        writeln!(
+            out,
+            "segment : {} at {:#x}, {} bytes, {}",
+            s.purpose, s.offset, s.size, s.compression
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::style::Palette;
+    use clap::Parser;
+    use lowell_test_util::{Compression, Initrd};
+
+    #[derive(Parser)]
+    struct Inspect {
+        #[command(flatten)]
+        args: InitrdArgs,
+    }
+
+    #[test]
+    fn file_by_flag_or_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("initramfs.img");
+        let initrd = Initrd::new()
+            .early("kernel/x86/microcode/AuthenticAMD.bin", b"ucode")
+            .file("init", b"#!/bin/sh\n")
+            .compression(Compression::Gzip)
+            .build();
+        std::fs::write(&path, initrd).unwrap();
+        let ctx = Ctx {
+            cfg: Config::default(),
+            palette: Palette::new(false),
+            porcelain: false,
+        };
+        let path = path.to_str().unwrap();
+        let run = |argv: &[&str]| {
+            let mut out = Vec::new();
+            Inspect::parse_from(argv).args.run(&ctx, &mut out).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&out).unwrap()
+        };
+
+        let report = run(&["initrd", "--file", path, "--format", "json"]);
+        assert_eq!(report, run(&["initrd", path, "--format", "json"]));
+        assert_eq!(report["compression"], "gzip");
+        assert_eq!(report["entries"], 2);
+        assert_eq!(report["segments"][0]["purpose"], "microcode");
+        assert_eq!(report["segments"][1]["compression"], "gzip");
+        assert_eq!(report["sha256"].as_str().map(str::len), Some(64));
+        assert!(Inspect::try_parse_from(["initrd"]).is_err());
+        assert!(Inspect::try_parse_from(["initrd", path, "--file", path]).is_err());
+    }
+
+    #[test]
+    fn size_report_totals_top_level_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("initramfs.img");
+        let initrd = Initrd::new()
+            .file("usr/lib/modules/kvm.ko", &[0; 3 << 20])
+            .file("usr/bin/sh", &[0; 1 << 20])
+            .file("init", b"#!/bin/sh\n")
+            .compression(Compression::Gzip)
+            .build();
+        std::fs::write(&path, initrd).unwrap();
+        let path = path.to_str().unwrap();
+        let run = |argv: &[&str], porcelain| {
+            let ctx = Ctx {
+                cfg: Config::default(),
+                palette: Palette::new(false),
+                porcelain,
+            };
+            let mut out = Vec::new();
+            Inspect::parse_from(argv).args.run(&ctx, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let out = run(&["initrd", path, "--size-report", "--top", "1"], false);
+        assert!(out.starts_with("total   : 4.0 MiB in 3 files\n"), "{out}");
+        assert!(
+            out.contains("     4.0 MiB 100.0%       2 files  usr\n"),
+            "{out}"
+        );
+        assert!(out.contains("largest :\n     3.0 MiB  usr/lib/modules/kvm.ko\n"));
+        let out = run(&["initrd", path, "--size-report"], true);
+        assert_eq!(out, "4194304\t2\tusr\n10\t1\tinit\n");
+        let json = run(
+            &["initrd", path, "--size-report", "--format", "json"],
+            false,
+        );
+        let sizes: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(sizes["total"], 4194314);
+        assert!(Inspect::try_parse_from(["initrd", path, "--top", "3"]).is_err());
+    }
+}
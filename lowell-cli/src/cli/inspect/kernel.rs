@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cache::Cache;
+use crate::cli::{Ctx, ReportFormat};
+use anyhow::Result;
+use clap::Args;
+use lowell_core::kernel::inspect::{self, Report};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct KernelArgs {
+    /// Kernel image (e.g. /boot/vmlinuz-$(uname -r))
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+    /// Print only this field (e.g. `version`); repeat for several
+    #[arg(long, value_name = "PATH")]
+    get: Vec<String>,
+    /// Reuse reports cached in DIR, keyed by each file's path, size and
+    /// mtime (default: `cache-dir` from config, else no cache)
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+}
+
+impl KernelArgs {
+    pub fn new(
+        file: &Path,
+        format: Option<ReportFormat>,
+        get: Vec<String>,
+        cache_dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            format,
+            get,
+            cache_dir,
+        }
+    }
+
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let report = match Cache::open(self.cache_dir.as_deref(), &ctx.cfg) {
+            Some(cache) => {
+                cache.report("kernel", &self.file, || Ok(inspect::inspect(&self.file)?))?
+            }
+            None => inspect::inspect(&self.file)?,
+        };
+        super::emit(ctx, out, self.format, &self.get, &report, |out| {
+            print_human(out, &report, ctx)
+        })
+    }
+}
+
+fn print_human(out: &mut dyn Write, r: &Report, ctx: &Ctx) -> Result<()> {
+    writeln!(
+        out,
+        "{} • {}",
+        ctx.palette.bold(r.format),
+        r.arch.as_deref().unwrap_or("unknown arch")
+    )?;
+    match &r.version {
+        Some(v) => writeln!(out, "version : {v}")?,
+        None => writeln!(out, "version : {}", ctx.palette.warn("not found"))?,
+    }
+    let stub = match r.efi_stub {
+        true => "yes".to_string(),
+        false => ctx.palette.warn("no").to_string(),
+    };
+    writeln!(out, "efi stub: {stub}")?;
+    if let Some(c) = &r.compression {
+        writeln!(out, "compress: {c}")?;
+    }
+    writeln!(out, "size    : {} bytes", r.size)?;
+    writeln!(out, "sha256  : {}", r.sha256)?;
+    Ok(())
+}
@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell inspect <file>`: sniff the input and run the matching inspector.
+//!
+//! `lowell inspect uki|initrd|kernel <file>` forces one when detection
+//! guesses wrong (or to get the subcommand's stricter errors).
+
+pub(in crate::cli) mod initrd;
+mod kernel;
+
+use crate::cli::uki::inspect::{InspectArgs as UkiInspectArgs, InspectFormat};
+use crate::cli::{Ctx, ReportFormat};
+use crate::error::Coded;
+use crate::select;
+use anyhow::{bail, Result};
+use clap::{Args, Subcommand};
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::sniff::{sniff_path, FileKind};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use tracing::debug;
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct AutoInspectArgs {
+    #[command(subcommand)]
+    cmd: Option<InspectCmd>,
+    #[command(flatten)]
+    auto: UkiInspectArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum InspectCmd {
+    /// Inspect a bare initramfs (compression, entries, sha256)
+    Initrd(initrd::InitrdArgs),
+    /// Inspect a bare kernel image (bzImage, arm64 Image, EFI zboot)
+    Kernel(kernel::KernelArgs),
+    /// Inspect a UKI (same as `lowell uki inspect`)
+    Uki(UkiInspectArgs),
+}
+
+impl AutoInspectArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        match &self.cmd {
+            Some(InspectCmd::Initrd(a)) => a.json_errors(default),
+            Some(InspectCmd::Kernel(a)) => a.json_errors(default),
+            Some(InspectCmd::Uki(a)) => a.json_errors(default),
+            None => self.auto.json_errors(default),
+        }
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        match self.cmd {
+            Some(InspectCmd::Initrd(a)) => a.run(ctx, out),
+            Some(InspectCmd::Kernel(a)) => a.run(ctx, out),
+            Some(InspectCmd::Uki(a)) => a.run(ctx, out),
+            None => {
+                // Nothing to detect: the schema, or the missing-FILE error.
+                let Some(file) = self.auto.files.first().cloned() else {
+                    return self.auto.run(ctx, out);
+                };
+                // Directories and globs collect `*.efi`: those are UKIs.
+                let kind = match self.auto.files.as_slice() {
+                    [file] if file.is_file() => detect(file)?,
+                    _ => FileKind::Uki,
+                };
+                debug!(path = %file.display(), %kind, "detected");
+                let a = self.auto;
+                let uki = kind == FileKind::Uki;
+                let misfits = [
+                    (
+                        a.baseline.is_some() && !uki,
+                        "--baseline compares UKI reports",
+                    ),
+                    (
+                        (a.sign_key.is_some() || a.sign) && !uki,
+                        "--sign-key signs UKI reports",
+                    ),
+                    (
+                        a.dump_section.is_some() && !uki,
+                        "--dump-section reads UKI sections",
+                    ),
+                    (
+                        a.format == Some(InspectFormat::JsonSchema) && !uki,
+                        "--format json-schema describes UKI reports",
+                    ),
+                    (
+                        a.dump_kernel_config && !uki,
+                        "--dump-kernel-config reads a UKI's .linux",
+                    ),
+                    (
+                        a.list_files && kind == FileKind::Kernel,
+                        "--list-files lists initramfs entries",
+                    ),
+                    (
+                        a.size_report && kind == FileKind::Kernel,
+                        "--size-report totals initramfs entries",
+                    ),
+                    (
+                        a.digest.is_some() && !uki,
+                        "--digest picks the UKI section digest",
+                    ),
+                    (!a.hash.is_empty() && !uki, "--hash hashes UKI sections"),
+                    (a.deep && !uki, "--deep counts a UKI's .initrd entries"),
+                    (
+                        a.headers_only && !uki,
+                        "--headers-only reads UKI section headers",
+                    ),
+                    (a.verbose && !uki, "--verbose extends UKI summaries"),
+                    (
+                        a.max_memory.is_some() && !uki,
+                        "--max-memory bounds UKI decompression",
+                    ),
+                ];
+                if let Some((_, what)) = misfits.iter().find(|(misfit, _)| *misfit) {
+                    bail!(Coded::new(
+                        ErrorCode::InvalidArgument,
+                        format!("{what}; {} is a {kind}", file.display())
+                    ));
+                }
+                match kind {
+                    FileKind::Uki => a.run(ctx, out),
+                    FileKind::Initramfs => initrd::InitrdArgs {
+                        file: Some(file),
+                        file_flag: None,
+                        format: a.report_format(),
+                        get: a.get,
+                        cache_dir: a.cache_dir,
+                        list: a.list_files,
+                        size_report: a.size_report,
+                        top: a.top,
+                    }
+                    .run(ctx, out),
+                    FileKind::Kernel => {
+                        kernel::KernelArgs::new(&file, a.report_format(), a.get, a.cache_dir)
+                            .run(ctx, out)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn detect(path: &Path) -> Result<FileKind> {
+    match sniff_path(path)? {
+        Some(kind) => Ok(kind),
+        None => bail!(Coded::new(
+            ErrorCode::UnknownFormat,
+            format!(
+                "{}: not a UKI, initramfs or kernel image (force one with `lowell inspect uki|initrd|kernel`)",
+                path.display()
+            )
+        )),
+    }
+}
+
+/// Shared tail of the non-UKI inspectors: `--get`, porcelain, or `format`.
+fn emit<T: Serialize>(
+    ctx: &Ctx,
+    out: &mut dyn Write,
+    format: Option<ReportFormat>,
+    get: &[String],
+    report: &T,
+    human: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    if !get.is_empty() {
+        return select::write_fields(out, report, get, "");
+    }
+    if ctx.porcelain {
+        return print_porcelain(out, report);
+    }
+    match format.or(ctx.cfg.format).unwrap_or(ReportFormat::Human) {
+        ReportFormat::Human => human(out)?,
+        ReportFormat::Json => {
+            serde_json::to_writer(&mut *out, report)?;
+            out.write_all(b"\n")?;
+        }
+        ReportFormat::JsonPretty => {
+            serde_json::to_writer_pretty(&mut *out, report)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// A listing serialized item by item as it is produced, never collected:
+/// one object per line (NDJSON), or a JSON array when `pretty`.
+pub(in crate::cli) struct JsonList<'w> {
+    out: &'w mut dyn Write,
+    pretty: bool,
+    empty: bool,
+}
+
+impl<'w> JsonList<'w> {
+    pub(in crate::cli) fn new(out: &'w mut dyn Write, pretty: bool) -> Self {
+        Self {
+            out,
+            pretty,
+            empty: true,
+        }
+    }
+
+    pub(in crate::cli) fn push<T: Serialize>(&mut self, item: &T) -> io::Result<()> {
+        if !self.pretty {
+            serde_json::to_writer(&mut *self.out, item)?;
+            return self.out.write_all(b"\n");
+        }
+        self.out
+            .write_all(if self.empty { b"[\n" } else { b",\n" })?;
+        self.empty = false;
+        let item = serde_json::to_string_pretty(item)?;
+        for (i, line) in item.lines().enumerate() {
+            if i > 0 {
+                self.out.write_all(b"\n")?;
+            }
+            write!(self.out, "  {line}")?;
+        }
+        Ok(())
+    }
+
+    pub(in crate::cli) fn finish(self) -> io::Result<()> {
+        match (self.pretty, self.empty) {
+            (false, _) => Ok(()),
+            (true, true) => self.out.write_all(b"[]\n"),
+            (true, false) => self.out.write_all(b"\n]\n"),
+        }
+    }
+}
+
+/// `key=value` for each top-level field (null prints as empty).
+fn print_porcelain<T: Serialize>(out: &mut dyn Write, report: &T) -> Result<()> {
+    let serde_json::Value::Object(fields) = serde_json::to_value(report)? else {
+        bail!("report is not an object");
+    };
+    for (key, value) in fields {
+        match value {
+            serde_json::Value::String(s) => writeln!(out, "{key}={s}")?,
+            serde_json::Value::Null => writeln!(out, "{key}=")?,
+            v => writeln!(out, "{key}={v}")?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::error::code_of;
+    use crate::style::Palette;
+    use clap::Parser;
+    use lowell_test_util::Initrd;
+
+    #[derive(Parser)]
+    struct Inspect {
+        #[command(flatten)]
+        args: AutoInspectArgs,
+    }
+
+    #[test]
+    fn uki_flags_are_refused_for_other_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("initramfs.img");
+        std::fs::write(&path, Initrd::new().file("init", b"#!/bin/sh\n").build()).unwrap();
+        let ctx = Ctx {
+            cfg: Config::default(),
+            palette: Palette::new(false),
+            porcelain: false,
+        };
+        let run = |flags: &[&str]| {
+            let mut argv = vec!["inspect", path.to_str().unwrap()];
+            argv.extend(flags);
+            Inspect::parse_from(argv).args.run(&ctx, &mut Vec::new())
+        };
+        assert!(run(&[]).is_ok());
+        for flags in [
+            &["--digest", "sha512"][..],
+            &["--hash", "sha384"],
+            &["--deep"],
+            &["--headers-only"],
+            &["-v"],
+            &["--max-memory", "64M"],
+        ] {
+            let err = run(flags).unwrap_err();
+            assert_eq!(code_of(&err), ErrorCode::InvalidArgument, "{flags:?}");
+            let flag = flags[0].replace("-v", "--verbose");
+            assert!(err.to_string().starts_with(&flag), "{err}");
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell install`: put a UKI, and optionally systemd-boot, on the ESP.
+
+use crate::bootvars::{self, LoadOption, Partition};
+use crate::cli::Ctx;
+use crate::error::Coded;
+use crate::esp::image::Image;
+use crate::esp::{self, grubenv, Action, Dir, Esp};
+use crate::secureboot::{self, Efivars};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::osrel::read_os_release;
+use lowell_core::formats::pe::PeFile;
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::debug;
+
+#[derive(Args, Debug)]
+pub struct InstallArgs {
+    /// UKI to install as `EFI/Linux/<NAME>.efi`
+    file: Option<PathBuf>,
+    /// ESP mount point (default: `esp` from config, else the first of
+    /// /efi, /boot/efi, /boot with an EFI directory)
+    #[arg(long, value_name = "DIR")]
+    esp: Option<PathBuf>,
+    /// Install into the ESP of a raw GPT disk image instead (no mounting;
+    /// a blank ESP is formatted FAT)
+    #[arg(long, value_name = "DISK", conflicts_with_all = ["esp", "boot_entry", "default"])]
+    image: Option<PathBuf>,
+    /// File name for the UKI under EFI/Linux, without `.efi` (default: the
+    /// UKI's file stem)
+    #[arg(long, value_name = "NAME", conflicts_with = "bls")]
+    name: Option<String>,
+    /// Install as a BLS type #1 entry (kernel, initrd, `loader/entries`
+    /// .conf) that GRUB's blscfg can boot, instead of the UKI itself; pass
+    /// the partition holding `loader/entries` (usually /boot) as --esp
+    #[arg(long, requires = "file")]
+    bls: bool,
+    /// Make the BLS entry GRUB's default (`saved_entry` in grubenv)
+    #[arg(long, requires = "bls")]
+    default: bool,
+    /// grubenv to update (default: grub2/grubenv or grub/grubenv under --esp)
+    #[arg(long, value_name = "FILE", requires = "default")]
+    grubenv: Option<PathBuf>,
+    /// Also install or update systemd-boot, from PATH or the copy shipped in
+    /// /usr/lib/systemd/boot/efi (a `.efi.signed` one if present)
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    systemd_boot: Option<Option<PathBuf>>,
+    /// Replace systemd-boot even when the installed copy is newer, or when
+    /// another loader owns the fallback path
+    #[arg(long)]
+    force: bool,
+    /// Create or update a firmware Boot#### entry for systemd-boot (if
+    /// installed) or the UKI, and put it first in BootOrder; LABEL defaults
+    /// to "Linux Boot Manager" or the UKI's PRETTY_NAME
+    #[arg(long, value_name = "LABEL", num_args = 0..=1)]
+    boot_entry: Option<Option<String>>,
+    /// Write Boot#### and BootOrder under DIR instead of efivarfs
+    #[arg(long, value_name = "DIR", default_value = secureboot::EFIVARS, requires = "boot_entry")]
+    efivars: PathBuf,
+}
+
+impl InstallArgs {
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        if self.file.is_none() && self.systemd_boot.is_none() {
+            bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                "nothing to install: give a UKI and/or --systemd-boot"
+            ));
+        }
+        if self.boot_entry.is_some() && self.bls && self.systemd_boot.is_none() {
+            bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                "a BLS entry is not an EFI program: --boot-entry needs --systemd-boot"
+            ));
+        }
+        let (dir, mut esp): (_, Box<dyn Esp>) = match &self.image {
+            Some(image) => (None, Box::new(Image::open(image)?)),
+            None => {
+                let dir = esp::find(self.esp.as_deref().or(ctx.cfg.esp.as_deref()))?;
+                (Some(dir.clone()), Box::new(Dir(dir)))
+            }
+        };
+        // Find the partition and variables before writing anything.
+        let firmware = match (&self.boot_entry, &dir) {
+            (Some(_), Some(dir)) => {
+                Some((Partition::of_mount(dir)?, Efivars::open(&self.efivars)?))
+            }
+            _ => None,
+        };
+        let mut done = Vec::new();
+        let mut arch = None;
+        let mut default = None;
+        // What a boot entry would start, and its label.
+        let mut target = None;
+        if let Some(file) = &self.file {
+            let pe = PeFile::from_path_mmap(file)
+                .with_context(|| format!("{}: not a UKI", file.display()))?;
+            if pe.find_section(".linux")?.is_none() {
+                bail!(Coded::new(
+                    ErrorCode::UnknownFormat,
+                    format!("{} has no .linux section: not a UKI", file.display())
+                ));
+            }
+            arch = Some(esp::efi_arch(&pe)?);
+            if self.bls {
+                let (id, files) = esp::install_bls(esp.as_mut(), &pe)?;
+                done.extend(files);
+                if self.default {
+                    let env = self
+                        .grubenv
+                        .clone()
+                        .or_else(|| dir.as_deref().and_then(grubenv::find))
+                        .context("no grubenv under the ESP; pass --grubenv")?;
+                    grubenv::set(&env, "saved_entry", &id)?;
+                    default = Some((id, env));
+                }
+            } else {
+                let name = match &self.name {
+                    Some(name) => name.clone(),
+                    None => file
+                        .file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .context("UKI path has no file name; pass --name")?,
+                };
+                let installed = esp::install_uki(esp.as_mut(), file, &name)?;
+                let title = read_os_release(&pe)?.and_then(|o| o.name).unwrap_or(name);
+                target = Some((installed.path.clone(), title));
+                done.push(installed);
+            }
+        }
+        if let Some(source) = self.systemd_boot {
+            let source = match source {
+                Some(path) => path,
+                None => {
+                    let arch = arch
+                        .or_else(esp::host_efi_arch)
+                        .context("no UEFI architecture for this host; pass --systemd-boot PATH")?;
+                    esp::shipped_systemd_boot(arch)?
+                }
+            };
+            let installed = esp::install_systemd_boot(esp.as_mut(), &source, self.force)?;
+            target = Some((installed[0].path.clone(), "Linux Boot Manager".into()));
+            done.extend(installed);
+        }
+        for installed in &done {
+            report(out, ctx, &esp.describe(&installed.path), &installed.action)?;
+        }
+        esp.close()?;
+        if let (Some((partition, vars)), Some((path, title))) = (firmware, target) {
+            let label = self.boot_entry.flatten().unwrap_or(title);
+            let option = LoadOption::new(&label, &partition, &path);
+            let entry = bootvars::ensure(&vars, &option)?;
+            let name = format!("Boot{:04X}", entry.number);
+            debug!(%name, action = ?entry.action, reordered = entry.reordered, "boot entry");
+            if ctx.porcelain {
+                writeln!(out, "boot-entry\t{name}\t{label}")?;
+            } else {
+                let state = match entry.action {
+                    Action::Current if !entry.reordered => "is up to date",
+                    Action::Current => "moved first in BootOrder",
+                    _ => "written, first in BootOrder",
+                };
+                writeln!(
+                    out,
+                    "{} {name} {label:?} {state}",
+                    ctx.palette.good("boot entry")
+                )?;
+            }
+        }
+        if let Some((id, env)) = default {
+            if ctx.porcelain {
+                writeln!(out, "default\t{id}")?;
+            } else {
+                writeln!(out, "default entry {id} (in {})", env.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn report(out: &mut dyn Write, ctx: &Ctx, path: &str, action: &Action) -> Result<()> {
+    debug!(path, ?action, "install");
+    if ctx.porcelain {
+        let action = match action {
+            Action::Installed => "installed",
+            Action::Updated { .. } => "updated",
+            Action::Current => "current",
+            Action::Kept { .. } => "kept",
+        };
+        writeln!(out, "{action}\t{path}")?;
+        return Ok(());
+    }
+    let p = ctx.palette;
+    match action {
+        Action::Installed => writeln!(out, "{} {path}", p.good("installed"))?,
+        Action::Updated { from: Some(v) } => {
+            writeln!(out, "{} {path} (was {v})", p.good("updated"))?
+        }
+        Action::Updated { from: None } => writeln!(out, "{} {path}", p.good("updated"))?,
+        Action::Current => writeln!(out, "{path} is up to date")?,
+        Action::Kept { reason } => writeln!(out, "{} {path}: {reason}", p.warn("kept"))?,
+    }
+    Ok(())
+}
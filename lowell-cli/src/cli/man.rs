@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use anyhow::{Context, Result};
+use clap::{Args, CommandFactory};
+use std::io;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct ManArgs {
+    /// Write one page per subcommand (lowell.1, lowell-uki-inspect.1, ...)
+    /// into this directory instead of printing lowell(1) to stdout
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+}
+
+impl ManArgs {
+    pub fn run(self, out: &mut dyn io::Write) -> Result<()> {
+        let cmd = super::Cli::command();
+        match self.out_dir {
+            Some(dir) => {
+                std::fs::create_dir_all(&dir)
+                    .with_context(|| format!("create {}", dir.display()))?;
+                clap_mangen::generate_to(cmd, &dir)
+                    .with_context(|| format!("write man pages to {}", dir.display()))?;
+                info!(dir = %dir.display(), "wrote man pages");
+            }
+            None => clap_mangen::Man::new(cmd).render(out)?,
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,32 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::config::Config;
+use crate::output::Output;
+use crate::style::{ColorChoice, Palette};
 use anyhow::Result;
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use tracing_subscriber::filter::Directive;
 
+mod bench;
+mod browse;
+mod build;
+mod completions;
+mod config;
+mod diff;
+mod extract;
+mod fetch;
+mod inspect;
+mod install;
+mod man;
+mod preflight;
+mod profile;
+mod pull;
+mod push;
+mod serve;
+mod sign;
+mod test;
 mod uki;
+mod verify;
 
 #[derive(Parser, Debug)]
 #[command(name = "lowell", version, about = "Hermetic initramfs/UKI builder")]
@@ -17,23 +41,154 @@ impl Cli {
     pub fn parse() -> Self {
         <Self as Parser>::parse()
     }
-    pub fn run(self) -> Result<()> {
+    /// Whether failures should be reported as JSON: `--format json`, or
+    /// `format = "json"` in config (`default`) for commands without one.
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        match &self.cmd {
+            Cmd::Diff(a) => a.json_errors(default),
+            Cmd::Fetch(a) => a.json_errors(default),
+            Cmd::Inspect(a) => a.json_errors(default),
+            Cmd::Preflight(a) => a.json_errors(default),
+            Cmd::Profile(a) => a.json_errors(),
+            Cmd::Test(a) => a.json_errors(default),
+            Cmd::Uki(a) => a.json_errors(default),
+            Cmd::Verify(a) => a.json_errors(default),
+            _ => false,
+        }
+    }
+
+    /// Config from `--config`, or the merged /etc and ~/.config files.
+    pub fn config(&self) -> Result<Config> {
+        Config::load(self.global.config.as_deref())
+    }
+
+    pub fn run(self, cfg: Config) -> Result<()> {
+        let mut out = Output::open(self.global.output.as_deref())?;
+        let ctx = Ctx {
+            cfg,
+            palette: Palette::new(self.global.color.enabled(out.is_terminal())),
+            porcelain: self.global.porcelain,
+        };
         match self.cmd {
-            Cmd::Uki(a) => a.run(),
+            Cmd::Bench(a) => a.run(&ctx, &mut out)?,
+            Cmd::Browse(a) => a.run()?,
+            Cmd::Build(a) => a.run(&ctx)?,
+            Cmd::Completions(a) => a.run(&mut out)?,
+            Cmd::Config(a) => a.run(&ctx, &mut out)?,
+            Cmd::Diff(a) => a.run(&ctx, &mut out)?,
+            Cmd::Extract(a) => a.run()?,
+            Cmd::Fetch(a) => a.run(&ctx, &mut out)?,
+            Cmd::Inspect(a) => a.run(&ctx, &mut out)?,
+            Cmd::Install(a) => a.run(&ctx, &mut out)?,
+            Cmd::Man(a) => a.run(&mut out)?,
+            Cmd::Profile(a) => a.run(&ctx, &mut out)?,
+            Cmd::Pull(a) => a.run(&ctx, &mut out)?,
+            Cmd::Preflight(a) => a.run(&ctx, &mut out)?,
+            Cmd::Push(a) => a.run(&ctx, &mut out)?,
+            Cmd::Serve(a) => a.run(&ctx)?,
+            Cmd::Sign(a) => a.run(&ctx, &mut out)?,
+            Cmd::Test(a) => a.run(&ctx, &mut out)?,
+            Cmd::Uki(a) => a.run(&ctx, &mut out)?,
+            Cmd::Verify(a) => a.run(&ctx, &mut out)?,
         }
+        // only reached on success: a failed command never replaces --output
+        out.commit()
     }
 }
 
+/// Per-invocation settings shared by every subcommand.
+pub struct Ctx {
+    pub cfg: Config,
+    pub palette: Palette,
+    /// `--porcelain`: stable line-oriented output, no logs
+    pub porcelain: bool,
+}
+
 #[derive(Args, Debug)]
 pub struct GlobalArgs {
     /// Sets the log verbosity (overridden by RUST_LOG if set)
     #[arg(long, value_enum, default_value_t = LogLevel::Info)]
     pub log_level: LogLevel,
+    /// Per-target verbosity, e.g. `--log goblin=debug --log lowell_core::formats=trace`
+    /// (repeatable; applied on top of --log-level or RUST_LOG)
+    #[arg(long, global = true, value_name = "TARGET=LEVEL")]
+    pub log: Vec<Directive>,
+    /// Log line format on stderr
+    #[arg(long, value_enum, global = true, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+    /// Read defaults from this file instead of /etc and ~/.config
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Write command output to FILE (atomically replaced) instead of stdout
+    #[arg(long, short = 'o', global = true, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+    /// Colorize human output: auto (TTY and no NO_COLOR), always, never
+    #[arg(long, value_enum, global = true, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// Script-friendly mode: no logs, stable `key=value` / tab-separated output
+    #[arg(long, global = true)]
+    pub porcelain: bool,
 }
 
 #[derive(Subcommand, Debug)]
 enum Cmd {
+    /// Time inspect and build over a corpus, with per-phase statistics
+    Bench(bench::BenchArgs),
+    /// Browse a UKI interactively: sections, initramfs tree, file previews
+    Browse(browse::BrowseArgs),
+    /// Assemble a UKI from a profile (optionally rebuilding on change)
+    Build(build::BuildArgs),
+    /// Print shell completions (bash, zsh, fish, powershell, elvish)
+    Completions(completions::CompletionsArgs),
+    /// Show the effective configuration
+    Config(config::ConfigArgs),
+    /// Show what changed between two artifacts
+    Diff(diff::DiffArgs),
+    /// Unpack UKI sections and initramfs contents
+    Extract(extract::ExtractArgs),
+    /// Download and verify a profile's `[fetch]` inputs into the cache
+    Fetch(fetch::FetchArgs),
+    /// Inspect a UKI, initramfs or kernel image (type is auto-detected)
+    Inspect(inspect::AutoInspectArgs),
+    /// Install a UKI (and optionally systemd-boot) on the EFI System Partition
+    Install(install::InstallArgs),
+    /// Print or generate roff man pages
+    Man(man::ManArgs),
+    /// Work with build profiles
+    Profile(profile::ProfileArgs),
+    /// Report Secure Boot state and whether a UKI would boot under it
+    Preflight(preflight::PreflightArgs),
+    /// Fetch a UKI artifact from an OCI registry
+    Pull(pull::PullArgs),
+    /// Push a UKI (with its report and an optional SBOM) to an OCI registry
+    Push(push::PushArgs),
+    /// Serve inspect, build and install over varlink (for system agents)
+    Serve(serve::ServeArgs),
+    /// Sign files with a Sigstore (cosign) key pair
+    Sign(sign::SignArgs),
+    /// Test built artifacts (boot them in QEMU)
+    Test(test::TestArgs),
     Uki(uki::UkiArgs),
+    /// Check Sigstore (cosign) signatures made by `lowell sign`, or (`verify
+    /// boot`) that this system booted a given UKI
+    Verify(verify::VerifyArgs),
+}
+
+/// Output format for reports (inspect and friends).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReportFormat {
+    Human,
+    Json,
+    JsonPretty,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// Newline-delimited JSON objects
+    Json,
 }
 
 #[derive(Clone, Copy, Debug, ValueEnum)]
@@ -57,12 +212,56 @@ impl LogLevel {
     }
 }
 
+/// A byte count for `--max-memory` and friends: digits with an optional
+/// binary suffix (`K`, `M`, `G`, `T`; `KiB`-style spellings too).
+fn parse_size(s: &str) -> Result<u64, String> {
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(digits);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("`{s}` is not a size (e.g. 512M)"))?;
+    let shift = match suffix.trim_end_matches("iB").trim_end_matches('B') {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" | "m" => 20,
+        "G" | "g" => 30,
+        "T" | "t" => 40,
+        other => return Err(format!("unknown size suffix `{other}` (use K, M, G or T)")),
+    };
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("`{s}` is too large"))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Cli;
-    use clap::CommandFactory;
+    use super::{parse_size, Cli, ReportFormat};
+    use clap::{CommandFactory, Parser};
     #[test]
     fn cli_ok() {
         Cli::command().debug_assert();
     }
+
+    #[test]
+    fn sizes_parse_with_binary_suffixes() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("256M"), Ok(256 << 20));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("1k"), Ok(1024));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("12X").is_err());
+        assert!(parse_size("99999999T").is_err());
+    }
+
+    #[test]
+    fn config_format_picks_json_errors() {
+        let json = Some(ReportFormat::Json);
+        let cli = Cli::parse_from(["lowell", "uki", "inspect", "x.efi"]);
+        assert!(!cli.json_errors(None));
+        assert!(cli.json_errors(json));
+        let cli = Cli::parse_from(["lowell", "uki", "inspect", "--format", "human", "x.efi"]);
+        assert!(!cli.json_errors(json));
+        let cli = Cli::parse_from(["lowell", "inspect", "--format", "json", "x.efi"]);
+        assert!(cli.json_errors(None));
+    }
 }
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell preflight`: would this machine boot a UKI under Secure Boot?
+
+use crate::cli::{Ctx, ReportFormat};
+use crate::error::Coded;
+use crate::secureboot::{self, Acceptance, Efivars, Entry, State};
+use crate::style::Palette;
+use anyhow::{bail, Result};
+use clap::Args;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::pe::PeFile;
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct PreflightArgs {
+    /// UKI to check against the enrolled keys
+    file: Option<PathBuf>,
+    /// Read EFI variables from DIR (e.g. a copy taken on another machine)
+    #[arg(long, value_name = "DIR", default_value = secureboot::EFIVARS)]
+    efivars: PathBuf,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    #[serde(flatten)]
+    state: State,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uki: Option<Verdict>,
+}
+
+#[derive(Serialize)]
+struct Verdict {
+    file: PathBuf,
+    verdict: Acceptance,
+    reasons: Vec<String>,
+}
+
+impl PreflightArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let state = State::read(&Efivars::open(&self.efivars)?)?;
+        let uki = match &self.file {
+            Some(file) => {
+                let (verdict, reasons) = state.check(&PeFile::from_path_mmap(file)?)?;
+                Some(Verdict {
+                    file: file.clone(),
+                    verdict,
+                    reasons,
+                })
+            }
+            None => None,
+        };
+        let report = Report { state, uki };
+        match self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human)
+        {
+            ReportFormat::Human => print_human(out, &report, ctx.palette)?,
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+            ReportFormat::JsonPretty => {
+                serde_json::to_writer_pretty(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        if let Some(v) = report.uki.filter(|v| v.verdict == Acceptance::Rejected) {
+            bail!(Coded::new(
+                ErrorCode::BadSignature,
+                format!("Secure Boot would refuse {}", v.file.display())
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn print_human(out: &mut dyn Write, r: &Report, palette: Palette) -> Result<()> {
+    let s = &r.state;
+    let flag = |v: Option<bool>, on: &'static str, off: &'static str| match v {
+        Some(true) => on,
+        Some(false) => off,
+        None => "unknown",
+    };
+    let row = |out: &mut dyn Write, label: &str, value: &dyn std::fmt::Display| {
+        writeln!(out, "{}{value}", palette.bold(format_args!("{label:<12}")))
+    };
+    row(
+        out,
+        "Secure Boot",
+        &flag(s.secure_boot, "enabled", "disabled"),
+    )?;
+    row(out, "Setup mode", &flag(s.setup_mode, "yes", "no"))?;
+    for (label, entries) in [("PK", &s.pk), ("KEK", &s.kek), ("db", &s.db)] {
+        match entries.as_slice() {
+            [] => row(out, label, &"(empty)")?,
+            [first, rest @ ..] => {
+                row(out, label, &describe(first))?;
+                for e in rest {
+                    row(out, "", &describe(e))?;
+                }
+            }
+        }
+    }
+    row(out, "dbx", &format_args!("{} entries", s.dbx))?;
+    match &s.shim {
+        Some(shim) => {
+            let mut text = format!("present, MokList {} entries", shim.mok.len());
+            if shim.validation_disabled {
+                text += ", validation disabled";
+            }
+            if let Some(level) = shim.sbat_level.as_deref().and_then(|l| l.lines().next()) {
+                text += &format!(", SBAT {level}");
+            }
+            row(out, "shim", &text)?;
+        }
+        None => row(out, "shim", &"not detected")?,
+    }
+    if let Some(v) = &r.uki {
+        let verdict = match v.verdict {
+            Acceptance::Accepted => palette.good("accepted").to_string(),
+            Acceptance::Rejected => palette.bad("rejected").to_string(),
+            Acceptance::Unknown => palette.warn("unknown").to_string(),
+        };
+        writeln!(out)?;
+        writeln!(out, "{}: {verdict}", v.file.display())?;
+        for reason in &v.reasons {
+            writeln!(out, "  - {reason}")?;
+        }
+    }
+    Ok(())
+}
+
+/// A certificate's CN (else its subject), or an image hash.
+fn describe(e: &Entry) -> String {
+    match &e.subject {
+        Some(subject) => subject
+            .split(',')
+            .find_map(|part| part.strip_prefix("CN="))
+            .unwrap_or(subject)
+            .to_string(),
+        None => format!("{}:{}", e.kind, e.sha256),
+    }
+}
@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cli::Ctx;
+use crate::style::Palette;
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use lowell_core::profile::diff::{self, ListDiff, ProfileDiff};
+use lowell_core::profile::Source;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum Output {
+    Human,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// Old profile: a TOML path, `builtin:<name>` or a name on the profile path
+    old: String,
+    /// New profile: a TOML path, `builtin:<name>` or a name on the profile path
+    new: String,
+    /// Output format (human by default)
+    #[arg(long, value_enum, default_value_t = Output::Human)]
+    format: Output,
+}
+
+impl DiffArgs {
+    pub fn json_errors(&self) -> bool {
+        matches!(self.format, Output::Json)
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let old = Source::locate(&self.old, &ctx.cfg.profile_path)?.load()?;
+        let new = Source::locate(&self.new, &ctx.cfg.profile_path)?.load()?;
+        let d = diff::diff(&old, &new);
+        if ctx.porcelain {
+            return print_porcelain(out, &d);
+        }
+        match self.format {
+            Output::Human => print_human(out, &d, ctx.palette)?,
+            Output::Json => {
+                serde_json::to_writer_pretty(&mut *out, &d)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_human(out: &mut dyn Write, d: &ProfileDiff, palette: Palette) -> Result<()> {
+    if let Some(c) = &d.name {
+        writeln!(out, "name: {} -> {}", c.from, c.to)?;
+    }
+    if d.is_empty() {
+        writeln!(out, "profiles are equivalent")?;
+        return Ok(());
+    }
+    if let Some(c) = &d.root {
+        writeln!(out, "root: {} -> {}", c.from, c.to)?;
+    }
+    print_list(out, "modules", &d.modules, palette)?;
+    print_list(out, "cmdline", &d.cmdline, palette)?;
+    if let Some(c) = &d.compression {
+        let show = |v: &Option<_>| v.map_or("<default>".to_string(), |c| format!("{c}"));
+        writeln!(out, "compression: {} -> {}", show(&c.from), show(&c.to))?;
+    }
+    if !d.artifacts.is_empty() {
+        writeln!(out, "artifacts:")?;
+        let show = |p: &Option<PathBuf>| {
+            p.as_ref()
+                .map_or("<none>".into(), |p| p.display().to_string())
+        };
+        for (name, c) in &d.artifacts {
+            writeln!(out, "  {name}: {} -> {}", show(&c.from), show(&c.to))?;
+        }
+    }
+    Ok(())
+}
+
+fn print_list(out: &mut dyn Write, label: &str, l: &ListDiff, palette: Palette) -> Result<()> {
+    if l.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "{label}:")?;
+    for a in &l.added {
+        writeln!(out, "  {}", palette.good(format_args!("+ {a}")))?;
+    }
+    for r in &l.removed {
+        writeln!(out, "  {}", palette.bad(format_args!("- {r}")))?;
+    }
+    if l.reordered {
+        writeln!(out, "  ~ reordered")?;
+    }
+    Ok(())
+}
+
+/// Tab-separated `<field>\t<op>\t<value>` lines; nothing at all when the
+/// profiles are equivalent, so `[ -z "$(lowell --porcelain profile diff a b)" ]`
+/// works as a check.
+fn print_porcelain(out: &mut dyn Write, d: &ProfileDiff) -> Result<()> {
+    if d.is_empty() {
+        return Ok(());
+    }
+    if let Some(c) = &d.root {
+        writeln!(out, "root\t-\t{}\nroot\t+\t{}", c.from, c.to)?;
+    }
+    for (label, l) in [("modules", &d.modules), ("cmdline", &d.cmdline)] {
+        for a in &l.added {
+            writeln!(out, "{label}\t+\t{a}")?;
+        }
+        for r in &l.removed {
+            writeln!(out, "{label}\t-\t{r}")?;
+        }
+        if l.reordered {
+            writeln!(out, "{label}\t~\t")?;
+        }
+    }
+    if let Some(c) = &d.compression {
+        if let Some(from) = c.from {
+            writeln!(out, "compression\t-\t{from}")?;
+        }
+        if let Some(to) = c.to {
+            writeln!(out, "compression\t+\t{to}")?;
+        }
+    }
+    for (name, c) in &d.artifacts {
+        if let Some(p) = &c.from {
+            writeln!(out, "artifact.{name}\t-\t{}", p.display())?;
+        }
+        if let Some(p) = &c.to {
+            writeln!(out, "artifact.{name}\t+\t{}", p.display())?;
+        }
+    }
+    Ok(())
+}
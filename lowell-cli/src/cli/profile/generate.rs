@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use anyhow::Result;
+use clap::{ArgGroup, Args};
+use lowell_core::profile::generate;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("source").required(true)))]
+pub struct GenerateArgs {
+    /// Derive the profile from the running system
+    #[arg(long, group = "source")]
+    from_host: bool,
+    /// Derive the profile from an existing UKI (e.g. built by dracut+ukify)
+    #[arg(long, group = "source", value_name = "UKI")]
+    from_uki: Option<PathBuf>,
+    /// Override the generated profile name
+    #[arg(long)]
+    name: Option<String>,
+}
+
+impl GenerateArgs {
+    pub fn run(self, out: &mut dyn Write) -> Result<()> {
+        let mut profile = match self.from_uki {
+            Some(uki) => generate::from_uki(&uki)?,
+            None => generate::from_host()?,
+        };
+        if let Some(name) = self.name {
+            profile.name = name;
+        }
+        out.write_all(profile.to_toml_string()?.as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use lowell_core::profile::import::{dracut, mkinitcpio};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Tool {
+    /// dracut.conf or a dracut.conf.d directory
+    Dracut,
+    /// mkinitcpio.conf (with its .conf.d drop-ins) or a drop-in directory
+    Mkinitcpio,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Configuration format to translate
+    #[arg(value_enum)]
+    tool: Tool,
+    /// Configuration file or directory (e.g. /etc/dracut.conf.d/,
+    /// /etc/mkinitcpio.conf)
+    path: PathBuf,
+    /// Where to detect the root and modules for a host-only configuration
+    /// (dracut's `hostonly`, mkinitcpio's `autodetect` hook)
+    #[arg(long, value_name = "DIR", default_value = "/")]
+    sysroot: PathBuf,
+    /// Override the generated profile name
+    #[arg(long)]
+    name: Option<String>,
+}
+
+impl ImportArgs {
+    pub fn run(self, out: &mut dyn Write) -> Result<()> {
+        let imported = match self.tool {
+            Tool::Dracut => dracut::import(&self.path, &self.sysroot)?,
+            Tool::Mkinitcpio => mkinitcpio::import(&self.path, &self.sysroot)?,
+        };
+        let mut profile = imported.profile;
+        if let Some(name) = self.name {
+            profile.name = name;
+        }
+        // What did not translate stays with the profile, for its review.
+        for note in &imported.notes {
+            writeln!(out, "# {note}")?;
+        }
+        if !imported.notes.is_empty() {
+            writeln!(out)?;
+        }
+        out.write_all(profile.to_toml_string()?.as_bytes())?;
+        Ok(())
+    }
+}
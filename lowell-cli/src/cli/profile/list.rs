@@ -0,0 +1,29 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cli::Ctx;
+use anyhow::Result;
+use clap::Args;
+use lowell_core::profile::{builtin, BUILTIN_PREFIX};
+use std::io::Write;
+
+#[derive(Args, Debug)]
+pub struct ListArgs {}
+
+impl ListArgs {
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        for name in builtin::names() {
+            let p = builtin::load(name)?;
+            if ctx.porcelain {
+                let (root, n) = (p.root.kind(), p.modules.len());
+                writeln!(out, "{BUILTIN_PREFIX}{name}\t{root}\t{n}")?;
+                continue;
+            }
+            writeln!(
+                out,
+                "{BUILTIN_PREFIX}{name:<20} root={:<8} {} modules",
+                p.root.kind(),
+                p.modules.len()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cli::Ctx;
+use crate::error::Coded;
+use crate::output;
+use anyhow::{bail, Result};
+use clap::Args;
+use lowell_core::error::ErrorCode;
+use lowell_core::profile::lock::Lockfile;
+use lowell_core::profile::Source;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct LockArgs {
+    /// Profile to lock: a TOML path, `builtin:<name>` or a name on the profile path
+    #[arg(long)]
+    profile: String,
+    /// Lockfile path (default: the profile path with a `.lock` extension)
+    #[arg(long)]
+    lockfile: Option<PathBuf>,
+    /// Verify the existing lockfile instead of writing; fail on any drift
+    #[arg(long)]
+    locked: bool,
+}
+
+impl LockArgs {
+    pub fn run(self, ctx: &Ctx) -> Result<()> {
+        let source = Source::locate(&self.profile, &ctx.cfg.profile_path)?;
+        let profile = source.load()?;
+        let (base_dir, default_lock) = match &source {
+            Source::Builtin(name) => (PathBuf::from("."), PathBuf::from(format!("{name}.lock"))),
+            Source::File(path) => {
+                let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+                (dir, path.with_extension("lock"))
+            }
+        };
+        let lockfile = self.lockfile.unwrap_or(default_lock);
+
+        if self.locked {
+            let locked = Lockfile::from_path(&lockfile)?;
+            let drift = locked.drift(&profile, &base_dir)?;
+            if !drift.is_empty() {
+                let lines: Vec<String> = drift.iter().map(|d| format!("  {d}")).collect();
+                bail!(Coded::new(
+                    ErrorCode::LockMismatch,
+                    format!(
+                        "{} is out of date:\n{}",
+                        lockfile.display(),
+                        lines.join("\n")
+                    )
+                ));
+            }
+            info!(lockfile = %lockfile.display(), "lockfile up to date");
+            return Ok(());
+        }
+
+        let lock = Lockfile::generate(&profile, &base_dir)?;
+        output::write_atomic(&lockfile, lock.to_toml_string()?.as_bytes())?;
+        info!(
+            lockfile = %lockfile.display(),
+            artifacts = lock.artifacts.len(),
+            "wrote lockfile"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+mod diff;
+mod generate;
+mod import;
+mod list;
+mod lock;
+mod show;
+
+use crate::cli::Ctx;
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use std::io::Write;
+
+#[derive(Args, Debug)]
+pub struct ProfileArgs {
+    #[command(subcommand)]
+    cmd: ProfileCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileCmd {
+    /// Show effective differences between two profiles
+    Diff(diff::DiffArgs),
+    /// Generate a profile from an existing system
+    Generate(generate::GenerateArgs),
+    /// Translate another tool's configuration (dracut, mkinitcpio) into a
+    /// profile
+    Import(import::ImportArgs),
+    /// List the built-in profiles
+    List(list::ListArgs),
+    /// Pin artifact digests in a lockfile (or verify it with --locked)
+    Lock(lock::LockArgs),
+    /// Print a resolved profile
+    Show(show::ShowArgs),
+}
+
+impl ProfileArgs {
+    pub fn json_errors(&self) -> bool {
+        match &self.cmd {
+            ProfileCmd::Diff(a) => a.json_errors(),
+            ProfileCmd::Show(a) => a.json_errors(),
+            _ => false,
+        }
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        match self.cmd {
+            ProfileCmd::Diff(a) => a.run(ctx, out),
+            ProfileCmd::Generate(a) => a.run(out),
+            ProfileCmd::Import(a) => a.run(out),
+            ProfileCmd::List(a) => a.run(ctx, out),
+            ProfileCmd::Lock(a) => a.run(ctx),
+            ProfileCmd::Show(a) => a.run(ctx, out),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cli::{build, Ctx};
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use lowell_core::profile::Source;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum Output {
+    Toml,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct ShowArgs {
+    /// Profile to show: a TOML path, `builtin:<name>` or a name on the profile path
+    #[arg(long)]
+    profile: String,
+    /// Complete an ostree profile from its deployment in this sysroot, as
+    /// `lowell build --ostree-sysroot` does
+    #[arg(long, value_name = "DIR")]
+    ostree_sysroot: Option<PathBuf>,
+    /// Output format (toml by default)
+    #[arg(long, value_enum, default_value_t = Output::Toml)]
+    format: Output,
+}
+
+impl ShowArgs {
+    pub fn json_errors(&self) -> bool {
+        matches!(self.format, Output::Json)
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let mut profile = Source::locate(&self.profile, &ctx.cfg.profile_path)?.load()?;
+        if let Some(sysroot) = &self.ostree_sysroot {
+            build::for_deployment(&mut profile, sysroot)?;
+        }
+        match self.format {
+            Output::Toml => out.write_all(profile.to_toml_string()?.as_bytes())?,
+            Output::Json => {
+                serde_json::to_writer_pretty(&mut *out, &profile)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell pull`: fetch a UKI artifact pushed with `lowell push`.
+
+use crate::cli::Ctx;
+use crate::error::Coded;
+use crate::output;
+use crate::registry::reference::Reference;
+use crate::registry::{self, Access, Client, Manifest};
+use crate::sign::{self, Payload};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use lowell_core::digest::{Algorithm, Digest};
+use lowell_core::error::ErrorCode;
+use p256::ecdsa::VerifyingKey;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+#[derive(Args, Debug)]
+pub struct PullArgs {
+    /// What to pull: `registry/repository:tag` or `…@sha256:…`
+    #[arg(value_name = "REF")]
+    reference: Reference,
+    /// Directory to write into; each layer keeps its title (the UKI's file
+    /// name, `report.json`, the SBOM's file name)
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    out_dir: PathBuf,
+    /// Require a signature by this public key, as `cosign verify --key`
+    /// checks, before writing anything
+    #[arg(long, value_name = "KEY")]
+    verify_key: Option<PathBuf>,
+    /// Talk to the registry over HTTP instead of HTTPS
+    #[arg(long)]
+    plain_http: bool,
+}
+
+impl PullArgs {
+    pub fn run(self, _ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let key = self
+            .verify_key
+            .as_deref()
+            .map(sign::verifying_key)
+            .transpose()?;
+        let mut client = Client::new(&self.reference, Access::Pull, self.plain_http);
+        let bytes = client.manifest(self.reference.target())?;
+        if let Some(pinned) = &self.reference.digest {
+            let want: Digest = pinned.parse().map_err(anyhow::Error::msg)?;
+            if want.algorithm.digest(&bytes) != want {
+                bail!(Coded::new(
+                    ErrorCode::Registry,
+                    format!("manifest for {} does not match its digest", self.reference)
+                ));
+            }
+        }
+        let manifest: Manifest = serde_json::from_slice(&bytes)
+            .with_context(|| format!("{}: not an OCI image manifest", self.reference))?;
+        if manifest.artifact_type.as_deref() != Some(registry::ARTIFACT_TYPE) {
+            bail!(Coded::new(
+                ErrorCode::UnknownFormat,
+                format!(
+                    "{} is not a lowell UKI (artifact type {})",
+                    self.reference,
+                    manifest.artifact_type.as_deref().unwrap_or("none")
+                )
+            ));
+        }
+        let digest = Algorithm::Sha256.digest(&bytes).to_string();
+        if let Some(key) = &key {
+            check_signature(&mut client, key, &digest).with_context(|| {
+                format!("{} ({digest}) is not signed by the key", self.reference)
+            })?;
+            info!(reference = %self.reference, %digest, "signature verified");
+        }
+        // Check every name before writing anything.
+        let mut files = Vec::new();
+        for layer in &manifest.layers {
+            let title = layer.title().unwrap_or_default();
+            if title.is_empty() || Path::new(title).file_name() != Some(title.as_ref()) {
+                bail!(Coded::new(
+                    ErrorCode::UnsafePath,
+                    format!("layer {} has no usable title ({title:?})", layer.digest)
+                ));
+            }
+            files.push((layer, self.out_dir.join(title)));
+        }
+        std::fs::create_dir_all(&self.out_dir)
+            .with_context(|| format!("create {}", self.out_dir.display()))?;
+        for (layer, dest) in files {
+            let data = client.blob(layer)?;
+            output::write_atomic(&dest, &data)?;
+            info!(path = %dest.display(), size = data.len(), media_type = %layer.media_type, "pulled");
+            writeln!(out, "{}", dest.display())?;
+        }
+        info!(reference = %self.reference, %digest, "pulled UKI");
+        Ok(())
+    }
+}
+
+/// Pass if one layer under manifest `digest`'s signature tag is a payload
+/// naming `digest`, signed by `key`.
+fn check_signature(client: &mut Client, key: &VerifyingKey, digest: &str) -> Result<()> {
+    let tag = sign::sig_tag(digest);
+    let bytes = match client.manifest(&tag) {
+        Err(e) if crate::error::code_of(&e) == ErrorCode::NotFound => {
+            bail!(Coded::new(
+                ErrorCode::BadSignature,
+                format!("no signature ({tag} not found)")
+            ))
+        }
+        r => r?,
+    };
+    let manifest: Manifest =
+        serde_json::from_slice(&bytes).with_context(|| format!("{tag}: not an image manifest"))?;
+    for layer in &manifest.layers {
+        let Some(signature) = layer.annotations.get(sign::SIGNATURE_ANNOTATION) else {
+            continue;
+        };
+        if layer.media_type != sign::SIMPLE_SIGNING || layer.size > 1 << 20 {
+            continue;
+        }
+        let payload = client.blob(layer)?;
+        if sign::verify(key, &payload, signature).is_err() {
+            continue;
+        }
+        match serde_json::from_slice::<Payload>(&payload) {
+            Ok(p) if p.covers(digest) => return Ok(()),
+            _ => warn!(layer = %layer.digest, "signed payload is for another manifest"),
+        }
+    }
+    bail!(Coded::new(
+        ErrorCode::BadSignature,
+        format!(
+            "none of the {} signatures under {tag} match",
+            manifest.layers.len()
+        )
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::push::PushArgs;
+    use crate::config::Config;
+    use crate::registry::mock;
+    use crate::style::Palette;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Push {
+        #[command(flatten)]
+        args: PushArgs,
+    }
+
+    #[derive(Parser)]
+    struct Pull {
+        #[command(flatten)]
+        args: PullArgs,
+    }
+
+    #[test]
+    fn pushed_ukis_pull_back() {
+        let (host, state) = mock::serve();
+        let dir = tempfile::tempdir().unwrap();
+        let uki = dir.path().join("fedora.efi");
+        let image = lowell_test_util::UkiBuilder::new()
+            .osrel("ID=fedora\nVERSION_ID=41\n")
+            .cmdline("quiet")
+            .linux(&[0xAA; 4096])
+            .initrd(&lowell_test_util::newc(&[(
+                "init",
+                0o100755,
+                b"#!/bin/sh\n",
+            )]))
+            .build();
+        std::fs::write(&uki, &image).unwrap();
+        let sbom = dir.path().join("sbom.spdx.json");
+        std::fs::write(&sbom, br#"{"spdxVersion":"SPDX-2.3","packages":[]}"#).unwrap();
+        let ctx = Ctx {
+            cfg: Config::default(),
+            palette: Palette::new(false),
+            porcelain: true,
+        };
+
+        let reference = format!("{host}/os/uki:41");
+        let push = |extra: &[&str]| {
+            let mut argv = vec!["push", uki.to_str().unwrap(), &reference, "--plain-http"];
+            argv.extend(extra);
+            let mut out = Vec::new();
+            Push::parse_from(argv).args.run(&ctx, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let pushed = push(&["--sbom", sbom.to_str().unwrap()]);
+        assert!(pushed.starts_with(&format!("{reference}@sha256:")));
+        assert_eq!(state.lock().unwrap().uploads, 4);
+        // Blobs the registry has are not sent again.
+        push(&[]);
+        assert_eq!(state.lock().unwrap().uploads, 4);
+
+        let out_dir = dir.path().join("pulled");
+        let pinned = format!(
+            "{host}/os/uki@{}",
+            pushed.trim().rsplit_once('@').unwrap().1
+        );
+        let mut out = Vec::new();
+        let argv = [
+            "pull",
+            &pinned,
+            "--plain-http",
+            "--out-dir",
+            out_dir.to_str().unwrap(),
+        ];
+        Pull::parse_from(argv).args.run(&ctx, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+        assert_eq!(std::fs::read(out_dir.join("fedora.efi")).unwrap(), image);
+        let report: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(out_dir.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["cmdline"], "quiet");
+        assert!(out_dir.join("sbom.spdx.json").is_file());
+
+        // Unsigned artifacts fail --verify-key; signed ones pass.
+        let (private, public) = crate::sign::tests::key_pair(dir.path());
+        let verified = |reference: &str| {
+            let argv = [
+                "pull",
+                reference,
+                "--plain-http",
+                "--verify-key",
+                public.to_str().unwrap(),
+                "--out-dir",
+                out_dir.to_str().unwrap(),
+            ];
+            Pull::parse_from(argv).args.run(&ctx, &mut Vec::new())
+        };
+        let err = verified(&reference).unwrap_err();
+        assert_eq!(crate::error::code_of(&err), ErrorCode::BadSignature);
+        let signed = push(&["--sign-key", private.to_str().unwrap()]);
+        let tag = crate::sign::sig_tag(signed.trim().rsplit_once('@').unwrap().1);
+        assert!(state
+            .lock()
+            .unwrap()
+            .manifests
+            .keys()
+            .any(|k| k.ends_with(&tag)));
+        verified(&reference).unwrap();
+
+        let missing = format!("{host}/os/uki:42");
+        let err = Pull::parse_from(["pull", &missing, "--plain-http"])
+            .args
+            .run(&ctx, &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(crate::error::code_of(&err), ErrorCode::NotFound);
+        assert!(format!("{err:#}").contains("MANIFEST_UNKNOWN"), "{err:#}");
+    }
+}
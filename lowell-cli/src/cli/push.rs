@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell push`: store a UKI, its report and an SBOM as an OCI artifact.
+
+use crate::cli::Ctx;
+use crate::error::Coded;
+use crate::provenance;
+use crate::registry::reference::Reference;
+use crate::registry::{self, Access, Client, Descriptor, Manifest};
+use crate::sign::{self, Payload};
+use anyhow::{bail, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::Args;
+use lowell_core::digest::Algorithm;
+use lowell_core::error::ErrorCode;
+use lowell_core::uki::inspect::{self, InspectOptions};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct PushArgs {
+    /// The UKI to push
+    file: PathBuf,
+    /// Where to push it: `registry/repository:tag`
+    #[arg(value_name = "REF")]
+    reference: Reference,
+    /// Attach this SBOM (SPDX or CycloneDX JSON)
+    #[arg(long, value_name = "FILE")]
+    sbom: Option<PathBuf>,
+    /// Attach this provenance (`build --provenance` output); its subject
+    /// must be the UKI
+    #[arg(long, value_name = "FILE")]
+    attestation: Option<PathBuf>,
+    /// Add an annotation to the manifest; repeat for several
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_annotation)]
+    annotation: Vec<(String, String)>,
+    /// Sign the pushed manifest as `cosign sign --key` would
+    #[arg(long, value_name = "KEY")]
+    sign_key: Option<PathBuf>,
+    /// `--sign-key` with `signing-key` from config
+    #[arg(long, conflicts_with = "sign_key")]
+    sign: bool,
+    /// Talk to the registry over HTTP instead of HTTPS
+    #[arg(long)]
+    plain_http: bool,
+}
+
+fn parse_annotation(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {s:?}")),
+    }
+}
+
+impl PushArgs {
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        if self.reference.digest.is_some() {
+            bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                format!("{}: push to a tag, not a digest", self.reference)
+            ));
+        }
+        // Load the key before uploading anything a bad password would waste.
+        let key = sign::requested_key(self.sign_key.as_deref(), self.sign, &ctx.cfg)?;
+        // Inspecting first also refuses files that are not UKIs.
+        let report = inspect::inspect(InspectOptions::new(&self.file).count_entries(true))?;
+        let uki =
+            std::fs::read(&self.file).with_context(|| format!("read {}", self.file.display()))?;
+        let mut blobs = vec![
+            (
+                Descriptor::of(registry::UKI_LAYER, &uki, Some(&title(&self.file)?)),
+                uki,
+            ),
+            {
+                let json = serde_json::to_vec_pretty(&report)?;
+                (
+                    Descriptor::of(registry::REPORT_LAYER, &json, Some("report.json")),
+                    json,
+                )
+            },
+        ];
+        if let Some(sbom) = &self.sbom {
+            let data = std::fs::read(sbom).with_context(|| format!("read {}", sbom.display()))?;
+            let media_type = sbom_type(&data)
+                .with_context(|| format!("{} is not SPDX or CycloneDX JSON", sbom.display()))?;
+            blobs.push((Descriptor::of(media_type, &data, Some(&title(sbom)?)), data));
+        }
+
+        if let Some(path) = &self.attestation {
+            let data = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+            let media_type = attestation_type(&data, &Algorithm::Sha256.digest(&blobs[0].1).hex)
+                .with_context(|| {
+                    format!(
+                        "{} is not an in-toto statement about {}",
+                        path.display(),
+                        self.file.display()
+                    )
+                })?;
+            blobs.push((Descriptor::of(media_type, &data, Some(&title(path)?)), data));
+        }
+
+        let mut annotations = BTreeMap::from([("vnd.lowell.uki.arch".into(), report.arch.clone())]);
+        if let Some(os) = &report.os_release {
+            annotations.extend(os.id.clone().map(|v| ("vnd.lowell.uki.os-id".into(), v)));
+            annotations.extend(
+                os.version_id
+                    .clone()
+                    .map(|v| ("vnd.lowell.uki.os-version-id".into(), v)),
+            );
+        }
+        annotations.extend(self.annotation.iter().cloned());
+        let config = Descriptor::of(registry::EMPTY_CONFIG, b"{}", None);
+        let manifest = Manifest {
+            schema_version: 2,
+            media_type: registry::MANIFEST.into(),
+            artifact_type: Some(registry::ARTIFACT_TYPE.into()),
+            config: config.clone(),
+            layers: blobs.iter().map(|(d, _)| d.clone()).collect(),
+            annotations,
+        };
+
+        let mut client = Client::new(&self.reference, Access::Push, self.plain_http);
+        blobs.insert(0, (config, b"{}".to_vec()));
+        push_blobs(&mut client, &blobs)?;
+        let digest =
+            client.put_manifest(self.reference.target(), &serde_json::to_vec(&manifest)?)?;
+        info!(reference = %self.reference, %digest, "pushed UKI");
+        if let Some(key) = &key {
+            self.push_signature(&mut client, key, &digest)?;
+        }
+        writeln!(out, "{}@{digest}", self.reference)?;
+        Ok(())
+    }
+}
+
+impl PushArgs {
+    /// Store a signature for manifest `digest` where `cosign verify` looks:
+    /// a simple-signing payload under the tag `sha256-<hex>.sig`.
+    fn push_signature(
+        &self,
+        client: &mut Client,
+        key: &p256::ecdsa::SigningKey,
+        digest: &str,
+    ) -> Result<()> {
+        let repository = format!("{}/{}", self.reference.registry, self.reference.repository);
+        let payload = serde_json::to_vec(&Payload::new(&repository, digest))?;
+        let mut layer = Descriptor::of(sign::SIMPLE_SIGNING, &payload, None);
+        layer
+            .annotations
+            .insert(sign::SIGNATURE_ANNOTATION.into(), sign::sign(key, &payload));
+        let config = Payload::config(&layer.digest);
+        let manifest = Manifest {
+            schema_version: 2,
+            media_type: registry::MANIFEST.into(),
+            artifact_type: None,
+            config: Descriptor::of(sign::IMAGE_CONFIG, &config, None),
+            layers: vec![layer.clone()],
+            annotations: BTreeMap::new(),
+        };
+        push_blobs(
+            client,
+            &[(manifest.config.clone(), config), (layer, payload)],
+        )?;
+        let tag = sign::sig_tag(digest);
+        client.put_manifest(&tag, &serde_json::to_vec(&manifest)?)?;
+        info!(reference = %self.reference, %tag, "pushed signature");
+        Ok(())
+    }
+}
+
+/// Upload whichever of `blobs` the repository lacks.
+fn push_blobs(client: &mut Client, blobs: &[(Descriptor, Vec<u8>)]) -> Result<()> {
+    for (desc, data) in blobs {
+        if client.has_blob(&desc.digest)? {
+            info!(digest = %desc.digest, title = desc.title(), "blob exists");
+            continue;
+        }
+        client.push_blob(desc, data)?;
+        info!(digest = %desc.digest, title = desc.title(), size = desc.size, "pushed blob");
+    }
+    Ok(())
+}
+
+/// A layer title: the file's name.
+fn title(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(str::to_string)
+        .with_context(|| format!("{}: no usable file name", path.display()))
+}
+
+/// The media type of an in-toto statement, bare or in a DSSE envelope,
+/// if one of its subjects has SHA-256 `hex`.
+fn attestation_type(data: &[u8], hex: &str) -> Option<&'static str> {
+    let doc: serde_json::Value = serde_json::from_slice(data).ok()?;
+    let (media_type, statement) = match doc.get("payloadType") {
+        Some(t) if t == provenance::IN_TOTO => {
+            let payload = BASE64_STANDARD.decode(doc["payload"].as_str()?).ok()?;
+            (registry::DSSE, serde_json::from_slice(&payload).ok()?)
+        }
+        Some(_) => return None,
+        None => (provenance::IN_TOTO, doc),
+    };
+    let about = statement["subject"]
+        .as_array()?
+        .iter()
+        .any(|s| s["digest"]["sha256"] == hex);
+    (statement["_type"] == provenance::STATEMENT_TYPE && about).then_some(media_type)
+}
+
+/// SPDX and CycloneDX JSON, told apart by their required top-level keys.
+fn sbom_type(data: &[u8]) -> Option<&'static str> {
+    let doc: serde_json::Value = serde_json::from_slice(data).ok()?;
+    if doc.get("spdxVersion").is_some() {
+        Some(registry::SPDX)
+    } else if doc.get("bomFormat").and_then(|f| f.as_str()) == Some("CycloneDX") {
+        Some(registry::CYCLONEDX)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sign::Envelope;
+
+    #[test]
+    fn attestations_must_be_about_the_uki() {
+        let hex = Algorithm::Sha256.digest(b"uki").hex;
+        let statement = serde_json::to_vec(&serde_json::json!({
+            "_type": provenance::STATEMENT_TYPE,
+            "subject": [{"name": "uki.efi", "digest": {"sha256": hex}}],
+            "predicateType": provenance::PREDICATE_TYPE,
+            "predicate": {},
+        }))
+        .unwrap();
+        assert_eq!(
+            attestation_type(&statement, &hex),
+            Some(provenance::IN_TOTO)
+        );
+        assert_eq!(attestation_type(&statement, "00"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (private, _) = crate::sign::tests::key_pair(dir.path());
+        let key = crate::sign::signing_key(&private).unwrap();
+        let envelope = Envelope::seal(&key, provenance::IN_TOTO, &statement);
+        let envelope = serde_json::to_vec(&envelope).unwrap();
+        assert_eq!(attestation_type(&envelope, &hex), Some(registry::DSSE));
+        assert_eq!(attestation_type(b"{}", &hex), None);
+    }
+}
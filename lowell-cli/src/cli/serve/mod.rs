@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell serve`: inspect, build and install over varlink, for agents
+//! that would otherwise spawn `lowell` and scrape its output.
+//!
+//! Each connection gets its own thread; calls on one connection run in
+//! order. A call with `more` gets `Progress` replies (phase starts and
+//! ends, and every few MiB) before its result. Varlink runs over UNIX
+//! sockets, so elsewhere `lowell serve` only reports that.
+
+#[cfg(unix)]
+mod server;
+
+use crate::cli::Ctx;
+use anyhow::Result;
+use clap::Args;
+use std::path::PathBuf;
+
+#[cfg(unix)]
+pub const INTERFACE: &str = "io.lowell";
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Socket to listen on, unless the service manager passes one (socket
+    /// activation) [default: $XDG_RUNTIME_DIR/io.lowell, else
+    /// /run/io.lowell]
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+}
+
+impl ServeArgs {
+    #[cfg(unix)]
+    pub fn run(self, ctx: &Ctx) -> Result<()> {
+        let socket = self.socket.unwrap_or_else(|| {
+            std::env::var_os("XDG_RUNTIME_DIR")
+                .map_or_else(|| PathBuf::from("/run"), PathBuf::from)
+                .join(INTERFACE)
+        });
+        server::serve(crate::varlink::listen(&socket)?, ctx)
+    }
+
+    #[cfg(not(unix))]
+    pub fn run(self, _ctx: &Ctx) -> Result<()> {
+        anyhow::bail!("lowell serve needs UNIX sockets")
+    }
+}
@@ -0,0 +1,372 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! The varlink server behind `lowell serve`.
+
+use super::INTERFACE;
+use crate::cli::Ctx;
+use crate::error::code_of;
+use crate::esp::{self, Dir, Installed};
+use crate::output;
+use crate::varlink::{self, Call, Connection};
+use anyhow::{Context, Result};
+use lowell_core::cancel::CancelToken;
+use lowell_core::progress::{Phase, ProgressSink};
+use lowell_core::uki::inspect::{self, InspectOptions};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, error, info};
+
+const DESCRIPTION: &str = "\
+# lowell: inspect, build and install Unified Kernel Images.
+interface io.lowell
+
+# How far a call has got: sent, with continues, to calls made with more.
+type Progress (
+  phase: string,
+  done: int,
+  total: ?int
+)
+
+# What installing one file did: installed, updated, current or kept.
+type Installed (
+  path: string,
+  action: string,
+  from: ?string,
+  reason: ?string
+)
+
+# Inspect a UKI: the report `lowell uki inspect --format json` prints.
+method Inspect(path: string) -> (progress: ?Progress, report: ?object)
+
+# Build a UKI from a profile (a path, builtin:<name> or a name on the
+# profile path) and write it to out.
+method Build(profile: string, out: string, cacheDir: ?string) -> (
+  progress: ?Progress,
+  size: ?int
+)
+
+# Install a UKI as EFI/Linux/<name>.efi (name: the file stem by default)
+# on the ESP (esp: from config, else /efi, /boot/efi or /boot).
+method Install(path: string, esp: ?string, name: ?string) -> (installed: []Installed)
+
+# The operation failed; code is one of lowell's E_* error codes.
+error Failed (code: string, message: string)
+";
+
+/// Bytes between two progress replies within a phase.
+const PROGRESS_STEP: u64 = 4 << 20;
+
+pub(super) fn serve(listener: UnixListener, ctx: &Ctx) -> Result<()> {
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = stream.context("accept")?;
+            scope.spawn(move || {
+                if let Err(e) = connection(stream, ctx) {
+                    error!("varlink connection: {e:#}");
+                }
+            });
+        }
+        Ok(())
+    })
+}
+
+fn connection(stream: UnixStream, ctx: &Ctx) -> Result<()> {
+    let mut conn = Connection::new(stream)?;
+    while let Some(call) = conn.read_call()? {
+        debug!(method = call.method, more = call.more, "varlink call");
+        let progress = match call.more && !call.oneway {
+            true => Some(conn.writer()?),
+            false => None,
+        };
+        let reply = dispatch(&call, ctx, progress);
+        if call.oneway {
+            continue;
+        }
+        match reply {
+            Ok(parameters) => conn.reply(parameters)?,
+            Err(Reply::Error(name, parameters)) => conn.error(&name, parameters)?,
+            Err(Reply::Failed(e)) => {
+                info!(method = call.method, "call failed: {e:#}");
+                let parameters = json!({
+                    "code": code_of(&e).as_str(),
+                    "message": format!("{e:#}"),
+                });
+                conn.error(&format!("{INTERFACE}.Failed"), parameters)?
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Why a call has no result.
+enum Reply {
+    /// A protocol error (unknown method, bad parameters).
+    Error(String, Value),
+    /// The operation itself failed.
+    Failed(anyhow::Error),
+}
+
+impl From<anyhow::Error> for Reply {
+    fn from(e: anyhow::Error) -> Self {
+        Reply::Failed(e)
+    }
+}
+
+fn dispatch(call: &Call, ctx: &Ctx, progress: Option<UnixStream>) -> Result<Value, Reply> {
+    let service = |error: &str, parameters: Value| {
+        Reply::Error(format!("{}.{error}", varlink::SERVICE), parameters)
+    };
+    match call.split() {
+        (varlink::SERVICE, "GetInfo") => Ok(varlink::service_info(&[INTERFACE])),
+        (varlink::SERVICE, "GetInterfaceDescription") => {
+            #[derive(Deserialize)]
+            struct Params {
+                interface: String,
+            }
+            let p: Params = params(call)?;
+            match p.interface.as_str() {
+                varlink::SERVICE => Ok(json!({ "description": varlink::SERVICE_DESCRIPTION })),
+                INTERFACE => Ok(json!({ "description": DESCRIPTION })),
+                other => Err(service("InterfaceNotFound", json!({ "interface": other }))),
+            }
+        }
+        (INTERFACE, "Inspect") => {
+            #[derive(Deserialize)]
+            #[serde(deny_unknown_fields)]
+            struct Params {
+                path: PathBuf,
+            }
+            let p: Params = params(call)?;
+            let mut opts = InspectOptions::new(&p.path);
+            if let Some(stream) = progress {
+                opts = opts.progress(Arc::new(Progress::new(stream)));
+            }
+            let report = inspect::inspect(opts).map_err(anyhow::Error::from)?;
+            Ok(json!({ "report": report }))
+        }
+        (INTERFACE, "Build") => {
+            #[derive(Deserialize)]
+            #[serde(rename_all = "camelCase", deny_unknown_fields)]
+            struct Params {
+                profile: String,
+                out: PathBuf,
+                #[serde(default)]
+                cache_dir: Option<PathBuf>,
+            }
+            let p: Params = params(call)?;
+            let (_, profile, inputs) =
+                crate::cli::build::load(&p.profile, p.cache_dir.as_deref(), None, ctx)?;
+            let image = match progress {
+                Some(stream) => inputs.build_with(&Progress::new(stream), &CancelToken::new()),
+                None => inputs.build(),
+            }
+            .map_err(anyhow::Error::from)?;
+            output::write_atomic(&p.out, &image)?;
+            info!(out = %p.out.display(), profile = %profile.name, "built UKI");
+            Ok(json!({ "size": image.len() }))
+        }
+        (INTERFACE, "Install") => {
+            #[derive(Deserialize)]
+            #[serde(deny_unknown_fields)]
+            struct Params {
+                path: PathBuf,
+                #[serde(default)]
+                esp: Option<PathBuf>,
+                #[serde(default)]
+                name: Option<String>,
+            }
+            let p: Params = params(call)?;
+            let dir = esp::find(p.esp.as_deref().or(ctx.cfg.esp.as_deref()))?;
+            let name = match p.name {
+                Some(name) => name,
+                None => p
+                    .path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .context("UKI path has no file name; pass name")?,
+            };
+            let installed: Installed = esp::install_uki(&mut Dir(dir), &p.path, &name)?;
+            Ok(json!({ "installed": [installed] }))
+        }
+        (INTERFACE, _) | (varlink::SERVICE, _) => {
+            Err(service("MethodNotFound", json!({ "method": call.method })))
+        }
+        (interface, _) => Err(service(
+            "InterfaceNotFound",
+            json!({ "interface": interface }),
+        )),
+    }
+}
+
+/// `call`'s parameters as `T`, or `InvalidParameter` naming the culprit.
+fn params<T: DeserializeOwned>(call: &Call) -> Result<T, Reply> {
+    serde_json::from_value(Value::Object(call.parameters.clone())).map_err(|e| {
+        // serde names the field in backticks: "missing field `path`".
+        let text = e.to_string();
+        let parameter = text.split('`').nth(1).unwrap_or("parameters").to_string();
+        Reply::Error(
+            format!("{}.InvalidParameter", varlink::SERVICE),
+            json!({ "parameter": parameter }),
+        )
+    })
+}
+
+/// Sends `Progress` replies to a call made with `more`.
+struct Progress {
+    stream: Mutex<(UnixStream, u64)>,
+}
+
+impl Progress {
+    fn new(stream: UnixStream) -> Self {
+        Self {
+            stream: Mutex::new((stream, 0)),
+        }
+    }
+
+    fn send(&self, phase: Phase, done: u64, total: Option<u64>) {
+        let mut guard = self.stream.lock().unwrap();
+        guard.1 = done;
+        let progress = json!({
+            "progress": {"phase": phase.to_string(), "done": done, "total": total},
+        });
+        // A client that went away finds out from the final reply.
+        if let Err(e) = varlink::send_continues(&mut guard.0, progress) {
+            debug!("progress reply: {e:#}");
+        }
+    }
+}
+
+impl ProgressSink for Progress {
+    fn phase_started(&self, phase: Phase, total: Option<u64>) {
+        self.send(phase, 0, total);
+    }
+
+    fn bytes_processed(&self, phase: Phase, done: u64) {
+        let last = self.stream.lock().unwrap().1;
+        if done >= last + PROGRESS_STEP {
+            self.send(phase, done, None);
+        }
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        let done = self.stream.lock().unwrap().1;
+        self.send(phase, done, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::style::Palette;
+    use std::io::{BufRead, BufReader, Write};
+
+    struct Client(BufReader<UnixStream>);
+
+    impl Client {
+        /// Every reply to `call`.
+        fn call(&mut self, call: Value) -> Vec<Value> {
+            let mut bytes = serde_json::to_vec(&call).unwrap();
+            bytes.push(0);
+            self.0.get_mut().write_all(&bytes).unwrap();
+            let mut replies = Vec::new();
+            loop {
+                let mut buf = Vec::new();
+                self.0.read_until(0, &mut buf).unwrap();
+                buf.pop();
+                let reply: Value = serde_json::from_slice(&buf).unwrap();
+                let more = reply["continues"] == true;
+                replies.push(reply);
+                if !more {
+                    return replies;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn agents_build_inspect_and_install_over_varlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("io.lowell");
+        let listener = varlink::listen(&socket).unwrap();
+        std::thread::spawn(move || {
+            let ctx = Ctx {
+                cfg: Config::default(),
+                palette: Palette::new(false),
+                porcelain: true,
+            };
+            serve(listener, &ctx)
+        });
+        let mut client = Client(BufReader::new(UnixStream::connect(&socket).unwrap()));
+
+        let info = client.call(json!({"method": "org.varlink.service.GetInfo"}));
+        assert_eq!(info[0]["parameters"]["interfaces"][1], INTERFACE);
+        let desc = client.call(json!({
+            "method": "org.varlink.service.GetInterfaceDescription",
+            "parameters": {"interface": "io.lowell"},
+        }));
+        let text = desc[0]["parameters"]["description"].as_str().unwrap();
+        assert!(text.starts_with("# lowell") && text.contains("method Build("));
+
+        let put = |name: &str, data: &[u8]| std::fs::write(dir.path().join(name), data).unwrap();
+        put("stub.efi", &lowell_test_util::stub());
+        put("vmlinuz", &[0xAA; 4096]);
+        put(
+            "initrd",
+            &lowell_test_util::newc(&[("init", 0o100755, b"#!/bin/sh\n")]),
+        );
+        put(
+            "p.toml",
+            b"name = \"p\"\nroot = \"ostree\"\ncmdline = \"quiet\"\n\n[artifacts]\n\
+              stub = \"stub.efi\"\nkernel = \"vmlinuz\"\ninitrd = \"initrd\"\n",
+        );
+        let uki = dir.path().join("p.efi");
+        let built = client.call(json!({
+            "method": "io.lowell.Build",
+            "parameters": {"profile": dir.path().join("p.toml"), "out": uki},
+            "more": true,
+        }));
+        let (last, progress) = built.split_last().unwrap();
+        assert!(
+            last["parameters"]["size"].as_u64().unwrap() > 4096,
+            "{last}"
+        );
+        assert!(progress
+            .iter()
+            .any(|r| r["parameters"]["progress"]["phase"] == "assemble"));
+
+        let inspected = client.call(json!({
+            "method": "io.lowell.Inspect",
+            "parameters": {"path": uki},
+        }));
+        assert_eq!(inspected.len(), 1);
+        let report = &inspected[0]["parameters"]["report"];
+        assert!(report.to_string().contains("quiet"), "{report}");
+
+        let esp = dir.path().join("esp");
+        std::fs::create_dir(&esp).unwrap();
+        let installed = client.call(json!({
+            "method": "io.lowell.Install",
+            "parameters": {"path": uki, "esp": esp},
+        }));
+        let first = &installed[0]["parameters"]["installed"][0];
+        assert_eq!(first["path"], "EFI/Linux/p.efi");
+        assert_eq!(first["action"], "installed");
+        assert!(esp.join("EFI/Linux/p.efi").is_file());
+
+        let failed = client.call(json!({
+            "method": "io.lowell.Inspect",
+            "parameters": {"path": dir.path().join("missing.efi")},
+        }));
+        assert_eq!(failed[0]["error"], "io.lowell.Failed");
+        assert_eq!(failed[0]["parameters"]["code"], "E_NOT_FOUND");
+        let bad = client.call(json!({"method": "io.lowell.Install", "parameters": {}}));
+        assert_eq!(bad[0]["error"], "org.varlink.service.InvalidParameter");
+        assert_eq!(bad[0]["parameters"]["parameter"], "path");
+        let unknown = client.call(json!({"method": "io.lowell.Frobnicate"}));
+        assert_eq!(unknown[0]["error"], "org.varlink.service.MethodNotFound");
+    }
+}
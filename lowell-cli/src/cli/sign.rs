@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cli::Ctx;
+use crate::error::Coded;
+use crate::{output, sign};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use lowell_core::error::ErrorCode;
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct SignArgs {
+    /// Files to sign (UKIs, initramfs images, reports, …)
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+    /// Private key: PKCS#8 or SEC1 PEM, or cosign's encrypted key
+    /// (password in `COSIGN_PASSWORD`; default: `signing-key` from config)
+    #[arg(long, value_name = "FILE")]
+    key: Option<PathBuf>,
+    /// Write the signature here instead of `<FILE>.sig` (one FILE only)
+    #[arg(long, value_name = "SIG")]
+    output_signature: Option<PathBuf>,
+}
+
+impl SignArgs {
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        if self.output_signature.is_some() && self.files.len() > 1 {
+            bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                "--output-signature takes a single FILE"
+            ));
+        }
+        let Some(key) = self.key.as_deref().or(ctx.cfg.signing_key.as_deref()) else {
+            bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                "no --key given and no `signing-key` in config"
+            ));
+        };
+        let key = sign::signing_key(key)?;
+        for file in &self.files {
+            let data = std::fs::read(file).with_context(|| format!("read {}", file.display()))?;
+            let dest = match &self.output_signature {
+                Some(path) => path.clone(),
+                None => sign::sig_path(file),
+            };
+            output::write_atomic(&dest, sign::sign(&key, &data).as_bytes())?;
+            info!(path = %file.display(), signature = %dest.display(), "signed");
+            writeln!(out, "{}", dest.display())?;
+        }
+        Ok(())
+    }
+}
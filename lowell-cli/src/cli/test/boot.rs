@@ -0,0 +1,324 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell test boot`: boot a UKI in QEMU and report pass/fail.
+
+use crate::cli::{Ctx, ReportFormat};
+use crate::error::Coded;
+use crate::esp;
+use crate::qemu::{self, Firmware, Outcome, Plan, Swtpm};
+use crate::style::Palette;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::pe::PeFile;
+use serde::Serialize;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+#[derive(Args, Debug)]
+pub struct BootArgs {
+    /// UKI to boot
+    #[arg(long, value_name = "FILE")]
+    uki: PathBuf,
+    /// OVMF code image (default: the distribution's, by architecture)
+    #[arg(long, value_name = "FILE", requires = "ovmf_vars")]
+    ovmf: Option<PathBuf>,
+    /// OVMF variable store to start from (copied; never modified). For
+    /// Secure Boot it must have the keys that signed the UKI enrolled
+    #[arg(long, value_name = "FILE", requires = "ovmf")]
+    ovmf_vars: Option<PathBuf>,
+    /// Boot with Secure Boot enforced (SMM, the `secboot` OVMF build and,
+    /// by default, a variable store with Microsoft's keys)
+    #[arg(long)]
+    secure_boot: bool,
+    /// Attach a TPM 2.0 emulated by swtpm
+    #[arg(long)]
+    tpm: bool,
+    /// Console text that means success (repeatable; any one passes)
+    #[arg(long, value_name = "TEXT", default_value = "login:")]
+    expect: Vec<String>,
+    /// Console text that means failure (repeatable; default: kernel panics
+    /// and firmware refusing the image)
+    #[arg(long, value_name = "TEXT")]
+    fail: Vec<String>,
+    /// Give up after this many seconds
+    #[arg(long, value_name = "SECS", default_value_t = 300)]
+    timeout: u64,
+    /// Guest memory in MiB
+    #[arg(long, value_name = "MIB", default_value_t = 2048)]
+    memory: u32,
+    /// Save everything the serial console printed to FILE
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+    /// QEMU binary (default: qemu-system-x86_64 or -aarch64, by the UKI's
+    /// architecture)
+    #[arg(long, value_name = "PATH")]
+    qemu: Option<PathBuf>,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+    /// Extra QEMU arguments, after `--`
+    #[arg(last = true, value_name = "QEMU_ARGS")]
+    qemu_args: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    uki: PathBuf,
+    arch: &'static str,
+    secure_boot: bool,
+    tpm: bool,
+    passed: bool,
+    #[serde(flatten)]
+    outcome: Outcome,
+    elapsed_ms: u128,
+}
+
+impl BootArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let pe = PeFile::from_path_mmap(&self.uki)
+            .with_context(|| format!("{}: not a UKI", self.uki.display()))?;
+        let arch = esp::efi_arch(&pe)?;
+        let firmware = match (&self.ovmf, &self.ovmf_vars) {
+            (Some(code), Some(vars)) => Firmware {
+                code: code.clone(),
+                vars: vars.clone(),
+            },
+            _ => Firmware::find(arch, self.secure_boot)?,
+        };
+        let qemu = match &self.qemu {
+            Some(path) => path.clone(),
+            None => Plan::default_qemu(arch)?.into(),
+        };
+
+        // The ESP, variable store and TPM state live only for this boot.
+        let scratch = tempfile::tempdir()?;
+        let esp = scratch.path().join("esp");
+        let boot = esp.join("EFI/BOOT");
+        std::fs::create_dir_all(&boot)?;
+        let loader = boot.join(format!("BOOT{}.EFI", arch.to_uppercase()));
+        std::fs::copy(&self.uki, &loader)
+            .with_context(|| format!("copy {}", self.uki.display()))?;
+        let vars = scratch.path().join("vars.fd");
+        std::fs::copy(&firmware.vars, &vars)
+            .with_context(|| format!("copy {}", firmware.vars.display()))?;
+        let tpm = match self.tpm {
+            true => {
+                let dir = scratch.path().join("tpm");
+                std::fs::create_dir(&dir)?;
+                Some(Swtpm::start(&dir)?)
+            }
+            false => None,
+        };
+
+        let plan = Plan {
+            qemu,
+            arch,
+            firmware,
+            secure_boot: self.secure_boot,
+            memory_mib: self.memory,
+            kvm: qemu::kvm_usable(arch),
+            extra: self.qemu_args.clone(),
+        };
+        let args = plan.args(&esp, &vars, tpm.as_ref().map(|t| t.socket.as_path()));
+        debug!(qemu = %plan.qemu.display(), ?args, "boot");
+        info!(uki = %self.uki.display(), kvm = plan.kvm, "booting in QEMU");
+        let started = Instant::now();
+        let mut child = Command::new(&plan.qemu)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("start {}", plan.qemu.display()))?;
+        let mut stderr = child.stderr.take().expect("piped");
+        let errors = std::thread::spawn(move || {
+            let mut text = String::new();
+            let _ = stderr.read_to_string(&mut text);
+            text
+        });
+        let mut log: Box<dyn Write> = match &self.log {
+            Some(path) => Box::new(
+                std::fs::File::create(path)
+                    .with_context(|| format!("create {}", path.display()))?,
+            ),
+            None => Box::new(std::io::sink()),
+        };
+        let fail = match self.fail.is_empty() {
+            true => qemu::DEFAULT_FAIL.iter().map(|s| s.to_string()).collect(),
+            false => self.fail.clone(),
+        };
+        let console = child.stdout.take().expect("piped");
+        let watched = qemu::watch(
+            console,
+            &self.expect,
+            &fail,
+            Duration::from_secs(self.timeout),
+            &mut log,
+        );
+        let _ = child.kill();
+        let status = child.wait()?;
+        drop(tpm);
+        let outcome = match watched? {
+            Some(outcome) => outcome,
+            None => Outcome::Exited {
+                status: status.to_string(),
+            },
+        };
+        let report = Report {
+            uki: self.uki.clone(),
+            arch,
+            secure_boot: self.secure_boot,
+            tpm: self.tpm,
+            passed: matches!(outcome, Outcome::Passed { .. }),
+            outcome,
+            elapsed_ms: started.elapsed().as_millis(),
+        };
+        match self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human)
+        {
+            ReportFormat::Human => print_human(out, &report, ctx.palette)?,
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+            ReportFormat::JsonPretty => {
+                serde_json::to_writer_pretty(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        if !report.passed {
+            let mut why = format!(
+                "{} did not boot: {}",
+                self.uki.display(),
+                describe(&report.outcome)
+            );
+            if let Outcome::Exited { .. } = report.outcome {
+                let errors = errors.join().unwrap_or_default();
+                if let Some(last) = errors.lines().rev().find(|l| !l.trim().is_empty()) {
+                    why += &format!(" ({last})");
+                }
+            }
+            bail!(Coded::new(ErrorCode::BootFailed, why));
+        }
+        Ok(())
+    }
+}
+
+fn describe(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Passed { matched } => format!("saw {matched:?}"),
+        Outcome::Failed { matched } => format!("saw {matched:?}"),
+        Outcome::Exited { status } => format!("QEMU exited ({status}) before a success marker"),
+        Outcome::TimedOut => "timed out waiting for a success marker".into(),
+    }
+}
+
+fn print_human(out: &mut dyn Write, r: &Report, palette: Palette) -> Result<()> {
+    let verdict = match r.passed {
+        true => palette.good("PASS").to_string(),
+        false => palette.bad("FAIL").to_string(),
+    };
+    let mut setup = vec![r.arch];
+    if r.secure_boot {
+        setup.push("Secure Boot");
+    }
+    if r.tpm {
+        setup.push("TPM");
+    }
+    writeln!(
+        out,
+        "{verdict} {} [{}]: {} after {:.1}s",
+        r.uki.display(),
+        setup.join(", "),
+        describe(&r.outcome),
+        r.elapsed_ms as f64 / 1000.0
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use clap::Parser;
+    use lowell_test_util::UkiBuilder;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: BootArgs,
+    }
+
+    /// A stand-in for QEMU that prints `console` and then idles.
+    fn fake_qemu(dir: &std::path::Path, console: &str) -> PathBuf {
+        let path = dir.join("qemu");
+        let script = format!("#!/bin/sh\nprintf '{console}'\nsleep 30\n");
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn boots_pass_on_the_marker_and_fail_otherwise() {
+        let dir = tempfile::tempdir().unwrap();
+        let uki = dir.path().join("uki.efi");
+        std::fs::write(&uki, UkiBuilder::new().build()).unwrap();
+        for fw in ["CODE.fd", "VARS.fd"] {
+            std::fs::write(dir.path().join(fw), b"firmware").unwrap();
+        }
+        let ctx = Ctx {
+            cfg: Config::default(),
+            palette: Palette::new(false),
+            porcelain: false,
+        };
+        let run = |qemu: &PathBuf, extra: &[&str]| {
+            let d = dir.path().display();
+            let mut argv = vec![
+                "t".to_string(),
+                format!("--uki={}", uki.display()),
+                format!("--ovmf={d}/CODE.fd"),
+                format!("--ovmf-vars={d}/VARS.fd"),
+                format!("--qemu={}", qemu.display()),
+                format!("--log={d}/console.log"),
+                "--timeout=5".into(),
+            ];
+            argv.extend(extra.iter().map(|s| s.to_string()));
+            let mut out = Vec::new();
+            let r = Cli::parse_from(argv).args.run(&ctx, &mut out);
+            (r, String::from_utf8(out).unwrap())
+        };
+
+        let qemu = fake_qemu(dir.path(), "BdsDxe: loading\\nWelcome\\nhost login: ");
+        let (r, out) = run(&qemu, &[]);
+        r.unwrap();
+        assert!(out.starts_with("PASS "), "{out}");
+        assert!(out.contains("[x64]: saw \"login:\""), "{out}");
+        let log = std::fs::read_to_string(dir.path().join("console.log")).unwrap();
+        assert!(log.contains("Welcome"));
+
+        let (r, out) = run(&qemu, &["--format=json", "--expect=never"]);
+        let err = r.unwrap_err();
+        assert_eq!(crate::error::code_of(&err), ErrorCode::BootFailed);
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json["outcome"], "timed-out");
+        assert_eq!(json["passed"], false);
+
+        let qemu = fake_qemu(dir.path(), "Kernel panic - not syncing\\nlogin:");
+        let (r, out) = run(&qemu, &[]);
+        assert!(r.is_err());
+        assert!(out.contains("saw \"Kernel panic\""), "{out}");
+    }
+}
@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+mod boot;
+mod ukify;
+
+use crate::cli::{Ctx, ReportFormat};
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use std::io::Write;
+
+#[derive(Args, Debug)]
+pub struct TestArgs {
+    #[command(subcommand)]
+    cmd: TestCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum TestCmd {
+    /// Boot a UKI in QEMU (optionally under Secure Boot and with a TPM)
+    /// and pass when its serial console shows a success marker
+    Boot(boot::BootArgs),
+    /// Build a profile's UKI with lowell and with systemd's ukify and
+    /// compare the two section by section
+    Ukify(ukify::UkifyArgs),
+}
+
+impl TestArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        match &self.cmd {
+            TestCmd::Boot(a) => a.json_errors(default),
+            TestCmd::Ukify(a) => a.json_errors(default),
+        }
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        match self.cmd {
+            TestCmd::Boot(a) => a.run(ctx, out),
+            TestCmd::Ukify(a) => a.run(ctx, out),
+        }
+    }
+}
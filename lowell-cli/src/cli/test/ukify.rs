@@ -0,0 +1,383 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell test ukify`: build a profile's UKI with lowell and with
+//! systemd's `ukify` from the same inputs, and compare the two section by
+//! section.
+//!
+//! Sections are matched by name. Each one is compared by its contents
+//! (trimmed to the virtual size, so file-alignment padding does not
+//! count), and the sections both images have must come in the same order.
+//! A section only one side has is a divergence too, unless it is
+//! `--ignore-section`ed: ukify adds `.uname` and `.sbat` on its own, for
+//! example. A directory initrd is packed by lowell, and ukify gets that
+//! archive, so only the assembly is compared.
+
+use crate::cli::{Ctx, ReportFormat};
+use crate::error::Coded;
+use crate::style::Palette;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use lowell_core::digest::Algorithm;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::pe::PeFile;
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use tracing::{debug, info};
+
+#[derive(Args, Debug)]
+pub struct UkifyArgs {
+    /// Profile to build (a path, `builtin:<name>` or a name on the
+    /// profile path)
+    #[arg(long)]
+    profile: String,
+    /// The ukify to compare with
+    #[arg(long, value_name = "PATH", default_value = "ukify")]
+    ukify: PathBuf,
+    /// A section that may be missing from either image (repeatable)
+    #[arg(long, value_name = "NAME")]
+    ignore_section: Vec<String>,
+    /// Keep both images in DIR, as `lowell.efi` and `ukify.efi`
+    #[arg(long, value_name = "DIR")]
+    keep: Option<PathBuf>,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Status {
+    Same,
+    Differs,
+    LowellOnly,
+    UkifyOnly,
+}
+
+#[derive(Debug, Serialize)]
+struct Side {
+    size: usize,
+    digest: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Row {
+    name: String,
+    status: Status,
+    ignored: bool,
+    lowell: Option<Side>,
+    ukify: Option<Side>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    profile: String,
+    conforms: bool,
+    /// The sections both images have are in the same order.
+    same_order: bool,
+    sections: Vec<Row>,
+}
+
+impl UkifyArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let (_, _, inputs) = crate::cli::build::load(&self.profile, None, None, ctx)?;
+        let scratch = tempfile::tempdir()?;
+        let dir = match &self.keep {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("create {}", dir.display()))?;
+                dir.clone()
+            }
+            None => scratch.path().to_path_buf(),
+        };
+
+        let ours = dir.join("lowell.efi");
+        let image = inputs.build()?;
+        std::fs::write(&ours, &image).with_context(|| format!("write {}", ours.display()))?;
+        let ours = PeFile::from_bytes(image)?;
+
+        let initrd = match inputs.initrd.is_dir() {
+            true => {
+                let packed = scratch.path().join("initrd");
+                let data = ours.section_contents(".initrd")?.unwrap_or_default();
+                std::fs::write(&packed, data)?;
+                packed
+            }
+            false => inputs.initrd.clone(),
+        };
+        let cmdline = scratch.path().join("cmdline");
+        std::fs::write(&cmdline, &inputs.cmdline)?;
+        let theirs = dir.join("ukify.efi");
+        let mut args = vec![
+            "build".to_string(),
+            format!("--linux={}", inputs.kernel.display()),
+            format!("--initrd={}", initrd.display()),
+            format!("--cmdline=@{}", cmdline.display()),
+            format!("--stub={}", inputs.stub.display()),
+        ];
+        if let Some(osrel) = &inputs.osrel {
+            args.push(format!("--os-release=@{}", osrel.display()));
+        }
+        if let Some(dtb) = &inputs.dtb {
+            args.push(format!("--devicetree={}", dtb.display()));
+        }
+        args.push(format!("--output={}", theirs.display()));
+        debug!(ukify = %self.ukify.display(), ?args, "ukify");
+        info!(profile = %self.profile, "building with ukify");
+        let result = Command::new(&self.ukify)
+            .args(&args)
+            .output()
+            .with_context(|| format!("run {}", self.ukify.display()))?;
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            let last = stderr.lines().rev().find(|l| !l.trim().is_empty());
+            bail!(Coded::new(
+                ErrorCode::BuildFailed,
+                format!(
+                    "{} failed ({}){}",
+                    self.ukify.display(),
+                    result.status,
+                    last.map(|l| format!(": {l}")).unwrap_or_default()
+                )
+            ));
+        }
+        let theirs = PeFile::from_path(&theirs)
+            .with_context(|| format!("{}: not a UKI", theirs.display()))?;
+
+        let report = compare(&self.profile, &ours, &theirs, &self.ignore_section)?;
+        match self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human)
+        {
+            ReportFormat::Human => print_human(out, &report, ctx.palette)?,
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+            ReportFormat::JsonPretty => {
+                serde_json::to_writer_pretty(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        if !report.conforms {
+            let diverged = report
+                .sections
+                .iter()
+                .filter(|r| r.status != Status::Same && !r.ignored)
+                .map(|r| r.name.as_str())
+                .collect::<Vec<_>>();
+            let mut why = format!("{}: lowell and ukify disagree", self.profile);
+            if !diverged.is_empty() {
+                why += &format!(" on {}", diverged.join(", "));
+            }
+            if !report.same_order {
+                why += " (section order differs)";
+            }
+            bail!(Coded::new(ErrorCode::ConformanceMismatch, why));
+        }
+        Ok(())
+    }
+}
+
+fn compare(profile: &str, ours: &PeFile, theirs: &PeFile, ignore: &[String]) -> Result<Report> {
+    let side = |pe: &PeFile| -> Result<Vec<(String, Side)>> {
+        Ok(pe
+            .sections()?
+            .iter()
+            .map(|s| {
+                let side = Side {
+                    size: s.contents().len(),
+                    digest: Algorithm::Sha256.digest(s.contents()).to_string(),
+                };
+                (s.name.to_string(), side)
+            })
+            .collect())
+    };
+    let mut ours = side(ours)?;
+    let mut theirs = side(theirs)?;
+    let common = |a: &[(String, Side)], b: &[(String, Side)]| {
+        a.iter()
+            .map(|(n, _)| n.clone())
+            .filter(|n| b.iter().any(|(m, _)| m == n))
+            .collect::<Vec<_>>()
+    };
+    let same_order = common(&ours, &theirs) == common(&theirs, &ours);
+
+    // In lowell's order, then whatever only ukify has, in ukify's.
+    let mut sections = Vec::new();
+    for (name, mine) in ours.drain(..) {
+        let other = theirs
+            .iter()
+            .position(|(n, _)| *n == name)
+            .map(|i| theirs.remove(i).1);
+        let status = match &other {
+            Some(o) if o.digest == mine.digest => Status::Same,
+            Some(_) => Status::Differs,
+            None => Status::LowellOnly,
+        };
+        sections.push(Row {
+            ignored: ignore.contains(&name),
+            name,
+            status,
+            lowell: Some(mine),
+            ukify: other,
+        });
+    }
+    for (name, other) in theirs {
+        sections.push(Row {
+            ignored: ignore.contains(&name),
+            name,
+            status: Status::UkifyOnly,
+            lowell: None,
+            ukify: Some(other),
+        });
+    }
+    let conforms = same_order
+        && sections
+            .iter()
+            .all(|r| r.status == Status::Same || r.ignored);
+    Ok(Report {
+        profile: profile.to_string(),
+        conforms,
+        same_order,
+        sections,
+    })
+}
+
+fn print_human(out: &mut dyn Write, r: &Report, palette: Palette) -> Result<()> {
+    let verdict = match r.conforms {
+        true => palette.good("PASS").to_string(),
+        false => palette.bad("FAIL").to_string(),
+    };
+    let same = r
+        .sections
+        .iter()
+        .filter(|s| s.status == Status::Same)
+        .count();
+    writeln!(
+        out,
+        "{verdict} {}: {same} of {} sections identical",
+        r.profile,
+        r.sections.len()
+    )?;
+    let describe = |s: &Side| format!("{} bytes {}", s.size, s.digest);
+    for row in r.sections.iter().filter(|s| s.status != Status::Same) {
+        let what = match (&row.lowell, &row.ukify) {
+            (Some(a), Some(b)) => format!("differs: lowell {}, ukify {}", describe(a), describe(b)),
+            (Some(a), None) => format!("lowell only: {}", describe(a)),
+            (None, Some(b)) => format!("ukify only: {}", describe(b)),
+            (None, None) => unreachable!("a row has at least one side"),
+        };
+        let ignored = if row.ignored { " (ignored)" } else { "" };
+        writeln!(out, "  {:<10} {what}{ignored}", palette.bold(&row.name))?;
+    }
+    if !r.same_order {
+        writeln!(out, "  section order differs")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use clap::Parser;
+    use lowell_test_util::{newc, stub, UkiBuilder};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: UkifyArgs,
+    }
+
+    /// A stand-in for ukify that records its arguments and "builds"
+    /// `image`.
+    fn fake_ukify(dir: &Path, image: &Path) -> PathBuf {
+        let path = dir.join("ukify");
+        let script = format!(
+            "#!/bin/sh\necho \"$@\" > {d}/args\n\
+             for a; do case $a in --output=*) cp {} \"${{a#--output=}}\";; esac; done\n",
+            image.display(),
+            d = dir.display()
+        );
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn images_are_compared_section_by_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let put = |name: &str, data: &[u8]| std::fs::write(dir.path().join(name), data).unwrap();
+        put("stub.efi", &stub());
+        put("vmlinuz", &[0xAA; 4096]);
+        put("initrd", &newc(&[("init", 0o100755, b"#!/bin/sh\n")]));
+        put(
+            "p.toml",
+            b"name = \"p\"\nroot = \"ostree\"\ncmdline = \"quiet\"\n\n[artifacts]\n\
+              stub = \"stub.efi\"\nkernel = \"vmlinuz\"\ninitrd = \"initrd\"\n",
+        );
+        let ctx = Ctx {
+            cfg: Config::default(),
+            palette: Palette::new(false),
+            porcelain: false,
+        };
+        let keep = dir.path().join("out");
+        let run = |ukify: &Path, extra: &[&str]| {
+            let mut argv = vec![
+                "t".to_string(),
+                format!("--profile={}", dir.path().join("p.toml").display()),
+                format!("--ukify={}", ukify.display()),
+                format!("--keep={}", keep.display()),
+            ];
+            argv.extend(extra.iter().map(|s| s.to_string()));
+            let mut out = Vec::new();
+            let r = Cli::parse_from(argv).args.run(&ctx, &mut out);
+            (r, String::from_utf8(out).unwrap())
+        };
+
+        // A ukify that produces exactly what lowell did.
+        let ukify = fake_ukify(dir.path(), &keep.join("lowell.efi"));
+        let (r, out) = run(&ukify, &[]);
+        r.unwrap();
+        assert!(out.starts_with("PASS "), "{out}");
+        let args = std::fs::read_to_string(dir.path().join("args")).unwrap();
+        assert!(args.starts_with("build --linux="), "{args}");
+        assert!(args.contains("--cmdline=@"), "{args}");
+        assert!(keep.join("ukify.efi").exists());
+
+        let other = dir.path().join("other.efi");
+        std::fs::write(
+            &other,
+            UkiBuilder::new()
+                .cmdline("quiet splash")
+                .linux(&[0xAA; 4096])
+                .initrd(&newc(&[("init", 0o100755, b"#!/bin/sh\n")]))
+                .section(".uname", b"6.11.0")
+                .build(),
+        )
+        .unwrap();
+        let ukify = fake_ukify(dir.path(), &other);
+        let (r, out) = run(&ukify, &["--format=json", "--ignore-section=.uname"]);
+        let err = r.unwrap_err();
+        assert_eq!(crate::error::code_of(&err), ErrorCode::ConformanceMismatch);
+        assert!(err.to_string().contains(".cmdline"), "{err}");
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json["conforms"], false);
+        let rows = json["sections"].as_array().unwrap();
+        let row = |name: &str| rows.iter().find(|r| r["name"] == name).unwrap();
+        assert_eq!(row(".cmdline")["status"], "differs");
+        assert_eq!(row(".uname")["status"], "ukify-only");
+        assert_eq!(row(".uname")["ignored"], true);
+    }
+}
@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cache::Cache;
+use crate::cli::Ctx;
+use crate::output;
+use anyhow::{Context, Result};
+use clap::{ArgGroup, Args};
+use lowell_core::digest::Algorithm;
+use lowell_core::uki::build;
+use lowell_core::uki::inspect::{self, InspectOptions, Report};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("change").required(true).multiple(true)))]
+pub struct EditArgs {
+    /// Path to the UKI
+    file: PathBuf,
+    /// New kernel command line; an empty TEXT removes `.cmdline`
+    #[arg(long, group = "change", value_name = "TEXT")]
+    cmdline: Option<String>,
+    /// New `.osrel` from FILE
+    #[arg(long, group = "change", value_name = "FILE")]
+    osrel: Option<PathBuf>,
+    /// New `.dtb` from FILE
+    #[arg(long, group = "change", value_name = "FILE")]
+    dtb: Option<PathBuf>,
+    /// Replace or add section NAME with the contents of FILE (e.g.
+    /// `.initrd=initrd.img`); repeat for several
+    #[arg(long, group = "change", value_name = "NAME=FILE", value_parser = parse_section)]
+    section: Vec<(String, PathBuf)>,
+    /// Remove section NAME; repeat for several
+    #[arg(long, group = "change", value_name = "NAME")]
+    remove: Vec<String>,
+    /// Where to write the edited UKI (default: replace FILE)
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+    /// Digest for the cached report on the result
+    #[arg(long, value_name = "ALG", default_value_t = Algorithm::Sha256)]
+    digest: Algorithm,
+    /// Cache the edited UKI's report in DIR, hashing only the sections
+    /// that changed when FILE's report is cached there (default:
+    /// `cache-dir` from config, else no cache)
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+}
+
+fn parse_section(s: &str) -> Result<(String, PathBuf), String> {
+    match s.split_once('=') {
+        Some((name, file)) if !name.is_empty() && !file.is_empty() => {
+            Ok((name.to_string(), PathBuf::from(file)))
+        }
+        _ => Err(format!("expected NAME=FILE, got {s:?}")),
+    }
+}
+
+impl EditArgs {
+    pub fn run(self, ctx: &Ctx) -> Result<()> {
+        let uki =
+            std::fs::read(&self.file).with_context(|| format!("read {}", self.file.display()))?;
+        let mut changes: Vec<(String, Option<Vec<u8>>)> = Vec::new();
+        if let Some(cmdline) = &self.cmdline {
+            let data = (!cmdline.is_empty()).then(|| format!("{cmdline}\0").into_bytes());
+            changes.push((".cmdline".into(), data));
+        }
+        let files = [(".osrel", &self.osrel), (".dtb", &self.dtb)];
+        let named = files
+            .into_iter()
+            .filter_map(|(name, file)| Some((name.to_string(), file.clone()?)));
+        for (name, file) in named.chain(self.section.iter().cloned()) {
+            let data = std::fs::read(&file).with_context(|| format!("read {}", file.display()))?;
+            changes.push((name, Some(data)));
+        }
+        changes.extend(self.remove.iter().map(|name| (name.clone(), None)));
+        let changes: Vec<(&str, Option<&[u8]>)> = changes
+            .iter()
+            .map(|(name, data)| (name.as_str(), data.as_deref()))
+            .collect();
+
+        // The input's report, to carry over what the edit leaves alone;
+        // only worth having when the result's report is cached too.
+        let cache = Cache::open(self.cache_dir.as_deref(), &ctx.cfg);
+        let kind = format!("uki-{}", self.digest);
+        let before = cache.as_ref().and_then(|cache| {
+            cache
+                .report(&kind, &self.file, || self.inspect(&self.file, None))
+                .inspect_err(|e| debug!("no report to reuse: {e:#}"))
+                .ok()
+        });
+
+        let edited = build::edit(&uki, &changes)?;
+        drop(uki);
+        let out = self.out.as_deref().unwrap_or(&self.file);
+        output::write_atomic(out, &edited.image)?;
+        if let (Some(cache), Some(before)) = (&cache, &before) {
+            cache.report(&kind, out, || {
+                self.inspect(out, Some((before, edited.unchanged.as_slice())))
+            })?;
+        }
+        info!(
+            out = %out.display(),
+            size = edited.image.len(),
+            unchanged = edited.unchanged.join(","),
+            "edited UKI"
+        );
+        Ok(())
+    }
+
+    /// The report `lowell uki inspect` caches, reusing `previous` for the
+    /// sections it names.
+    fn inspect(&self, file: &Path, previous: Option<(&Report, &[String])>) -> Result<Report> {
+        let mut opts = InspectOptions::new(file)
+            .digest(self.digest)
+            .count_entries(true);
+        if let Some((report, unchanged)) = previous {
+            opts = opts.reuse(report, unchanged);
+        }
+        Ok(inspect::inspect(opts)?)
+    }
+}
@@ -1,103 +1,1021 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
-use anyhow::Result;
-use clap::{Args, ValueEnum};
-use lowell_core::uki::inspect::{self, InspectOptions, Report};
-use std::io::{self, Write};
-use std::path::PathBuf;
-
-#[derive(Copy, Clone, Debug, ValueEnum)]
-enum Output {
-    Human,
-    Json,
-    JsonPretty,
-}
+use crate::baseline;
+use crate::cache::Cache;
+use crate::cli::inspect::initrd::{print_entry, write_sizes};
+use crate::cli::inspect::JsonList;
+use crate::cli::{parse_size, Ctx, ReportFormat};
+use crate::error::Coded;
+use crate::sign::{self, Envelope};
+use crate::style::Palette;
+use crate::{inputs, output, provenance, select};
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Args, ValueEnum};
+use lowell_core::digest::{Algorithm, Digest};
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::initramfs::Compression;
+use lowell_core::formats::kernel;
+use lowell_core::formats::pe::PeFile;
+use lowell_core::initrd::inspect::{list_bytes, sizes_bytes};
+use lowell_core::limits::ParserLimits;
+use lowell_core::uki::inspect::{self, InspectOptions, Report, SectionInfo};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
 
 #[derive(Args, Debug)]
+#[command(group(ArgGroup::new("dump").args(["dump_section", "dump_kernel_config"])))]
 pub struct InspectArgs {
-    /// Path to the UKI to inspect
-    file: PathBuf,
-    /// Output format (human by default)
-    #[arg(long, value_enum, default_value_t = Output::Human)]
-    format: Output,
+    /// UKIs to inspect: files, directories (searched recursively for
+    /// `*.efi`) or quoted glob patterns
+    #[arg(value_name = "FILE")]
+    pub(in crate::cli) files: Vec<PathBuf>,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    pub(in crate::cli) format: Option<InspectFormat>,
     /// Show more fields in human output
     #[arg(long, short = 'v')]
-    verbose: bool,
+    pub(in crate::cli) verbose: bool,
+    /// Print only this field (dotted path into the JSON report, e.g.
+    /// `initrd.digest`); repeat for several, one value per line
+    #[arg(long, value_name = "PATH")]
+    pub(in crate::cli) get: Vec<String>,
+    /// Digest for `.linux`/`.initrd`: sha256 (the default), sha384, sha512
+    /// or blake3
+    #[arg(long, value_name = "ALG")]
+    pub(in crate::cli) digest: Option<Algorithm>,
+    /// Digests for every section with each ALG, in one pass (e.g.
+    /// `--hash sha384,blake3`); the first is `digest`, the others
+    /// `extra_digests`. Instead of `--digest`
+    #[arg(
+        long,
+        value_name = "ALG",
+        value_delimiter = ',',
+        conflicts_with = "digest"
+    )]
+    pub(in crate::cli) hash: Vec<Algorithm>,
+    /// Reuse reports cached in DIR, keyed by each file's path, size and
+    /// mtime (default: `cache-dir` from config, else no cache)
+    #[arg(long, value_name = "DIR")]
+    pub(in crate::cli) cache_dir: Option<PathBuf>,
+    /// Decompress within SIZE of working memory (e.g. `64M`): fewer xz
+    /// threads, and no read-ahead below a few MiB. Slower, never an error
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub(in crate::cli) max_memory: Option<u64>,
+    /// Read only the PE headers and the first bytes of each section: no
+    /// hashes, entry counts or certificate counts, in milliseconds per file
+    #[arg(long)]
+    pub(in crate::cli) headers_only: bool,
+    /// Decompress `.initrd` to count its entries (`initrd.entries_estimate`)
+    /// in every output format; `--verbose` does so for human output
+    #[arg(long, conflicts_with = "headers_only")]
+    pub(in crate::cli) deep: bool,
+    /// Compare the report with FILE (one report as `--format json` writes
+    /// it) and fail with E_BASELINE_MISMATCH where they differ
+    #[arg(long, value_name = "FILE", conflicts_with = "get")]
+    pub(in crate::cli) baseline: Option<PathBuf>,
+    /// With `--baseline`: a field allowed to differ (a dotted path as for
+    /// `--get`, `*` matching any one segment, e.g. `*.digest`); repeatable
+    #[arg(long, value_name = "PATH", requires = "baseline")]
+    ignore: Vec<String>,
+    /// Print the report signed by KEY: a DSSE envelope around an in-toto
+    /// statement naming the UKI by SHA-256, which `lowell verify` and
+    /// `cosign verify-blob-attestation` check
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["get", "baseline"])]
+    pub(in crate::cli) sign_key: Option<PathBuf>,
+    /// `--sign-key` with `signing-key` from config
+    #[arg(long, conflicts_with_all = ["get", "baseline", "sign_key"])]
+    pub(in crate::cli) sign: bool,
+    /// List the entries of the `.initrd` section (type, mode, size, path)
+    /// instead of the report; one JSON object per line with `--format
+    /// json`
+    #[arg(long, conflicts_with_all = ["get", "baseline", "sign_key", "sign"])]
+    pub(in crate::cli) list_files: bool,
+    /// Write the raw contents of section NAME (e.g. `.sbat`) instead of the
+    /// report, to `--out` or the command's output
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["get", "baseline", "sign_key", "sign", "list_files"]
+    )]
+    pub(in crate::cli) dump_section: Option<String>,
+    /// Decompress `.initrd` and total its file sizes per top-level
+    /// directory instead of the report, largest first
+    #[arg(
+        long,
+        conflicts_with_all = ["get", "baseline", "sign_key", "sign", "list_files", "dump_section"]
+    )]
+    pub(in crate::cli) size_report: bool,
+    /// Write the `.config` embedded in `.linux` (`CONFIG_IKCONFIG=y`)
+    /// instead of the report, to `--out` or the command's output
+    #[arg(
+        long,
+        conflicts_with_all = ["get", "baseline", "sign_key", "sign", "list_files", "size_report"]
+    )]
+    pub(in crate::cli) dump_kernel_config: bool,
+    /// With `--size-report`: also list the N largest files
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "size_report")]
+    pub(in crate::cli) top: usize,
+    /// File for `--dump-section` or `--dump-kernel-config`
+    #[arg(long, value_name = "PATH", requires = "dump")]
+    out: Option<PathBuf>,
+}
+
+/// The report fields hashing fills, as `--get` paths (`*` for any one
+/// segment).
+const DIGEST_FIELDS: [&str; 6] = [
+    "linux.digest",
+    "linux.extra_digests",
+    "initrd.digest",
+    "initrd.extra_digests",
+    "section_table.*.digest",
+    "section_table.*.extra_digests",
+];
+
+/// Whether `--get` path `get` selects `field` or something inside or
+/// around it: one is a prefix of the other, segment by segment.
+fn overlaps(get: &str, field: &str) -> bool {
+    get.split('.')
+        .zip(field.split('.'))
+        .all(|(g, f)| f == "*" || g == f)
+}
+
+/// `--format` for UKI reports: [`ReportFormat`], or the reports' schema.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InspectFormat {
+    Human,
+    Json,
+    JsonPretty,
+    /// The JSON Schema that `json` reports follow, instead of a report (no
+    /// FILE needed)
+    JsonSchema,
+}
+
+impl InspectArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.report_format().or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    /// `--format` as a report format; `None` without one or for the schema.
+    pub(in crate::cli) fn report_format(&self) -> Option<ReportFormat> {
+        match self.format? {
+            InspectFormat::Human => Some(ReportFormat::Human),
+            InspectFormat::Json => Some(ReportFormat::Json),
+            InspectFormat::JsonPretty => Some(ReportFormat::JsonPretty),
+            InspectFormat::JsonSchema => None,
+        }
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        if self.format == Some(InspectFormat::JsonSchema) {
+            serde_json::to_writer_pretty(&mut *out, &inspect::report_schema())?;
+            out.write_all(b"\n")?;
+            return Ok(());
+        }
+        if self.files.is_empty() {
+            bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                "no FILE to inspect (UKIs, directories or glob patterns)"
+            ));
+        }
+        let format = self
+            .report_format()
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human);
+        let files = inputs::expand(&self.files)?;
+        if let Some(baseline) = &self.baseline {
+            let [file] = files.as_slice() else {
+                bail!(Coded::new(
+                    ErrorCode::InvalidArgument,
+                    format!("--baseline compares one UKI ({} given)", files.len())
+                ));
+            };
+            let report = self.inspect(ctx, format, file)?;
+            return self.check_baseline(ctx, out, format, baseline, &report);
+        }
+        if let Some(name) = &self.dump_section {
+            let [file] = files.as_slice() else {
+                bail!(Coded::new(
+                    ErrorCode::InvalidArgument,
+                    format!("--dump-section reads one UKI ({} given)", files.len())
+                ));
+            };
+            return dump_section(out, file, name, self.out.as_deref());
+        }
+        if self.dump_kernel_config {
+            let [file] = files.as_slice() else {
+                bail!(Coded::new(
+                    ErrorCode::InvalidArgument,
+                    format!("--dump-kernel-config reads one UKI ({} given)", files.len())
+                ));
+            };
+            return dump_kernel_config(out, file, self.out.as_deref());
+        }
+        if self.list_files {
+            let [file] = files.as_slice() else {
+                bail!(Coded::new(
+                    ErrorCode::InvalidArgument,
+                    format!("--list-files lists one UKI ({} given)", files.len())
+                ));
+            };
+            return list_files(out, format, file);
+        }
+        if self.size_report {
+            let [file] = files.as_slice() else {
+                bail!(Coded::new(
+                    ErrorCode::InvalidArgument,
+                    format!("--size-report reads one UKI ({} given)", files.len())
+                ));
+            };
+            return size_report(ctx, out, self.report_format(), file, self.top);
+        }
+        if let Some(key) = sign::requested_key(self.sign_key.as_deref(), self.sign, &ctx.cfg)? {
+            let [file] = files.as_slice() else {
+                bail!(Coded::new(
+                    ErrorCode::InvalidArgument,
+                    format!("--sign-key signs one report ({} given)", files.len())
+                ));
+            };
+            let report = self.inspect(ctx, format, file)?;
+            let statement = provenance::report_statement(file, serde_json::to_value(&report)?)?;
+            let envelope =
+                Envelope::seal(&key, provenance::IN_TOTO, &serde_json::to_vec(&statement)?);
+            match format {
+                ReportFormat::JsonPretty => serde_json::to_writer_pretty(&mut *out, &envelope)?,
+                _ => serde_json::to_writer(&mut *out, &envelope)?,
+            }
+            out.write_all(b"\n")?;
+            return Ok(());
+        }
+        if let ([arg], [file]) = (self.files.as_slice(), files.as_slice()) {
+            if arg == file {
+                let report = self.inspect(ctx, format, file)?;
+                return self.print_one(ctx, out, format, &report);
+            }
+        }
+        self.run_many(ctx, out, format, &files)
+    }
+
+    fn inspect(&self, ctx: &Ctx, format: ReportFormat, file: &Path) -> Result<Report> {
+        // A headers-only read costs about as much as a cache lookup.
+        let cache = Cache::open(self.cache_dir.as_deref(), &ctx.cfg);
+        let Some(cache) = cache.filter(|_| !self.headers_only) else {
+            return Ok(inspect::inspect(self.options(ctx, format, file))?);
+        };
+        // Cache complete reports, so one entry serves every output mode.
+        let (digest, extra) = self.algorithms();
+        let mut kind = format!("uki-{digest}");
+        for alg in extra {
+            kind.push_str(&format!("+{alg}"));
+        }
+        cache.report(&kind, file, || {
+            let opts = InspectOptions::new(file)
+                .digest(digest)
+                .extra_digests(extra.iter().copied())
+                .limits(self.limits())
+                .count_entries(true);
+            Ok(inspect::inspect(opts)?)
+        })
+    }
+
+    /// The main digest algorithm and the others `--hash` asks for.
+    fn algorithms(&self) -> (Algorithm, &[Algorithm]) {
+        match self.hash.split_first() {
+            Some((first, rest)) => (*first, rest),
+            None => (self.digest.unwrap_or(Algorithm::Sha256), &[]),
+        }
+    }
+
+    fn limits(&self) -> ParserLimits {
+        let mut limits = ParserLimits::default();
+        if let Some(max) = self.max_memory {
+            limits.max_memory = max;
+        }
+        limits
+    }
+
+    /// Skip the analyses whose results won't be shown: the human summary
+    /// has no hashes, and `--get` only needs what it names.
+    fn options(&self, ctx: &Ctx, format: ReportFormat, file: &Path) -> InspectOptions {
+        let (digest, extra) = self.algorithms();
+        let opts = InspectOptions::new(file)
+            .digest(digest)
+            .extra_digests(extra.iter().copied())
+            .limits(self.limits())
+            .headers_only(self.headers_only)
+            .count_entries(self.deep);
+        if self.baseline.is_some() || self.sign_key.is_some() || self.sign {
+            return opts;
+        }
+        if !self.get.is_empty() {
+            let wants = |fields: &[&str]| {
+                self.get
+                    .iter()
+                    .any(|g| fields.iter().any(|f| overlaps(g, f)))
+            };
+            return opts
+                .hash(wants(&DIGEST_FIELDS))
+                .os_release(wants(&["os_release"]))
+                .kernel_version(wants(&["kernel_version"]))
+                .checksum(wants(&["checksum"]))
+                .count_entries(self.deep || wants(&["initrd.entries_estimate"]));
+        }
+        match format {
+            ReportFormat::Human if !ctx.porcelain => opts
+                .hash(self.verbose)
+                .count_entries(self.deep || self.verbose),
+            _ => opts,
+        }
+    }
+
+    /// Several inputs: keep going past files that fail (e.g. a plain EFI
+    /// binary in an ESP), then report how many did.
+    fn run_many(
+        &self,
+        ctx: &Ctx,
+        out: &mut dyn Write,
+        format: ReportFormat,
+        files: &[PathBuf],
+    ) -> Result<()> {
+        let mut reports = Vec::new();
+        for file in files {
+            match self.inspect(ctx, format, file) {
+                Ok(r) => reports.push(Entry {
+                    path: file,
+                    report: r,
+                }),
+                Err(e) => warn!(path = %file.display(), "skipped: {e:#}"),
+            }
+        }
+
+        if !self.get.is_empty() {
+            for e in &reports {
+                let prefix = format!("{}\t", e.path.display());
+                select::write_fields(out, &e.report, &self.get, &prefix)?;
+            }
+        } else if ctx.porcelain {
+            for (i, e) in reports.iter().enumerate() {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                writeln!(out, "path={}", e.path.display())?;
+                print_porcelain(out, &e.report)?;
+            }
+        } else {
+            match format {
+                ReportFormat::Human => {
+                    for (i, e) in reports.iter().enumerate() {
+                        if i > 0 {
+                            writeln!(out)?;
+                        }
+                        let header = format!("==> {} <==", e.path.display());
+                        writeln!(out, "{}", ctx.palette.bold(header))?;
+                        print_human(out, &e.report, self.verbose, ctx.palette)?;
+                    }
+                }
+                ReportFormat::Json => {
+                    serde_json::to_writer(&mut *out, &reports)?;
+                    out.write_all(b"\n")?;
+                }
+                ReportFormat::JsonPretty => {
+                    serde_json::to_writer_pretty(&mut *out, &reports)?;
+                    out.write_all(b"\n")?;
+                }
+            }
+        }
+
+        let failed = files.len() - reports.len();
+        if failed > 0 {
+            bail!(Coded::new(
+                ErrorCode::PartialFailure,
+                format!("{failed} of {} inputs could not be inspected", files.len())
+            ));
+        }
+        Ok(())
+    }
+
+    fn print_one(
+        &self,
+        ctx: &Ctx,
+        out: &mut dyn Write,
+        format: ReportFormat,
+        report: &Report,
+    ) -> Result<()> {
+        if !self.get.is_empty() {
+            return select::write_fields(out, report, &self.get, "");
+        }
+        if ctx.porcelain {
+            return print_porcelain(out, report);
+        }
+        match format {
+            ReportFormat::Human => print_human(out, report, self.verbose, ctx.palette)?,
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut *out, report)?;
+                out.write_all(b"\n")?;
+            }
+            ReportFormat::JsonPretty => {
+                serde_json::to_writer_pretty(&mut *out, report)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl InspectArgs {
-    pub fn run(self) -> Result<()> {
-        let report = inspect::inspect(InspectOptions { file: self.file })?;
-        match self.format {
-            Output::Human => print_human(&report, self.verbose)?,
-            Output::Json => {
-                serde_json::to_writer(io::stdout(), &report)?;
-                io::stdout().write_all(b"\n")?;
+    fn check_baseline(
+        &self,
+        ctx: &Ctx,
+        out: &mut dyn Write,
+        format: ReportFormat,
+        path: &Path,
+        report: &Report,
+    ) -> Result<()> {
+        let text = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        let expected: serde_json::Value = serde_json::from_slice(&text)
+            .with_context(|| format!("{}: not a JSON report", path.display()))?;
+        let current = serde_json::to_value(report)?;
+        let diffs = baseline::compare(&expected, &current, &self.ignore);
+        let show =
+            |v: &Option<serde_json::Value>| v.as_ref().map_or(String::new(), |v| v.to_string());
+        if ctx.porcelain {
+            for d in &diffs {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}",
+                    d.path,
+                    show(&d.baseline),
+                    show(&d.current)
+                )?;
             }
-            Output::JsonPretty => {
-                serde_json::to_writer_pretty(io::stdout(), &report)?;
-                io::stdout().write_all(b"\n")?;
+        } else {
+            match format {
+                ReportFormat::Human if diffs.is_empty() => writeln!(
+                    out,
+                    "{} baseline {}",
+                    ctx.palette.good("matches"),
+                    path.display()
+                )?,
+                ReportFormat::Human => {
+                    writeln!(
+                        out,
+                        "{} baseline {}:",
+                        ctx.palette.bad("differs from"),
+                        path.display()
+                    )?;
+                    for d in &diffs {
+                        let side = |v: &Option<serde_json::Value>| match v {
+                            Some(v) => v.to_string(),
+                            None => "<absent>".to_string(),
+                        };
+                        writeln!(
+                            out,
+                            "  {}: {} -> {}",
+                            ctx.palette.bold(&d.path),
+                            side(&d.baseline),
+                            side(&d.current)
+                        )?;
+                    }
+                }
+                ReportFormat::Json | ReportFormat::JsonPretty => {
+                    let doc = serde_json::json!({
+                        "baseline": path,
+                        "matches": diffs.is_empty(),
+                        "differences": diffs,
+                    });
+                    match format {
+                        ReportFormat::Json => serde_json::to_writer(&mut *out, &doc)?,
+                        _ => serde_json::to_writer_pretty(&mut *out, &doc)?,
+                    }
+                    out.write_all(b"\n")?;
+                }
             }
         }
+        if !diffs.is_empty() {
+            bail!(Coded::new(
+                ErrorCode::BaselineMismatch,
+                format!(
+                    "{} field(s) differ from baseline {}",
+                    diffs.len(),
+                    path.display()
+                )
+            ));
+        }
         Ok(())
     }
 }
 
-fn print_human(r: &Report, verbose: bool) -> Result<()> {
-    let mut out = io::BufWriter::new(io::stdout());
+/// Section `name` of `file`, byte for byte (without alignment padding).
+fn dump_section(out: &mut dyn Write, file: &Path, name: &str, dest: Option<&Path>) -> Result<()> {
+    let pe = PeFile::from_path_mmap(file)?;
+    let Some(bytes) = pe.section_contents(name)? else {
+        let table: Vec<String> = pe
+            .section_table()?
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        bail!(Coded::new(
+            ErrorCode::SectionMissing,
+            format!(
+                "no {name} section in {} (has: {})",
+                file.display(),
+                table.join(", ")
+            )
+        ));
+    };
+    dump(out, dest, name, bytes)
+}
 
+/// The kernel config embedded in `file`'s `.linux`, as `--dump-kernel-config`
+/// writes it.
+fn dump_kernel_config(out: &mut dyn Write, file: &Path, dest: Option<&Path>) -> Result<()> {
+    let pe = PeFile::from_path_mmap(file)?;
+    let linux =
+        pe.section_contents(".linux")?
+            .ok_or_else(|| lowell_core::Error::SectionMissing {
+                name: ".linux".to_string(),
+            })?;
+    let Some(format) = kernel::detect(linux) else {
+        bail!(Coded::new(
+            ErrorCode::NotKernel,
+            format!("{}: .linux is not a kernel image", file.display())
+        ));
+    };
+    let Some(config) = kernel::config(linux, format)? else {
+        bail!(Coded::new(
+            ErrorCode::NotFound,
+            format!(
+                "{}: no embedded kernel config in .linux (CONFIG_IKCONFIG not set, \
+                 built as a module, or a payload compression lowell cannot read)",
+                file.display()
+            )
+        ));
+    };
+    dump(out, dest, "kernel config", config.as_bytes())
+}
+
+/// Write `bytes` to `dest`, or to `out` without one.
+fn dump(out: &mut dyn Write, dest: Option<&Path>, what: &str, bytes: &[u8]) -> Result<()> {
+    match dest {
+        Some(dest) => {
+            output::write_atomic(dest, bytes)?;
+            info!(%what, path = %dest.display(), size = bytes.len(), "dumped");
+        }
+        None => out.write_all(bytes)?,
+    }
+    Ok(())
+}
+
+/// The entries of `file`'s `.initrd`, as `inspect initrd --list` prints
+/// them.
+fn list_files(out: &mut dyn Write, format: ReportFormat, file: &Path) -> Result<()> {
+    let pe = PeFile::from_path_mmap(file)?;
+    let initrd =
+        pe.section_contents(".initrd")?
+            .ok_or_else(|| lowell_core::Error::SectionMissing {
+                name: ".initrd".to_string(),
+            })?;
+    match format {
+        ReportFormat::Human => {
+            list_bytes(initrd, |e| Ok(print_entry(&mut *out, &e)?))?;
+        }
+        format => {
+            let mut list = JsonList::new(&mut *out, format == ReportFormat::JsonPretty);
+            list_bytes(initrd, |e| Ok(list.push(&e)?))?;
+            list.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// The `--size-report` of `file`'s `.initrd`.
+fn size_report(
+    ctx: &Ctx,
+    out: &mut dyn Write,
+    format: Option<ReportFormat>,
+    file: &Path,
+    top: usize,
+) -> Result<()> {
+    let pe = PeFile::from_path_mmap(file)?;
+    let initrd =
+        pe.section_contents(".initrd")?
+            .ok_or_else(|| lowell_core::Error::SectionMissing {
+                name: ".initrd".to_string(),
+            })?;
+    write_sizes(ctx, out, format, &sizes_bytes(initrd, top)?)
+}
+
+/// One report in multi-file JSON output: the input path plus the report.
+#[derive(serde::Serialize)]
+struct Entry<'a> {
+    path: &'a Path,
+    #[serde(flatten)]
+    report: Report,
+}
+
+fn print_human(out: &mut dyn Write, r: &Report, verbose: bool, palette: Palette) -> Result<()> {
     // Header / identity
     writeln!(
         out,
         "{} • {} • {}",
-        r.os_release
-            .as_ref()
-            .and_then(|o| o.name.as_deref())
-            .unwrap_or("<unknown>"),
+        palette.bold(
+            r.os_release
+                .as_ref()
+                .and_then(|o| o.name.as_deref())
+                .unwrap_or("<unknown>")
+        ),
         r.arch,
         if r.pe32_plus { "PE32+" } else { "PE32" }
     )?;
 
     // Secure Boot / signatures
-    let sig = if r.has_signature {
-        format!("signed ({} certs)", r.cert_count)
-    } else {
-        "unsigned".to_string()
+    let sig = match (r.cert_count, r.has_signature) {
+        (Some(n), _) if n > 0 => palette.good(format!("signed ({n} certs)")).to_string(),
+        (None, Some(true)) => palette.good("signed").to_string(),
+        _ => palette.bad("unsigned").to_string(),
     };
     writeln!(out, "secure-boot: {sig}")?;
+    // Who signed: the subject, or the issuer and serial the signature
+    // names when the signer's certificate is not embedded
+    for s in &r.signatures {
+        let who = s.subject.as_deref().unwrap_or("<certificate not embedded>");
+        writeln!(
+            out,
+            "  signer: {who} (issuer {}, serial {})",
+            s.issuer, s.serial
+        )?;
+        if verbose {
+            let (from, to) = (
+                s.not_before.as_deref().unwrap_or("?"),
+                s.not_after.as_deref().unwrap_or("?"),
+            );
+            writeln!(out, "    valid {from} to {to}, {}", s.digest_algorithm)?;
+        }
+    }
+
+    // PE checksum: 0 is "not set", which firmware that checks lets through
+    if let Some(c) = &r.checksum {
+        let state = match (c.stored, c.matches) {
+            (0, _) => palette.warn("not set").to_string(),
+            (_, true) => palette.good("ok").to_string(),
+            (_, false) => palette
+                .bad(format!("mismatch, computed {:#010x}", c.computed))
+                .to_string(),
+        };
+        writeln!(out, "checksum: {:#010x} ({state})", c.stored)?;
+    }
+
+    // Image-based OSes: which image, and which version of it
+    if let Some(os) = &r.os_release {
+        let image: Vec<_> = [os.image_id(), os.image_version.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        if !image.is_empty() {
+            writeln!(out, "image  : {}", image.join(" "))?;
+        }
+    }
 
     // Cmdline (trimmed already)
     if !r.cmdline.is_empty() {
         writeln!(out, "cmdline: {}", r.cmdline)?;
     }
 
+    // Stub: unknown when it carries no `.sdmagic` (pre-254 or not systemd)
+    let stub = match &r.stub {
+        Some(s) => format!("{} {}", s.product, s.version),
+        None => palette.warn("unknown (no .sdmagic)").to_string(),
+    };
+    writeln!(out, "stub    : {stub}")?;
+
+    // SBAT: `component,generation` pairs; every field with --verbose
+    if !r.sbat.is_empty() && !verbose {
+        let pairs: Vec<_> = r
+            .sbat
+            .iter()
+            .map(|e| format!("{},{}", e.component, e.generation))
+            .collect();
+        writeln!(out, "sbat    : {}", pairs.join(" "))?;
+    } else if !r.sbat.is_empty() {
+        writeln!(out, "sbat    :")?;
+        for e in &r.sbat {
+            let optional =
+                [&e.vendor, &e.package, &e.version, &e.url].map(|f| f.as_deref().unwrap_or("-"));
+            writeln!(
+                out,
+                "  {},{} {}",
+                e.component,
+                e.generation,
+                optional.join(" | ")
+            )?;
+        }
+    }
+    if let Some(p) = &r.sbat_problem {
+        writeln!(out, "sbat    : {}", palette.bad(&p.problem))?;
+        if verbose {
+            for line in p.text.lines() {
+                writeln!(out, "  {line}")?;
+            }
+        }
+    }
+
+    // Profiles: each with what it overrides; its cmdline in full
+    if !r.profiles.is_empty() {
+        writeln!(out, "profiles: {}", r.profiles.len())?;
+    }
+    for (i, p) in r.profiles.iter().enumerate() {
+        let name = match (&p.id, &p.title) {
+            (Some(id), Some(title)) => format!("{id} ({title})"),
+            (id, title) => id.clone().or(title.clone()).unwrap_or_default(),
+        };
+        let overrides = match p.sections.is_empty() {
+            true => "no overrides".to_string(),
+            false => p.sections.join(" "),
+        };
+        writeln!(out, "  @{i} {name}: {overrides}")?;
+        if let Some(cmdline) = &p.cmdline {
+            writeln!(out, "    cmdline: {cmdline}")?;
+        }
+    }
+
+    // Devicetrees: how many, and the board each names
+    if !r.devicetrees.is_empty() {
+        let boards: Vec<_> = r
+            .devicetrees
+            .iter()
+            .map(|d| match &d.devicetree {
+                Some(dt) => dt
+                    .model
+                    .as_deref()
+                    .or(dt.compatible.first().map(String::as_str))
+                    .unwrap_or("?")
+                    .to_string(),
+                None => palette.bad("unreadable").to_string(),
+            })
+            .collect();
+        writeln!(
+            out,
+            "dtb     : {} ({})",
+            r.devicetrees.len(),
+            boards.join(", ")
+        )?;
+        if verbose {
+            for d in &r.devicetrees {
+                let detail = match (&d.devicetree, &d.problem) {
+                    (Some(dt), _) => dt.compatible.join(" "),
+                    (None, problem) => palette
+                        .bad(problem.as_deref().unwrap_or("unreadable"))
+                        .to_string(),
+                };
+                writeln!(out, "  {:<9} {detail}", d.section)?;
+            }
+        }
+    }
+
+    // Splash: what the stub draws, or why it would not
+    if let Some(s) = &r.splash {
+        let image = s
+            .bitmap
+            .as_ref()
+            .map(|b| format!("{}x{}, {}-bit", b.width, b.height, b.bits_per_pixel));
+        match (image, &s.problem) {
+            (Some(image), None) => writeln!(out, "splash  : {image}")?,
+            (Some(image), Some(problem)) => writeln!(
+                out,
+                "splash  : {image} ({})",
+                palette.bad(format!("not drawn: {problem}"))
+            )?,
+            (None, problem) => writeln!(
+                out,
+                "splash  : {}",
+                palette.bad(problem.as_deref().unwrap_or("unreadable"))
+            )?,
+        }
+    }
+
     // Sections
+    let version = r
+        .kernel_version
+        .as_ref()
+        .map(|v| format!("{v}, "))
+        .unwrap_or_default();
     writeln!(
         out,
-        "kernel  : {} ({})",
+        "kernel  : {version}{} ({})",
         fmt_bytes(r.linux.size),
         fmt_offset(r.linux.offset)
     )?;
     if verbose {
-        writeln!(out, "  sha256: {}", r.linux.sha256)?;
+        for d in r.linux.digest.iter().chain(&r.linux.extra_digests) {
+            writeln!(out, "  {}: {}", d.algorithm, d.hex)?;
+        }
     }
 
+    let compression = match r.initrd.compression {
+        Compression::Unknown => palette.warn(Compression::Unknown).to_string(),
+        c => c.to_string(),
+    };
     writeln!(
         out,
         "initrd  : {} ({}), compression: {}",
         fmt_bytes(r.initrd.section.size),
         fmt_offset(r.initrd.section.offset),
-        r.initrd.compression
+        compression
     )?;
     if verbose {
-        writeln!(out, "  sha256: {}", r.initrd.section.sha256)?;
+        let s = &r.initrd.section;
+        for d in s.digest.iter().chain(&s.extra_digests) {
+            writeln!(out, "  {}: {}", d.algorithm, d.hex)?;
+        }
+    }
+    if let Some(n) = r.initrd.entries_estimate {
+        writeln!(out, "  entries: {n}")?;
+    }
+    // Segments: worth a line once there is more than the one archive
+    if verbose || r.initrd.segments.len() > 1 {
+        for s in &r.initrd.segments {
+            writeln!(
+                out,
+                "  {:<9} {} ({}), {}",
+                s.purpose,
+                fmt_bytes(s.size),
+                fmt_offset(s.offset),
+                s.compression
+            )?;
+        }
+    }
+
+    // Layout problems show whether or not verbose: they are why it won't boot
+    if !r.layout_problems.is_empty() {
+        writeln!(
+            out,
+            "layout  : {}",
+            palette.warn(format!("{} problem(s)", r.layout_problems.len()))
+        )?;
+        for p in &r.layout_problems {
+            match &p.section {
+                Some(section) => writeln!(out, "  {section}: {}", p.problem)?,
+                None => writeln!(out, "  {}", p.problem)?,
+            }
+        }
+    }
+
+    if !verbose {
+        let names: Vec<_> = r.section_table.iter().map(|s| s.name.as_str()).collect();
+        writeln!(out, "sections: {}", names.join(" "))?;
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "sections: (alignment {:#x} in memory, {:#x} in the file)",
+        r.layout.section_alignment, r.layout.file_alignment
+    )?;
+    for s in &r.section_table {
+        writeln!(
+            out,
+            "  {:<9} {:>10} bytes ({}, va {:#x})",
+            s.name,
+            s.section.size,
+            fmt_offset(s.section.offset),
+            s.virtual_address
+        )?;
+        for d in s.section.digest.iter().chain(&s.section.extra_digests) {
+            writeln!(out, "    {}: {}", d.algorithm, d.hex)?;
+        }
     }
 
-    out.flush()?;
+    Ok(())
+}
+
+/// One `key=value` per line; keys are stable, values run to end of line.
+fn print_porcelain(out: &mut dyn Write, r: &Report) -> Result<()> {
+    let os = r.os_release.as_ref();
+    // `*_sha256` predate `--digest`; they stay empty unless SHA-256 is
+    // among the algorithms.
+    let sha256 = |s: &SectionInfo| {
+        s.digest
+            .iter()
+            .chain(&s.extra_digests)
+            .find(|d| d.algorithm == Algorithm::Sha256)
+            .map(|d| d.hex.clone())
+            .unwrap_or_default()
+    };
+    let digest = |d: &Option<Digest>| d.as_ref().map(Digest::to_string).unwrap_or_default();
+    let sections: Vec<_> = r.section_table.iter().map(|s| s.name.as_str()).collect();
+    let sbat: Vec<_> = r
+        .sbat
+        .iter()
+        .map(|e| format!("{},{}", e.component, e.generation))
+        .collect();
+    let signers: Vec<_> = r
+        .signatures
+        .iter()
+        .map(|s| s.subject.as_deref().unwrap_or(&s.issuer))
+        .collect();
+    let compatible: Vec<_> = r
+        .devicetrees
+        .iter()
+        .filter_map(|d| {
+            d.devicetree
+                .as_ref()?
+                .compatible
+                .first()
+                .map(String::as_str)
+        })
+        .collect();
+    let profiles: Vec<_> = r
+        .profiles
+        .iter()
+        .map(|p| p.id.as_deref().unwrap_or("-"))
+        .collect();
+    let stub = r.stub.as_ref();
+    let os_get = |key: &str| os.and_then(|o| o.get(key)).unwrap_or_default().to_string();
+    let checksum = r.checksum.as_ref();
+    let splash = r.splash.as_ref();
+    let fields: [(&str, String); 34] = [
+        ("arch", r.arch.to_string()),
+        ("pe32_plus", r.pe32_plus.to_string()),
+        ("signed", r.has_signature.unwrap_or_default().to_string()),
+        ("cert_count", r.cert_count.unwrap_or_default().to_string()),
+        ("signers", signers.join("; ")),
+        ("os_id", os.and_then(|o| o.id.clone()).unwrap_or_default()),
+        (
+            "os_version_id",
+            os.and_then(|o| o.version_id.clone()).unwrap_or_default(),
+        ),
+        ("os_variant_id", os_get("VARIANT_ID")),
+        ("os_image_id", os_get("IMAGE_ID")),
+        ("os_image_version", os_get("IMAGE_VERSION")),
+        ("os_build_id", os_get("BUILD_ID")),
+        ("cmdline", r.cmdline.clone()),
+        (
+            "kernel_version",
+            r.kernel_version.clone().unwrap_or_default(),
+        ),
+        ("stub", stub.map(|s| s.product.clone()).unwrap_or_default()),
+        (
+            "stub_version",
+            stub.map(|s| s.version.clone()).unwrap_or_default(),
+        ),
+        ("sbat", sbat.join(" ")),
+        (
+            "sbat_problem",
+            r.sbat_problem
+                .as_ref()
+                .map(|p| p.problem.clone())
+                .unwrap_or_default(),
+        ),
+        ("profiles", profiles.join(" ")),
+        ("devicetrees", r.devicetrees.len().to_string()),
+        ("dtb_compatible", compatible.join(" ")),
+        (
+            "splash",
+            splash
+                .and_then(|s| s.bitmap.as_ref())
+                .map(|b| format!("{}x{}x{}", b.width, b.height, b.bits_per_pixel))
+                .unwrap_or_default(),
+        ),
+        (
+            "splash_problem",
+            splash.and_then(|s| s.problem.clone()).unwrap_or_default(),
+        ),
+        ("linux_size", r.linux.size.to_string()),
+        ("linux_sha256", sha256(&r.linux)),
+        ("linux_digest", digest(&r.linux.digest)),
+        ("initrd_size", r.initrd.section.size.to_string()),
+        ("initrd_sha256", sha256(&r.initrd.section)),
+        ("initrd_digest", digest(&r.initrd.section.digest)),
+        ("initrd_compression", r.initrd.compression.to_string()),
+        (
+            "initrd_entries",
+            r.initrd
+                .entries_estimate
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+        ),
+        ("sections", sections.join(",")),
+        ("layout_problems", r.layout_problems.len().to_string()),
+        (
+            "pe_checksum",
+            checksum
+                .map(|c| format!("{:#010x}", c.stored))
+                .unwrap_or_default(),
+        ),
+        (
+            "pe_checksum_ok",
+            checksum.map(|c| c.matches.to_string()).unwrap_or_default(),
+        ),
+    ];
+    for (key, value) in fields {
+        writeln!(out, "{key}={value}")?;
+    }
     Ok(())
 }
 
 // tiny helpers (no deps)
-fn fmt_bytes(n: usize) -> String {
+pub(in crate::cli) fn fmt_bytes(n: usize) -> String {
     // MiB with one decimal place
     let mib = (n as f64) / (1024.0 * 1024.0);
     format!("{mib:.1} MiB")
@@ -105,3 +1023,429 @@ fn fmt_bytes(n: usize) -> String {
 fn fmt_offset(off: usize) -> String {
     format!("offset {off:#x}")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::error::code_of;
+    use clap::Parser;
+    use lowell_test_util::UkiBuilder;
+
+    #[derive(Parser)]
+    struct Inspect {
+        #[command(flatten)]
+        args: InspectArgs,
+    }
+
+    fn ctx(porcelain: bool) -> Ctx {
+        Ctx {
+            cfg: Config::default(),
+            palette: Palette::new(false),
+            porcelain,
+        }
+    }
+
+    /// `inspect ARGS`: its result and what it wrote.
+    fn run_with(ctx: &Ctx, args: &[&str]) -> (Result<()>, Vec<u8>) {
+        let mut out = Vec::new();
+        let argv = std::iter::once("inspect").chain(args.iter().copied());
+        let result = Inspect::parse_from(argv).args.run(ctx, &mut out);
+        (result, out)
+    }
+
+    /// What a successful `inspect ARGS` wrote, without porcelain.
+    fn run(args: &[&str]) -> String {
+        let (result, out) = run_with(&ctx(false), args);
+        result.unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// A UKI booting a 4 KiB kernel with `initrd` and `quiet`.
+    fn uki(initrd: &[u8]) -> UkiBuilder {
+        UkiBuilder::new()
+            .cmdline("quiet")
+            .linux(&[0xAA; 4096])
+            .initrd(initrd)
+    }
+
+    /// `image` written to `uki.efi` in `dir`; its path.
+    fn write(dir: &Path, image: &[u8]) -> String {
+        let path = dir.join("uki.efi");
+        std::fs::write(&path, image).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn baselines_gate_on_unexpected_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let uki = |name: &str, cmdline: &str, linux: u8| {
+            let path = dir.path().join(name);
+            let image = UkiBuilder::new()
+                .cmdline(cmdline)
+                .linux(&[linux; 4096])
+                .initrd(&lowell_test_util::newc(&[(
+                    "init",
+                    0o100755,
+                    b"#!/bin/sh\n",
+                )]))
+                .build();
+            std::fs::write(&path, image).unwrap();
+            path
+        };
+        let old = uki("old.efi", "quiet", 0xAA);
+        let baseline = dir.path().join("baseline.json");
+        std::fs::write(&baseline, run(&[old.to_str().unwrap(), "--format", "json"])).unwrap();
+
+        // A new kernel: only its digest moved (here and in the section
+        // table), and the image checksum with it.
+        let new = uki("new.efi", "quiet", 0xBB);
+        let gate = |file: &Path, ignore: &[&str]| {
+            let mut argv = vec![
+                file.to_str().unwrap(),
+                "--baseline",
+                baseline.to_str().unwrap(),
+            ];
+            for i in ignore {
+                argv.extend(["--ignore", i]);
+            }
+            let (result, out) = run_with(&ctx(false), &argv);
+            (result, String::from_utf8(out).unwrap())
+        };
+        let (result, out) = gate(&new, &[]);
+        assert_eq!(code_of(&result.unwrap_err()), ErrorCode::BaselineMismatch);
+        assert!(out.contains("linux.digest: "), "{out}");
+        let (result, out) = gate(
+            &new,
+            &[
+                "linux.digest",
+                "section_table.*.digest",
+                "checksum.computed",
+            ],
+        );
+        result.unwrap();
+        assert!(out.starts_with("matches baseline"), "{out}");
+
+        // A changed command line is not allowed to slip through.
+        let (result, out) = gate(
+            &uki("cmd.efi", "quiet splash", 0xBB),
+            &["*.digest", "checksum"],
+        );
+        assert!(result.is_err());
+        assert!(
+            out.contains("cmdline: \"quiet\" -> \"quiet splash\""),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn deep_reports_count_initrd_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let initrd = lowell_test_util::Initrd::new()
+            .early("kernel/x86/microcode/GenuineIntel.bin", b"ucode")
+            .file("init", b"#!/bin/sh\n")
+            .compression(lowell_test_util::Compression::Gzip)
+            .build();
+        let path = write(dir.path(), &uki(&initrd).build());
+        let entries = |extra: &[&str]| {
+            let mut argv = vec![path.as_str(), "--format", "json"];
+            argv.extend(extra);
+            let report: serde_json::Value = serde_json::from_str(&run(&argv)).unwrap();
+            report["initrd"]["entries_estimate"].as_u64()
+        };
+        assert_eq!(entries(&[]), None);
+        assert_eq!(entries(&["--deep"]), Some(2));
+        assert!(Inspect::try_parse_from(["inspect", "--deep", "--headers-only", "x.efi"]).is_err());
+    }
+
+    #[test]
+    fn schema_needs_no_file() {
+        let schema: serde_json::Value =
+            serde_json::from_str(&run(&["--format", "json-schema"])).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            inspect::SCHEMA_VERSION
+        );
+        assert!(schema["$defs"]["SectionInfo"].is_object());
+
+        let (result, _) = run_with(&ctx(false), &["--format", "json"]);
+        assert_eq!(code_of(&result.unwrap_err()), ErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn splash_is_described() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |splash: &[u8], porcelain| {
+            let image = uki(&lowell_test_util::newc(&[]))
+                .section(".splash", splash)
+                .build();
+            let (result, out) = run_with(&ctx(porcelain), &[&write(dir.path(), &image)]);
+            result.unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let good = lowell_test_util::bmp(640, 480, 24);
+        assert!(run(&good, false).contains("splash  : 640x480, 24-bit\n"));
+        let out = run(&good, true);
+        assert!(
+            out.contains("splash=640x480x24\nsplash_problem=\n"),
+            "{out}"
+        );
+
+        let mut short = good.clone();
+        short.truncate(1000);
+        let out = run(&short, false);
+        assert!(
+            out.contains("splash  : malformed bitmap: truncated"),
+            "{out}"
+        );
+        let rle = [&good[..30], &[1], &good[31..]].concat();
+        let out = run(&rle, false);
+        assert!(
+            out.contains("splash  : 640x480, 24-bit (not drawn: compression 1"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn checksums_are_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut image = uki(&lowell_test_util::newc(&[])).build();
+        let field = u32::from_le_bytes(image[0x3c..0x40].try_into().unwrap()) as usize + 88;
+        image[field..field + 4].copy_from_slice(&1u32.to_le_bytes());
+        let path = write(dir.path(), &image);
+        let computed: u32 = run(&[&path, "--get", "checksum.computed"])
+            .trim()
+            .parse()
+            .unwrap();
+        let out = run(&[&path]);
+        assert!(
+            out.contains(&format!(
+                "checksum: 0x00000001 (mismatch, computed {computed:#010x})"
+            )),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn get_of_a_parent_runs_its_analyses() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = uki(&lowell_test_util::newc(&[("init", 0o100755, b"")])).build();
+        let path = write(dir.path(), &image);
+        let linux: serde_json::Value =
+            serde_json::from_str(&run(&[&path, "--get", "linux"])).unwrap();
+        let sha256 = Algorithm::Sha256.digest(&[0xAA; 4096]).to_string();
+        assert_eq!(linux["digest"], sha256.as_str());
+        let initrd: serde_json::Value =
+            serde_json::from_str(&run(&[&path, "--get", "initrd"])).unwrap();
+        assert_eq!(initrd["entries_estimate"], 1);
+        let table: serde_json::Value =
+            serde_json::from_str(&run(&[&path, "--get", "section_table"])).unwrap();
+        assert!(table[2]["digest"].is_string(), "{table}");
+        // Unrelated paths still skip the work.
+        assert!(!overlaps("linux.offset", "linux.digest"));
+    }
+
+    #[test]
+    fn hash_takes_several_algorithms() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = uki(&lowell_test_util::newc(&[("init", 0o100755, b"")])).build();
+        let path = write(dir.path(), &image);
+        let out = run(&[&path, "--format", "json", "--hash", "sha384,sha256"]);
+        let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+        let sha256 = Algorithm::Sha256.digest(&[0xAA; 4096]).to_string();
+        assert!(report["linux"]["digest"]
+            .as_str()
+            .unwrap()
+            .starts_with("sha384:"));
+        assert_eq!(report["linux"]["extra_digests"][0], sha256.as_str());
+        assert_eq!(
+            run(&[
+                &path,
+                "--get",
+                "linux.extra_digests.0",
+                "--hash",
+                "sha512",
+                "--hash",
+                "sha256"
+            ]),
+            format!("{sha256}\n")
+        );
+        // The SHA-256 porcelain keys still fill in when it is not the first.
+        let (result, out) = run_with(&ctx(true), &[&path, "--hash", "sha512,sha256"]);
+        result.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains(&format!("linux_sha256={}\n", &sha256[7..])),
+            "{out}"
+        );
+        assert!(Inspect::try_parse_from([
+            "inspect", "--digest", "sha512", "--hash", "sha256", "x.efi"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn signed_reports_name_the_uki_they_describe() {
+        let dir = tempfile::tempdir().unwrap();
+        let (key, public) = crate::sign::tests::key_pair(dir.path());
+        let image = uki(&lowell_test_util::newc(&[(
+            "init",
+            0o100755,
+            b"#!/bin/sh\n",
+        )]))
+        .build();
+        let path = write(dir.path(), &image);
+        let out = run(&[&path, "--sign-key", key.to_str().unwrap()]);
+
+        let envelope: Envelope = serde_json::from_str(&out).unwrap();
+        assert_eq!(envelope.payload_type, provenance::IN_TOTO);
+        let public = sign::verifying_key(&public).unwrap();
+        let statement: serde_json::Value =
+            serde_json::from_slice(&envelope.open(&public).unwrap()).unwrap();
+        assert_eq!(
+            statement["predicateType"],
+            provenance::REPORT_PREDICATE_TYPE
+        );
+        assert_eq!(statement["subject"][0]["name"], "uki.efi");
+        assert_eq!(
+            statement["subject"][0]["digest"]["sha256"],
+            Algorithm::Sha256.digest(&image).hex
+        );
+        // The full report, digests included, whatever the output format.
+        assert_eq!(statement["predicate"]["cmdline"], "quiet");
+        assert_eq!(
+            statement["predicate"]["linux"]["digest"],
+            Algorithm::Sha256.digest(&[0xAA; 4096]).to_string()
+        );
+
+        // A configured key signs only when `--sign` asks for it.
+        let (result, _) = run_with(&ctx(false), &[&path, "--sign"]);
+        assert_eq!(code_of(&result.unwrap_err()), ErrorCode::InvalidArgument);
+        let ctx = Ctx {
+            cfg: Config {
+                signing_key: Some(key.clone()),
+                ..Config::default()
+            },
+            ..ctx(false)
+        };
+        let run = |args: &[&str]| {
+            let (result, out) = run_with(&ctx, args);
+            result.unwrap();
+            out
+        };
+        let report: serde_json::Value =
+            serde_json::from_slice(&run(&[&path, "--format", "json"])).unwrap();
+        assert_eq!(report["cmdline"], "quiet");
+        let envelope: Envelope = serde_json::from_slice(&run(&[&path, "--sign"])).unwrap();
+        assert!(envelope.open(&public).is_ok());
+    }
+
+    #[test]
+    fn initrd_entries_are_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let initrd = lowell_test_util::Initrd::new()
+            .dir("usr")
+            .file("usr/init", b"#!/bin/sh\n")
+            .symlink("init", "usr/init")
+            .build();
+        let path = write(dir.path(), &uki(&initrd).build());
+
+        let out = run(&[&path, "--list-files"]);
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 3, "{out}");
+        assert!(lines[1].ends_with(" 10 usr/init"), "{out}");
+        assert!(lines[2].ends_with("init -> usr/init"), "{out}");
+        let out = run(&[&path, "--list-files", "--format", "json"]);
+        let entry: serde_json::Value = serde_json::from_str(out.lines().nth(1).unwrap()).unwrap();
+        assert_eq!(entry["path"], "usr/init");
+        assert_eq!(entry["size"], 10);
+    }
+
+    #[test]
+    fn initrd_sizes_are_totalled() {
+        let dir = tempfile::tempdir().unwrap();
+        let initrd = lowell_test_util::Initrd::new()
+            .file("usr/lib/libc.so.6", &[0; 2000])
+            .file("etc/hostname", b"box\n")
+            .build();
+        let path = write(dir.path(), &uki(&initrd).build());
+        let (result, out) = run_with(&ctx(true), &[&path, "--size-report", "--top", "1"]);
+        result.unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2000\t1\tusr\n4\t1\tetc\n\n2000\t1\tusr/lib/libc.so.6\n"
+        );
+        let both = ["inspect", "a.efi", "--size-report", "--list-files"];
+        assert!(Inspect::try_parse_from(both).is_err());
+    }
+
+    #[test]
+    fn sections_are_dumped_raw() {
+        let dir = tempfile::tempdir().unwrap();
+        let sbat = b"sbat,1,SBAT Version,sbat,1,https://github.com/rhboot/shim/blob/main/SBAT.md\n";
+        let image = UkiBuilder::new()
+            .cmdline("quiet")
+            .linux(&[0xAA; 4096])
+            .section(".sbat", sbat)
+            .build();
+        let path = write(dir.path(), &image);
+
+        assert_eq!(run(&[&path, "--dump-section", ".sbat"]).as_bytes(), sbat);
+        let dest = dir.path().join("sbat.csv");
+        let args = [
+            &path,
+            "--dump-section",
+            ".cmdline",
+            "--out",
+            dest.to_str().unwrap(),
+        ];
+        assert!(run(&args).is_empty());
+        assert_eq!(std::fs::read(&dest).unwrap(), b"quiet\0");
+        let (result, _) = run_with(&ctx(false), &[&path, "--dump-section", ".pcrsig"]);
+        let err = result.unwrap_err();
+        assert_eq!(code_of(&err), ErrorCode::SectionMissing);
+        assert!(err.to_string().contains("has: "), "{err}");
+    }
+
+    #[test]
+    fn kernel_config_is_dumped() {
+        use lowell_test_util::{compress, Compression};
+        let dir = tempfile::tempdir().unwrap();
+        let config = "CONFIG_IKCONFIG=y\nCONFIG_SECURITY_LOCKDOWN_LSM=y\n";
+        // An arm64 Image: the magic at 0x38, then the kernel proper.
+        let mut linux = vec![0u8; 0x100];
+        linux[0x38..0x3c].copy_from_slice(b"ARM\x64");
+        linux.extend_from_slice(b"IKCFG_ST");
+        linux.extend(compress(config.as_bytes(), Compression::Gzip));
+        linux.extend_from_slice(b"IKCFG_ED");
+        let dump = |linux: &[u8], extra: &[&str]| {
+            let image = UkiBuilder::new().cmdline("quiet").linux(linux).build();
+            let path = write(dir.path(), &image);
+            let mut argv = vec![path.as_str(), "--dump-kernel-config"];
+            argv.extend(extra);
+            run_with(&ctx(false), &argv)
+        };
+
+        let (result, out) = dump(&linux, &[]);
+        result.unwrap();
+        assert_eq!(out, config.as_bytes());
+        let dest = dir.path().join("config");
+        let (result, out) = dump(&linux, &["--out", dest.to_str().unwrap()]);
+        result.unwrap();
+        assert!(out.is_empty());
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), config);
+
+        let (result, _) = dump(&linux[..0x100], &[]);
+        assert_eq!(code_of(&result.unwrap_err()), ErrorCode::NotFound);
+        let (result, _) = dump(&[0xAA; 4096], &[]);
+        assert_eq!(code_of(&result.unwrap_err()), ErrorCode::NotKernel);
+        let both = [
+            "inspect",
+            "a.efi",
+            "--dump-kernel-config",
+            "--dump-section",
+            ".sbat",
+        ];
+        assert!(Inspect::try_parse_from(both).is_err());
+    }
+}
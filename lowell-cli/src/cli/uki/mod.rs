@@ -1,8 +1,12 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
-mod inspect;
+mod edit;
+pub(super) mod inspect;
+mod pcrs;
 
+use crate::cli::{Ctx, ReportFormat};
 use anyhow::Result;
 use clap::{Args, Subcommand};
+use std::io::Write;
 
 #[derive(Args, Debug)]
 pub struct UkiArgs {
@@ -14,12 +18,27 @@ pub struct UkiArgs {
 enum UkiCmd {
     /// Inspect contents from a UKI
     Inspect(inspect::InspectArgs),
+    /// Replace, add or remove sections of a UKI (drops any signature)
+    Edit(edit::EditArgs),
+    /// Predict the PCR 11 and PCR 4 measurements of a UKI and check them
+    /// against a TPM event log
+    Pcrs(pcrs::PcrsArgs),
 }
 
 impl UkiArgs {
-    pub fn run(self) -> Result<()> {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        match &self.cmd {
+            UkiCmd::Inspect(a) => a.json_errors(default),
+            UkiCmd::Edit(_) => false,
+            UkiCmd::Pcrs(a) => a.json_errors(default),
+        }
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
         match self.cmd {
-            UkiCmd::Inspect(a) => a.run(),
+            UkiCmd::Inspect(a) => a.run(ctx, out),
+            UkiCmd::Edit(a) => a.run(ctx),
+            UkiCmd::Pcrs(a) => a.run(ctx, out),
         }
     }
 }
@@ -0,0 +1,321 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cli::{Ctx, ReportFormat};
+use crate::error::Coded;
+use crate::refvalues;
+use crate::style::Palette;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::pe::PeFile;
+use lowell_core::formats::tpm::{self, Bank, EventLog, Measurement};
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct PcrsArgs {
+    /// Path to the UKI
+    file: PathBuf,
+    /// Compare with a TCG event log, e.g.
+    /// `/sys/kernel/security/tpm0/binary_bios_measurements`
+    #[arg(long, value_name = "FILE")]
+    event_log: Option<PathBuf>,
+    /// PCR bank: sha1, sha256, sha384 or sha512
+    #[arg(long, default_value_t = Bank::Sha256)]
+    bank: Bank,
+    /// Print reference values for attestation verifiers in FORMAT instead
+    /// of the prediction report
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "event_log")]
+    reference: Option<refvalues::Format>,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    bank: Bank,
+    pcr11: Pcr11,
+    pcr4: Vec<Image>,
+}
+
+#[derive(Serialize)]
+struct Pcr11 {
+    predicted: String,
+    measurements: Vec<Step>,
+    /// From the event log, when one was given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replayed: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Step {
+    label: String,
+    digest: String,
+    /// Whether the event log's measurement at this position agrees.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logged: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct Image {
+    /// `image` (the UKI) or `.linux`.
+    label: String,
+    digest: String,
+    /// Whether a PCR 4 event in the log has this digest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    measured: Option<bool>,
+}
+
+impl Report {
+    /// Why the log disagrees with the prediction, if it does.
+    fn mismatch(&self) -> Option<String> {
+        if self.pcr11.replayed.as_ref()? != &self.pcr11.predicted {
+            return Some("PCR 11 does not match the prediction".into());
+        }
+        match self.pcr4.first() {
+            Some(image) if image.measured == Some(false) => {
+                Some("the UKI's digest is not in PCR 4".into())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl PcrsArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let pe = PeFile::from_path_mmap(&self.file)?;
+        if let Some(format) = self.reference {
+            let name = self.file.file_stem().unwrap_or_default().to_string_lossy();
+            let doc = refvalues::document(&pe, self.bank, format, &name)?;
+            match self.format.or(ctx.cfg.format) {
+                Some(ReportFormat::Json) => serde_json::to_writer(&mut *out, &doc)?,
+                _ => serde_json::to_writer_pretty(&mut *out, &doc)?,
+            }
+            out.write_all(b"\n")?;
+            return Ok(());
+        }
+        let log = match &self.event_log {
+            Some(path) => {
+                let bytes =
+                    std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+                let log = EventLog::parse(&bytes)
+                    .with_context(|| format!("{}: not a TCG event log", path.display()))?;
+                if !log.banks.contains(&self.bank) {
+                    bail!(Coded::new(
+                        ErrorCode::InvalidArgument,
+                        format!(
+                            "{} has no {} bank (it has {})",
+                            path.display(),
+                            self.bank,
+                            log.banks
+                                .iter()
+                                .map(|b| b.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    ));
+                }
+                Some(log)
+            }
+            None => None,
+        };
+        let report = self.report(&pe, log.as_ref())?;
+        match self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human)
+        {
+            ReportFormat::Human => print_human(out, &report, ctx.palette)?,
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+            ReportFormat::JsonPretty => {
+                serde_json::to_writer_pretty(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        if let Some(why) = report.mismatch() {
+            bail!(Coded::new(ErrorCode::PcrMismatch, why));
+        }
+        Ok(())
+    }
+
+    fn report(&self, pe: &PeFile, log: Option<&EventLog>) -> Result<Report> {
+        let bank = self.bank;
+        let steps = tpm::pcr11(pe, bank)?;
+        let logged: Option<Vec<&[u8]>> =
+            log.map(|l| l.measurements(11).filter_map(|e| e.digest(bank)).collect());
+        let measurements = steps
+            .iter()
+            .enumerate()
+            .map(|(i, m)| Step {
+                label: m.label.clone(),
+                digest: tpm::hex(&m.digest),
+                logged: logged
+                    .as_ref()
+                    .map(|l| l.get(i) == Some(&m.digest.as_slice())),
+            })
+            .collect();
+        let replayed = log.map(|l| {
+            let pcrs = l.replay(bank).unwrap_or_default();
+            pcrs.get(&11)
+                .map_or_else(|| tpm::hex(&vec![0; bank.size()]), |v| tpm::hex(v))
+        });
+        let pcr4 = tpm::pcr4(pe, bank)?
+            .into_iter()
+            .map(|Measurement { label, digest }| Image {
+                measured: log.map(|l| {
+                    l.measurements(4).any(|e| {
+                        e.kind == tpm::EV_EFI_BOOT_SERVICES_APPLICATION
+                            && e.digest(bank) == Some(&digest)
+                    })
+                }),
+                label,
+                digest: tpm::hex(&digest),
+            })
+            .collect();
+        Ok(Report {
+            bank,
+            pcr11: Pcr11 {
+                predicted: tpm::hex(&tpm::predict(bank, &steps)),
+                measurements,
+                replayed,
+            },
+            pcr4,
+        })
+    }
+}
+
+fn print_human(out: &mut dyn Write, r: &Report, palette: Palette) -> Result<()> {
+    writeln!(out, "{} ({})", palette.bold("PCR 11"), r.bank)?;
+    writeln!(out, "  predicted  {}", r.pcr11.predicted)?;
+    if let Some(replayed) = &r.pcr11.replayed {
+        let verdict = match replayed == &r.pcr11.predicted {
+            true => palette.good("match").to_string(),
+            false => palette.bad("MISMATCH").to_string(),
+        };
+        writeln!(out, "  replayed   {replayed}  {verdict}")?;
+        for step in &r.pcr11.measurements {
+            if step.logged == Some(false) {
+                writeln!(out, "  first difference: {}", step.label)?;
+                break;
+            }
+        }
+    }
+    writeln!(out, "{} ({})", palette.bold("PCR 4"), r.bank)?;
+    for image in &r.pcr4 {
+        let verdict = match image.measured {
+            Some(true) => palette.good("measured").to_string(),
+            Some(false) => palette.bad("not measured").to_string(),
+            None => String::new(),
+        };
+        let line = format!("  {:<9}  {}  {verdict}", image.label, image.digest);
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Pcrs {
+        #[command(flatten)]
+        args: PcrsArgs,
+    }
+
+    /// An agile log with only SHA-256 digests.
+    fn log(events: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let mut spec = b"Spec ID Event03\0".to_vec();
+        spec.extend([0, 0, 0, 0, 0, 2, 0, 2]);
+        spec.extend(1u32.to_le_bytes());
+        spec.extend([0x0b, 0, 32, 0, 0]);
+        let mut log = Vec::new();
+        for v in [0, tpm::EV_NO_ACTION] {
+            log.extend(v.to_le_bytes());
+        }
+        log.extend([0; 20]);
+        log.extend((spec.len() as u32).to_le_bytes());
+        log.extend(spec);
+        for &(pcr, kind, digest) in events {
+            for v in [pcr, kind, 1] {
+                log.extend(v.to_le_bytes());
+            }
+            log.extend([0x0b, 0]);
+            log.extend(digest);
+            log.extend(0u32.to_le_bytes());
+        }
+        log
+    }
+
+    #[test]
+    fn event_logs_check_against_predictions() {
+        let dir = tempfile::tempdir().unwrap();
+        let uki = dir.path().join("uki.efi");
+        std::fs::write(
+            &uki,
+            lowell_test_util::UkiBuilder::new()
+                .cmdline("quiet")
+                .linux(&[0xAA; 4096])
+                .build(),
+        )
+        .unwrap();
+        let pe = PeFile::from_path(&uki).unwrap();
+        let (pcr11, pcr4) = (
+            tpm::pcr11(&pe, Bank::Sha256).unwrap(),
+            tpm::pcr4(&pe, Bank::Sha256).unwrap(),
+        );
+        let ctx = Ctx {
+            cfg: Config::default(),
+            palette: Palette::new(false),
+            porcelain: false,
+        };
+        let check = |events: &[(u32, u32, &[u8])]| {
+            let path = dir.path().join("log.bin");
+            std::fs::write(&path, log(events)).unwrap();
+            let argv = [
+                "pcrs",
+                uki.to_str().unwrap(),
+                "--event-log",
+                path.to_str().unwrap(),
+            ];
+            let mut out = Vec::new();
+            let result = Pcrs::parse_from(argv).args.run(&ctx, &mut out);
+            (result, String::from_utf8(out).unwrap())
+        };
+
+        let app = tpm::EV_EFI_BOOT_SERVICES_APPLICATION;
+        let mut events: Vec<(u32, u32, &[u8])> =
+            vec![(4, app, &[0x11; 32]), (4, app, &pcr4[0].digest)];
+        events.extend(pcr11.iter().map(|m| (11, tpm::EV_IPL, m.digest.as_slice())));
+        let (result, out) = check(&events);
+        result.unwrap();
+        assert!(out.contains("match") && out.contains("measured"), "{out}");
+
+        // A different command line than the one booted.
+        events[5].2 = &[0x22; 32];
+        let (result, out) = check(&events);
+        let err = result.unwrap_err();
+        assert_eq!(crate::error::code_of(&err), ErrorCode::PcrMismatch);
+        assert!(out.contains("first difference: .cmdline data"), "{out}");
+
+        // Booted some other UKI.
+        let (result, _) = check(&[(4, app, &[0x11; 32])]);
+        assert_eq!(
+            crate::error::code_of(&result.unwrap_err()),
+            ErrorCode::PcrMismatch
+        );
+    }
+}
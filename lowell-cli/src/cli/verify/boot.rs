@@ -0,0 +1,424 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `lowell verify boot`: on the booted system, check that it runs the UKI
+//! it was meant to.
+//!
+//! * **cmdline**: `/proc/cmdline` is the UKI's `.cmdline`, possibly
+//!   followed by arguments from add-ons
+//! * **kernel**: the running release (`uname -r`) is the UKI's kernel's
+//! * **microcode**: on x86, the running revision is at least the newest
+//!   one the UKI carries for this CPU (firmware may have loaded a newer
+//!   one)
+//! * with `--pcrs`, from the TCG event log: the UKI's Authenticode digest
+//!   was measured into PCR 4, the log replays to the TPM's current PCR 4,
+//!   and PCR 11 as the stub left it is the predicted value
+
+use crate::cli::{Ctx, ReportFormat};
+use crate::error::Coded;
+use crate::style::Palette;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::microcode::{self, Vendor};
+use lowell_core::formats::pe::PeFile;
+use lowell_core::formats::tpm::{self, Bank, EventLog};
+use lowell_core::uki::inspect;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const EVENT_LOG: &str = "sys/kernel/security/tpm0/binary_bios_measurements";
+
+#[derive(Args, Debug)]
+pub struct BootArgs {
+    /// The UKI the system should have booted
+    #[arg(long, value_name = "FILE")]
+    uki: PathBuf,
+    /// Also check the TPM measurements (needs the event log, usually root)
+    #[arg(long)]
+    pcrs: bool,
+    /// PCR bank for --pcrs: sha1, sha256, sha384 or sha512
+    #[arg(long, default_value_t = Bank::Sha256, requires = "pcrs")]
+    bank: Bank,
+    /// Output format (default: `format` from config, else human)
+    #[arg(long, value_enum)]
+    format: Option<ReportFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pass,
+    Fail,
+    Skip,
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    expected: Option<String>,
+    actual: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, expected: Option<String>, actual: Option<String>) -> Self {
+        let status = match (&expected, &actual) {
+            (Some(e), Some(a)) if e == a => Status::Pass,
+            (Some(_), Some(_)) => Status::Fail,
+            _ => Status::Skip,
+        };
+        Self {
+            name,
+            status,
+            expected,
+            actual,
+            note: None,
+        }
+    }
+
+    fn note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    uki: PathBuf,
+    booted: bool,
+    checks: Vec<Check>,
+}
+
+/// What the running system reports about itself, read from `/proc` and
+/// `/sys` below a root directory.
+#[derive(Debug, Default)]
+struct Host {
+    cmdline: Option<String>,
+    release: Option<String>,
+    cpu: Option<Cpu>,
+    event_log: Option<EventLog>,
+    /// The TPM's current PCR 4 in the checked bank.
+    pcr4: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cpu {
+    vendor: Vendor,
+    signature: u32,
+    microcode: u32,
+}
+
+impl Host {
+    fn read(root: &Path, pcrs: Option<Bank>) -> Result<Self> {
+        let text = |rel: &str| {
+            std::fs::read_to_string(root.join(rel))
+                .ok()
+                .map(|s| s.trim().to_string())
+        };
+        let mut host = Host {
+            cmdline: text("proc/cmdline"),
+            release: text("proc/sys/kernel/osrelease"),
+            cpu: text("proc/cpuinfo").and_then(|c| cpuinfo(&c)),
+            ..Default::default()
+        };
+        if let Some(bank) = pcrs {
+            let path = root.join(EVENT_LOG);
+            let bytes = std::fs::read(&path)
+                .with_context(|| format!("read {} (run as root, with a TPM)", path.display()))?;
+            let log = EventLog::parse(&bytes)
+                .with_context(|| format!("{}: not a TCG event log", path.display()))?;
+            host.event_log = Some(log);
+            host.pcr4 =
+                text(&format!("sys/class/tpm/tpm0/pcr-{bank}/4")).map(|v| v.to_ascii_lowercase());
+        }
+        Ok(host)
+    }
+}
+
+/// The first CPU's vendor, signature and microcode revision.
+fn cpuinfo(text: &str) -> Option<Cpu> {
+    let first = text.split("\n\n").next()?;
+    let field = |key: &str| {
+        first.lines().find_map(|l| {
+            let (k, v) = l.split_once(':')?;
+            (k.trim() == key).then(|| v.trim())
+        })
+    };
+    let number = |key: &str| field(key)?.parse::<u32>().ok();
+    let microcode = field("microcode")?;
+    Some(Cpu {
+        vendor: Vendor::from_id(field("vendor_id")?)?,
+        signature: microcode::signature(
+            number("cpu family")?,
+            number("model")?,
+            number("stepping")?,
+        ),
+        microcode: u32::from_str_radix(microcode.trim_start_matches("0x"), 16).ok()?,
+    })
+}
+
+impl BootArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        matches!(
+            self.format.or(default),
+            Some(ReportFormat::Json | ReportFormat::JsonPretty)
+        )
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let pe = PeFile::from_path_mmap(&self.uki)
+            .with_context(|| format!("{}: not a UKI", self.uki.display()))?;
+        let host = Host::read(Path::new("/"), self.pcrs.then_some(self.bank))?;
+        let checks = checks(&pe, &host, self.bank)?;
+        let report = Report {
+            uki: self.uki.clone(),
+            booted: checks.iter().all(|c| c.status != Status::Fail),
+            checks,
+        };
+        match self
+            .format
+            .or(ctx.cfg.format)
+            .unwrap_or(ReportFormat::Human)
+        {
+            ReportFormat::Human if ctx.porcelain => print_porcelain(out, &report)?,
+            ReportFormat::Human => print_human(out, &report, ctx.palette)?,
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+            ReportFormat::JsonPretty => {
+                serde_json::to_writer_pretty(&mut *out, &report)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        if !report.booted {
+            let failed: Vec<&str> = report
+                .checks
+                .iter()
+                .filter(|c| c.status == Status::Fail)
+                .map(|c| c.name)
+                .collect();
+            bail!(Coded::new(
+                ErrorCode::BootMismatch,
+                format!(
+                    "this system did not boot {} ({} differ)",
+                    self.uki.display(),
+                    failed.join(", ")
+                )
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn checks(pe: &PeFile, host: &Host, bank: Bank) -> Result<Vec<Check>> {
+    let mut checks = Vec::new();
+
+    let cmdline = pe.read_text(".cmdline")?.map(|c| c.trim().to_string());
+    let mut check = Check::new("cmdline", cmdline.clone(), host.cmdline.clone());
+    if let (Some(uki), Some(running)) = (&cmdline, &host.cmdline) {
+        if let Some(extra) = running.strip_prefix(uki.as_str()) {
+            if extra.starts_with(' ') {
+                check.status = Status::Pass;
+                check = check.note(format!("extra arguments:{extra}"));
+            }
+        }
+    }
+    checks.push(check);
+
+    checks.push(Check::new(
+        "kernel",
+        inspect::kernel_release(pe)?,
+        host.release.clone(),
+    ));
+
+    checks.push(microcode_check(pe, host)?);
+
+    if let Some(log) = &host.event_log {
+        let image = tpm::hex(&tpm::pcr4(pe, bank)?[0].digest);
+        let measured = log.measurements(4).any(|e| {
+            e.kind == tpm::EV_EFI_BOOT_SERVICES_APPLICATION
+                && e.digest(bank).map(tpm::hex).as_ref() == Some(&image)
+        });
+        let logged = measured.then(|| image.clone());
+        checks.push(
+            Check {
+                status: if measured { Status::Pass } else { Status::Fail },
+                ..Check::new("pcr4", Some(image), logged)
+            }
+            .note("the UKI's Authenticode digest among PCR 4's image events"),
+        );
+        let replayed = log.replay(bank).unwrap_or_default();
+        let zero = || tpm::hex(&vec![0; bank.size()]);
+        checks.push(
+            Check::new(
+                "event-log",
+                Some(replayed.get(&4).map_or_else(zero, |v| tpm::hex(v))),
+                host.pcr4.clone(),
+            )
+            .note("PCR 4 replayed from the log, against the TPM"),
+        );
+        let predicted = tpm::hex(&tpm::predict(bank, &tpm::pcr11(pe, bank)?));
+        checks.push(Check::new(
+            "pcr11",
+            Some(predicted),
+            Some(replayed.get(&11).map_or_else(zero, |v| tpm::hex(v))),
+        ));
+    }
+    Ok(checks)
+}
+
+/// The running microcode against the newest the UKI carries for this CPU,
+/// in `.ucode` or the early archive of `.initrd`.
+fn microcode_check(pe: &PeFile, host: &Host) -> Result<Check> {
+    let skip = |why: &str| Ok(Check::new("microcode", None, None).note(why));
+    let Some(cpu) = host.cpu else {
+        return skip("no x86 microcode revision in /proc/cpuinfo");
+    };
+    let mut carried = None;
+    for section in [".ucode", ".initrd"] {
+        if let Some(data) = pe.section_contents(section)? {
+            if let Some(blob) = microcode::from_cpio(data, cpu.vendor)? {
+                carried = microcode::revision(&blob, cpu.vendor, cpu.signature);
+                break;
+            }
+        }
+    }
+    let Some(carried) = carried else {
+        return skip("the UKI carries no microcode for this CPU");
+    };
+    let check = Check::new(
+        "microcode",
+        Some(format!("{carried:#x}")),
+        Some(format!("{:#x}", cpu.microcode)),
+    );
+    Ok(match cpu.microcode {
+        r if r > carried => Check {
+            status: Status::Pass,
+            ..check
+        }
+        .note("newer than the UKI's (loaded by firmware)"),
+        _ => check,
+    })
+}
+
+fn print_human(out: &mut dyn Write, r: &Report, palette: Palette) -> Result<()> {
+    for c in &r.checks {
+        let status = match c.status {
+            Status::Pass => palette.good("PASS").to_string(),
+            Status::Fail => palette.bad("FAIL").to_string(),
+            Status::Skip => "SKIP".to_string(),
+        };
+        let detail = match (c.status, &c.expected, &c.actual) {
+            (Status::Fail, Some(e), Some(a)) => format!("expected {e}, running {a}"),
+            (Status::Fail, Some(e), None) => format!("expected {e}, not found"),
+            (_, _, Some(a)) => a.clone(),
+            _ => String::new(),
+        };
+        let note = match (&c.note, detail.is_empty()) {
+            (Some(note), true) => note.clone(),
+            (Some(note), false) => format!(" ({note})"),
+            (None, _) => String::new(),
+        };
+        writeln!(out, "{status} {:<10} {detail}{note}", c.name)?;
+    }
+    let verdict = match r.booted {
+        true => palette.good("booted").to_string(),
+        false => palette.bad("did not boot").to_string(),
+    };
+    writeln!(out, "{verdict} {}", r.uki.display())?;
+    Ok(())
+}
+
+fn print_porcelain(out: &mut dyn Write, r: &Report) -> Result<()> {
+    for c in &r.checks {
+        let status = match c.status {
+            Status::Pass => "pass",
+            Status::Fail => "fail",
+            Status::Skip => "skip",
+        };
+        writeln!(
+            out,
+            "{}\t{status}\t{}\t{}",
+            c.name,
+            c.expected.as_deref().unwrap_or("-"),
+            c.actual.as_deref().unwrap_or("-")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::{Initrd, UkiBuilder};
+
+    #[test]
+    fn cpuinfo_describes_the_first_cpu() {
+        let text = "processor\t: 0\nvendor_id\t: GenuineIntel\ncpu family\t: 6\nmodel\t\t: 85\n\
+                    stepping\t: 4\nmicrocode\t: 0x2007006\n\nprocessor\t: 1\n";
+        let cpu = cpuinfo(text).unwrap();
+        assert_eq!(cpu.vendor, Vendor::Intel);
+        assert_eq!(cpu.signature, 0x50654);
+        assert_eq!(cpu.microcode, 0x2007006);
+        assert_eq!(cpuinfo("processor\t: 0\nCPU implementer\t: 0x41\n"), None);
+    }
+
+    #[test]
+    fn the_booted_system_is_checked_against_the_uki() {
+        let mut ucode = vec![0u8; 48 + 16];
+        ucode[0] = 1;
+        ucode[4..8].copy_from_slice(&0x2007006u32.to_le_bytes());
+        ucode[12..16].copy_from_slice(&0x50654u32.to_le_bytes());
+        ucode[28] = 16;
+        ucode[32] = 64;
+        let initrd = Initrd::new()
+            .early(Vendor::Intel.cpio_path(), &ucode)
+            .file("init", b"#!/bin/sh\n")
+            .build();
+        let uki = UkiBuilder::new()
+            .cmdline("root=/dev/vda2 quiet")
+            .linux(&[0xAA; 4096])
+            .initrd(&initrd)
+            .section(".uname", b"6.11.4-301.fc41.x86_64\n")
+            .build();
+        let pe = PeFile::from_bytes(uki).unwrap();
+
+        let cpu = Cpu {
+            vendor: Vendor::Intel,
+            signature: 0x50654,
+            microcode: 0x2007006,
+        };
+        let host = Host {
+            cmdline: Some("root=/dev/vda2 quiet systemd.debug".into()),
+            release: Some("6.11.4-301.fc41.x86_64".into()),
+            cpu: Some(cpu),
+            ..Default::default()
+        };
+        let status = |checks: &[Check]| checks.iter().map(|c| c.status).collect::<Vec<_>>();
+        let result = checks(&pe, &host, Bank::Sha256).unwrap();
+        assert_eq!(status(&result), [Status::Pass; 3]);
+        assert_eq!(
+            result[0].note.as_deref(),
+            Some("extra arguments: systemd.debug")
+        );
+
+        let host = Host {
+            cmdline: Some("root=/dev/vda2".into()),
+            release: Some("6.10.0".into()),
+            cpu: Some(Cpu {
+                microcode: 0x100,
+                ..cpu
+            }),
+            ..Default::default()
+        };
+        let result = checks(&pe, &host, Bank::Sha256).unwrap();
+        assert_eq!(status(&result), [Status::Fail; 3]);
+        assert_eq!(result[2].expected.as_deref(), Some("0x2007006"));
+
+        let result = checks(&pe, &Host::default(), Bank::Sha256).unwrap();
+        assert_eq!(status(&result), [Status::Skip; 3]);
+    }
+}
@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+mod boot;
+
+use crate::cli::{Ctx, ReportFormat};
+use crate::error::Coded;
+use crate::sign::{self, Envelope};
+use anyhow::{bail, Context, Result};
+use clap::{Args, Subcommand};
+use lowell_core::error::ErrorCode;
+use p256::ecdsa::VerifyingKey;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct VerifyArgs {
+    #[command(subcommand)]
+    cmd: Option<VerifyCmd>,
+    /// Files to check, each against `<FILE>.sig`; a DSSE envelope (such as
+    /// `build --provenance` output) with no `.sig` is checked on its own
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+    /// Public key (PEM, e.g. `cosign.pub`)
+    #[arg(long, value_name = "FILE", required = true)]
+    key: Option<PathBuf>,
+    /// Read the signature from SIG instead of `<FILE>.sig` (one FILE only)
+    #[arg(long, value_name = "SIG")]
+    signature: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum VerifyCmd {
+    /// On a booted system, check that it booted a UKI: its cmdline, kernel
+    /// release, microcode and (with --pcrs) TPM measurements
+    Boot(boot::BootArgs),
+}
+
+impl VerifyArgs {
+    pub fn json_errors(&self, default: Option<ReportFormat>) -> bool {
+        match &self.cmd {
+            Some(VerifyCmd::Boot(a)) => a.json_errors(default),
+            None => false,
+        }
+    }
+
+    pub fn run(self, ctx: &Ctx, out: &mut dyn Write) -> Result<()> {
+        let key = match self.cmd {
+            Some(VerifyCmd::Boot(a)) => return a.run(ctx, out),
+            None => self.key.as_deref().expect("clap requires --key"),
+        };
+        if self.signature.is_some() && self.files.len() > 1 {
+            bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                "--signature takes a single FILE"
+            ));
+        }
+        let key = sign::verifying_key(key)?;
+        let mut failed = 0;
+        for file in &self.files {
+            let sig = self
+                .signature
+                .clone()
+                .unwrap_or_else(|| sign::sig_path(file));
+            let checked = if self.signature.is_none() && !sig.exists() {
+                check_envelope(&key, file)
+            } else {
+                std::fs::read_to_string(&sig)
+                    .with_context(|| format!("read {}", sig.display()))
+                    .and_then(|s| {
+                        let data = std::fs::read(file)
+                            .with_context(|| format!("read {}", file.display()))?;
+                        sign::verify(&key, &data, &s)
+                    })
+            };
+            match checked {
+                Ok(()) => writeln!(out, "{}: OK", file.display())?,
+                Err(e) if self.files.len() == 1 => {
+                    return Err(e.context(format!("{}: not verified", file.display())))
+                }
+                Err(e) => {
+                    warn!(path = %file.display(), "not verified: {e:#}");
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            bail!(Coded::new(
+                ErrorCode::BadSignature,
+                format!("{failed} of {} files failed verification", self.files.len())
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `file` as a DSSE envelope signed by `key`.
+fn check_envelope(key: &VerifyingKey, file: &Path) -> Result<()> {
+    let data = std::fs::read(file).with_context(|| format!("read {}", file.display()))?;
+    let envelope: Envelope = serde_json::from_slice(&data).map_err(|_| {
+        Coded::new(
+            ErrorCode::BadSignature,
+            format!(
+                "no {} and not a DSSE envelope",
+                sign::sig_path(file).display()
+            ),
+        )
+    })?;
+    envelope.open(key).map(drop)
+}
@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Persistent defaults from `config.toml`.
+//!
+//! Lookup order (later wins, field by field):
+//! 1. `/etc/lowell/config.toml`
+//! 2. `$XDG_CONFIG_HOME/lowell/config.toml` (or `~/.config/lowell/config.toml`,
+//!    or `%APPDATA%\lowell\config.toml` on Windows)
+//!
+//! `--config <PATH>` replaces both. Command-line flags always take precedence
+//! over anything set here.
+
+use crate::cli::ReportFormat;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+const SYSTEM_CONFIG: &str = "/etc/lowell/config.toml";
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Default report format for inspect commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<ReportFormat>,
+    /// EFI System Partition mount point
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub esp: Option<PathBuf>,
+    /// Key for `sign` without `--key`, and for `--sign` (a cosign key, see
+    /// `lowell sign`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<PathBuf>,
+    /// Cache directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
+    /// Directories searched for `<name>.toml` profiles
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profile_path: Vec<PathBuf>,
+    /// Files this configuration was assembled from (not a config key)
+    #[serde(skip)]
+    pub loaded_from: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Load the effective configuration.
+    ///
+    /// With `explicit`, only that file is read (and it must exist).
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        if let Some(path) = explicit {
+            return Self::from_path(path);
+        }
+        let mut config = Config::default();
+        for path in [Some(PathBuf::from(SYSTEM_CONFIG)), user_config_path()]
+            .into_iter()
+            .flatten()
+        {
+            if path.is_file() {
+                config = config.merge(Self::from_path(&path)?);
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(&text).with_context(|| format!("parse {}", path.display()))?;
+        config.loaded_from.push(path.to_path_buf());
+        debug!(path = %path.display(), "loaded config");
+        Ok(config)
+    }
+
+    /// Overlay `other` on top of `self`: set fields win, search paths of
+    /// `other` are tried first.
+    pub fn merge(self, other: Config) -> Config {
+        let mut profile_path = other.profile_path;
+        profile_path.extend(self.profile_path);
+        let mut loaded_from = self.loaded_from;
+        loaded_from.extend(other.loaded_from);
+        Config {
+            format: other.format.or(self.format),
+            esp: other.esp.or(self.esp),
+            signing_key: other.signing_key.or(self.signing_key),
+            cache_dir: other.cache_dir.or(self.cache_dir),
+            profile_path,
+            loaded_from,
+        }
+    }
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(base.join("lowell/config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_config_overrides_system_field_by_field() {
+        let system: Config = toml::from_str(
+            "format = \"json\"\nesp = \"/boot/efi\"\nprofile-path = [\"/usr/share/lowell/profiles\"]\n",
+        )
+        .unwrap();
+        let user: Config =
+            toml::from_str("esp = \"/efi\"\nprofile-path = [\"/home/me/profiles\"]\n").unwrap();
+
+        let merged = system.merge(user);
+        assert_eq!(merged.format, Some(ReportFormat::Json));
+        assert_eq!(merged.esp.as_deref(), Some(Path::new("/efi")));
+        assert_eq!(
+            merged.profile_path,
+            [
+                PathBuf::from("/home/me/profiles"),
+                PathBuf::from("/usr/share/lowell/profiles")
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("colour = \"always\"\n").is_err());
+    }
+}
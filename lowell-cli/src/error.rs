@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Error codes for `anyhow` errors in the CLI.
+//!
+//! Library failures arrive as [`lowell_core::Error`] and carry their own
+//! code. Places in the CLI that know *why* something failed attach a
+//! [`Coded`] (as the error itself or as context), and [`code_of`] recovers
+//! the code from any error chain. I/O errors without an explicit code are
+//! classified by their `ErrorKind`.
+
+use lowell_core::error::ErrorCode;
+use serde::Serialize;
+use std::fmt;
+
+/// The outermost code found in `err`'s chain, else [`ErrorCode::Unknown`].
+pub fn code_of(err: &anyhow::Error) -> ErrorCode {
+    // Sees through `.context(Coded)`, which `chain()` does not.
+    if let Some(c) = err.downcast_ref::<Coded>() {
+        return c.code;
+    }
+    for cause in err.chain() {
+        if let Some(c) = cause.downcast_ref::<Coded>() {
+            return c.code;
+        }
+        if let Some(e) = cause.downcast_ref::<lowell_core::Error>() {
+            return e.code();
+        }
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return ErrorCode::from_io(io);
+        }
+    }
+    ErrorCode::Unknown
+}
+
+/// An error message tagged with an [`ErrorCode`].
+///
+/// Use it as the error (`bail!(Coded::new(…))`) or as context
+/// (`.context(Coded::new(…))`); it displays as the message alone.
+#[derive(Debug)]
+pub struct Coded {
+    pub code: ErrorCode,
+    message: String,
+}
+
+impl Coded {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Coded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Coded {}
+
+/// JSON shape of a failed command: `{"error": {"code", "message", "causes"}}`.
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    /// The outermost message.
+    pub message: String,
+    /// Underlying causes, outermost first.
+    pub causes: Vec<String>,
+}
+
+impl ErrorReport {
+    pub fn new(err: &anyhow::Error) -> Self {
+        Self {
+            error: ErrorBody {
+                code: code_of(err),
+                message: err.to_string(),
+                causes: err.chain().skip(1).map(|c| c.to_string()).collect(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn code_survives_context_and_io_is_classified() {
+        let err =
+            anyhow::Error::new(Coded::new(ErrorCode::NotPe, "not a PE")).context("inspect a.efi");
+        assert_eq!(code_of(&err), ErrorCode::NotPe);
+
+        let err = std::fs::read("/nonexistent/lowell")
+            .context("read /nonexistent/lowell")
+            .unwrap_err();
+        assert_eq!(code_of(&err), ErrorCode::NotFound);
+
+        let err = Err::<(), _>(anyhow::anyhow!("bad toml"))
+            .context(Coded::new(ErrorCode::ProfileInvalid, "invalid profile"))
+            .unwrap_err();
+        let report = ErrorReport::new(&err);
+        assert_eq!(report.error.code, ErrorCode::ProfileInvalid);
+        assert_eq!(report.error.message, "invalid profile");
+        assert_eq!(report.error.causes, ["bad toml"]);
+    }
+
+    #[test]
+    fn core_errors_keep_their_code() {
+        let err = anyhow::Error::new(lowell_core::Error::SectionMissing {
+            name: ".linux".into(),
+        })
+        .context("inspect a.efi");
+        assert_eq!(code_of(&err), ErrorCode::SectionMissing);
+    }
+}
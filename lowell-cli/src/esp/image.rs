@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! The ESP inside a raw GPT disk image, written in place without loop
+//! devices or root.
+//!
+//! The GPT header is looked for at LBA 1 with 512- and then 4096-byte
+//! sectors; the first partition of the EFI System type is used. Its FAT
+//! file system is read and written through `fatfs`. A partition that is
+//! still blank (an all-zero first sector, as `sgdisk` or `systemd-repart`
+//! without `Format=` leave it) is formatted first. File times are the FAT
+//! epoch (1980-01-01), so identical inputs give identical images.
+
+use super::Esp;
+use crate::bootvars::parse_guid;
+use anyhow::{bail, Context, Result};
+use fatfs::{FileSystem, FormatVolumeOptions, FsOptions};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Partition type GUID of an EFI System Partition.
+pub const ESP_TYPE: &str = "c12a7328-f81f-11d2-ba4b-00a0c93ec93b";
+
+/// The ESP of a disk image, open for writing.
+pub struct Image {
+    path: PathBuf,
+    fs: FileSystem<Window>,
+}
+
+impl Image {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("open {}", path.display()))?;
+        let (start, len) =
+            find_esp(&mut file).with_context(|| format!("{}: no ESP", path.display()))?;
+        let mut window = Window {
+            file,
+            start,
+            len,
+            pos: 0,
+        };
+        let mut first = [0u8; 512];
+        window.read_exact(&mut first)?;
+        window.seek(SeekFrom::Start(0))?;
+        if first.iter().all(|&b| b == 0) {
+            let label = *b"ESP        ";
+            fatfs::format_volume(&mut window, FormatVolumeOptions::new().volume_label(label))
+                .with_context(|| format!("{}: format the ESP", path.display()))?;
+            window.seek(SeekFrom::Start(0))?;
+            info!(image = %path.display(), size = len, "formatted blank ESP as FAT");
+        }
+        let fs = FileSystem::new(window, FsOptions::new().update_accessed_date(false))
+            .with_context(|| format!("{}: the ESP is not a FAT file system", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            fs,
+        })
+    }
+}
+
+impl Esp for Image {
+    fn read(&mut self, rel: &str) -> Result<Option<Vec<u8>>> {
+        let mut file = match self.fs.root_dir().open_file(rel) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("read {}", self.describe(rel))),
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .with_context(|| format!("read {}", self.describe(rel)))?;
+        Ok(Some(data))
+    }
+
+    fn write(&mut self, rel: &str, data: &[u8]) -> Result<()> {
+        let what = || format!("write {}", self.describe(rel));
+        let (dirs, name) = rel.rsplit_once('/').unwrap_or(("", rel));
+        let mut dir = self.fs.root_dir();
+        for part in dirs.split('/').filter(|p| !p.is_empty()) {
+            dir = dir.create_dir(part).with_context(what)?;
+        }
+        let mut file = dir.create_file(name).with_context(what)?;
+        file.truncate().with_context(what)?;
+        file.write_all(data).with_context(what)?;
+        file.flush().with_context(what)
+    }
+
+    fn describe(&self, rel: &str) -> String {
+        format!("{}:/{rel}", self.path.display())
+    }
+
+    fn close(self: Box<Self>) -> Result<()> {
+        let path = self.path;
+        self.fs
+            .unmount()
+            .with_context(|| format!("{}: write the ESP back", path.display()))
+    }
+}
+
+/// Byte offset and length of the first EFI System Partition.
+fn find_esp(file: &mut File) -> Result<(u64, u64)> {
+    let esp = parse_guid(ESP_TYPE).expect("valid GUID");
+    for sector in [512u64, 4096] {
+        let mut header = [0u8; 92];
+        file.seek(SeekFrom::Start(sector))?;
+        if file.read_exact(&mut header).is_err() || &header[..8] != b"EFI PART" {
+            continue;
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let entries = u64::from_le_bytes(header[72..80].try_into().unwrap());
+        let (count, size) = (u32_at(80) as usize, u32_at(84) as usize);
+        if !(128..=4096).contains(&size) || count > 4096 {
+            bail!("implausible GPT: {count} entries of {size} bytes");
+        }
+        let mut table = vec![0u8; count * size];
+        file.seek(SeekFrom::Start(entries * sector))?;
+        file.read_exact(&mut table)
+            .context("read the partition table")?;
+        for entry in table.chunks_exact(size) {
+            if entry[..16] != esp {
+                continue;
+            }
+            let first = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+            let last = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+            if last < first {
+                bail!("ESP ends before it starts");
+            }
+            return Ok((first * sector, (last - first + 1) * sector));
+        }
+        bail!("no partition of type EFI System ({ESP_TYPE})");
+    }
+    bail!("no GPT header")
+}
+
+/// One partition of the image file, as a file of its own.
+struct Window {
+    file: File,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl Window {
+    /// How much of `want` fits before the partition's end.
+    fn room(&self, want: usize) -> usize {
+        want.min(self.len.saturating_sub(self.pos) as usize)
+    }
+}
+
+impl Read for Window {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.room(buf.len());
+        self.file.seek(SeekFrom::Start(self.start + self.pos))?;
+        let n = self.file.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for Window {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.room(buf.len());
+        self.file.seek(SeekFrom::Start(self.start + self.pos))?;
+        let n = self.file.write(&buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for Window {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let pos = match to {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+        };
+        match pos {
+            Some(pos) if pos <= self.len => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek outside the partition",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esp::{install_uki, Action};
+    use lowell_test_util::UkiBuilder;
+
+    /// A sparse 64 MiB image with one blank ESP from LBA 2048 to the end.
+    fn disk(path: &Path) {
+        let file = File::create(path).unwrap();
+        file.set_len(64 << 20).unwrap();
+        let last = (64 << 20) / 512 - 34;
+        let mut header = b"EFI PART".to_vec();
+        header.extend([0, 0, 1, 0, 92, 0, 0, 0]);
+        header.resize(72, 0);
+        header.extend(2u64.to_le_bytes());
+        header.extend(128u32.to_le_bytes());
+        header.extend(128u32.to_le_bytes());
+        let mut entry = parse_guid(ESP_TYPE).unwrap().to_vec();
+        entry.extend([0x11; 16]);
+        entry.extend(2048u64.to_le_bytes());
+        entry.extend((last as u64).to_le_bytes());
+        let file = &mut &file;
+        file.seek(SeekFrom::Start(512)).unwrap();
+        file.write_all(&header).unwrap();
+        file.seek(SeekFrom::Start(1024)).unwrap();
+        file.write_all(&entry).unwrap();
+    }
+
+    #[test]
+    fn ukis_land_in_the_image_esp() {
+        let dir = tempfile::tempdir().unwrap();
+        let (raw, uki) = (dir.path().join("disk.raw"), dir.path().join("uki.efi"));
+        disk(&raw);
+        std::fs::write(&uki, UkiBuilder::new().cmdline("quiet").build()).unwrap();
+
+        let mut esp = Box::new(Image::open(&raw).unwrap());
+        let r = install_uki(esp.as_mut(), &uki, "fedora").unwrap();
+        assert_eq!(r.action, Action::Installed);
+        assert_eq!(
+            esp.describe(&r.path),
+            format!("{}:/EFI/Linux/fedora.efi", raw.display())
+        );
+        (esp as Box<dyn Esp>).close().unwrap();
+
+        // Reopened, the file is there and reinstalling changes nothing.
+        let mut esp = Box::new(Image::open(&raw).unwrap());
+        let data = esp.read("EFI/Linux/fedora.efi").unwrap().unwrap();
+        assert_eq!(data, std::fs::read(&uki).unwrap());
+        let r = install_uki(esp.as_mut(), &uki, "fedora").unwrap();
+        assert_eq!(r.action, Action::Current);
+        assert!(esp.read("EFI/Linux/other.efi").unwrap().is_none());
+        (esp as Box<dyn Esp>).close().unwrap();
+        // Nothing outside the partition was touched.
+        let image = std::fs::read(&raw).unwrap();
+        assert!(image[2048..2048 * 512].iter().all(|&b| b == 0));
+
+        let plain = dir.path().join("plain.raw");
+        std::fs::write(&plain, vec![0u8; 1 << 20]).unwrap();
+        assert!(Image::open(&plain).is_err());
+    }
+}
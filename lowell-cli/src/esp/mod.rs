@@ -0,0 +1,538 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! The EFI System Partition: where UKIs and systemd-boot go.
+//!
+//! Layout follows the Boot Loader Specification and `bootctl install`:
+//!
+//! * UKIs in `EFI/Linux/<name>.efi` (BLS type #2), which systemd-boot finds
+//!   without loader entries.
+//! * systemd-boot in `EFI/systemd/systemd-boot<arch>.efi`, and as the
+//!   removable-media fallback `EFI/BOOT/BOOT<ARCH>.EFI`. The fallback is only
+//!   written when it is missing or already systemd-boot, so another loader
+//!   there (shim, a vendor's) is left alone.
+//!
+//! * For GRUB (`blscfg`), a BLS type #1 entry instead: the UKI's kernel,
+//!   microcode and initrd under `<token>/<version>/` (kernel-install's `bls`
+//!   layout, the token being os-release `ID`) and
+//!   `loader/entries/<token>-<version>.conf` with the `grub_*` keys
+//!   Fedora's GRUB expects. The default entry is `saved_entry` in grubenv.
+//!
+//! Files go to an [`Esp`]: a mounted directory ([`Dir`]) or the FAT file
+//! system inside a raw disk image ([`image::Image`]).
+//!
+//! Versions come from the `.sdmagic` section systemd-boot carries
+//! (`#### LoaderInfo: systemd-boot 256.4 ####`) and compare like
+//! `bootctl update` does: an older binary never replaces a newer one unless
+//! forced.
+
+use crate::output;
+use anyhow::{bail, Context, Result};
+use lowell_core::formats::pe::PeFile;
+use lowell_core::uki::inspect;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+pub mod image;
+
+/// Mount points tried, in order, when no ESP is configured.
+pub const CANDIDATES: &[&str] = &["/efi", "/boot/efi", "/boot"];
+/// Where distributions ship systemd-boot.
+pub const SYSTEMD_BOOT_DIR: &str = "/usr/lib/systemd/boot/efi";
+
+/// `explicit`, else the first of [`CANDIDATES`] with an `EFI` directory.
+pub fn find(explicit: Option<&Path>) -> Result<PathBuf> {
+    if let Some(dir) = explicit {
+        if !dir.is_dir() {
+            bail!("ESP {} is not a directory", dir.display());
+        }
+        return Ok(dir.to_path_buf());
+    }
+    CANDIDATES
+        .iter()
+        .map(PathBuf::from)
+        .find(|dir| dir.join("EFI").is_dir())
+        .with_context(|| {
+            format!(
+                "no ESP found (tried {}); pass --esp or set `esp` in config.toml",
+                CANDIDATES.join(", ")
+            )
+        })
+}
+
+/// The UEFI name for `pe`'s architecture (`x64`, `aa64`, …), as used in
+/// `BOOTX64.EFI` and `systemd-bootx64.efi`.
+pub fn efi_arch(pe: &PeFile) -> Result<&'static str> {
+    Ok(match pe.arch_summary()?.0 {
+        "x86_64" => "x64",
+        "aarch64" => "aa64",
+        "arm" => "arm",
+        "i386" => "ia32",
+        other => bail!("no UEFI name for architecture {other}"),
+    })
+}
+
+/// The UEFI name for the host's architecture.
+pub fn host_efi_arch() -> Option<&'static str> {
+    match std::env::consts::ARCH {
+        "x86_64" => Some("x64"),
+        "aarch64" => Some("aa64"),
+        "arm" => Some("arm"),
+        "x86" => Some("ia32"),
+        "riscv64" => Some("riscv64"),
+        "loongarch64" => Some("loongarch64"),
+        _ => None,
+    }
+}
+
+/// The systemd-boot binary shipped for `arch`, preferring a signed copy
+/// (`.efi.signed`) as `bootctl` does.
+pub fn shipped_systemd_boot(arch: &str) -> Result<PathBuf> {
+    let base = Path::new(SYSTEMD_BOOT_DIR).join(format!("systemd-boot{arch}.efi"));
+    let signed = base.with_extension("efi.signed");
+    [signed, base.clone()]
+        .into_iter()
+        .find(|p| p.is_file())
+        .with_context(|| format!("{} not found: is systemd-boot installed?", base.display()))
+}
+
+/// `(product, version)` from a systemd-boot `.sdmagic` section, e.g.
+/// `("systemd-boot", "256.4-1.fc41")`.
+pub fn loader_info(pe: &PeFile) -> Result<Option<(String, String)>> {
+    Ok(inspect::loader_info(pe)?.map(|info| (info.product, info.version)))
+}
+
+/// Compare versions the way systemd's `strverscmp_improved` does: runs of
+/// digits numerically, runs of letters alphabetically, digits above
+/// letters, and `~` (a pre-release) below everything, even the end.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    let separator = |c: &u8| !c.is_ascii_alphanumeric() && *c != b'~';
+    loop {
+        a = &a[a.iter().take_while(|c| separator(c)).count()..];
+        b = &b[b.iter().take_while(|c| separator(c)).count()..];
+        match (a.first(), b.first()) {
+            (Some(b'~'), Some(b'~')) => {
+                (a, b) = (&a[1..], &b[1..]);
+                continue;
+            }
+            (Some(b'~'), _) => return Ordering::Less,
+            (_, Some(b'~')) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+            (None, _) => return Ordering::Less,
+            (_, None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() != y.is_ascii_digit() => {
+                return x.is_ascii_digit().cmp(&y.is_ascii_digit());
+            }
+            _ => {}
+        }
+        let digits = a[0].is_ascii_digit();
+        let run = |s: &[u8]| {
+            s.iter()
+                .take_while(|c| {
+                    if digits {
+                        c.is_ascii_digit()
+                    } else {
+                        c.is_ascii_alphabetic()
+                    }
+                })
+                .count()
+        };
+        let (ra, rb) = (&a[..run(a)], &b[..run(b)]);
+        let order = if digits {
+            fn trim(s: &[u8]) -> &[u8] {
+                &s[s.iter().take_while(|&&c| c == b'0').count()..]
+            }
+            let (na, nb) = (trim(ra), trim(rb));
+            na.len().cmp(&nb.len()).then_with(|| na.cmp(nb))
+        } else {
+            ra.cmp(rb)
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+        (a, b) = (&a[ra.len()..], &b[rb.len()..]);
+    }
+}
+
+/// What installing one file did.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case", tag = "action")]
+pub enum Action {
+    Installed,
+    /// Replaced an older copy (or a different one, for UKIs).
+    Updated {
+        from: Option<String>,
+    },
+    /// The same version is already there.
+    Current,
+    /// Left alone: the installed copy is newer, or is another loader.
+    Kept {
+        reason: String,
+    },
+}
+
+/// One file placed on the ESP.
+#[derive(Debug, serde::Serialize)]
+pub struct Installed {
+    /// Relative to the ESP's root, `/`-separated.
+    pub path: String,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Where installed files go. Paths are relative to the partition's root
+/// and `/`-separated.
+pub trait Esp {
+    /// `rel`'s contents, `None` if it does not exist.
+    fn read(&mut self, rel: &str) -> Result<Option<Vec<u8>>>;
+    /// Create or replace `rel`, and any directories above it.
+    fn write(&mut self, rel: &str, data: &[u8]) -> Result<()>;
+    /// `rel` as shown to the user.
+    fn describe(&self, rel: &str) -> String;
+    /// Flush what was written.
+    fn close(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A mounted ESP.
+pub struct Dir(pub PathBuf);
+
+impl Esp for Dir {
+    fn read(&mut self, rel: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.0.join(rel);
+        match std::fs::read(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("read {}", path.display())),
+        }
+    }
+
+    fn write(&mut self, rel: &str, data: &[u8]) -> Result<()> {
+        let dest = self.0.join(rel);
+        if let Some(dir) = dest.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+        }
+        output::write_atomic(&dest, data)
+    }
+
+    fn describe(&self, rel: &str) -> String {
+        self.0.join(rel).display().to_string()
+    }
+}
+
+/// Install `uki` as `EFI/Linux/<name>.efi`.
+pub fn install_uki(esp: &mut dyn Esp, uki: &Path, name: &str) -> Result<Installed> {
+    if name.is_empty() || name.contains('/') {
+        bail!("UKI name {name:?} must be a plain file name");
+    }
+    let data = std::fs::read(uki).with_context(|| format!("read {}", uki.display()))?;
+    place(esp, format!("EFI/Linux/{name}.efi"), &data)
+}
+
+/// Install `uki` as a BLS type #1 entry: its `.linux`, `.ucode` and
+/// `.initrd` as files, its `.cmdline` as `options`. Returns the entry id
+/// (the `.conf` file's stem, which GRUB's `saved_entry` names) and what
+/// was written.
+pub fn install_bls(esp: &mut dyn Esp, uki: &PeFile) -> Result<(String, Vec<Installed>)> {
+    let Some(linux) = uki.section_contents(".linux")? else {
+        bail!("no .linux section");
+    };
+    let osrel = match uki.read_text(".osrel")? {
+        Some(text) => lowell_core::formats::osrel::read_os_release_from_str(&text)?,
+        None => None,
+    };
+    let token = osrel
+        .as_ref()
+        .and_then(|o| o.id.clone())
+        .unwrap_or_else(|| "linux".into());
+    let version = inspect::kernel_release(uki)?
+        .context("kernel version unknown: no .uname section or version banner")?;
+    if [&token, &version]
+        .iter()
+        .any(|s| s.contains(['/', '\n']) || s.starts_with('.'))
+    {
+        bail!("unusable entry token {token:?} or version {version:?}");
+    }
+    let dir = format!("{token}/{version}");
+    let title = osrel.and_then(|o| o.name).unwrap_or_else(|| "Linux".into());
+    let mut conf = format!("title {title} ({version})\nversion {version}\nsort-key {token}\n");
+    conf += &format!("linux /{dir}/linux\n");
+    let mut files = vec![("linux", linux)];
+    for name in ["ucode", "initrd"] {
+        if let Some(data) = uki.section_contents(&format!(".{name}"))? {
+            conf += &format!("initrd /{dir}/{name}\n");
+            files.push((name, data));
+        }
+    }
+    if let Some(cmdline) = uki.read_text(".cmdline")? {
+        conf += &format!("options {}\n", cmdline.trim());
+    }
+    conf += &format!("grub_users $grub_users\ngrub_arg --unrestricted\ngrub_class {token}\n");
+
+    let mut done = Vec::new();
+    for (name, data) in files {
+        done.push(place(esp, format!("{dir}/{name}"), data)?);
+    }
+    let id = format!("{token}-{version}");
+    let entry = format!("loader/entries/{id}.conf");
+    done.push(place(esp, entry, conf.as_bytes())?);
+    Ok((id, done))
+}
+
+/// GRUB's environment block: `key=value` lines padded with `#` to a
+/// fixed size, since GRUB rewrites it in place.
+pub mod grubenv {
+    use anyhow::{bail, Context, Result};
+    use std::path::{Path, PathBuf};
+
+    const HEADER: &str = "# GRUB Environment Block\n";
+    const SIZE: usize = 1024;
+
+    /// `grub2/grubenv` or `grub/grubenv` under `boot`, whichever exists.
+    pub fn find(boot: &Path) -> Option<PathBuf> {
+        ["grub2/grubenv", "grub/grubenv"]
+            .iter()
+            .map(|rel| boot.join(rel))
+            .find(|p| p.is_file())
+    }
+
+    /// Set `key` in the block at `path`, keeping every other variable.
+    pub fn set(path: &Path, key: &str, value: &str) -> Result<()> {
+        if value.contains(['\n', '\\']) || key.contains(['=', '\n']) {
+            bail!("{key}={value:?}: not representable in grubenv");
+        }
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        let Some(body) = text.strip_prefix(HEADER) else {
+            bail!("{}: not a GRUB environment block", path.display());
+        };
+        let mut block = HEADER.to_string();
+        for line in body
+            .lines()
+            .filter(|l| !l.starts_with('#') && !l.is_empty())
+        {
+            if line.split_once('=').map(|(k, _)| k) != Some(key) {
+                block += &format!("{line}\n");
+            }
+        }
+        block += &format!("{key}={value}\n");
+        if block.len() > SIZE {
+            bail!("{}: no room for {key}", path.display());
+        }
+        block += &"#".repeat(SIZE - block.len());
+        crate::output::write_atomic(path, block.as_bytes())
+    }
+}
+
+/// Write `data` to `dest` unless it already holds exactly that.
+fn place(esp: &mut dyn Esp, rel: String, data: &[u8]) -> Result<Installed> {
+    let action = match esp.read(&rel)? {
+        Some(old) if old == data => Action::Current,
+        Some(_) => Action::Updated { from: None },
+        None => Action::Installed,
+    };
+    if action != Action::Current {
+        esp.write(&rel, data)?;
+    }
+    Ok(Installed { path: rel, action })
+}
+
+/// Install or update systemd-boot from `source` (both its own path and,
+/// if free or already systemd-boot, the fallback path). Older binaries
+/// don't replace newer ones unless `force`.
+pub fn install_systemd_boot(
+    esp: &mut dyn Esp,
+    source: &Path,
+    force: bool,
+) -> Result<Vec<Installed>> {
+    let data = std::fs::read(source).with_context(|| format!("read {}", source.display()))?;
+    let pe = PeFile::from_bytes(data.clone())
+        .with_context(|| format!("{}: not a PE image", source.display()))?;
+    let version = match loader_info(&pe)? {
+        Some((product, version)) if product == "systemd-boot" => version,
+        _ => bail!("{} is not systemd-boot (no LoaderInfo)", source.display()),
+    };
+    let arch = efi_arch(&pe)?;
+    let targets = [
+        (format!("EFI/systemd/systemd-boot{arch}.efi"), false),
+        (format!("EFI/BOOT/BOOT{}.EFI", arch.to_uppercase()), true),
+    ];
+    let mut done = Vec::new();
+    for (rel, fallback) in targets {
+        let installed = esp.read(&rel)?.map(|old| {
+            PeFile::from_bytes(old)
+                .ok()
+                .and_then(|pe| loader_info(&pe).ok().flatten())
+        });
+        let action = match installed {
+            None => Action::Installed,
+            Some(Some((product, old))) if product == "systemd-boot" => {
+                match vercmp(&version, &old) {
+                    Ordering::Equal if !force => Action::Current,
+                    Ordering::Less if !force => Action::Kept {
+                        reason: format!("installed {old} is newer than {version}"),
+                    },
+                    _ => Action::Updated { from: Some(old) },
+                }
+            }
+            Some(_) if fallback && !force => Action::Kept {
+                reason: "not systemd-boot".into(),
+            },
+            Some(other) => Action::Updated {
+                from: other.map(|(_, v)| v),
+            },
+        };
+        if matches!(action, Action::Installed | Action::Updated { .. }) {
+            esp.write(&rel, &data)?;
+        }
+        done.push(Installed { path: rel, action });
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::UkiBuilder;
+
+    fn boot(version: &str) -> Vec<u8> {
+        let magic = format!("{}systemd-boot {version} ####\0", inspect::LOADER_INFO);
+        UkiBuilder::new()
+            .section(".sdmagic", magic.as_bytes())
+            .build()
+    }
+
+    #[test]
+    fn versions_compare_like_systemd() {
+        use Ordering::*;
+        for (a, b, want) in [
+            ("256.4", "256.4", Equal),
+            ("256.10", "256.9", Greater),
+            ("255", "256~rc1", Less),
+            ("256~rc1", "256", Less),
+            ("256~rc2", "256~rc1", Greater),
+            ("256.4-1.fc41", "256.4-2.fc41", Less),
+            ("256.4", "256.4a", Less),
+            ("256.a", "256.1", Less),
+            ("0256", "256", Equal),
+        ] {
+            assert_eq!(vercmp(a, b), want, "{a} vs {b}");
+            assert_eq!(vercmp(b, a), want.reverse(), "{b} vs {a}");
+        }
+    }
+
+    #[test]
+    fn systemd_boot_updates_only_forward() {
+        let dir = tempfile::tempdir().unwrap();
+        let (esp, src) = (dir.path().join("esp"), dir.path().join("src"));
+        std::fs::create_dir_all(&esp).unwrap();
+        std::fs::create_dir_all(&src).unwrap();
+        let new = src.join("new.efi");
+        let old = src.join("old.efi");
+        std::fs::write(&new, boot("256.4")).unwrap();
+        std::fs::write(&old, boot("255.1")).unwrap();
+        let actions = |r: Vec<Installed>| r.into_iter().map(|i| i.action).collect::<Vec<_>>();
+
+        let mut dest = Dir(esp.clone());
+        let esp = &mut dest as &mut dyn Esp;
+        let r = install_systemd_boot(esp, &old, false).unwrap();
+        assert_eq!(r[0].path, "EFI/systemd/systemd-bootx64.efi");
+        assert_eq!(r[1].path, "EFI/BOOT/BOOTX64.EFI");
+        assert_eq!(actions(r), [Action::Installed, Action::Installed]);
+
+        let updated = Action::Updated {
+            from: Some("255.1".into()),
+        };
+        let r = install_systemd_boot(esp, &new, false).unwrap();
+        assert_eq!(actions(r), [updated.clone(), updated]);
+        let r = install_systemd_boot(esp, &new, false).unwrap();
+        assert_eq!(actions(r), [Action::Current, Action::Current]);
+        let r = install_systemd_boot(esp, &old, false).unwrap();
+        assert!(matches!(r[0].action, Action::Kept { .. }));
+        let pe = PeFile::from_bytes(esp.read(&r[0].path).unwrap().unwrap()).unwrap();
+        assert_eq!(loader_info(&pe).unwrap().unwrap().1, "256.4");
+
+        // Another loader in the fallback path stays unless forced.
+        esp.write("EFI/BOOT/BOOTX64.EFI", &UkiBuilder::new().build())
+            .unwrap();
+        let r = install_systemd_boot(esp, &new, false).unwrap();
+        assert_eq!(
+            actions(r)[1],
+            Action::Kept {
+                reason: "not systemd-boot".into()
+            }
+        );
+        let r = install_systemd_boot(esp, &new, true).unwrap();
+        assert_eq!(actions(r)[1], Action::Updated { from: None });
+
+        assert!(install_systemd_boot(esp, &src.join("missing.efi"), false).is_err());
+        let uki = src.join("uki.efi");
+        std::fs::write(&uki, UkiBuilder::new().build()).unwrap();
+        assert!(install_systemd_boot(esp, &uki, false).is_err());
+        let r = install_uki(esp, &uki, "fedora").unwrap();
+        assert_eq!(
+            (r.path.as_str(), r.action),
+            ("EFI/Linux/fedora.efi", Action::Installed)
+        );
+        assert_eq!(
+            install_uki(esp, &uki, "fedora").unwrap().action,
+            Action::Current
+        );
+    }
+
+    #[test]
+    fn bls_entries_split_the_uki_for_grub() {
+        let dir = tempfile::tempdir().unwrap();
+        let boot = dir.path();
+        let uki = UkiBuilder::new()
+            .osrel("ID=fedora\nPRETTY_NAME=\"Fedora Linux 41\"\n")
+            .section(".uname", b"6.11.4-301.fc41.x86_64")
+            .cmdline("root=LABEL=root quiet\n")
+            .linux(b"kernel")
+            .initrd(b"initrd")
+            .build();
+        let pe = PeFile::from_bytes(uki).unwrap();
+        let mut esp = Dir(boot.to_path_buf());
+        let (id, files) = install_bls(&mut esp, &pe).unwrap();
+        assert_eq!(id, "fedora-6.11.4-301.fc41.x86_64");
+        assert_eq!(files.len(), 3);
+        let dir = boot.join("fedora/6.11.4-301.fc41.x86_64");
+        assert_eq!(std::fs::read(dir.join("linux")).unwrap(), b"kernel");
+        assert_eq!(std::fs::read(dir.join("initrd")).unwrap(), b"initrd");
+        let conf = std::fs::read_to_string(boot.join(format!("loader/entries/{id}.conf"))).unwrap();
+        assert_eq!(
+            conf,
+            "title Fedora Linux 41 (6.11.4-301.fc41.x86_64)\n\
+             version 6.11.4-301.fc41.x86_64\n\
+             sort-key fedora\n\
+             linux /fedora/6.11.4-301.fc41.x86_64/linux\n\
+             initrd /fedora/6.11.4-301.fc41.x86_64/initrd\n\
+             options root=LABEL=root quiet\n\
+             grub_users $grub_users\n\
+             grub_arg --unrestricted\n\
+             grub_class fedora\n"
+        );
+        let (_, again) = install_bls(&mut esp, &pe).unwrap();
+        assert!(again.iter().all(|i| i.action == Action::Current));
+
+        // No version to name the entry after.
+        let bare = PeFile::from_bytes(UkiBuilder::new().linux(b"kernel").build()).unwrap();
+        assert!(install_bls(&mut esp, &bare).is_err());
+
+        let env = boot.join("grub2/grubenv");
+        std::fs::create_dir_all(env.parent().unwrap()).unwrap();
+        let block = format!(
+            "{}saved_entry=old\nboot_success=1\n",
+            "# GRUB Environment Block\n"
+        );
+        std::fs::write(&env, format!("{block}{}", "#".repeat(1024 - block.len()))).unwrap();
+        assert_eq!(grubenv::find(boot), Some(env.clone()));
+        grubenv::set(&env, "saved_entry", &id).unwrap();
+        let text = std::fs::read_to_string(&env).unwrap();
+        assert_eq!(text.len(), 1024);
+        assert!(text.starts_with(&format!(
+            "# GRUB Environment Block\nboot_success=1\nsaved_entry={id}\n#"
+        )));
+        assert!(grubenv::set(&boot.join("fedora/6.11.4-301.fc41.x86_64/linux"), "a", "b").is_err());
+    }
+}
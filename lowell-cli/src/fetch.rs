@@ -0,0 +1,336 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Verified downloads of a profile's `[fetch]` inputs (`lowell fetch`,
+//! `lowell build`).
+//!
+//! Each input is stored once, by content, as `<dir>/fetch/sha256/<hex>`,
+//! where `<dir>` is `--cache-dir`, `cache-dir` from config, else
+//! `$XDG_CACHE_HOME/lowell` (`~/.cache/lowell`). A download lands in a
+//! temporary file beside its entry and is renamed into place only once its
+//! sha256 matches the profile and, when the profile names one, its
+//! detached signature verifies with the profile's key. The signature is
+//! checked when the file is downloaded: the entry's digest is pinned by the
+//! same profile, so a later build trusts what passed then. Entries are
+//! hashed again on every use, and a damaged one is downloaded afresh.
+//!
+//! URLs may be `https://`, `http://` or `file://` (a local mirror).
+
+use crate::config::Config;
+use crate::error::Coded;
+use crate::sign;
+use anyhow::{bail, Context, Result};
+use lowell_core::error::ErrorCode;
+use lowell_core::profile::{Profile, Remote};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Detached signatures are a few hundred bytes of base64.
+const MAX_SIGNATURE: u64 = 64 << 10;
+
+/// Downloads into a content-addressed cache.
+pub struct Fetcher {
+    dir: PathBuf,
+    agent: ureq::Agent,
+}
+
+/// One input, verified and ready to use.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fetched {
+    pub name: String,
+    pub url: String,
+    pub path: PathBuf,
+    pub sha256: String,
+    pub size: u64,
+    /// Already in the cache; nothing was downloaded.
+    pub cached: bool,
+    /// The profile declared a signature (checked when downloaded).
+    pub signed: bool,
+}
+
+impl Fetcher {
+    /// The cache under `flag`, else under the configured `cache-dir`, else
+    /// the user's cache directory.
+    pub fn open(flag: Option<&Path>, cfg: &Config) -> Result<Self> {
+        let base = match flag.or(cfg.cache_dir.as_deref()) {
+            Some(dir) => dir.to_path_buf(),
+            None => user_cache_dir()
+                .context("no cache directory: set --cache-dir, `cache-dir` in config, or $HOME")?,
+        };
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .user_agent(concat!("lowell/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .new_agent();
+        Ok(Self {
+            dir: base.join("fetch/sha256"),
+            agent,
+        })
+    }
+
+    /// Fetch every `[fetch]` input of `profile`; `base_dir` resolves
+    /// relative key paths. Keyed by input name, like `artifacts`.
+    pub fn resolve(&self, profile: &Profile, base_dir: &Path) -> Result<BTreeMap<String, Fetched>> {
+        let mut out = BTreeMap::new();
+        for (name, remote) in &profile.fetch {
+            if profile.artifacts.contains_key(name) {
+                bail!(Coded::new(
+                    ErrorCode::ProfileInvalid,
+                    format!("{name:?} is in both [artifacts] and [fetch]")
+                ));
+            }
+            out.insert(name.clone(), self.fetch(name, remote, base_dir)?);
+        }
+        Ok(out)
+    }
+
+    /// The cached copy of `remote`, downloading and verifying it first if
+    /// there is none.
+    pub fn fetch(&self, name: &str, remote: &Remote, base_dir: &Path) -> Result<Fetched> {
+        let invalid =
+            |why: String| Coded::new(ErrorCode::ProfileInvalid, format!("fetch.{name}: {why}"));
+        let want = remote.sha256.to_ascii_lowercase();
+        if want.len() != 64 || !want.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!(invalid(format!(
+                "sha256 {:?} is not 64 hex digits",
+                remote.sha256
+            )));
+        }
+        let key = match (&remote.signature, &remote.key) {
+            (Some(url), Some(key)) => Some((url, base_dir.join(key))),
+            (None, None) => None,
+            _ => bail!(invalid("`signature` and `key` go together".into())),
+        };
+        let (path, size, cached) = self
+            .store(&want, |file| {
+                info!(name, url = %remote.url, "downloading");
+                self.download(&remote.url, file, u64::MAX)?;
+                if let Some((url, key)) = &key {
+                    let key = sign::verifying_key(key)?;
+                    let mut signature = Vec::new();
+                    self.download(url, &mut signature, MAX_SIGNATURE)?;
+                    let mut data = Vec::new();
+                    file.seek(SeekFrom::Start(0))?;
+                    file.read_to_end(&mut data)?;
+                    sign::verify(&key, &data, &String::from_utf8_lossy(&signature))
+                        .with_context(|| format!("{}: signature {url}", remote.url))?;
+                }
+                Ok(())
+            })
+            .with_context(|| format!("fetch.{name}: {}", remote.url))?;
+        Ok(Fetched {
+            name: name.to_string(),
+            url: remote.url.clone(),
+            path,
+            sha256: want,
+            size,
+            cached,
+            signed: key.is_some(),
+        })
+    }
+
+    /// The cache entry for the sha256 `want`: the stored file if it is
+    /// intact, else what `fill` writes, kept only if it hashes to `want`.
+    /// Returns its path and size, and whether it was a cache hit.
+    pub fn store(
+        &self,
+        want: &str,
+        fill: impl FnOnce(&mut std::fs::File) -> Result<()>,
+    ) -> Result<(PathBuf, u64, bool)> {
+        let path = self.dir.join(want);
+        if path.is_file() {
+            match hash_file(&path) {
+                Ok((size, got)) if got == want => {
+                    debug!(path = %path.display(), "fetch cache hit");
+                    return Ok((path, size, true));
+                }
+                Ok((_, got)) => warn!(
+                    path = %path.display(),
+                    sha256 = got,
+                    "cached download is damaged, fetching again"
+                ),
+                Err(e) => warn!(path = %path.display(), "cached download unreadable: {e:#}"),
+            }
+        }
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("create {}", self.dir.display()))?;
+        let mut tmp = tempfile::NamedTempFile::new_in(&self.dir)
+            .with_context(|| format!("create a file in {}", self.dir.display()))?;
+        fill(tmp.as_file_mut())?;
+        let (size, got) = hash_file(tmp.path())?;
+        if got != want {
+            bail!(Coded::new(
+                ErrorCode::FetchFailed,
+                format!("sha256 {got}, expected {want}")
+            ));
+        }
+        tmp.persist(&path)
+            .with_context(|| format!("store {}", path.display()))?;
+        info!(path = %path.display(), size, "downloaded and verified");
+        Ok((path, size, false))
+    }
+
+    /// Copy `url` to `out`, failing past `limit` bytes.
+    fn download(&self, url: &str, out: &mut dyn Write, limit: u64) -> Result<u64> {
+        let failed = |why: String| Coded::new(ErrorCode::FetchFailed, format!("{url}: {why}"));
+        let mut reader: Box<dyn Read> = if let Some(path) = url.strip_prefix("file://") {
+            Box::new(std::fs::File::open(path).map_err(|e| failed(e.to_string()))?)
+        } else if url.starts_with("https://") || url.starts_with("http://") {
+            let resp = self
+                .agent
+                .get(url)
+                .call()
+                .map_err(|e| failed(e.to_string()))?;
+            if !resp.status().is_success() {
+                bail!(failed(format!("HTTP {}", resp.status())));
+            }
+            Box::new(resp.into_body().into_reader())
+        } else {
+            bail!(Coded::new(
+                ErrorCode::ProfileInvalid,
+                format!("{url}: only https://, http:// and file:// URLs are supported")
+            ));
+        };
+        let n =
+            io::copy(&mut reader.by_ref().take(limit), out).map_err(|e| failed(e.to_string()))?;
+        if n == limit && reader.read(&mut [0u8])? > 0 {
+            bail!(failed(format!("larger than {limit} bytes")));
+        }
+        Ok(n)
+    }
+}
+
+fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let size =
+        io::copy(&mut file, &mut hasher).with_context(|| format!("read {}", path.display()))?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+fn user_cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+    Some(base.join("lowell"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::code_of;
+    use std::net::TcpListener;
+
+    fn remote(url: String, data: &[u8]) -> Remote {
+        Remote {
+            url,
+            sha256: format!("{:x}", Sha256::digest(data)),
+            signature: None,
+            key: None,
+        }
+    }
+
+    #[test]
+    fn downloads_are_verified_then_served_from_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = Config::default();
+        let fetcher = Fetcher::open(Some(&dir.path().join("cache")), &cfg).unwrap();
+        let kernel = dir.path().join("vmlinuz");
+        std::fs::write(&kernel, b"kernel image").unwrap();
+        let url = format!("file://{}", kernel.display());
+
+        let mut kernel_remote = remote(url.clone(), b"kernel image");
+        let got = fetcher.fetch("kernel", &kernel_remote, dir.path()).unwrap();
+        assert!(!got.cached && !got.signed);
+        assert_eq!(got.size, 12);
+        assert_eq!(std::fs::read(&got.path).unwrap(), b"kernel image");
+        assert!(got.path.ends_with(&got.sha256));
+        let again = fetcher.fetch("kernel", &kernel_remote, dir.path()).unwrap();
+        assert!(again.cached);
+
+        // A damaged entry is replaced; a changed upstream file is refused.
+        std::fs::write(&got.path, b"bit rot").unwrap();
+        assert!(
+            !fetcher
+                .fetch("kernel", &kernel_remote, dir.path())
+                .unwrap()
+                .cached
+        );
+        std::fs::write(&kernel, b"kernel image v2").unwrap();
+        std::fs::remove_file(&got.path).unwrap();
+        let err = fetcher
+            .fetch("kernel", &kernel_remote, dir.path())
+            .unwrap_err();
+        assert_eq!(code_of(&err), ErrorCode::FetchFailed);
+        assert!(!got.path.exists());
+        assert_eq!(std::fs::read_dir(&fetcher.dir).unwrap().count(), 0);
+
+        // Signed: the signature must verify with the profile's key.
+        let (private, public) = sign::tests::key_pair(dir.path());
+        let key = sign::signing_key(&private).unwrap();
+        std::fs::write(
+            dir.path().join("good.sig"),
+            sign::sign(&key, b"kernel image v2"),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("bad.sig"),
+            sign::sign(&key, b"something else"),
+        )
+        .unwrap();
+        kernel_remote = remote(url, b"kernel image v2");
+        kernel_remote.key = Some(public.file_name().unwrap().into());
+        kernel_remote.signature = Some(format!("file://{}/bad.sig", dir.path().display()));
+        let err = fetcher
+            .fetch("kernel", &kernel_remote, dir.path())
+            .unwrap_err();
+        assert_eq!(code_of(&err), ErrorCode::BadSignature);
+        kernel_remote.signature = Some(format!("file://{}/good.sig", dir.path().display()));
+        assert!(
+            fetcher
+                .fetch("kernel", &kernel_remote, dir.path())
+                .unwrap()
+                .signed
+        );
+
+        kernel_remote.key = None;
+        let err = fetcher
+            .fetch("kernel", &kernel_remote, dir.path())
+            .unwrap_err();
+        assert_eq!(code_of(&err), ErrorCode::ProfileInvalid);
+    }
+
+    #[test]
+    fn fetches_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            for status in ["200 OK", "404 Not Found"] {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut request = [0u8; 4096];
+                let _ = conn.read(&mut request).unwrap();
+                let body = "systemd-stub";
+                write!(
+                    conn,
+                    "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let fetcher = Fetcher::open(Some(dir.path()), &Config::default()).unwrap();
+        let stub = remote(format!("http://{addr}/stub.efi"), b"systemd-stub");
+
+        let got = fetcher.fetch("stub", &stub, dir.path()).unwrap();
+        assert_eq!(std::fs::read(&got.path).unwrap(), b"systemd-stub");
+        std::fs::remove_file(&got.path).unwrap();
+        let err = fetcher.fetch("stub", &stub, dir.path()).unwrap_err();
+        assert_eq!(code_of(&err), ErrorCode::FetchFailed);
+        assert!(format!("{err:#}").contains("HTTP 404"), "{err:#}");
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Expand command-line inputs into a list of UKI files.
+//!
+//! Each argument may be a file, a directory (searched recursively for
+//! `*.efi`), or a glob pattern the shell did not expand (`'out/**/*.efi'`).
+//! Order is preserved per argument; directory and glob results are sorted.
+
+use crate::error::Coded;
+use anyhow::{bail, Context, Result};
+use lowell_core::error::ErrorCode;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn expand(args: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+    for arg in args {
+        if arg.is_dir() {
+            let start = out.len();
+            collect_efi(arg, &mut out)?;
+            out[start..].sort();
+        } else if !arg.exists() && is_pattern(arg) {
+            let pattern = arg.to_string_lossy();
+            let mut matches = glob::glob(&pattern)
+                .with_context(|| format!("bad glob {pattern:?}"))?
+                .collect::<Result<Vec<_>, _>>()?;
+            if matches.is_empty() {
+                bail!(Coded::new(
+                    ErrorCode::NotFound,
+                    format!("{pattern:?} matched no files")
+                ));
+            }
+            matches.sort();
+            out.extend(matches);
+        } else {
+            out.push(arg.clone());
+        }
+    }
+    Ok(out)
+}
+
+fn is_pattern(p: &Path) -> bool {
+    p.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn collect_efi(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        // file_type() does not follow symlinks, so link loops can't recurse.
+        let ty = entry.file_type()?;
+        if ty.is_dir() {
+            collect_efi(&path, out)?;
+        } else if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("efi"))
+        {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_dirs_recursively_and_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("EFI/Linux")).unwrap();
+        for f in [
+            "EFI/Linux/b.efi",
+            "EFI/Linux/a.EFI",
+            "EFI/Linux/notes.txt",
+            "c.efi",
+        ] {
+            fs::write(root.join(f), b"").unwrap();
+        }
+
+        let got = expand(&[root.join("EFI")]).unwrap();
+        assert_eq!(
+            got,
+            [root.join("EFI/Linux/a.EFI"), root.join("EFI/Linux/b.efi")]
+        );
+
+        let got = expand(&[root.join("*.efi"), root.join("EFI/Linux/b.efi")]).unwrap();
+        assert_eq!(got, [root.join("c.efi"), root.join("EFI/Linux/b.efi")]);
+
+        assert!(expand(&[root.join("*.img")]).is_err());
+    }
+}
@@ -1,11 +1,52 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
-use anyhow::Result;
+use error::ErrorReport;
+use std::process::ExitCode;
 
+mod baseline;
+mod bootc;
+mod bootvars;
+mod cache;
 mod cli;
+mod config;
+mod error;
+mod esp;
+mod fetch;
+mod inputs;
+mod output;
+mod provenance;
+mod qemu;
+mod refvalues;
+mod registry;
+mod secureboot;
+mod select;
+mod sign;
+mod style;
+mod sysupdate;
 mod tracing_init;
+#[cfg(unix)]
+mod varlink;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = cli::Cli::parse();
-    tracing_init::init(&cli.global)?;
-    cli.run()
+    // Until the config loads only --format can ask for JSON errors.
+    let mut json_errors = cli.json_errors(None);
+    let result = tracing_init::init(&cli.global)
+        .and_then(|()| cli.config())
+        .and_then(|cfg| {
+            json_errors = cli.json_errors(cfg.format);
+            cli.run(cfg)
+        });
+    let Err(err) = result else {
+        return ExitCode::SUCCESS;
+    };
+    if json_errors {
+        // One line on stderr; stdout stays reserved for the report itself.
+        match serde_json::to_string(&ErrorReport::new(&err)) {
+            Ok(line) => eprintln!("{line}"),
+            Err(_) => eprintln!("Error: {err:?}"),
+        }
+    } else {
+        eprintln!("Error: {err:?}");
+    }
+    ExitCode::FAILURE
 }
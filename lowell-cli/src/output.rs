@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Command output sink: stdout, or a file replaced atomically.
+//!
+//! File output goes to a temporary sibling (`.<name>.XXXXXX.tmp`) that is
+//! fsynced and renamed over the destination only on [`Output::commit`].
+//! If the command fails (or the process dies) the destination is untouched.
+//!
+//! [`clone_atomic`] copies a file the same way, sharing its extents with
+//! the source (a reflink) on filesystems that support it, so a cached
+//! artifact and the output built from it take the space of one.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+#[cfg(target_os = "linux")]
+use tracing::debug;
+
+pub enum Output {
+    Stdout(BufWriter<io::Stdout>),
+    File {
+        tmp: BufWriter<NamedTempFile>,
+        dest: PathBuf,
+    },
+}
+
+impl Output {
+    /// Stdout when `dest` is `None` (or `-`), otherwise a pending atomic file.
+    pub fn open(dest: Option<&Path>) -> Result<Self> {
+        match dest {
+            None => Ok(Output::Stdout(BufWriter::new(io::stdout()))),
+            Some(p) if p == Path::new("-") => Ok(Output::Stdout(BufWriter::new(io::stdout()))),
+            Some(dest) => Ok(Output::File {
+                tmp: BufWriter::new(temp_sibling(dest)?),
+                dest: dest.to_path_buf(),
+            }),
+        }
+    }
+
+    /// True when writing straight to a terminal (drives `--color auto`).
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Output::Stdout(_)) && io::stdout().is_terminal()
+    }
+
+    /// Flush everything and, for files, move the result into place.
+    pub fn commit(self) -> Result<()> {
+        match self {
+            Output::Stdout(mut out) => {
+                out.flush()?;
+                Ok(())
+            }
+            Output::File { tmp, dest } => {
+                let tmp = tmp.into_inner().map_err(|e| e.into_error())?;
+                persist(tmp, &dest)
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(out) => out.write(buf),
+            Output::File { tmp, .. } => tmp.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(out) => out.flush(),
+            Output::File { tmp, .. } => tmp.flush(),
+        }
+    }
+}
+
+/// Write `bytes` to `dest` atomically (temp file + fsync + rename).
+pub fn write_atomic(dest: &Path, bytes: &[u8]) -> Result<()> {
+    let mut tmp = temp_sibling(dest)?;
+    tmp.write_all(bytes)
+        .with_context(|| format!("write {}", dest.display()))?;
+    persist(tmp, dest)
+}
+
+/// Copy `src` to `dest` atomically, as a reflink where the filesystem
+/// allows (btrfs, XFS, bcachefs, …) and otherwise by an in-kernel copy
+/// (`copy_file_range`, which may still share extents, e.g. over NFS).
+/// Returns whether the copy is a reflink.
+pub fn clone_atomic(src: &Path, dest: &Path) -> Result<bool> {
+    let mut from = File::open(src).with_context(|| format!("open {}", src.display()))?;
+    let mut tmp = temp_sibling(dest)?;
+    let reflinked = reflink(&from, tmp.as_file());
+    if !reflinked {
+        // std copies file to file with copy_file_range on Linux.
+        io::copy(&mut from, tmp.as_file_mut())
+            .with_context(|| format!("copy {} to {}", src.display(), dest.display()))?;
+    }
+    persist(tmp, dest)?;
+    Ok(reflinked)
+}
+
+/// Make `to` (empty) share all of `from`'s extents; false if the
+/// filesystem can't, or the two are on different filesystems.
+#[cfg(target_os = "linux")]
+fn reflink(from: &File, to: &File) -> bool {
+    use std::os::fd::AsRawFd;
+    // SAFETY: FICLONE takes the source descriptor as its argument; both
+    // descriptors are open for the duration of the call.
+    let rc = unsafe { libc::ioctl(to.as_raw_fd(), libc::FICLONE, from.as_raw_fd()) };
+    if rc != 0 {
+        debug!(error = %io::Error::last_os_error(), "no reflink, copying");
+    }
+    rc == 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_: &File, _: &File) -> bool {
+    false
+}
+
+fn temp_sibling(dest: &Path) -> Result<NamedTempFile> {
+    let dir = match dest.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        ".{}.",
+        dest.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(&prefix).suffix(".tmp");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o644));
+    }
+    builder
+        .tempfile_in(dir)
+        .with_context(|| format!("create temporary file in {}", dir.display()))
+}
+
+fn persist(tmp: NamedTempFile, dest: &Path) -> Result<()> {
+    tmp.as_file()
+        .sync_all()
+        .with_context(|| format!("sync {}", dest.display()))?;
+    tmp.persist(dest)
+        .map_err(|e| e.error)
+        .with_context(|| format!("rename into {}", dest.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_appears_only_on_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("report.json");
+        std::fs::write(&dest, b"old").unwrap();
+
+        let mut out = Output::open(Some(&dest)).unwrap();
+        out.write_all(b"new contents").unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"old");
+        out.commit().unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"new contents");
+
+        let mut abandoned = Output::open(Some(&dest)).unwrap();
+        abandoned.write_all(b"partial").unwrap();
+        drop(abandoned);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"new contents");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn clones_replace_the_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("cached.efi"), dir.path().join("out.efi"));
+        let data: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+        std::fs::write(&src, &data).unwrap();
+        std::fs::write(&dest, b"old").unwrap();
+        // Reflink or copy depends on the filesystem; the bytes must not.
+        clone_atomic(&src, &dest).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+        assert_eq!(std::fs::read(&src).unwrap(), data);
+        assert!(clone_atomic(&dir.path().join("missing"), &dest).is_err());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
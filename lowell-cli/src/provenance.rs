@@ -0,0 +1,286 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! SLSA provenance for builds (`lowell build --provenance`).
+//!
+//! A build records an in-toto Statement v1 whose subject is the UKI and
+//! whose predicate is SLSA Provenance v1:
+//!
+//! * `buildDefinition.externalParameters`: the profile (its spec, name and
+//!   the SHA-256 of its canonical TOML) and the kernel command line.
+//! * `buildDefinition.internalParameters`: the initrd compression.
+//! * `buildDefinition.resolvedDependencies`: every input file and its
+//!   SHA-256. A directory initrd is one entry whose `dirhash` digest covers
+//!   each path below it, its type and its contents (or link target).
+//! * `runDetails`: the builder id, lowell's version, host OS and
+//!   architecture, `SOURCE_DATE_EPOCH` when set, and start/finish times.
+//!
+//! With a signing key the statement is wrapped in a DSSE envelope (see
+//! [`sign::Envelope`](crate::sign::Envelope)), which is what `cosign
+//! verify-blob-attestation` and SLSA verifiers expect.
+//!
+//! `uki inspect --sign-key` attests a report the same way: the subject is
+//! the inspected UKI and the predicate is its JSON report, so a later stage
+//! can trust the report for that exact file without inspecting it again.
+
+use anyhow::{Context, Result};
+use lowell_core::digest::Algorithm;
+use lowell_core::mapped::Mapped;
+use lowell_core::uki::build::BuildInputs;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `payloadType` of an in-toto statement in a DSSE envelope, and its media
+/// type as an OCI layer.
+pub const IN_TOTO: &str = "application/vnd.in-toto+json";
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+pub const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+/// `predicateType` of an inspection report.
+pub const REPORT_PREDICATE_TYPE: &str = "https://github.com/SamD2021/lowell/inspect/v1";
+const BUILD_TYPE: &str = "https://github.com/SamD2021/lowell/build/v1";
+/// Who built it, when `--builder-id` is not given.
+pub const DEFAULT_BUILDER: &str = "https://github.com/SamD2021/lowell";
+
+#[derive(Debug, Serialize)]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub kind: &'static str,
+    pub subject: Vec<Resource>,
+    #[serde(rename = "predicateType")]
+    pub predicate_type: &'static str,
+    pub predicate: Value,
+}
+
+/// An SLSA ResourceDescriptor, reduced to what lowell fills in.
+#[derive(Debug, Serialize)]
+pub struct Resource {
+    pub name: String,
+    pub digest: BTreeMap<String, String>,
+}
+
+impl Resource {
+    /// `path` under `name`, hashed.
+    fn of(name: String, path: &Path) -> Result<Self> {
+        let meta = std::fs::metadata(path).with_context(|| format!("stat {}", path.display()))?;
+        let (alg, hex) = if meta.is_dir() {
+            let mut listing = String::new();
+            dir_listing(path, Path::new(""), &mut listing)?;
+            ("dirhash", Algorithm::Sha256.digest(listing.as_bytes()).hex)
+        } else {
+            ("sha256", sha256(path)?)
+        };
+        Ok(Self {
+            name,
+            digest: BTreeMap::from([(alg.to_string(), hex)]),
+        })
+    }
+}
+
+/// What a build was, besides its inputs.
+pub struct Build<'a> {
+    /// The `--profile` spec.
+    pub profile_spec: &'a str,
+    pub profile_name: &'a str,
+    /// The profile as canonical TOML.
+    pub profile_toml: &'a str,
+    pub builder_id: &'a str,
+    pub started: SystemTime,
+    pub finished: SystemTime,
+}
+
+/// The provenance statement for `out`, built from `inputs`.
+pub fn statement(out: &Path, inputs: &BuildInputs, build: &Build) -> Result<Statement> {
+    let subject_name = out
+        .file_name()
+        .map_or_else(|| out.display().to_string(), |n| n.to_string_lossy().into());
+    let roles = [
+        ("stub", Some(&inputs.stub)),
+        ("kernel", Some(&inputs.kernel)),
+        ("initrd", Some(&inputs.initrd)),
+        ("osrel", inputs.osrel.as_ref()),
+        ("dtb", inputs.dtb.as_ref()),
+    ];
+    let mut dependencies = Vec::new();
+    for (role, path) in roles {
+        if let Some(path) = path {
+            let resource = Resource::of(format!("{role}:{}", path.display()), path)?;
+            dependencies.push(resource);
+        }
+    }
+    let mut environment = json!({
+        "lowell": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+    });
+    if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") {
+        environment["sourceDateEpoch"] = epoch.into();
+    }
+    let predicate = json!({
+        "buildDefinition": {
+            "buildType": BUILD_TYPE,
+            "externalParameters": {
+                "profile": {
+                    "spec": build.profile_spec,
+                    "name": build.profile_name,
+                    "digest": {"sha256": Algorithm::Sha256.digest(build.profile_toml.as_bytes()).hex},
+                },
+                "cmdline": inputs.cmdline,
+            },
+            "internalParameters": {
+                "initrdCompression": inputs.initrd_compression.to_string(),
+            },
+            "resolvedDependencies": dependencies,
+        },
+        "runDetails": {
+            "builder": {
+                "id": build.builder_id,
+                "version": {"lowell": env!("CARGO_PKG_VERSION")},
+            },
+            "metadata": {
+                "startedOn": rfc3339(build.started),
+                "finishedOn": rfc3339(build.finished),
+            },
+            "byproducts": [{"name": "environment", "content": environment}],
+        },
+    });
+    Ok(Statement {
+        kind: STATEMENT_TYPE,
+        subject: vec![Resource::of(subject_name, out)?],
+        predicate_type: PREDICATE_TYPE,
+        predicate,
+    })
+}
+
+/// The statement that `report` describes the file at `path`.
+pub fn report_statement(path: &Path, report: Value) -> Result<Statement> {
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into(),
+    );
+    Ok(Statement {
+        kind: STATEMENT_TYPE,
+        subject: vec![Resource::of(name, path)?],
+        predicate_type: REPORT_PREDICATE_TYPE,
+        predicate: report,
+    })
+}
+
+fn sha256(path: &Path) -> Result<String> {
+    let data = Mapped::open(path)?;
+    Ok(Algorithm::Sha256.digest(&data).hex)
+}
+
+/// One line per entry below `dir`, sorted: relative path, type, then the
+/// content hash, link target or nothing.
+fn dir_listing(dir: &Path, rel: &Path, out: &mut String) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| format!("read {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let (path, rel) = (entry.path(), rel.join(entry.file_name()));
+        let meta = std::fs::symlink_metadata(&path)?;
+        let line = if meta.is_symlink() {
+            format!("l {}", std::fs::read_link(&path)?.display())
+        } else if meta.is_dir() {
+            "d".to_string()
+        } else {
+            format!("f {}", sha256(&path)?)
+        };
+        *out += &format!("{}\0{line}\n", rel.display());
+        if meta.is_dir() {
+            dir_listing(&path, &rel, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// `t` as `YYYY-MM-DDThh:mm:ssZ`.
+fn rfc3339(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Days to civil date (Howard Hinnant's algorithm), epoch 0000-03-01.
+    let z = days + 719_468;
+    let (era, doe) = (z / 146_097, z % 146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn statements_name_every_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, data: &[u8]| {
+            let p = dir.path().join(name);
+            std::fs::write(&p, data).unwrap();
+            p
+        };
+        let tree = dir.path().join("initrd");
+        std::fs::create_dir(&tree).unwrap();
+        std::fs::write(tree.join("init"), b"#!/bin/sh\n").unwrap();
+        let inputs = BuildInputs {
+            stub: file("stub.efi", b"stub"),
+            kernel: file("vmlinuz", b"kernel"),
+            initrd: tree.clone(),
+            initrd_compression: lowell_core::uki::build::DEFAULT_COMPRESSION,
+            osrel: None,
+            dtb: None,
+            cmdline: "quiet".into(),
+        };
+        let out = file("uki.efi", b"uki");
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let build = Build {
+            profile_spec: "builtin:kvm-virtio",
+            profile_name: "kvm-virtio",
+            profile_toml: "name = \"kvm-virtio\"\n",
+            builder_id: DEFAULT_BUILDER,
+            started: at,
+            finished: at + Duration::from_secs(61),
+        };
+        let s = serde_json::to_value(statement(&out, &inputs, &build).unwrap()).unwrap();
+        assert_eq!(s["_type"], STATEMENT_TYPE);
+        assert_eq!(s["subject"][0]["name"], "uki.efi");
+        assert_eq!(
+            s["subject"][0]["digest"]["sha256"],
+            Algorithm::Sha256.digest(b"uki").hex
+        );
+        let deps = &s["predicate"]["buildDefinition"]["resolvedDependencies"];
+        assert_eq!(deps.as_array().unwrap().len(), 3);
+        assert_eq!(
+            deps[1]["digest"]["sha256"],
+            Algorithm::Sha256.digest(b"kernel").hex
+        );
+        let initrd = deps[2]["digest"]["dirhash"].clone();
+        assert!(initrd.is_string());
+        let meta = &s["predicate"]["runDetails"]["metadata"];
+        assert_eq!(meta["startedOn"], "2023-11-14T22:13:20Z");
+        assert_eq!(meta["finishedOn"], "2023-11-14T22:14:21Z");
+
+        // Any change below a directory input changes its digest.
+        std::fs::write(tree.join("init"), b"#!/bin/busybox sh\n").unwrap();
+        let s = serde_json::to_value(statement(&out, &inputs, &build).unwrap()).unwrap();
+        assert_ne!(
+            s["predicate"]["buildDefinition"]["resolvedDependencies"][2]["digest"]["dirhash"],
+            initrd
+        );
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+}
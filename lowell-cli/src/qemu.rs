@@ -0,0 +1,415 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Boot a UKI in QEMU and watch its serial console (`lowell test boot`).
+//!
+//! The UKI is copied to `EFI/BOOT/BOOT<ARCH>.EFI` in a scratch directory
+//! that QEMU presents as a FAT disk (`fat:`), so OVMF boots it through the
+//! removable-media path with no boot entries to set up. Firmware runs from
+//! pflash with a scratch copy of its variable store; with Secure Boot the
+//! machine gets SMM and the `secboot` build of OVMF, with a TPM a `swtpm`
+//! instance on a socket.
+//!
+//! The console is `-serial stdio`: output is matched as it arrives against
+//! the success and failure patterns, so the UKI's command line needs
+//! `console=ttyS0` (x86) or `console=ttyAMA0` (arm64) to be seen past the
+//! firmware.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Patterns that fail a boot unless `--fail` replaces them.
+pub const DEFAULT_FAIL: &[&str] = &["Kernel panic", "Access Denied", "Security Violation"];
+
+/// Firmware images known by distribution path: (code, variable store
+/// template), per UEFI architecture and Secure Boot. Secure Boot stores
+/// come with Microsoft's keys enrolled.
+const FIRMWARE: &[(&str, bool, &str, &str)] = &[
+    (
+        "x64",
+        false,
+        "/usr/share/edk2/ovmf/OVMF_CODE.fd",
+        "/usr/share/edk2/ovmf/OVMF_VARS.fd",
+    ),
+    (
+        "x64",
+        false,
+        "/usr/share/OVMF/OVMF_CODE_4M.fd",
+        "/usr/share/OVMF/OVMF_VARS_4M.fd",
+    ),
+    (
+        "x64",
+        false,
+        "/usr/share/OVMF/OVMF_CODE.fd",
+        "/usr/share/OVMF/OVMF_VARS.fd",
+    ),
+    (
+        "x64",
+        false,
+        "/usr/share/edk2/x64/OVMF_CODE.4m.fd",
+        "/usr/share/edk2/x64/OVMF_VARS.4m.fd",
+    ),
+    (
+        "x64",
+        true,
+        "/usr/share/edk2/ovmf/OVMF_CODE.secboot.fd",
+        "/usr/share/edk2/ovmf/OVMF_VARS.secboot.fd",
+    ),
+    (
+        "x64",
+        true,
+        "/usr/share/OVMF/OVMF_CODE_4M.secboot.fd",
+        "/usr/share/OVMF/OVMF_VARS_4M.ms.fd",
+    ),
+    (
+        "x64",
+        true,
+        "/usr/share/OVMF/OVMF_CODE.secboot.fd",
+        "/usr/share/OVMF/OVMF_VARS.ms.fd",
+    ),
+    (
+        "aa64",
+        false,
+        "/usr/share/edk2/aarch64/QEMU_EFI-pflash.raw",
+        "/usr/share/edk2/aarch64/vars-template-pflash.raw",
+    ),
+    (
+        "aa64",
+        false,
+        "/usr/share/AAVMF/AAVMF_CODE.fd",
+        "/usr/share/AAVMF/AAVMF_VARS.fd",
+    ),
+    (
+        "aa64",
+        true,
+        "/usr/share/AAVMF/AAVMF_CODE.ms.fd",
+        "/usr/share/AAVMF/AAVMF_VARS.ms.fd",
+    ),
+];
+
+/// OVMF's code and variable store template.
+#[derive(Debug, Clone)]
+pub struct Firmware {
+    pub code: PathBuf,
+    pub vars: PathBuf,
+}
+
+impl Firmware {
+    /// The first installed pair for `arch` (`x64`, `aa64`).
+    pub fn find(arch: &str, secure_boot: bool) -> Result<Self> {
+        FIRMWARE
+            .iter()
+            .filter(|(a, sb, _, _)| *a == arch && *sb == secure_boot)
+            .map(|(_, _, code, vars)| Self {
+                code: code.into(),
+                vars: vars.into(),
+            })
+            .find(|f| f.code.is_file() && f.vars.is_file())
+            .with_context(|| {
+                let kind = if secure_boot { "Secure Boot " } else { "" };
+                format!("no {kind}OVMF firmware for {arch} found; pass --ovmf and --ovmf-vars")
+            })
+    }
+}
+
+/// Everything one boot needs.
+#[derive(Debug)]
+pub struct Plan {
+    pub qemu: PathBuf,
+    /// UEFI architecture of the UKI.
+    pub arch: &'static str,
+    pub firmware: Firmware,
+    pub secure_boot: bool,
+    pub memory_mib: u32,
+    /// Use KVM when the host can run the guest natively.
+    pub kvm: bool,
+    /// Appended to QEMU's command line.
+    pub extra: Vec<String>,
+}
+
+impl Plan {
+    /// `qemu-system-x86_64` or `qemu-system-aarch64`.
+    pub fn default_qemu(arch: &str) -> Result<&'static str> {
+        Ok(match arch {
+            "x64" => "qemu-system-x86_64",
+            "aa64" => "qemu-system-aarch64",
+            other => bail!("no QEMU target for {other}"),
+        })
+    }
+
+    /// QEMU's arguments, with the ESP directory, writable variable store
+    /// and TPM socket (if any) in place.
+    pub fn args(&self, esp: &Path, vars: &Path, tpm: Option<&Path>) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+        let mut push = |items: &[&str]| args.extend(items.iter().map(OsString::from));
+        let machine = match (self.arch, self.secure_boot) {
+            ("x64", true) => "q35,smm=on",
+            ("x64", false) => "q35",
+            _ => "virt",
+        };
+        push(&["-machine", machine, "-cpu", "max"]);
+        let memory = self.memory_mib.to_string();
+        push(&["-m", &memory]);
+        push(&["-accel", if self.kvm { "kvm" } else { "tcg" }]);
+        push(&["-display", "none", "-monitor", "none", "-serial", "stdio"]);
+        push(&["-no-reboot", "-nodefaults"]);
+        if self.secure_boot && self.arch == "x64" {
+            push(&["-global", "driver=cfi.pflash01,property=secure,value=on"]);
+        }
+        let mut drive = |spec: String| {
+            args.push("-drive".into());
+            args.push(spec.into());
+        };
+        drive(format!(
+            "if=pflash,format=raw,unit=0,readonly=on,file={}",
+            self.firmware.code.display()
+        ));
+        drive(format!(
+            "if=pflash,format=raw,unit=1,file={}",
+            vars.display()
+        ));
+        drive(format!("if=virtio,format=raw,file=fat:{}", esp.display()));
+        if let Some(socket) = tpm {
+            let device = if self.arch == "x64" {
+                "tpm-tis"
+            } else {
+                "tpm-tis-device"
+            };
+            args.extend(
+                [
+                    "-chardev".into(),
+                    format!("socket,id=chrtpm,path={}", socket.display()),
+                    "-tpmdev".into(),
+                    "emulator,id=tpm0,chardev=chrtpm".into(),
+                    "-device".into(),
+                    format!("{device},tpmdev=tpm0"),
+                ]
+                .map(OsString::from),
+            );
+        }
+        args.extend(self.extra.iter().map(OsString::from));
+        args
+    }
+}
+
+/// How a boot ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "outcome")]
+pub enum Outcome {
+    /// A success pattern appeared.
+    Passed {
+        matched: String,
+    },
+    /// A failure pattern appeared.
+    Failed {
+        matched: String,
+    },
+    /// QEMU exited before either.
+    Exited {
+        status: String,
+    },
+    TimedOut,
+}
+
+/// Watch `console` until a pattern from `expect` (pass) or `fail` shows
+/// up, the stream ends, or `timeout` passes. Everything read is copied to
+/// `log`.
+pub fn watch(
+    console: impl Read + Send + 'static,
+    expect: &[String],
+    fail: &[String],
+    timeout: Duration,
+    log: &mut dyn Write,
+) -> Result<Option<Outcome>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut console = console;
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = console.read(&mut buf) {
+            if n == 0 || tx.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    let deadline = Instant::now() + timeout;
+    let longest = expect
+        .iter()
+        .chain(fail)
+        .map(String::len)
+        .max()
+        .unwrap_or(0);
+    let mut seen = String::new();
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let chunk = match rx.recv_timeout(left) {
+            Ok(chunk) => chunk,
+            Err(mpsc::RecvTimeoutError::Timeout) => return Ok(Some(Outcome::TimedOut)),
+            // The caller knows how the process ended.
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(None),
+        };
+        log.write_all(&chunk)?;
+        // Look again at the tail of what came before, in case a pattern
+        // straddles two reads.
+        let from = seen.len().saturating_sub(longest);
+        seen += &String::from_utf8_lossy(&chunk);
+        let from = (0..=from)
+            .rev()
+            .find(|&i| seen.is_char_boundary(i))
+            .unwrap_or(0);
+        let window = &seen[from..];
+        if let Some(m) = fail.iter().find(|p| window.contains(p.as_str())) {
+            return Ok(Some(Outcome::Failed { matched: m.clone() }));
+        }
+        if let Some(m) = expect.iter().find(|p| window.contains(p.as_str())) {
+            return Ok(Some(Outcome::Passed { matched: m.clone() }));
+        }
+    }
+}
+
+/// A `swtpm` instance serving a TPM 2.0 on a socket in `dir`.
+pub struct Swtpm {
+    child: Child,
+    pub socket: PathBuf,
+}
+
+impl Swtpm {
+    pub fn start(dir: &Path) -> Result<Self> {
+        let socket = dir.join("swtpm.sock");
+        let child = Command::new("swtpm")
+            .arg("socket")
+            .arg("--tpm2")
+            .arg("--tpmstate")
+            .arg(format!("dir={}", dir.display()))
+            .arg("--ctrl")
+            .arg(format!("type=unixio,path={}", socket.display()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("start swtpm (is it installed?)")?;
+        let mut tpm = Self { child, socket };
+        let ready = Instant::now() + Duration::from_secs(5);
+        while !tpm.socket.exists() {
+            if let Some(status) = tpm.child.try_wait()? {
+                bail!("swtpm exited with {status}");
+            }
+            if Instant::now() > ready {
+                bail!("swtpm did not create {}", tpm.socket.display());
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        Ok(tpm)
+    }
+}
+
+impl Drop for Swtpm {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Whether `/dev/kvm` can run a guest of UEFI architecture `arch`.
+pub fn kvm_usable(arch: &str) -> bool {
+    crate::esp::host_efi_arch() == Some(arch)
+        && std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/kvm")
+            .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn patterns_match_across_reads() {
+        // A console that trickles out one byte at a time.
+        struct Slow(&'static [u8]);
+        impl Read for Slow {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let Some((&b, rest)) = self.0.split_first() else {
+                    return Ok(0);
+                };
+                (buf[0], self.0) = (b, rest);
+                Ok(1)
+            }
+        }
+        let (expect, fail) = (strings(&["login:"]), strings(&["Kernel panic"]));
+        let mut log = Vec::new();
+        let r = watch(
+            Slow(b"BdsDxe: loading Boot0001\r\nfedora login: "),
+            &expect,
+            &fail,
+            Duration::from_secs(5),
+            &mut log,
+        );
+        assert_eq!(
+            r.unwrap(),
+            Some(Outcome::Passed {
+                matched: "login:".into()
+            })
+        );
+        assert!(log.starts_with(b"BdsDxe"));
+
+        let r = watch(
+            &b"[ 1.0] Kernel panic - not syncing\nlogin:"[..],
+            &expect,
+            &fail,
+            Duration::from_secs(5),
+            &mut Vec::new(),
+        );
+        assert!(matches!(r.unwrap(), Some(Outcome::Failed { .. })));
+        let r = watch(
+            &b"quiet"[..],
+            &expect,
+            &fail,
+            Duration::from_secs(5),
+            &mut Vec::new(),
+        );
+        assert_eq!(r.unwrap(), None);
+    }
+
+    #[test]
+    fn secure_boot_with_tpm_adds_smm_and_a_tpm_device() {
+        let plan = Plan {
+            qemu: "qemu-system-x86_64".into(),
+            arch: "x64",
+            firmware: Firmware {
+                code: "/fw/CODE.fd".into(),
+                vars: "/fw/VARS.fd".into(),
+            },
+            secure_boot: true,
+            memory_mib: 1024,
+            kvm: false,
+            extra: strings(&["-smp", "2"]),
+        };
+        let args = plan.args(
+            Path::new("/t/esp"),
+            Path::new("/t/vars.fd"),
+            Some(Path::new("/t/tpm.sock")),
+        );
+        let args: Vec<String> = args.iter().map(|a| a.to_string_lossy().into()).collect();
+        let joined = args.join(" ");
+        assert!(joined.starts_with("-machine q35,smm=on -cpu max -m 1024 -accel tcg"));
+        for want in [
+            "-global driver=cfi.pflash01,property=secure,value=on",
+            "-drive if=pflash,format=raw,unit=0,readonly=on,file=/fw/CODE.fd",
+            "-drive if=pflash,format=raw,unit=1,file=/t/vars.fd",
+            "-drive if=virtio,format=raw,file=fat:/t/esp",
+            "-chardev socket,id=chrtpm,path=/t/tpm.sock",
+            "-device tpm-tis,tpmdev=tpm0",
+        ] {
+            assert!(joined.contains(want), "{want} in {joined}");
+        }
+        assert!(joined.ends_with("-smp 2"));
+    }
+}
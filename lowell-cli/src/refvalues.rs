@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Reference values for remote attestation (`lowell uki pcrs --reference`,
+//! `lowell build --reference-values`): what a verifier should expect a TPM
+//! to report once a UKI has booted, from the predictions in
+//! [`tpm`](lowell_core::formats::tpm).
+//!
+//! * **`tpm2-policy`**: the PCR policy JSON of `systemd-measure calculate
+//!   --json`, `{"<bank>": [{"pcr": 11, "value": "<hex>"}]}`, which
+//!   `systemd-measure sign` and policy tooling built on it take as input.
+//! * **`corim`**: a CoMID (the module inside a CoRIM) in the JSON form
+//!   Veraison's `cocli comid create` compiles to CBOR. One
+//!   `reference-values` triple carries PCR 11's final value (key: the PCR
+//!   index) and every event behind it and PCR 4's image digests (keys like
+//!   `pcr11/.linux data`, `pcr4/image`), digests as `<alg>;<base64>` with
+//!   IANA named-information algorithm names. The tag is identified by the
+//!   UKI's SHA-256 Authenticode digest, and the environment by its
+//!   os-release `ID` and `PRETTY_NAME`.
+//! * **`pcrlock`**: a systemd-pcrlock component, as `systemd-pcrlock
+//!   lock-uki` writes one: a PCR 4 record for the UKI's image digest, then
+//!   a PCR 11 record per stub measurement, each with a digest for every
+//!   bank (whatever the bank asked for). Several UKIs that may boot go in
+//!   one `.pcrlock.d` directory as alternatives, e.g.
+//!   `/var/lib/pcrlock.d/670-uki.pcrlock.d/<name>.pcrlock`, before
+//!   `systemd-pcrlock make-policy`.
+
+use anyhow::Result;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::ValueEnum;
+use lowell_core::formats::osrel::read_os_release;
+use lowell_core::formats::pe::PeFile;
+use lowell_core::formats::tpm::{self, Bank};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// `systemd-measure calculate --json` PCR values
+    Tpm2Policy,
+    /// A CoMID in Veraison's JSON template form
+    Corim,
+    /// A systemd-pcrlock `.pcrlock` component
+    Pcrlock,
+}
+
+/// The reference values for `uki` in `bank`, as `format`. `name` names
+/// the environment when the UKI has no os-release.
+pub fn document(uki: &PeFile, bank: Bank, format: Format, name: &str) -> Result<Value> {
+    Ok(match format {
+        Format::Tpm2Policy => {
+            let predicted = tpm::predict(bank, &tpm::pcr11(uki, bank)?);
+            json!({
+                bank.as_str(): [{"pcr": 11, "value": tpm::hex(&predicted)}],
+            })
+        }
+        Format::Pcrlock => pcrlock(uki)?,
+        Format::Corim => {
+            let pcr11 = tpm::pcr11(uki, bank)?;
+            let predicted = tpm::predict(bank, &pcr11);
+            let digest =
+                |bytes: &[u8]| json!([format!("{};{}", alg(bank), BASE64_STANDARD.encode(bytes))]);
+            let mut measurements = vec![json!({
+                "key": {"type": "uint", "value": 11},
+                "value": {"digests": digest(&predicted)},
+            })];
+            let events = pcr11
+                .iter()
+                .map(|m| (format!("pcr11/{}", m.label), &m.digest));
+            let images = tpm::pcr4(uki, bank)?;
+            let images = images
+                .iter()
+                .map(|m| (format!("pcr4/{}", m.label), &m.digest));
+            for (key, bytes) in events.chain(images) {
+                measurements.push(json!({
+                    "key": {"type": "string", "value": key},
+                    "value": {"digests": digest(bytes)},
+                }));
+            }
+            let id = tpm::hex(&Bank::Sha256.hash(&uki.authenticode_parts()?));
+            let os = read_os_release(uki)?;
+            let model = os.as_ref().and_then(|o| o.name.as_deref()).unwrap_or(name);
+            let mut class = json!({"model": model});
+            if let Some(vendor) = os.as_ref().and_then(|o| o.id.as_ref()) {
+                class["vendor"] = json!(vendor);
+            }
+            json!({
+                "lang": "en",
+                "tag-identity": {"id": id, "version": 0},
+                "entities": [{
+                    "name": "lowell",
+                    "regid": "https://github.com/SamD2021/lowell",
+                    "roles": ["tagCreator"],
+                }],
+                "triples": {
+                    "reference-values": [{
+                        "environment": {"class": class},
+                        "measurements": measurements,
+                    }],
+                },
+            })
+        }
+    })
+}
+
+/// PCR 4's image record and PCR 11's records, each with its digest in
+/// every bank.
+fn pcrlock(uki: &PeFile) -> Result<Value> {
+    let mut records: Vec<Value> = Vec::new();
+    for bank in Bank::ALL {
+        let image = tpm::pcr4(uki, bank)?.swap_remove(0);
+        let pcr11 = tpm::pcr11(uki, bank)?.into_iter().map(|m| (11, m));
+        for (i, (pcr, m)) in [(4, image)].into_iter().chain(pcr11).enumerate() {
+            if i == records.len() {
+                records.push(json!({"pcr": pcr, "digests": []}));
+            }
+            let digest = json!({"hashAlg": bank.as_str(), "digest": tpm::hex(&m.digest)});
+            records[i]["digests"]
+                .as_array_mut()
+                .expect("digests is an array")
+                .push(digest);
+        }
+    }
+    Ok(json!({ "records": records }))
+}
+
+/// The IANA named-information name of `bank`'s hash.
+fn alg(bank: Bank) -> &'static str {
+    match bank {
+        Bank::Sha1 => "sha-1",
+        Bank::Sha256 => "sha-256",
+        Bank::Sha384 => "sha-384",
+        Bank::Sha512 => "sha-512",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_agree_with_the_prediction() {
+        let image = lowell_test_util::UkiBuilder::new()
+            .osrel("ID=fedora\nPRETTY_NAME=\"Fedora Linux 41\"\n")
+            .cmdline("quiet")
+            .linux(&[0xAA; 4096])
+            .build();
+        let pe = PeFile::from_bytes(image).unwrap();
+        let predicted = tpm::predict(Bank::Sha384, &tpm::pcr11(&pe, Bank::Sha384).unwrap());
+
+        let policy = document(&pe, Bank::Sha384, Format::Tpm2Policy, "uki").unwrap();
+        assert_eq!(policy["sha384"][0]["pcr"], 11);
+        assert_eq!(policy["sha384"][0]["value"], tpm::hex(&predicted));
+
+        let comid = document(&pe, Bank::Sha384, Format::Corim, "uki").unwrap();
+        let triple = &comid["triples"]["reference-values"][0];
+        assert_eq!(triple["environment"]["class"]["vendor"], "fedora");
+        assert_eq!(triple["environment"]["class"]["model"], "Fedora Linux 41");
+        let pcr = &triple["measurements"][0];
+        assert_eq!(pcr["key"]["value"], 11);
+        let (alg, b64) = pcr["value"]["digests"][0]
+            .as_str()
+            .unwrap()
+            .split_once(';')
+            .unwrap();
+        assert_eq!(alg, "sha-384");
+        assert_eq!(BASE64_STANDARD.decode(b64).unwrap(), predicted);
+        let keys: Vec<&str> = triple["measurements"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|m| m["key"]["value"].as_str())
+            .collect();
+        assert!(keys.contains(&"pcr11/.cmdline data"), "{keys:?}");
+        assert!(keys.contains(&"pcr4/image"), "{keys:?}");
+
+        let lock = document(&pe, Bank::Sha256, Format::Pcrlock, "uki").unwrap();
+        let records = lock["records"].as_array().unwrap();
+        assert_eq!(records[0]["pcr"], 4);
+        assert_eq!(
+            records.len(),
+            1 + tpm::pcr11(&pe, Bank::Sha256).unwrap().len()
+        );
+        let last = &records[records.len() - 1];
+        assert_eq!(last["pcr"], 11);
+        assert_eq!(last["digests"].as_array().unwrap().len(), Bank::ALL.len());
+        let sha384 = &last["digests"][2];
+        assert_eq!(sha384["hashAlg"], "sha384");
+        let pcr11 = tpm::pcr11(&pe, Bank::Sha384).unwrap();
+        assert_eq!(sha384["digest"], tpm::hex(&pcr11.last().unwrap().digest));
+    }
+}
@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Registry credentials and authentication challenges.
+//!
+//! Credentials come from the files `podman login` and `docker login`
+//! write, first match wins: `$REGISTRY_AUTH_FILE`,
+//! `$XDG_RUNTIME_DIR/containers/auth.json`,
+//! `~/.config/containers/auth.json`, then `$DOCKER_CONFIG/config.json`
+//! (`~/.docker/config.json`). Only inline `auth` entries are read;
+//! credential helpers are not run.
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Docker Hub's key in auth files.
+const DOCKER_HUB_KEY: &str = "https://index.docker.io/v1/";
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Credentials {
+    /// The `Authorization` value for HTTP basic auth.
+    pub fn basic(&self) -> String {
+        let pair = format!("{}:{}", self.username, self.password);
+        format!("Basic {}", BASE64_STANDARD.encode(pair))
+    }
+
+    /// Credentials for `registry` from the first auth file that has them.
+    pub fn find(registry: &str) -> Option<Self> {
+        auth_files().iter().find_map(|file| {
+            let found = from_file(file, registry);
+            if found.is_some() {
+                debug!(registry, file = %file.display(), "using stored credentials");
+            }
+            found
+        })
+    }
+}
+
+fn auth_files() -> Vec<PathBuf> {
+    let env = |k: &str| std::env::var_os(k).map(PathBuf::from);
+    let home = env("HOME");
+    let mut files = Vec::new();
+    files.extend(env("REGISTRY_AUTH_FILE"));
+    files.extend(env("XDG_RUNTIME_DIR").map(|d| d.join("containers/auth.json")));
+    files.extend(
+        home.as_ref()
+            .map(|h| h.join(".config/containers/auth.json")),
+    );
+    match env("DOCKER_CONFIG") {
+        Some(dir) => files.push(dir.join("config.json")),
+        None => files.extend(home.map(|h| h.join(".docker/config.json"))),
+    }
+    files
+}
+
+#[derive(Deserialize)]
+struct AuthFile {
+    #[serde(default)]
+    auths: BTreeMap<String, Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    auth: Option<String>,
+}
+
+fn from_file(file: &Path, registry: &str) -> Option<Credentials> {
+    let bytes = std::fs::read(file).ok()?;
+    let parsed: AuthFile = serde_json::from_slice(&bytes)
+        .inspect_err(|e| debug!(file = %file.display(), "ignoring auth file: {e}"))
+        .ok()?;
+    // Keys may carry a scheme or path (`https://quay.io/v1/`).
+    let host = |key: &str| {
+        let key = key.split_once("://").map_or(key, |(_, rest)| rest);
+        key.split('/').next().unwrap_or(key).to_string()
+    };
+    let want = match registry {
+        super::reference::DOCKER_HUB => host(DOCKER_HUB_KEY),
+        r => r.to_string(),
+    };
+    let entry = parsed.auths.iter().find(|(k, _)| host(k) == want)?.1;
+    let decoded = BASE64_STANDARD.decode(entry.auth.as_deref()?).ok()?;
+    let (username, password) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+    Some(Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    })
+}
+
+/// A `WWW-Authenticate` challenge: the scheme and its parameters.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Challenge {
+    /// Lowercase: `bearer` or `basic`.
+    pub scheme: String,
+    pub params: BTreeMap<String, String>,
+}
+
+impl Challenge {
+    pub fn parse(header: &str) -> Option<Self> {
+        let (scheme, rest) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+        let mut params = BTreeMap::new();
+        let mut rest = rest.trim();
+        while let Some((key, value)) = rest.split_once('=') {
+            let key = key
+                .trim()
+                .trim_start_matches(',')
+                .trim()
+                .to_ascii_lowercase();
+            let value = value.trim_start();
+            let (value, tail) = match value.strip_prefix('"') {
+                Some(quoted) => quoted.split_once('"')?,
+                None => value.split_once(',').unwrap_or((value, "")),
+            };
+            params.insert(key, value.to_string());
+            rest = tail.trim_start_matches(',').trim();
+        }
+        Some(Self {
+            scheme: scheme.to_ascii_lowercase(),
+            params,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenges_and_auth_files_parse() {
+        let c = Challenge::parse(
+            r#"Bearer realm="https://auth.example/token",service="registry.example",scope="repository:os/uki:pull,push""#,
+        )
+        .unwrap();
+        assert_eq!(c.scheme, "bearer");
+        assert_eq!(c.params["realm"], "https://auth.example/token");
+        assert_eq!(c.params["scope"], "repository:os/uki:pull,push");
+        let c = Challenge::parse(r#"Basic realm="registry""#).unwrap();
+        assert_eq!((c.scheme.as_str(), c.params.len()), ("basic", 1));
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("auth.json");
+        let auth = BASE64_STANDARD.encode("me:s3cret");
+        std::fs::write(
+            &file,
+            format!(r#"{{"auths":{{"https://quay.io/v1/":{{"auth":"{auth}"}},"{DOCKER_HUB_KEY}":{{"auth":"{auth}"}}}}}}"#),
+        )
+        .unwrap();
+        let creds = from_file(&file, "quay.io").unwrap();
+        assert_eq!(
+            (creds.username.as_str(), creds.password.as_str()),
+            ("me", "s3cret")
+        );
+        assert_eq!(creds.basic(), format!("Basic {auth}"));
+        assert!(from_file(&file, super::super::reference::DOCKER_HUB).is_some());
+        assert!(from_file(&file, "ghcr.io").is_none());
+    }
+}
@@ -0,0 +1,606 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! UKIs as OCI artifacts (`lowell push`, `lowell pull`).
+//!
+//! An artifact is stored the way ORAS stores files: an OCI image manifest
+//! with an `artifactType`, the empty config blob, and one layer per file,
+//! named by its `org.opencontainers.image.title` annotation:
+//!
+//! | layer                   | media type                                   |
+//! |-------------------------|----------------------------------------------|
+//! | the UKI                 | `application/vnd.lowell.uki.layer.v1.efi`    |
+//! | its inspect report      | `application/vnd.lowell.uki.report.v1+json`  |
+//! | an SBOM (optional)      | `application/spdx+json` or `application/vnd.cyclonedx+json` |
+//! | provenance (optional)   | `application/vnd.in-toto+json`, or `application/vnd.dsse.envelope.v1+json` when signed |
+//!
+//! [`Client`] speaks just enough of the distribution API for that:
+//! monolithic blob uploads, manifests by tag or digest, and bearer-token
+//! or basic auth with credentials from [`auth`].
+
+pub mod auth;
+pub mod reference;
+
+use crate::error::Coded;
+use anyhow::{bail, Context, Result};
+use auth::{Challenge, Credentials};
+use lowell_core::digest::{Algorithm, Digest};
+use lowell_core::error::ErrorCode;
+use reference::Reference;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use tracing::debug;
+use ureq::http::{Method, Request, Response};
+use ureq::Body;
+
+pub const MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const INDEX: &str = "application/vnd.oci.image.index.v1+json";
+pub const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+pub const DOCKER_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+pub const EMPTY_CONFIG: &str = "application/vnd.oci.empty.v1+json";
+pub const ARTIFACT_TYPE: &str = "application/vnd.lowell.uki.v1";
+pub const UKI_LAYER: &str = "application/vnd.lowell.uki.layer.v1.efi";
+pub const REPORT_LAYER: &str = "application/vnd.lowell.uki.report.v1+json";
+pub const SPDX: &str = "application/spdx+json";
+pub const CYCLONEDX: &str = "application/vnd.cyclonedx+json";
+pub const DSSE: &str = "application/vnd.dsse.envelope.v1+json";
+/// Layer annotation holding the file name.
+pub const TITLE: &str = "org.opencontainers.image.title";
+/// Largest manifest accepted, as most registries enforce.
+const MAX_MANIFEST: u64 = 4 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub schema_version: u32,
+    pub media_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    pub config: Descriptor,
+    pub layers: Vec<Descriptor>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    /// `algorithm:hex`.
+    pub digest: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl Descriptor {
+    /// Describe `data` as `media_type`, titled `title` if given.
+    pub fn of(media_type: &str, data: &[u8], title: Option<&str>) -> Self {
+        Self {
+            media_type: media_type.to_string(),
+            digest: Algorithm::Sha256.digest(data).to_string(),
+            size: data.len() as u64,
+            annotations: title
+                .map(|t| BTreeMap::from([(TITLE.to_string(), t.to_string())]))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.annotations.get(TITLE).map(String::as_str)
+    }
+
+    /// Fail unless `data` is what this describes.
+    pub fn verify(&self, data: &[u8]) -> Result<()> {
+        let want: Digest = self.digest.parse().map_err(anyhow::Error::msg)?;
+        if data.len() as u64 != self.size || want.algorithm.digest(data) != want {
+            bail!(Coded::new(
+                ErrorCode::Registry,
+                format!("blob {} does not match its digest or size", self.digest)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A multi-platform image: one manifest per platform (OCI index or Docker
+/// manifest list).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Index {
+    pub manifests: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct IndexEntry {
+    #[serde(flatten)]
+    pub descriptor: Descriptor,
+    #[serde(default)]
+    pub platform: Option<Platform>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+}
+
+impl Index {
+    /// The manifest for Linux on this host's architecture.
+    pub fn for_host(&self) -> Result<&Descriptor> {
+        let arch = host_goarch();
+        self.manifests
+            .iter()
+            .find(|m| {
+                m.platform
+                    .as_ref()
+                    .is_some_and(|p| p.os == "linux" && p.architecture == arch)
+            })
+            .map(|m| &m.descriptor)
+            .ok_or_else(|| Coded::new(ErrorCode::NotFound, format!("no linux/{arch} image")).into())
+    }
+}
+
+/// This host's architecture as OCI platforms spell it.
+pub fn host_goarch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64le",
+        other => other,
+    }
+}
+
+/// Parse a manifest that may be an index: an image manifest as is, an
+/// index by following `pick` to one of its entries.
+pub fn image_manifest(
+    bytes: &[u8],
+    mut pick: impl FnMut(&Descriptor) -> Result<Vec<u8>>,
+) -> Result<Manifest> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Kind {
+        #[serde(default)]
+        media_type: Option<String>,
+        #[serde(default)]
+        manifests: Option<serde_json::Value>,
+    }
+    let kind: Kind = serde_json::from_slice(bytes).context("not an image manifest")?;
+    let is_index = match kind.media_type.as_deref() {
+        Some(INDEX | DOCKER_LIST) => true,
+        Some(_) => false,
+        None => kind.manifests.is_some(),
+    };
+    if !is_index {
+        return serde_json::from_slice(bytes).context("not an image manifest");
+    }
+    let index: Index = serde_json::from_slice(bytes).context("not an image index")?;
+    let desc = index.for_host()?;
+    let bytes = pick(desc)?;
+    desc.verify(&bytes)?;
+    serde_json::from_slice(&bytes).context("not an image manifest")
+}
+
+/// What a [`Client`] asks a token service for.
+#[derive(Debug, Clone, Copy)]
+pub enum Access {
+    Pull,
+    Push,
+}
+
+/// One repository on one registry.
+pub struct Client {
+    agent: ureq::Agent,
+    /// `https://host` (or `http://` with `--plain-http`).
+    base: String,
+    repository: String,
+    access: Access,
+    credentials: Option<Credentials>,
+    /// `Authorization` value, once a challenge has been answered.
+    authorization: Option<String>,
+}
+
+impl Client {
+    pub fn new(reference: &Reference, access: Access, plain_http: bool) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .user_agent(concat!("lowell/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .new_agent();
+        let scheme = if plain_http { "http" } else { "https" };
+        Self {
+            agent,
+            base: format!("{scheme}://{}", reference.api_host()),
+            repository: reference.repository.clone(),
+            access,
+            credentials: Credentials::find(&reference.registry),
+            authorization: None,
+        }
+    }
+
+    /// Whether the repository already has the blob `digest`.
+    pub fn has_blob(&mut self, digest: &str) -> Result<bool> {
+        let url = format!("{}/v2/{}/blobs/{digest}", self.base, self.repository);
+        let resp = self.send(Method::HEAD, &url, &[], &[])?;
+        match resp.status().as_u16() {
+            404 => Ok(false),
+            _ => check(resp, "check blob").map(|_| true),
+        }
+    }
+
+    /// Upload `data` as the blob `desc` in one request.
+    pub fn push_blob(&mut self, desc: &Descriptor, data: &[u8]) -> Result<()> {
+        let url = format!("{}/v2/{}/blobs/uploads/", self.base, self.repository);
+        let resp = check(self.send(Method::POST, &url, &[], &[])?, "start upload")?;
+        let location = resp
+            .headers()
+            .get("location")
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| Coded::new(ErrorCode::Registry, "upload has no Location"))?;
+        let mut url = match location.starts_with('/') {
+            true => format!("{}{location}", self.base),
+            false => location.to_string(),
+        };
+        url.push(if url.contains('?') { '&' } else { '?' });
+        url += &format!("digest={}", encode(&desc.digest));
+        let headers = [("content-type", "application/octet-stream")];
+        check(self.send(Method::PUT, &url, &headers, data)?, "upload blob")?;
+        Ok(())
+    }
+
+    /// Store `manifest` under `target` (a tag); returns its digest.
+    pub fn put_manifest(&mut self, target: &str, manifest: &[u8]) -> Result<String> {
+        let url = format!("{}/v2/{}/manifests/{target}", self.base, self.repository);
+        let headers = [("content-type", MANIFEST)];
+        check(
+            self.send(Method::PUT, &url, &headers, manifest)?,
+            "push manifest",
+        )?;
+        Ok(Algorithm::Sha256.digest(manifest).to_string())
+    }
+
+    /// The manifest at `target` (a tag or digest), as sent.
+    pub fn manifest(&mut self, target: &str) -> Result<Vec<u8>> {
+        self.manifest_as(target, MANIFEST)
+    }
+
+    /// The image manifest at `target`, following an index to this host's
+    /// platform, and the digest of what `target` named.
+    pub fn image_manifest(&mut self, target: &str) -> Result<(Manifest, String)> {
+        let accept = [MANIFEST, INDEX, DOCKER_MANIFEST, DOCKER_LIST].join(", ");
+        let bytes = self.manifest_as(target, &accept)?;
+        let digest = Algorithm::Sha256.digest(&bytes).to_string();
+        let manifest = image_manifest(&bytes, |desc| self.manifest_as(&desc.digest, &accept))?;
+        Ok((manifest, digest))
+    }
+
+    fn manifest_as(&mut self, target: &str, accept: &str) -> Result<Vec<u8>> {
+        let url = format!("{}/v2/{}/manifests/{target}", self.base, self.repository);
+        let resp = self.send(Method::GET, &url, &[("accept", accept)], &[])?;
+        let mut resp = check(resp, "fetch manifest")?;
+        Ok(resp
+            .body_mut()
+            .with_config()
+            .limit(MAX_MANIFEST)
+            .read_to_vec()?)
+    }
+
+    /// The blob `desc`, checked against its digest and size.
+    pub fn blob(&mut self, desc: &Descriptor) -> Result<Vec<u8>> {
+        let url = format!("{}/v2/{}/blobs/{}", self.base, self.repository, desc.digest);
+        let resp = self.send(Method::GET, &url, &[], &[])?;
+        let mut resp = check(resp, "fetch blob")?;
+        let data = resp
+            .body_mut()
+            .with_config()
+            .limit(desc.size.saturating_add(1))
+            .read_to_vec()?;
+        desc.verify(&data)?;
+        Ok(data)
+    }
+
+    /// Stream the blob `desc` to `out`, checked against its digest and size
+    /// (sha256 only) once it has all arrived.
+    pub fn blob_to(&mut self, desc: &Descriptor, out: &mut dyn Write) -> Result<()> {
+        let want: Digest = desc.digest.parse().map_err(anyhow::Error::msg)?;
+        if want.algorithm != Algorithm::Sha256 {
+            bail!("blob {}: only sha256 blobs can be streamed", desc.digest);
+        }
+        let url = format!("{}/v2/{}/blobs/{}", self.base, self.repository, desc.digest);
+        let resp = self.send(Method::GET, &url, &[], &[])?;
+        let mut resp = check(resp, "fetch blob")?;
+        let mut body = resp
+            .body_mut()
+            .with_config()
+            .limit(desc.size.saturating_add(1))
+            .reader();
+        let (mut hasher, mut size, mut buf) = (Sha256::new(), 0u64, vec![0u8; 1 << 16]);
+        loop {
+            let n = body.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            out.write_all(&buf[..n])?;
+            size += n as u64;
+        }
+        if size != desc.size || format!("{:x}", hasher.finalize()) != want.hex {
+            bail!(Coded::new(
+                ErrorCode::Registry,
+                format!("blob {} does not match its digest or size", desc.digest)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Send a request, answering one authentication challenge.
+    fn send(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response<Body>> {
+        let mut challenged = false;
+        loop {
+            let mut req = Request::builder().method(method.clone()).uri(url);
+            for (k, v) in headers {
+                req = req.header(*k, *v);
+            }
+            if let Some(auth) = &self.authorization {
+                req = req.header("authorization", auth);
+            }
+            debug!(%method, url, "registry request");
+            let resp = self
+                .agent
+                .run(req.body(body)?)
+                .with_context(|| format!("{method} {url}"))?;
+            if resp.status() != 401 || challenged {
+                return Ok(resp);
+            }
+            challenged = true;
+            let challenge = resp
+                .headers()
+                .get("www-authenticate")
+                .and_then(|v| v.to_str().ok())
+                .and_then(Challenge::parse)
+                .ok_or_else(|| {
+                    Coded::new(
+                        ErrorCode::Registry,
+                        format!("{url}: 401 without a challenge"),
+                    )
+                })?;
+            self.authorization = Some(self.answer(&challenge)?);
+        }
+    }
+
+    fn answer(&self, challenge: &Challenge) -> Result<String> {
+        let need_login = || {
+            Coded::new(
+                ErrorCode::PermissionDenied,
+                format!(
+                    "{} needs credentials: log in with `podman login` or `docker login`",
+                    self.base
+                ),
+            )
+        };
+        if challenge.scheme == "basic" {
+            return Ok(self.credentials.as_ref().ok_or_else(need_login)?.basic());
+        }
+        let realm = challenge
+            .params
+            .get("realm")
+            .ok_or_else(|| Coded::new(ErrorCode::Registry, "bearer challenge without a realm"))?;
+        let actions = match self.access {
+            Access::Pull => "pull",
+            Access::Push => "pull,push",
+        };
+        let scope = challenge
+            .params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:{actions}", self.repository));
+        let mut url = format!(
+            "{realm}{}scope={}",
+            if realm.contains('?') { '&' } else { '?' },
+            encode(&scope)
+        );
+        if let Some(service) = challenge.params.get("service") {
+            url += &format!("&service={}", encode(service));
+        }
+        let mut req = self.agent.get(&url);
+        if let Some(creds) = &self.credentials {
+            req = req.header("authorization", creds.basic());
+        }
+        let resp = req.call().with_context(|| format!("GET {realm}"))?;
+        if resp.status() == 401 {
+            return Err(need_login().into());
+        }
+        let mut resp = check(resp, "fetch token")?;
+        #[derive(Deserialize)]
+        struct Token {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+        let token: Token = serde_json::from_slice(&resp.body_mut().read_to_vec()?)?;
+        let token = token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| Coded::new(ErrorCode::Registry, "token response has no token"))?;
+        Ok(format!("Bearer {token}"))
+    }
+}
+
+/// `resp` if it succeeded, else an error with the registry's messages.
+fn check(mut resp: Response<Body>, what: &str) -> Result<Response<Body>> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    #[derive(Deserialize)]
+    struct Errors {
+        errors: Vec<ErrorEntry>,
+    }
+    #[derive(Deserialize)]
+    struct ErrorEntry {
+        code: String,
+        #[serde(default)]
+        message: String,
+    }
+    let body = resp
+        .body_mut()
+        .with_config()
+        .limit(64 << 10)
+        .read_to_vec()
+        .unwrap_or_default();
+    let detail = match serde_json::from_slice::<Errors>(&body) {
+        Ok(e) => e
+            .errors
+            .iter()
+            .map(|e| format!("{}: {}", e.code, e.message))
+            .collect::<Vec<_>>()
+            .join("; "),
+        Err(_) => String::from_utf8_lossy(&body).trim().to_string(),
+    };
+    let code = match status.as_u16() {
+        401 | 403 => ErrorCode::PermissionDenied,
+        404 => ErrorCode::NotFound,
+        _ => ErrorCode::Registry,
+    };
+    bail!(Coded::new(
+        code,
+        format!("{what}: {status} {detail}").trim_end().to_string()
+    ))
+}
+
+/// Percent-encode a query value.
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// A registry on a local port, in memory, that wants a bearer token.
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    const TOKEN: &str = "t0k3n";
+
+    #[derive(Default)]
+    pub struct State {
+        pub blobs: HashMap<String, Vec<u8>>,
+        pub manifests: HashMap<String, Vec<u8>>,
+        /// Blob uploads completed.
+        pub uploads: usize,
+    }
+
+    /// Serve until the process exits; returns `host:port` and the state.
+    pub fn serve() -> (String, Arc<Mutex<State>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let state = Arc::new(Mutex::new(State::default()));
+        let (shared, host) = (state.clone(), addr.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = handle(stream.unwrap(), &host, &shared);
+            }
+        });
+        (addr, state)
+    }
+
+    fn handle(stream: TcpStream, host: &str, state: &Mutex<State>) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let (mut len, mut authorized) = (0, false);
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header)?;
+            let Some((k, v)) = header.trim_end().split_once(':') else {
+                break;
+            };
+            match k.to_ascii_lowercase().as_str() {
+                "content-length" => len = v.trim().parse().unwrap_or(0),
+                "authorization" => authorized = v.trim() == format!("Bearer {TOKEN}"),
+                _ => {}
+            }
+        }
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body)?;
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut s = state.lock().unwrap();
+        let (status, headers, out): (u16, Vec<String>, Vec<u8>) =
+            match (method, segments.as_slice()) {
+                ("GET", ["", "token"]) => (200, vec![], format!(r#"{{"token":"{TOKEN}"}}"#).into()),
+                (_, ["", "v2", ..]) if !authorized => (
+                    401,
+                    vec![format!(
+                        r#"WWW-Authenticate: Bearer realm="http://{host}/token",service="mock""#
+                    )],
+                    vec![],
+                ),
+                ("HEAD" | "GET", [.., "blobs", digest]) => match s.blobs.get(*digest) {
+                    Some(b) => (
+                        200,
+                        vec![],
+                        if method == "GET" { b.clone() } else { vec![] },
+                    ),
+                    None => (404, vec![], vec![]),
+                },
+                ("POST", [.., "uploads", ""]) => (
+                    202,
+                    vec![format!("Location: {}/1", path.trim_end_matches('/'))],
+                    vec![],
+                ),
+                ("PUT", [.., "uploads", _]) => {
+                    let digest = query.trim_start_matches("digest=").replace("%3A", ":");
+                    s.blobs.insert(digest, body);
+                    s.uploads += 1;
+                    (201, vec![], vec![])
+                }
+                ("PUT", [.., "manifests", tag]) => {
+                    let digest = super::Algorithm::Sha256.digest(&body).to_string();
+                    s.manifests.insert(tag.to_string(), body.clone());
+                    s.manifests.insert(digest, body);
+                    (201, vec![], vec![])
+                }
+                ("GET", [.., "manifests", target]) => match s.manifests.get(*target) {
+                    Some(m) => (200, vec![], m.clone()),
+                    None => (
+                        404,
+                        vec![],
+                        br#"{"errors":[{"code":"MANIFEST_UNKNOWN","message":"no such tag"}]}"#
+                            .to_vec(),
+                    ),
+                },
+                _ => (405, vec![], vec![]),
+            };
+        drop(s);
+        let mut w = stream;
+        write!(
+            w,
+            "HTTP/1.1 {status} X\r\nConnection: close\r\nContent-Length: {}\r\n",
+            out.len()
+        )?;
+        for h in headers {
+            write!(w, "{h}\r\n")?;
+        }
+        write!(w, "\r\n")?;
+        if method != "HEAD" {
+            w.write_all(&out)?;
+        }
+        w.flush()
+    }
+}
@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Image references: `[registry/]repository[:tag][@digest]`.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Where references without a registry point, as with `podman pull`.
+pub const DOCKER_HUB: &str = "docker.io";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// Host, with a port if one was given (`localhost:5000`).
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    /// `algorithm:hex`, pinning the manifest.
+    pub digest: Option<String>,
+}
+
+impl Reference {
+    /// What to ask the registry for: the digest, else the tag, else
+    /// `latest`.
+    pub fn target(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
+
+    /// The host to connect to (Docker Hub's API lives elsewhere).
+    pub fn api_host(&self) -> &str {
+        match self.registry.as_str() {
+            DOCKER_HUB => "registry-1.docker.io",
+            host => host,
+        }
+    }
+}
+
+impl FromStr for Reference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (rest, digest) = match s.split_once('@') {
+            Some((rest, digest)) if digest.contains(':') => (rest, Some(digest.to_string())),
+            Some(_) => bail!("{s:?}: digest must be algorithm:hex"),
+            None => (s, None),
+        };
+        // A `:` after the last `/` starts the tag; before it, a port.
+        let (name, tag) = match rest.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, Some(tag.to_string())),
+            _ => (rest, None),
+        };
+        if name.is_empty() {
+            bail!("{s:?}: no repository");
+        }
+        // The first component is a registry if it looks like a host.
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, repo)) if host.contains(['.', ':']) || host == "localhost" => {
+                (host.to_string(), repo.to_string())
+            }
+            _ => (DOCKER_HUB.to_string(), name.to_string()),
+        };
+        let repository = match (registry.as_str(), repository.contains('/')) {
+            (DOCKER_HUB, false) => format!("library/{repository}"),
+            _ => repository,
+        };
+        let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-/".contains(c);
+        if repository.is_empty() || !repository.chars().all(valid) {
+            bail!("{s:?}: repository must be lowercase letters, digits and . _ - /");
+        }
+        if tag.as_deref().is_some_and(str::is_empty) {
+            bail!("{s:?}: empty tag");
+        }
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{tag}")?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{digest}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_parse_like_podman() {
+        let r: Reference = "quay.io/lowell/uki:41".parse().unwrap();
+        assert_eq!(
+            (r.registry.as_str(), r.repository.as_str(), r.target()),
+            ("quay.io", "lowell/uki", "41")
+        );
+        let r: Reference = "localhost:5000/uki".parse().unwrap();
+        assert_eq!(
+            (r.registry.as_str(), r.target()),
+            ("localhost:5000", "latest")
+        );
+        let r: Reference = "fedora".parse().unwrap();
+        assert_eq!(r.to_string(), "docker.io/library/fedora");
+        assert_eq!(r.api_host(), "registry-1.docker.io");
+
+        let pinned = "ghcr.io/o/uki:41@sha256:abcd";
+        let r: Reference = pinned.parse().unwrap();
+        assert_eq!(r.target(), "sha256:abcd");
+        assert_eq!(r.to_string(), pinned);
+
+        for bad in ["", "Quay.io/x/UKI", "reg.io/uki:", "reg.io/uki@abcd"] {
+            assert!(bad.parse::<Reference>().is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,588 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Secure Boot state from efivarfs, and whether it would let a UKI boot
+//! (`lowell preflight`).
+//!
+//! Variables are read from `/sys/firmware/efi/efivars` (or a copy of it):
+//! `SecureBoot`, `SetupMode`, `PK`, `KEK`, `db` and `dbx`, plus shim's
+//! runtime copies `MokListRT`, `MokSBStateRT` and `SbatLevelRT`, whose
+//! presence is how shim is detected.
+//!
+//! The verdict follows firmware's rules (and shim's, when shim is there)
+//! as far as can be decided without running them: the Authenticode digest
+//! is looked up in `dbx`, `db` and MokList, the signature's embedded
+//! digest must match the image, and the signer's chain (as embedded in
+//! the signature) must contain, or be issued by, an enrolled certificate
+//! and none revoked in `dbx`. Certificates are matched by bytes and
+//! names; their signatures are not verified. Shim's built-in vendor
+//! certificate cannot be read from the running system, so an image only
+//! shim could vouch for is reported as unknown.
+
+use crate::error::Coded;
+use anyhow::{Context, Result};
+use lowell_core::digest::Algorithm;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::authenticode::Signed;
+use lowell_core::formats::pe::PeFile;
+use lowell_core::formats::sbat;
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use x509_cert::der::Decode;
+use x509_cert::Certificate;
+
+pub const EFIVARS: &str = "/sys/firmware/efi/efivars";
+/// `EFI_GLOBAL_VARIABLE`: SecureBoot, BootOrder, Boot####, …
+pub const GLOBAL: &str = "8be4df61-93ca-11d2-aa0d-00e098032b8c";
+const IMAGE_SECURITY: &str = "d719b2cb-3d3a-4596-a3bc-dad00e67656f";
+const SHIM: &str = "605dab50-e046-4300-abb6-3dd810dd8b23";
+
+/// `EFI_CERT_X509_GUID` and `EFI_CERT_SHA256_GUID`, as stored.
+const CERT_X509: [u8; 16] = guid(
+    0xa5c059a1,
+    0x94e4,
+    0x4aa7,
+    [0x87, 0xb5, 0xab, 0x15, 0x5c, 0x2b, 0xf0, 0x72],
+);
+const CERT_SHA256: [u8; 16] = guid(
+    0xc1c41626,
+    0x504c,
+    0x4092,
+    [0xac, 0xa9, 0x41, 0xf9, 0x36, 0x93, 0x43, 0x28],
+);
+
+pub const fn guid(a: u32, b: u16, c: u16, d: [u8; 8]) -> [u8; 16] {
+    let (a, b, c) = (a.to_le_bytes(), b.to_le_bytes(), c.to_le_bytes());
+    [
+        a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6],
+        d[7],
+    ]
+}
+
+/// One entry of a signature database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signature {
+    /// DER certificate.
+    X509(Vec<u8>),
+    /// Authenticode SHA-256 of an image.
+    Sha256(Vec<u8>),
+    /// Another `EFI_CERT_*` type, by GUID bytes.
+    Other([u8; 16]),
+}
+
+/// The variables under one efivarfs directory.
+pub struct Efivars {
+    dir: PathBuf,
+}
+
+impl Efivars {
+    /// `dir`, or an error if it does not exist (not booted with UEFI).
+    pub fn open(dir: &Path) -> Result<Self> {
+        if !dir.is_dir() {
+            anyhow::bail!(Coded::new(
+                ErrorCode::NotFound,
+                format!("{} not found: not booted with UEFI?", dir.display())
+            ));
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// A variable's data (after its attributes), `None` if unset.
+    pub fn read(&self, name: &str, guid: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.dir.join(format!("{name}-{guid}"));
+        match std::fs::read(&path) {
+            Ok(data) => Ok(Some(data.get(4..).unwrap_or_default().to_vec())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("read {}", path.display())),
+        }
+    }
+
+    /// Create or replace a variable. efivarfs marks existing variables
+    /// immutable, so that flag is cleared first; the attributes and data
+    /// must then go in one `write`, without truncating (efivarfs sizes the
+    /// file itself; a copy elsewhere is cut to length after).
+    pub fn write(&self, name: &str, guid: &str, attributes: u32, data: &[u8]) -> Result<()> {
+        let path = self.dir.join(format!("{name}-{guid}"));
+        if path.exists() {
+            clear_immutable(&path);
+        }
+        let mut bytes = attributes.to_le_bytes().to_vec();
+        bytes.extend(data);
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("open {}", path.display()))?;
+        io::Write::write_all(&mut file, &bytes)
+            .with_context(|| format!("write {}", path.display()))?;
+        if file.metadata()?.len() > bytes.len() as u64 {
+            file.set_len(bytes.len() as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Names of the variables under `guid`.
+    pub fn names(&self, guid: &str) -> Result<Vec<String>> {
+        let suffix = format!("-{guid}");
+        let mut names = Vec::new();
+        for entry in
+            std::fs::read_dir(&self.dir).with_context(|| format!("read {}", self.dir.display()))?
+        {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(name) = name.strip_suffix(&suffix) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn flag(&self, name: &str, guid: &str) -> Result<Option<bool>> {
+        Ok(self.read(name, guid)?.map(|d| d.first() == Some(&1)))
+    }
+
+    fn signatures(&self, name: &str, guid: &str) -> Result<Vec<Signature>> {
+        Ok(self
+            .read(name, guid)?
+            .map(|d| signature_lists(&d))
+            .unwrap_or_default())
+    }
+}
+
+/// Best effort: outside efivarfs there is nothing to clear, and a write
+/// that still fails says why.
+#[cfg(target_os = "linux")]
+fn clear_immutable(path: &Path) {
+    use std::os::fd::AsRawFd;
+    const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+    let Ok(file) = std::fs::File::open(path) else {
+        return;
+    };
+    let mut flags: libc::c_int = 0;
+    // SAFETY: both ioctls take a pointer to an int, valid for the call.
+    unsafe {
+        if libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) == 0
+            && flags & FS_IMMUTABLE_FL != 0
+        {
+            flags &= !FS_IMMUTABLE_FL;
+            libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clear_immutable(_: &Path) {}
+
+/// Parse concatenated `EFI_SIGNATURE_LIST`s, stopping at the first
+/// malformed one.
+pub fn signature_lists(mut data: &[u8]) -> Vec<Signature> {
+    let u32_at = |d: &[u8], at: usize| {
+        d.get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+    };
+    let mut out = Vec::new();
+    while data.len() >= 28 {
+        let kind: [u8; 16] = data[..16].try_into().unwrap();
+        let (Some(list), Some(header), Some(size)) =
+            (u32_at(data, 16), u32_at(data, 20), u32_at(data, 24))
+        else {
+            break;
+        };
+        if list > data.len() || size <= 16 || 28 + header > list {
+            break;
+        }
+        for entry in data[28 + header..list].chunks_exact(size) {
+            // Each entry starts with its owner's GUID.
+            let body = entry[16..].to_vec();
+            out.push(match kind {
+                CERT_X509 => Signature::X509(body),
+                CERT_SHA256 => Signature::Sha256(body),
+                other => Signature::Other(other),
+            });
+        }
+        data = &data[list..];
+    }
+    out
+}
+
+/// Whether the firmware (and shim) would run an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Acceptance {
+    Accepted,
+    Rejected,
+    Unknown,
+}
+
+/// An enrolled signature, for display.
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    /// `x509`, `sha256` or the GUID of another type.
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// SHA-256 of the certificate, or the image hash itself.
+    pub sha256: String,
+}
+
+impl Entry {
+    fn of(sig: &Signature) -> Self {
+        match sig {
+            Signature::X509(der) => Entry {
+                kind: "x509".into(),
+                subject: Certificate::from_der(der)
+                    .ok()
+                    .map(|c| c.tbs_certificate.subject.to_string()),
+                sha256: Algorithm::Sha256.digest(der).hex,
+            },
+            Signature::Sha256(hash) => Entry {
+                kind: "sha256".into(),
+                subject: None,
+                sha256: hash.iter().map(|b| format!("{b:02x}")).collect(),
+            },
+            Signature::Other(guid) => Entry {
+                kind: guid.iter().map(|b| format!("{b:02x}")).collect(),
+                subject: None,
+                sha256: String::new(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Shim {
+    pub mok: Vec<Entry>,
+    /// `MokSBStateRT`: the user turned shim's checks off.
+    pub validation_disabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sbat_level: Option<String>,
+}
+
+/// The machine's Secure Boot state.
+#[derive(Debug, Serialize)]
+pub struct State {
+    pub secure_boot: Option<bool>,
+    pub setup_mode: Option<bool>,
+    pub pk: Vec<Entry>,
+    pub kek: Vec<Entry>,
+    pub db: Vec<Entry>,
+    pub dbx: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shim: Option<Shim>,
+    #[serde(skip)]
+    raw: Databases,
+}
+
+#[derive(Debug, Default)]
+struct Databases {
+    db: Vec<Signature>,
+    dbx: Vec<Signature>,
+    mok: Vec<Signature>,
+}
+
+impl State {
+    pub fn read(vars: &Efivars) -> Result<Self> {
+        let db = vars.signatures("db", IMAGE_SECURITY)?;
+        let dbx = vars.signatures("dbx", IMAGE_SECURITY)?;
+        let mok = vars.signatures("MokListRT", SHIM)?;
+        let sbat_level = vars.read("SbatLevelRT", SHIM)?.map(|d| {
+            String::from_utf8_lossy(&d)
+                .trim_end_matches('\0')
+                .to_string()
+        });
+        let validation_disabled = vars.flag("MokSBStateRT", SHIM)?.unwrap_or(false);
+        let shim_present =
+            sbat_level.is_some() || !mok.is_empty() || vars.read("MokListRT", SHIM)?.is_some();
+        let entries = |sigs: &[Signature]| sigs.iter().map(Entry::of).collect::<Vec<_>>();
+        Ok(Self {
+            secure_boot: vars.flag("SecureBoot", GLOBAL)?,
+            setup_mode: vars.flag("SetupMode", GLOBAL)?,
+            pk: entries(&vars.signatures("PK", GLOBAL)?),
+            kek: entries(&vars.signatures("KEK", GLOBAL)?),
+            db: entries(&db),
+            dbx: dbx.len(),
+            shim: shim_present.then(|| Shim {
+                mok: entries(&mok),
+                validation_disabled,
+                sbat_level,
+            }),
+            raw: Databases { db, dbx, mok },
+        })
+    }
+
+    /// Whether `uki` would boot here, and why.
+    pub fn check(&self, uki: &PeFile) -> Result<(Acceptance, Vec<String>)> {
+        use Acceptance::*;
+        let mut why = Vec::new();
+        if self.secure_boot != Some(true) {
+            why.push("Secure Boot is disabled: any image boots".into());
+            return Ok((Accepted, why));
+        }
+        if self.setup_mode == Some(true) {
+            why.push("firmware is in setup mode: any image boots".into());
+            return Ok((Accepted, why));
+        }
+        let hash = uki.authenticode(Algorithm::Sha256)?;
+        let hash = hex_bytes(&hash.hex);
+        let raw = &self.raw;
+        if raw.dbx.contains(&Signature::Sha256(hash.clone())) {
+            why.push("its Authenticode hash is revoked in dbx".into());
+            return Ok((Rejected, why));
+        }
+        let signed: Vec<Signed> = uki
+            .certificate_blobs()?
+            .into_iter()
+            .filter_map(Signed::parse)
+            .collect();
+        let mut firmware = raw.db.contains(&Signature::Sha256(hash.clone()));
+        if firmware {
+            why.push("its Authenticode hash is enrolled in db".into());
+        }
+        let mut mok = raw.mok.contains(&Signature::Sha256(hash.clone()));
+        if mok {
+            why.push("its Authenticode hash is enrolled in MokList".into());
+        }
+        for s in &signed {
+            let Some(algorithm) = s.algorithm else {
+                why.push("a signature uses an unsupported digest algorithm".into());
+                continue;
+            };
+            if hex_bytes(&uki.authenticode(algorithm)?.hex) != s.digest {
+                why.push("a signature does not cover this image (modified after signing?)".into());
+                continue;
+            }
+            let chain = s.chain();
+            let Some(signer) = chain.first() else {
+                why.push("a signature carries no signer certificate".into());
+                continue;
+            };
+            let name = signer.tbs_certificate.subject.to_string();
+            if chain.iter().any(|c| anchored(c, &raw.dbx)) {
+                why.push(format!(
+                    "signer {name} chains to a certificate revoked in dbx"
+                ));
+                return Ok((Rejected, why));
+            }
+            if chain.iter().any(|c| anchored(c, &raw.db)) {
+                why.push(format!(
+                    "signed by {name}, which chains to a db certificate"
+                ));
+                firmware = true;
+            } else if chain.iter().any(|c| anchored(c, &raw.mok)) {
+                why.push(format!(
+                    "signed by {name}, which chains to a MokList certificate"
+                ));
+                mok = true;
+            } else {
+                why.push(format!(
+                    "signed by {name}, which no enrolled certificate vouches for"
+                ));
+            }
+        }
+        if signed.is_empty() {
+            why.push("the image is not signed".into());
+        }
+        if firmware {
+            return Ok((Accepted, why));
+        }
+        let Some(shim) = &self.shim else {
+            return Ok((Rejected, why));
+        };
+        if let Some(revoked) = shim
+            .sbat_level
+            .as_deref()
+            .and_then(|l| sbat_revoked(uki, l))
+        {
+            why.push(format!("shim's SBAT level revokes {revoked}"));
+            return Ok((Rejected, why));
+        }
+        if shim.validation_disabled {
+            why.push("shim validation is disabled (MokSBState)".into());
+            return Ok((Accepted, why));
+        }
+        if mok {
+            return Ok((Accepted, why));
+        }
+        if signed.is_empty() {
+            return Ok((Rejected, why));
+        }
+        why.push("shim's built-in vendor certificate may still accept it".into());
+        Ok((Unknown, why))
+    }
+}
+
+/// `cert` is enrolled in `db`, or issued by a certificate that is.
+fn anchored(cert: &Certificate, db: &[Signature]) -> bool {
+    let der = x509_cert::der::Encode::to_der(cert).unwrap_or_default();
+    db.iter().any(|sig| match sig {
+        Signature::X509(enrolled) if *enrolled == der => true,
+        Signature::X509(enrolled) => Certificate::from_der(enrolled)
+            .is_ok_and(|e| e.tbs_certificate.subject == cert.tbs_certificate.issuer),
+        _ => false,
+    })
+}
+
+/// The first `.sbat` component whose generation is below `level`'s.
+fn sbat_revoked(uki: &PeFile, level: &str) -> Option<String> {
+    let entries = sbat::parse(&uki.read_text(".sbat").ok()??).ok()?;
+    let level = sbat::parse(level).ok()?;
+    let (entry, min) = *sbat::revoked(&entries, &level).first()?;
+    Some(format!(
+        "{},{} (needs {min})",
+        entry.component, entry.generation
+    ))
+}
+
+fn hex_bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .filter_map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::UkiBuilder;
+    use p256::ecdsa::{DerSignature, SigningKey};
+    use std::time::Duration;
+    use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+    use x509_cert::der::Encode;
+    use x509_cert::serial_number::SerialNumber;
+    use x509_cert::spki::SubjectPublicKeyInfoOwned;
+    use x509_cert::time::Validity;
+
+    fn cert(subject: &str, key: &SigningKey, issuer: Option<(&str, &SigningKey)>) -> Certificate {
+        let profile = match issuer {
+            None => Profile::Root,
+            Some((name, _)) => Profile::Leaf {
+                issuer: name.parse().unwrap(),
+                enable_key_agreement: false,
+                enable_key_encipherment: false,
+            },
+        };
+        let spki = SubjectPublicKeyInfoOwned::from_key(*key.verifying_key()).unwrap();
+        let signer = issuer.map_or(key, |(_, k)| k);
+        CertificateBuilder::new(
+            profile,
+            SerialNumber::from(7u32),
+            Validity::from_now(Duration::from_secs(3600)).unwrap(),
+            subject.parse().unwrap(),
+            spki,
+            signer,
+        )
+        .unwrap()
+        .build::<DerSignature>()
+        .unwrap()
+    }
+
+    /// Authenticode SignedData over `digest` (SHA-256), by `leaf`.
+    fn signed_data(digest: &[u8], leaf: &Certificate) -> Vec<u8> {
+        let tbs = &leaf.tbs_certificate;
+        lowell_test_util::signed_data(
+            digest,
+            &leaf.to_der().unwrap(),
+            &tbs.issuer.to_der().unwrap(),
+            &tbs.serial_number.to_der().unwrap(),
+        )
+    }
+
+    fn list(kind: [u8; 16], entries: &[&[u8]]) -> Vec<u8> {
+        let size = 16 + entries[0].len();
+        let mut out = kind.to_vec();
+        for v in [28 + size * entries.len(), 0, size] {
+            out.extend((v as u32).to_le_bytes());
+        }
+        for e in entries {
+            out.extend([0xee; 16]);
+            out.extend(*e);
+        }
+        out
+    }
+
+    fn set(dir: &Path, name: &str, guid: &str, data: &[u8]) {
+        let mut bytes = 0x27u32.to_le_bytes().to_vec();
+        bytes.extend(data);
+        std::fs::write(dir.join(format!("{name}-{guid}")), bytes).unwrap();
+    }
+
+    #[test]
+    fn verdicts_follow_db_dbx_and_the_signed_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let vars = dir.path();
+        let (ca_key, leaf_key) = (
+            SigningKey::from_bytes(&[3u8; 32].into()).unwrap(),
+            SigningKey::from_bytes(&[4u8; 32].into()).unwrap(),
+        );
+        let ca = cert("CN=Lowell Test CA,O=lowell", &ca_key, None);
+        let leaf = cert(
+            "CN=Lowell Test Signer",
+            &leaf_key,
+            Some(("CN=Lowell Test CA,O=lowell", &ca_key)),
+        );
+        let unsigned = UkiBuilder::new().cmdline("quiet").linux(&[1; 4096]);
+        let hash = PeFile::from_bytes(unsigned.build())
+            .unwrap()
+            .authenticode(Algorithm::Sha256)
+            .unwrap();
+        let hash = hex_bytes(&hash.hex);
+        let blob = signed_data(&hash, &leaf);
+        let signed = PeFile::from_bytes(unsigned.clone().certificate(&blob).build()).unwrap();
+
+        set(vars, "SecureBoot", GLOBAL, &[1]);
+        set(vars, "SetupMode", GLOBAL, &[0]);
+        set(
+            vars,
+            "db",
+            IMAGE_SECURITY,
+            &list(CERT_X509, &[&ca.to_der().unwrap()]),
+        );
+        let state = || State::read(&Efivars::open(vars).unwrap()).unwrap();
+        let s = state();
+        assert_eq!(
+            s.db[0].subject.as_deref(),
+            Some("CN=Lowell Test CA,O=lowell")
+        );
+        assert!(s.shim.is_none());
+        let (verdict, why) = s.check(&signed).unwrap();
+        assert_eq!(verdict, Acceptance::Accepted, "{why:?}");
+
+        // Edited after signing: the signature no longer covers it.
+        let edited = UkiBuilder::new()
+            .cmdline("quiet debug")
+            .linux(&[1; 4096])
+            .certificate(&blob)
+            .build();
+        let (verdict, why) = s.check(&PeFile::from_bytes(edited).unwrap()).unwrap();
+        assert_eq!(verdict, Acceptance::Rejected);
+        assert!(why[0].contains("does not cover"), "{why:?}");
+        assert_eq!(
+            s.check(&PeFile::from_bytes(unsigned.build()).unwrap())
+                .unwrap()
+                .0,
+            Acceptance::Rejected
+        );
+
+        // Revoked by hash; then shim with the image's hash in MokList.
+        set(vars, "dbx", IMAGE_SECURITY, &list(CERT_SHA256, &[&hash]));
+        assert_eq!(state().check(&signed).unwrap().0, Acceptance::Rejected);
+        std::fs::remove_file(vars.join(format!("dbx-{IMAGE_SECURITY}"))).unwrap();
+        std::fs::remove_file(vars.join(format!("db-{IMAGE_SECURITY}"))).unwrap();
+        assert_eq!(state().check(&signed).unwrap().0, Acceptance::Rejected);
+        set(vars, "SbatLevelRT", SHIM, b"sbat,1,2023012900\n");
+        assert_eq!(state().check(&signed).unwrap().0, Acceptance::Unknown);
+        set(vars, "MokListRT", SHIM, &list(CERT_SHA256, &[&hash]));
+        assert_eq!(state().check(&signed).unwrap().0, Acceptance::Accepted);
+
+        set(vars, "SecureBoot", GLOBAL, &[0]);
+        assert_eq!(
+            state()
+                .check(&PeFile::from_bytes(unsigned.build()).unwrap())
+                .unwrap()
+                .0,
+            Acceptance::Accepted
+        );
+    }
+}
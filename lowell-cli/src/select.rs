@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `--get <path>`: pull single fields out of a report without jq.
+//!
+//! Paths are dot-separated keys into the report's JSON form
+//! (`initrd.digest`, `os_release.name`); numeric segments index arrays.
+//! Strings print raw, everything else as compact JSON, one line per path,
+//! each preceded by `prefix` (used to label values with their input file).
+
+use crate::error::Coded;
+use anyhow::{bail, Result};
+use lowell_core::error::ErrorCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+
+pub fn write_fields<T: Serialize>(
+    out: &mut dyn Write,
+    report: &T,
+    paths: &[String],
+    prefix: &str,
+) -> Result<()> {
+    let value = serde_json::to_value(report)?;
+    for path in paths {
+        match lookup(&value, path) {
+            Some(Value::String(s)) => writeln!(out, "{prefix}{s}")?,
+            Some(Value::Null) => writeln!(out, "{prefix}")?,
+            Some(v) => writeln!(out, "{prefix}{v}")?,
+            None => bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                format!("no field {path:?} in report")
+            )),
+        }
+    }
+    Ok(())
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return None;
+    }
+    let pointer: String = path
+        .split('.')
+        .map(|seg| format!("/{}", seg.replace('~', "~0").replace('/', "~1")))
+        .collect();
+    value.pointer(&pointer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn dotted_paths_select_nested_fields() {
+        let report = json!({
+            "arch": "x86_64",
+            "initrd": { "sha256": "abc", "size": 42 },
+            "sections": [{ "name": ".linux" }],
+            "os_release": null,
+        });
+        let mut out = Vec::new();
+        let paths = [
+            "initrd.sha256",
+            "initrd.size",
+            "sections.0.name",
+            "os_release",
+        ]
+        .map(String::from);
+        write_fields(&mut out, &report, &paths, "").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "abc\n42\n.linux\n\n");
+
+        let err = write_fields(&mut Vec::new(), &report, &["initrd.nope".into()], "");
+        assert!(err.is_err());
+    }
+}
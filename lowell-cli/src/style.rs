@@ -0,0 +1,100 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Terminal colors for human-readable output.
+//!
+//! `--color auto` (the default) colors only when writing to a terminal and
+//! `NO_COLOR` is unset or empty; `always`/`never` force it either way.
+
+use anstyle::{AnsiColor, Style};
+use clap::ValueEnum;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to emit ANSI escapes on a stream that is (or isn't) a TTY.
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                is_terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        }
+    }
+}
+
+/// Semantic styles; every method is a no-op when color is disabled.
+#[derive(Copy, Clone, Debug)]
+pub struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// Healthy / expected state (e.g. signed).
+    pub fn good<T: fmt::Display>(self, text: T) -> Painted<T> {
+        self.paint(AnsiColor::Green.on_default(), text)
+    }
+
+    /// Problem state (e.g. unsigned).
+    pub fn bad<T: fmt::Display>(self, text: T) -> Painted<T> {
+        self.paint(AnsiColor::Red.on_default(), text)
+    }
+
+    /// Something we could not classify.
+    pub fn warn<T: fmt::Display>(self, text: T) -> Painted<T> {
+        self.paint(AnsiColor::Yellow.on_default(), text)
+    }
+
+    pub fn bold<T: fmt::Display>(self, text: T) -> Painted<T> {
+        self.paint(Style::new().bold(), text)
+    }
+
+    fn paint<T: fmt::Display>(self, style: Style, text: T) -> Painted<T> {
+        let style = if self.enabled { style } else { Style::new() };
+        Painted { style, text }
+    }
+}
+
+pub struct Painted<T> {
+    style: Style,
+    text: T,
+}
+
+impl<T: fmt::Display> fmt::Display for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            self.style.render(),
+            self.text,
+            self.style.render_reset()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_palette_is_plain_text() {
+        assert_eq!(Palette::new(false).bad("unsigned").to_string(), "unsigned");
+        assert_eq!(
+            Palette::new(true).good("signed").to_string(),
+            "\x1b[32msigned\x1b[0m"
+        );
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Auto.enabled(false));
+    }
+}
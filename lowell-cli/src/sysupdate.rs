@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! systemd-sysupdate transfer definitions for versioned UKIs (`lowell
+//! build --sysupdate DIR`).
+//!
+//! A UKI written as `NAME_VERSION.efi` (any prefix and suffix around the
+//! version) gets a `<component>.transfer` that matches every version of
+//! it: the source pattern is the file name with the version replaced by
+//! `@v`, and the target is `EFI/Linux` on the boot partition with
+//! systemd-boot's boot counting (`+@l-@d`) allowed in the name:
+//!
+//! ```ini
+//! [Transfer]
+//! ProtectVersion=%A
+//!
+//! [Source]
+//! Type=url-file
+//! Path=https://example.com/os/
+//! MatchPattern=fedora_@v.efi
+//!
+//! [Target]
+//! Type=regular-file
+//! Path=/EFI/Linux
+//! PathRelativeTo=boot
+//! MatchPattern=fedora_@v+@l-@d.efi fedora_@v+@l.efi fedora_@v.efi
+//! Mode=0444
+//! TriesLeft=3
+//! TriesDone=0
+//! InstancesMax=2
+//! ```
+//!
+//! Without a URL the source is the output directory itself
+//! (`Type=regular-file`). sysupdate only downloads files listed in the
+//! source's `SHA256SUMS`, so with a URL that manifest is kept up to date
+//! next to the UKI (signing it, as `SHA256SUMS.gpg`, is left to the
+//! publisher).
+
+use crate::error::Coded;
+use crate::output;
+use anyhow::{bail, Context, Result};
+use lowell_core::digest::Algorithm;
+use lowell_core::error::ErrorCode;
+use std::path::{Path, PathBuf};
+
+/// Boot attempts a new UKI gets before systemd-boot falls back.
+const TRIES: u32 = 3;
+/// Versions kept on the boot partition.
+const INSTANCES: u32 = 2;
+
+/// The transfer for one versioned UKI file name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// Before and after the version in the file name.
+    prefix: String,
+    suffix: String,
+    /// Where sysupdate finds new versions: a URL, or a local directory.
+    source: String,
+}
+
+impl Transfer {
+    /// The transfer for `uki` at `version`, published at `url` (else
+    /// picked up from `uki`'s directory).
+    pub fn new(uki: &Path, version: &str, url: Option<&str>) -> Result<Self> {
+        let name = uki
+            .file_name()
+            .and_then(|n| n.to_str())
+            .with_context(|| format!("{}: not a file name", uki.display()))?;
+        let Some(at) = name.rfind(version).filter(|_| !version.is_empty()) else {
+            bail!(Coded::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "{name} does not contain its version {version:?} (name it NAME_{version}.efi)"
+                )
+            ));
+        };
+        let source = match url {
+            Some(url) => url.to_string(),
+            None => std::path::absolute(uki.parent().unwrap_or(Path::new(".")))?
+                .display()
+                .to_string(),
+        };
+        Ok(Self {
+            prefix: name[..at].to_string(),
+            suffix: name[at + version.len()..].to_string(),
+            source,
+        })
+    }
+
+    /// The `.transfer` file name: the prefix without trailing separators.
+    pub fn file_name(&self) -> String {
+        match self.prefix.trim_end_matches(['_', '-', '.']) {
+            "" => "uki.transfer".to_string(),
+            component => format!("{component}.transfer"),
+        }
+    }
+
+    fn is_url(&self) -> bool {
+        self.source.contains("://")
+    }
+
+    pub fn render(&self) -> String {
+        let (prefix, suffix) = (&self.prefix, &self.suffix);
+        let kind = if self.is_url() {
+            "url-file"
+        } else {
+            "regular-file"
+        };
+        format!(
+            "[Transfer]\nProtectVersion=%A\n\n\
+             [Source]\nType={kind}\nPath={}\nMatchPattern={prefix}@v{suffix}\n\n\
+             [Target]\nType=regular-file\nPath=/EFI/Linux\nPathRelativeTo=boot\n\
+             MatchPattern={prefix}@v+@l-@d{suffix} {prefix}@v+@l{suffix} {prefix}@v{suffix}\n\
+             Mode=0444\nTriesLeft={TRIES}\nTriesDone=0\nInstancesMax={INSTANCES}\n",
+            self.source
+        )
+    }
+
+    /// Write the transfer into `dir` and, for a URL source, list `uki` in
+    /// the `SHA256SUMS` beside it. Returns the files written.
+    pub fn write(&self, dir: &Path, uki: &Path) -> Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+        let transfer = dir.join(self.file_name());
+        output::write_atomic(&transfer, self.render().as_bytes())?;
+        let mut written = vec![transfer];
+        if self.is_url() {
+            written.push(record_sum(uki)?);
+        }
+        Ok(written)
+    }
+}
+
+/// Add or replace `file`'s line in the `SHA256SUMS` of its directory.
+fn record_sum(file: &Path) -> Result<PathBuf> {
+    let name = file
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let data = std::fs::read(file).with_context(|| format!("read {}", file.display()))?;
+    let line = format!("{}  {name}", Algorithm::Sha256.digest(&data).hex);
+    let sums = file.with_file_name("SHA256SUMS");
+    let old = match std::fs::read_to_string(&sums) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("read {}", sums.display())),
+    };
+    let mut lines: Vec<String> = old
+        .lines()
+        .filter(|l| l.split_once("  ").map(|(_, n)| n.trim_start_matches('*')) != Some(name))
+        .map(str::to_string)
+        .collect();
+    lines.push(line);
+    lines.sort_by(|a, b| {
+        a.split_once("  ")
+            .map(|x| x.1)
+            .cmp(&b.split_once("  ").map(|x| x.1))
+    });
+    output::write_atomic(&sums, format!("{}\n", lines.join("\n")).as_bytes())?;
+    Ok(sums)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfers_match_every_version_of_the_uki() {
+        let dir = tempfile::tempdir().unwrap();
+        let uki = dir.path().join("fedora_41.20261016.efi");
+        std::fs::write(&uki, b"uki").unwrap();
+        let url = "https://example.com/os/";
+        let transfer = Transfer::new(&uki, "41.20261016", Some(url)).unwrap();
+        assert_eq!(transfer.file_name(), "fedora.transfer");
+        let text = transfer.render();
+        assert!(
+            text.contains("Type=url-file\nPath=https://example.com/os/\n"),
+            "{text}"
+        );
+        assert!(text.contains("\nMatchPattern=fedora_@v.efi\n"), "{text}");
+        assert!(text.contains("MatchPattern=fedora_@v+@l-@d.efi fedora_@v+@l.efi fedora_@v.efi\n"));
+
+        let out = dir.path().join("sysupdate.d");
+        std::fs::write(
+            dir.path().join("SHA256SUMS"),
+            "00  fedora_40.efi\n11  fedora_41.20261016.efi\n",
+        )
+        .unwrap();
+        let written = transfer.write(&out, &uki).unwrap();
+        assert_eq!(std::fs::read_to_string(&written[0]).unwrap(), text);
+        let sums = std::fs::read_to_string(&written[1]).unwrap();
+        let hash = Algorithm::Sha256.digest(b"uki").hex;
+        assert_eq!(
+            sums,
+            format!("00  fedora_40.efi\n{hash}  fedora_41.20261016.efi\n")
+        );
+
+        let local = Transfer::new(&uki, "41.20261016", None).unwrap();
+        assert!(local.render().contains("Type=regular-file\nPath=/"));
+        assert!(Transfer::new(&uki, "42", None).is_err());
+    }
+}
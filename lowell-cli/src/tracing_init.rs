@@ -1,23 +1,49 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 use anyhow::Result;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use std::io::IsTerminal;
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
 
-use crate::cli::GlobalArgs;
+use crate::cli::{GlobalArgs, LogFormat};
 
 pub fn init(g: &GlobalArgs) -> Result<()> {
-    // If RUST_LOG is set, honor it entirely (user can set goblin=trace themselves).
-    let filter = if std::env::var_os("RUST_LOG").is_some() {
-        EnvFilter::from_default_env()
+    // If RUST_LOG is set, honor it (user can set goblin=trace themselves);
+    // `--log target=level` directives are layered on top either way.
+    let filter = if g.porcelain {
+        // Porcelain output must be the only thing a script sees.
+        EnvFilter::new("off")
     } else {
-        // Default to the CLI level, but quiet down goblin’s debug churn.
-        EnvFilter::new(g.log_level.as_str()).add_directive("goblin=warn".parse().unwrap())
-        // add more noisy deps here if needed:
-        // .add_directive("goblin::pe=warn".parse().unwrap())
-        // .add_directive("object=warn".parse().unwrap())
+        let base = if std::env::var_os("RUST_LOG").is_some() {
+            EnvFilter::from_default_env()
+        } else {
+            // Default to the CLI level, but quiet down goblin’s debug churn.
+            EnvFilter::new(g.log_level.as_str()).add_directive("goblin=warn".parse().unwrap())
+            // add more noisy deps here if needed:
+            // .add_directive("goblin::pe=warn".parse().unwrap())
+            // .add_directive("object=warn".parse().unwrap())
+        };
+        g.log.iter().cloned().fold(base, EnvFilter::add_directive)
+    };
+
+    let layer: Box<dyn Layer<Registry> + Send + Sync> = match g.log_format {
+        LogFormat::Text => fmt::layer()
+            .without_time()
+            .with_writer(std::io::stderr)
+            .with_ansi(g.color.enabled(std::io::stderr().is_terminal()))
+            .boxed(),
+        // One object per line with timestamp, level, fields and the span
+        // stack (e.g. inspect{path} → elapsed_ms, sizes) for log pipelines.
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(std::io::stderr)
+            .boxed(),
     };
 
     tracing_subscriber::registry()
-        .with(fmt::layer().without_time())
+        .with(layer)
         .with(filter)
         .init();
 
@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! The server side of the varlink protocol, as much as `lowell serve`
+//! needs.
+//!
+//! Varlink messages are JSON objects, each followed by a NUL byte, on a
+//! UNIX stream socket. A call names a fully qualified `method` and its
+//! `parameters`; the reply carries `parameters`, or an `error` (a fully
+//! qualified error name) with its own parameters. A call with `"more":
+//! true` may get several replies, all but the last with `"continues":
+//! true`; a `"oneway"` call gets none. Every service implements
+//! `org.varlink.service` ([`GetInfo`](service_info) and
+//! `GetInterfaceDescription`), which is how `varlinkctl` discovers it.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use tracing::info;
+
+/// The interface every varlink service implements.
+pub const SERVICE: &str = "org.varlink.service";
+
+pub const SERVICE_DESCRIPTION: &str = "\
+# The Varlink Service Interface is provided by every varlink service. It
+# describes the service and the interfaces it implements.
+interface org.varlink.service
+
+# Get a list of all the interfaces a service provides and information
+# about the implementation.
+method GetInfo() -> (
+  vendor: string,
+  product: string,
+  version: string,
+  url: string,
+  interfaces: []string
+)
+
+# Get the description of an interface that is implemented by this service.
+method GetInterfaceDescription(interface: string) -> (description: string)
+
+# The requested interface was not found.
+error InterfaceNotFound (interface: string)
+
+# The requested method was not found
+error MethodNotFound (method: string)
+
+# The interface defines the requested method, but the service does not
+# implement it.
+error MethodNotImplemented (method: string)
+
+# One of the passed parameters is invalid.
+error InvalidParameter (parameter: string)
+
+# Client is denied access
+error PermissionDenied ()
+
+# Method is expected to be called with 'more' set to true, but wasn't
+error ExpectedMore ()
+";
+
+/// One method call.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Call {
+    pub method: String,
+    #[serde(default)]
+    pub parameters: Map<String, Value>,
+    /// The caller accepts several replies.
+    #[serde(default)]
+    pub more: bool,
+    /// The caller wants no reply.
+    #[serde(default)]
+    pub oneway: bool,
+}
+
+impl Call {
+    /// The method's interface and its name within it.
+    pub fn split(&self) -> (&str, &str) {
+        self.method.rsplit_once('.').unwrap_or(("", &self.method))
+    }
+}
+
+/// A client's connection.
+pub struct Connection {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Connection {
+    pub fn new(stream: UnixStream) -> Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    /// The next call, `None` once the client hangs up.
+    pub fn read_call(&mut self) -> Result<Option<Call>> {
+        let mut buf = Vec::new();
+        if self.reader.read_until(0, &mut buf)? == 0 {
+            return Ok(None);
+        }
+        if buf.pop() != Some(0) {
+            return Ok(None);
+        }
+        serde_json::from_slice(&buf)
+            .map(Some)
+            .context("malformed varlink call")
+    }
+
+    /// Another handle on the socket, for replies sent while a call runs.
+    pub fn writer(&self) -> Result<UnixStream> {
+        Ok(self.writer.try_clone()?)
+    }
+
+    pub fn reply(&mut self, parameters: Value) -> Result<()> {
+        send(&mut self.writer, &json!({ "parameters": parameters }))
+    }
+
+    pub fn error(&mut self, error: &str, parameters: Value) -> Result<()> {
+        send(
+            &mut self.writer,
+            &json!({ "error": error, "parameters": parameters }),
+        )
+    }
+}
+
+/// A reply that more will follow (to a call with `more`).
+pub fn send_continues(stream: &mut UnixStream, parameters: Value) -> Result<()> {
+    send(
+        stream,
+        &json!({ "parameters": parameters, "continues": true }),
+    )
+}
+
+fn send(stream: &mut UnixStream, message: &Value) -> Result<()> {
+    let mut bytes = serde_json::to_vec(message)?;
+    bytes.push(0);
+    stream.write_all(&bytes)?;
+    Ok(())
+}
+
+/// `org.varlink.service.GetInfo`'s reply.
+pub fn service_info(interfaces: &[&str]) -> Value {
+    let mut all = vec![SERVICE];
+    all.extend(interfaces);
+    json!({
+        "vendor": "lowell",
+        "product": "lowell",
+        "version": env!("CARGO_PKG_VERSION"),
+        "url": env!("CARGO_PKG_REPOSITORY"),
+        "interfaces": all,
+    })
+}
+
+/// The socket systemd passed (socket activation), else a new one bound at
+/// `path`, replacing a stale socket file.
+pub fn listen(path: &Path) -> Result<UnixListener> {
+    if let Some(listener) = activated() {
+        info!("using the socket passed by the service manager");
+        return Ok(listener);
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("remove {}", path.display()))
+        }
+        _ => {}
+    }
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("listen on {}", path.display()))?;
+    info!(socket = %path.display(), "listening");
+    Ok(listener)
+}
+
+/// The first socket in `LISTEN_FDS`, if it is meant for this process.
+fn activated() -> Option<UnixListener> {
+    use std::os::fd::FromRawFd;
+    const SD_LISTEN_FDS_START: i32 = 3;
+    let pid: u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+    let fds: u32 = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    if pid != std::process::id() || fds == 0 {
+        return None;
+    }
+    // SAFETY: the service manager passes the listening socket as fd 3 and
+    // nothing else in this process owns it.
+    Some(unsafe { UnixListener::from_raw_fd(SD_LISTEN_FDS_START) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn messages_are_nul_terminated_json() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let mut conn = Connection::new(server).unwrap();
+        let mut c = client.try_clone().unwrap();
+        c.write_all(b"{\"method\":\"org.varlink.service.GetInfo\"}\0{\"method\":\"a.B\",\"more\":true,\"parameters\":{\"x\":1}}\0")
+            .unwrap();
+        let call = conn.read_call().unwrap().unwrap();
+        assert_eq!(call.split(), (SERVICE, "GetInfo"));
+        let call = conn.read_call().unwrap().unwrap();
+        assert!(call.more && !call.oneway);
+        assert_eq!(call.parameters["x"], 1);
+
+        send_continues(&mut conn.writer().unwrap(), json!({"n": 1})).unwrap();
+        conn.error("a.Failed", json!({})).unwrap();
+        drop(c);
+        drop(conn);
+        let mut out = String::new();
+        (&client).read_to_string(&mut out).unwrap();
+        assert_eq!(
+            out,
+            "{\"continues\":true,\"parameters\":{\"n\":1}}\0{\"error\":\"a.Failed\",\"parameters\":{}}\0"
+        );
+    }
+}
@@ -1,4 +0,0 @@
-// SPDX-License-Identifier: MIT OR Apache-2.0
-pub mod initramfs;
-pub mod osrel;
-pub mod pe;
@@ -1,3 +1,34 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
-pub mod formats;
-pub mod uki;
+//! The whole lowell library under one name.
+//!
+//! Re-exports `lowell-formats` (parsers, errors, limits, progress),
+//! `lowell-inspect` (reports) and `lowell-build` (UKI assembly, profiles)
+//! at their historical paths. Depend on those crates directly to leave out
+//! what you don't use.
+
+#[cfg(feature = "profile")]
+pub use lowell_build::profile;
+pub use lowell_formats::{cancel, digest, error, limits, mapped, progress};
+
+pub mod formats {
+    pub use lowell_formats::{
+        authenticode, bmp, cpio, fdt, initramfs, kernel, microcode, osrel, pe, sbat, sniff, tpm,
+        unpack,
+    };
+}
+
+pub mod uki {
+    pub use lowell_build::uki as build;
+    pub use lowell_inspect::handler;
+    pub use lowell_inspect::uki as inspect;
+}
+
+pub mod initrd {
+    pub use lowell_inspect::initrd as inspect;
+}
+
+pub mod kernel {
+    pub use lowell_inspect::kernel as inspect;
+}
+
+pub use error::{Error, Result};
@@ -1,3 +0,0 @@
-// SPDX-License-Identifier: MIT OR Apache-2.0
-pub mod ext;
-pub mod inspect;
@@ -0,0 +1,65 @@
+/* SPDX-License-Identifier: MIT OR Apache-2.0 */
+
+#ifndef LOWELL_H
+#define LOWELL_H
+
+/* Generated by cbindgen from lowell-ffi/src/lib.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of every `lowell_*` call.
+typedef enum LowellStatus {
+  LOWELL_STATUS_OK = 0,
+  // The operation failed; the out-parameter holds a JSON error.
+  LOWELL_STATUS_ERROR = 1,
+  // A pointer was NULL or a string was not UTF-8.
+  LOWELL_STATUS_INVALID_ARGUMENT = 2,
+  // lowell panicked (a bug); the out-parameter holds a JSON error.
+  LOWELL_STATUS_PANIC = 3,
+} LowellStatus;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Version of this library, e.g. `"0.0.1"`. Static; do not free.
+const char *lowell_version(void);
+
+// Inspect a UKI, bare initramfs or kernel image (detected from its
+// leading bytes) and return the report as JSON in `*out_json`.
+//
+// The JSON is what `lowell inspect --format json <path>` prints.
+//
+// # Safety
+//
+// `path` must be NULL or a NUL-terminated string; `out_json` must be NULL
+// or point to writable storage for a pointer.
+LowellStatus lowell_inspect_json(const char *path, char **out_json);
+
+// Build a UKI from a profile TOML file and write it to `out_path`.
+//
+// Relative `[artifacts]` paths resolve against the profile's directory,
+// as with `lowell build`. On failure `*out_error` receives a JSON error;
+// on success it is set to NULL.
+//
+// # Safety
+//
+// `profile_path` and `out_path` must be NULL or NUL-terminated strings;
+// `out_error` must be NULL or point to writable storage for a pointer.
+LowellStatus lowell_build(const char *profile_path, const char *out_path, char **out_error);
+
+// Release a string returned by this library. NULL is ignored.
+//
+// # Safety
+//
+// `s` must come from a `lowell_*` out-parameter and not be freed twice.
+void lowell_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LOWELL_H */
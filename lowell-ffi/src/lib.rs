@@ -0,0 +1,291 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! C ABI for `lowell-core`.
+//!
+//! The header is `include/lowell.h` (generated by cbindgen). Every entry
+//! point returns a [`LowellStatus`] and hands back at most one string
+//! through an out-parameter:
+//!
+//! - on success, the result (a JSON report for `lowell_inspect_json`,
+//!   nothing for `lowell_build`);
+//! - on failure, a JSON error in the CLI's `--format json` shape,
+//!   `{"error": {"code": "E_NOT_PE", "message": …, "causes": […]}}`.
+//!
+//! Strings returned by this library must be released with
+//! [`lowell_string_free`]. Panics never cross the boundary; they are
+//! reported as [`LowellStatus::Panic`].
+
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::sniff::{sniff_path, FileKind};
+use lowell_core::profile::Profile;
+use lowell_core::uki::build::BuildInputs;
+use lowell_core::uki::inspect::InspectOptions;
+use lowell_core::{initrd, kernel, uki};
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+/// Result of every `lowell_*` call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LowellStatus {
+    Ok = 0,
+    /// The operation failed; the out-parameter holds a JSON error.
+    Error = 1,
+    /// A pointer was NULL or a string was not UTF-8.
+    InvalidArgument = 2,
+    /// lowell panicked (a bug); the out-parameter holds a JSON error.
+    Panic = 3,
+}
+
+/// A failure on its way to the caller as JSON.
+struct Failure {
+    status: LowellStatus,
+    code: ErrorCode,
+    message: String,
+    causes: Vec<String>,
+}
+
+impl Failure {
+    fn new(status: LowellStatus, code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            causes: Vec::new(),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::json!({
+            "error": {
+                "code": self.code,
+                "message": self.message,
+                "causes": self.causes,
+            }
+        })
+        .to_string()
+    }
+}
+
+impl From<lowell_core::Error> for Failure {
+    fn from(err: lowell_core::Error) -> Self {
+        let mut causes = Vec::new();
+        let mut source = std::error::Error::source(&err);
+        while let Some(e) = source {
+            causes.push(e.to_string());
+            source = e.source();
+        }
+        Self {
+            status: LowellStatus::Error,
+            code: err.code(),
+            message: err.to_string(),
+            causes,
+        }
+    }
+}
+
+/// Version of this library, e.g. `"0.0.1"`. Static; do not free.
+#[no_mangle]
+pub extern "C" fn lowell_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Inspect a UKI, bare initramfs or kernel image (detected from its
+/// leading bytes) and return the report as JSON in `*out_json`.
+///
+/// The JSON is what `lowell inspect --format json <path>` prints.
+///
+/// # Safety
+///
+/// `path` must be NULL or a NUL-terminated string; `out_json` must be NULL
+/// or point to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn lowell_inspect_json(
+    path: *const c_char,
+    out_json: *mut *mut c_char,
+) -> LowellStatus {
+    call(out_json, || {
+        let path = path_arg(path, "path")?;
+        inspect_json(&path).map(Some)
+    })
+}
+
+/// Build a UKI from a profile TOML file and write it to `out_path`.
+///
+/// Relative `[artifacts]` paths resolve against the profile's directory,
+/// as with `lowell build`. On failure `*out_error` receives a JSON error;
+/// on success it is set to NULL.
+///
+/// # Safety
+///
+/// `profile_path` and `out_path` must be NULL or NUL-terminated strings;
+/// `out_error` must be NULL or point to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn lowell_build(
+    profile_path: *const c_char,
+    out_path: *const c_char,
+    out_error: *mut *mut c_char,
+) -> LowellStatus {
+    call(out_error, || {
+        let profile_path = path_arg(profile_path, "profile_path")?;
+        let out_path = path_arg(out_path, "out_path")?;
+        build(&profile_path, &out_path)?;
+        Ok(None)
+    })
+}
+
+/// Release a string returned by this library. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must come from a `lowell_*` out-parameter and not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn lowell_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+fn inspect_json(path: &Path) -> Result<String, Failure> {
+    let json = match sniff_path(path)? {
+        Some(FileKind::Uki) => {
+            serde_json::to_string(&uki::inspect::inspect(InspectOptions::new(path))?)
+        }
+        Some(FileKind::Initramfs) => serde_json::to_string(&initrd::inspect::inspect(path)?),
+        Some(FileKind::Kernel) => serde_json::to_string(&kernel::inspect::inspect(path)?),
+        None => {
+            return Err(Failure::new(
+                LowellStatus::Error,
+                ErrorCode::UnknownFormat,
+                format!("{}: not a UKI, initramfs or kernel image", path.display()),
+            ))
+        }
+    };
+    // Reports are plain data; serializing them cannot fail.
+    Ok(json.expect("serialize report"))
+}
+
+fn build(profile_path: &Path, out_path: &Path) -> Result<(), Failure> {
+    let profile = Profile::from_path(profile_path)?;
+    let base_dir = profile_path.parent().unwrap_or(Path::new("."));
+    let image = BuildInputs::from_profile(&profile, base_dir)?.build()?;
+    std::fs::write(out_path, image).map_err(|source| lowell_core::Error::File {
+        op: "write",
+        path: out_path.to_path_buf(),
+        source,
+    })?;
+    Ok(())
+}
+
+/// # Safety
+///
+/// `p` must be NULL or a NUL-terminated string.
+unsafe fn path_arg(p: *const c_char, name: &str) -> Result<PathBuf, Failure> {
+    let invalid = |why: &str| {
+        Failure::new(
+            LowellStatus::InvalidArgument,
+            ErrorCode::InvalidArgument,
+            format!("{name} {why}"),
+        )
+    };
+    if p.is_null() {
+        return Err(invalid("is NULL"));
+    }
+    let s = CStr::from_ptr(p)
+        .to_str()
+        .map_err(|_| invalid("is not valid UTF-8"))?;
+    Ok(PathBuf::from(s))
+}
+
+/// Run `f`, turning its result (or a panic) into a status plus an optional
+/// string stored in `*out`.
+///
+/// # Safety
+///
+/// `out` must be NULL or point to writable storage for a pointer.
+unsafe fn call(
+    out: *mut *mut c_char,
+    f: impl FnOnce() -> Result<Option<String>, Failure>,
+) -> LowellStatus {
+    let (status, text) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(text)) => (LowellStatus::Ok, text),
+        Ok(Err(failure)) => (failure.status, Some(failure.to_json())),
+        Err(_) => {
+            let failure = Failure::new(
+                LowellStatus::Panic,
+                ErrorCode::Unknown,
+                "internal error (panic) in lowell",
+            );
+            (failure.status, Some(failure.to_json()))
+        }
+    };
+    if !out.is_null() {
+        // JSON never contains a raw NUL, so this cannot fail in practice.
+        *out = text
+            .and_then(|t| CString::new(t).ok())
+            .map_or(ptr::null_mut(), CString::into_raw);
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Call an entry point and take ownership of the returned string.
+    fn take(f: impl FnOnce(*mut *mut c_char) -> LowellStatus) -> (LowellStatus, Option<String>) {
+        let mut out = ptr::null_mut();
+        let status = f(&mut out);
+        let text = (!out.is_null()).then(|| unsafe {
+            let s = CStr::from_ptr(out).to_str().unwrap().to_owned();
+            lowell_string_free(out);
+            s
+        });
+        (status, text)
+    }
+
+    #[test]
+    fn inspect_reports_json_and_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("initrd.img");
+        let mut cpio = lowell_test_util::newc(&[]);
+        cpio.resize(512, 0);
+        std::fs::write(&path, &cpio).unwrap();
+
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let (status, json) = take(|out| unsafe { lowell_inspect_json(c_path.as_ptr(), out) });
+        assert_eq!(status, LowellStatus::Ok);
+        let report: serde_json::Value = serde_json::from_str(&json.unwrap()).unwrap();
+        assert_eq!(report["compression"], "uncompressed");
+        assert_eq!(report["size"], 512);
+
+        let missing = CString::new("/nonexistent/lowell.efi").unwrap();
+        let (status, json) = take(|out| unsafe { lowell_inspect_json(missing.as_ptr(), out) });
+        assert_eq!(status, LowellStatus::Error);
+        let err: serde_json::Value = serde_json::from_str(&json.unwrap()).unwrap();
+        assert_eq!(err["error"]["code"], "E_NOT_FOUND");
+
+        let (status, json) = take(|out| unsafe { lowell_inspect_json(ptr::null(), out) });
+        assert_eq!(status, LowellStatus::InvalidArgument);
+        assert!(json.unwrap().contains("E_INVALID_ARGUMENT"));
+    }
+
+    #[test]
+    fn build_reports_missing_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let profile = dir.path().join("p.toml");
+        std::fs::write(&profile, "name = \"p\"\nroot = \"plain\"\n").unwrap();
+        let profile = CString::new(profile.to_str().unwrap()).unwrap();
+        let out = CString::new(dir.path().join("uki.efi").to_str().unwrap()).unwrap();
+
+        let (status, json) =
+            take(|err| unsafe { lowell_build(profile.as_ptr(), out.as_ptr(), err) });
+        assert_eq!(status, LowellStatus::Error);
+        assert!(json.unwrap().contains("E_PROFILE_INVALID"));
+        assert!(unsafe { CStr::from_ptr(lowell_version()) }
+            .to_str()
+            .unwrap()
+            .starts_with("0."));
+    }
+}
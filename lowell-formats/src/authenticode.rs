@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Authenticode signatures: the PKCS#7 SignedData blobs in a PE image's
+//! certificate table ([`PeFile::certificate_blobs`](crate::pe::PeFile)).
+//!
+//! [`Signed`] reads what a signature claims — the image digest, the
+//! embedded certificates and which of them is the signer's — with the
+//! `certificates` feature; [`Signer`] is that summed up for a report.
+//! Nothing here verifies a signature.
+
+#[cfg(feature = "certificates")]
+use crate::digest::Algorithm;
+#[cfg(feature = "certificates")]
+use x509_cert::der::{asn1::ObjectIdentifier, Decode, Encode};
+#[cfg(feature = "certificates")]
+use x509_cert::{name::Name, Certificate};
+
+/// Who made one signature, and when their certificate is valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct Signer {
+    /// The signer certificate's subject (RFC 4514); `None` if the
+    /// signature does not embed that certificate.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub subject: Option<String>,
+    /// The issuer the signature names (RFC 4514).
+    pub issuer: String,
+    /// The signer certificate's serial number, hex.
+    pub serial: String,
+    /// The validity window, `YYYY-MM-DDTHH:MM:SSZ`; `None` without the
+    /// signer certificate.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub not_before: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub not_after: Option<String>,
+    /// The algorithm of the signed image digest (`sha256`), or its OID
+    /// if lowell has no name for it.
+    pub digest_algorithm: String,
+}
+
+/// What an Authenticode signature (PKCS#7 SignedData) says.
+#[cfg(feature = "certificates")]
+#[derive(Debug)]
+pub struct Signed {
+    pub algorithm: Option<Algorithm>,
+    /// The image digest that was signed.
+    pub digest: Vec<u8>,
+    /// Embedded certificates, DER.
+    pub certificates: Vec<Vec<u8>>,
+    /// The digest algorithm's OID, DER.
+    algorithm_oid: Vec<u8>,
+    /// `issuerAndSerialNumber` of the first signer, DER.
+    signer: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+#[cfg(feature = "certificates")]
+impl Signed {
+    pub fn parse(blob: &[u8]) -> Option<Self> {
+        // ContentInfo { contentType, [0] SignedData }
+        let content_info = children(tlv(blob)?.0.body)?;
+        let signed_data = children(tlv(content_info.get(1)?.body)?.0.body)?;
+        // SignedData { version, digestAlgorithms, contentInfo, [0] certs, [1] crls, signerInfos }
+        let spc = children(signed_data.get(2)?.body)?;
+        let indirect = children(tlv(spc.get(1)?.body)?.0.body)?;
+        let digest_info = children(indirect.get(1)?.body)?;
+        let algorithm = children(digest_info.first()?.body)?.first()?.raw;
+        let digest = digest_info.get(1)?.body.to_vec();
+        let certificates = signed_data
+            .iter()
+            .find(|t| t.tag == 0xa0)
+            .and_then(|t| children(t.body))
+            .map(|certs| certs.iter().map(|c| c.raw.to_vec()).collect())
+            .unwrap_or_default();
+        let signer = signed_data
+            .last()
+            .and_then(|infos| children(infos.body))
+            .and_then(|infos| children(infos.first()?.body))
+            .and_then(|info| children(info.get(1)?.body))
+            .and_then(|id| Some((id.first()?.raw.to_vec(), id.get(1)?.raw.to_vec())));
+        Some(Self {
+            algorithm: digest_algorithm(algorithm),
+            digest,
+            certificates,
+            algorithm_oid: algorithm.to_vec(),
+            signer,
+        })
+    }
+
+    /// The signer's certificate, then each issuer found among the
+    /// embedded certificates.
+    pub fn chain(&self) -> Vec<Certificate> {
+        let certs: Vec<Certificate> = self
+            .certificates
+            .iter()
+            .filter_map(|c| Certificate::from_der(c).ok())
+            .collect();
+        let signer = self.signer.as_ref().and_then(|(issuer, serial)| {
+            certs.iter().find(|c| {
+                let tbs = &c.tbs_certificate;
+                tbs.issuer.to_der().ok().as_ref() == Some(issuer)
+                    && tbs.serial_number.to_der().ok().as_ref() == Some(serial)
+            })
+        });
+        let mut chain: Vec<Certificate> = signer.into_iter().cloned().collect();
+        while let Some(last) = chain.last() {
+            let issuer = &last.tbs_certificate.issuer;
+            if *issuer == last.tbs_certificate.subject || chain.len() > 8 {
+                break;
+            }
+            match certs.iter().find(|c| c.tbs_certificate.subject == *issuer) {
+                Some(next) => chain.push(next.clone()),
+                None => break,
+            }
+        }
+        chain
+    }
+
+    /// The signer, if the signature names one.
+    pub fn signer(&self) -> Option<Signer> {
+        let (issuer, serial) = self.signer.as_ref()?;
+        let chain = self.chain();
+        let cert = chain.first().map(|c| &c.tbs_certificate);
+        Some(Signer {
+            subject: cert.map(|c| c.subject.to_string()),
+            issuer: Name::from_der(issuer).ok()?.to_string(),
+            serial: tlv(serial)?
+                .0
+                .body
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+            not_before: cert.map(|c| c.validity.not_before.to_string()),
+            not_after: cert.map(|c| c.validity.not_after.to_string()),
+            digest_algorithm: match self.algorithm {
+                Some(algorithm) => algorithm.as_str().to_string(),
+                None => ObjectIdentifier::from_der(&self.algorithm_oid)
+                    .map_or_else(|_| "unknown".to_string(), |oid| oid.to_string()),
+            },
+        })
+    }
+}
+
+#[cfg(feature = "certificates")]
+fn digest_algorithm(oid: &[u8]) -> Option<Algorithm> {
+    const SHA2: &[u8] = &[0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02];
+    match oid.strip_prefix(SHA2)? {
+        [1] => Some(Algorithm::Sha256),
+        [2] => Some(Algorithm::Sha384),
+        [3] => Some(Algorithm::Sha512),
+        _ => None,
+    }
+}
+
+/// A DER element.
+#[cfg(feature = "certificates")]
+struct Tlv<'a> {
+    tag: u8,
+    body: &'a [u8],
+    /// Tag, length and body.
+    raw: &'a [u8],
+}
+
+/// The first element of `data` and what follows it.
+#[cfg(feature = "certificates")]
+fn tlv(data: &[u8]) -> Option<(Tlv<'_>, &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = match first {
+        n if n < 0x80 => (usize::from(n), rest),
+        n @ 0x81..=0x84 => {
+            let k = usize::from(n & 0x7f);
+            let len = rest
+                .get(..k)?
+                .iter()
+                .fold(0usize, |acc, &b| acc << 8 | usize::from(b));
+            (len, &rest[k..])
+        }
+        _ => return None,
+    };
+    let body = rest.get(..len)?;
+    let used = data.len() - rest.len() + len;
+    Some((
+        Tlv {
+            tag,
+            body,
+            raw: &data[..used],
+        },
+        &rest[len..],
+    ))
+}
+
+#[cfg(feature = "certificates")]
+fn children(mut data: &[u8]) -> Option<Vec<Tlv<'_>>> {
+    let mut out = Vec::new();
+    while !data.is_empty() {
+        let (t, rest) = tlv(data)?;
+        out.push(t);
+        data = rest;
+    }
+    Some(out)
+}
+
+#[cfg(all(test, feature = "certificates"))]
+mod tests {
+    use super::*;
+    use p256::ecdsa::{DerSignature, SigningKey};
+    use std::time::Duration;
+    use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+    use x509_cert::serial_number::SerialNumber;
+    use x509_cert::spki::SubjectPublicKeyInfoOwned;
+    use x509_cert::time::Validity;
+
+    #[test]
+    fn signer_details() {
+        let key = SigningKey::from_bytes(&[5u8; 32].into()).unwrap();
+        let spki = SubjectPublicKeyInfoOwned::from_key(*key.verifying_key()).unwrap();
+        let cert = CertificateBuilder::new(
+            Profile::Root,
+            SerialNumber::from(0x1234u32),
+            Validity::from_now(Duration::from_secs(3600)).unwrap(),
+            "CN=Lowell Test Signer,O=lowell".parse().unwrap(),
+            spki,
+            &key,
+        )
+        .unwrap()
+        .build::<DerSignature>()
+        .unwrap();
+        let tbs = &cert.tbs_certificate;
+        let blob = lowell_test_util::signed_data(
+            &[0xab; 32],
+            &cert.to_der().unwrap(),
+            &tbs.issuer.to_der().unwrap(),
+            &tbs.serial_number.to_der().unwrap(),
+        );
+
+        let signed = Signed::parse(&blob).unwrap();
+        assert_eq!(signed.algorithm, Some(Algorithm::Sha256));
+        assert_eq!(signed.digest, [0xab; 32]);
+        let signer = signed.signer().unwrap();
+        assert_eq!(
+            signer.subject.as_deref(),
+            Some("CN=Lowell Test Signer,O=lowell")
+        );
+        assert_eq!(signer.issuer, "CN=Lowell Test Signer,O=lowell");
+        assert_eq!(signer.serial, "1234");
+        assert_eq!(signer.not_after, Some(tbs.validity.not_after.to_string()));
+        assert!(signer.not_before.unwrap().ends_with('Z'));
+        assert_eq!(signer.digest_algorithm, "sha256");
+
+        // Signed by a certificate it does not embed.
+        let bare = lowell_test_util::signed_data(
+            &[0xab; 32],
+            &[],
+            &tbs.issuer.to_der().unwrap(),
+            &[0x02, 0x01, 0x07],
+        );
+        let signer = Signed::parse(&bare).unwrap().signer().unwrap();
+        assert_eq!((signer.subject, signer.serial.as_str()), (None, "07"));
+        assert!(Signed::parse(b"fake pkcs7").is_none());
+    }
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Windows bitmaps (BMP), as systemd-stub draws them from a UKI's
+//! `.splash` section before it starts the kernel.
+//!
+//! The file is little-endian: a 14-byte file header (magic `BM`, file size
+//! at 2, pixel data offset at 10), then a DIB header whose first field is
+//! its own size — 40 for `BITMAPINFOHEADER`, more for its successors —
+//! with width and height at 4 and 8 (a negative height stores rows top
+//! down), planes at 12, bits per pixel at 14 and compression at 16. Rows
+//! of uncompressed pixel data are padded to four bytes.
+//!
+//! [`parse`] rejects what is malformed; [`Bitmap::stub_problem`] names
+//! what is well-formed but still not drawn by the stub, which fails
+//! silently and boots without a splash.
+
+use crate::error::{Error, Result};
+
+/// The first two bytes of every bitmap file.
+pub const MAGIC: [u8; 2] = *b"BM";
+
+const FILE_HEADER: usize = 14;
+const INFO_HEADER: usize = 40;
+
+/// `BI_RGB`: uncompressed rows.
+pub const RGB: u32 = 0;
+/// `BI_BITFIELDS`: uncompressed rows with explicit channel masks.
+pub const BITFIELDS: u32 = 3;
+
+/// The largest pixel array the stub accepts.
+const STUB_MAX_PIXELS: u64 = 64 * 1024 * 1024;
+
+/// What a bitmap's headers say about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct Bitmap {
+    pub width: u32,
+    /// Rows, whichever way they are stored.
+    pub height: u32,
+    pub bits_per_pixel: u16,
+    /// The `biCompression` value: [`RGB`], [`BITFIELDS`], 1 and 2 for RLE,
+    /// 4 and 5 for embedded JPEG and PNG.
+    pub compression: u32,
+    /// Rows are stored top row first (a negative height).
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_false"))]
+    pub top_down: bool,
+    /// The file size the header declares.
+    pub file_size: u32,
+}
+
+#[cfg(feature = "serde")]
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+impl Bitmap {
+    /// Bytes in one padded row of uncompressed pixel data.
+    pub fn row_size(&self) -> u64 {
+        (u64::from(self.width) * u64::from(self.bits_per_pixel)).div_ceil(32) * 4
+    }
+
+    /// Why systemd-stub would not draw this bitmap when it is `len` bytes
+    /// long; `None` if it would.
+    pub fn stub_problem(&self, len: usize) -> Option<String> {
+        let uncompressed = match self.bits_per_pixel {
+            16 | 32 => matches!(self.compression, RGB | BITFIELDS),
+            _ => self.compression == RGB,
+        };
+        if !uncompressed {
+            return Some(format!(
+                "compression {} at {} bits per pixel is not supported",
+                self.compression, self.bits_per_pixel
+            ));
+        }
+        if self.top_down {
+            return Some("top-down rows (negative height) are not supported".into());
+        }
+        if self.file_size as usize != len {
+            return Some(format!(
+                "header declares {} bytes but the section has {len}",
+                self.file_size
+            ));
+        }
+        if self.row_size() * u64::from(self.height) > STUB_MAX_PIXELS {
+            return Some("pixel data larger than 64 MiB".into());
+        }
+        None
+    }
+}
+
+fn u32_at(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u16_at(b: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(b.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// The headers of the bitmap in `blob`, checked against each other and
+/// against its length.
+pub fn parse(blob: &[u8]) -> Result<Bitmap> {
+    let bad = |why: String| Error::Bitmap(why);
+    if !blob.starts_with(&MAGIC) {
+        return Err(bad("no BM magic".into()));
+    }
+    let dib = u32_at(blob, FILE_HEADER).ok_or_else(|| bad("truncated header".into()))?;
+    if (dib as usize) < INFO_HEADER {
+        return Err(bad(format!(
+            "{dib}-byte DIB header (BITMAPINFOHEADER or later needed)"
+        )));
+    }
+    let header = blob
+        .get(FILE_HEADER..FILE_HEADER + INFO_HEADER)
+        .ok_or_else(|| bad("truncated header".into()))?;
+    let (file_size, offset) = (u32_at(blob, 2).unwrap(), u32_at(blob, 10).unwrap());
+    let width = u32_at(header, 4).unwrap() as i32;
+    let height = u32_at(header, 8).unwrap() as i32;
+    let planes = u16_at(header, 12).unwrap();
+    let bits_per_pixel = u16_at(header, 14).unwrap();
+    let compression = u32_at(header, 16).unwrap();
+
+    if file_size as usize > blob.len() {
+        return Err(bad(format!(
+            "truncated: header declares {file_size} bytes, {} present",
+            blob.len()
+        )));
+    }
+    if (offset as usize) < FILE_HEADER + dib as usize || offset > file_size {
+        return Err(bad(format!("pixel data offset {offset} out of bounds")));
+    }
+    if width <= 0 || height == 0 {
+        return Err(bad(format!("{width}x{height} pixels")));
+    }
+    if planes != 1 {
+        return Err(bad(format!("{planes} planes")));
+    }
+    if !matches!(bits_per_pixel, 1 | 4 | 8 | 16 | 24 | 32) {
+        return Err(bad(format!("{bits_per_pixel} bits per pixel")));
+    }
+    if compression > 6 {
+        return Err(bad(format!("unknown compression {compression}")));
+    }
+    let bitmap = Bitmap {
+        width: width as u32,
+        height: height.unsigned_abs(),
+        bits_per_pixel,
+        compression,
+        top_down: height < 0,
+        file_size,
+    };
+    if matches!(compression, RGB | BITFIELDS) {
+        let needed = bitmap.row_size() * u64::from(bitmap.height);
+        if u64::from(offset) + needed > u64::from(file_size) {
+            return Err(bad(format!(
+                "pixel data needs {needed} bytes, {} present",
+                file_size - offset
+            )));
+        }
+    }
+    Ok(bitmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::bmp;
+
+    #[test]
+    fn headers_describe_the_image() {
+        let blob = bmp(3, 2, 24);
+        let bitmap = parse(&blob).unwrap();
+        assert_eq!(
+            (bitmap.width, bitmap.height, bitmap.bits_per_pixel),
+            (3, 2, 24)
+        );
+        // 9 bytes of pixels, padded to 12.
+        assert_eq!(bitmap.row_size(), 12);
+        assert_eq!(bitmap.stub_problem(blob.len()), None);
+
+        let flipped = parse(&bmp(3, -2, 32)).unwrap();
+        assert_eq!((flipped.height, flipped.top_down), (2, true));
+        assert!(flipped.stub_problem(54 + 24).unwrap().contains("top-down"));
+        // Padding after the declared size is not drawn past.
+        let padded = [&blob[..], &[0; 8]].concat();
+        assert!(parse(&padded).unwrap().stub_problem(padded.len()).is_some());
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let err = |blob: &[u8]| parse(blob).unwrap_err().to_string();
+        assert!(err(b"PNG").contains("magic"));
+        assert!(err(&bmp(3, 2, 24)[..30]).contains("truncated"));
+        let mut short = bmp(3, 2, 24);
+        short.truncate(short.len() - 1);
+        assert!(err(&short).contains("truncated"));
+        let mut bits = bmp(3, 2, 24);
+        bits[28] = 7;
+        assert!(err(&bits).contains("7 bits per pixel"));
+        let mut rows = bmp(3, 2, 24);
+        rows[22] = 3;
+        assert!(err(&rows).contains("pixel data needs 36 bytes, 24 present"));
+        assert!(err(&bmp(0, 2, 24)).contains("0x2"));
+
+        // RLE data is not sized by rows, but the stub cannot draw it.
+        let mut rle = bmp(4, 4, 8);
+        rle[30] = 1;
+        let rle = parse(&rle).unwrap();
+        assert!(rle.stub_problem(54 + 16).unwrap().contains("compression 1"));
+    }
+}
@@ -0,0 +1,438 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Streaming reader and writer for `newc` cpio archives (the initramfs format).
+//!
+//! Layout per entry: a 110-byte ASCII header (`070701` or `070702` magic
+//! followed by thirteen 8-digit hex fields), the NUL-terminated name padded
+//! to 4 bytes, then the file data padded to 4 bytes. An entry named
+//! `TRAILER!!!` ends the archive.
+//!
+//! The kernel accepts several archives back to back (zero padding allowed in
+//! between), so [`Reader`] transparently continues into the next archive and
+//! only reports the end once the input is exhausted or stops looking like
+//! cpio (e.g. a compressed segment follows).
+
+use crate::error::{Error, Result};
+use std::io::{self, Read, Write};
+
+const HEADER_LEN: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+/// Longest name (with its NUL) the kernel will unpack (`PATH_MAX`).
+const MAX_NAME: usize = 4096;
+
+/// File type bits of `mode` (see `stat(2)`).
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+/// One decoded newc header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub mtime: u32,
+    pub filesize: u32,
+    pub devmajor: u32,
+    pub devminor: u32,
+    pub rdevmajor: u32,
+    pub rdevminor: u32,
+    /// Path as stored in the archive (usually relative, no leading `/`).
+    pub name: String,
+}
+
+impl Header {
+    /// Header for a new entry: owned by root, one link, everything else zero.
+    pub fn new(name: impl Into<String>, mode: u32) -> Self {
+        Header {
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            nlink: 1,
+            mtime: 0,
+            filesize: 0,
+            devmajor: 0,
+            devminor: 0,
+            rdevmajor: 0,
+            rdevminor: 0,
+            name: name.into(),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+
+/// Pull-style newc reader over any byte stream.
+///
+/// Call [`Reader::next_entry`] to advance; between calls, [`Reader::data`]
+/// borrows the current entry's contents. Unread data is skipped automatically.
+pub struct Reader<R> {
+    inner: R,
+    /// Bytes read ahead while probing for a magic, not yet consumed.
+    lookahead: Vec<u8>,
+    /// Data bytes of the current entry not yet consumed, and its padding.
+    pending: u64,
+    pending_pad: u64,
+    /// Total bytes consumed from the input.
+    position: u64,
+    /// Stop at the first trailer; see [`Reader::single_archive`].
+    single: bool,
+    done: bool,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            lookahead: Vec::new(),
+            pending: 0,
+            pending_pad: 0,
+            position: 0,
+            single: false,
+            done: false,
+        }
+    }
+
+    /// End at the first archive's trailer rather than going on into the
+    /// next, so that [`Reader::position`] then says where it ends.
+    pub fn single_archive(mut self) -> Self {
+        self.single = true;
+        self
+    }
+
+    /// Bytes consumed from the input so far.
+    ///
+    /// After [`Reader::next_entry`] returns `Ok(None)`, this is the offset of
+    /// the first byte that is not part of a cpio archive.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Advance to the next entry; `Ok(None)` once no further archive follows.
+    pub fn next_entry(&mut self) -> Result<Option<Header>> {
+        let skip = self.pending + self.pending_pad;
+        self.skip(skip)?;
+        self.pending = 0;
+        self.pending_pad = 0;
+
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+            self.skip_zeros()?;
+            if !self.at_cpio_magic()? {
+                return Ok(None);
+            }
+            let header = self.read_header()?;
+            if header.name == TRAILER {
+                self.done = self.single;
+                continue;
+            }
+            self.pending = u64::from(header.filesize);
+            self.pending_pad = pad4(self.pending);
+            return Ok(Some(header));
+        }
+    }
+
+    /// Reader over the current entry's data (empty before the first entry).
+    pub fn data(&mut self) -> impl Read + '_ {
+        EntryData { reader: self }
+    }
+
+    fn read_header(&mut self) -> Result<Header> {
+        let mut raw = [0u8; HEADER_LEN];
+        self.read_exact(&mut raw)
+            .map_err(|e| truncated("cpio header", e))?;
+        let field = |i: usize| -> Result<u32> {
+            let start = 6 + i * 8;
+            let text = std::str::from_utf8(&raw[start..start + 8])
+                .map_err(|_| Error::cpio("non-ASCII cpio header field"))?;
+            u32::from_str_radix(text, 16)
+                .map_err(|_| Error::cpio(format!("bad cpio header field {text:?}")))
+        };
+        let namesize = field(11)? as usize;
+        if namesize == 0 {
+            return Err(Error::cpio("cpio entry with empty name"));
+        }
+        if namesize > MAX_NAME {
+            return Err(Error::cpio(format!("cpio name of {namesize} bytes")));
+        }
+        let mut name = vec![0u8; namesize];
+        self.read_exact(&mut name)
+            .map_err(|e| truncated("cpio name", e))?;
+        let name_end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        name.truncate(name_end);
+        self.skip(pad4((HEADER_LEN + namesize) as u64))?;
+
+        Ok(Header {
+            ino: field(0)?,
+            mode: field(1)?,
+            uid: field(2)?,
+            gid: field(3)?,
+            nlink: field(4)?,
+            mtime: field(5)?,
+            filesize: field(6)?,
+            devmajor: field(7)?,
+            devminor: field(8)?,
+            rdevmajor: field(9)?,
+            rdevminor: field(10)?,
+            name: String::from_utf8_lossy(&name).into_owned(),
+        })
+    }
+
+    /// True if the next bytes are a newc magic (`070701` / `070702`).
+    fn at_cpio_magic(&mut self) -> Result<bool> {
+        self.fill_lookahead(6)?;
+        Ok(matches!(&self.lookahead[..], b"070701" | b"070702"))
+    }
+
+    /// Consume NUL padding between archives (the kernel allows any amount).
+    fn skip_zeros(&mut self) -> Result<()> {
+        loop {
+            self.fill_lookahead(1)?;
+            match self.lookahead.first() {
+                Some(0) => {
+                    self.lookahead.remove(0);
+                    self.position += 1;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Make sure up to `n` bytes are buffered (fewer only at end of input).
+    fn fill_lookahead(&mut self, n: usize) -> io::Result<()> {
+        while self.lookahead.len() < n {
+            let mut buf = [0u8; 8];
+            let want = n - self.lookahead.len();
+            let got = self.inner.read(&mut buf[..want])?;
+            if got == 0 {
+                break;
+            }
+            self.lookahead.extend_from_slice(&buf[..got]);
+        }
+        Ok(())
+    }
+
+    /// Raw read honoring the lookahead buffer; advances `position`.
+    fn read_raw(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = if self.lookahead.is_empty() {
+            self.inner.read(buf)?
+        } else {
+            let n = buf.len().min(self.lookahead.len());
+            buf[..n].copy_from_slice(&self.lookahead[..n]);
+            self.lookahead.drain(..n);
+            n
+        };
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n = self.read_raw(buf)?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            buf = &mut buf[n..];
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, mut n: u64) -> Result<()> {
+        let mut scratch = [0u8; 8192];
+        while n > 0 {
+            let want = scratch.len().min(usize::try_from(n).unwrap_or(usize::MAX));
+            let got = self.read_raw(&mut scratch[..want])?;
+            if got == 0 {
+                return Err(Error::cpio("truncated cpio data"));
+            }
+            n -= got as u64;
+        }
+        Ok(())
+    }
+}
+
+fn truncated(what: &str, source: io::Error) -> Error {
+    Error::Cpio {
+        msg: format!("truncated {what}"),
+        source: Some(source),
+    }
+}
+
+struct EntryData<'a, R> {
+    reader: &'a mut Reader<R>,
+}
+
+impl<R: Read> Read for EntryData<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = buf
+            .len()
+            .min(usize::try_from(self.reader.pending).unwrap_or(usize::MAX));
+        if max == 0 {
+            return Ok(0);
+        }
+        let n = self.reader.read_raw(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.reader.pending -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Streaming newc writer, the counterpart of [`Reader`].
+///
+/// Inode numbers are assigned sequentially; `filesize` is taken from the
+/// data actually written. [`Writer::finish`] appends the trailer.
+pub struct Writer<W> {
+    inner: W,
+    next_ino: u32,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, next_ino: 1 }
+    }
+
+    /// Append one entry with `data` as its contents (symlink target for links).
+    pub fn append(&mut self, header: &Header, data: &[u8]) -> Result<()> {
+        let filesize = u32::try_from(data.len())
+            .map_err(|_| Error::cpio(format!("{} is too large for cpio", header.name)))?;
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.write_header(&Header {
+            ino,
+            filesize,
+            ..header.clone()
+        })?;
+        self.inner.write_all(data)?;
+        self.inner
+            .write_all(&[0; 3][..pad4(data.len() as u64) as usize])?;
+        Ok(())
+    }
+
+    /// Write the `TRAILER!!!` entry and hand back the inner writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.write_header(&Header::new(TRAILER, 0))?;
+        Ok(self.inner)
+    }
+
+    fn write_header(&mut self, h: &Header) -> Result<()> {
+        let namesize = h.name.len() + 1;
+        let mut raw = String::with_capacity(HEADER_LEN);
+        raw.push_str("070701");
+        for v in [
+            h.ino,
+            h.mode,
+            h.uid,
+            h.gid,
+            h.nlink,
+            h.mtime,
+            h.filesize,
+            h.devmajor,
+            h.devminor,
+            h.rdevmajor,
+            h.rdevminor,
+            namesize as u32,
+            0,
+        ] {
+            raw.push_str(&format!("{v:08X}"));
+        }
+        self.inner.write_all(raw.as_bytes())?;
+        self.inner.write_all(h.name.as_bytes())?;
+        let pad = pad4((HEADER_LEN + namesize) as u64) as usize;
+        self.inner.write_all(&[0; 4][..1 + pad])?;
+        Ok(())
+    }
+}
+
+#[inline]
+fn pad4(n: u64) -> u64 {
+    (4 - n % 4) % 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::newc;
+
+    #[test]
+    fn reads_entries_data_and_stops_at_trailer() {
+        let bytes = newc(&[
+            ("usr", S_IFDIR | 0o755, b""),
+            ("usr/hello", S_IFREG | 0o644, b"hello world"),
+        ]);
+        let mut r = Reader::new(&bytes[..]);
+
+        let dir = r.next_entry().unwrap().expect("dir entry");
+        assert_eq!(dir.name, "usr");
+        assert!(dir.is_dir());
+
+        let file = r.next_entry().unwrap().expect("file entry");
+        assert!(file.is_file());
+        let mut data = String::new();
+        r.data().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "hello world");
+
+        assert!(r.next_entry().unwrap().is_none());
+        assert_eq!(r.position(), bytes.len() as u64);
+    }
+
+    #[test]
+    fn continues_into_concatenated_archive_and_stops_before_foreign_bytes() {
+        let mut bytes = newc(&[("a", S_IFREG, b"1")]);
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&newc(&[("b", S_IFREG, b"22")]));
+        let archive_len = bytes.len() as u64;
+        bytes.extend_from_slice(&[0x1F, 0x8B, 0x08, 0x00, 0, 0, 0, 0]);
+
+        let mut r = Reader::new(&bytes[..]);
+        let names: Vec<_> = std::iter::from_fn(|| r.next_entry().unwrap())
+            .map(|h| h.name)
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(r.position(), archive_len);
+    }
+
+    #[test]
+    fn writer_output_reads_back() {
+        let mut w = Writer::new(Vec::new());
+        w.append(&Header::new("etc", S_IFDIR | 0o755), b"").unwrap();
+        w.append(&Header::new("etc/hostname", S_IFREG | 0o644), b"lowell\n")
+            .unwrap();
+        let bytes = w.finish().unwrap();
+        assert_eq!(bytes.len() % 4, 0);
+
+        let mut r = Reader::new(&bytes[..]);
+        assert_eq!(r.next_entry().unwrap().unwrap().name, "etc");
+        let file = r.next_entry().unwrap().unwrap();
+        assert_eq!(
+            (file.name.as_str(), file.filesize, file.ino),
+            ("etc/hostname", 7, 2)
+        );
+        assert!(r.next_entry().unwrap().is_none());
+        assert_eq!(r.position(), bytes.len() as u64);
+    }
+
+    #[test]
+    fn truncated_archive_is_an_error() {
+        let bytes = newc(&[("a", S_IFREG, b"payload")]);
+        let mut r = Reader::new(&bytes[..HEADER_LEN + 4]);
+        r.next_entry().unwrap();
+        assert!(r.next_entry().is_err());
+    }
+}
@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Digest algorithms for section hashes.
+//!
+//! A [`Digest`] prints (and serializes) in the OCI `algorithm:hex` form,
+//! e.g. `sha256:2daa…`, so reports say which algorithm produced them.
+//! SHA-2 is always available; BLAKE3 needs the `blake3` feature and is
+//! several times faster on multi-megabyte sections.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum Algorithm {
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl Algorithm {
+    /// Every algorithm this build supports, in [`Display`](fmt::Display) form.
+    pub const NAMES: &'static [&'static str] = &[
+        "sha256",
+        "sha384",
+        "sha512",
+        #[cfg(feature = "blake3")]
+        "blake3",
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha384 => "sha384",
+            Algorithm::Sha512 => "sha512",
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Digest of `data` in one go.
+    pub fn digest(self, data: &[u8]) -> Digest {
+        let mut hasher = Hasher::new(self);
+        hasher.update(data);
+        hasher.finish()
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Ok(match s {
+            "sha256" => Algorithm::Sha256,
+            "sha384" => Algorithm::Sha384,
+            "sha512" => Algorithm::Sha512,
+            #[cfg(feature = "blake3")]
+            "blake3" => Algorithm::Blake3,
+            _ => {
+                return Err(format!(
+                    "unknown digest algorithm {s:?} (available: {})",
+                    Algorithm::NAMES.join(", ")
+                ))
+            }
+        })
+    }
+}
+
+/// A digest and the algorithm that produced it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Digest {
+    pub algorithm: Algorithm,
+    /// Lowercase hex.
+    pub hex: String,
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.hex)
+    }
+}
+
+impl FromStr for Digest {
+    type Err = String;
+
+    /// Parse the `algorithm:hex` form that [`Display`](fmt::Display) writes.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (algorithm, hex) = s
+            .split_once(':')
+            .ok_or_else(|| format!("digest {s:?} is not in algorithm:hex form"))?;
+        if hex.is_empty()
+            || !hex
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        {
+            return Err(format!("digest {s:?} is not lowercase hex"));
+        }
+        Ok(Digest {
+            algorithm: algorithm.parse()?,
+            hex: hex.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Digest {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Digest {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(d)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Digest {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Digest".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "A digest in `algorithm:hex` form, e.g. `sha256:2daa…`.",
+            "type": "string",
+            "pattern": "^[a-z0-9]+:[0-9a-f]+$",
+        })
+    }
+}
+
+/// Incremental hashing with any [`Algorithm`].
+pub(crate) enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha384(sha2::Sha384),
+    Sha512(sha2::Sha512),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: Algorithm) -> Self {
+        use sha2::Digest as _;
+        match algorithm {
+            Algorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            Algorithm::Sha384 => Hasher::Sha384(sha2::Sha384::new()),
+            Algorithm::Sha512 => Hasher::Sha512(sha2::Sha512::new()),
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        use sha2::Digest as _;
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha384(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> Digest {
+        use sha2::Digest as _;
+        let (algorithm, hex) = match self {
+            Hasher::Sha256(h) => (Algorithm::Sha256, format!("{:x}", h.finalize())),
+            Hasher::Sha384(h) => (Algorithm::Sha384, format!("{:x}", h.finalize())),
+            Hasher::Sha512(h) => (Algorithm::Sha512, format!("{:x}", h.finalize())),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(h) => (Algorithm::Blake3, h.finalize().to_hex().to_string()),
+        };
+        Digest { algorithm, hex }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithms_round_trip_and_prefix_their_digests() {
+        for name in Algorithm::NAMES {
+            let alg: Algorithm = name.parse().unwrap();
+            assert_eq!(alg.to_string(), *name);
+            let d = alg.digest(b"abc");
+            assert!(d.to_string().starts_with(&format!("{name}:")));
+        }
+        assert_eq!(
+            Algorithm::Sha256.digest(b"abc").hex,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(Algorithm::Sha512.digest(b"").hex.len(), 128);
+        assert!("md5".parse::<Algorithm>().is_err());
+
+        let d = Algorithm::Sha256.digest(b"abc");
+        assert_eq!(d.to_string().parse::<Digest>(), Ok(d));
+        assert!("sha256".parse::<Digest>().is_err());
+        assert!("sha256:XYZ".parse::<Digest>().is_err());
+    }
+}
@@ -0,0 +1,342 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! The library's error type and the stable codes derived from it.
+//!
+//! Every fallible function in the lowell crates returns [`Result`], so
+//! callers can match on [`Error`] variants instead of parsing messages.
+//! Variants carry the underlying error as their `source()`; the CLI prints
+//! the whole chain.
+//!
+//! [`ErrorCode`] is the coarse, serializable classification used for
+//! machine-readable output. The `E_*` strings are part of the CLI's JSON
+//! contract: add new codes, never rename or reuse old ones.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// I/O on a named file (`read /boot/uki.efi`).
+    #[error("{op} {}", path.display())]
+    File {
+        op: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("not a valid PE/EFI image")]
+    NotPe(#[source] goblin::error::Error),
+    /// The stub handed to the UKI assembler is not usable.
+    #[error("{0}")]
+    BadStub(String),
+    #[error("{0}")]
+    Assemble(String),
+    #[error("no {name} section found in the UKI")]
+    SectionMissing { name: String },
+    /// A section header points past the end of the file.
+    #[error("section {name} ({size:#x} bytes at {offset:#x}) lies outside the file")]
+    SectionOutOfBounds {
+        name: String,
+        offset: usize,
+        size: usize,
+    },
+    #[error("{}: not a bzImage, arm64 Image or EFI zboot kernel", path.display())]
+    NotKernel { path: PathBuf },
+    #[error("invalid os-release")]
+    OsRelease(#[from] rs_release::OsReleaseError),
+
+    #[error("unsupported initramfs compression")]
+    UnsupportedCompression,
+    #[error("decompression failed")]
+    Decompress(#[source] io::Error),
+    /// Malformed cpio data; `source` is set when reading failed.
+    #[error("{msg}")]
+    Cpio {
+        msg: String,
+        #[source]
+        source: Option<io::Error>,
+    },
+    #[error("cpio segment at offset {offset:#x}")]
+    Segment {
+        offset: usize,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("unrecognized initramfs data at offset {offset:#x}")]
+    UnrecognizedInitramfs { offset: usize },
+    /// An archive entry would be written outside the destination.
+    #[error("{0}")]
+    UnsafePath(String),
+    #[error("{} {what}", path.display())]
+    Conflict { path: PathBuf, what: &'static str },
+
+    #[cfg(feature = "profile")]
+    #[error("invalid profile{}", in_file(path.as_deref()))]
+    InvalidProfile {
+        path: Option<PathBuf>,
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+    /// A profile value failed validation (root spec, cmdline fragments).
+    #[error("{0}")]
+    Invalid(String),
+    #[error("profile {spec:?} not found (not a file, nor in {searched} search path(s))")]
+    ProfileNotFound { spec: String, searched: usize },
+    #[error("unknown built-in profile {name:?} (available: {available})")]
+    UnknownBuiltin { name: String, available: String },
+    #[error("profile {profile:?} has no `{key}` in [artifacts]{hint}")]
+    MissingArtifact {
+        profile: String,
+        key: &'static str,
+        hint: &'static str,
+    },
+    #[cfg(feature = "profile")]
+    #[error("invalid lockfile{}", in_file(Some(path)))]
+    InvalidLockfile {
+        path: PathBuf,
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+    #[cfg(feature = "profile")]
+    #[error("serialize TOML")]
+    Serialize(#[from] toml::ser::Error),
+    /// Input went past a [`ParserLimits`](crate::limits::ParserLimits) bound.
+    #[error("{what} exceeds the limit of {limit}")]
+    LimitExceeded { what: &'static str, limit: u64 },
+    /// A TPM event log is malformed or truncated.
+    #[error("{0}")]
+    EventLog(String),
+    /// An `.sbat` section or SBAT level is not valid SBAT CSV.
+    #[error("malformed SBAT: {0}")]
+    Sbat(String),
+    /// A `.dtb` / `.dtbauto` section is not a flattened devicetree.
+    #[error("malformed devicetree: {0}")]
+    Devicetree(String),
+    /// A bitmap (a `.splash` section) is truncated or not BMP.
+    #[error("malformed bitmap: {0}")]
+    Bitmap(String),
+    /// A [`CancelToken`](crate::cancel::CancelToken) was cancelled.
+    #[error("cancelled")]
+    Cancelled,
+}
+
+#[cfg(feature = "profile")]
+fn in_file(path: Option<&Path>) -> String {
+    path.map(|p| format!(" {}", p.display()))
+        .unwrap_or_default()
+}
+
+impl Error {
+    pub(crate) fn cpio(msg: impl Into<String>) -> Self {
+        Error::Cpio {
+            msg: msg.into(),
+            source: None,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::File { source, .. } | Error::Io(source) => ErrorCode::from_io(source),
+            Error::NotPe(_) | Error::BadStub(_) | Error::SectionOutOfBounds { .. } => {
+                ErrorCode::NotPe
+            }
+            Error::Assemble(_) => ErrorCode::BuildFailed,
+            Error::SectionMissing { .. } => ErrorCode::SectionMissing,
+            Error::NotKernel { .. } => ErrorCode::NotKernel,
+            Error::UnsupportedCompression => ErrorCode::UnsupportedCompression,
+            Error::Decompress(_)
+            | Error::Cpio { .. }
+            | Error::Segment { .. }
+            | Error::UnrecognizedInitramfs { .. } => ErrorCode::BadInitramfs,
+            Error::UnsafePath(_) => ErrorCode::UnsafePath,
+            Error::Conflict { .. } => ErrorCode::Io,
+            #[cfg(feature = "profile")]
+            Error::InvalidProfile { .. } => ErrorCode::ProfileInvalid,
+            Error::Invalid(_) | Error::MissingArtifact { .. } => ErrorCode::ProfileInvalid,
+            Error::ProfileNotFound { .. } | Error::UnknownBuiltin { .. } => {
+                ErrorCode::ProfileNotFound
+            }
+            #[cfg(feature = "profile")]
+            Error::InvalidLockfile { .. } => ErrorCode::LockInvalid,
+            Error::LimitExceeded { .. } => ErrorCode::LimitExceeded,
+            Error::Cancelled => ErrorCode::Cancelled,
+            Error::EventLog(_) => ErrorCode::BadEventLog,
+            Error::Sbat(_) => ErrorCode::BadSbat,
+            Error::Devicetree(_) => ErrorCode::BadDevicetree,
+            Error::Bitmap(_) => ErrorCode::BadBitmap,
+            #[cfg(feature = "profile")]
+            Error::Serialize(_) => ErrorCode::Unknown,
+            Error::OsRelease(_) => ErrorCode::Unknown,
+        }
+    }
+}
+
+/// `.at("read", path)` on I/O results: names the file in the error.
+pub trait IoResultExt<T> {
+    fn at(self, op: &'static str, path: &Path) -> Result<T>;
+}
+
+impl<T> IoResultExt<T> for io::Result<T> {
+    fn at(self, op: &'static str, path: &Path) -> Result<T> {
+        self.map_err(|source| Error::File {
+            op,
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// Input is not a PE/EFI image.
+    NotPe,
+    /// Input is not a recognized kernel image.
+    NotKernel,
+    /// Input is not a UKI, initramfs or kernel image.
+    UnknownFormat,
+    /// A required PE section (`.linux`, `.initrd`, …) is absent.
+    SectionMissing,
+    /// initramfs compression lowell cannot decode.
+    UnsupportedCompression,
+    /// Malformed or truncated cpio / initramfs data.
+    BadInitramfs,
+    /// An archive entry would escape the extraction directory.
+    UnsafePath,
+    /// Profile TOML failed to parse or validate.
+    ProfileInvalid,
+    /// No built-in, file or search-path entry matches the profile spec.
+    ProfileNotFound,
+    /// Artifacts no longer match the lockfile.
+    LockMismatch,
+    /// The lockfile itself failed to parse.
+    LockInvalid,
+    /// The UKI could not be assembled from its inputs.
+    BuildFailed,
+    /// A command-line value was rejected (unknown field, empty glob, …).
+    InvalidArgument,
+    /// Some, but not necessarily all, of several inputs failed.
+    PartialFailure,
+    /// The operation was cancelled before it finished.
+    Cancelled,
+    /// The input exceeds a parser limit (section size, entries, …).
+    LimitExceeded,
+    /// An OCI registry refused a request or sent something unexpected.
+    Registry,
+    /// A signature is missing, malformed or does not match.
+    BadSignature,
+    /// A TPM event log is malformed or truncated.
+    BadEventLog,
+    /// SBAT metadata is not valid SBAT CSV.
+    BadSbat,
+    /// A devicetree blob is truncated or not FDT.
+    BadDevicetree,
+    /// A bitmap is truncated or not BMP.
+    BadBitmap,
+    /// Measured PCR values differ from the predicted ones.
+    PcrMismatch,
+    /// A test boot failed, hung or showed no success marker.
+    BootFailed,
+    /// A declared download failed or does not match its pinned digest.
+    FetchFailed,
+    /// A report differs from its baseline outside the ignored fields.
+    BaselineMismatch,
+    /// A UKI built by lowell differs from ukify's from the same inputs.
+    ConformanceMismatch,
+    /// The running system did not boot the UKI it was checked against.
+    BootMismatch,
+    NotFound,
+    PermissionDenied,
+    /// Any other I/O failure.
+    Io,
+    /// Not classified (yet).
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn from_io(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => ErrorCode::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+            _ => ErrorCode::Io,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::NotPe => "E_NOT_PE",
+            ErrorCode::NotKernel => "E_NOT_KERNEL",
+            ErrorCode::UnknownFormat => "E_UNKNOWN_FORMAT",
+            ErrorCode::SectionMissing => "E_SECTION_MISSING",
+            ErrorCode::UnsupportedCompression => "E_UNSUPPORTED_COMPRESSION",
+            ErrorCode::BadInitramfs => "E_BAD_INITRAMFS",
+            ErrorCode::UnsafePath => "E_UNSAFE_PATH",
+            ErrorCode::ProfileInvalid => "E_PROFILE_INVALID",
+            ErrorCode::ProfileNotFound => "E_PROFILE_NOT_FOUND",
+            ErrorCode::LockMismatch => "E_LOCK_MISMATCH",
+            ErrorCode::LockInvalid => "E_LOCK_INVALID",
+            ErrorCode::BuildFailed => "E_BUILD_FAILED",
+            ErrorCode::InvalidArgument => "E_INVALID_ARGUMENT",
+            ErrorCode::PartialFailure => "E_PARTIAL_FAILURE",
+            ErrorCode::Cancelled => "E_CANCELLED",
+            ErrorCode::LimitExceeded => "E_LIMIT_EXCEEDED",
+            ErrorCode::Registry => "E_REGISTRY",
+            ErrorCode::BadSignature => "E_BAD_SIGNATURE",
+            ErrorCode::BadEventLog => "E_BAD_EVENT_LOG",
+            ErrorCode::BadSbat => "E_BAD_SBAT",
+            ErrorCode::BadDevicetree => "E_BAD_DEVICETREE",
+            ErrorCode::BadBitmap => "E_BAD_BITMAP",
+            ErrorCode::PcrMismatch => "E_PCR_MISMATCH",
+            ErrorCode::BootFailed => "E_BOOT_FAILED",
+            ErrorCode::FetchFailed => "E_FETCH_FAILED",
+            ErrorCode::BaselineMismatch => "E_BASELINE_MISMATCH",
+            ErrorCode::ConformanceMismatch => "E_CONFORMANCE_MISMATCH",
+            ErrorCode::BootMismatch => "E_BOOT_MISMATCH",
+            ErrorCode::NotFound => "E_NOT_FOUND",
+            ErrorCode::PermissionDenied => "E_PERMISSION_DENIED",
+            ErrorCode::Io => "E_IO",
+            ErrorCode::Unknown => "E_UNKNOWN",
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ErrorCode {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.as_str())
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_follow_variants_and_io_kinds() {
+        let err = std::fs::read("/nonexistent/lowell")
+            .at("read", Path::new("/nonexistent/lowell"))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "read /nonexistent/lowell");
+        assert_eq!(err.code(), ErrorCode::NotFound);
+
+        let err = Error::Segment {
+            offset: 0x200,
+            source: Box::new(Error::cpio("truncated cpio header")),
+        };
+        assert_eq!(err.code(), ErrorCode::BadInitramfs);
+        assert_eq!(err.to_string(), "cpio segment at offset 0x200");
+        assert_eq!(ErrorCode::SectionMissing.to_string(), "E_SECTION_MISSING");
+    }
+}
@@ -0,0 +1,138 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Flattened devicetrees (FDT), as UKIs for ARM64 and RISC-V boards carry
+//! them in `.dtb` (one, always loaded) and `.dtbauto` (any number; the
+//! stub picks the one whose `compatible` matches the firmware's) sections.
+//!
+//! Only what names the board is read: the root node's `model` and
+//! `compatible` properties. The blob is big-endian throughout: a header
+//! (magic `d00dfeed`, total size at 4, structure block offset at 8,
+//! strings block offset at 12, structure size at 36), then tokens in the
+//! structure block — `BEGIN_NODE` (1) and a padded name, `PROP` (3) with
+//! length, name offset and padded value, `END_NODE` (2), `NOP` (4) and
+//! `END` (9). A node's properties come before its children.
+
+use crate::error::{Error, Result};
+
+/// The first four bytes of every FDT blob.
+pub const MAGIC: [u8; 4] = [0xd0, 0x0d, 0xfe, 0xed];
+
+const BEGIN_NODE: u32 = 1;
+const END_NODE: u32 = 2;
+const PROP: u32 = 3;
+const NOP: u32 = 4;
+
+/// Which board a devicetree describes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct Devicetree {
+    /// The root `model`, e.g. `Raspberry Pi 4 Model B Rev 1.4`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub model: Option<String>,
+    /// The root `compatible` list, most specific first.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub compatible: Vec<String>,
+}
+
+fn u32_at(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// The root node's `model` and `compatible` in `blob`.
+pub fn parse(blob: &[u8]) -> Result<Devicetree> {
+    let bad = |why: &str| Error::Devicetree(why.to_string());
+    if !blob.starts_with(&MAGIC) {
+        return Err(bad("no FDT magic"));
+    }
+    let header = |at| u32_at(blob, at).map(|v| v as usize);
+    let (Some(total), Some(structs), Some(strings), Some(struct_size)) =
+        (header(4), header(8), header(12), header(36))
+    else {
+        return Err(bad("truncated header"));
+    };
+    let blob = blob.get(..total).ok_or_else(|| bad("truncated"))?;
+    let block = structs
+        .checked_add(struct_size)
+        .and_then(|end| blob.get(structs..end))
+        .ok_or_else(|| bad("structure block out of bounds"))?;
+    let strings = blob
+        .get(strings..)
+        .ok_or_else(|| bad("strings block out of bounds"))?;
+
+    let mut out = Devicetree::default();
+    let mut at = 0;
+    let mut in_root = false;
+    while let Some(token) = u32_at(block, at) {
+        at += 4;
+        match token {
+            NOP => {}
+            BEGIN_NODE if !in_root => {
+                let name = block.get(at..).unwrap_or_default();
+                let len = name
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| bad("unterminated node name"))?;
+                at += (len + 1).next_multiple_of(4);
+                in_root = true;
+            }
+            PROP if in_root => {
+                let (Some(len), Some(name)) = (u32_at(block, at), u32_at(block, at + 4)) else {
+                    return Err(bad("truncated property"));
+                };
+                let value = block
+                    .get(at + 8..at + 8 + len as usize)
+                    .ok_or_else(|| bad("property out of bounds"))?;
+                at += 8 + (len as usize).next_multiple_of(4);
+                let name = strings.get(name as usize..).unwrap_or_default();
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                let strs = || {
+                    value
+                        .split(|&b| b == 0)
+                        .filter(|s| !s.is_empty())
+                        .map(|s| String::from_utf8_lossy(s).into_owned())
+                };
+                match name {
+                    b"model" => out.model = strs().next(),
+                    b"compatible" => out.compatible = strs().collect(),
+                    _ => {}
+                }
+            }
+            // The root's first child, or its end: no more root properties.
+            BEGIN_NODE | END_NODE => return Ok(out),
+            _ => return Err(bad(&format!("unexpected token {token}"))),
+        }
+    }
+    Err(bad("structure block ends inside the root node"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_model_and_compatible() {
+        let blob = lowell_test_util::fdt(&[
+            ("#address-cells", &[0, 0, 0, 2]),
+            ("compatible", b"raspberrypi,4-model-b\0brcm,bcm2711\0"),
+            ("model", b"Raspberry Pi 4 Model B\0"),
+        ]);
+        let dt = parse(&blob).unwrap();
+        assert_eq!(dt.model.as_deref(), Some("Raspberry Pi 4 Model B"));
+        assert_eq!(dt.compatible, ["raspberrypi,4-model-b", "brcm,bcm2711"]);
+
+        assert_eq!(
+            parse(&lowell_test_util::fdt(&[])).unwrap(),
+            Devicetree::default()
+        );
+        assert!(parse(b"\xd0\x0d\xfe\xed").is_err());
+        assert!(parse(&blob[..blob.len() - 20]).is_err());
+        assert!(parse(b"not a devicetree").is_err());
+    }
+}
@@ -0,0 +1,507 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::cpio;
+use crate::error::{Error, Result};
+use crate::limits::{Limited, ParserLimits};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::pipeline;
+use crate::timing::Stopwatch;
+use crate::trace::{debug, debug_span, trace};
+use std::cell::Cell;
+use std::fmt;
+use std::io::Read;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+    Uncompressed,
+    Unknown,
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Compression::Gzip => "gzip",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+            Compression::Uncompressed => "uncompressed",
+            Compression::Unknown => "unknown",
+        };
+        f.write_str(s)
+    }
+}
+
+#[inline]
+pub fn detect(bytes: &[u8]) -> Compression {
+    match bytes {
+        // gzip: 1F 8B
+        [0x1F, 0x8B, ..] => Compression::Gzip,
+
+        // xz: FD 37 7A 58 5A 00
+        [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00, ..] => Compression::Xz,
+
+        // zstd: 28 B5 2F FD
+        [0x28, 0xB5, 0x2F, 0xFD, ..] => Compression::Zstd,
+
+        // uncompressed cpio (newc): ASCII "070701"
+        [b'0', b'7', b'0', b'7', b'0', b'1', ..] => Compression::Uncompressed,
+
+        // anything else
+        _ => Compression::Unknown,
+    }
+}
+
+/// Wrap `bytes` in a decompressor for `compression`.
+///
+/// `Uncompressed` passes the bytes through; `Unknown` is an error. xz is
+/// decoded by liblzma's threaded decoder, which spreads the blocks of
+/// multi-block streams (`xz -T`, the default since xz 5.4) over all CPUs.
+pub fn decoder<'a>(compression: Compression, bytes: &'a [u8]) -> Result<Box<dyn Read + Send + 'a>> {
+    decoder_within(compression, bytes, ParserLimits::default().max_memory)
+}
+
+/// [`decoder`], with xz threads held to `memory` bytes between them.
+fn decoder_within<'a>(
+    compression: Compression,
+    bytes: &'a [u8],
+    memory: u64,
+) -> Result<Box<dyn Read + Send + 'a>> {
+    #[cfg(not(all(feature = "xz", not(target_arch = "wasm32"))))]
+    let _ = memory;
+    Ok(match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(bytes)),
+        #[cfg(all(feature = "xz", not(target_arch = "wasm32")))]
+        Compression::Xz => Box::new(XzStreams {
+            rest: bytes,
+            current: None,
+            memory,
+        }),
+        #[cfg(all(feature = "zstd", not(target_arch = "wasm32")))]
+        Compression::Zstd => {
+            Box::new(zstd::stream::read::Decoder::with_buffer(bytes).map_err(Error::Decompress)?)
+        }
+        // No C toolchain for liblzma/libzstd there: pure-Rust zstd, no xz.
+        #[cfg(all(feature = "zstd", target_arch = "wasm32"))]
+        Compression::Zstd => Box::new(
+            ruzstd::decoding::StreamingDecoder::new(bytes)
+                .map_err(|e| Error::Decompress(std::io::Error::other(e)))?,
+        ),
+        Compression::Uncompressed => Box::new(bytes),
+        // Unknown data, or a backend compiled out (see the crate features).
+        #[allow(unreachable_patterns)]
+        _ => return Err(Error::UnsupportedCompression),
+    })
+}
+
+/// Concatenated .xz streams, each through liblzma's threaded decoder.
+///
+/// Unlike the single-threaded one, that decoder cannot be told to go on
+/// past the end of a stream, so each stream gets its own; the zero padding
+/// allowed between streams is skipped.
+#[cfg(all(feature = "xz", not(target_arch = "wasm32")))]
+struct XzStreams<'a> {
+    rest: &'a [u8],
+    current: Option<liblzma::bufread::XzDecoder<&'a [u8]>>,
+    /// Past this, liblzma drops back to fewer threads (or one).
+    memory: u64,
+}
+
+#[cfg(all(feature = "xz", not(target_arch = "wasm32")))]
+impl Read for XzStreams<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some(stream) = &mut self.current {
+                let n = stream.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    return Ok(n);
+                }
+                self.rest = stream.get_ref();
+                self.current = None;
+            }
+            let padding = self.rest.iter().take_while(|&&b| b == 0).count();
+            self.rest = &self.rest[padding..];
+            if self.rest.is_empty() {
+                return Ok(0);
+            }
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            let stream = liblzma::stream::MtStreamBuilder::new()
+                .threads(u32::try_from(threads).unwrap_or(u32::MAX))
+                .memlimit_threading(self.memory)
+                .memlimit_stop(u64::MAX)
+                .decoder()
+                .map_err(std::io::Error::other)?;
+            self.current = Some(liblzma::bufread::XzDecoder::new_stream(self.rest, stream));
+        }
+    }
+}
+
+/// Visit every cpio entry of an initramfs, across all of its segments.
+///
+/// Handles the common layout of uncompressed archives (e.g. early microcode)
+/// followed by a compressed main archive. `f` receives each header and a
+/// reader over the entry's data; anything it leaves unread is skipped.
+///
+/// Enforces the default [`ParserLimits`]; see [`walk_with_limits`].
+pub fn walk<F>(bytes: &[u8], f: F) -> Result<()>
+where
+    F: FnMut(&cpio::Header, &mut dyn Read) -> Result<()>,
+{
+    walk_with_limits(bytes, &ParserLimits::default(), f)
+}
+
+/// [`walk`], failing with [`Error::LimitExceeded`] past `limits`'
+/// entry count or total decompressed size, and decompressing within
+/// [`ParserLimits::max_memory`].
+pub fn walk_with_limits<F>(bytes: &[u8], limits: &ParserLimits, mut f: F) -> Result<()>
+where
+    F: FnMut(&cpio::Header, &mut dyn Read) -> Result<()>,
+{
+    let tripped = Cell::new(false);
+    let result = walk_segments(bytes, limits, &tripped, &mut f);
+    if tripped.get() {
+        return Err(Error::LimitExceeded {
+            what: "decompressed size",
+            limit: limits.max_decompressed_bytes,
+        });
+    }
+    result
+}
+
+fn walk_segments<F>(
+    bytes: &[u8],
+    limits: &ParserLimits,
+    tripped: &Cell<bool>,
+    f: &mut F,
+) -> Result<()>
+where
+    F: FnMut(&cpio::Header, &mut dyn Read) -> Result<()>,
+{
+    let mut entries = 0u64;
+    // Shared by all segments, so the budget is for the whole initramfs.
+    let mut budget = limits.max_decompressed_bytes;
+    let mut offset = 0usize;
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        let skip = rest.iter().take_while(|&&b| b == 0).count();
+        if skip == rest.len() {
+            break;
+        }
+        offset += skip;
+        let rest = &bytes[offset..];
+
+        let compression = detect(rest);
+        let _span = debug_span!("segment", offset, %compression).entered();
+        let t = Stopwatch::start();
+        let first = entries;
+        let input = decoder_within(compression, rest, limits.max_memory)?;
+        // Decompress on a second thread while this one parses, unless
+        // its read-ahead queue would not fit the memory budget.
+        #[cfg(not(target_arch = "wasm32"))]
+        let pipelined =
+            compression != Compression::Uncompressed && limits.max_memory >= pipeline::MEMORY;
+        #[cfg(target_arch = "wasm32")]
+        let pipelined = false;
+        let unpacked = match pipelined {
+            #[cfg(not(target_arch = "wasm32"))]
+            true => std::thread::scope(|s| {
+                let input = Limited::new(pipeline::spawn(s, input), budget, tripped);
+                walk_archive(input, offset, limits, &mut entries, f)
+            })?,
+            _ => walk_archive(
+                Limited::new(input, budget, tripped),
+                offset,
+                limits,
+                &mut entries,
+                f,
+            )?,
+        };
+        debug!(
+            entries = entries - first,
+            unpacked,
+            elapsed_ms = t.ms(),
+            "segment"
+        );
+        budget = budget.saturating_sub(unpacked);
+
+        if compression != Compression::Uncompressed {
+            // A compressed stream runs to the end of the section.
+            break;
+        }
+        let consumed = usize::try_from(unpacked).unwrap_or(usize::MAX);
+        if consumed == 0 {
+            return Err(Error::UnrecognizedInitramfs { offset });
+        }
+        offset += consumed;
+    }
+    Ok(())
+}
+
+/// Walk the cpio archive in `input`, returning how many bytes it spans.
+fn walk_archive<F>(
+    input: impl Read,
+    offset: usize,
+    limits: &ParserLimits,
+    entries: &mut u64,
+    f: &mut F,
+) -> Result<u64>
+where
+    F: FnMut(&cpio::Header, &mut dyn Read) -> Result<()>,
+{
+    let mut reader = cpio::Reader::new(input);
+    while let Some(header) = reader.next_entry().map_err(|e| Error::Segment {
+        offset,
+        source: Box::new(e),
+    })? {
+        *entries += 1;
+        ParserLimits::check("cpio entry count", *entries, limits.max_entries)?;
+        trace!(name = %header.name, mode = header.mode, size = header.filesize, "entry");
+        f(&header, &mut reader.data())?;
+    }
+    Ok(reader.position())
+}
+
+/// Compression of the main (last) archive, looking past uncompressed
+/// prefixes such as an early-microcode cpio.
+pub fn payload_compression(bytes: &[u8]) -> Result<Compression> {
+    Ok(segments(bytes)?
+        .last()
+        .map_or(Compression::Unknown, |s| s.compression))
+}
+
+/// What one archive of an initramfs is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Purpose {
+    /// An uncompressed cpio of CPU microcode (`kernel/x86/microcode/`)
+    /// for the kernel to load before anything else.
+    Microcode,
+    /// Anything else: the root file system, or other early files.
+    Main,
+}
+
+impl fmt::Display for Purpose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Purpose::Microcode => "microcode",
+            Purpose::Main => "main",
+        })
+    }
+}
+
+/// One of the concatenated archives of an initramfs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Segment {
+    pub offset: usize,
+    /// Bytes up to the next segment; a compressed segment, whose end is
+    /// only known once decompressed, runs to the end of the initramfs.
+    pub size: usize,
+    pub compression: Compression,
+    pub purpose: Purpose,
+}
+
+/// The archives of an initramfs in order, from their headers: each
+/// uncompressed cpio is walked (without reading file data) to find where
+/// it ends, and scanning stops at the first compressed or unrecognized
+/// segment. Zero padding between segments is skipped.
+pub fn segments(bytes: &[u8]) -> Result<Vec<Segment>> {
+    const MICROCODE: &str = "kernel/x86/microcode/";
+    let mut out = Vec::new();
+    let mut offset = 0usize;
+    while offset < bytes.len() {
+        offset += bytes[offset..].iter().take_while(|&&b| b == 0).count();
+        if offset == bytes.len() {
+            break;
+        }
+        let compression = detect(&bytes[offset..]);
+        if compression != Compression::Uncompressed {
+            out.push(Segment {
+                offset,
+                size: bytes.len() - offset,
+                compression,
+                purpose: Purpose::Main,
+            });
+            break;
+        }
+        // Microcode, and dracut's `early_cpio` marker, is all there is.
+        let (mut microcode, mut other) = (false, false);
+        let mut reader = cpio::Reader::new(&bytes[offset..]).single_archive();
+        while let Some(h) = reader.next_entry()? {
+            let name = h.name.trim_start_matches("./");
+            match () {
+                _ if h.is_dir() || name == "early_cpio" => {}
+                _ if name.starts_with(MICROCODE) => microcode = true,
+                _ => other = true,
+            }
+        }
+        let size = usize::try_from(reader.position()).unwrap_or(usize::MAX);
+        if size == 0 {
+            return Err(Error::UnrecognizedInitramfs { offset });
+        }
+        out.push(Segment {
+            offset,
+            size,
+            compression,
+            purpose: match microcode && !other {
+                true => Purpose::Microcode,
+                false => Purpose::Main,
+            },
+        });
+        offset = offset.saturating_add(size);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpio::S_IFREG;
+    use lowell_test_util::newc;
+    #[cfg(feature = "gzip")]
+    use std::io::Write;
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn walk_uncompressed_then_gzip_segment() {
+        let mut bytes = newc(&[("kernel/x86/microcode/GenuineIntel.bin", S_IFREG, b"ucode")]);
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&newc(&[("init", S_IFREG, b"#!/bin/sh\n")]))
+            .unwrap();
+        bytes.extend_from_slice(&gz.finish().unwrap());
+
+        let mut seen = Vec::new();
+        walk(&bytes, |h, data| {
+            let mut body = Vec::new();
+            data.read_to_end(&mut body)?;
+            seen.push((h.name.clone(), body.len()));
+            Ok(())
+        })
+        .expect("walk");
+        assert_eq!(
+            seen,
+            [
+                ("kernel/x86/microcode/GenuineIntel.bin".to_string(), 5),
+                ("init".to_string(), 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn payload_compression_skips_early_cpio() {
+        let mut bytes = newc(&[("early", S_IFREG, b"x")]);
+        bytes.extend_from_slice(&[0x28, 0xB5, 0x2F, 0xFD, 0, 0]);
+        assert_eq!(payload_compression(&bytes).unwrap(), Compression::Zstd);
+        let plain = newc(&[("only", S_IFREG, b"y")]);
+        assert_eq!(
+            payload_compression(&plain).unwrap(),
+            Compression::Uncompressed
+        );
+    }
+
+    #[test]
+    fn segments_by_offset_and_purpose() {
+        use crate::cpio::S_IFDIR;
+        let mut bytes = newc(&[
+            ("early_cpio", S_IFREG, b"1"),
+            ("kernel/x86/microcode", S_IFDIR, b""),
+            ("kernel/x86/microcode/AuthenticAMD.bin", S_IFREG, b"ucode"),
+        ]);
+        let early = bytes.len();
+        bytes.extend([0; 4]);
+        bytes.extend(newc(&[("kernel/firmware/acpi/ssdt.aml", S_IFREG, b"x")]));
+        let main = bytes.len();
+        bytes.extend_from_slice(&[0x28, 0xB5, 0x2F, 0xFD, 0, 0]);
+
+        let segments = segments(&bytes).unwrap();
+        let summary: Vec<_> = segments
+            .iter()
+            .map(|s| (s.offset, s.compression, s.purpose))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, Compression::Uncompressed, Purpose::Microcode),
+                (early + 4, Compression::Uncompressed, Purpose::Main),
+                (main, Compression::Zstd, Purpose::Main),
+            ]
+        );
+        assert_eq!((segments[0].size, segments[2].size), (early, 6));
+        assert!(super::segments(b"").unwrap().is_empty());
+        let unknown = super::segments(b"junk").unwrap();
+        assert_eq!(unknown[0].compression, Compression::Unknown);
+    }
+
+    #[test]
+    fn walk_enforces_entry_and_size_limits() {
+        let bytes = newc(&[
+            ("a", S_IFREG, &[b'a'; 100]),
+            ("b", S_IFREG, b"b"),
+            ("c", S_IFREG, b"c"),
+        ]);
+        let limits = ParserLimits {
+            max_entries: 2,
+            ..Default::default()
+        };
+        let err = walk_with_limits(&bytes, &limits, |_, _| Ok(())).unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded { limit: 2, .. }));
+
+        let limits = ParserLimits {
+            max_decompressed_bytes: 150,
+            ..Default::default()
+        };
+        let err = walk_with_limits(&bytes, &limits, |_, data| {
+            data.read_to_end(&mut Vec::new())?;
+            Ok(())
+        })
+        .unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded { limit: 150, .. }));
+
+        walk_with_limits(&bytes, &ParserLimits::default(), |_, _| Ok(())).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "xz")]
+    fn walk_concatenated_xz_streams() {
+        use lowell_test_util::{compress, Compression as C};
+        // One archive split across two streams, with stream padding between.
+        let archive = newc(&[("a", S_IFREG, b"alpha"), ("b", S_IFREG, b"beta")]);
+        let (head, tail) = archive.split_at(archive.len() / 2);
+        let mut bytes = compress(head, C::Xz);
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&compress(tail, C::Xz));
+
+        let mut names = Vec::new();
+        walk(&bytes, |h, _| {
+            names.push(h.name.clone());
+            Ok(())
+        })
+        .expect("walk");
+        assert_eq!(names, ["a", "b"]);
+
+        // No memory to spare: one thread, no read-ahead, same entries.
+        let limits = ParserLimits {
+            max_memory: 0,
+            ..Default::default()
+        };
+        names.clear();
+        walk_with_limits(&bytes, &limits, |h, _| {
+            names.push(h.name.clone());
+            Ok(())
+        })
+        .expect("walk");
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn walk_rejects_unknown_data() {
+        assert!(walk(b"not an initramfs", |_, _| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,373 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Bare Linux kernel images.
+//!
+//! - **bzImage** (x86): `HdrS` at 0x202; the setup header points at the
+//!   version string (`kernel_version`, 0x20e), says whether the kernel is
+//!   64-bit (`xloadflags`, 0x236) and where the compressed payload is
+//!   (`payload_offset`/`payload_length`, 0x248/0x24c, from the end of the
+//!   `setup_sects` (0x1f1) real-mode sectors).
+//! - **ARM64 `Image`**: `ARM\x64` at 0x38; the version string sits in the
+//!   uncompressed image.
+//! - **EFI zboot** (`vmlinuz.efi` on arm64/riscv64/loongarch): a PE with
+//!   `zimg` at offset 4 wrapping a compressed `Image`; payload offset/size at
+//!   8/12 and the compression name at 24.
+//!
+//! bzImages and arm64 Images with an EFI stub also start with `MZ` (and
+//! have a PE header, see [`efi_stub`]), so check [`detect`] before
+//! treating a file as a PE/UKI.
+//!
+//! A kernel built with `CONFIG_IKCONFIG=y` carries its `.config`, gzipped,
+//! between the markers `IKCFG_ST` and `IKCFG_ED` in the kernel proper;
+//! [`config`] decompresses the image to find it.
+
+use super::initramfs::{self, Compression};
+use crate::error::{Error, Result};
+use crate::limits::{Limited, ParserLimits};
+use crate::timing::Stopwatch;
+use crate::trace::debug;
+use std::cell::Cell;
+use std::io::Read;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum KernelFormat {
+    #[cfg_attr(feature = "serde", serde(rename = "bzimage"))]
+    BzImage,
+    Arm64Image,
+    Zboot,
+}
+
+impl std::fmt::Display for KernelFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            KernelFormat::BzImage => "bzImage",
+            KernelFormat::Arm64Image => "arm64 Image",
+            KernelFormat::Zboot => "EFI zboot",
+        })
+    }
+}
+
+const ARM64_MAGIC: &[u8] = b"ARM\x64";
+const VERSION_PREFIX: &[u8] = b"Linux version ";
+/// `IKCFG_ST` and the start of a gzip member, as `extract-ikconfig` looks
+/// for it.
+const IKCONFIG_START: &[u8] = b"IKCFG_ST\x1f\x8b\x08";
+const IKCONFIG_END: &[u8] = b"IKCFG_ED";
+
+pub fn detect(bytes: &[u8]) -> Option<KernelFormat> {
+    if bytes.get(0x202..0x206) == Some(b"HdrS") {
+        Some(KernelFormat::BzImage)
+    } else if bytes.starts_with(b"MZ") && bytes.get(4..8) == Some(b"zimg") {
+        Some(KernelFormat::Zboot)
+    } else if bytes.get(0x38..0x3c) == Some(ARM64_MAGIC) {
+        Some(KernelFormat::Arm64Image)
+    } else {
+        None
+    }
+}
+
+/// Best-effort architecture from the image headers.
+pub fn arch(bytes: &[u8], format: KernelFormat) -> Option<&'static str> {
+    match format {
+        KernelFormat::BzImage => {
+            let xloadflags = u16::from_le_bytes(bytes.get(0x236..0x238)?.try_into().ok()?);
+            Some(if xloadflags & 1 != 0 {
+                "x86_64"
+            } else {
+                "i386"
+            })
+        }
+        KernelFormat::Arm64Image => Some("aarch64"),
+        KernelFormat::Zboot => crate::pe::PeFile::from_bytes(bytes.to_vec())
+            .and_then(|pe| pe.arch_summary())
+            .ok()
+            .map(|(arch, _)| arch),
+    }
+}
+
+/// `Linux version …` banner, if it can be found.
+///
+/// zboot payloads are decompressed to look for it (gzip/xz/zstd only),
+/// up to the default [`ParserLimits::max_decompressed_bytes`].
+pub fn version(bytes: &[u8], format: KernelFormat) -> Result<Option<String>> {
+    Ok(match format {
+        KernelFormat::BzImage => bzimage_version(bytes),
+        KernelFormat::Arm64Image => find_banner(bytes),
+        KernelFormat::Zboot => match zboot_payload(bytes) {
+            Some(payload) => decompress(payload)?.and_then(|image| find_banner(&image)),
+            None => None,
+        },
+    })
+}
+
+/// The `.config` the kernel was built with, if it embeds one
+/// (`CONFIG_IKCONFIG=y`; with `=m` it lives in the `configs` module).
+///
+/// bzImage and zboot payloads are decompressed to look for it
+/// (gzip/xz/zstd only), up to the default
+/// [`ParserLimits::max_decompressed_bytes`].
+pub fn config(bytes: &[u8], format: KernelFormat) -> Result<Option<String>> {
+    let payload = match format {
+        KernelFormat::Arm64Image => return ikconfig(bytes),
+        KernelFormat::BzImage => bzimage_payload(bytes),
+        KernelFormat::Zboot => zboot_payload(bytes),
+    };
+    match payload.map(decompress).transpose()?.flatten() {
+        Some(image) => ikconfig(&image),
+        None => Ok(None),
+    }
+}
+
+/// The gzipped config between the IKCONFIG markers in `image`, inflated.
+fn ikconfig(image: &[u8]) -> Result<Option<String>> {
+    let Some(start) = find(image, IKCONFIG_START) else {
+        return Ok(None);
+    };
+    // The gzip member follows the 8-byte marker.
+    let data = &image[start + 8..];
+    let Some(end) = find(data, IKCONFIG_END) else {
+        return Ok(None);
+    };
+    let mut config = String::new();
+    initramfs::decoder(Compression::Gzip, &data[..end])?
+        .read_to_string(&mut config)
+        .map_err(Error::Decompress)?;
+    Ok(Some(config))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// A kernel payload decompressed, if lowell can (gzip/xz/zstd).
+///
+/// The build appends the uncompressed size after the compressed stream,
+/// which a decoder can take for the start of another one: an error after
+/// some output keeps that output.
+fn decompress(payload: &[u8]) -> Result<Option<Vec<u8>>> {
+    let compression = initramfs::detect(payload);
+    if !matches!(
+        compression,
+        Compression::Gzip | Compression::Xz | Compression::Zstd
+    ) {
+        return Ok(None);
+    }
+    let t = Stopwatch::start();
+    let limit = ParserLimits::default().max_decompressed_bytes;
+    let tripped = Cell::new(false);
+    let mut image = Vec::new();
+    let decoder = initramfs::decoder(compression, payload)?;
+    if let Err(e) = Limited::new(decoder, limit, &tripped).read_to_end(&mut image) {
+        if tripped.get() {
+            return Err(Error::LimitExceeded {
+                what: "decompressed size",
+                limit,
+            });
+        }
+        if image.is_empty() {
+            return Err(Error::Decompress(e));
+        }
+        debug!(error = %e, "trailing data after the kernel payload");
+    }
+    debug!(
+        compressed = payload.len(),
+        size = image.len(),
+        elapsed_ms = t.ms(),
+        "kernel_decompress"
+    );
+    Ok(Some(image))
+}
+
+/// Whether the image can be started by UEFI firmware directly: `MZ`, and
+/// a PE signature where `e_lfanew` (0x3c) points.
+pub fn efi_stub(bytes: &[u8]) -> bool {
+    let pe = bytes
+        .get(0x3c..0x40)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize);
+    bytes.starts_with(b"MZ")
+        && pe.and_then(|at| bytes.get(at..at.checked_add(4)?)) == Some(b"PE\0\0")
+}
+
+/// How the kernel proper is compressed in the image: `gzip`, `xz`,
+/// `zstd`, `lzma`, `lz4`, `lzo` or `bzip2`; `uncompressed` for an arm64
+/// `Image`. `None` if it cannot be told.
+pub fn compression(bytes: &[u8], format: KernelFormat) -> Option<&'static str> {
+    let payload = match format {
+        KernelFormat::Arm64Image => return Some("uncompressed"),
+        // The zboot header names it, as `CONFIG_EFI_ZBOOT` spells it.
+        KernelFormat::Zboot => {
+            let name = bytes.get(24..56)?;
+            let name = &name[..name.iter().position(|&b| b == 0)?];
+            return match name {
+                b"gzip" => Some("gzip"),
+                b"xzkern" | b"xz" => Some("xz"),
+                b"zstd22" | b"zstd" => Some("zstd"),
+                b"lzma" => Some("lzma"),
+                b"lz4" => Some("lz4"),
+                b"lzo" => Some("lzo"),
+                b"bzip2" => Some("bzip2"),
+                _ => None,
+            };
+        }
+        KernelFormat::BzImage => bzimage_payload(bytes)?,
+    };
+    match payload {
+        [0x1f, 0x8b, ..] => Some("gzip"),
+        [0xfd, b'7', b'z', b'X', b'Z', 0, ..] => Some("xz"),
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => Some("zstd"),
+        [0x5d, 0, 0, ..] => Some("lzma"),
+        [0x02, 0x21, 0x4c, 0x18, ..] => Some("lz4"),
+        [0x89, b'L', b'Z', b'O', ..] => Some("lzo"),
+        [b'B', b'Z', b'h', ..] => Some("bzip2"),
+        _ => None,
+    }
+}
+
+fn bzimage_payload(bytes: &[u8]) -> Option<&[u8]> {
+    let u32_at = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+    };
+    let setup = match *bytes.get(0x1f1)? {
+        0 => 4,
+        n => usize::from(n),
+    };
+    let start = (setup + 1) * 512 + u32_at(0x248)?;
+    bytes.get(start..start.checked_add(u32_at(0x24c)?)?)
+}
+
+fn zboot_payload(bytes: &[u8]) -> Option<&[u8]> {
+    let u32_at = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+    };
+    let (off, size) = (u32_at(8)?, u32_at(12)?);
+    bytes.get(off..off.checked_add(size)?)
+}
+
+fn bzimage_version(bytes: &[u8]) -> Option<String> {
+    let ptr = u16::from_le_bytes(bytes.get(0x20e..0x210)?.try_into().ok()?) as usize;
+    if ptr == 0 {
+        return None;
+    }
+    let s = bytes.get(ptr + 0x200..)?;
+    let end = s.iter().position(|&b| b == 0).unwrap_or(s.len()).min(256);
+    let v = String::from_utf8_lossy(&s[..end]).trim().to_string();
+    (!v.is_empty()).then_some(v)
+}
+
+fn find_banner(bytes: &[u8]) -> Option<String> {
+    let start = bytes
+        .windows(VERSION_PREFIX.len())
+        .position(|w| w == VERSION_PREFIX)?
+        + VERSION_PREFIX.len();
+    let rest = &bytes[start..bytes.len().min(start + 256)];
+    let end = rest
+        .iter()
+        .position(|&b| b == 0 || b == b'\n')
+        .unwrap_or(rest.len());
+    Some(String::from_utf8_lossy(&rest[..end]).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_formats_and_versions() {
+        let mut bz = vec![0u8; 0x400];
+        bz[..2].copy_from_slice(b"MZ");
+        bz[0x202..0x206].copy_from_slice(b"HdrS");
+        bz[0x20e..0x210].copy_from_slice(&0x100u16.to_le_bytes());
+        bz[0x236] = 1;
+        bz[0x300..0x30a].copy_from_slice(b"6.11.0 (x)");
+        assert_eq!(detect(&bz), Some(KernelFormat::BzImage));
+        assert_eq!(arch(&bz, KernelFormat::BzImage), Some("x86_64"));
+        assert_eq!(
+            version(&bz, KernelFormat::BzImage).unwrap().as_deref(),
+            Some("6.11.0 (x)")
+        );
+        assert!(!efi_stub(&bz));
+        assert_eq!(compression(&bz, KernelFormat::BzImage), None);
+        // One setup sector; a zstd payload 0x10 into the protected-mode code.
+        bz[0x1f1] = 1;
+        bz[0x248..0x24c].copy_from_slice(&0x10u32.to_le_bytes());
+        bz[0x24c..0x250].copy_from_slice(&8u32.to_le_bytes());
+        bz.resize(0x800, 0);
+        bz[0x410..0x414].copy_from_slice(&[0x28, 0xb5, 0x2f, 0xfd]);
+        assert_eq!(compression(&bz, KernelFormat::BzImage), Some("zstd"));
+        bz[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        bz[0x80..0x84].copy_from_slice(b"PE\0\0");
+        assert!(efi_stub(&bz));
+
+        let mut image = vec![0u8; 0x100];
+        image[0x38..0x3c].copy_from_slice(ARM64_MAGIC);
+        image.extend_from_slice(b"Linux version 6.12.1-arm64 (gcc)\n\0");
+        assert_eq!(detect(&image), Some(KernelFormat::Arm64Image));
+        assert_eq!(
+            version(&image, KernelFormat::Arm64Image)
+                .unwrap()
+                .as_deref(),
+            Some("6.12.1-arm64 (gcc)")
+        );
+
+        assert_eq!(
+            compression(&image, KernelFormat::Arm64Image),
+            Some("uncompressed")
+        );
+
+        let mut zboot = vec![0u8; 0x80];
+        zboot[..2].copy_from_slice(b"MZ");
+        zboot[4..8].copy_from_slice(b"zimg");
+        zboot[24..30].copy_from_slice(b"zstd22");
+        assert_eq!(detect(&zboot), Some(KernelFormat::Zboot));
+        assert_eq!(compression(&zboot, KernelFormat::Zboot), Some("zstd"));
+
+        assert_eq!(detect(b"070701"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn embedded_config_is_found_in_the_kernel_proper() {
+        use lowell_test_util::{compress, Compression as C};
+        let config_of = |bytes: &[u8], format| super::config(bytes, format).unwrap();
+        let config = "CONFIG_SECURITY_LOCKDOWN_LSM=y\n# CONFIG_DEBUG_FS is not set\n";
+        let mut image = vec![0u8; 0x100];
+        image[0x38..0x3c].copy_from_slice(ARM64_MAGIC);
+        image.extend_from_slice(b"IKCFG_ST");
+        image.extend(compress(config.as_bytes(), C::Gzip));
+        image.extend_from_slice(b"IKCFG_ED");
+        assert_eq!(
+            config_of(&image, KernelFormat::Arm64Image).as_deref(),
+            Some(config)
+        );
+        assert_eq!(config_of(&image[..0x100], KernelFormat::Arm64Image), None);
+
+        // The build appends the image size to the compressed payload.
+        let mut payload = compress(&image, C::Gzip);
+        payload.extend((image.len() as u32).to_le_bytes());
+        let mut zboot = vec![0u8; 0x80];
+        zboot[..2].copy_from_slice(b"MZ");
+        zboot[4..8].copy_from_slice(b"zimg");
+        zboot[8..12].copy_from_slice(&0x80u32.to_le_bytes());
+        zboot[12..16].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        zboot.extend(&payload);
+        assert_eq!(
+            config_of(&zboot, KernelFormat::Zboot).as_deref(),
+            Some(config)
+        );
+
+        let mut bz = vec![0u8; 0x400];
+        bz[0x202..0x206].copy_from_slice(b"HdrS");
+        bz[0x1f1] = 1;
+        bz[0x24c..0x250].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        bz.extend(&payload);
+        assert_eq!(
+            config_of(&bz, KernelFormat::BzImage).as_deref(),
+            Some(config)
+        );
+    }
+}
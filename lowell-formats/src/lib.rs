@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Parsers for boot artifacts, and the types every lowell crate shares.
+//!
+//! This is the bottom of the stack: PE/UKI, cpio, initramfs, kernel image
+//! and os-release parsing, plus [`Error`], [`limits`], [`cancel`],
+//! [`progress`] and [`digest`]. It has no build machinery; take it with
+//! `default-features = false` for a parser-only dependency.
+//! `lowell-inspect` and `lowell-build` sit on top, and `lowell-core`
+//! re-exports all three.
+
+pub mod authenticode;
+pub mod bmp;
+pub mod cancel;
+pub mod cpio;
+pub mod digest;
+pub mod error;
+pub mod fdt;
+pub mod initramfs;
+pub mod kernel;
+pub mod limits;
+pub mod mapped;
+pub mod microcode;
+pub mod osrel;
+pub mod pe;
+#[cfg(not(target_arch = "wasm32"))]
+mod pipeline;
+pub mod progress;
+pub mod sbat;
+pub mod sniff;
+pub mod tpm;
+pub mod unpack;
+
+// Plumbing for lowell-inspect and lowell-build, not public API.
+#[doc(hidden)]
+#[cfg(feature = "tokio")]
+pub mod task;
+#[doc(hidden)]
+pub mod timing;
+#[doc(hidden)]
+pub mod trace;
+#[doc(hidden)]
+pub mod uring;
+
+pub use error::{Error, Result};
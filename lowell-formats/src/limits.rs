@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Upper bounds on what the parsers will accept from an image.
+//!
+//! Images are untrusted input: a crafted UKI can claim a multi-gigabyte
+//! section, and a small initramfs can decompress to terabytes or hold
+//! millions of empty entries. Every parser checks these limits and fails
+//! with [`Error::LimitExceeded`] (`E_LIMIT_EXCEEDED`) instead.
+//!
+//! The defaults are far above anything a real boot image needs; lower them
+//! when inspecting images from untrusted sources:
+//!
+//! ```
+//! # use lowell_formats::limits::ParserLimits;
+//! let mut limits = ParserLimits::default();
+//! limits.max_decompressed_bytes = 512 << 20;
+//! ```
+
+use crate::error::{Error, Result};
+use std::cell::Cell;
+use std::io::{self, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParserLimits {
+    /// Largest PE section (raw size in the file), in bytes.
+    pub max_section_size: u64,
+    /// Most cpio entries across all segments of an initramfs.
+    pub max_entries: u64,
+    /// Most bytes produced by decompressing an initramfs or zboot payload.
+    pub max_decompressed_bytes: u64,
+    /// Working memory for decompression, in bytes. Under it, xz decodes on
+    /// fewer threads (each holds whole blocks) and small budgets decode on
+    /// the parsing thread, without a read-ahead queue. Never an error.
+    pub max_memory: u64,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self {
+            max_section_size: 1 << 30,
+            max_entries: 1 << 20,
+            max_decompressed_bytes: 4 << 30,
+            max_memory: 512 << 20,
+        }
+    }
+}
+
+impl ParserLimits {
+    /// No limits at all (trusted input only).
+    pub fn unlimited() -> Self {
+        Self {
+            max_section_size: u64::MAX,
+            max_entries: u64::MAX,
+            max_decompressed_bytes: u64::MAX,
+            max_memory: u64::MAX,
+        }
+    }
+
+    /// `Err(LimitExceeded)` if `value` is over `limit`.
+    pub(crate) fn check(what: &'static str, value: u64, limit: u64) -> Result<()> {
+        if value > limit {
+            return Err(Error::LimitExceeded { what, limit });
+        }
+        Ok(())
+    }
+}
+
+/// A reader that fails once more than `limit` bytes have come through.
+///
+/// The failure surfaces as whatever error the consumer makes of an I/O
+/// error, so it also sets `tripped`; callers check that to report
+/// [`Error::LimitExceeded`] instead.
+pub(crate) struct Limited<'t, R> {
+    inner: R,
+    remaining: u64,
+    tripped: &'t Cell<bool>,
+}
+
+impl<'t, R: Read> Limited<'t, R> {
+    pub(crate) fn new(inner: R, limit: u64, tripped: &'t Cell<bool>) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+            tripped,
+        }
+    }
+}
+
+impl<R: Read> Read for Limited<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        match self.remaining.checked_sub(n as u64) {
+            Some(rest) => {
+                self.remaining = rest;
+                Ok(n)
+            }
+            None => {
+                self.tripped.set(true);
+                Err(io::Error::other("decompressed size limit exceeded"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limited_reader_stops_past_the_limit() {
+        let tripped = Cell::new(false);
+        let mut out = Vec::new();
+        Limited::new(&[0u8; 64][..], 64, &tripped)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out.len(), 64);
+        assert!(!tripped.get());
+
+        assert!(Limited::new(&[0u8; 100][..], 64, &tripped)
+            .read_to_end(&mut Vec::new())
+            .is_err());
+        assert!(tripped.get());
+    }
+}
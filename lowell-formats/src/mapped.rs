@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! File contents without reading them onto the heap.
+//!
+//! [`Mapped`] maps a file read-only on native targets: pages load as they
+//! are touched and the kernel can drop them again under memory pressure,
+//! so hashing or walking a multi-gigabyte image keeps peak memory flat.
+//! wasm32 has no mmap; there the file is read.
+
+use crate::error::{IoResultExt, Result};
+#[cfg(not(target_arch = "wasm32"))]
+use memmap2::Mmap;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Read-only bytes of a file, mapped or owned.
+#[derive(Debug)]
+pub struct Mapped(Inner);
+
+#[derive(Debug)]
+enum Inner {
+    Owned(Box<[u8]>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Map(Mmap),
+}
+
+impl Mapped {
+    /// Map `path` read-only.
+    ///
+    /// The file must not be truncated or rewritten in place while the
+    /// `Mapped` is alive (tools that replace files by renaming, like
+    /// `lowell build`, are fine).
+    pub fn open(path: &Path) -> Result<Self> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let file = std::fs::File::open(path).at("open", path)?;
+            // Mapping an empty file fails on some platforms.
+            if file.metadata().at("stat", path)?.len() == 0 {
+                return Ok(Self::from(Vec::new()));
+            }
+            // SAFETY: the map is read-only and private to this value; see
+            // the doc comment for the caller's side of the contract.
+            let map = unsafe { Mmap::map(&file) }.at("map", path)?;
+            Ok(Self(Inner::Map(map)))
+        }
+        #[cfg(target_arch = "wasm32")]
+        Ok(Self::from(std::fs::read(path).at("read", path)?))
+    }
+}
+
+impl From<Vec<u8>> for Mapped {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(Inner::Owned(bytes.into_boxed_slice()))
+    }
+}
+
+impl Deref for Mapped {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Inner::Owned(b) => b,
+            #[cfg(not(target_arch = "wasm32"))]
+            Inner::Map(m) => m,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_files_including_empty_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let (full, empty) = (dir.path().join("full"), dir.path().join("empty"));
+        std::fs::write(&full, b"070701").unwrap();
+        std::fs::write(&empty, b"").unwrap();
+        assert_eq!(&*Mapped::open(&full).unwrap(), b"070701");
+        assert!(Mapped::open(&empty).unwrap().is_empty());
+        assert!(Mapped::open(&dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! x86 CPU microcode as the kernel loads it early: from
+//! `kernel/x86/microcode/<vendor>.bin` in an uncompressed cpio archive at
+//! the start of the initramfs (or in a UKI's `.ucode` section).
+//!
+//! [`revision`] finds the newest update in such a file for one CPU,
+//! identified by its CPUID signature ([`signature`]):
+//!
+//! * **Intel**: a sequence of updates, each a 48-byte header (revision at
+//!   4, processor signature at 12, data and total size at 28 and 32) and
+//!   an optional extended signature table after the data. The platform
+//!   flags are not matched, so with several platform variants the newest
+//!   revision wins.
+//! * **AMD**: one or more containers (magic `DMA\0`), each an equivalence
+//!   table mapping CPU signatures to 16-bit ids, then patches whose header
+//!   carries the revision (`patch_id`) and the id it applies to.
+
+use crate::cpio;
+use crate::error::Result;
+use std::io::Read;
+
+/// The CPU vendors with early-loadable microcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vendor {
+    Intel,
+    Amd,
+}
+
+impl Vendor {
+    /// By CPUID vendor string (`vendor_id` in `/proc/cpuinfo`).
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "GenuineIntel" => Some(Vendor::Intel),
+            "AuthenticAMD" => Some(Vendor::Amd),
+            _ => None,
+        }
+    }
+
+    /// Where the kernel looks for this vendor's file in the early archive.
+    pub fn cpio_path(self) -> &'static str {
+        match self {
+            Vendor::Intel => "kernel/x86/microcode/GenuineIntel.bin",
+            Vendor::Amd => "kernel/x86/microcode/AuthenticAMD.bin",
+        }
+    }
+}
+
+/// The CPUID leaf 1 signature for a CPU as `/proc/cpuinfo` describes it
+/// (display family and model, stepping).
+pub fn signature(family: u32, model: u32, stepping: u32) -> u32 {
+    let (base_family, ext_family) = match family {
+        f if f >= 0xf => (0xf, f - 0xf),
+        f => (f, 0),
+    };
+    let (base_model, ext_model) = match base_family {
+        6 | 0xf => (model & 0xf, model >> 4),
+        _ => (model, 0),
+    };
+    ext_family << 20 | ext_model << 16 | base_family << 8 | base_model << 4 | stepping
+}
+
+/// `vendor`'s microcode file in the uncompressed cpio archive(s) at the
+/// start of `initrd`, if there is one.
+pub fn from_cpio(initrd: &[u8], vendor: Vendor) -> Result<Option<Vec<u8>>> {
+    let mut reader = cpio::Reader::new(initrd);
+    while let Some(header) = reader.next_entry()? {
+        if header.name.trim_start_matches("./") == vendor.cpio_path() {
+            let mut data = Vec::new();
+            reader.data().read_to_end(&mut data)?;
+            return Ok(Some(data));
+        }
+    }
+    Ok(None)
+}
+
+/// The newest revision in `blob` for the CPU with `signature`.
+pub fn revision(blob: &[u8], vendor: Vendor, signature: u32) -> Option<u32> {
+    match vendor {
+        Vendor::Intel => intel(blob, signature),
+        Vendor::Amd => amd(blob, signature),
+    }
+}
+
+fn u32_at(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u16_at(b: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(b.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn intel(mut blob: &[u8], signature: u32) -> Option<u32> {
+    const HEADER: usize = 48;
+    let mut newest = None;
+    while blob.len() >= HEADER {
+        let revision = u32_at(blob, 4)?;
+        let data = match u32_at(blob, 28)? {
+            0 => 2000,
+            n => n as usize,
+        };
+        let total = match u32_at(blob, 32)? {
+            0 => 2048,
+            n => n as usize,
+        };
+        if u32_at(blob, 0)? != 1 || total < HEADER + data || total > blob.len() {
+            break;
+        }
+        let mut sigs = vec![u32_at(blob, 12)?];
+        // Extended signatures: a count, then 12-byte entries after a
+        // 20-byte table header.
+        let ext = &blob[HEADER + data..total];
+        if let Some(count) = u32_at(ext, 0) {
+            sigs.extend((0..count as usize).filter_map(|i| u32_at(ext, 20 + i * 12)));
+        }
+        if sigs.contains(&signature) {
+            newest = newest.max(Some(revision));
+        }
+        blob = &blob[total..];
+    }
+    newest
+}
+
+fn amd(mut blob: &[u8], signature: u32) -> Option<u32> {
+    const MAGIC: u32 = 0x0041_4d44;
+    let mut newest = None;
+    while u32_at(blob, 0) == Some(MAGIC) {
+        // The equivalence table: type 0.
+        if u32_at(blob, 4)? != 0 {
+            break;
+        }
+        let len = u32_at(blob, 8)? as usize;
+        let table = blob.get(12..12 + len)?;
+        let id = table
+            .chunks_exact(16)
+            .find(|e| u32_at(e, 0) == Some(signature))
+            .and_then(|e| u16_at(e, 12));
+        blob = &blob[12 + len..];
+        // Patches (type 1) until the next container or the end.
+        while u32_at(blob, 0) == Some(1) {
+            let len = u32_at(blob, 4)? as usize;
+            let patch = blob.get(8..8 + len)?;
+            if id.is_some() && u16_at(patch, 24) == id {
+                newest = newest.max(u32_at(patch, 4));
+            }
+            blob = &blob[8 + len..];
+        }
+    }
+    newest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intel_update(revision: u32, sig: u32, extended: &[u32]) -> Vec<u8> {
+        let data = 16;
+        let ext_len = if extended.is_empty() {
+            0
+        } else {
+            20 + 12 * extended.len()
+        };
+        let mut b = vec![0u8; 48 + data + ext_len];
+        b[0..4].copy_from_slice(&1u32.to_le_bytes());
+        b[4..8].copy_from_slice(&revision.to_le_bytes());
+        b[12..16].copy_from_slice(&sig.to_le_bytes());
+        b[28..32].copy_from_slice(&(data as u32).to_le_bytes());
+        let total = b.len() as u32;
+        b[32..36].copy_from_slice(&total.to_le_bytes());
+        if !extended.is_empty() {
+            let at = 48 + data;
+            b[at..at + 4].copy_from_slice(&(extended.len() as u32).to_le_bytes());
+            for (i, s) in extended.iter().enumerate() {
+                let e = at + 20 + i * 12;
+                b[e..e + 4].copy_from_slice(&s.to_le_bytes());
+            }
+        }
+        b
+    }
+
+    #[test]
+    fn signatures_from_cpuinfo() {
+        // Skylake-SP stepping 4; Zen 3 (family 25, model 33, stepping 0).
+        assert_eq!(signature(6, 85, 4), 0x50654);
+        assert_eq!(signature(25, 33, 0), 0xa20f10);
+    }
+
+    #[test]
+    fn newest_matching_intel_update() {
+        let mut blob = intel_update(0x100, 0x50654, &[]);
+        blob.extend(intel_update(0x2007006, 0x50657, &[0x50654]));
+        blob.extend(intel_update(0x300, 0x906ea, &[]));
+        assert_eq!(revision(&blob, Vendor::Intel, 0x50654), Some(0x2007006));
+        assert_eq!(revision(&blob, Vendor::Intel, 0x906ea), Some(0x300));
+        assert_eq!(revision(&blob, Vendor::Intel, 0x1), None);
+    }
+
+    #[test]
+    fn amd_patch_for_the_equivalent_id() {
+        let mut blob = Vec::new();
+        blob.extend(0x0041_4d44u32.to_le_bytes());
+        blob.extend(0u32.to_le_bytes());
+        blob.extend(32u32.to_le_bytes());
+        let mut entry = [0u8; 16];
+        entry[0..4].copy_from_slice(&0xa20f10u32.to_le_bytes());
+        entry[12..14].copy_from_slice(&0xa210u16.to_le_bytes());
+        blob.extend(entry);
+        blob.extend([0u8; 16]);
+        for (patch_id, equiv) in [(0x0a201016u32, 0xa210u16), (0x0a201099, 0xa211)] {
+            let mut patch = vec![0u8; 64];
+            patch[4..8].copy_from_slice(&patch_id.to_le_bytes());
+            patch[24..26].copy_from_slice(&equiv.to_le_bytes());
+            blob.extend(1u32.to_le_bytes());
+            blob.extend((patch.len() as u32).to_le_bytes());
+            blob.extend(patch);
+        }
+        assert_eq!(revision(&blob, Vendor::Amd, 0xa20f10), Some(0x0a201016));
+        assert_eq!(revision(&blob, Vendor::Amd, 0xa50f00), None);
+    }
+}
@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+use crate::error::Result;
+use crate::pe::PeFile;
+use rs_release::parse_os_release_str;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct OsRelease {
+    pub name: Option<String>,
+    pub id: Option<String>,
+    pub version_id: Option<String>,
+    /// `IMAGE_VERSION`, for image-based OSes.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub image_version: Option<String>,
+    /// Every assignment, by key (`IMAGE_ID`, `VARIANT_ID`, …), unquoted.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub all: BTreeMap<String, String>,
+}
+
+impl OsRelease {
+    /// The value of `key`, if set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.all.get(key).map(String::as_str)
+    }
+
+    /// `IMAGE_ID`: which image of an image-based OS this is.
+    pub fn image_id(&self) -> Option<&str> {
+        self.get("IMAGE_ID")
+    }
+
+    /// `VARIANT_ID`, e.g. `server` or `workstation`.
+    pub fn variant_id(&self) -> Option<&str> {
+        self.get("VARIANT_ID")
+    }
+
+    /// `BUILD_ID`: the build of the OS image.
+    pub fn build_id(&self) -> Option<&str> {
+        self.get("BUILD_ID")
+    }
+}
+
+pub fn read_os_release_from_str(text: &str) -> Result<Option<OsRelease>> {
+    let m = parse_os_release_str(text)?;
+    let name = m
+        .get("PRETTY_NAME")
+        .cloned()
+        .or_else(|| m.get("NAME").cloned());
+    let id = m.get("ID").cloned();
+    let version_id = m.get("VERSION_ID").cloned();
+    let image_version = m.get("IMAGE_VERSION").cloned();
+    let all = m.into_iter().map(|(k, v)| (k.into_owned(), v)).collect();
+    Ok(Some(OsRelease {
+        name,
+        id,
+        version_id,
+        image_version,
+        all,
+    }))
+}
+
+pub fn read_os_release(pef: &PeFile) -> Result<Option<OsRelease>> {
+    let Some(text) = pef.read_text(".osrel")? else {
+        return Ok(None);
+    };
+    read_os_release_from_str(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_key_is_kept() {
+        let os = read_os_release_from_str(
+            "NAME=\"Fedora Linux\"\nID=fedora\nVARIANT_ID=iot\n\
+             IMAGE_ID=fedora-iot\nIMAGE_VERSION=41.20241016.0\nBUILD_ID=\"20241016.0\"\n",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(os.name.as_deref(), Some("Fedora Linux"));
+        assert_eq!(os.image_id(), Some("fedora-iot"));
+        assert_eq!(os.variant_id(), Some("iot"));
+        assert_eq!(os.build_id(), Some("20241016.0"));
+        assert_eq!(os.image_version.as_deref(), Some("41.20241016.0"));
+        assert_eq!(os.all.len(), 6);
+        assert_eq!(os.get("VERSION_ID"), None);
+    }
+}
@@ -0,0 +1,912 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! PE/COFF helpers for Unified Kernel Images (UKI)
+//!
+//! Read-only introspection of PE/EFI images (UKIs) with small, ergonomic helpers.
+//! We **own** the file bytes and parse them with `goblin` once, keeping the
+//! few header facts we need; methods then slice into `self.data`, so the
+//! public API stays lifetime-free. [`PeFile::from_path_mmap`] maps the file
+//! instead of reading it, for large images.
+//!
+//! [`PeFile::section`] is the main entry point for section data: it returns
+//! a [`Section`] (header fields plus borrowed bytes) or a typed error,
+//! [`Error::SectionMissing`] or [`Error::SectionOutOfBounds`].
+//!
+//! ### UKI sections you’ll typically care about
+//! - `.linux`   — kernel image (Image/bzImage)
+//! - `.initrd`  — initramfs blob (often gzip/xz/zstd; can be concatenated cpio)
+//! - `.cmdline` — kernel command line (ASCII/UTF-8, NUL-padded)
+//! - `.osrel`   — os-release contents (text)
+//! - `.sbat`    — SBAT CSV (shim)
+//! - `.sdmagic` — systemd-stub marker
+//!
+//! ### Certificates / Authenticode (Secure Boot)
+//! - Presence is indicated by the **Security** data directory (index 4).
+//! - In PE/COFF, **only** this directory uses a **file offset** (not an RVA).
+//! - `goblin` already parses certificates into `pe.certificates`, so you can
+//!   inspect counts, lengths, types, and get the raw blobs directly
+//!   (`certificates` feature; without it only the directory is checked).
+//! - We DO NOT verify signatures here; presence ≠ validity.
+//!
+//! [`PeFile::authenticode`] computes the image hash a signature covers,
+//! which is also what firmware extends into PCR 4 when it loads the image.
+//!
+//! ### Headers only
+//! [`PeHeaders::read`] fetches the headers, section table and the first
+//! [`SECTION_HEAD`] bytes of each section with a handful of ranged reads,
+//! for scans that only need the layout and each payload's format.
+
+use crate::digest::{Algorithm, Digest, Hasher};
+use crate::error::{Error, IoResultExt, Result};
+use crate::limits::ParserLimits;
+use crate::mapped::Mapped;
+use crate::timing::Stopwatch;
+use crate::trace::{debug, debug_span, trace};
+use goblin::pe::header::Header;
+use goblin::pe::optional_header;
+use goblin::pe::section_table::SectionTable;
+use goblin::pe::{options::ParseOptions, PE};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+#[cfg(feature = "certificates")]
+use std::ops::Range;
+use std::path::Path;
+
+/// Bytes of each section [`PeHeaders::read`] fetches: enough to tell a
+/// payload's format, and to hold a `.cmdline` or `.osrel` whole.
+pub const SECTION_HEAD: usize = 4096;
+/// First read of [`PeHeaders::read`]; headers rarely go past it.
+const HEADER_PROBE: usize = 4096;
+const SECTION_HEADER_LEN: usize = 40;
+
+/// An owning wrapper around a PE/EFI image (UKI).
+///
+/// Holds the file bytes plus the header facts parsed from them once at
+/// construction, and returns borrowed slices tied to `&self`. This avoids
+/// lifetimes in the public API and side-steps self-referential types.
+#[derive(Debug)]
+pub struct PeFile {
+    /// Entire image bytes (owned or mapped).
+    data: Mapped,
+    machine: u16,
+    is_64: bool,
+    layout: Layout,
+    sections: Vec<SectionHeader>,
+    /// The Security data directory is non-empty.
+    #[cfg(not(feature = "certificates"))]
+    signed: bool,
+    #[cfg(feature = "certificates")]
+    certificates: Vec<Certificate>,
+}
+
+/// A section header, as far as we use it.
+#[derive(Debug)]
+struct SectionHeader {
+    /// Up to the first NUL; rendered lossily if not UTF-8.
+    name: String,
+    offset: usize,
+    size: usize,
+    virtual_address: usize,
+    virtual_size: usize,
+}
+
+/// The optional header fields that place sections in memory and the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Layout {
+    /// `SectionAlignment`: each section's virtual address is a multiple.
+    pub section_alignment: u32,
+    /// `FileAlignment`: each section's raw data offset is a multiple.
+    pub file_alignment: u32,
+    /// `SizeOfImage`: the loaded image, every section included.
+    pub size_of_image: u32,
+}
+
+impl Layout {
+    fn from_optional_header(header: Option<&optional_header::OptionalHeader>) -> Self {
+        header.map_or_else(Layout::default, |h| Layout {
+            section_alignment: h.windows_fields.section_alignment,
+            file_alignment: h.windows_fields.file_alignment,
+            size_of_image: h.windows_fields.size_of_image,
+        })
+    }
+}
+
+/// The optional header `CheckSum` and the one computed over the image
+/// ([`PeFile::checksum`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct Checksum {
+    pub stored: u32,
+    pub computed: u32,
+    /// `stored == computed`. Most EFI images store 0, which firmware that
+    /// checks at all takes as "not set".
+    pub matches: bool,
+}
+
+/// A section of a [`PeFile`]: its header fields and borrowed raw bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Section<'a> {
+    /// Up to the first NUL; rendered lossily if not UTF-8.
+    pub name: &'a str,
+    /// File offset of the raw data (`PointerToRawData`).
+    pub offset: usize,
+    /// Raw size in the file (`SizeOfRawData`), file-alignment padding included.
+    pub size: usize,
+    /// Where it is loaded, relative to the image base (`VirtualAddress`).
+    pub virtual_address: usize,
+    /// Size once loaded (`VirtualSize`); 0 in some linkers' output.
+    pub virtual_size: usize,
+    /// The `size` raw bytes at `offset`.
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Section<'a> {
+    /// The bytes trimmed to `virtual_size`, without file-alignment padding
+    /// (what `objcopy -O binary --only-section` would write).
+    pub fn contents(&self) -> &'a [u8] {
+        match self.virtual_size {
+            v if v > 0 && v < self.bytes.len() => &self.bytes[..v],
+            _ => self.bytes,
+        }
+    }
+}
+
+/// WIN_CERTIFICATE header fields and where the blob sits in `data`.
+#[cfg(feature = "certificates")]
+#[derive(Debug)]
+struct Certificate {
+    length: u32,
+    revision: u16,
+    typ: u16,
+    blob: Range<usize>,
+}
+
+impl PeFile {
+    /// Read a PE/EFI image from disk and own its bytes.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).at("read", path)?;
+        Self::from_bytes(bytes)
+    }
+
+    /// Map a PE/EFI image read-only instead of copying it into memory.
+    ///
+    /// Pages are loaded as sections are touched, so inspecting a large UKI
+    /// costs little more than its headers. The file must not be truncated
+    /// or rewritten in place while the `PeFile` is alive (tools that replace
+    /// files by renaming, like `lowell build`, are fine).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path_mmap(path: &Path) -> Result<Self> {
+        Self::from_path_mmap_with_limits(path, &ParserLimits::default())
+    }
+
+    /// [`from_path_mmap`](Self::from_path_mmap), rejecting sections larger
+    /// than `limits.max_section_size`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path_mmap_with_limits(path: &Path, limits: &ParserLimits) -> Result<Self> {
+        Self::parse(Mapped::open(path)?, limits)
+    }
+
+    /// Construct from a caller-provided byte vector.
+    ///
+    /// Parses the headers once; fails with [`Error::NotPe`] if they are
+    /// malformed.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Self::from_bytes_with_limits(bytes, &ParserLimits::default())
+    }
+
+    /// [`from_bytes`](Self::from_bytes), rejecting sections larger than
+    /// `limits.max_section_size`.
+    pub fn from_bytes_with_limits(bytes: Vec<u8>, limits: &ParserLimits) -> Result<Self> {
+        Self::parse(Mapped::from(bytes), limits)
+    }
+
+    fn parse(data: Mapped, limits: &ParserLimits) -> Result<Self> {
+        let _span = debug_span!("parse_pe", len = data.len()).entered();
+        let t = Stopwatch::start();
+        let mut opts = ParseOptions::default();
+        // Certificates are only walked when someone can ask for them.
+        opts.parse_attribute_certificates = cfg!(feature = "certificates");
+        let pe = PE::parse_with_opts(&data, &opts).map_err(Error::NotPe)?;
+
+        let sections: Vec<SectionHeader> = pe
+            .sections
+            .iter()
+            .map(|s| {
+                ParserLimits::check(
+                    "PE section size",
+                    u64::from(s.size_of_raw_data),
+                    limits.max_section_size,
+                )?;
+                let end = s.name.iter().position(|&c| c == 0).unwrap_or(s.name.len());
+                let section = SectionHeader {
+                    name: String::from_utf8_lossy(&s.name[..end]).into_owned(),
+                    offset: s.pointer_to_raw_data as usize,
+                    size: s.size_of_raw_data as usize,
+                    virtual_address: s.virtual_address as usize,
+                    virtual_size: s.virtual_size as usize,
+                };
+                trace!(
+                    name = %section.name,
+                    offset = section.offset,
+                    size = section.size,
+                    "section"
+                );
+                Ok(section)
+            })
+            .collect::<Result<_>>()?;
+        // goblin hands out blobs borrowed from `data`; keep their offsets.
+        #[cfg(feature = "certificates")]
+        let base = data.as_ptr() as usize;
+        #[cfg(feature = "certificates")]
+        let certificates = pe
+            .certificates
+            .iter()
+            .map(|c| {
+                let start = c.certificate.as_ptr() as usize - base;
+                Certificate {
+                    length: c.length,
+                    revision: c.revision as u16,
+                    typ: c.certificate_type as u16,
+                    blob: start..start + c.certificate.len(),
+                }
+            })
+            .collect();
+        let (machine, is_64) = (pe.header.coff_header.machine, pe.is_64);
+        let layout = Layout::from_optional_header(pe.header.optional_header.as_ref());
+        debug!(
+            machine,
+            is_64,
+            sections = sections.len(),
+            elapsed_ms = t.ms(),
+            "pe_headers"
+        );
+        #[cfg(not(feature = "certificates"))]
+        let signed = pe
+            .header
+            .optional_header
+            .and_then(|oh| oh.data_directories.get_certificate_table().copied())
+            .is_some_and(|dir| dir.size > 0);
+
+        Ok(Self {
+            data,
+            machine,
+            is_64,
+            layout,
+            sections,
+            #[cfg(not(feature = "certificates"))]
+            signed,
+            #[cfg(feature = "certificates")]
+            certificates,
+        })
+    }
+
+    /// Access the full image buffer (read-only).
+    pub fn image(&self) -> &[u8] {
+        &self.data
+    }
+
+    // ---------- Basics ----------
+
+    /// Return a human-oriented architecture label and PE32+ flag.
+    ///
+    /// Common results:
+    /// - `("x86_64", true)` for amd64 UKIs
+    /// - `("aarch64", true)` for ARM64 UKIs
+    /// - `("i386", false)` for 32-bit x86
+    pub fn arch_summary(&self) -> Result<(&'static str, bool)> {
+        Ok((arch_name(self.machine), self.is_64))
+    }
+
+    /// Section and file alignment and the image size, from the optional
+    /// header (all 0 without one).
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    // ---------- Sections ----------
+
+    fn header(&self, name: &str) -> Option<&SectionHeader> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    fn view<'a>(&'a self, s: &'a SectionHeader) -> Result<Section<'a>> {
+        let bytes = s
+            .offset
+            .checked_add(s.size)
+            .and_then(|end| self.data.get(s.offset..end))
+            .ok_or_else(|| Error::SectionOutOfBounds {
+                name: s.name.clone(),
+                offset: s.offset,
+                size: s.size,
+            })?;
+        Ok(Section {
+            name: &s.name,
+            offset: s.offset,
+            size: s.size,
+            virtual_address: s.virtual_address,
+            virtual_size: s.virtual_size,
+            bytes,
+        })
+    }
+
+    /// A named section, or [`Error::SectionMissing`] if there is none and
+    /// [`Error::SectionOutOfBounds`] if its raw data lies past the end of
+    /// the file.
+    ///
+    /// ```no_run
+    /// # use lowell_formats::pe::PeFile;
+    /// # fn main() -> lowell_formats::Result<()> {
+    /// let pe = PeFile::from_path("uki.efi".as_ref())?;
+    /// let initrd = pe.section(".initrd")?;
+    /// println!("{} bytes at {:#x}", initrd.contents().len(), initrd.offset);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn section(&self, name: &str) -> Result<Section<'_>> {
+        self.find_section(name)?
+            .ok_or_else(|| Error::SectionMissing {
+                name: name.to_string(),
+            })
+    }
+
+    /// Like [`PeFile::section`], but `Ok(None)` for a missing section.
+    pub fn find_section(&self, name: &str) -> Result<Option<Section<'_>>> {
+        self.header(name).map(|s| self.view(s)).transpose()
+    }
+
+    /// Every section in header order; fails on the first one whose raw
+    /// data lies outside the file.
+    pub fn sections(&self) -> Result<Vec<Section<'_>>> {
+        self.sections.iter().map(|s| self.view(s)).collect()
+    }
+
+    /// Offset and file size of a named section, if it exists.
+    /// (file_offset, file_size)
+    pub fn section_info(&self, name: &str) -> Result<Option<(usize, usize)>> {
+        Ok(self.header(name).map(|s| (s.offset, s.size)))
+    }
+
+    /// Every section in header order as (name, file_offset, file_size).
+    /// Names that are not valid UTF-8 are rendered lossily.
+    pub fn section_table(&self) -> Result<Vec<(String, usize, usize)>> {
+        Ok(self
+            .sections
+            .iter()
+            .map(|s| (s.name.clone(), s.offset, s.size))
+            .collect())
+    }
+
+    /// Borrow raw bytes of a named section (e.g., ".initrd", ".linux", ".cmdline").
+    ///
+    /// Returns `Ok(None)` if the section is missing or coordinates are invalid;
+    /// [`PeFile::section`] tells the two apart.
+    pub fn section_bytes(&self, name: &str) -> Result<Option<&[u8]>> {
+        Ok(self.header(name).and_then(|s| {
+            let end = s.offset.checked_add(s.size)?;
+            self.data.get(s.offset..end)
+        }))
+    }
+
+    /// Like [`PeFile::section_bytes`], but trimmed to the section's
+    /// `VirtualSize` so file-alignment padding is dropped (what
+    /// `objcopy -O binary --only-section` would write).
+    pub fn section_contents(&self, name: &str) -> Result<Option<&[u8]>> {
+        let virtual_size = self.header(name).map(|s| s.virtual_size);
+        Ok(self.section_bytes(name)?.map(|b| match virtual_size {
+            Some(v) if v > 0 && v < b.len() => &b[..v],
+            _ => b,
+        }))
+    }
+
+    /// Read a section as text (trim at first NUL). Ideal for `.cmdline` / `.osrel`.
+    pub fn read_text(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.section_bytes(name)?.map(|b| {
+            let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+            String::from_utf8_lossy(&b[..end]).to_string()
+        }))
+    }
+
+    // ---------- Certificates (Authenticode) ----------
+
+    /// True if the image contains one or more Attribute Certificates.
+    ///
+    /// Presence indicates a **Certificate Table** exists; it does **not** mean
+    /// the signature is valid. Modifying sections (e.g., `.initrd`) will typically
+    /// invalidate verification in Secure Boot.
+    pub fn is_signed(&self) -> Result<bool> {
+        #[cfg(feature = "certificates")]
+        return Ok(!self.certificates.is_empty());
+        #[cfg(not(feature = "certificates"))]
+        Ok(self.signed)
+    }
+
+    /// Lightweight metadata for each attribute certificate: (length, revision, type).
+    ///
+    /// `revision` and `typ` come from the WIN_CERTIFICATE header. The blob itself is
+    /// usually PKCS#7 SignedData (`typ` 0x0002).
+    #[cfg(feature = "certificates")]
+    pub fn certificate_metadata(&self) -> Result<Vec<(u32, u16, u16)>> {
+        Ok(self
+            .certificates
+            .iter()
+            .map(|c| (c.length, c.revision, c.typ))
+            .collect())
+    }
+
+    /// The raw certificate blobs (`&[u8]`) for each attribute certificate.
+    #[cfg(feature = "certificates")]
+    pub fn certificate_blobs(&self) -> Result<Vec<&[u8]>> {
+        Ok(self
+            .certificates
+            .iter()
+            .map(|c| &self.data[c.blob.clone()])
+            .collect())
+    }
+}
+
+impl PeFile {
+    /// The image checksum as `CheckSumMappedFile` (and `pesign`) compute
+    /// it: the file summed as little-endian 16-bit words with carries
+    /// folded back in, the `CheckSum` field counted as zero, plus the
+    /// file length. Reads the whole image.
+    pub fn checksum(&self) -> Result<Checksum> {
+        let d: &[u8] = &self.data;
+        let field = d
+            .get(0x3c..0x40)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize + 24 + 64)
+            .filter(|at| at + 4 <= d.len())
+            .ok_or_else(|| Error::SectionOutOfBounds {
+                name: "headers".to_string(),
+                offset: 0x3c,
+                size: 4,
+            })?;
+        let stored = u32::from_le_bytes(d[field..field + 4].try_into().unwrap());
+        let computed = image_checksum(d, field);
+        Ok(Checksum {
+            stored,
+            computed,
+            matches: stored == computed,
+        })
+    }
+
+    /// The Authenticode digest of the image: everything but the
+    /// `CheckSum` field, the Security directory entry and the certificate
+    /// table, with sections in file order.
+    pub fn authenticode(&self, algorithm: Algorithm) -> Result<Digest> {
+        let mut hasher = Hasher::new(algorithm);
+        for part in self.authenticode_parts()? {
+            hasher.update(part);
+        }
+        Ok(hasher.finish())
+    }
+
+    /// The byte ranges [`authenticode`](Self::authenticode) hashes, in
+    /// order, for hashing them some other way.
+    pub fn authenticode_parts(&self) -> Result<Vec<&[u8]>> {
+        let d: &[u8] = &self.data;
+        let out_of_bounds = |what: &str, offset: usize, size: usize| Error::SectionOutOfBounds {
+            name: what.to_string(),
+            offset,
+            size,
+        };
+        let u32_at = |at: usize| {
+            d.get(at..at + 4)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+                .ok_or_else(|| out_of_bounds("headers", at, 4))
+        };
+        let optional = u32_at(0x3c)? + 24;
+        let checksum = optional + 64;
+        let (rva_count, directories) = match self.is_64 {
+            true => (optional + 108, optional + 112),
+            false => (optional + 92, optional + 96),
+        };
+        let headers_end = u32_at(optional + 60)?;
+        // SizeOfHeaders comes from the file: it has to cover the field
+        // it is read past, or the slices below would not be in bounds.
+        let headers = d
+            .get(..headers_end)
+            .filter(|_| headers_end >= checksum + 4)
+            .ok_or_else(|| out_of_bounds("headers", 0, headers_end))?;
+        let mut parts = vec![&headers[..checksum]];
+        let security = directories + 4 * 8;
+        let mut cert_size = 0;
+        if u32_at(rva_count)? > 4 && security + 8 <= headers_end {
+            parts.push(&headers[checksum + 4..security]);
+            parts.push(&headers[security + 8..]);
+            cert_size = u32_at(security + 4)?;
+        } else {
+            parts.push(&headers[checksum + 4..]);
+        }
+        let mut sections: Vec<_> = self.sections.iter().filter(|s| s.size > 0).collect();
+        sections.sort_by_key(|s| s.offset);
+        let mut hashed = headers_end;
+        for s in sections {
+            parts.push(self.view(s)?.bytes);
+            hashed += s.size;
+        }
+        // Data after the last section, up to the certificate table.
+        let end = d.len().saturating_sub(cert_size);
+        if end > hashed {
+            parts.push(&d[hashed..end]);
+        }
+        Ok(parts)
+    }
+}
+
+fn arch_name(machine: u16) -> &'static str {
+    use goblin::pe::header::*;
+    match machine {
+        COFF_MACHINE_X86_64 => "x86_64",
+        COFF_MACHINE_ARM64 => "aarch64",
+        COFF_MACHINE_ARM => "arm",
+        COFF_MACHINE_X86 => "i386",
+        _ => "unknown",
+    }
+}
+
+/// The headers of a PE image and the start of each section, read without
+/// loading or mapping the rest of the file.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PeHeaders {
+    /// As in [`PeFile::arch_summary`].
+    pub arch: &'static str,
+    pub pe32_plus: bool,
+    /// The Security data directory is non-empty; certificates are not read.
+    pub signed: bool,
+    pub file_size: u64,
+    /// As in [`PeFile::layout`].
+    pub layout: Layout,
+    /// In header order.
+    pub sections: Vec<SectionHead>,
+}
+
+/// A section header and up to [`SECTION_HEAD`] bytes of its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SectionHead {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+    pub virtual_address: usize,
+    pub virtual_size: usize,
+    /// The start of the contents (trimmed to `virtual_size`).
+    pub head: Vec<u8>,
+}
+
+impl SectionHead {
+    /// `head` is the whole of the contents.
+    pub fn is_whole(&self) -> bool {
+        let len = match self.virtual_size {
+            v if v > 0 && v < self.size => v,
+            _ => self.size,
+        };
+        self.head.len() == len
+    }
+}
+
+impl PeHeaders {
+    /// Read the headers of the PE image at `path`, then the start of each
+    /// section. Fails like [`PeFile::from_path_mmap_with_limits`] on a bad
+    /// image or an oversized section, and with
+    /// [`Error::SectionOutOfBounds`] for data past the end of the file.
+    pub fn read(path: &Path, limits: &ParserLimits) -> Result<Self> {
+        let _span = debug_span!("read_pe_headers", path = %path.display()).entered();
+        let t = Stopwatch::start();
+        let mut file = File::open(path).at("open", path)?;
+        let file_size = file.metadata().at("stat", path)?.len();
+        let mut head = read_range(&mut file, path, file_size, 0, HEADER_PROBE)?;
+        // DOS header + PE signature, COFF header, optional header, table.
+        let pe_pointer = head
+            .get(0x3c..0x40)
+            .map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap()) as usize);
+        let wanted = pe_pointer.saturating_add(4 + 20 + 240);
+        if wanted as u64 > file_size {
+            return Err(Error::NotPe(goblin::error::Error::Malformed(format!(
+                "PE header at {pe_pointer:#x} runs past the end of the file ({file_size} bytes)"
+            ))));
+        }
+        if head.len() < wanted {
+            head = read_range(&mut file, path, file_size, 0, wanted)?;
+        }
+        let (machine, pe32_plus, signed, layout, table, count) = {
+            let header = Header::parse(&head).map_err(Error::NotPe)?;
+            let coff = header.coff_header;
+            let opt = header.optional_header;
+            let table = pe_pointer + 4 + 20 + usize::from(coff.size_of_optional_header);
+            (
+                coff.machine,
+                opt.is_some_and(|o| o.standard_fields.magic == optional_header::MAGIC_64),
+                opt.and_then(|o| o.data_directories.get_certificate_table().copied())
+                    .is_some_and(|dir| dir.size > 0),
+                Layout::from_optional_header(opt.as_ref()),
+                table,
+                usize::from(coff.number_of_sections),
+            )
+        };
+        let table_end = table + count * SECTION_HEADER_LEN;
+        if head.len() < table_end {
+            head = read_range(&mut file, path, file_size, 0, table_end)?;
+        }
+        let table = (0..count)
+            .map(|i| {
+                let mut at = table + i * SECTION_HEADER_LEN;
+                SectionTable::parse(&head, &mut at, 0).map_err(Error::NotPe)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut sections = Vec::with_capacity(table.len());
+        for s in &table {
+            ParserLimits::check(
+                "PE section size",
+                u64::from(s.size_of_raw_data),
+                limits.max_section_size,
+            )?;
+            let end = s.name.iter().position(|&c| c == 0).unwrap_or(s.name.len());
+            let name = String::from_utf8_lossy(&s.name[..end]).into_owned();
+            let (offset, size) = (s.pointer_to_raw_data as usize, s.size_of_raw_data as usize);
+            if offset as u64 + size as u64 > file_size {
+                return Err(Error::SectionOutOfBounds { name, offset, size });
+            }
+            let virtual_size = s.virtual_size as usize;
+            let contents = match virtual_size {
+                v if v > 0 && v < size => v,
+                _ => size,
+            };
+            let head = read_range(
+                &mut file,
+                path,
+                file_size,
+                offset as u64,
+                contents.min(SECTION_HEAD),
+            )?;
+            sections.push(SectionHead {
+                name,
+                offset,
+                size,
+                virtual_address: s.virtual_address as usize,
+                virtual_size,
+                head,
+            });
+        }
+        debug!(sections = sections.len(), elapsed_ms = t.ms(), "pe_headers");
+        Ok(Self {
+            arch: arch_name(machine),
+            pe32_plus,
+            signed,
+            file_size,
+            layout,
+            sections,
+        })
+    }
+
+    /// The first section called `name`.
+    pub fn section(&self, name: &str) -> Option<&SectionHead> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+}
+
+/// Up to `len` bytes of `file` from `offset` (fewer at the end of the file,
+/// `file_size` bytes long). `len` may come from the file itself, so the
+/// buffer is sized by what is there to read.
+fn read_range(
+    file: &mut File,
+    path: &Path,
+    file_size: u64,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let len = len.min(file_size.saturating_sub(offset) as usize);
+    let mut buf = Vec::with_capacity(len);
+    file.seek(SeekFrom::Start(offset)).at("seek", path)?;
+    file.take(len as u64)
+        .read_to_end(&mut buf)
+        .at("read", path)?;
+    Ok(buf)
+}
+
+/// [`PeFile::checksum`] of `data` with its `CheckSum` field at `field`.
+fn image_checksum(data: &[u8], field: usize) -> u32 {
+    // Carries are folded once at the end: a u64 holds any file's sum.
+    let mut sum: u64 = 0;
+    for (i, word) in data.chunks(2).enumerate() {
+        let at = i * 2;
+        if (field..field + 4).contains(&at) {
+            continue;
+        }
+        sum += u64::from(word[0]) | u64::from(word.get(1).copied().unwrap_or(0)) << 8;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    (sum as u32).wrapping_add(data.len() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::UkiBuilder;
+
+    #[test]
+    fn checksums_fold_carries_and_skip_their_field() {
+        // 0x0001 + 0xffff + 0x0002 folds to 0x0003; the length is 9.
+        let data = [1, 0, 9, 9, 9, 9, 0xff, 0xff, 2];
+        assert_eq!(image_checksum(&data, 2), 0x0003 + 9);
+
+        let mut img = UkiBuilder::new().cmdline("quiet").build();
+        let computed = PeFile::from_bytes(img.clone())
+            .unwrap()
+            .checksum()
+            .unwrap()
+            .computed;
+        let field = u32::from_le_bytes(img[0x3c..0x40].try_into().unwrap()) as usize + 88;
+        img[field..field + 4].copy_from_slice(&computed.to_le_bytes());
+        let checksum = PeFile::from_bytes(img).unwrap().checksum().unwrap();
+        assert_eq!((checksum.stored, checksum.matches), (computed, true));
+    }
+
+    #[test]
+    fn mapped_and_owned_images_agree() {
+        let img = UkiBuilder::new().cmdline("quiet").build();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, &img).unwrap();
+
+        let mapped = PeFile::from_path_mmap(&path).unwrap();
+        let owned = PeFile::from_bytes(img).unwrap();
+        assert_eq!(mapped.image(), owned.image());
+        assert_eq!(
+            mapped.section_table().unwrap(),
+            owned.section_table().unwrap()
+        );
+        assert_eq!(mapped.read_text(".cmdline").unwrap().unwrap(), "quiet");
+
+        std::fs::write(&path, b"not a PE").unwrap();
+        assert!(matches!(
+            PeFile::from_path_mmap(&path),
+            Err(Error::NotPe(_))
+        ));
+    }
+
+    #[test]
+    fn authenticode_skips_checksum_and_certificates() {
+        let plain = UkiBuilder::new()
+            .linux(&[7u8; 4096])
+            .cmdline("quiet")
+            .build();
+        let signed = UkiBuilder::new()
+            .linux(&[7u8; 4096])
+            .cmdline("quiet")
+            .certificate(b"pkcs7")
+            .build();
+        let digest = |img: &[u8]| {
+            PeFile::from_bytes(img.to_vec())
+                .unwrap()
+                .authenticode(Algorithm::Sha256)
+                .unwrap()
+        };
+        assert_ne!(plain, signed);
+        assert_eq!(digest(&plain), digest(&signed));
+
+        let checksum = u32::from_le_bytes(plain[0x3c..0x40].try_into().unwrap()) as usize + 88;
+        let mut tweaked = plain.clone();
+        tweaked[checksum] ^= 0xff;
+        assert_eq!(digest(&tweaked), digest(&plain));
+        let (linux, _) = PeFile::from_bytes(plain.clone())
+            .unwrap()
+            .section_info(".linux")
+            .unwrap()
+            .unwrap();
+        tweaked[linux] ^= 0xff;
+        assert_ne!(digest(&tweaked), digest(&plain));
+    }
+
+    #[test]
+    fn authenticode_rejects_headers_short_of_the_checksum() {
+        let mut img = UkiBuilder::new().linux(&[7u8; 4096]).build();
+        let optional = u32::from_le_bytes(img[0x3c..0x40].try_into().unwrap()) as usize + 24;
+        img[optional + 60..optional + 64].copy_from_slice(&0x40u32.to_le_bytes());
+        let pe = PeFile::from_bytes(img).unwrap();
+        assert!(matches!(
+            pe.authenticode_parts(),
+            Err(Error::SectionOutOfBounds { .. })
+        ));
+        assert!(pe.authenticode(Algorithm::Sha256).is_err());
+    }
+
+    #[test]
+    fn oversized_sections_are_rejected() {
+        let img = UkiBuilder::new().linux(&[0u8; 4096]).build();
+        let limits = ParserLimits {
+            max_section_size: 1024,
+            ..Default::default()
+        };
+        let err = PeFile::from_bytes_with_limits(img.clone(), &limits).unwrap_err();
+        assert_eq!(err.code(), crate::error::ErrorCode::LimitExceeded);
+        assert!(PeFile::from_bytes(img).is_ok());
+    }
+
+    #[test]
+    fn section_lookup_reports_typed_errors() {
+        let mut img = UkiBuilder::new().linux(&[7u8; 4096]).build();
+        let pe = PeFile::from_bytes(img.clone()).unwrap();
+        let linux = pe.section(".linux").unwrap();
+        assert_eq!(linux.name, ".linux");
+        assert_eq!(linux.contents(), &[7u8; 4096][..]);
+        assert_eq!(
+            pe.section_info(".linux").unwrap(),
+            Some((linux.offset, linux.size))
+        );
+        assert!(matches!(
+            pe.section(".initrd"),
+            Err(Error::SectionMissing { name }) if name == ".initrd"
+        ));
+        assert!(pe.find_section(".initrd").unwrap().is_none());
+
+        img.truncate(linux.offset + 16);
+        let pe = PeFile::from_bytes(img).unwrap();
+        assert!(matches!(
+            pe.section(".linux"),
+            Err(Error::SectionOutOfBounds { .. })
+        ));
+        assert!(pe.sections().is_err());
+    }
+
+    #[test]
+    fn headers_match_a_full_parse() {
+        let img = UkiBuilder::new()
+            .cmdline("quiet")
+            .linux(&[7u8; 3 * SECTION_HEAD])
+            .build();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, &img).unwrap();
+
+        let headers = PeHeaders::read(&path, &ParserLimits::default()).unwrap();
+        let pe = PeFile::from_bytes(img.clone()).unwrap();
+        assert_eq!(
+            (headers.arch, headers.pe32_plus),
+            pe.arch_summary().unwrap()
+        );
+        assert_eq!(headers.signed, pe.is_signed().unwrap());
+        let table: Vec<_> = headers
+            .sections
+            .iter()
+            .map(|s| (s.name.clone(), s.offset, s.size))
+            .collect();
+        assert_eq!(table, pe.section_table().unwrap());
+        let cmdline = headers.section(".cmdline").unwrap();
+        assert!(cmdline.is_whole());
+        assert_eq!(
+            cmdline.head,
+            pe.section_contents(".cmdline").unwrap().unwrap()
+        );
+        let linux = headers.section(".linux").unwrap();
+        assert!(!linux.is_whole());
+        assert_eq!(linux.head, [7u8; SECTION_HEAD]);
+
+        let linux = pe.section(".linux").unwrap();
+        std::fs::write(&path, &img[..linux.offset + 16]).unwrap();
+        assert!(matches!(
+            PeHeaders::read(&path, &ParserLimits::default()),
+            Err(Error::SectionOutOfBounds { .. })
+        ));
+        std::fs::write(&path, b"not a PE").unwrap();
+        assert!(matches!(
+            PeHeaders::read(&path, &ParserLimits::default()),
+            Err(Error::NotPe(_))
+        ));
+        // A PE header pointer far past the end is refused before any read.
+        let mut far = img[..0x200].to_vec();
+        far[0x3c..0x40].copy_from_slice(&0xffff_0000u32.to_le_bytes());
+        std::fs::write(&path, &far).unwrap();
+        assert!(matches!(
+            PeHeaders::read(&path, &ParserLimits::default()),
+            Err(Error::NotPe(_))
+        ));
+    }
+}
@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Decompression on a second thread, overlapped with parsing.
+//!
+//! Walking a compressed initramfs alternates between inflating a few KiB
+//! and parsing cpio headers out of them. [`spawn`] moves the decoder onto
+//! a scoped thread that fills a small queue of chunks ahead of the parser,
+//! so both run at once. Dropping the returned reader stops the decoder at
+//! its next chunk.
+
+use std::io::{self, Read};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::Scope;
+
+/// Decoded bytes per message.
+const CHUNK: usize = 1 << 20;
+/// Chunks the decoder may run ahead of the parser.
+const DEPTH: usize = 4;
+/// Most memory the queue holds: the chunks in it, plus the one being
+/// filled and the one being read.
+pub(crate) const MEMORY: u64 = ((DEPTH + 2) * CHUNK) as u64;
+
+/// Read `inner` on a thread of `scope`; the returned reader yields the same
+/// bytes, including a read error at the point it occurred.
+pub(crate) fn spawn<'scope, R>(scope: &'scope Scope<'scope, '_>, mut inner: R) -> Pipelined
+where
+    R: Read + Send + 'scope,
+{
+    let (tx, rx) = sync_channel(DEPTH);
+    scope.spawn(move || loop {
+        let mut chunk = Vec::with_capacity(CHUNK);
+        // On error, `chunk` still holds what was read before it.
+        let result = (&mut inner).take(CHUNK as u64).read_to_end(&mut chunk);
+        // A send error means the reader is gone; so is any reason to go on.
+        if !chunk.is_empty() && tx.send(Ok(chunk)).is_err() {
+            return;
+        }
+        match result {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                return;
+            }
+        }
+    });
+    Pipelined {
+        rx,
+        chunk: Vec::new(),
+        pos: 0,
+    }
+}
+
+pub(crate) struct Pipelined {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for Pipelined {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.recv() {
+                Ok(chunk) => (self.chunk, self.pos) = (chunk?, 0),
+                // The decoder finished.
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipelined_reader_matches_source() {
+        let data: Vec<u8> = (0..3 * CHUNK + 17).map(|i| i as u8).collect();
+        let out = std::thread::scope(|s| {
+            let mut out = Vec::new();
+            spawn(s, &data[..]).read_to_end(&mut out).unwrap();
+            out
+        });
+        assert_eq!(out, data);
+
+        // Errors arrive after the bytes that preceded them.
+        let failing = (&data[..10]).chain(ErrReader);
+        std::thread::scope(|s| {
+            let mut r = spawn(s, failing);
+            let mut out = Vec::new();
+            assert!(r.read_to_end(&mut out).is_err());
+            assert_eq!(out.len(), 10);
+        });
+    }
+
+    struct ErrReader;
+
+    impl Read for ErrReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("boom"))
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Progress reporting for long-running operations.
+//!
+//! Inspect and build call into a [`ProgressSink`] as they go, so embedders
+//! can drive a progress bar or a status line without scraping `tracing`
+//! output. Every method has an empty default; implement the ones you need.
+//!
+//! Calls for one operation come from one thread, in order: `phase_started`,
+//! any number of `bytes_processed`, then `phase_finished`. Phases do not
+//! nest. Byte counts are cumulative within a phase.
+
+use crate::cancel::CancelToken;
+use crate::digest::{Algorithm, Digest, Hasher};
+use crate::error::{IoResultExt, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// A step of an inspect or build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Reading and parsing the PE headers.
+    Parse,
+    /// Digest of a section (`.linux`, `.initrd`).
+    Hash(&'static str),
+    /// Walking the initramfs to count its entries.
+    CountEntries,
+    /// Reading a build input (the stub or a section payload).
+    Read(&'static str),
+    /// Laying out the UKI from its inputs.
+    Assemble,
+    /// Packing a directory into an initramfs (uncompressed bytes).
+    Pack,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Parse => f.write_str("parse"),
+            Phase::Hash(section) => write!(f, "hash {section}"),
+            Phase::CountEntries => f.write_str("count entries"),
+            Phase::Read(what) => write!(f, "read {what}"),
+            Phase::Assemble => f.write_str("assemble"),
+            Phase::Pack => f.write_str("pack"),
+        }
+    }
+}
+
+pub trait ProgressSink: Send + Sync {
+    /// `total` is the phase's size in bytes, when known up front.
+    fn phase_started(&self, _phase: Phase, _total: Option<u64>) {}
+    /// `done` bytes of the phase are processed so far.
+    fn bytes_processed(&self, _phase: Phase, _done: u64) {}
+    fn phase_finished(&self, _phase: Phase) {}
+}
+
+impl fmt::Debug for dyn ProgressSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressSink")
+    }
+}
+
+/// The default sink: ignores everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {}
+
+/// Granularity of `bytes_processed` while hashing.
+const CHUNK: usize = 4 << 20;
+
+/// `algorithm` digest of `data`, reported as `phase` in `CHUNK` steps;
+/// checks `cancel` before each step.
+pub fn digest(
+    data: &[u8],
+    algorithm: Algorithm,
+    phase: Phase,
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Digest> {
+    let mut digests = digests(data, &[algorithm], phase, sink, cancel)?;
+    Ok(digests.remove(0))
+}
+
+/// [`digest`] with each of `algorithms`, in one pass over `data` (each
+/// chunk is fed to every hasher while it is in cache).
+pub fn digests(
+    data: &[u8],
+    algorithms: &[Algorithm],
+    phase: Phase,
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<Digest>> {
+    sink.phase_started(phase, Some(data.len() as u64));
+    let mut hashers: Vec<Hasher> = algorithms.iter().map(|a| Hasher::new(*a)).collect();
+    let mut done = 0u64;
+    for chunk in data.chunks(CHUNK) {
+        cancel.check()?;
+        for hasher in &mut hashers {
+            hasher.update(chunk);
+        }
+        done += chunk.len() as u64;
+        sink.bytes_processed(phase, done);
+    }
+    sink.phase_finished(phase);
+    Ok(hashers.into_iter().map(Hasher::finish).collect())
+}
+
+/// Read `path` in `CHUNK` steps, reported as `phase`; checks `cancel`
+/// before each step.
+pub fn read_file(
+    path: &Path,
+    phase: Phase,
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<u8>> {
+    let mut file = File::open(path).at("read", path)?;
+    let total = file.metadata().ok().map(|m| m.len());
+    sink.phase_started(phase, total);
+    if let Some(len) = total {
+        let mut progress = |done| {
+            cancel.check()?;
+            sink.bytes_processed(phase, done);
+            Ok(())
+        };
+        if let Some(data) = crate::uring::read_sized(&file, path, len, &mut progress) {
+            sink.phase_finished(phase);
+            return data;
+        }
+    }
+    let mut data = Vec::with_capacity(total.unwrap_or(0) as usize);
+    loop {
+        cancel.check()?;
+        let n = (&mut file)
+            .take(CHUNK as u64)
+            .read_to_end(&mut data)
+            .at("read", path)?;
+        if n == 0 {
+            break;
+        }
+        sink.bytes_processed(phase, data.len() as u64);
+    }
+    sink.phase_finished(phase);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records every call as a line (test helper).
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ProgressSink for Recorder {
+        fn phase_started(&self, phase: Phase, total: Option<u64>) {
+            let total = total.map_or("?".into(), |t| t.to_string());
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("start {phase} {total}"));
+        }
+        fn bytes_processed(&self, phase: Phase, done: u64) {
+            self.0.lock().unwrap().push(format!("{phase} {done}"));
+        }
+        fn phase_finished(&self, phase: Phase) {
+            self.0.lock().unwrap().push(format!("end {phase}"));
+        }
+    }
+
+    #[test]
+    fn hashing_reports_cumulative_chunks() {
+        let data = vec![0u8; CHUNK + 10];
+        let rec = Recorder::default();
+        let d = digest(
+            &data,
+            Algorithm::Sha384,
+            Phase::Hash(".linux"),
+            &rec,
+            &CancelToken::new(),
+        )
+        .unwrap();
+        assert_eq!(d, Algorithm::Sha384.digest(&data));
+        let expected = [
+            format!("start hash .linux {}", CHUNK + 10),
+            format!("hash .linux {CHUNK}"),
+            format!("hash .linux {}", CHUNK + 10),
+            "end hash .linux".to_string(),
+        ];
+        assert_eq!(*rec.0.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn cancelled_token_stops_hashing() {
+        let cancel = CancelToken::new();
+        cancel.clone().cancel();
+        let err = digest(
+            b"data",
+            Algorithm::Sha256,
+            Phase::Hash(".initrd"),
+            &NoProgress,
+            &cancel,
+        )
+        .unwrap_err();
+        assert!(matches!(err, crate::Error::Cancelled));
+        assert_eq!(err.code().as_str(), "E_CANCELLED");
+    }
+}
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! SBAT (UEFI Secure Boot Advanced Targeting) metadata: the `.sbat` CSV
+//! section shim checks before it runs an image.
+//!
+//! Each line is one component: `component_name,component_generation,
+//! vendor_name,vendor_package_name,vendor_version,vendor_url`. The first
+//! record is conventionally `sbat,1,...`, the version of the format. shim's
+//! revocation level (`SbatLevelRT`, or a policy shipped as a file) uses the
+//! same format with only name and generation (its own first line carries a
+//! date in the third field): an image is revoked when one of its components
+//! has a lower generation than the level lists for that name.
+
+use crate::error::{Error, Result};
+
+/// One SBAT record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "schema", schemars(rename = "SbatEntry"))]
+#[non_exhaustive]
+pub struct Entry {
+    pub component: String,
+    pub generation: u32,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub vendor: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub package: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub version: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub url: Option<String>,
+}
+
+/// The records in `text`, in order. Blank lines are skipped; a record
+/// without a name or a decimal generation is an error.
+pub fn parse(text: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches(['\r', '\0']);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.split(',').map(str::trim);
+        let bad = |why: &str| Error::Sbat(format!("line {}: {why}: {line:?}", n + 1));
+        let component = match fields.next() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => return Err(bad("no component name")),
+        };
+        let generation = fields
+            .next()
+            .and_then(|g| g.parse().ok())
+            .ok_or_else(|| bad("no generation number"))?;
+        let mut optional = || fields.next().filter(|f| !f.is_empty()).map(str::to_string);
+        entries.push(Entry {
+            component,
+            generation,
+            vendor: optional(),
+            package: optional(),
+            version: optional(),
+            url: optional(),
+        });
+    }
+    Ok(entries)
+}
+
+/// The records of `entries` that revocation level `level` rejects, each
+/// with the generation the level requires.
+pub fn revoked<'a>(entries: &'a [Entry], level: &[Entry]) -> Vec<(&'a Entry, u32)> {
+    entries
+        .iter()
+        .filter_map(|e| {
+            level
+                .iter()
+                .find(|l| l.component == e.component && e.generation < l.generation)
+                .map(|l| (e, l.generation))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYSTEMD: &str = "\
+sbat,1,SBAT Version,sbat,1,https://github.com/rhboot/shim/blob/main/SBAT.md
+systemd-stub,1,The systemd Developers,systemd,256,https://systemd.io/
+systemd-stub.fedora,1,Fedora Linux,systemd,256.7-1.fc41,https://bugzilla.redhat.com/
+";
+
+    #[test]
+    fn records_split_into_fields() {
+        let entries = parse(SYSTEMD).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].component, "sbat");
+        assert_eq!(entries[1].generation, 1);
+        assert_eq!(entries[2].vendor.as_deref(), Some("Fedora Linux"));
+        assert_eq!(entries[2].version.as_deref(), Some("256.7-1.fc41"));
+        assert_eq!(
+            entries[2].url.as_deref(),
+            Some("https://bugzilla.redhat.com/")
+        );
+
+        let short = parse("shim,4\n\n").unwrap();
+        assert_eq!((short[0].generation, short[0].vendor.as_ref()), (4, None));
+        assert!(parse("shim,four\n").is_err());
+        assert!(parse(",1\n").is_err());
+    }
+
+    #[test]
+    fn levels_revoke_older_generations() {
+        let entries = parse(SYSTEMD).unwrap();
+        let level = parse("sbat,1,2024010900\nshim,4\nsystemd-stub,2\n").unwrap();
+        let revoked = revoked(&entries, &level);
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(
+            (revoked[0].0.component.as_str(), revoked[0].1),
+            ("systemd-stub", 2)
+        );
+        assert!(super::revoked(&entries, &parse("systemd-stub,1\n").unwrap()).is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Guess what kind of boot artifact a file is from its leading bytes.
+
+use super::initramfs::{self, Compression};
+use super::kernel;
+use crate::error::{IoResultExt, Result};
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum FileKind {
+    /// A PE/EFI image that is not a bare kernel (normally a UKI).
+    Uki,
+    Initramfs,
+    Kernel,
+}
+
+impl std::fmt::Display for FileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FileKind::Uki => "uki",
+            FileKind::Initramfs => "initramfs",
+            FileKind::Kernel => "kernel",
+        })
+    }
+}
+
+/// Kernel magic is checked first: EFI-stub kernels are PE images too.
+/// Only the first 4 KiB of `head` are needed.
+pub fn sniff(head: &[u8]) -> Option<FileKind> {
+    if kernel::detect(head).is_some() {
+        Some(FileKind::Kernel)
+    } else if head.starts_with(b"MZ") {
+        Some(FileKind::Uki)
+    } else if initramfs::detect(head) != Compression::Unknown {
+        Some(FileKind::Initramfs)
+    } else {
+        None
+    }
+}
+
+/// [`sniff`] the first 4 KiB of the file at `path`.
+pub fn sniff_path(path: &Path) -> Result<Option<FileKind>> {
+    let mut head = Vec::with_capacity(4096);
+    std::fs::File::open(path)
+        .and_then(|f| f.take(4096).read_to_end(&mut head))
+        .at("read", path)?;
+    Ok(sniff(&head))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn efi_stub_kernels_are_not_ukis() {
+        let mut bz = vec![0u8; 0x300];
+        bz[..2].copy_from_slice(b"MZ");
+        bz[0x202..0x206].copy_from_slice(b"HdrS");
+        assert_eq!(sniff(&bz), Some(FileKind::Kernel));
+        bz[0x202] = 0;
+        assert_eq!(sniff(&bz), Some(FileKind::Uki));
+        assert_eq!(sniff(&[0x1f, 0x8b, 8, 0]), Some(FileKind::Initramfs));
+        assert_eq!(sniff(b"#!/bin/sh"), None);
+    }
+}
@@ -0,0 +1,21 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Offloading blocking work from async callers (`tokio` feature).
+
+use crate::error::Result;
+use std::io;
+
+/// Run `f` on tokio's blocking pool. Panics in `f` resume in the caller.
+pub async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(r) => r,
+        Err(e) => match e.try_into_panic() {
+            Ok(payload) => std::panic::resume_unwind(payload),
+            // Cancelled: the runtime is shutting down.
+            Err(e) => Err(io::Error::other(e).into()),
+        },
+    }
+}
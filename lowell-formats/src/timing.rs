@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Elapsed times for debug logs.
+//!
+//! `std::time::Instant` panics on wasm32-unknown-unknown (no clock), so
+//! there every measurement reads 0.
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub fn ms(&self) -> u64 {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed().as_millis() as u64;
+        #[cfg(target_arch = "wasm32")]
+        return 0;
+    }
+}
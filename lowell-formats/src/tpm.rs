@@ -0,0 +1,484 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! TPM 2.0 event logs, and the PCR values a UKI should produce.
+//!
+//! [`EventLog::parse`] reads a TCG PC Client event log as Linux exposes it
+//! in `/sys/kernel/security/tpm0/binary_bios_measurements`: crypto-agile
+//! (a `Spec ID Event03` header, then one digest per bank for each event),
+//! or the SHA-1-only format of older firmware. [`EventLog::replay`]
+//! recomputes each PCR of one bank from the events.
+//!
+//! The predictions cover what a UKI itself measures:
+//!
+//! * **PCR 11**: systemd-stub extends the name (NUL included) and then the
+//!   contents (trimmed to `VirtualSize`) of each section it knows, in a
+//!   fixed order, `.pcrsig` excepted ([`pcr11`]). Multi-profile UKIs are
+//!   not modelled. The stub's events land in the firmware log, so a replay
+//!   of PCR 11 should equal [`predict`] of them; userspace phase
+//!   measurements (`enter-initrd`, …) come later and are not logged there.
+//! * **PCR 4**: firmware extends the Authenticode digest of every image it
+//!   loads ([`pcr4`]), so the UKI's digest, and the
+//!   kernel's when the stub loads `.linux` as an image, should appear
+//!   among its `EV_EFI_BOOT_SERVICES_APPLICATION` events. The final value
+//!   also depends on the boot loader chain, so only that is checked.
+
+use crate::error::{Error, Result};
+use crate::pe::PeFile;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Event types lowell looks at.
+pub const EV_NO_ACTION: u32 = 0x3;
+pub const EV_IPL: u32 = 0xd;
+pub const EV_EFI_BOOT_SERVICES_APPLICATION: u32 = 0x8000_0003;
+
+/// The sections systemd-stub measures into PCR 11, in measurement order.
+pub const PCR11_SECTIONS: &[&str] = &[
+    ".linux", ".osrel", ".cmdline", ".initrd", ".ucode", ".splash", ".dtb", ".uname", ".sbat",
+    ".pcrpkey", ".profile", ".dtbauto", ".hwids",
+];
+
+const SPEC_ID: &[u8] = b"Spec ID Event03\0";
+const STARTUP_LOCALITY: &[u8] = b"StartupLocality\0";
+/// PCRs in a PC Client TPM.
+const PCRS: u32 = 24;
+
+/// A PCR bank: the hash algorithm behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Bank {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Bank {
+    pub const ALL: [Bank; 4] = [Bank::Sha1, Bank::Sha256, Bank::Sha384, Bank::Sha512];
+
+    /// The `TPM_ALG_ID`.
+    pub fn alg_id(self) -> u16 {
+        match self {
+            Bank::Sha1 => 0x0004,
+            Bank::Sha256 => 0x000b,
+            Bank::Sha384 => 0x000c,
+            Bank::Sha512 => 0x000d,
+        }
+    }
+
+    pub fn from_alg_id(id: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|b| b.alg_id() == id)
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Bank::Sha1 => 20,
+            Bank::Sha256 => 32,
+            Bank::Sha384 => 48,
+            Bank::Sha512 => 64,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Bank::Sha1 => "sha1",
+            Bank::Sha256 => "sha256",
+            Bank::Sha384 => "sha384",
+            Bank::Sha512 => "sha512",
+        }
+    }
+
+    /// Hash of `parts` concatenated.
+    pub fn hash(self, parts: &[&[u8]]) -> Vec<u8> {
+        fn run<D: sha2::Digest>(parts: &[&[u8]]) -> Vec<u8> {
+            let mut h = D::new();
+            for p in parts {
+                h.update(p);
+            }
+            h.finalize().to_vec()
+        }
+        match self {
+            Bank::Sha1 => run::<sha1::Sha1>(parts),
+            Bank::Sha256 => run::<sha2::Sha256>(parts),
+            Bank::Sha384 => run::<sha2::Sha384>(parts),
+            Bank::Sha512 => run::<sha2::Sha512>(parts),
+        }
+    }
+
+    /// `pcr` extended with `digest`.
+    pub fn extend(self, pcr: &[u8], digest: &[u8]) -> Vec<u8> {
+        self.hash(&[pcr, digest])
+    }
+}
+
+impl fmt::Display for Bank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Bank {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|b| b.as_str() == s)
+            .ok_or_else(|| format!("unknown PCR bank {s:?} (sha1, sha256, sha384, sha512)"))
+    }
+}
+
+/// One measurement in an [`EventLog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub pcr: u32,
+    pub kind: u32,
+    /// One digest per bank the log records (banks lowell does not know are
+    /// skipped).
+    pub digests: Vec<(Bank, Vec<u8>)>,
+    pub data: Vec<u8>,
+}
+
+impl Event {
+    pub fn digest(&self, bank: Bank) -> Option<&[u8]> {
+        self.digests
+            .iter()
+            .find(|(b, _)| *b == bank)
+            .map(|(_, d)| d.as_slice())
+    }
+}
+
+/// A parsed event log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventLog {
+    /// Banks the log carries digests for, in header order.
+    pub banks: Vec<Bank>,
+    /// Every event after the header.
+    pub events: Vec<Event>,
+    /// Locality the TPM started in (PCR 0's initial last byte).
+    pub startup_locality: u8,
+}
+
+impl EventLog {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader { bytes, at: 0 };
+        // The first event is always in the SHA-1 format.
+        let header = r.legacy_event()?;
+        let spec = header
+            .data
+            .strip_prefix(SPEC_ID)
+            .filter(|_| header.kind == EV_NO_ACTION);
+        let Some(spec) = spec else {
+            return Self::parse_legacy(header, r);
+        };
+        // platformClass, version, uintnSize: 8 bytes, then the algorithms.
+        let mut s = Reader { bytes: spec, at: 8 };
+        let count = s.u32()?;
+        let mut sizes = BTreeMap::new();
+        let mut banks = Vec::new();
+        for _ in 0..count {
+            let (id, size) = (s.u16()?, s.u16()?);
+            sizes.insert(id, usize::from(size));
+            banks.extend(Bank::from_alg_id(id));
+        }
+        let mut log = Self {
+            banks,
+            events: Vec::new(),
+            startup_locality: 0,
+        };
+        while !r.done() {
+            let (pcr, kind) = (r.u32()?, r.u32()?);
+            let count = r.u32()?;
+            let mut digests = Vec::new();
+            for _ in 0..count {
+                let id = r.u16()?;
+                let size = *sizes.get(&id).ok_or_else(|| {
+                    invalid(
+                        r.at,
+                        format!("digest algorithm {id:#06x} not in the header"),
+                    )
+                })?;
+                let digest = r.take(size)?;
+                digests.extend(Bank::from_alg_id(id).map(|b| (b, digest.to_vec())));
+            }
+            let len = r.u32()? as usize;
+            let data = r.take(len)?.to_vec();
+            log.push(Event {
+                pcr,
+                kind,
+                digests,
+                data,
+            });
+        }
+        Ok(log)
+    }
+
+    fn parse_legacy(first: Event, mut r: Reader) -> Result<Self> {
+        let mut log = Self {
+            banks: vec![Bank::Sha1],
+            events: Vec::new(),
+            startup_locality: 0,
+        };
+        log.push(first);
+        while !r.done() {
+            let event = r.legacy_event()?;
+            log.push(event);
+        }
+        Ok(log)
+    }
+
+    fn push(&mut self, event: Event) {
+        if event.kind == EV_NO_ACTION && event.pcr == 0 {
+            if let Some(rest) = event.data.strip_prefix(STARTUP_LOCALITY) {
+                self.startup_locality = rest.first().copied().unwrap_or(0);
+            }
+        }
+        self.events.push(event);
+    }
+
+    /// Every PCR the log extends in `bank`, recomputed from its events.
+    /// `None` if the log has no digests for `bank`.
+    pub fn replay(&self, bank: Bank) -> Option<BTreeMap<u32, Vec<u8>>> {
+        if !self.banks.contains(&bank) {
+            return None;
+        }
+        let mut pcrs = BTreeMap::new();
+        for e in &self.events {
+            if e.kind == EV_NO_ACTION || e.pcr >= PCRS {
+                continue;
+            }
+            let Some(digest) = e.digest(bank) else {
+                continue;
+            };
+            let pcr = pcrs.entry(e.pcr).or_insert_with(|| {
+                let mut initial = vec![0; bank.size()];
+                if e.pcr == 0 {
+                    initial[bank.size() - 1] = self.startup_locality;
+                }
+                initial
+            });
+            *pcr = bank.extend(pcr, digest);
+        }
+        Some(pcrs)
+    }
+
+    /// The events that extended `pcr`, in order.
+    pub fn measurements(&self, pcr: u32) -> impl Iterator<Item = &Event> {
+        self.events
+            .iter()
+            .filter(move |e| e.pcr == pcr && e.kind != EV_NO_ACTION)
+    }
+}
+
+/// One extension of a predicted PCR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Measurement {
+    /// What was hashed: `.linux` (the name) or `.linux data`.
+    pub label: String,
+    pub digest: Vec<u8>,
+}
+
+/// What systemd-stub measures into PCR 11 for `uki`, in order.
+pub fn pcr11(uki: &PeFile, bank: Bank) -> Result<Vec<Measurement>> {
+    let mut out = Vec::new();
+    for &name in PCR11_SECTIONS {
+        let Some(section) = uki.find_section(name)? else {
+            continue;
+        };
+        let mut tagged = name.as_bytes().to_vec();
+        tagged.push(0);
+        out.push(Measurement {
+            label: name.to_string(),
+            digest: bank.hash(&[&tagged]),
+        });
+        out.push(Measurement {
+            label: format!("{name} data"),
+            digest: bank.hash(&[section.contents()]),
+        });
+    }
+    Ok(out)
+}
+
+/// The digests firmware extends into PCR 4 for `uki`: the UKI's
+/// Authenticode digest, then its `.linux`'s if that is a PE image.
+pub fn pcr4(uki: &PeFile, bank: Bank) -> Result<Vec<Measurement>> {
+    let mut out = vec![Measurement {
+        label: "image".to_string(),
+        digest: bank.hash(&uki.authenticode_parts()?),
+    }];
+    let kernel = uki
+        .section_contents(".linux")?
+        .and_then(|k| PeFile::from_bytes(k.to_vec()).ok());
+    if let Some(kernel) = kernel {
+        out.push(Measurement {
+            label: ".linux".to_string(),
+            digest: bank.hash(&kernel.authenticode_parts()?),
+        });
+    }
+    Ok(out)
+}
+
+/// A PCR starting from zero, extended with each of `measurements`.
+pub fn predict(bank: Bank, measurements: &[Measurement]) -> Vec<u8> {
+    measurements
+        .iter()
+        .fold(vec![0; bank.size()], |pcr, m| bank.extend(&pcr, &m.digest))
+}
+
+/// Lowercase hex of `bytes`.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn invalid(offset: usize, msg: impl fmt::Display) -> Error {
+    Error::EventLog(format!("event log at offset {offset:#x}: {msg}"))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn done(&self) -> bool {
+        self.at >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let out = self
+            .at
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.at..end))
+            .ok_or_else(|| invalid(self.at, format!("truncated ({len} bytes wanted)")))?;
+        self.at += len;
+        Ok(out)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// A `TCG_PCR_EVENT`: PCR, type, SHA-1 digest, data.
+    fn legacy_event(&mut self) -> Result<Event> {
+        let (pcr, kind) = (self.u32()?, self.u32()?);
+        let digest = self.take(20)?.to_vec();
+        let len = self.u32()? as usize;
+        let data = self.take(len)?.to_vec();
+        Ok(Event {
+            pcr,
+            kind,
+            digests: vec![(Bank::Sha1, digest)],
+            data,
+        })
+    }
+}
+
+/// Build event logs for tests: the crypto-agile format, SHA-1 and SHA-256
+/// banks.
+#[cfg(test)]
+pub(crate) fn agile_log(events: &[(u32, u32, &[u8])]) -> Vec<u8> {
+    let mut spec = SPEC_ID.to_vec();
+    spec.extend([0, 0, 0, 0, 0, 2, 0, 2]);
+    spec.extend(2u32.to_le_bytes());
+    for bank in [Bank::Sha1, Bank::Sha256] {
+        spec.extend(bank.alg_id().to_le_bytes());
+        spec.extend((bank.size() as u16).to_le_bytes());
+    }
+    spec.push(0);
+    let mut log = Vec::new();
+    log.extend(0u32.to_le_bytes());
+    log.extend(EV_NO_ACTION.to_le_bytes());
+    log.extend([0; 20]);
+    log.extend((spec.len() as u32).to_le_bytes());
+    log.extend(spec);
+    for &(pcr, kind, data) in events {
+        log.extend(pcr.to_le_bytes());
+        log.extend(kind.to_le_bytes());
+        log.extend(2u32.to_le_bytes());
+        for bank in [Bank::Sha1, Bank::Sha256] {
+            log.extend(bank.alg_id().to_le_bytes());
+            log.extend(bank.hash(&[data]));
+        }
+        log.extend((data.len() as u32).to_le_bytes());
+        log.extend(data);
+    }
+    log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::UkiBuilder;
+
+    #[test]
+    fn logs_replay_per_bank() {
+        let mut locality = STARTUP_LOCALITY.to_vec();
+        locality.push(3);
+        let bytes = agile_log(&[
+            (0, EV_NO_ACTION, &locality),
+            (0, 0x8, b"crtm"),
+            (4, EV_EFI_BOOT_SERVICES_APPLICATION, b"shim"),
+            (4, EV_EFI_BOOT_SERVICES_APPLICATION, b"uki"),
+        ]);
+        let log = EventLog::parse(&bytes).unwrap();
+        assert_eq!(log.banks, [Bank::Sha1, Bank::Sha256]);
+        assert_eq!((log.events.len(), log.startup_locality), (4, 3));
+
+        let pcrs = log.replay(Bank::Sha256).unwrap();
+        let b = Bank::Sha256;
+        let pcr4 = b.extend(&b.extend(&[0; 32], &b.hash(&[b"shim"])), &b.hash(&[b"uki"]));
+        assert_eq!(pcrs[&4], pcr4);
+        let mut start = [0u8; 32];
+        start[31] = 3;
+        assert_eq!(pcrs[&0], b.extend(&start, &b.hash(&[b"crtm"])));
+        assert_eq!(log.replay(Bank::Sha1).unwrap()[&4].len(), 20);
+        assert!(log.replay(Bank::Sha384).is_none());
+        assert_eq!(log.measurements(4).count(), 2);
+
+        let err = EventLog::parse(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(err.code(), crate::error::ErrorCode::BadEventLog);
+    }
+
+    #[test]
+    fn pcr11_follows_stub_order() {
+        let uki = UkiBuilder::new()
+            .initrd(b"initrd")
+            .cmdline("quiet")
+            .linux(b"kernel")
+            .build();
+        let pe = PeFile::from_bytes(uki).unwrap();
+        let b = Bank::Sha256;
+        let m = pcr11(&pe, b).unwrap();
+        let labels: Vec<_> = m.iter().map(|m| m.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                ".linux",
+                ".linux data",
+                ".cmdline",
+                ".cmdline data",
+                ".initrd",
+                ".initrd data"
+            ]
+        );
+        assert_eq!(m[0].digest, b.hash(&[b".linux\0"]));
+        assert_eq!(m[1].digest, b.hash(&[b"kernel"]));
+
+        let mut pcr = vec![0; 32];
+        for part in [
+            &b".linux\0"[..],
+            b"kernel",
+            b".cmdline\0",
+            pe.section_contents(".cmdline").unwrap().unwrap(),
+            b".initrd\0",
+            b"initrd",
+        ] {
+            pcr = b.extend(&pcr, &b.hash(&[part]));
+        }
+        assert_eq!(predict(b, &m), pcr);
+    }
+}
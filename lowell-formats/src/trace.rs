@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! `tracing` macros, or no-ops without the `tracing` feature.
+//!
+//! Modules in all lowell crates import `debug!`, `debug_span!`, `trace!`
+//! from here rather than from `tracing`. With the feature off the macros expand to `if false`
+//! blocks: field expressions still type-check (so values computed only
+//! for a log line don't warn as unused) but nothing runs, and the crate
+//! does not depend on `tracing` at all.
+//!
+//! Conventions: one `debug_span!` per phase (parse, segment, build step),
+//! `debug!` for a phase's result with `elapsed_ms` and sizes, `trace!` for
+//! per-entry detail.
+
+#[cfg(feature = "tracing")]
+pub use tracing::{debug, debug_span, trace};
+
+/// Stand-in for `tracing::Span` (and its guard) when tracing is off.
+#[cfg(not(feature = "tracing"))]
+pub struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub fn entered(self) -> Self {
+        self
+    }
+}
+
+/// Mention every field expression of a `tracing` macro call, without
+/// evaluating it (the caller wraps this in `if false`).
+#[cfg(not(feature = "tracing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_fields {
+    () => {};
+    ($k:ident = % $v:expr $(, $($rest:tt)*)?) => { let _ = &$v; $($crate::__trace_fields!($($rest)*);)? };
+    ($k:ident = ? $v:expr $(, $($rest:tt)*)?) => { let _ = &$v; $($crate::__trace_fields!($($rest)*);)? };
+    ($k:ident = $v:expr $(, $($rest:tt)*)?) => { let _ = &$v; $($crate::__trace_fields!($($rest)*);)? };
+    (% $k:ident $(, $($rest:tt)*)?) => { let _ = &$k; $($crate::__trace_fields!($($rest)*);)? };
+    (? $k:ident $(, $($rest:tt)*)?) => { let _ = &$k; $($crate::__trace_fields!($($rest)*);)? };
+    ($k:ident $(, $($rest:tt)*)?) => { let _ = &$k; $($crate::__trace_fields!($($rest)*);)? };
+    ($msg:literal $(, $arg:expr)* $(,)?) => { $(let _ = &$arg;)* };
+}
+
+#[cfg(not(feature = "tracing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_debug {
+    ($($t:tt)*) => {
+        if false {
+            $crate::__trace_fields!($($t)*);
+        }
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_trace {
+    ($($t:tt)*) => {
+        if false {
+            $crate::__trace_fields!($($t)*);
+        }
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_debug_span {
+    ($name:literal $(, $($t:tt)*)?) => {{
+        if false {
+            $($crate::__trace_fields!($($t)*);)?
+        }
+        $crate::trace::Span
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub use crate::{__trace_debug as debug, __trace_debug_span as debug_span, __trace_trace as trace};
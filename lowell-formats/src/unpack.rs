@@ -0,0 +1,305 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Safe extraction of an initramfs into a directory.
+//!
+//! Archives are untrusted input, so every entry is confined to the
+//! destination:
+//! - `..` components are rejected; leading `/` and `./` are dropped.
+//! - Parent directories are created one component at a time and must be
+//!   real directories, so a symlink planted by an earlier entry can never
+//!   redirect a later write outside the tree.
+//! - Existing files and symlinks at a target path are replaced, never
+//!   written through.
+//! - Device nodes, FIFOs and sockets are skipped.
+//!
+//! Modes and mtimes are restored; setuid/setgid bits and ownership only
+//! with [`UnpackOptions::preserve_owner`] (normally requires root). newc
+//! archives carry no extended attributes, so there are none to restore.
+
+use super::cpio::{self, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+use super::initramfs;
+use crate::error::{Error, IoResultExt, Result};
+use crate::trace::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Default)]
+pub struct UnpackOptions {
+    /// Only extract entries below this archive path, with the prefix removed.
+    pub strip_prefix: Option<PathBuf>,
+    /// Restore uid/gid and setuid/setgid bits.
+    pub preserve_owner: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnpackStats {
+    pub files: usize,
+    pub dirs: usize,
+    pub symlinks: usize,
+    /// Special files and entries outside `strip_prefix`.
+    pub skipped: usize,
+}
+
+/// Unpack every segment of `bytes` (see [`initramfs::walk`]) into `dest`.
+pub fn unpack(bytes: &[u8], dest: &Path, opts: &UnpackOptions) -> Result<UnpackStats> {
+    fs::create_dir_all(dest).at("create", dest)?;
+    let mut stats = UnpackStats::default();
+    // Directory metadata is applied last: a read-only dir must stay
+    // writable while its children are extracted.
+    let mut dirs: Vec<(PathBuf, cpio::Header)> = Vec::new();
+    // Hard links: newc stores the data only with the last name of an inode.
+    let mut links: HashMap<(u32, u32, u32), Vec<PathBuf>> = HashMap::new();
+
+    initramfs::walk(bytes, |h, data| {
+        let Some(rel) = relative_path(&h.name, opts.strip_prefix.as_deref())? else {
+            stats.skipped += 1;
+            return Ok(());
+        };
+        if rel.as_os_str().is_empty() {
+            // The root itself (`.`, or the stripped prefix directory).
+            return Ok(());
+        }
+        let target = dest.join(&rel);
+        create_parents(dest, &rel)?;
+
+        match h.mode & S_IFMT {
+            S_IFDIR => {
+                match fs::symlink_metadata(&target) {
+                    Ok(m) if m.is_dir() => {}
+                    Ok(_) => {
+                        fs::remove_file(&target)?;
+                        fs::create_dir(&target)?;
+                    }
+                    Err(_) => fs::create_dir(&target).at("create", &target)?,
+                }
+                dirs.push((target, h.clone()));
+                stats.dirs += 1;
+            }
+            S_IFREG => {
+                remove_existing(&target)?;
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&target)
+                    .at("create", &target)?;
+                io::copy(data, &mut file).at("write", &target)?;
+                file.set_modified(mtime(h))?;
+                drop(file);
+                apply_mode_and_owner(&target, h, opts)?;
+
+                if h.nlink > 1 {
+                    let key = (h.devmajor, h.devminor, h.ino);
+                    if h.filesize == 0 {
+                        links.entry(key).or_default().push(target);
+                    } else {
+                        for alias in links.remove(&key).unwrap_or_default() {
+                            fs::remove_file(&alias)?;
+                            fs::hard_link(&target, &alias).at("link", &alias)?;
+                        }
+                    }
+                }
+                stats.files += 1;
+            }
+            S_IFLNK => {
+                let mut link = String::new();
+                data.read_to_string(&mut link)
+                    .at("read link target of", Path::new(&h.name))?;
+                remove_existing(&target)?;
+                symlink(&link, &target)?;
+                stats.symlinks += 1;
+            }
+            _ => {
+                debug!(name = %h.name, mode = format!("{:o}", h.mode), "skipping special file");
+                stats.skipped += 1;
+            }
+        }
+        Ok(())
+    })?;
+
+    // Deepest first, so setting a parent's mtime is not undone by children.
+    dirs.sort_by_key(|(p, _)| std::cmp::Reverse(p.components().count()));
+    for (dir, h) in &dirs {
+        apply_mode_and_owner(dir, h, opts)?;
+        fs::File::open(dir)
+            .and_then(|f| f.set_modified(mtime(h)))
+            .at("set mtime on", dir)?;
+    }
+    Ok(stats)
+}
+
+/// Archive name → safe relative path (empty for the root), or `None` when
+/// the entry lies outside `strip_prefix`.
+fn relative_path(name: &str, strip_prefix: Option<&Path>) -> Result<Option<PathBuf>> {
+    let mut parts = Vec::new();
+    for c in Path::new(name).components() {
+        match c {
+            Component::Normal(p) => parts.push(p),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(Error::UnsafePath(format!(
+                    "refusing cpio entry {name:?}: path escapes the destination"
+                )))
+            }
+        }
+    }
+    let mut rel: PathBuf = parts.into_iter().collect();
+    if let Some(prefix) = strip_prefix {
+        let prefix: PathBuf = prefix
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        match rel.strip_prefix(&prefix) {
+            Ok(rest) => rel = rest.to_path_buf(),
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(Some(rel))
+}
+
+/// Create missing ancestors of `rel` under `dest`, refusing symlinks.
+fn create_parents(dest: &Path, rel: &Path) -> Result<()> {
+    let mut dir = dest.to_path_buf();
+    let Some(parent) = rel.parent() else {
+        return Ok(());
+    };
+    for c in parent.components() {
+        dir.push(c);
+        match fs::symlink_metadata(&dir) {
+            Ok(m) if m.is_dir() => {}
+            Ok(m) if m.file_type().is_symlink() => {
+                return Err(Error::UnsafePath(format!(
+                    "refusing cpio entry {}: parent {} is a symlink",
+                    rel.display(),
+                    dir.display()
+                )))
+            }
+            Ok(_) => {
+                return Err(Error::Conflict {
+                    path: dir,
+                    what: "exists and is not a directory",
+                })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir(&dir).at("create", &dir)?
+            }
+            Err(e) => return Err(e).at("stat", &dir),
+        }
+    }
+    Ok(())
+}
+
+/// Remove a file or symlink at `path` (without following it).
+fn remove_existing(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(m) if m.is_dir() => Err(Error::Conflict {
+            path: path.to_path_buf(),
+            what: "already exists as a directory",
+        }),
+        Ok(_) => fs::remove_file(path).at("remove", path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).at("stat", path),
+    }
+}
+
+fn mtime(h: &cpio::Header) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(u64::from(h.mtime))
+}
+
+#[cfg(unix)]
+fn apply_mode_and_owner(path: &Path, h: &cpio::Header, opts: &UnpackOptions) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mask = if opts.preserve_owner { 0o7777 } else { 0o1777 };
+    if opts.preserve_owner {
+        std::os::unix::fs::chown(path, Some(h.uid), Some(h.gid)).at("chown", path)?;
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(h.mode & mask)).at("chmod", path)
+}
+
+#[cfg(not(unix))]
+fn apply_mode_and_owner(_: &Path, _: &cpio::Header, _: &UnpackOptions) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(link: &str, target: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(link, target).at("symlink", target)
+}
+
+#[cfg(not(unix))]
+fn symlink(link: &str, target: &Path) -> Result<()> {
+    debug!(path = %target.display(), link, "symlinks unsupported here; skipped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::newc;
+
+    #[test]
+    fn unpacks_tree_with_modes_links_and_prefix() {
+        let archive = newc(&[
+            (".", S_IFDIR | 0o755, b""),
+            ("usr/lib/modules", S_IFDIR | 0o555, b""),
+            ("usr/lib/modules/a.ko", S_IFREG | 0o4644, b"ko"),
+            ("bin", S_IFLNK | 0o777, b"usr/bin"),
+            ("dev/console", 0o020600, b""),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let stats = unpack(&archive, dir.path(), &UnpackOptions::default()).unwrap();
+        assert_eq!(
+            stats,
+            UnpackStats {
+                files: 1,
+                dirs: 1,
+                symlinks: 1,
+                skipped: 1
+            }
+        );
+        let ko = dir.path().join("usr/lib/modules/a.ko");
+        assert_eq!(fs::read(&ko).unwrap(), b"ko");
+        assert_eq!(
+            fs::read_link(dir.path().join("bin")).unwrap(),
+            Path::new("usr/bin")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&ko).unwrap().permissions().mode();
+            assert_eq!(
+                mode & 0o7777,
+                0o644,
+                "setuid dropped without preserve_owner"
+            );
+        }
+
+        let sub = tempfile::tempdir().unwrap();
+        let opts = UnpackOptions {
+            strip_prefix: Some("usr/lib".into()),
+            ..Default::default()
+        };
+        let stats = unpack(&archive, sub.path(), &opts).unwrap();
+        assert_eq!((stats.files, stats.dirs, stats.skipped), (1, 1, 3));
+        assert!(sub.path().join("modules/a.ko").is_file());
+    }
+
+    #[test]
+    fn refuses_traversal_and_symlinked_parents() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+
+        let dotdot = newc(&[("../evil", S_IFREG | 0o644, b"x")]);
+        assert!(unpack(&dotdot, dir.path(), &UnpackOptions::default()).is_err());
+
+        let target = outside.path().to_str().unwrap().as_bytes();
+        let planted = newc(&[
+            ("lib", S_IFLNK | 0o777, target),
+            ("lib/evil", S_IFREG | 0o644, b"x"),
+        ]);
+        assert!(unpack(&planted, dir.path(), &UnpackOptions::default()).is_err());
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,301 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! File reads through io_uring (Linux, `io-uring` feature).
+//!
+//! Two shapes of read benefit from keeping many requests in flight: a
+//! large file read in chunks (build inputs), and a batch of small files
+//! (the tree behind a directory initrd). Both functions return `None` when
+//! io_uring cannot be used — another OS, the feature off, or a kernel or
+//! seccomp policy that refuses `io_uring_setup` — and the caller reads the
+//! ordinary way. File sizes are taken when the read starts.
+
+use crate::error::Result;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Read all `len` bytes of `file` (opened from `path`), several chunks at
+/// a time, calling `progress` with the running total as chunks land; an
+/// error from `progress` stops the read.
+pub fn read_sized(
+    file: &File,
+    path: &Path,
+    len: u64,
+    progress: &mut dyn FnMut(u64) -> Result<()>,
+) -> Option<Result<Vec<u8>>> {
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    return ring::read_sized(file, path, len, progress);
+    #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+    {
+        let _ = (file, path, len, progress);
+        None
+    }
+}
+
+/// Read each of `paths` whole, in one batch; results are in `paths` order.
+pub fn read_many(paths: &[&Path]) -> Option<Vec<io::Result<Vec<u8>>>> {
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    return ring::read_many(paths);
+    #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+    {
+        let _ = paths;
+        None
+    }
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod ring {
+    use crate::error::{Error, IoResultExt, Result};
+    use crate::trace::debug;
+    use io_uring::{opcode, types, IoUring};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::path::Path;
+
+    /// Requests in flight at once.
+    const DEPTH: u32 = 32;
+    /// Bytes per request when reading one large file.
+    const CHUNK: usize = 1 << 20;
+    const EINTR: i32 = 4;
+    const EAGAIN: i32 = 11;
+    const EBUSY: i32 = 16;
+
+    thread_local! {
+        static RING: RefCell<Option<IoUring>> = RefCell::new(
+            IoUring::new(DEPTH)
+                .inspect_err(|e| debug!(error = %e, "io_uring unavailable"))
+                .ok(),
+        );
+    }
+
+    fn with_ring<T>(f: impl FnOnce(&mut IoUring) -> T) -> Option<T> {
+        RING.with(|ring| {
+            let mut slot = ring.try_borrow_mut().ok()?;
+            let out = f(slot.as_mut()?);
+            // A failed `io_uring_enter` can leave requests queued that the
+            // kernel never took; their buffers are gone, so the ring goes
+            // with them and later reads on this thread take the plain path.
+            if slot.as_mut().is_some_and(|r| !r.submission().is_empty()) {
+                *slot = None;
+            }
+            Some(out)
+        })
+    }
+
+    /// One read: `buf` fills from `offset` of `fd`.
+    struct Job<'a> {
+        fd: RawFd,
+        offset: u64,
+        buf: &'a mut [u8],
+        done: usize,
+        /// Hit end of file before `buf` was full.
+        short: bool,
+        error: Option<io::Error>,
+    }
+
+    impl<'a> Job<'a> {
+        fn new(fd: RawFd, offset: u64, buf: &'a mut [u8]) -> Self {
+            Self {
+                fd,
+                offset,
+                buf,
+                done: 0,
+                short: false,
+                error: None,
+            }
+        }
+    }
+
+    pub(super) fn read_sized(
+        file: &File,
+        path: &Path,
+        len: u64,
+        progress: &mut dyn FnMut(u64) -> Result<()>,
+    ) -> Option<Result<Vec<u8>>> {
+        let mut data = vec![0; usize::try_from(len).ok()?];
+        let fd = file.as_raw_fd();
+        let mut jobs: Vec<Job> = data
+            .chunks_mut(CHUNK)
+            .enumerate()
+            .map(|(i, buf)| Job::new(fd, (i * CHUNK) as u64, buf))
+            .collect();
+        let mut total = 0u64;
+        let result = with_ring(|ring| {
+            run(ring, &mut jobs, &mut |n| {
+                total += n as u64;
+                progress(total)
+            })
+        })?;
+        // Chunks are contiguous: the first short or failed one ends it.
+        let end = result.and_then(|()| {
+            let mut end = 0;
+            for job in jobs {
+                if let Some(e) = job.error {
+                    return Err(e).at("read", path);
+                }
+                end += job.done;
+                if job.short {
+                    break;
+                }
+            }
+            Ok(end)
+        });
+        Some(end.map(|end| {
+            data.truncate(end);
+            data
+        }))
+    }
+
+    pub(super) fn read_many(paths: &[&Path]) -> Option<Vec<io::Result<Vec<u8>>>> {
+        // Open and size everything first; failures there are per-file results.
+        let mut files: Vec<io::Result<(File, Vec<u8>)>> = paths
+            .iter()
+            .map(|p| {
+                let file = File::open(p)?;
+                let len = usize::try_from(file.metadata()?.len()).map_err(io::Error::other)?;
+                Ok((file, vec![0; len]))
+            })
+            .collect();
+        let mut jobs: Vec<Job> = files
+            .iter_mut()
+            .filter_map(|f| {
+                let (file, buf) = f.as_mut().ok()?;
+                Some(Job::new(file.as_raw_fd(), 0, buf))
+            })
+            .collect();
+        with_ring(|ring| run(ring, &mut jobs, &mut |_| Ok(())))?.ok()?;
+        let outcomes: Vec<_> = jobs.into_iter().map(|j| (j.done, j.error)).collect();
+        let mut outcomes = outcomes.into_iter();
+        Some(
+            files
+                .into_iter()
+                .map(|f| {
+                    let (_, mut buf) = f?;
+                    let (done, error) = outcomes.next().expect("a job per opened file");
+                    if let Some(e) = error {
+                        return Err(e);
+                    }
+                    buf.truncate(done);
+                    Ok(buf)
+                })
+                .collect(),
+        )
+    }
+
+    /// Drive `jobs` to completion with up to [`DEPTH`] reads in flight,
+    /// resubmitting short reads. `progress` sees each completion's byte
+    /// count; once it fails, nothing new is submitted, and the error is
+    /// returned after every request in flight has completed (the kernel
+    /// writes into the buffers until then). So is a failed
+    /// `io_uring_enter`, once the requests the kernel took have completed.
+    fn run(
+        ring: &mut IoUring,
+        jobs: &mut [Job],
+        progress: &mut dyn FnMut(usize) -> Result<()>,
+    ) -> Result<()> {
+        let mut queue: VecDeque<usize> = (0..jobs.len())
+            .filter(|&i| !jobs[i].buf.is_empty())
+            .collect();
+        let mut in_flight = 0u32;
+        let mut stopped: Option<Error> = None;
+        loop {
+            while stopped.is_none() && in_flight < DEPTH {
+                let Some(i) = queue.pop_front() else { break };
+                let job = &mut jobs[i];
+                let rest = &mut job.buf[job.done..];
+                let len = u32::try_from(rest.len()).unwrap_or(u32::MAX);
+                let read = opcode::Read::new(types::Fd(job.fd), rest.as_mut_ptr(), len)
+                    .offset(job.offset + job.done as u64)
+                    .build()
+                    .user_data(i as u64);
+                // SAFETY: the buffer and fd outlive the request: `run`
+                // does not return while anything is in flight.
+                if unsafe { ring.submission().push(&read) }.is_err() {
+                    queue.push_front(i);
+                    break;
+                }
+                in_flight += 1;
+            }
+            if in_flight == 0 {
+                break;
+            }
+            if let Err(e) = ring.submit_and_wait(1) {
+                if matches!(e.raw_os_error(), Some(EINTR | EAGAIN | EBUSY)) {
+                    continue;
+                }
+                // Requests the kernel took may still be writing into the
+                // buffers: wait them out without entering the kernel
+                // again. Those it did not take are never submitted.
+                let mut pending = in_flight as usize - ring.submission().len();
+                while pending > 0 {
+                    match ring.completion().count() {
+                        0 => std::thread::sleep(std::time::Duration::from_millis(1)),
+                        n => pending = pending.saturating_sub(n),
+                    }
+                }
+                return Err(Error::Io(e));
+            }
+            let done: Vec<_> = ring
+                .completion()
+                .map(|c| (c.user_data(), c.result()))
+                .collect();
+            for (i, res) in done {
+                in_flight -= 1;
+                let job = &mut jobs[i as usize];
+                match res {
+                    n if n > 0 => {
+                        job.done += n as usize;
+                        if job.done < job.buf.len() {
+                            queue.push_back(i as usize);
+                        }
+                        if stopped.is_none() {
+                            stopped = progress(n as usize).err();
+                        }
+                    }
+                    0 => job.short = true,
+                    e if -e == EINTR || -e == EAGAIN => queue.push_back(i as usize),
+                    e => job.error = Some(io::Error::from_raw_os_error(-e)),
+                }
+            }
+        }
+        stopped.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_match_plain_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let (big, small) = (dir.path().join("big"), dir.path().join("small"));
+        let data: Vec<u8> = (0..(3 << 20) + 5).map(|i| i as u8).collect();
+        std::fs::write(&big, &data).unwrap();
+        std::fs::write(&small, b"tiny").unwrap();
+
+        let file = File::open(&big).unwrap();
+        let mut seen = 0;
+        let mut progress = |done| {
+            seen = done;
+            Ok(())
+        };
+        // `None` where io_uring is off or refused: nothing to compare.
+        if let Some(read) = read_sized(&file, &big, data.len() as u64, &mut progress) {
+            assert_eq!(read.unwrap(), data);
+            assert_eq!(seen, data.len() as u64);
+        }
+
+        let missing = dir.path().join("missing");
+        if let Some(reads) = read_many(&[&small, &missing, &big]) {
+            assert_eq!(reads[0].as_deref().unwrap(), b"tiny");
+            assert_eq!(
+                reads[1].as_ref().unwrap_err().kind(),
+                io::ErrorKind::NotFound
+            );
+            assert_eq!(reads[2].as_deref().unwrap(), data);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Handlers for PE sections lowell does not know about.
+//!
+//! UKI inspect reads `.linux`, `.initrd`, `.cmdline` and `.osrel` itself.
+//! Anything else, say a vendor `.ident` section, can be described by a
+//! [`SectionHandler`] registered in [`SectionHandlers`] and passed to
+//! [`InspectOptions::handlers`](crate::uki::InspectOptions::handlers). Each
+//! handler turns its section into [`Fields`], which land in the report's
+//! `sections` map under the section name, and [`SectionHandlers::diff`]
+//! compares two images field by field.
+//!
+//! ```
+//! use lowell_inspect::handler::{SectionHandlers, TextSection};
+//!
+//! let mut handlers = SectionHandlers::new();
+//! handlers.register(TextSection::new(".ident"));
+//! ```
+
+use lowell_formats::error::Result;
+use lowell_formats::pe::PeFile;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// What a handler reports for its section: flat, ordered key/value pairs,
+/// so reports stay plain JSON objects and diffs can go key by key.
+pub type Fields = BTreeMap<String, String>;
+
+pub trait SectionHandler: Send + Sync {
+    /// The section this handler reads, e.g. `.ident`.
+    fn section(&self) -> &str;
+
+    /// Describe the section's contents (padding already trimmed).
+    fn inspect(&self, contents: &[u8]) -> Result<Fields>;
+
+    /// Changes between two versions of the section. The default compares
+    /// [`inspect`](Self::inspect) output key by key; override it when a
+    /// field-level comparison says too little.
+    fn diff(&self, old: &[u8], new: &[u8]) -> Result<Vec<FieldChange>> {
+        Ok(diff_fields(&self.inspect(old)?, &self.inspect(new)?))
+    }
+}
+
+impl fmt::Debug for dyn SectionHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SectionHandler({})", self.section())
+    }
+}
+
+/// One field that differs between two images; `None` where it is absent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Key-by-key difference of two field maps, in key order.
+pub fn diff_fields(old: &Fields, new: &Fields) -> Vec<FieldChange> {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|k| old.get(*k) != new.get(*k))
+        .map(|k| FieldChange {
+            field: k.clone(),
+            old: old.get(k).cloned(),
+            new: new.get(k).cloned(),
+        })
+        .collect()
+}
+
+/// The handlers to run, at most one per section name.
+#[derive(Debug, Clone, Default)]
+pub struct SectionHandlers(Vec<Arc<dyn SectionHandler>>);
+
+impl SectionHandlers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `handler`, replacing any earlier one for the same section.
+    pub fn register(&mut self, handler: impl SectionHandler + 'static) -> &mut Self {
+        self.0.retain(|h| h.section() != handler.section());
+        self.0.push(Arc::new(handler));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Run every handler whose section is present in `pe`.
+    pub fn inspect(&self, pe: &PeFile) -> Result<BTreeMap<String, Fields>> {
+        let mut out = BTreeMap::new();
+        for h in &self.0 {
+            if let Some(s) = pe.find_section(h.section())? {
+                out.insert(h.section().to_string(), h.inspect(s.contents())?);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Per-section changes between `old` and `new`; sections without
+    /// changes are left out. A section present on one side only diffs
+    /// against empty contents.
+    pub fn diff(&self, old: &PeFile, new: &PeFile) -> Result<BTreeMap<String, Vec<FieldChange>>> {
+        let mut out = BTreeMap::new();
+        for h in &self.0 {
+            let (a, b) = (
+                old.find_section(h.section())?,
+                new.find_section(h.section())?,
+            );
+            if a.is_none() && b.is_none() {
+                continue;
+            }
+            let a = a.map_or(&[][..], |s| s.contents());
+            let b = b.map_or(&[][..], |s| s.contents());
+            let changes = h.diff(a, b)?;
+            if !changes.is_empty() {
+                out.insert(h.section().to_string(), changes);
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// A section holding text (up to the first NUL), reported as `text`.
+#[derive(Debug, Clone)]
+pub struct TextSection(String);
+
+impl TextSection {
+    pub fn new(section: impl Into<String>) -> Self {
+        Self(section.into())
+    }
+}
+
+impl SectionHandler for TextSection {
+    fn section(&self) -> &str {
+        &self.0
+    }
+
+    fn inspect(&self, contents: &[u8]) -> Result<Fields> {
+        let end = contents
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(contents.len());
+        let text = String::from_utf8_lossy(&contents[..end])
+            .trim_end()
+            .to_string();
+        Ok(Fields::from([("text".to_string(), text)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::UkiBuilder;
+
+    /// `KEY=value` lines, one field each.
+    struct Ident;
+
+    impl SectionHandler for Ident {
+        fn section(&self) -> &str {
+            ".ident"
+        }
+
+        fn inspect(&self, contents: &[u8]) -> Result<Fields> {
+            Ok(String::from_utf8_lossy(contents)
+                .lines()
+                .filter_map(|l| l.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn handlers_report_and_diff_their_sections() {
+        let pe = |ident: &str| {
+            PeFile::from_bytes(
+                UkiBuilder::new()
+                    .section(".ident", ident.as_bytes())
+                    .build(),
+            )
+            .unwrap()
+        };
+        let (old, new) = (pe("VENDOR=acme\nBUILD=1\n"), pe("VENDOR=acme\nBUILD=2\n"));
+        let mut handlers = SectionHandlers::new();
+        handlers
+            .register(TextSection::new(".ident"))
+            .register(Ident);
+        handlers.register(TextSection::new(".absent"));
+
+        let report = handlers.inspect(&old).unwrap();
+        assert_eq!(report.len(), 1, "Ident replaced the text handler");
+        assert_eq!(report[".ident"]["BUILD"], "1");
+
+        let diff = handlers.diff(&old, &new).unwrap();
+        assert_eq!(
+            diff[".ident"],
+            [FieldChange {
+                field: "BUILD".into(),
+                old: Some("1".into()),
+                new: Some("2".into()),
+            }]
+        );
+        assert!(handlers.diff(&old, &old).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Inspect a bare initramfs file (split kernel + initrd boot flows).
+
+use lowell_formats::cpio;
+use lowell_formats::digest::Algorithm;
+use lowell_formats::error::Result;
+use lowell_formats::initramfs::{self, Compression};
+use lowell_formats::mapped::Mapped;
+use lowell_formats::timing::Stopwatch;
+use lowell_formats::trace::{debug, debug_span};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    pub size: usize,
+    pub sha256: String,
+    /// Compression of the main archive (past any early-microcode cpio).
+    pub compression: Compression,
+    /// cpio entries across all segments, trailers excluded.
+    pub entries: usize,
+    /// The concatenated archives, in order; see [`initramfs::segments`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub segments: Vec<initramfs::Segment>,
+}
+
+/// One cpio entry, as handed out by [`list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    /// Path as stored in the archive.
+    pub path: String,
+    pub kind: EntryKind,
+    /// Permission bits (`mode & 0o7777`).
+    pub mode: u32,
+    pub size: u64,
+    /// Link target, for symlinks.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    /// Device nodes, fifos, sockets.
+    Other,
+}
+
+/// [`inspect`] on tokio's blocking pool, so hashing a large initramfs
+/// does not stall the executor.
+#[cfg(feature = "tokio")]
+pub async fn inspect_async(path: impl Into<std::path::PathBuf>) -> Result<Report> {
+    let path = path.into();
+    lowell_formats::task::blocking(move || inspect(&path)).await
+}
+
+pub fn inspect(path: &Path) -> Result<Report> {
+    let _span = debug_span!("inspect_initrd", path = %path.display()).entered();
+    inspect_bytes(&Mapped::open(path)?)
+}
+
+/// Inspect an initramfs already in memory.
+pub fn inspect_bytes(bytes: &[u8]) -> Result<Report> {
+    let t = Stopwatch::start();
+    let sha256 = Algorithm::Sha256.digest(bytes).hex;
+    debug!(size = bytes.len(), elapsed_ms = t.ms(), "sha256");
+
+    let t = Stopwatch::start();
+    let segments = initramfs::segments(bytes)?;
+    let compression = segments
+        .last()
+        .map_or(Compression::Unknown, |s| s.compression);
+    let mut entries = 0usize;
+    initramfs::walk(bytes, |_, _| {
+        entries += 1;
+        Ok(())
+    })?;
+    debug!(entries, elapsed_ms = t.ms(), "walk");
+
+    Ok(Report {
+        size: bytes.len(),
+        sha256,
+        compression,
+        entries,
+        segments,
+    })
+}
+
+/// Call `f` with each entry of the initramfs at `path`, in archive order.
+///
+/// Entries are handed out as the walk reaches them and nothing is kept,
+/// so memory stays flat however many there are and a consumer can start
+/// on the first entry before the last is decompressed.
+pub fn list(path: &Path, f: impl FnMut(Entry) -> Result<()>) -> Result<()> {
+    let _span = debug_span!("list_initrd", path = %path.display()).entered();
+    list_bytes(&Mapped::open(path)?, f)
+}
+
+/// [`list`] for an initramfs already in memory.
+pub fn list_bytes(bytes: &[u8], mut f: impl FnMut(Entry) -> Result<()>) -> Result<()> {
+    initramfs::walk(bytes, |h, data| {
+        let kind = if h.is_file() {
+            EntryKind::File
+        } else if h.is_dir() {
+            EntryKind::Dir
+        } else if h.is_symlink() {
+            EntryKind::Symlink
+        } else {
+            EntryKind::Other
+        };
+        let target = if kind == EntryKind::Symlink {
+            let mut target = String::new();
+            data.read_to_string(&mut target)?;
+            Some(target)
+        } else {
+            None
+        };
+        f(Entry {
+            path: h.name.clone(),
+            kind,
+            mode: h.mode & !cpio::S_IFMT,
+            size: u64::from(h.filesize),
+            target,
+        })
+    })
+}
+
+/// How the contents of two initramfs images differ, by path, each list in
+/// path order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// In both, with a different type, mode, contents or link target.
+    pub modified: Vec<String>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Compare the entries of the initramfs images `old` and `new`. A path
+/// stored more than once (in several concatenated archives) counts with
+/// its last entry, the one the kernel leaves in place.
+pub fn diff_bytes(old: &[u8], new: &[u8]) -> Result<Diff> {
+    let (old, new) = (contents(old)?, contents(new)?);
+    let mut diff = Diff::default();
+    for (path, entry) in &old {
+        match new.get(path) {
+            None => diff.removed.push(path.clone()),
+            Some(other) if other != entry => diff.modified.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.added = new
+        .keys()
+        .filter(|p| !old.contains_key(*p))
+        .cloned()
+        .collect();
+    Ok(diff)
+}
+
+/// Where the uncompressed bytes of an initramfs go, as [`sizes`] adds
+/// them up: regular-file data only, each path counted with its last entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sizes {
+    pub total: u64,
+    pub files: usize,
+    /// Per top-level entry (a directory, or anything else at the root),
+    /// largest first.
+    pub top_level: Vec<SizeEntry>,
+    /// The largest files, largest first, as many as were asked for.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub largest: Vec<SizeEntry>,
+}
+
+/// A path and the file bytes under it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SizeEntry {
+    pub path: String,
+    pub size: u64,
+    /// Regular files at or under `path`.
+    pub files: usize,
+}
+
+/// Total the file sizes of the initramfs at `path` per top-level
+/// directory, and list its `largest` files.
+pub fn sizes(path: &Path, largest: usize) -> Result<Sizes> {
+    let _span = debug_span!("initrd_sizes", path = %path.display()).entered();
+    sizes_bytes(&Mapped::open(path)?, largest)
+}
+
+/// [`sizes`] for an initramfs already in memory.
+pub fn sizes_bytes(bytes: &[u8], largest: usize) -> Result<Sizes> {
+    // A later archive replaces what an earlier one stored at a path.
+    let mut entries = BTreeMap::new();
+    initramfs::walk(bytes, |h, _| {
+        let path = h.name.trim_start_matches("./").trim_start_matches('/');
+        if !path.is_empty() && path != "." {
+            let size = h.is_file().then_some(u64::from(h.filesize));
+            entries.insert(path.to_string(), size);
+        }
+        Ok(())
+    })?;
+
+    let mut sizes = Sizes::default();
+    let mut top_level: BTreeMap<&str, SizeEntry> = BTreeMap::new();
+    for (path, size) in &entries {
+        let top = path.split('/').next().unwrap_or(path);
+        let entry = top_level.entry(top).or_insert_with(|| SizeEntry {
+            path: top.to_string(),
+            size: 0,
+            files: 0,
+        });
+        if let Some(size) = *size {
+            entry.size += size;
+            entry.files += 1;
+            sizes.total += size;
+            sizes.files += 1;
+        }
+    }
+    let by_size = |a: &SizeEntry, b: &SizeEntry| b.size.cmp(&a.size).then(a.path.cmp(&b.path));
+    sizes.top_level = top_level.into_values().collect();
+    sizes.top_level.sort_by(by_size);
+    if largest > 0 {
+        sizes.largest = entries
+            .into_iter()
+            .filter_map(|(path, size)| {
+                Some(SizeEntry {
+                    path,
+                    size: size?,
+                    files: 1,
+                })
+            })
+            .collect();
+        sizes.largest.sort_by(by_size);
+        sizes.largest.truncate(largest);
+    }
+    Ok(sizes)
+}
+
+/// Each path's mode (type bits included) and the SHA-256 of its data.
+fn contents(bytes: &[u8]) -> Result<BTreeMap<String, (u32, String)>> {
+    let mut entries = BTreeMap::new();
+    initramfs::walk(bytes, |h, data| {
+        let mut buf = Vec::new();
+        data.read_to_end(&mut buf)?;
+        let path = h.name.trim_start_matches("./").to_string();
+        entries.insert(path, (h.mode, Algorithm::Sha256.digest(&buf).hex));
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_test_util::Initrd;
+
+    #[test]
+    fn list_streams_entries_in_order() {
+        let bytes = Initrd::new()
+            .dir("usr")
+            .file("usr/init", b"#!/bin/sh\n")
+            .symlink("init", "usr/init")
+            .build();
+        let mut seen = Vec::new();
+        list_bytes(&bytes, |e| {
+            seen.push(e);
+            Ok(())
+        })
+        .unwrap();
+        let kinds: Vec<_> = seen.iter().map(|e| (e.path.as_str(), e.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("usr", EntryKind::Dir),
+                ("usr/init", EntryKind::File),
+                ("init", EntryKind::Symlink),
+            ]
+        );
+        assert_eq!(seen[1].size, 10);
+        assert_eq!(seen[2].target.as_deref(), Some("usr/init"));
+    }
+
+    #[test]
+    fn sizes_add_up_per_top_level_entry() {
+        let early = Initrd::new()
+            .file("kernel/x86/microcode/GenuineIntel.bin", &[0; 100])
+            .build();
+        let main = Initrd::new()
+            .dir("usr")
+            .file("usr/lib/libc.so.6", &[0; 2000])
+            .file("usr/bin/sh", &[0; 500])
+            .file("./init", &[0; 10])
+            .symlink("bin", "usr/bin")
+            .file("etc/hostname", b"old\n")
+            .build();
+        let late = Initrd::new().file("etc/hostname", b"box\n").build();
+        let bytes = [early, main, late].concat();
+
+        let sizes = sizes_bytes(&bytes, 0).unwrap();
+        assert_eq!((sizes.total, sizes.files), (2614, 5));
+        let top: Vec<_> = sizes
+            .top_level
+            .iter()
+            .map(|e| (e.path.as_str(), e.size, e.files))
+            .collect();
+        assert_eq!(
+            top,
+            [
+                ("usr", 2500, 2),
+                ("kernel", 100, 1),
+                ("init", 10, 1),
+                ("etc", 4, 1),
+                ("bin", 0, 0),
+            ]
+        );
+        assert!(sizes.largest.is_empty());
+
+        let largest = sizes_bytes(&bytes, 2).unwrap().largest;
+        let paths: Vec<_> = largest.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["usr/lib/libc.so.6", "usr/bin/sh"]);
+    }
+
+    #[test]
+    fn diff_names_changed_paths() {
+        let old = Initrd::new()
+            .file("init", b"#!/bin/sh\n")
+            .file("etc/fstab", b"")
+            .symlink("bin", "usr/bin")
+            .build();
+        let new = Initrd::new()
+            .file("init", b"#!/bin/bash\n")
+            .symlink("bin", "usr/bin")
+            .file("etc/hostname", b"box\n")
+            .build();
+        let diff = diff_bytes(&old, &new).unwrap();
+        assert_eq!(diff.added, ["etc/hostname"]);
+        assert_eq!(diff.removed, ["etc/fstab"]);
+        assert_eq!(diff.modified, ["init"]);
+        assert!(diff_bytes(&old, &old).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Inspect a bare kernel image (bzImage, arm64 Image, EFI zboot).
+
+use lowell_formats::digest::Algorithm;
+use lowell_formats::error::{Error, Result};
+use lowell_formats::kernel::{self, KernelFormat};
+use lowell_formats::mapped::Mapped;
+use lowell_formats::timing::Stopwatch;
+use lowell_formats::trace::{debug, debug_span};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    pub format: KernelFormat,
+    pub arch: Option<String>,
+    /// `uname -r`-style release plus build info, from the version banner.
+    pub version: Option<String>,
+    /// Whether UEFI firmware can start the image itself (a PE header).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub efi_stub: bool,
+    /// The kernel's compression (`gzip`, `zstd`, …, `uncompressed`); see
+    /// [`kernel::compression`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub compression: Option<String>,
+    pub size: usize,
+    pub sha256: String,
+}
+
+/// [`inspect`] on tokio's blocking pool, so hashing a large kernel image
+/// does not stall the executor.
+#[cfg(feature = "tokio")]
+pub async fn inspect_async(path: impl Into<std::path::PathBuf>) -> Result<Report> {
+    let path = path.into();
+    lowell_formats::task::blocking(move || inspect(&path)).await
+}
+
+pub fn inspect(path: &Path) -> Result<Report> {
+    let _span = debug_span!("inspect_kernel", path = %path.display()).entered();
+    let bytes = Mapped::open(path)?;
+    let Some(format) = kernel::detect(&bytes) else {
+        return Err(Error::NotKernel {
+            path: path.to_path_buf(),
+        });
+    };
+
+    let t = Stopwatch::start();
+    let version = kernel::version(&bytes, format)?;
+    debug!(
+        %format,
+        elapsed_ms = t.ms(),
+        "version"
+    );
+    let sha256 = Algorithm::Sha256.digest(&bytes).hex;
+
+    Ok(Report {
+        format,
+        arch: kernel::arch(&bytes, format).map(str::to_string),
+        version,
+        efi_stub: kernel::efi_stub(&bytes),
+        compression: kernel::compression(&bytes, format).map(str::to_string),
+        size: bytes.len(),
+        sha256,
+    })
+}
@@ -0,0 +1,11 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Reports on UKIs, initramfs archives and kernel images.
+//!
+//! Each module's `inspect` returns what the matching `lowell inspect`
+//! command prints. Parsing comes from `lowell-formats`; this crate adds
+//! hashing, progress and the report types.
+
+pub mod handler;
+pub mod initrd;
+pub mod kernel;
+pub mod uki;
@@ -0,0 +1,2063 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Inspect a Unified Kernel Image.
+//!
+//! ### Report stability
+//! [`Report`] is serialized as `lowell uki inspect --format json`, so its
+//! shape is a contract with downstream parsers:
+//! - New fields may appear in any release; consumers must ignore unknown
+//!   keys. The structs are `#[non_exhaustive]` for the same reason on the
+//!   Rust side.
+//! - Optional fields are omitted rather than `null` when an analysis was
+//!   skipped (see [`InspectOptions`]); `os_release` is `null` when absent.
+//! - Renaming or removing a field, or changing its type or meaning, bumps
+//!   [`SCHEMA_VERSION`].
+//! - With the `schema` feature, [`report_schema`] describes the JSON; each
+//!   version's is checked in as `schema/uki-report-v<N>.json`.
+use crate::handler::{diff_fields, FieldChange, Fields, SectionHandlers};
+use lowell_formats::authenticode::Signer;
+use lowell_formats::cancel::CancelToken;
+use lowell_formats::digest::{Algorithm, Digest};
+use lowell_formats::error::{Error, Result};
+use lowell_formats::initramfs::{self, detect, Compression};
+use lowell_formats::kernel;
+use lowell_formats::limits::ParserLimits;
+use lowell_formats::osrel::{read_os_release, read_os_release_from_str, OsRelease};
+use lowell_formats::pe::{Checksum, Layout, PeFile, PeHeaders, Section, SectionHead};
+use lowell_formats::progress::{digests, NoProgress, Phase, ProgressSink};
+use lowell_formats::sbat;
+use lowell_formats::timing::Stopwatch;
+use lowell_formats::trace::{debug, debug_span};
+use lowell_formats::{bmp, fdt};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// What to inspect, and which of the costlier analyses to run.
+///
+/// ```no_run
+/// # use lowell_inspect::uki::{inspect, InspectOptions};
+/// // Only the cmdline and arch: skip hashing and os-release parsing.
+/// let opts = InspectOptions::new("/boot/efi/EFI/Linux/uki.efi")
+///     .hash(false)
+///     .os_release(false);
+/// let report = inspect(opts)?;
+/// # Ok::<(), lowell_formats::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct InspectOptions {
+    file: PathBuf,
+    hash: bool,
+    digest: Algorithm,
+    extra_digests: Vec<Algorithm>,
+    certificates: bool,
+    os_release: bool,
+    kernel_version: bool,
+    checksum: bool,
+    count_entries: bool,
+    headers_only: bool,
+    progress: Arc<dyn ProgressSink>,
+    cancel: CancelToken,
+    limits: ParserLimits,
+    handlers: SectionHandlers,
+    reused: Reused,
+}
+
+/// Results taken over by [`InspectOptions::reuse`].
+#[derive(Debug, Clone, Default)]
+struct Reused {
+    linux: Option<SectionInfo>,
+    initrd: Option<InitrdInfo>,
+}
+
+impl InspectOptions {
+    /// Inspect `file` with the default analyses: everything except
+    /// [`count_entries`](Self::count_entries).
+    pub fn new(file: impl Into<PathBuf>) -> Self {
+        Self {
+            file: file.into(),
+            hash: true,
+            digest: Algorithm::default(),
+            extra_digests: Vec::new(),
+            certificates: true,
+            os_release: true,
+            kernel_version: true,
+            checksum: true,
+            count_entries: false,
+            headers_only: false,
+            progress: Arc::new(NoProgress),
+            cancel: CancelToken::new(),
+            limits: ParserLimits::default(),
+            handlers: SectionHandlers::new(),
+            reused: Reused::default(),
+        }
+    }
+
+    /// Report progress (parse, hashes, entry count) to `sink`.
+    pub fn progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.progress = sink;
+        self
+    }
+
+    /// Stop with [`Error::Cancelled`](lowell_formats::Error::Cancelled) once
+    /// `token` is cancelled (checked while hashing and counting entries).
+    pub fn cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Bounds on section size, initrd entries and decompressed bytes.
+    pub fn limits(mut self, limits: ParserLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Digest of `.linux` and `.initrd` (reads both sections in full).
+    pub fn hash(mut self, on: bool) -> Self {
+        self.hash = on;
+        self
+    }
+
+    /// Algorithm for [`hash`](Self::hash); SHA-256 by default.
+    pub fn digest(mut self, algorithm: Algorithm) -> Self {
+        self.digest = algorithm;
+        self
+    }
+
+    /// Further algorithms for [`hash`](Self::hash), computed in the same
+    /// pass over each section (see [`SectionInfo::extra_digests`]).
+    pub fn extra_digests(mut self, algorithms: impl IntoIterator<Item = Algorithm>) -> Self {
+        self.extra_digests = algorithms.into_iter().collect();
+        self
+    }
+
+    /// Report whether the image is signed and how many certificates it has.
+    pub fn certificates(mut self, on: bool) -> Self {
+        self.certificates = on;
+        self
+    }
+
+    /// Parse the `.osrel` section.
+    pub fn os_release(mut self, on: bool) -> Self {
+        self.os_release = on;
+        self
+    }
+
+    /// Find the kernel release (`.uname`, else the version banner in
+    /// `.linux`; an EFI zboot kernel is decompressed to look for it).
+    pub fn kernel_version(mut self, on: bool) -> Self {
+        self.kernel_version = on;
+        self
+    }
+
+    /// Compute the PE checksum to compare with the stored one (reads the
+    /// whole image).
+    pub fn checksum(mut self, on: bool) -> Self {
+        self.checksum = on;
+        self
+    }
+
+    /// Count cpio entries in `.initrd` (decompresses the whole archive).
+    pub fn count_entries(mut self, on: bool) -> Self {
+        self.count_entries = on;
+        self
+    }
+
+    /// Read only the PE headers and the first [`SECTION_HEAD`](lowell_formats::pe::SECTION_HEAD) bytes of each
+    /// section (ranged reads; the image is not mapped). `.initrd`'s
+    /// compression comes from its first bytes; `.cmdline` and `.osrel` are
+    /// read when they fit. Hashes, entry counts, certificate counts, the
+    /// checksum and [`handlers`](Self::handlers) are skipped whatever else
+    /// is set, and
+    /// `has_signature` only says the certificate table is non-empty.
+    pub fn headers_only(mut self, on: bool) -> Self {
+        self.headers_only = on;
+        self
+    }
+
+    /// Report on further sections with `handlers` (see [`crate::handler`]).
+    pub fn handlers(mut self, handlers: SectionHandlers) -> Self {
+        self.handlers = handlers;
+        self
+    }
+
+    /// Take the `.linux` / `.initrd` digests and the entry count from
+    /// `previous`, a report on an earlier version of this image, for the
+    /// sections named in `unchanged` — those whose bytes are known to be
+    /// the same, such as `Edited::unchanged` from `lowell_build::uki::edit`.
+    /// Each is used only if the section's size matches and, for digests,
+    /// the algorithms do; anything else is computed as usual.
+    pub fn reuse<S: AsRef<str>>(mut self, previous: &Report, unchanged: &[S]) -> Self {
+        let kept = |name: &str| unchanged.iter().any(|s| s.as_ref() == name);
+        self.reused = Reused {
+            linux: kept(".linux").then(|| previous.linux.clone()),
+            initrd: kept(".initrd").then(|| previous.initrd.clone()),
+        };
+        self
+    }
+}
+
+/// Version of the [`Report`] JSON schema; see the module docs.
+///
+/// - 2: `sha256` became `digest`, an `algorithm:hex` string.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The JSON Schema (draft 2020-12) of a serialized [`Report`], with
+/// `schema_version` pinned to [`SCHEMA_VERSION`].
+#[cfg(feature = "schema")]
+pub fn report_schema() -> schemars::Schema {
+    let mut schema = schemars::schema_for!(Report);
+    if let Some(version) = schema
+        .pointer_mut("/properties/schema_version")
+        .and_then(|v| v.as_object_mut())
+    {
+        version.insert("const".into(), SCHEMA_VERSION.into());
+    }
+    schema
+}
+
+/// What [`inspect`] found in a UKI; `--format json` writes it as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct Report {
+    /// Always [`SCHEMA_VERSION`].
+    pub schema_version: u32,
+    pub arch: String,    // e.g. "aarch64"
+    pub pe32_plus: bool, // PE32+?
+    /// Authenticode present? `None` if certificates were not inspected.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub has_signature: Option<bool>,
+    /// Number of certs; `None` if certificates were not inspected (or the
+    /// `certificates` feature is off).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub cert_count: Option<usize>,
+    /// The signer of each signature that could be decoded; empty if the
+    /// image is unsigned or certificates were not inspected (or the
+    /// `certificates` feature is off).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub signatures: Vec<Signer>,
+    /// The stored and computed PE checksums; `None` if not computed.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub checksum: Option<Checksum>,
+    pub cmdline: String,
+    pub os_release: Option<OsRelease>,
+    /// The kernel's `uname -r` release; see [`kernel_release`]. `None` if
+    /// the image does not say or it was not looked for.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub kernel_version: Option<String>,
+    /// The stub the image was built on, from its `.sdmagic` marker; `None`
+    /// for a stub that has none (older than systemd 254, or not
+    /// systemd-stub).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub stub: Option<LoaderInfo>,
+    /// The `.sbat` records, for comparison with shim's revocation level;
+    /// empty without an `.sbat` section, or with one that does not parse.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub sbat: Vec<sbat::Entry>,
+    /// An `.sbat` section whose records do not parse; `None` otherwise.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub sbat_problem: Option<SbatProblem>,
+    /// The `.dtb` and `.dtbauto` sections in header order, each with the
+    /// board its devicetree names; empty if there are none.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub devicetrees: Vec<DevicetreeInfo>,
+    /// The `.splash` bitmap the stub draws at boot; `None` without one (or
+    /// with `headers_only`, if the section is larger than its head).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub splash: Option<SplashInfo>,
+    /// The profiles of a multi-profile UKI (systemd 257 and later), in
+    /// order; empty for a single-profile image.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub profiles: Vec<ProfileInfo>,
+    pub linux: SectionInfo,
+    pub initrd: InitrdInfo,
+    /// Every section in header order, `.linux` and `.initrd` included, each
+    /// hashed like those two (raw size, file-alignment padding included).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub section_table: Vec<NamedSection>,
+    /// Section and file alignment and the image size from the PE headers.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub layout: Layout,
+    /// Where the section layout breaks what PE loaders and systemd-stub
+    /// expect (see [`LayoutProblem`]); empty when it is sound. An image
+    /// that boots in QEMU can still fail on firmware that enforces these.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub layout_problems: Vec<LayoutProblem>,
+    /// Output of [`InspectOptions::handlers`], by section name; only
+    /// sections present in the image appear.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub sections: BTreeMap<String, Fields>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct SectionInfo {
+    pub offset: usize,
+    pub size: usize,
+    /// `None` if hashing was turned off.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub digest: Option<Digest>,
+    /// With [`InspectOptions::extra_digests`]' algorithms, in that order.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub extra_digests: Vec<Digest>,
+}
+
+impl SectionInfo {
+    /// Take `digests` (the main one first) as this section's.
+    fn set_digests(&mut self, mut digests: Vec<Digest>) {
+        self.extra_digests = digests.split_off(1.min(digests.len()));
+        self.digest = digests.pop();
+    }
+}
+
+impl From<Section<'_>> for SectionInfo {
+    fn from(s: Section<'_>) -> Self {
+        Self {
+            offset: s.offset,
+            size: s.size,
+            digest: None,
+            extra_digests: Vec::new(),
+        }
+    }
+}
+
+/// What an `.sdmagic` marker names, e.g. `systemd-stub` `256.7-1.fc41`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct LoaderInfo {
+    pub product: String,
+    pub version: String,
+}
+
+/// One profile of a multi-profile UKI: a `.profile` section and those
+/// after it (up to the next `.profile`), which replace the base image's
+/// sections of the same name when the profile is booted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct ProfileInfo {
+    /// `ID=` and `TITLE=` in `.profile`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub id: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub title: Option<String>,
+    /// The profile's own `.cmdline`, trimmed; `None` if it uses the base
+    /// image's.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub cmdline: Option<String>,
+    /// The sections the profile overrides, in header order.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub sections: Vec<String>,
+}
+
+/// The profiles among `sections` (name and, where it could be read,
+/// contents), in header order.
+fn profiles<'a>(
+    sections: impl IntoIterator<Item = (&'a str, Option<&'a [u8]>)>,
+) -> Vec<ProfileInfo> {
+    let text = |b: Option<&[u8]>| {
+        let b = b.unwrap_or_default();
+        let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+        String::from_utf8_lossy(&b[..end]).into_owned()
+    };
+    let mut out: Vec<ProfileInfo> = Vec::new();
+    for (name, contents) in sections {
+        if name == ".profile" {
+            let mut profile = ProfileInfo::default();
+            for line in text(contents).lines() {
+                let Some((key, value)) = line.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches(['"', '\'']).to_string();
+                match key.trim() {
+                    "ID" => profile.id = Some(value),
+                    "TITLE" => profile.title = Some(value),
+                    _ => {}
+                }
+            }
+            out.push(profile);
+        } else if let Some(profile) = out.last_mut() {
+            if name == ".cmdline" {
+                profile.cmdline = Some(text(contents).trim().to_string());
+            }
+            profile.sections.push(name.to_string());
+        }
+    }
+    out
+}
+
+/// An entry of [`Report::section_table`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct NamedSection {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub section: SectionInfo,
+    /// Where it is loaded, relative to the image base.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub virtual_address: usize,
+    /// Its size once loaded; 0 from some linkers (the raw size is used).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub virtual_size: usize,
+}
+
+/// An entry of [`Report::layout_problems`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct LayoutProblem {
+    /// The section at fault; `None` for the image as a whole.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub section: Option<String>,
+    pub problem: String,
+}
+
+/// The page size UEFI allocates and protects memory in.
+const PAGE: usize = 4096;
+
+/// Where `table` (in header order) breaks the PE rules loaders check or
+/// what systemd-stub needs: a section alignment below the page size
+/// (firmware that maps images with NX protections rejects it), virtual
+/// addresses off `SectionAlignment` and raw data off `FileAlignment`,
+/// `.linux` off a page boundary (some firmware then fails to start the
+/// kernel), sections that overlap or run backwards in memory, and
+/// sections past `SizeOfImage`.
+fn layout_problems(layout: Layout, table: &[NamedSection]) -> Vec<LayoutProblem> {
+    let mut out = Vec::new();
+    let mut flag = |section: Option<&str>, problem: String| {
+        out.push(LayoutProblem {
+            section: section.map(str::to_string),
+            problem,
+        })
+    };
+    let section_alignment = layout.section_alignment as usize;
+    let file_alignment = layout.file_alignment as usize;
+    if !section_alignment.is_power_of_two() {
+        flag(
+            None,
+            format!("SectionAlignment {section_alignment:#x} is not a power of two"),
+        );
+    } else if section_alignment < PAGE {
+        flag(
+            None,
+            format!("SectionAlignment {section_alignment:#x} is below the 4 KiB page size"),
+        );
+    }
+    let mut previous: Option<(&str, usize)> = None;
+    for s in table {
+        let name = Some(s.name.as_str());
+        let va = s.virtual_address;
+        let end = va + s.virtual_size.max(s.section.size);
+        if section_alignment.is_power_of_two() && va % section_alignment != 0 {
+            flag(
+                name,
+                format!("virtual address {va:#x} is not a multiple of SectionAlignment {section_alignment:#x}"),
+            );
+        } else if s.name == ".linux" && va % PAGE != 0 {
+            flag(name, format!("virtual address {va:#x} is not page-aligned"));
+        }
+        if file_alignment.is_power_of_two()
+            && s.section.size > 0
+            && s.section.offset % file_alignment != 0
+        {
+            flag(
+                name,
+                format!(
+                    "raw data at {:#x} is not a multiple of FileAlignment {file_alignment:#x}",
+                    s.section.offset
+                ),
+            );
+        }
+        if let Some((prev, prev_end)) = previous {
+            if va < prev_end {
+                flag(
+                    name,
+                    format!(
+                        "virtual address {va:#x} is inside {prev}, which ends at {prev_end:#x}"
+                    ),
+                );
+            }
+        }
+        if end > layout.size_of_image as usize {
+            flag(
+                name,
+                format!(
+                    "ends at {end:#x}, past SizeOfImage {:#x}",
+                    layout.size_of_image
+                ),
+            );
+        }
+        previous = Some((&s.name, end));
+    }
+    out
+}
+
+/// An entry of [`Report::devicetrees`]. A blob that is not a devicetree
+/// is reported here rather than failing the inspection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct DevicetreeInfo {
+    /// `.dtb` or `.dtbauto`.
+    pub section: String,
+    /// `None` if the section is not a well-formed devicetree.
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub devicetree: Option<fdt::Devicetree>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub problem: Option<String>,
+}
+
+/// The devicetree sections among `sections` (name and contents).
+fn devicetrees<'a>(sections: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> Vec<DevicetreeInfo> {
+    sections
+        .into_iter()
+        .filter(|(name, _)| matches!(*name, ".dtb" | ".dtbauto"))
+        .map(|(name, blob)| {
+            let (devicetree, problem) = match fdt::parse(blob) {
+                Ok(dt) => (Some(dt), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            DevicetreeInfo {
+                section: name.to_string(),
+                devicetree,
+                problem,
+            }
+        })
+        .collect()
+}
+
+/// [`Report::sbat_problem`]: the section as read, and why its records do
+/// not parse. A malformed `.sbat` is reported here rather than failing the
+/// inspection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct SbatProblem {
+    pub text: String,
+    pub problem: String,
+}
+
+/// The records of an `.sbat` section read as `text`, or what is wrong
+/// with them.
+fn sbat_records(text: Option<String>) -> (Vec<sbat::Entry>, Option<SbatProblem>) {
+    let Some(text) = text else {
+        return (Vec::new(), None);
+    };
+    match sbat::parse(&text) {
+        Ok(entries) => (entries, None),
+        Err(e) => (
+            Vec::new(),
+            Some(SbatProblem {
+                text,
+                problem: e.to_string(),
+            }),
+        ),
+    }
+}
+
+/// [`Report::splash`]: the bitmap's headers, and why the stub would not
+/// draw it. A broken splash is reported here rather than failing the
+/// inspection, as the stub skips it and boots anyway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct SplashInfo {
+    /// `None` if the section is not a well-formed bitmap.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub bitmap: Option<bmp::Bitmap>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub problem: Option<String>,
+}
+
+/// The `.splash` section among `sections` (name and contents), checked.
+fn splash<'a>(sections: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> Option<SplashInfo> {
+    let (_, blob) = sections.into_iter().find(|(name, _)| *name == ".splash")?;
+    Some(match bmp::parse(blob) {
+        Ok(bitmap) => SplashInfo {
+            problem: bitmap.stub_problem(blob.len()),
+            bitmap: Some(bitmap),
+        },
+        Err(e) => SplashInfo {
+            bitmap: None,
+            problem: Some(e.to_string()),
+        },
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct InitrdInfo {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub section: SectionInfo,
+    pub compression: Compression,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub entries_estimate: Option<usize>,
+    /// The concatenated archives (early microcode, then the main one);
+    /// see [`initramfs::segments`]. Empty with `headers_only`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub segments: Vec<initramfs::Segment>,
+}
+
+impl Report {
+    /// Every value in the report by its JSON path (`initrd.digest`,
+    /// `os_release.id`, `sections..ident.text`), as displayed text.
+    /// Analyses that were skipped have no entry.
+    pub fn fields(&self) -> Fields {
+        let mut f = Fields::new();
+        let mut put = |k: &str, v: Option<String>| {
+            if let Some(v) = v {
+                f.insert(k.to_string(), v);
+            }
+        };
+        put("schema_version", Some(self.schema_version.to_string()));
+        put("arch", Some(self.arch.clone()));
+        put("pe32_plus", Some(self.pe32_plus.to_string()));
+        put("has_signature", self.has_signature.map(|b| b.to_string()));
+        put("cert_count", self.cert_count.map(|n| n.to_string()));
+        for (i, s) in self.signatures.iter().enumerate() {
+            let key = |field: &str| format!("signatures.{i}.{field}");
+            put(&key("subject"), s.subject.clone());
+            put(&key("issuer"), Some(s.issuer.clone()));
+            put(&key("serial"), Some(s.serial.clone()));
+            put(&key("not_before"), s.not_before.clone());
+            put(&key("not_after"), s.not_after.clone());
+            put(&key("digest_algorithm"), Some(s.digest_algorithm.clone()));
+        }
+        if let Some(c) = &self.checksum {
+            put("checksum.stored", Some(format!("{:#010x}", c.stored)));
+            put("checksum.computed", Some(format!("{:#010x}", c.computed)));
+            put("checksum.matches", Some(c.matches.to_string()));
+        }
+        put("cmdline", Some(self.cmdline.clone()));
+        if let Some(os) = &self.os_release {
+            put("os_release.name", os.name.clone());
+            put("os_release.id", os.id.clone());
+            put("os_release.version_id", os.version_id.clone());
+            for (key, value) in &os.all {
+                put(&format!("os_release.all.{key}"), Some(value.clone()));
+            }
+        }
+        put("kernel_version", self.kernel_version.clone());
+        if let Some(stub) = &self.stub {
+            put("stub.product", Some(stub.product.clone()));
+            put("stub.version", Some(stub.version.clone()));
+        }
+        for (i, e) in self.sbat.iter().enumerate() {
+            let key = |field: &str| format!("sbat.{i}.{field}");
+            put(&key("component"), Some(e.component.clone()));
+            put(&key("generation"), Some(e.generation.to_string()));
+            put(&key("vendor"), e.vendor.clone());
+            put(&key("package"), e.package.clone());
+            put(&key("version"), e.version.clone());
+            put(&key("url"), e.url.clone());
+        }
+        if let Some(p) = &self.sbat_problem {
+            put("sbat_problem.text", Some(p.text.clone()));
+            put("sbat_problem.problem", Some(p.problem.clone()));
+        }
+        for (i, p) in self.profiles.iter().enumerate() {
+            let key = |field: &str| format!("profiles.{i}.{field}");
+            put(&key("id"), p.id.clone());
+            put(&key("title"), p.title.clone());
+            put(&key("cmdline"), p.cmdline.clone());
+            for (j, s) in p.sections.iter().enumerate() {
+                put(&key(&format!("sections.{j}")), Some(s.clone()));
+            }
+        }
+        for (i, d) in self.devicetrees.iter().enumerate() {
+            let key = |field: &str| format!("devicetrees.{i}.{field}");
+            put(&key("section"), Some(d.section.clone()));
+            if let Some(dt) = &d.devicetree {
+                put(&key("model"), dt.model.clone());
+                for (j, c) in dt.compatible.iter().enumerate() {
+                    put(&key(&format!("compatible.{j}")), Some(c.clone()));
+                }
+            }
+            put(&key("problem"), d.problem.clone());
+        }
+        if let Some(s) = &self.splash {
+            if let Some(b) = &s.bitmap {
+                put("splash.width", Some(b.width.to_string()));
+                put("splash.height", Some(b.height.to_string()));
+                put("splash.bits_per_pixel", Some(b.bits_per_pixel.to_string()));
+                put("splash.compression", Some(b.compression.to_string()));
+                put("splash.top_down", Some(b.top_down.to_string()));
+                put("splash.file_size", Some(b.file_size.to_string()));
+            }
+            put("splash.problem", s.problem.clone());
+        }
+        for (name, s) in [("linux", &self.linux), ("initrd", &self.initrd.section)] {
+            put(&format!("{name}.offset"), Some(s.offset.to_string()));
+            put(&format!("{name}.size"), Some(s.size.to_string()));
+            put(
+                &format!("{name}.digest"),
+                s.digest.as_ref().map(Digest::to_string),
+            );
+            for (j, d) in s.extra_digests.iter().enumerate() {
+                put(&format!("{name}.extra_digests.{j}"), Some(d.to_string()));
+            }
+        }
+        put(
+            "initrd.compression",
+            Some(self.initrd.compression.to_string()),
+        );
+        put(
+            "initrd.entries_estimate",
+            self.initrd.entries_estimate.map(|n| n.to_string()),
+        );
+        for (i, s) in self.initrd.segments.iter().enumerate() {
+            let key = |field: &str| format!("initrd.segments.{i}.{field}");
+            put(&key("offset"), Some(s.offset.to_string()));
+            put(&key("size"), Some(s.size.to_string()));
+            put(&key("compression"), Some(s.compression.to_string()));
+            put(&key("purpose"), Some(s.purpose.to_string()));
+        }
+        for (i, s) in self.section_table.iter().enumerate() {
+            let key = |field: &str| format!("section_table.{i}.{field}");
+            put(&key("name"), Some(s.name.clone()));
+            put(&key("offset"), Some(s.section.offset.to_string()));
+            put(&key("size"), Some(s.section.size.to_string()));
+            put(
+                &key("digest"),
+                s.section.digest.as_ref().map(Digest::to_string),
+            );
+            for (j, d) in s.section.extra_digests.iter().enumerate() {
+                put(&key(&format!("extra_digests.{j}")), Some(d.to_string()));
+            }
+            put(&key("virtual_address"), Some(s.virtual_address.to_string()));
+            put(&key("virtual_size"), Some(s.virtual_size.to_string()));
+        }
+        let layout = &self.layout;
+        put(
+            "layout.section_alignment",
+            Some(layout.section_alignment.to_string()),
+        );
+        put(
+            "layout.file_alignment",
+            Some(layout.file_alignment.to_string()),
+        );
+        put(
+            "layout.size_of_image",
+            Some(layout.size_of_image.to_string()),
+        );
+        for (i, p) in self.layout_problems.iter().enumerate() {
+            let key = |field: &str| format!("layout_problems.{i}.{field}");
+            put(&key("section"), p.section.clone());
+            put(&key("problem"), Some(p.problem.clone()));
+        }
+        for (section, fields) in &self.sections {
+            for (k, v) in fields {
+                put(&format!("sections.{section}.{k}"), Some(v.clone()));
+            }
+        }
+        f
+    }
+
+    /// What changed from `self` to `other`, field by field (see
+    /// [`fields`](Self::fields)); e.g. a baseline stored as JSON against a
+    /// fresh report.
+    pub fn compare(&self, other: &Report) -> ReportDelta {
+        ReportDelta {
+            changes: diff_fields(&self.fields(), &other.fields()),
+        }
+    }
+}
+
+/// The result of [`Report::compare`]: changed fields in path order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReportDelta {
+    pub changes: Vec<FieldChange>,
+}
+
+impl ReportDelta {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The change to `field`, if it changed.
+    pub fn get(&self, field: &str) -> Option<&FieldChange> {
+        self.changes.iter().find(|c| c.field == field)
+    }
+}
+
+/// [`inspect`] on tokio's blocking pool. The UKI is memory-mapped there
+/// too, so only the pages that are hashed get read.
+#[cfg(feature = "tokio")]
+pub async fn inspect_async(opts: InspectOptions) -> Result<Report> {
+    lowell_formats::task::blocking(move || inspect(opts)).await
+}
+
+pub fn inspect(opts: InspectOptions) -> Result<Report> {
+    let uki = &opts.file;
+    // Parent span
+    let _inspect_span = debug_span!("inspect", path = %uki.display()).entered();
+    if opts.headers_only {
+        return inspect_headers(&opts);
+    }
+
+    // 1) Map + parse PE + arch
+    let t = Stopwatch::start();
+    opts.progress.phase_started(Phase::Parse, None);
+    #[cfg(not(target_arch = "wasm32"))]
+    let pef = PeFile::from_path_mmap_with_limits(uki, &opts.limits)?;
+    #[cfg(target_arch = "wasm32")]
+    let pef = {
+        use lowell_formats::error::IoResultExt;
+        PeFile::from_bytes_with_limits(std::fs::read(uki).at("read", uki)?, &opts.limits)?
+    };
+    opts.progress.phase_finished(Phase::Parse);
+    analyze(&pef, &opts, t)
+}
+
+/// Inspect a UKI already in memory (e.g. a file dropped into a browser).
+///
+/// `opts`'s file only names the image in logs; it is not opened.
+pub fn inspect_bytes(bytes: Vec<u8>, opts: InspectOptions) -> Result<Report> {
+    let _inspect_span = debug_span!("inspect", path = %opts.file.display()).entered();
+    let t = Stopwatch::start();
+    opts.progress.phase_started(Phase::Parse, None);
+    let pef = PeFile::from_bytes_with_limits(bytes, &opts.limits)?;
+    opts.progress.phase_finished(Phase::Parse);
+    analyze(&pef, &opts, t)
+}
+
+/// [`InspectOptions::headers_only`]: the report from [`PeHeaders`].
+fn inspect_headers(opts: &InspectOptions) -> Result<Report> {
+    opts.progress.phase_started(Phase::Parse, None);
+    let pe = PeHeaders::read(&opts.file, &opts.limits)?;
+    opts.progress.phase_finished(Phase::Parse);
+    // Text sections too large for the head are left out.
+    let text = |name: &str| {
+        let s = pe.section(name).filter(|s| s.is_whole())?;
+        let end = s.head.iter().position(|&c| c == 0).unwrap_or(s.head.len());
+        Some(String::from_utf8_lossy(&s.head[..end]).into_owned())
+    };
+    let cmdline = text(".cmdline").unwrap_or_default().trim().to_string();
+    let os_release = match text(".osrel") {
+        Some(text) if opts.os_release => read_os_release_from_str(&text)?,
+        _ => None,
+    };
+    let section = |name: &str| {
+        pe.section(name).ok_or_else(|| Error::SectionMissing {
+            name: name.to_string(),
+        })
+    };
+    let info = |s: &SectionHead| SectionInfo {
+        offset: s.offset,
+        size: s.size,
+        digest: None,
+        extra_digests: Vec::new(),
+    };
+    let kernel_version = text(".uname")
+        .map(|u| u.trim().to_string())
+        .filter(|u| opts.kernel_version && !u.is_empty());
+    let stub = text(".sdmagic").and_then(|t| parse_loader_info(&t));
+    let (sbat, sbat_problem) = sbat_records(text(".sbat"));
+    let devicetrees = devicetrees(
+        pe.sections
+            .iter()
+            .filter(|s| s.is_whole())
+            .map(|s| (s.name.as_str(), &s.head[..])),
+    );
+    let splash = splash(
+        pe.sections
+            .iter()
+            .filter(|s| s.is_whole())
+            .map(|s| (s.name.as_str(), &s.head[..])),
+    );
+    let profiles = profiles(
+        pe.sections
+            .iter()
+            .map(|s| (s.name.as_str(), s.is_whole().then_some(&s.head[..]))),
+    );
+    let (linux, initrd) = (section(".linux")?, section(".initrd")?);
+    let section_table = pe
+        .sections
+        .iter()
+        .map(|s| NamedSection {
+            name: s.name.clone(),
+            section: info(s),
+            virtual_address: s.virtual_address,
+            virtual_size: s.virtual_size,
+        })
+        .collect::<Vec<_>>();
+    let layout_problems = layout_problems(pe.layout, &section_table);
+    Ok(Report {
+        schema_version: SCHEMA_VERSION,
+        arch: pe.arch.to_string(),
+        pe32_plus: pe.pe32_plus,
+        has_signature: opts.certificates.then_some(pe.signed),
+        cert_count: None,
+        signatures: Vec::new(),
+        checksum: None,
+        cmdline,
+        os_release,
+        kernel_version,
+        stub,
+        sbat,
+        sbat_problem,
+        devicetrees,
+        splash,
+        profiles,
+        linux: info(linux),
+        initrd: InitrdInfo {
+            section: info(initrd),
+            compression: detect(&initrd.head),
+            entries_estimate: None,
+            segments: Vec::new(),
+        },
+        section_table,
+        layout: pe.layout,
+        layout_problems,
+        sections: BTreeMap::new(),
+    })
+}
+
+/// Steps 1 (arch) to 5 on a parsed image; `t` started before parsing.
+fn analyze(pef: &PeFile, opts: &InspectOptions, t: Stopwatch) -> Result<Report> {
+    let (arch, pe32p) = pef.arch_summary()?;
+    debug!(
+        len = pef.image().len(),
+        arch,
+        pe32_plus = pe32p,
+        elapsed_ms = t.ms(),
+        "parse_pe"
+    );
+
+    // 2) cmdline + os-release
+    let t = Stopwatch::start();
+    let cmdline = pef
+        .read_text(".cmdline")?
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let os_release: Option<OsRelease> = if opts.os_release {
+        read_os_release(pef)?
+    } else {
+        None
+    };
+    let kernel_version = match opts.kernel_version {
+        true => kernel_release(pef)?,
+        false => None,
+    };
+    let stub = loader_info(pef)?;
+    let (sbat, sbat_problem) = sbat_records(pef.read_text(".sbat")?);
+    let devicetrees = devicetrees(pef.sections()?.iter().map(|s| (s.name, s.contents())));
+    let splash = splash(pef.sections()?.iter().map(|s| (s.name, s.contents())));
+    let profiles = profiles(pef.sections()?.iter().map(|s| (s.name, Some(s.contents()))));
+    debug!(elapsed_ms = t.ms(), "metadata");
+
+    // Every digest asked for, the main one first.
+    let algorithms: Vec<Algorithm> = std::iter::once(opts.digest)
+        .chain(opts.extra_digests.iter().copied())
+        .collect();
+    // Digests from `opts.reuse`, if they still apply.
+    let reused = |prev: Option<&SectionInfo>, size: usize| {
+        let prev = prev.filter(|p| p.size == size)?;
+        let digests: Vec<Digest> = prev
+            .digest
+            .iter()
+            .chain(&prev.extra_digests)
+            .cloned()
+            .collect();
+        digests
+            .iter()
+            .map(|d| d.algorithm)
+            .eq(algorithms.iter().copied())
+            .then_some(digests)
+    };
+
+    // 3) .linux: fetch + hash
+    let linux = pef.section(".linux")?;
+    let (mut linux_info, linux_bytes) = (SectionInfo::from(linux), linux.bytes);
+    let known = reused(opts.reused.linux.as_ref(), linux_info.size);
+    if let (true, Some(d)) = (opts.hash, known) {
+        debug!(algorithm = %opts.digest, "digest_linux reused");
+        linux_info.set_digests(d);
+    } else if opts.hash {
+        let t = Stopwatch::start();
+        let d = digests(
+            linux_bytes,
+            &algorithms,
+            Phase::Hash(".linux"),
+            &*opts.progress,
+            &opts.cancel,
+        )?;
+        linux_info.set_digests(d);
+        debug!(
+            size = linux_bytes.len(),
+            algorithm = %opts.digest,
+            elapsed_ms = t.ms(),
+            "digest_linux"
+        );
+    }
+
+    // 4) .initrd: fetch + hash + detect
+    let initrd = pef.section(".initrd")?;
+    let (mut initrd_info, initrd_bytes) = (SectionInfo::from(initrd), initrd.bytes);
+    let prev_initrd = opts
+        .reused
+        .initrd
+        .as_ref()
+        .filter(|p| p.section.size == initrd_info.size);
+    let known = reused(prev_initrd.map(|p| &p.section), initrd_info.size);
+    if let (true, Some(d)) = (opts.hash, known) {
+        debug!(algorithm = %opts.digest, "digest_initrd reused");
+        initrd_info.set_digests(d);
+    } else if opts.hash {
+        let t = Stopwatch::start();
+        let d = digests(
+            initrd_bytes,
+            &algorithms,
+            Phase::Hash(".initrd"),
+            &*opts.progress,
+            &opts.cancel,
+        )?;
+        initrd_info.set_digests(d);
+        debug!(
+            size = initrd_bytes.len(),
+            algorithm = %opts.digest,
+            elapsed_ms = t.ms(),
+            "digest_initrd"
+        );
+    }
+    let compression = detect(initrd_bytes);
+    let known = prev_initrd.and_then(|p| p.entries_estimate);
+    let entries_estimate = if let (true, Some(entries)) = (opts.count_entries, known) {
+        debug!(entries, "initrd_entries reused");
+        Some(entries)
+    } else if opts.count_entries {
+        let t = Stopwatch::start();
+        opts.progress.phase_started(Phase::CountEntries, None);
+        // Trimmed: zstd reads file-alignment padding as a broken frame.
+        let archive = initrd.contents();
+        let mut entries = 0usize;
+        initramfs::walk_with_limits(archive, &opts.limits, |_, _| {
+            opts.cancel.check()?;
+            entries += 1;
+            Ok(())
+        })?;
+        opts.progress.phase_finished(Phase::CountEntries);
+        debug!(entries, elapsed_ms = t.ms(), "initrd_entries");
+        Some(entries)
+    } else {
+        None
+    };
+
+    // The whole section table; `.linux` and `.initrd` keep their digests.
+    let t = Stopwatch::start();
+    let mut section_table = Vec::new();
+    for s in pef.sections()? {
+        let mut section = SectionInfo::from(s);
+        let known = match s.name {
+            ".linux" => Some(&linux_info),
+            ".initrd" => Some(&initrd_info),
+            _ => None,
+        };
+        if let Some(known) = known {
+            section.digest = known.digest.clone();
+            section.extra_digests = known.extra_digests.clone();
+        } else if opts.hash {
+            opts.cancel.check()?;
+            section.set_digests(algorithms.iter().map(|a| a.digest(s.bytes)).collect());
+        }
+        section_table.push(NamedSection {
+            name: s.name.to_string(),
+            section,
+            virtual_address: s.virtual_address,
+            virtual_size: s.virtual_size,
+        });
+    }
+    debug!(
+        sections = section_table.len(),
+        elapsed_ms = t.ms(),
+        "section_table"
+    );
+    let layout_problems = layout_problems(pef.layout(), &section_table);
+
+    // 5) Certificates (do once; reuse for has_signature, count and signers)
+    // Without the `certificates` feature only presence is known.
+    #[cfg(feature = "certificates")]
+    let (has_signature, cert_count, signatures) = if opts.certificates {
+        let blobs = pef.certificate_blobs()?;
+        let signatures: Vec<Signer> = blobs
+            .iter()
+            .filter_map(|b| lowell_formats::authenticode::Signed::parse(b)?.signer())
+            .collect();
+        debug!(
+            cert_count = blobs.len(),
+            signers = signatures.len(),
+            "certificates"
+        );
+        (Some(!blobs.is_empty()), Some(blobs.len()), signatures)
+    } else {
+        (None, None, Vec::new())
+    };
+    #[cfg(not(feature = "certificates"))]
+    let (has_signature, cert_count, signatures) = if opts.certificates {
+        (Some(pef.is_signed()?), None, Vec::new())
+    } else {
+        (None, None, Vec::new())
+    };
+
+    let initrd = InitrdInfo {
+        section: initrd_info,
+        compression,
+        entries_estimate,
+        segments: initramfs::segments(initrd.contents())?,
+    };
+
+    let checksum = if opts.checksum {
+        let t = Stopwatch::start();
+        let checksum = pef.checksum()?;
+        debug!(
+            stored = checksum.stored,
+            computed = checksum.computed,
+            elapsed_ms = t.ms(),
+            "checksum"
+        );
+        Some(checksum)
+    } else {
+        None
+    };
+
+    // 6) Registered handlers
+    let sections = opts.handlers.inspect(pef)?;
+
+    Ok(Report {
+        schema_version: SCHEMA_VERSION,
+        arch: arch.to_string(),
+        pe32_plus: pe32p,
+        has_signature,
+        cert_count,
+        signatures,
+        checksum,
+        cmdline,
+        os_release,
+        kernel_version,
+        stub,
+        sbat,
+        sbat_problem,
+        devicetrees,
+        splash,
+        profiles,
+        linux: linux_info,
+        initrd,
+        section_table,
+        layout: pef.layout(),
+        layout_problems,
+        sections,
+    })
+}
+
+/// How systemd-boot and systemd-stub (254 and later) start their
+/// `.sdmagic` section: `#### LoaderInfo: systemd-stub 256.7-1.fc41 ####`.
+pub const LOADER_INFO: &str = "#### LoaderInfo: ";
+
+/// The product and version in `pef`'s `.sdmagic` marker, if it has one.
+pub fn loader_info(pef: &PeFile) -> Result<Option<LoaderInfo>> {
+    Ok(pef
+        .section_contents(".sdmagic")?
+        .and_then(|magic| parse_loader_info(&String::from_utf8_lossy(magic))))
+}
+
+fn parse_loader_info(text: &str) -> Option<LoaderInfo> {
+    let (product, version) = text
+        .trim_end_matches('\0')
+        .strip_prefix(LOADER_INFO)?
+        .trim_end()
+        .strip_suffix("####")?
+        .trim()
+        .rsplit_once(' ')?;
+    Some(LoaderInfo {
+        product: product.to_string(),
+        version: version.to_string(),
+    })
+}
+
+/// The `uname -r` release of a UKI's kernel: its `.uname` section, else the
+/// first word of the `Linux version` banner in `.linux` (from the bzImage
+/// setup header, an arm64 Image, or an EFI zboot payload).
+pub fn kernel_release(pef: &PeFile) -> Result<Option<String>> {
+    if let Some(uname) = pef.read_text(".uname")? {
+        if !uname.trim().is_empty() {
+            return Ok(Some(uname.trim().to_string()));
+        }
+    }
+    let Some(linux) = pef.section_contents(".linux")? else {
+        return Ok(None);
+    };
+    Ok(kernel::detect(linux)
+        .map(|format| kernel::version(linux, format))
+        .transpose()?
+        .flatten()
+        .and_then(|banner| banner.split_whitespace().next().map(str::to_string)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lowell_formats::initramfs::{detect, Compression};
+    use lowell_formats::osrel::read_os_release_from_str;
+    use std::sync::Mutex;
+
+    // ---- initramfs detection (pure unit tests) ----
+
+    #[test]
+    fn initramfs_detects_gzip_xz_zstd_newc_unknown() {
+        // gzip magic: 1F 8B
+        assert!(matches!(
+            detect(&[0x1F, 0x8B, 0x08, 0x00]),
+            Compression::Gzip
+        ));
+
+        // xz magic: FD 37 7A 58 5A 00
+        assert!(matches!(
+            detect(&[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00]),
+            Compression::Xz
+        ));
+
+        // zstd magic: 28 B5 2F FD
+        assert!(matches!(
+            detect(&[0x28, 0xB5, 0x2F, 0xFD]),
+            Compression::Zstd
+        ));
+
+        // newc cpio (uncompressed): ASCII "070701" at start
+        assert!(matches!(detect(b"070701..."), Compression::Uncompressed));
+
+        // unknown / too short
+        assert!(matches!(detect(&[]), Compression::Unknown));
+        assert!(matches!(detect(&[0x00, 0x01]), Compression::Unknown));
+    }
+
+    // ---- os-release parsing (pure unit tests) ----
+
+    #[test]
+    fn osrelease_parses_fedora41_and_prefers_pretty_name() {
+        // Realistic snippet (trimmed)
+        let fedora = r#"NAME="Fedora Linux"
+VERSION="41 (Forty One)"
+ID=fedora
+VERSION_ID=41
+PRETTY_NAME="Fedora Linux 41 (Forty One)"
+"#;
+
+        let os = read_os_release_from_str(fedora)
+            .expect("parse ok")
+            .expect("Some(os-release)");
+
+        // PRETTY_NAME takes priority for human display
+        assert_eq!(os.name.as_deref(), Some("Fedora Linux 41 (Forty One)"));
+        // Stable fields used for tooling/logic
+        assert_eq!(os.id.as_deref(), Some("fedora"));
+        assert_eq!(os.version_id.as_deref(), Some("41"));
+    }
+
+    #[test]
+    fn osrelease_falls_back_to_name_when_pretty_missing() {
+        let minimal = r#"NAME="MyOS"
+ID=myos
+VERSION_ID="1.2.3"
+"#;
+        let os = read_os_release_from_str(minimal)
+            .expect("parse ok")
+            .expect("Some(os-release)");
+
+        // PRETTY_NAME absent → fall back to NAME
+        assert_eq!(os.name.as_deref(), Some("MyOS"));
+        assert_eq!(os.id.as_deref(), Some("myos"));
+        assert_eq!(os.version_id.as_deref(), Some("1.2.3"));
+    }
+
+    /// Records the phases that finished.
+    #[derive(Default)]
+    struct Finished(Mutex<Vec<String>>);
+
+    impl ProgressSink for Finished {
+        fn phase_finished(&self, phase: Phase) {
+            self.0.lock().unwrap().push(phase.to_string());
+        }
+    }
+
+    #[test]
+    fn options_toggle_analyses() {
+        let initrd = lowell_test_util::newc(&[("init", 0o100755, b"#!/bin/sh\n")]);
+        let img = lowell_build::uki::assemble(
+            &lowell_test_util::stub(),
+            &[
+                (".osrel", b"NAME=Test\n"),
+                (".cmdline", b"quiet\0"),
+                (".linux", b"kernel"),
+                (".initrd", &initrd),
+            ],
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, &img).unwrap();
+
+        let rec = Arc::new(Finished::default());
+        let mut handlers = crate::handler::SectionHandlers::new();
+        handlers.register(crate::handler::TextSection::new(".cmdline"));
+        let full = inspect(
+            InspectOptions::new(&path)
+                .count_entries(true)
+                .progress(rec.clone())
+                .handlers(handlers),
+        )
+        .unwrap();
+        assert_eq!(
+            *rec.0.lock().unwrap(),
+            ["parse", "hash .linux", "hash .initrd", "count entries"]
+        );
+        assert_eq!(full.schema_version, SCHEMA_VERSION);
+        assert_eq!(
+            full.linux.digest.as_ref().map(|d| d.algorithm),
+            Some(Algorithm::Sha256)
+        );
+        assert_eq!(full.has_signature, Some(false));
+        let checksum = full.checksum.unwrap();
+        assert_eq!(checksum.matches, checksum.stored == checksum.computed);
+        assert_eq!(full.initrd.entries_estimate, Some(1));
+        assert!(full.os_release.is_some());
+        assert_eq!(full.sections[".cmdline"]["text"], "quiet");
+
+        let lean = inspect(
+            InspectOptions::new(&path)
+                .hash(false)
+                .certificates(false)
+                .os_release(false)
+                .checksum(false),
+        )
+        .unwrap();
+        assert_eq!(lean.cmdline, "quiet");
+        assert_eq!(lean.checksum, None);
+        assert_eq!(lean.initrd.section.digest, None);
+        assert_eq!(lean.cert_count, None);
+        assert!(lean.os_release.is_none());
+        assert_eq!(lean.initrd.entries_estimate, None);
+
+        let dropped = inspect_bytes(
+            img,
+            InspectOptions::new("dropped.efi")
+                .digest(Algorithm::Sha512)
+                .extra_digests([Algorithm::Sha384, Algorithm::Sha256]),
+        )
+        .unwrap();
+        let d = dropped.linux.digest.as_ref().unwrap();
+        assert_eq!(d.algorithm, Algorithm::Sha512);
+        assert_eq!(d.hex.len(), 128);
+        let extra = &dropped.initrd.section.extra_digests;
+        assert_eq!(extra[0].algorithm, Algorithm::Sha384);
+        assert_eq!(Some(&extra[1]), full.initrd.section.digest.as_ref());
+        let fields = dropped.fields();
+        assert_eq!(
+            fields.get("linux.extra_digests.1"),
+            full.linux.digest.as_ref().map(Digest::to_string).as_ref()
+        );
+        assert!(fields.contains_key("section_table.0.extra_digests.0"));
+    }
+
+    #[test]
+    fn edits_rehash_only_changed_sections() {
+        let initrd = lowell_test_util::newc(&[("init", 0o100755, b"#!/bin/sh\n")]);
+        let img = lowell_build::uki::assemble(
+            &lowell_test_util::stub(),
+            &[
+                (".cmdline", b"quiet\0"),
+                (".initrd", &initrd),
+                (".linux", b"kernel"),
+            ],
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, &img).unwrap();
+        let before = inspect(InspectOptions::new(&path).count_entries(true)).unwrap();
+
+        let edited = lowell_build::uki::edit(&img, &[(".cmdline", Some(b"debug\0"))]).unwrap();
+        std::fs::write(&path, &edited.image).unwrap();
+        let rec = Arc::new(Finished::default());
+        let after = inspect(
+            InspectOptions::new(&path)
+                .count_entries(true)
+                .reuse(&before, &edited.unchanged)
+                .progress(rec.clone()),
+        )
+        .unwrap();
+        assert_eq!(*rec.0.lock().unwrap(), ["parse"]);
+        assert_eq!(
+            after,
+            inspect(InspectOptions::new(&path).count_entries(true)).unwrap()
+        );
+        assert_eq!(after.cmdline, "debug");
+
+        // Another algorithm, or a section not vouched for, is recomputed.
+        let rec = Arc::new(Finished::default());
+        inspect(
+            InspectOptions::new(&path)
+                .digest(Algorithm::Sha512)
+                .reuse(&before, &[".initrd"])
+                .progress(rec.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            *rec.0.lock().unwrap(),
+            ["parse", "hash .linux", "hash .initrd"]
+        );
+    }
+
+    #[test]
+    fn headers_only_agrees_with_a_lean_inspect() {
+        use lowell_test_util::UkiBuilder;
+        let img = UkiBuilder::new()
+            .osrel("NAME=Test\n")
+            .cmdline("quiet")
+            .linux(&[0xAA; 10_000])
+            .initrd(&[0x1F, 0x8B, 8, 0])
+            .section(".vendor", b"custom")
+            .certificate(b"fake pkcs7")
+            .build();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, &img).unwrap();
+
+        let rec = Arc::new(Finished::default());
+        let fast = inspect(
+            InspectOptions::new(&path)
+                .headers_only(true)
+                .count_entries(true)
+                .progress(rec.clone()),
+        )
+        .unwrap();
+        assert_eq!(*rec.0.lock().unwrap(), ["parse"]);
+        let lean = inspect(InspectOptions::new(&path).hash(false)).unwrap();
+        assert_eq!(fast.linux, lean.linux);
+        assert_eq!(fast.initrd.compression, Compression::Gzip);
+        assert_eq!(fast.initrd.section, lean.initrd.section);
+        assert_eq!(fast.has_signature, Some(true));
+        assert_eq!(fast.cert_count, None);
+        assert_eq!(fast.section_table, lean.section_table);
+        let vendor = fast.section_table.last().unwrap();
+        assert_eq!(
+            (vendor.name.as_str(), vendor.section.digest.as_ref()),
+            (".vendor", None)
+        );
+        let full = inspect(InspectOptions::new(&path)).unwrap();
+        let digests: Vec<_> = full
+            .section_table
+            .iter()
+            .map(|s| &s.section.digest)
+            .collect();
+        assert!(digests.iter().all(|d| d.is_some()));
+        let linux = full.section_table.iter().find(|s| s.name == ".linux");
+        assert_eq!(linux.unwrap().section.digest, full.linux.digest);
+        assert_eq!(
+            (fast.arch, fast.cmdline, fast.os_release),
+            (lean.arch, lean.cmdline, lean.os_release)
+        );
+    }
+
+    #[test]
+    fn kernel_version_from_uname_or_banner() {
+        use lowell_test_util::UkiBuilder;
+        let mut image = vec![0u8; 0x100];
+        image[0x38..0x3c].copy_from_slice(b"ARM\x64");
+        image.extend_from_slice(b"Linux version 6.12.1-arm64 (gcc) #1 SMP\n\0");
+        let uki = |uname: Option<&str>| {
+            let mut b = UkiBuilder::new().cmdline("quiet").linux(&image).initrd(b"");
+            if let Some(uname) = uname {
+                b = b.section(".uname", uname.as_bytes());
+            }
+            b.build()
+        };
+        let version =
+            |img: Vec<u8>, opts: InspectOptions| inspect_bytes(img, opts).unwrap().kernel_version;
+
+        let opts = || InspectOptions::new("uki.efi").hash(false);
+        assert_eq!(version(uki(None), opts()).as_deref(), Some("6.12.1-arm64"));
+        assert_eq!(
+            version(uki(Some("6.12.1-300.fc41.aarch64\n")), opts()).as_deref(),
+            Some("6.12.1-300.fc41.aarch64")
+        );
+        assert_eq!(version(uki(None), opts().kernel_version(false)), None);
+
+        // Without reading `.linux`, only `.uname` can say.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, uki(Some("6.12.1"))).unwrap();
+        let fast = inspect(InspectOptions::new(&path).headers_only(true)).unwrap();
+        assert_eq!(fast.kernel_version.as_deref(), Some("6.12.1"));
+        assert_eq!(fast.fields()["kernel_version"], "6.12.1");
+    }
+
+    #[test]
+    fn stub_comes_from_the_sdmagic_marker() {
+        use lowell_test_util::UkiBuilder;
+        let uki = |magic: Option<&str>| {
+            let mut b = UkiBuilder::new()
+                .cmdline("quiet")
+                .linux(b"kernel")
+                .initrd(b"");
+            if let Some(magic) = magic {
+                b = b.section(".sdmagic", magic.as_bytes());
+            }
+            b.build()
+        };
+        let stub = |img: Vec<u8>| {
+            inspect_bytes(img, InspectOptions::new("uki.efi").hash(false))
+                .unwrap()
+                .stub
+        };
+
+        let modern = stub(uki(Some(
+            "#### LoaderInfo: systemd-stub 256.7-1.fc41 ####\0",
+        )));
+        assert_eq!(
+            modern,
+            Some(LoaderInfo {
+                product: "systemd-stub".into(),
+                version: "256.7-1.fc41".into(),
+            })
+        );
+        assert_eq!(stub(uki(None)), None);
+        assert_eq!(stub(uki(Some("not a marker"))), None);
+    }
+
+    #[test]
+    fn sbat_records_are_reported() {
+        use lowell_test_util::UkiBuilder;
+        let sbat = "sbat,1,SBAT Version,sbat,1,https://github.com/rhboot/shim/blob/main/SBAT.md\n\
+                    systemd-stub,1,The systemd Developers,systemd,256,https://systemd.io/\n";
+        let uki = |sbat: &str| {
+            UkiBuilder::new()
+                .cmdline("quiet")
+                .linux(b"kernel")
+                .initrd(b"")
+                .section(".sbat", sbat.as_bytes())
+                .build()
+        };
+        let opts = || InspectOptions::new("uki.efi").hash(false);
+
+        let report = inspect_bytes(uki(sbat), opts()).unwrap();
+        let stub = &report.sbat[1];
+        assert_eq!(
+            (stub.component.as_str(), stub.generation),
+            ("systemd-stub", 1)
+        );
+        assert_eq!(stub.version.as_deref(), Some("256"));
+        assert_eq!(report.fields()["sbat.1.vendor"], "The systemd Developers");
+
+        assert_eq!(report.sbat_problem, None);
+
+        // A malformed section is kept as read, with why, and the rest of
+        // the image is still inspected.
+        let report = inspect_bytes(uki("systemd-stub,one\n"), opts()).unwrap();
+        assert!(report.sbat.is_empty());
+        let problem = report.sbat_problem.as_ref().unwrap();
+        assert_eq!(problem.text, "systemd-stub,one\n");
+        assert!(problem.problem.contains("line 1: no generation number"));
+        assert_eq!(report.cmdline, "quiet");
+        let fields = report.fields();
+        assert_eq!(fields["sbat_problem.text"], "systemd-stub,one\n");
+        assert_eq!(fields["sbat_problem.problem"], problem.problem);
+    }
+
+    #[test]
+    fn profiles_are_enumerated() {
+        use lowell_test_util::UkiBuilder;
+        let uki = UkiBuilder::new()
+            .cmdline("quiet")
+            .linux(b"kernel")
+            .initrd(b"")
+            .section(".profile", b"ID=default\nTITLE=\"Default\"\n")
+            .section(".profile", b"ID=rescue\nTITLE=\"Rescue shell\"\n")
+            .section(".cmdline", b"quiet systemd.unit=rescue.target \0")
+            .section(".splash", b"BM")
+            .build();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, uki).unwrap();
+
+        for opts in [
+            InspectOptions::new(&path).hash(false),
+            InspectOptions::new(&path).headers_only(true),
+        ] {
+            let report = inspect(opts).unwrap();
+            assert_eq!(report.cmdline, "quiet");
+            let [default, rescue] = &report.profiles[..] else {
+                panic!("{:?}", report.profiles);
+            };
+            assert_eq!(default.id.as_deref(), Some("default"));
+            assert_eq!(
+                (default.cmdline.as_ref(), default.sections.len()),
+                (None, 0)
+            );
+            assert_eq!(rescue.title.as_deref(), Some("Rescue shell"));
+            assert_eq!(
+                rescue.cmdline.as_deref(),
+                Some("quiet systemd.unit=rescue.target")
+            );
+            assert_eq!(rescue.sections, [".cmdline", ".splash"]);
+            assert_eq!(report.fields()["profiles.1.sections.1"], ".splash");
+        }
+    }
+
+    #[test]
+    fn devicetrees_are_reported() {
+        use lowell_test_util::{fdt, UkiBuilder};
+        let pi = fdt(&[
+            ("compatible", b"raspberrypi,4-model-b\0brcm,bcm2711\0"),
+            ("model", b"Raspberry Pi 4 Model B\0"),
+        ]);
+        let virt = fdt(&[("compatible", b"linux,dummy-virt\0")]);
+        let uki = |dtbauto: &[u8]| {
+            UkiBuilder::new()
+                .cmdline("quiet")
+                .linux(b"kernel")
+                .initrd(b"")
+                .section(".dtb", &virt)
+                .section(".dtbauto", &pi)
+                .section(".dtbauto", dtbauto)
+                .build()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, uki(&virt)).unwrap();
+
+        for opts in [
+            InspectOptions::new(&path).hash(false),
+            InspectOptions::new(&path).headers_only(true),
+        ] {
+            let report = inspect(opts).unwrap();
+            let sections: Vec<_> = report.devicetrees.iter().map(|d| &d.section).collect();
+            assert_eq!(sections, [".dtb", ".dtbauto", ".dtbauto"]);
+            let pi = report.devicetrees[1].devicetree.as_ref().unwrap();
+            assert_eq!(pi.model.as_deref(), Some("Raspberry Pi 4 Model B"));
+            assert_eq!(pi.compatible, ["raspberrypi,4-model-b", "brcm,bcm2711"]);
+            let fields = report.fields();
+            assert_eq!(fields["devicetrees.0.compatible.0"], "linux,dummy-virt");
+            assert!(!fields.contains_key("devicetrees.0.model"));
+        }
+
+        // One bad blob is reported on its entry; the rest still are read.
+        let report = inspect_bytes(uki(b"not a dtb"), InspectOptions::new("uki.efi")).unwrap();
+        let bad = &report.devicetrees[2];
+        assert_eq!(bad.devicetree, None);
+        assert!(bad.problem.as_deref().unwrap().contains("magic"));
+        assert!(report.devicetrees[0].problem.is_none());
+        assert_eq!(report.cmdline, "quiet");
+        let fields = report.fields();
+        assert_eq!(
+            fields["devicetrees.2.problem"],
+            bad.problem.clone().unwrap()
+        );
+        assert!(!fields.contains_key("devicetrees.2.model"));
+    }
+
+    #[test]
+    fn splash_problems_do_not_fail_inspection() {
+        use lowell_test_util::{bmp, UkiBuilder};
+        let uki = |splash: &[u8]| {
+            UkiBuilder::new()
+                .cmdline("quiet")
+                .linux(b"kernel")
+                .initrd(b"")
+                .section(".splash", splash)
+                .build()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uki.efi");
+        std::fs::write(&path, uki(&bmp(4, 3, 24))).unwrap();
+        for opts in [
+            InspectOptions::new(&path).hash(false),
+            InspectOptions::new(&path).headers_only(true),
+        ] {
+            let splash = inspect(opts).unwrap().splash.unwrap();
+            let bitmap = splash.bitmap.unwrap();
+            assert_eq!(
+                (bitmap.width, bitmap.height, bitmap.bits_per_pixel),
+                (4, 3, 24)
+            );
+            assert_eq!(splash.problem, None);
+        }
+
+        let opts = || InspectOptions::new("uki.efi");
+        let report = inspect_bytes(uki(&bmp(4, -3, 24)), opts()).unwrap();
+        let fields = report.fields();
+        assert_eq!(fields["splash.top_down"], "true");
+        assert!(fields["splash.problem"].contains("top-down"));
+        let report = inspect_bytes(uki(b"GIF89a"), opts()).unwrap();
+        let splash = report.splash.unwrap();
+        assert_eq!(splash.bitmap, None);
+        assert_eq!(
+            splash.problem.as_deref(),
+            Some("malformed bitmap: no BM magic")
+        );
+        assert_eq!(
+            inspect_bytes(uki(b""), opts())
+                .unwrap()
+                .splash
+                .unwrap()
+                .bitmap,
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "certificates")]
+    fn signers_are_reported() {
+        use lowell_test_util::authenticode::{der, signed_data};
+        use lowell_test_util::UkiBuilder;
+        // CN=Test CA; the signer's certificate is not embedded.
+        const CN: &[u8] = &[0x06, 0x03, 0x55, 0x04, 0x03];
+        let issuer = der(
+            0x30,
+            &[&der(0x31, &[&der(0x30, &[CN, &der(0x0c, &[b"Test CA"])])])],
+        );
+        let blob = signed_data(&[0; 32], &[], &issuer, &[0x02, 0x02, 0x01, 0x00]);
+        let uki = UkiBuilder::new()
+            .cmdline("quiet")
+            .linux(b"kernel")
+            .initrd(b"")
+            .certificate(&blob)
+            .certificate(b"fake pkcs7")
+            .build();
+
+        let report = inspect_bytes(uki, InspectOptions::new("uki.efi").hash(false)).unwrap();
+        assert_eq!(report.cert_count, Some(2));
+        assert_eq!(report.signatures.len(), 1);
+        let signer = &report.signatures[0];
+        assert_eq!(
+            (signer.issuer.as_str(), signer.serial.as_str()),
+            ("CN=Test CA", "0100")
+        );
+        assert_eq!(signer.subject, None);
+        let fields = report.fields();
+        assert_eq!(fields["signatures.0.digest_algorithm"], "sha256");
+        assert!(!fields.contains_key("signatures.0.not_after"));
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn inspect_synthetic_signed_uki() {
+        use lowell_test_util::{Compression as C, Initrd, UkiBuilder};
+
+        let initrd = Initrd::new()
+            .early("kernel/x86/microcode/GenuineIntel.bin", b"ucode")
+            .dir("usr")
+            .file("init", b"#!/bin/sh\n")
+            .symlink("bin", "usr/bin")
+            .compression(C::Zstd)
+            .build();
+        let img = UkiBuilder::new()
+            .arch(lowell_test_util::Arch::Aarch64)
+            .osrel("PRETTY_NAME=\"Synthetic\"\n")
+            .cmdline(" console=ttyAMA0 ")
+            .linux(b"kernel")
+            .initrd(&initrd)
+            .certificate(b"fake pkcs7")
+            .build();
+
+        let report = inspect_bytes(
+            img,
+            InspectOptions::new("synthetic.efi").count_entries(true),
+        )
+        .unwrap();
+        assert_eq!(report.arch, "aarch64");
+        assert_eq!(report.cmdline, "console=ttyAMA0");
+        assert_eq!(report.has_signature, Some(true));
+        #[cfg(feature = "certificates")]
+        assert_eq!(report.cert_count, Some(1));
+        assert_eq!(report.initrd.compression, Compression::Uncompressed);
+        assert_eq!(report.initrd.entries_estimate, Some(4));
+        let segments: Vec<_> = report
+            .initrd
+            .segments
+            .iter()
+            .map(|s| (s.purpose, s.compression))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (initramfs::Purpose::Microcode, Compression::Uncompressed),
+                (initramfs::Purpose::Main, Compression::Zstd)
+            ]
+        );
+        assert_eq!(
+            report.os_release.and_then(|os| os.name).as_deref(),
+            Some("Synthetic")
+        );
+    }
+
+    #[test]
+    fn layout_problems_name_the_section() {
+        let img = lowell_test_util::UkiBuilder::new()
+            .cmdline("quiet")
+            .linux(&[0xAA; 4096])
+            .initrd(&lowell_test_util::newc(&[]))
+            .build();
+        let report = inspect_bytes(img, InspectOptions::new("uki.efi")).unwrap();
+        assert_eq!(report.layout_problems, []);
+        assert!(report.layout.section_alignment >= 4096);
+
+        let section = |name: &str, offset, virtual_address, size| NamedSection {
+            name: name.to_string(),
+            section: SectionInfo {
+                offset,
+                size,
+                digest: None,
+                extra_digests: Vec::new(),
+            },
+            virtual_address,
+            virtual_size: size,
+        };
+        let layout = Layout {
+            section_alignment: 0x200,
+            file_alignment: 0x200,
+            size_of_image: 0x4000,
+        };
+        let table = [
+            section(".text", 0x400, 0x1000, 0xe00),
+            section(".linux", 0x1200, 0x1e00, 0x2000),
+            section(".initrd", 0x3410, 0x3c00, 0x600),
+        ];
+        let problems: Vec<_> = layout_problems(layout, &table)
+            .into_iter()
+            .map(|p| (p.section, p.problem))
+            .collect();
+        let at = |name: &str| Some(name.to_string());
+        assert_eq!(
+            problems,
+            [
+                (
+                    None,
+                    "SectionAlignment 0x200 is below the 4 KiB page size".into()
+                ),
+                (
+                    at(".linux"),
+                    "virtual address 0x1e00 is not page-aligned".into()
+                ),
+                (
+                    at(".initrd"),
+                    "raw data at 0x3410 is not a multiple of FileAlignment 0x200".into()
+                ),
+                (
+                    at(".initrd"),
+                    "virtual address 0x3c00 is inside .linux, which ends at 0x3e00".into()
+                ),
+                (
+                    at(".initrd"),
+                    "ends at 0x4200, past SizeOfImage 0x4000".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "schema")]
+    fn published_schema_is_current() {
+        use lowell_test_util::{bmp, newc, UkiBuilder};
+        let schema = serde_json::to_value(report_schema()).unwrap();
+        let path = format!(
+            "{}/../schema/uki-report-v{SCHEMA_VERSION}.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let published: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(
+            published == schema,
+            "{path} is stale: regenerate it with `just schema`"
+        );
+
+        // Every field of a report is described.
+        let img = UkiBuilder::new()
+            .cmdline("quiet")
+            .linux(b"kernel")
+            .initrd(&newc(&[]))
+            .section(".sbat", b"sbat,1,SBAT Version,sbat,1,x\n")
+            .section(".splash", &bmp(2, 2, 24))
+            .build();
+        let report =
+            serde_json::to_value(inspect_bytes(img, InspectOptions::new("uki.efi")).unwrap())
+                .unwrap();
+        let properties = |def: &serde_json::Value| def["properties"].as_object().unwrap().clone();
+        for (key, value) in report.as_object().unwrap() {
+            assert!(properties(&schema).contains_key(key), "{key}");
+            if let Some(fields) = value.as_object() {
+                let def = match key.as_str() {
+                    "linux" => "SectionInfo",
+                    "initrd" => "InitrdInfo",
+                    "layout" => "Layout",
+                    "checksum" => "Checksum",
+                    "splash" => "SplashInfo",
+                    _ => continue,
+                };
+                let described = properties(&schema["$defs"][def]);
+                for field in fields.keys() {
+                    assert!(described.contains_key(field), "{key}.{field}");
+                }
+            }
+        }
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            SCHEMA_VERSION
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn stored_reports_load_and_compare() {
+        use lowell_test_util::{newc, UkiBuilder};
+
+        let uki = |cmdline: &str| {
+            let img = UkiBuilder::new()
+                .cmdline(cmdline)
+                .linux(b"kernel")
+                .initrd(&newc(&[]))
+                .build();
+            inspect_bytes(img, InspectOptions::new("uki.efi")).unwrap()
+        };
+        let baseline: Report =
+            serde_json::from_str(&serde_json::to_string(&uki("quiet")).unwrap()).unwrap();
+        assert_eq!(baseline, uki("quiet"));
+        assert!(baseline.compare(&uki("quiet")).is_empty());
+
+        // The command line, the digest and loaded size of the section
+        // holding it, and the image checksum.
+        let delta = baseline.compare(&uki("quiet debug"));
+        let at = baseline
+            .section_table
+            .iter()
+            .position(|s| s.name == ".cmdline")
+            .unwrap();
+        let fields: Vec<_> = delta.changes.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            [
+                "checksum.computed".to_string(),
+                "cmdline".to_string(),
+                format!("section_table.{at}.digest"),
+                format!("section_table.{at}.virtual_size"),
+            ]
+        );
+        assert_eq!(
+            delta.get("cmdline"),
+            Some(&FieldChange {
+                field: "cmdline".into(),
+                old: Some("quiet".into()),
+                new: Some("quiet debug".into()),
+            })
+        );
+    }
+
+    // ---- optional integration smoke test (ignored by default) ----
+    //
+    // Run with:  UKI_PATH=/full/path/to/vmlinuz.efi  cargo test -- --ignored
+    // or:        cargo test inspect_real_uki_smoke -- --ignored
+    #[test]
+    #[ignore = "requires UKI_PATH"]
+    fn inspect_real_uki_smoke() {
+        let uki_path = std::env::var("UKI_PATH").expect("set UKI_PATH to a real UKI");
+        let report = inspect(InspectOptions::new(uki_path)).expect("inspect report");
+
+        // Sanity checks that don’t depend on a specific distro
+        assert!(!report.arch.is_empty());
+        assert!(report.linux.size > 0);
+        assert!(report.initrd.section.size > 0);
+        assert_ne!(report.initrd.compression, Compression::Unknown);
+
+        // sha256 digests should be 64 hex chars
+        for d in [&report.linux.digest, &report.initrd.section.digest] {
+            let d = d.as_ref().expect("hashed by default");
+            assert_eq!(d.algorithm, Algorithm::Sha256);
+            assert_eq!(d.hex.len(), 64);
+            assert!(d.hex.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+
+        // If the UKI embeds .cmdline, it should be trimmed
+        assert_eq!(report.cmdline, report.cmdline.trim());
+    }
+}
@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Python bindings for `lowell-core` (`import lowell`).
+//!
+//! ```python
+//! import lowell
+//!
+//! report = lowell.inspect("/boot/efi/EFI/Linux/uki.efi")  # dict, as --format json
+//! uki = lowell.Uki("/boot/efi/EFI/Linux/uki.efi")
+//! print(uki.arch, uki.cmdline, [name for name, _, _ in uki.sections])
+//! initrd = uki.section(".initrd")  # bytes
+//! ```
+//!
+//! Failures raise `lowell.LowellError`; its `code` attribute is the stable
+//! `E_*` code the CLI prints under `--format json`.
+
+use lowell_core::digest::Algorithm;
+use lowell_core::error::ErrorCode;
+use lowell_core::formats::osrel::read_os_release;
+use lowell_core::formats::pe::PeFile;
+use lowell_core::formats::sniff::{sniff_path, FileKind};
+use lowell_core::uki::inspect::InspectOptions;
+use lowell_core::{initrd, kernel, uki};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+create_exception!(
+    lowell,
+    LowellError,
+    PyException,
+    "A lowell operation failed; `code` holds the stable E_* error code."
+);
+
+fn error(code: ErrorCode, message: String) -> PyErr {
+    let err = LowellError::new_err(message);
+    Python::attach(|py| {
+        // Setting an attribute on a fresh exception instance cannot fail.
+        let _ = err.value(py).setattr("code", code.as_str());
+    });
+    err
+}
+
+/// Message plus causes, the way the CLI prints them on one line.
+fn core_error(err: lowell_core::Error) -> PyErr {
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(&err);
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    error(err.code(), message)
+}
+
+/// Round-trip through `json.loads` so reports arrive as plain dicts with
+/// exactly the CLI's JSON keys.
+fn to_py<'py, T: Serialize>(py: Python<'py>, value: &T) -> PyResult<Bound<'py, PyAny>> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| error(ErrorCode::Unknown, format!("serialize report: {e}")))?;
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+/// inspect(path) -> dict
+///
+/// Inspect a UKI, bare initramfs or kernel image, detected from its leading
+/// bytes. Returns what `lowell inspect --format json` prints.
+#[pyfunction]
+fn inspect(py: Python<'_>, path: PathBuf) -> PyResult<Bound<'_, PyAny>> {
+    let kind = py
+        .detach(|| sniff_path(&path))
+        .map_err(core_error)?
+        .ok_or_else(|| {
+            error(
+                ErrorCode::UnknownFormat,
+                format!("{}: not a UKI, initramfs or kernel image", path.display()),
+            )
+        })?;
+    match kind {
+        FileKind::Uki => {
+            let report = py
+                .detach(|| uki::inspect::inspect(InspectOptions::new(&path)))
+                .map_err(core_error)?;
+            to_py(py, &report)
+        }
+        FileKind::Initramfs => {
+            let report = py
+                .detach(|| initrd::inspect::inspect(&path))
+                .map_err(core_error)?;
+            to_py(py, &report)
+        }
+        FileKind::Kernel => {
+            let report = py
+                .detach(|| kernel::inspect::inspect(&path))
+                .map_err(core_error)?;
+            to_py(py, &report)
+        }
+    }
+}
+
+/// Uki(path)
+///
+/// A Unified Kernel Image, memory-mapped. Raises `LowellError` with code
+/// `E_NOT_PE` if the file is not a PE/EFI image.
+#[pyclass(module = "lowell", frozen)]
+struct Uki {
+    path: PathBuf,
+    pe: PeFile,
+}
+
+#[pymethods]
+impl Uki {
+    #[new]
+    fn new(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        let pe = py
+            .detach(|| PeFile::from_path_mmap(&path))
+            .map_err(core_error)?;
+        Ok(Self { path, pe })
+    }
+
+    #[getter]
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `"x86_64"`, `"aarch64"`, …
+    #[getter]
+    fn arch(&self) -> PyResult<&'static str> {
+        Ok(self.pe.arch_summary().map_err(core_error)?.0)
+    }
+
+    #[getter]
+    fn pe32_plus(&self) -> PyResult<bool> {
+        Ok(self.pe.arch_summary().map_err(core_error)?.1)
+    }
+
+    /// True if an Authenticode certificate table is present (not verified).
+    #[getter]
+    fn signed(&self) -> PyResult<bool> {
+        self.pe.is_signed().map_err(core_error)
+    }
+
+    /// The `.cmdline` section, trimmed, or None.
+    #[getter]
+    fn cmdline(&self) -> PyResult<Option<String>> {
+        let text = self.pe.read_text(".cmdline").map_err(core_error)?;
+        Ok(text.map(|t| t.trim().to_string()))
+    }
+
+    /// The parsed `.osrel` section as a dict, or None.
+    #[getter]
+    fn os_release<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        match read_os_release(&self.pe).map_err(core_error)? {
+            Some(os) => to_py(py, &os).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// `[(name, file_offset, file_size), …]` in header order.
+    #[getter]
+    fn sections(&self) -> PyResult<Vec<(String, usize, usize)>> {
+        self.pe.section_table().map_err(core_error)
+    }
+
+    /// section(name, trim=True) -> bytes | None
+    ///
+    /// A section's contents; with `trim`, file-alignment padding past the
+    /// section's virtual size is dropped.
+    #[pyo3(signature = (name, trim = true))]
+    fn section<'py>(
+        &self,
+        py: Python<'py>,
+        name: &str,
+        trim: bool,
+    ) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let section = self.pe.find_section(name).map_err(core_error)?;
+        Ok(section.map(|s| PyBytes::new(py, if trim { s.contents() } else { s.bytes })))
+    }
+
+    /// inspect(hash=True, count_entries=False, digest="sha256") -> dict
+    ///
+    /// The full report, as `lowell uki inspect --format json` prints it.
+    /// Turn `hash` off to skip hashing `.linux`/`.initrd`; `digest` picks
+    /// the algorithm (sha256, sha384, sha512, blake3).
+    #[pyo3(signature = (hash = true, count_entries = false, digest = "sha256"))]
+    fn inspect<'py>(
+        &self,
+        py: Python<'py>,
+        hash: bool,
+        count_entries: bool,
+        digest: &str,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let digest: Algorithm = digest
+            .parse()
+            .map_err(|e| error(ErrorCode::InvalidArgument, e))?;
+        let opts = InspectOptions::new(&self.path)
+            .hash(hash)
+            .count_entries(count_entries)
+            .digest(digest);
+        let report = py
+            .detach(|| uki::inspect::inspect(opts))
+            .map_err(core_error)?;
+        to_py(py, &report)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Uki({:?})", self.path.display().to_string())
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "lowell")]
+fn lowell_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("LowellError", m.py().get_type::<LowellError>())?;
+    m.add_function(wrap_pyfunction!(inspect, m)?)?;
+    m.add_class::<Uki>()?;
+    Ok(())
+}
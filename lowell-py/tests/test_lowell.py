@@ -0,0 +1,33 @@
+# SPDX-License-Identifier: MIT OR Apache-2.0
+# Run with `maturin develop && pytest` from lowell-py/.
+import pytest
+
+import lowell
+
+
+def newc_trailer() -> bytes:
+    """A newc archive holding only the trailer, padded to 512 bytes."""
+    fields = [0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 11, 0]
+    header = b"070701" + b"".join(b"%08x" % v for v in fields)
+    return (header + b"TRAILER!!!\0").ljust(512, b"\0")
+
+
+def test_inspect_initramfs(tmp_path):
+    path = tmp_path / "initrd.img"
+    path.write_bytes(newc_trailer())
+    report = lowell.inspect(path)
+    assert report["compression"] == "uncompressed"
+    assert report["size"] == 512
+    assert len(report["sha256"]) == 64
+
+
+def test_errors_carry_codes(tmp_path):
+    with pytest.raises(lowell.LowellError) as e:
+        lowell.inspect(tmp_path / "missing.efi")
+    assert e.value.code == "E_NOT_FOUND"
+
+    path = tmp_path / "not-a-uki.efi"
+    path.write_bytes(b"MZ" + b"\0" * 64)
+    with pytest.raises(lowell.LowellError) as e:
+        lowell.Uki(path)
+    assert e.value.code == "E_NOT_PE"
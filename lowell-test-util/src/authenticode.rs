@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Authenticode signatures: PKCS#7 SignedData as it sits in a PE
+//! certificate table, minus the signature itself (nothing here checks it).
+
+/// A DER element: `tag`, then the definite length of `parts` joined.
+pub fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let body = parts.concat();
+    let mut out = vec![tag];
+    match body.len() {
+        n if n < 0x80 => out.push(n as u8),
+        n => {
+            let len = (n as u32).to_be_bytes();
+            let skip = len.iter().take_while(|&&b| b == 0).count();
+            out.push(0x80 | (4 - skip) as u8);
+            out.extend(&len[skip..]);
+        }
+    }
+    out.extend(body);
+    out
+}
+
+/// SignedData over the image `digest` (SHA-256), embedding `certificate`
+/// (DER) and naming its signer by `issuer` and `serial` (the DER `Name`
+/// and `INTEGER` of the signer's certificate).
+pub fn signed_data(digest: &[u8], certificate: &[u8], issuer: &[u8], serial: &[u8]) -> Vec<u8> {
+    const SIGNED_DATA: &[u8] = &[
+        0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02,
+    ];
+    const SPC: &[u8] = &[
+        0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x04,
+    ];
+    const SHA256: &[u8] = &[
+        0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+    ];
+    let version: &[u8] = &[0x02, 0x01, 0x01];
+    let digest_info = der(
+        0x30,
+        &[&der(0x30, &[SHA256, &[0x05, 0x00]]), &der(0x04, &[digest])],
+    );
+    let indirect = der(0x30, &[&[0x30, 0x00], &digest_info]);
+    let content = der(0x30, &[SPC, &der(0xa0, &[&indirect])]);
+    let signer_info = der(0x30, &[version, &der(0x30, &[issuer, serial])]);
+    let signed = der(
+        0x30,
+        &[
+            version,
+            &[0x31, 0x00],
+            &content,
+            &der(0xa0, &[certificate]),
+            &der(0x31, &[&signer_info]),
+        ],
+    );
+    der(0x30, &[SIGNED_DATA, &der(0xa0, &[&signed])])
+}
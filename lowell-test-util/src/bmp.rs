@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Windows bitmaps, as UKIs carry in `.splash`.
+
+/// An uncompressed (`BI_RGB`) bitmap with a 40-byte `BITMAPINFOHEADER`
+/// and zeroed pixels; a negative `height` stores rows top down.
+pub fn bmp(width: i32, height: i32, bits_per_pixel: u16) -> Vec<u8> {
+    let row = (width.max(0) as usize * bits_per_pixel as usize).div_ceil(32) * 4;
+    let size = 54 + row * height.unsigned_abs() as usize;
+    let mut b = vec![0u8; size];
+    b[..2].copy_from_slice(b"BM");
+    b[2..6].copy_from_slice(&(size as u32).to_le_bytes());
+    b[10..14].copy_from_slice(&54u32.to_le_bytes());
+    b[14..18].copy_from_slice(&40u32.to_le_bytes());
+    b[18..22].copy_from_slice(&width.to_le_bytes());
+    b[22..26].copy_from_slice(&height.to_le_bytes());
+    b[26..28].copy_from_slice(&1u16.to_le_bytes());
+    b[28..30].copy_from_slice(&bits_per_pixel.to_le_bytes());
+    b
+}
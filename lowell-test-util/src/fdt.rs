@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Flattened devicetree blobs, as UKIs carry in `.dtb` and `.dtbauto`.
+
+/// An FDT (version 17) whose root node has `props`, in order, and one
+/// empty child, `cpus`.
+pub fn fdt(props: &[(&str, &[u8])]) -> Vec<u8> {
+    const BEGIN_NODE: u32 = 1;
+    const END_NODE: u32 = 2;
+    const PROP: u32 = 3;
+    const END: u32 = 9;
+    let (mut structs, mut strings) = (Vec::new(), Vec::new());
+    let token = |s: &mut Vec<u8>, t: u32| s.extend(t.to_be_bytes());
+    token(&mut structs, BEGIN_NODE);
+    structs.extend([0; 4]);
+    for (name, value) in props {
+        token(&mut structs, PROP);
+        token(&mut structs, value.len() as u32);
+        token(&mut structs, strings.len() as u32);
+        strings.extend(name.bytes().chain([0]));
+        structs.extend(*value);
+        structs.resize(structs.len().next_multiple_of(4), 0);
+    }
+    token(&mut structs, BEGIN_NODE);
+    structs.extend(b"cpus\0\0\0\0");
+    token(&mut structs, END_NODE);
+    token(&mut structs, END_NODE);
+    token(&mut structs, END);
+
+    let header = 40;
+    let total = header + structs.len() + strings.len();
+    let fields = [
+        (0, 0xd00d_feed),
+        (4, total),
+        (8, header),
+        (12, header + structs.len()),
+        (20, 17),
+        (24, 16),
+        (32, strings.len()),
+        (36, structs.len()),
+    ];
+    let mut blob = vec![0u8; header];
+    for (at, v) in fields {
+        blob[at..at + 4].copy_from_slice(&(v as u32).to_be_bytes());
+    }
+    blob.extend(structs);
+    blob.extend(strings);
+    blob
+}
@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! newc cpio archives and the compressions initramfs images use.
+
+use std::io::{Read, Write};
+
+/// File type and permission bits for [`newc`] entries.
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+const HEADER_LEN: usize = 110;
+
+/// Encode a newc archive from `(name, mode, data)` triples, trailer included.
+pub fn newc(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut push = |ino: u32, name: &str, mode: u32, data: &[u8]| {
+        let namesize = name.len() + 1;
+        out.extend_from_slice(b"070701");
+        for v in [
+            ino,
+            mode,
+            0,
+            0,
+            1,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            namesize as u32,
+            0,
+        ] {
+            out.extend_from_slice(format!("{v:08X}").as_bytes());
+        }
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.resize(out.len() + pad4(HEADER_LEN + namesize), 0);
+        out.extend_from_slice(data);
+        out.resize(out.len() + pad4(data.len()), 0);
+    };
+    for (ino, (name, mode, data)) in (1..).zip(entries) {
+        push(ino, name, *mode, data);
+    }
+    push(0, "TRAILER!!!", 0, b"");
+    out
+}
+
+fn pad4(n: usize) -> usize {
+    (4 - n % 4) % 4
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+/// `data` compressed as a single stream (fast settings).
+pub fn compress(data: &[u8], compression: Compression) -> Vec<u8> {
+    match compression {
+        Compression::None => data.to_vec(),
+        Compression::Gzip => {
+            let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            gz.write_all(data).expect("gzip to memory");
+            gz.finish().expect("gzip to memory")
+        }
+        Compression::Xz => {
+            let mut out = Vec::new();
+            liblzma::read::XzEncoder::new(data, 1)
+                .read_to_end(&mut out)
+                .expect("xz to memory");
+            out
+        }
+        Compression::Zstd => zstd::encode_all(data, 1).expect("zstd to memory"),
+    }
+}
+
+/// An initramfs: an optional uncompressed early archive (microcode) and a
+/// main archive in the chosen [`Compression`].
+#[derive(Debug, Clone, Default)]
+pub struct Initrd {
+    early: Vec<(String, u32, Vec<u8>)>,
+    entries: Vec<(String, u32, Vec<u8>)>,
+    compression: Compression,
+}
+
+impl Initrd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Any entry; `mode` includes the file type bits.
+    pub fn entry(mut self, name: &str, mode: u32, data: &[u8]) -> Self {
+        self.entries.push((name.to_string(), mode, data.to_vec()));
+        self
+    }
+
+    /// A regular file, mode 0644.
+    pub fn file(self, name: &str, data: &[u8]) -> Self {
+        self.entry(name, S_IFREG | 0o644, data)
+    }
+
+    /// A directory, mode 0755.
+    pub fn dir(self, name: &str) -> Self {
+        self.entry(name, S_IFDIR | 0o755, b"")
+    }
+
+    pub fn symlink(self, name: &str, target: &str) -> Self {
+        self.entry(name, S_IFLNK | 0o777, target.as_bytes())
+    }
+
+    /// A file in the uncompressed archive placed before the main one.
+    pub fn early(mut self, name: &str, data: &[u8]) -> Self {
+        self.early
+            .push((name.to_string(), S_IFREG | 0o644, data.to_vec()));
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let archive = |entries: &[(String, u32, Vec<u8>)]| {
+            let refs: Vec<_> = entries
+                .iter()
+                .map(|(n, m, d)| (n.as_str(), *m, d.as_slice()))
+                .collect();
+            newc(&refs)
+        };
+        let mut out = Vec::new();
+        if !self.early.is_empty() {
+            out = archive(&self.early);
+            // Like dracut: the kernel wants the next archive 4-byte aligned.
+            out.resize(out.len().next_multiple_of(4), 0);
+        }
+        out.extend_from_slice(&compress(&archive(&self.entries), self.compression));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressions_have_their_magic() {
+        let data = Initrd::new().file("init", b"x").build();
+        assert!(data.starts_with(b"070701"));
+        for (c, magic) in [
+            (Compression::Gzip, &[0x1f, 0x8b][..]),
+            (Compression::Xz, &[0xfd, b'7', b'z', b'X', b'Z', 0]),
+            (Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+        ] {
+            assert!(compress(&data, c).starts_with(magic), "{c:?}");
+        }
+    }
+}
@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Synthetic boot artifacts for tests.
+//!
+//! Builds small but valid UKIs and initramfs archives in memory, so tests
+//! need neither multi-megabyte fixtures nor a real image in `UKI_PATH`:
+//!
+//! ```
+//! use lowell_test_util::{Compression, Initrd, UkiBuilder};
+//!
+//! let initrd = Initrd::new()
+//!     .early("kernel/x86/microcode/GenuineIntel.bin", b"ucode")
+//!     .file("init", b"#!/bin/sh\n")
+//!     .compression(Compression::Zstd)
+//!     .build();
+//! let uki = UkiBuilder::new()
+//!     .linux(b"kernel")
+//!     .initrd(&initrd)
+//!     .cmdline("quiet")
+//!     .certificate(b"not really PKCS#7")
+//!     .build();
+//! assert_eq!(&uki[..2], b"MZ");
+//! ```
+
+pub mod authenticode;
+pub mod bmp;
+pub mod fdt;
+pub mod initrd;
+pub mod uki;
+
+pub use authenticode::signed_data;
+pub use bmp::bmp;
+pub use fdt::fdt;
+pub use initrd::{compress, newc, Compression, Initrd};
+pub use uki::{stub, Arch, UkiBuilder};
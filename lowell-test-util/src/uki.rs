@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Minimal PE32+ EFI images: a systemd-stub look-alike plus payload
+//! sections, optionally with an (unverifiable) Authenticode table.
+//!
+//! Layout: headers in the first 0x400 bytes, a `.text` section of `ret`
+//! instructions, then one section per payload in insertion order. File
+//! alignment is 0x200 and section alignment 0x1000, as in systemd's stubs.
+
+const PE_OFFSET: usize = 0x80;
+const COFF: usize = PE_OFFSET + 4;
+const OPT: usize = COFF + 20;
+const OPT_LEN: usize = 240;
+const SECTIONS: usize = OPT + OPT_LEN;
+const SECTION_HEADER_LEN: usize = 40;
+const FILE_ALIGN: usize = 0x200;
+const SECTION_ALIGN: usize = 0x1000;
+/// IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ
+const DATA_SECTION: u32 = 0x4000_0040;
+/// IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ
+const CODE_SECTION: u32 = 0x6000_0020;
+/// WIN_CERT_REVISION_2_0 / WIN_CERT_TYPE_PKCS_SIGNED_DATA
+const CERT_REVISION: u16 = 0x0200;
+const CERT_TYPE: u16 = 0x0002;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arch {
+    #[default]
+    X86_64,
+    Aarch64,
+}
+
+impl Arch {
+    fn machine(self) -> u16 {
+        match self {
+            Arch::X86_64 => 0x8664,
+            Arch::Aarch64 => 0xaa64,
+        }
+    }
+}
+
+/// A stub with no payload sections, like systemd's `linuxx64.efi.stub`.
+pub fn stub() -> Vec<u8> {
+    UkiBuilder::new().build()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UkiBuilder {
+    arch: Arch,
+    sections: Vec<(String, Vec<u8>)>,
+    certificates: Vec<Vec<u8>>,
+}
+
+impl UkiBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn arch(mut self, arch: Arch) -> Self {
+        self.arch = arch;
+        self
+    }
+
+    /// Any section; names longer than 8 bytes are truncated, as in PE.
+    pub fn section(mut self, name: &str, data: &[u8]) -> Self {
+        self.sections.push((name.to_string(), data.to_vec()));
+        self
+    }
+
+    pub fn linux(self, data: &[u8]) -> Self {
+        self.section(".linux", data)
+    }
+
+    pub fn initrd(self, data: &[u8]) -> Self {
+        self.section(".initrd", data)
+    }
+
+    /// `.cmdline`, NUL-terminated as ukify writes it.
+    pub fn cmdline(self, cmdline: &str) -> Self {
+        self.section(".cmdline", format!("{cmdline}\0").as_bytes())
+    }
+
+    pub fn osrel(self, os_release: &str) -> Self {
+        self.section(".osrel", os_release.as_bytes())
+    }
+
+    /// Append a WIN_CERTIFICATE holding `blob`. Nothing checks the blob:
+    /// the image looks signed but will never verify.
+    pub fn certificate(mut self, blob: &[u8]) -> Self {
+        self.certificates.push(blob.to_vec());
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let count = 1 + self.sections.len();
+        let headers = (SECTIONS + count * SECTION_HEADER_LEN)
+            .next_multiple_of(FILE_ALIGN)
+            .max(0x400);
+        let mut img = vec![0u8; headers];
+        img[..2].copy_from_slice(b"MZ");
+        put_u32(&mut img, 0x3c, PE_OFFSET as u32);
+        img[PE_OFFSET..COFF].copy_from_slice(b"PE\0\0");
+        put_u16(&mut img, COFF, self.arch.machine());
+        put_u16(&mut img, COFF + 2, count as u16);
+        put_u16(&mut img, COFF + 16, OPT_LEN as u16);
+        put_u16(&mut img, COFF + 18, 0x0022); // executable, large address aware
+        put_u16(&mut img, OPT, 0x20b); // PE32+
+        put_u32(&mut img, OPT + 16, SECTION_ALIGN as u32); // entry point
+        put_u32(&mut img, OPT + 20, SECTION_ALIGN as u32); // base of code
+        put_u32(&mut img, OPT + 32, SECTION_ALIGN as u32);
+        put_u32(&mut img, OPT + 36, FILE_ALIGN as u32);
+        put_u32(&mut img, OPT + 60, headers as u32);
+        put_u16(&mut img, OPT + 68, 10); // EFI application
+        put_u32(&mut img, OPT + 108, 16); // data directories
+
+        let text = [0xc3u8; 0x10];
+        let payloads = std::iter::once((".text", &text[..], CODE_SECTION)).chain(
+            self.sections
+                .iter()
+                .map(|(name, data)| (name.as_str(), data.as_slice(), DATA_SECTION)),
+        );
+        let mut rva = SECTION_ALIGN;
+        for (i, (name, data, flags)) in payloads.enumerate() {
+            let hdr = SECTIONS + i * SECTION_HEADER_LEN;
+            let name = &name.as_bytes()[..name.len().min(8)];
+            img[hdr..hdr + name.len()].copy_from_slice(name);
+            let raw = data.len().next_multiple_of(FILE_ALIGN);
+            put_u32(&mut img, hdr + 8, data.len() as u32);
+            put_u32(&mut img, hdr + 12, rva as u32);
+            put_u32(&mut img, hdr + 16, raw as u32);
+            let offset = img.len() as u32;
+            put_u32(&mut img, hdr + 20, offset);
+            put_u32(&mut img, hdr + 36, flags);
+            img.extend_from_slice(data);
+            img.resize(img.len() + raw - data.len(), 0);
+            rva += data.len().max(1).next_multiple_of(SECTION_ALIGN);
+        }
+        put_u32(&mut img, OPT + 56, rva as u32); // SizeOfImage
+
+        if !self.certificates.is_empty() {
+            let start = img.len();
+            for blob in &self.certificates {
+                let len = 8 + blob.len();
+                img.extend_from_slice(&(len as u32).to_le_bytes());
+                img.extend_from_slice(&CERT_REVISION.to_le_bytes());
+                img.extend_from_slice(&CERT_TYPE.to_le_bytes());
+                img.extend_from_slice(blob);
+                img.resize(img.len().next_multiple_of(8), 0);
+            }
+            // Security directory (index 4): a file offset, not an RVA.
+            put_u32(&mut img, OPT + 112 + 4 * 8, start as u32);
+            let size = (img.len() - start) as u32;
+            put_u32(&mut img, OPT + 112 + 4 * 8 + 4, size);
+        }
+        img
+    }
+}
+
+fn put_u16(img: &mut [u8], at: usize, v: u16) {
+    img[at..at + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(img: &mut [u8], at: usize, v: u32) {
+    img[at..at + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_are_file_aligned_in_order() {
+        let img = UkiBuilder::new()
+            .arch(Arch::Aarch64)
+            .cmdline("quiet")
+            .linux(&[1; 0x300])
+            .build();
+        let u32_at = |at: usize| u32::from_le_bytes(img[at..at + 4].try_into().unwrap());
+        assert_eq!(u16::from_le_bytes([img[COFF], img[COFF + 1]]), 0xaa64);
+        assert_eq!(img[COFF + 2], 3);
+        let linux = SECTIONS + 2 * SECTION_HEADER_LEN;
+        assert_eq!(&img[linux..linux + 6], b".linux");
+        assert_eq!(u32_at(linux + 8), 0x300);
+        assert_eq!(u32_at(linux + 20) as usize % FILE_ALIGN, 0);
+        assert_eq!(img.len() % FILE_ALIGN, 0);
+    }
+}